@@ -0,0 +1,112 @@
+//Allocation and collection benchmarks
+/*
+    A baseline for the hot paths of the collector, at several heap sizes, so a redesign of any of them
+    can show what it gained (or lost):
+
+        free_alloc -> first fit, so its cost is how far it scans. The best case finds the first cell free,
+                      the worst finds only the last one free (what a free list would make as cheap as the best)
+        mark -> a wide graph (one root referencing every other cell) against a deep one (a single chain from the root),
+                which grow the worklist in opposite ways
+        sweep -> a heap that is mostly live (1 in 10 cells is garbage) against one that is mostly dead (1 in 10 survives)
+
+        cargo bench --bench collector
+        cargo bench --bench collector -- mark       (only the mark benchmarks)
+
+    Events are logged as they are in the REPL, but not printed.
+*/
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use gc_rust::{assign_reference, clear_marks, free_alloc, log::EventLog, mark, scan_roots, spec_alloc, sweep, Cell, Heap, MarkOrder};
+
+const SIZES: [usize; 3] = [100, 1_000, 10_000];
+
+//A heap of `size` cells with no events printed
+fn heap(size: usize) -> Heap {
+    let mut heap: Heap = Heap::with_seed(size, 0);
+    heap.log.echo = false;
+    heap
+}
+
+//A heap with every cell in use, cell 0 a root, and a reference for each (from, to) -> with the marks cleared, ready to mark
+fn graph(size: usize, edges: impl Iterator<Item = (usize, usize)>) -> Heap {
+    let mut heap: Heap = heap(size);
+    for i in 0..size {
+        spec_alloc(&mut heap, i as i32, None, i).unwrap();
+    }
+    heap.add_root("bench", 0).unwrap();
+    for (from, to) in edges {
+        assign_reference(&mut heap, from, to);
+    }
+    clear_marks(&mut heap);
+    heap
+}
+
+fn bench_free_alloc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("free_alloc");
+    for size in SIZES {
+        //Each allocation's cell is handed straight back, so every iteration scans the same heap
+        let mut empty: Heap = heap(size);
+        group.bench_with_input(BenchmarkId::new("first-free", size), &size, |b, _| {
+            b.iter(|| {
+                let index: usize = free_alloc(&mut empty, 1, None).unwrap();
+                empty.cells[index].freed = true;
+            })
+        });
+
+        let mut full: Heap = heap(size);
+        for i in 0..size - 1 {
+            spec_alloc(&mut full, 1, None, i).unwrap();
+        }
+        group.bench_with_input(BenchmarkId::new("last-free", size), &size, |b, _| {
+            b.iter(|| {
+                let index: usize = free_alloc(&mut full, 1, None).unwrap();
+                full.cells[index].freed = true;
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_mark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mark");
+    for size in SIZES {
+        let wide: Heap = graph(size, (1..size).map(|i| (0, i)));
+        let deep: Heap = graph(size, (1..size).map(|i| (i - 1, i)));
+
+        for (shape, heap) in [("wide", &wide), ("deep", &deep)] {
+            let roots: Vec<usize> = scan_roots(&heap.cells);
+            group.bench_with_input(BenchmarkId::new(shape, size), &size, |b, _| {
+                b.iter_batched_ref(
+                    || heap.cells.clone(),
+                    |cells| mark(cells, roots.clone(), MarkOrder::Dfs, None),
+                    BatchSize::LargeInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+fn bench_sweep(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sweep");
+    for size in SIZES {
+        for (survival, kept) in [("mostly-live", 9), ("mostly-dead", 1)] {       //Cells kept in every 10
+            //Every cell is in use, and the marked ones are what a mark phase would have kept
+            let mut heap: Heap = heap(size);
+            for i in 0..size {
+                spec_alloc(&mut heap, i as i32, None, i).unwrap();
+                heap.cells[i].marked = i % 10 < kept;
+            }
+
+            let mut log: EventLog = EventLog::new();
+            log.echo = false;
+            group.bench_with_input(BenchmarkId::new(survival, size), &size, |b, _| {
+                b.iter_batched_ref(|| heap.cells.clone(), |cells: &mut Vec<Cell>| sweep(cells, &mut log), BatchSize::LargeInput)
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_free_alloc, bench_mark, bench_sweep);
+criterion_main!(benches);
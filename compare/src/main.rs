@@ -0,0 +1,372 @@
+//Differential benchmark
+/*
+    Runs the same workloads against this crate's collectors and (with their features on) two production Rust
+    garbage collectors, then reports how fast each allocates and collects, relative to the fastest:
+
+        gc -> `Gc<T>` pointers, reference counted while rooted, with a mark and sweep that finds cycles
+        gc-arena -> a safe arena whose pointers are branded with its lifetime, collected incrementally by mark and sweep
+
+    Every workload is written once per collector (their pointer types have nothing in common), but each does the same:
+
+        list -> a linked list of n objects, collected while it is all live (tracing cost) and again once it's dropped (sweeping cost)
+        tree -> a complete binary tree of about n objects, the same way
+        churn -> half of n objects stay live in a list, while 10 rounds each allocate a tenth of n as garbage and collect
+
+    Timings are the best of several runs. Only the time spent in collections run by hand counts as collecting,
+    anything a collector does by itself while allocating (the `gc` crate collects once it passes a threshold) counts as allocating.
+    This crate's heap allocates first fit, scanning for a free cell, so its allocation gets slower the bigger the heap.
+
+        cargo run --release --features gc,gc-arena -- [--objects <n>] [--runs <r>]
+*/
+
+use std::{
+    env,
+    time::{Duration, Instant},
+};
+
+use gc_rust::{collect, handle::Handle, roots::GLOBALS, Algorithm, Heap};
+
+/// Rounds of garbage in the churn workload
+const ROUNDS: usize = 10;
+
+/// This crate's collectors, each benchmarked on its own
+const ALGORITHMS: [Algorithm; 3] = [Algorithm::MarkSweep, Algorithm::Copying, Algorithm::RefCount];
+
+/// A workload, see the top of this file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Workload {
+    List,
+    Tree,
+    Churn,
+}
+
+impl Workload {
+    const ALL: [Workload; 3] = [Workload::List, Workload::Tree, Workload::Churn];
+
+    fn about(&self, objects: usize) -> String {
+        match self {
+            Workload::List => format!("list: a linked list of {} objects, collected live and then dropped", objects),
+            Workload::Tree => format!("tree: a binary tree of {} objects, collected live and then dropped", tree_size(tree_depth(objects))),
+            Workload::Churn => format!("churn: {} live objects, while {} rounds of {} garbage objects are collected", objects / 2, ROUNDS, objects / ROUNDS),
+        }
+    }
+}
+
+/// How long a workload took under a collector
+#[derive(Clone, Copy, Debug, Default)]
+struct Timing {
+    allocated: usize,               //Objects allocated
+    alloc: Duration,                //Time spent allocating (and linking) them
+    collect: Duration,              //Time spent in collections
+}
+
+/// A garbage collector the workloads can be run against
+trait Collector {
+    fn name(&self) -> String;
+    fn run(&self, workload: Workload, objects: usize) -> Timing;
+}
+
+//The deepest complete binary tree with no more than `objects` objects
+fn tree_depth(objects: usize) -> u32 {
+    (objects + 1).ilog2().max(1) - 1
+}
+
+fn tree_size(depth: u32) -> usize {
+    (1 << (depth + 1)) - 1
+}
+
+//Times `f`, adding how long it took to `total`
+fn timed<T>(total: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let start: Instant = Instant::now();
+    let result: T = f();
+    *total += start.elapsed();
+    result
+}
+
+/// This crate's heap, under one of its algorithms
+struct GcRust(Algorithm);
+
+impl GcRust {
+    //A heap big enough that the workload never fills it, so every collection is one run by hand
+    fn heap(&self, cells: usize) -> Heap {
+        let mut heap: Heap = Heap::with_seed(cells, 0);
+        heap.log.echo = false;
+        heap.policy.algorithm = self.0;
+        heap
+    }
+
+    fn list(heap: &mut Heap, length: usize) -> Handle {
+        let mut head: Handle = heap.alloc(0).expect("the heap has room for the workload");
+        for data in 1..length {
+            let node: Handle = heap.alloc(data as i32).expect("the heap has room for the workload");
+            heap.link(node, head).expect("both were just allocated");
+            head = node;
+        }
+        head
+    }
+
+    fn tree(heap: &mut Heap, depth: u32) -> Handle {
+        let node: Handle = heap.alloc(depth as i32).expect("the heap has room for the workload");
+        if depth > 0 {
+            for _ in 0..2 {
+                let child: Handle = GcRust::tree(heap, depth - 1);
+                heap.link(node, child).expect("both were just allocated");
+            }
+        }
+        node
+    }
+}
+
+impl Collector for GcRust {
+    fn name(&self) -> String {
+        format!("gc-rust {}", self.0)
+    }
+
+    fn run(&self, workload: Workload, objects: usize) -> Timing {
+        let mut timing: Timing = Timing::default();
+        let mut heap: Heap = match workload {
+            Workload::Tree => self.heap(tree_size(tree_depth(objects))),
+            _ => self.heap(objects),
+        };
+
+        match workload {
+            Workload::List | Workload::Tree => {
+                let root: Handle = timed(&mut timing.alloc, || match workload {
+                    Workload::List => GcRust::list(&mut heap, objects),
+                    _ => GcRust::tree(&mut heap, tree_depth(objects)),
+                });
+                heap.add_root(GLOBALS, root.index).expect("the root was just allocated");
+                timed(&mut timing.collect, || collect(&mut heap));
+
+                heap.drop_root_set(GLOBALS);            //Follows the root wherever the copying collector moved it
+                timed(&mut timing.collect, || collect(&mut heap));
+            }
+            Workload::Churn => {
+                let live: Handle = timed(&mut timing.alloc, || GcRust::list(&mut heap, objects / 2));
+                heap.add_root(GLOBALS, live.index).expect("the root was just allocated");
+                for _ in 0..ROUNDS {
+                    timed(&mut timing.alloc, || GcRust::list(&mut heap, objects / ROUNDS));
+                    timed(&mut timing.collect, || collect(&mut heap));
+                }
+            }
+        }
+
+        timing.allocated = heap.stats().allocations;
+        timing
+    }
+}
+
+/// The `gc` crate
+#[cfg(feature = "gc")]
+mod gc_crate {
+    use gc::{force_collect, Finalize, Gc, Trace};
+
+    use super::{timed, tree_depth, Collector, Timing, Workload, ROUNDS};
+
+    #[derive(Trace, Finalize)]
+    struct Node {
+        #[allow(dead_code)]
+        data: i32,                  //Only a payload, as a cell's data is
+        children: Vec<Gc<Node>>,
+    }
+
+    fn list(length: usize) -> Gc<Node> {
+        let mut head: Gc<Node> = Gc::new(Node { data: 0, children: Vec::new() });
+        for data in 1..length {
+            head = Gc::new(Node { data: data as i32, children: vec![head] });
+        }
+        head
+    }
+
+    fn tree(depth: u32) -> Gc<Node> {
+        let children: Vec<Gc<Node>> = if depth > 0 { vec![tree(depth - 1), tree(depth - 1)] } else { Vec::new() };
+        Gc::new(Node { data: depth as i32, children })
+    }
+
+    fn count(node: &Gc<Node>) -> usize {
+        1 + node.children.iter().map(count).sum::<usize>()
+    }
+
+    pub struct GcCrate;
+
+    impl Collector for GcCrate {
+        fn name(&self) -> String {
+            "gc".to_string()
+        }
+
+        fn run(&self, workload: Workload, objects: usize) -> Timing {
+            let mut timing: Timing = Timing::default();
+            match workload {
+                Workload::List | Workload::Tree => {
+                    let root: Gc<Node> = timed(&mut timing.alloc, || match workload {
+                        Workload::List => list(objects),
+                        _ => tree(tree_depth(objects)),
+                    });
+                    timing.allocated = count(&root);
+                    timed(&mut timing.collect, force_collect);
+
+                    drop(root);
+                    timed(&mut timing.collect, force_collect);
+                }
+                Workload::Churn => {
+                    let live: Gc<Node> = timed(&mut timing.alloc, || list(objects / 2));
+                    for _ in 0..ROUNDS {
+                        timed(&mut timing.alloc, || list(objects / ROUNDS));
+                        timed(&mut timing.collect, force_collect);
+                    }
+                    timing.allocated = objects / 2 + ROUNDS * (objects / ROUNDS);
+                    drop(live);
+                    force_collect();
+                }
+            }
+            timing
+        }
+    }
+}
+
+/// The `gc-arena` crate
+#[cfg(feature = "gc-arena")]
+mod arena {
+    use gc_arena::{Arena, Collect, Gc, Mutation, Rootable};
+
+    use super::{timed, tree_depth, Collector, Timing, Workload, ROUNDS};
+
+    #[derive(Collect)]
+    #[collect(no_drop)]
+    struct Node<'gc> {
+        #[allow(dead_code)]
+        data: i32,                  //Only a payload, as a cell's data is
+        children: Vec<Gc<'gc, Node<'gc>>>,
+    }
+
+    type Roots = Rootable![Vec<Gc<'_, Node<'_>>>];
+
+    fn list<'gc>(mc: &Mutation<'gc>, length: usize) -> Gc<'gc, Node<'gc>> {
+        let mut head: Gc<'gc, Node<'gc>> = Gc::new(mc, Node { data: 0, children: Vec::new() });
+        for data in 1..length {
+            head = Gc::new(mc, Node { data: data as i32, children: vec![head] });
+        }
+        head
+    }
+
+    fn tree<'gc>(mc: &Mutation<'gc>, depth: u32) -> Gc<'gc, Node<'gc>> {
+        let children: Vec<Gc<'gc, Node<'gc>>> = if depth > 0 { vec![tree(mc, depth - 1), tree(mc, depth - 1)] } else { Vec::new() };
+        Gc::new(mc, Node { data: depth as i32, children })
+    }
+
+    fn count(node: &Gc<'_, Node<'_>>) -> usize {
+        1 + node.children.iter().map(count).sum::<usize>()
+    }
+
+    pub struct GcArena;
+
+    impl Collector for GcArena {
+        fn name(&self) -> String {
+            "gc-arena".to_string()
+        }
+
+        fn run(&self, workload: Workload, objects: usize) -> Timing {
+            let mut timing: Timing = Timing::default();
+            let mut arena: Arena<Roots> = Arena::new(|_| Vec::new());
+            match workload {
+                Workload::List | Workload::Tree => {
+                    timed(&mut timing.alloc, || {
+                        arena.mutate_root(|mc, roots| {
+                            roots.push(match workload {
+                                Workload::List => list(mc, objects),
+                                _ => tree(mc, tree_depth(objects)),
+                            })
+                        })
+                    });
+                    timing.allocated = arena.mutate(|_, roots| roots.iter().map(count).sum());
+                    timed(&mut timing.collect, || arena.collect_all());
+
+                    arena.mutate_root(|_, roots| roots.clear());
+                    timed(&mut timing.collect, || arena.collect_all());
+                }
+                Workload::Churn => {
+                    timed(&mut timing.alloc, || arena.mutate_root(|mc, roots| roots.push(list(mc, objects / 2))));
+                    for _ in 0..ROUNDS {
+                        timed(&mut timing.alloc, || arena.mutate(|mc, _| drop(list(mc, objects / ROUNDS))));
+                        timed(&mut timing.collect, || arena.collect_all());
+                    }
+                    timing.allocated = objects / 2 + ROUNDS * (objects / ROUNDS);
+                }
+            }
+            timing
+        }
+    }
+}
+
+/// Every collector this build can run, this crate's first
+fn collectors() -> Vec<Box<dyn Collector>> {
+    let others: Vec<Box<dyn Collector>> = vec![
+        #[cfg(feature = "gc")]
+        Box::new(gc_crate::GcCrate),
+        #[cfg(feature = "gc-arena")]
+        Box::new(arena::GcArena),
+    ];
+    ALGORITHMS.into_iter().map(|algorithm| Box::new(GcRust(algorithm)) as Box<dyn Collector>).chain(others).collect()
+}
+
+//The value of a flag given as `--name <value>`, or `default`
+fn flag(args: &[String], name: &str, default: usize) -> usize {
+    match args.iter().position(|arg| arg == name).map(|at| args.get(at + 1).map(|value| value.parse::<usize>())) {
+        None => default,
+        Some(Some(Ok(value))) if value > 0 => value,
+        _ => {
+            eprintln!("Usage: gc-rust-compare [--objects <n>] [--runs <r>], both greater than 0");
+            std::process::exit(2);
+        }
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let objects: usize = flag(&args, "--objects", 10_000).max(ROUNDS);
+    let runs: usize = flag(&args, "--runs", 5);
+    let collectors: Vec<Box<dyn Collector>> = collectors();
+    if collectors.len() == ALGORITHMS.len() {
+        println!("Only gc-rust's own collectors are built, add --features gc,gc-arena to compare against those crates\n");
+    }
+
+    for workload in Workload::ALL {
+        println!("{} (best of {} runs)", workload.about(objects), runs);
+        println!("    {:<20}{:>14}{:>14}{:>12}{:>12}", "collector", "allocs/sec", "collecting", "alloc", "collect");
+
+        //The best run of each, so a stray pause on the machine doesn't count against a collector
+        let timings: Vec<Timing> = collectors
+            .iter()
+            .map(|collector| {
+                (0..runs)
+                    .map(|_| collector.run(workload, objects))
+                    .reduce(|best, run| Timing {
+                        allocated: run.allocated,
+                        alloc: best.alloc.min(run.alloc),
+                        collect: best.collect.min(run.collect),
+                    })
+                    .unwrap_or_default()
+            })
+            .collect();
+
+        let fastest_alloc: f64 = timings.iter().map(|timing| per_object(timing.alloc, timing.allocated)).fold(f64::INFINITY, f64::min);
+        let fastest_collect: f64 = timings.iter().map(|timing| timing.collect.as_secs_f64()).fold(f64::INFINITY, f64::min);
+        for (collector, timing) in collectors.iter().zip(&timings) {
+            println!(
+                "    {:<20}{:>14.0}{:>14}{:>11.2}x{:>11.2}x",
+                collector.name(),
+                timing.allocated as f64 / timing.alloc.as_secs_f64(),
+                format!("{:.2?}", timing.collect),
+                per_object(timing.alloc, timing.allocated) / fastest_alloc,
+                timing.collect.as_secs_f64() / fastest_collect,
+            );
+        }
+        println!();
+    }
+    println!("alloc and collect are relative to the fastest collector (1.00x), lower is better");
+}
+
+//Seconds spent allocating each object
+fn per_object(time: Duration, objects: usize) -> f64 {
+    time.as_secs_f64() / objects.max(1) as f64
+}
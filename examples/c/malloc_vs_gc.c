@@ -0,0 +1,101 @@
+/*
+    The same job done twice from C -> build a linked list, drop the front half of it, then build some more.
+
+    With malloc/free, whoever drops a node has to free it (forgetting leaks it, and freeing it twice or using it
+    afterwards is a bug nothing catches). With the collector, dropping the reference is all there is to it.
+
+        cargo rustc --lib --crate-type cdylib --features ffi
+        cc examples/c/malloc_vs_gc.c -Iinclude -Ltarget/debug -lgc_rust -o malloc_vs_gc
+        LD_LIBRARY_PATH=target/debug ./malloc_vs_gc
+*/
+
+#include <stdio.h>
+#include <stdlib.h>
+
+#include "gc_rust.h"
+
+#define LENGTH 8
+
+struct node {
+    int data;
+    struct node *next;
+};
+
+/* By hand -> every node dropped from the list is freed one at a time, and the count has to be kept right */
+static void with_malloc(void) {
+    struct node *head = NULL;
+    for (int i = 0; i < LENGTH; i++) {
+        struct node *node = malloc(sizeof *node);
+        node->data = i;
+        node->next = head;
+        head = node;
+    }
+
+    for (int i = 0; i < LENGTH / 2; i++) {
+        struct node *dropped = head;
+        head = head->next;
+        free(dropped);  /* Leave this out and the node leaks */
+    }
+
+    int remaining = 0;
+    for (struct node *node = head; node != NULL; node = node->next) {
+        remaining++;
+    }
+    printf("malloc/free: %d nodes left, %d freed one at a time\n", remaining, LENGTH / 2);
+
+    while (head != NULL) {
+        struct node *next = head->next;
+        free(head);
+        head = next;
+    }
+}
+
+/* With the collector -> the list hangs off a root, dropping nodes is just moving the root along */
+static int with_gc(void) {
+    GcHeap *heap = gc_heap_create(LENGTH, 1);
+    size_t head = 0;
+    size_t previous = 0;
+
+    for (int i = 0; i < LENGTH; i++) {
+        size_t node;
+        GcStatus status = gc_alloc(heap, i, &node);
+        if (status != GC_STATUS_OK) {
+            printf("Unable to allocate: %s\n", gc_status_message(status));
+            gc_heap_destroy(heap);
+            return 1;
+        }
+        if (i == 0) {
+            head = node;
+            gc_root(heap, head);
+        } else {
+            gc_link(heap, previous, node);
+        }
+        previous = node;
+    }
+
+    /* Drop the front half -> root the middle of the list instead of its head, and nothing is freed by hand */
+    gc_root(heap, head + LENGTH / 2);
+    gc_unroot(heap, head);
+
+    size_t swept = gc_collect(heap);
+    GcStats stats = gc_stats(heap);
+    printf("collector: %zu cells live, %zu swept by the collection\n", stats.live, swept);
+
+    /* The heap is full again, but allocating collects when it needs to */
+    size_t node;
+    gc_alloc(heap, 42, &node);
+    stats = gc_stats(heap);
+    printf("collector: allocated into cell %zu, %zu allocations and %zu collections so far\n", node, stats.allocations, stats.collections);
+
+    /* Using a swept cell is caught, rather than reading whatever is there now */
+    GcStatus status = gc_link(heap, head + 1, node);
+    printf("collector: linking from a swept cell -> %s\n", gc_status_message(status));
+
+    gc_heap_destroy(heap);
+    return 0;
+}
+
+int main(void) {
+    with_malloc();
+    return with_gc();
+}
@@ -0,0 +1,116 @@
+#ifndef GC_RUST_H
+#define GC_RUST_H
+
+/* Generated by cbindgen from src/ffi.rs, don't edit it by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Whether a call succeeded, and if not why not (see gc_status_message())
+typedef enum GcStatus {
+  GC_STATUS_OK = 0,
+  GC_STATUS_NULL_HEAP,
+  GC_STATUS_OCCUPIED,
+  GC_STATUS_NO_FREE_MEMORY,
+  GC_STATUS_DATA_IS_FREE,
+  GC_STATUS_FREED_ROOT,
+  GC_STATUS_DOUBLE_FREE,
+  GC_STATUS_STALE,
+  GC_STATUS_USE_AFTER_FREE,
+  GC_STATUS_OUT_OF_BOUNDS,
+} GcStatus;
+
+// #### The 'Virtual Heap' as a whole.
+// Owns the memory pool (`Vec<Cell>`) alongside the policies that govern how it is collected.
+typedef struct GcHeap GcHeap;
+
+// Numbers about the heap, see HeapStats
+typedef struct GcStats {
+  size_t capacity;
+  size_t live;
+  size_t free;
+  size_t roots;
+  size_t allocations;
+  size_t collections;
+  size_t high_water;
+  size_t bytes_capacity;
+  size_t bytes_used;
+  size_t bytes_reclaimed;
+} GcStats;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Creates a heap of `size` cells, whose arbitrary data comes from `seed` (the same seed always gives the same heap).
+// Returns null if `size` is 0. The heap must be given back to gc_heap_destroy() once finished with
+struct GcHeap *gc_heap_create(size_t size,
+                              uint64_t seed);
+
+// Destroys a heap made by gc_heap_create() (does nothing if it is null)
+//
+// # Safety
+// `heap` must be null or from gc_heap_create(), and not used again afterwards
+void gc_heap_destroy(struct GcHeap *heap);
+
+// Allocates `data` in the first free cell (collecting first if there are none), writing its index to `index`
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create(), and `index` null or valid to write to
+enum GcStatus gc_alloc(struct GcHeap *heap,
+                       int32_t data,
+                       size_t *index);
+
+// Makes cell `from` reference cell `to`
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+enum GcStatus gc_link(struct GcHeap *heap, size_t from, size_t to);
+
+// Removes the reference from cell `from` to cell `to`
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+enum GcStatus gc_unlink(struct GcHeap *heap, size_t from, size_t to);
+
+// Frees a cell by hand, the way free() would (roots can't be freed)
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+enum GcStatus gc_free(struct GcHeap *heap, size_t index);
+
+// Makes a cell a root (in the "globals" root set, like --root)
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+enum GcStatus gc_root(struct GcHeap *heap, size_t index);
+
+// Stops a cell being a root, returning false if it wasn't one (or the heap is null)
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+bool gc_unroot(struct GcHeap *heap, size_t index);
+
+// Runs a collection with the current algorithm, returning how many cells were swept
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+size_t gc_collect(struct GcHeap *heap);
+
+// Numbers about the heap as it is now (all 0 if the heap is null)
+//
+// # Safety
+// `heap` must be null or a live heap from gc_heap_create()
+struct GcStats gc_stats(const struct GcHeap *heap);
+
+// Describes a status, as a string that lives for the whole program (so it must not be freed)
+const char *gc_status_message(enum GcStatus status);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* GC_RUST_H */
@@ -0,0 +1,90 @@
+//Write barrier
+/*
+    A write barrier is a small piece of code that runs on every write the program makes to the heap,
+    just before the write happens.
+
+    A stop-the-world collector (such as mark and sweep) doesn't need one, as nothing changes while it runs.
+    Collectors that work in pieces between the program's writes (incremental), or only collect part of the heap
+    (generational), do -> they hook in here to hear about changes they would otherwise miss, i.e. a generational
+    collector records old -> young references in its remembered set.
+
+    Every mutation on the heap is routed through `Heap::write_barrier()`, which counts them, and remembers every old cell
+    made to reference a young one for the generational collector's minor collections (see generational.rs).
+    It also shades the cell being referenced for the treadmill, whose cycles run between the program's writes (see treadmill.rs).
+
+    Debug builds can turn the barrier off (`--set-policy barriers off`) to show what it is there for -> make an old cell
+    reference a young one, and the next minor collection sweeps the young cell while it is still referenced.
+    `--verify` (or `--set-policy verify-remembered on`) then points out the reference the barrier should have seen.
+    Release builds always run the barrier.
+*/
+
+use crate::{generational::CollectionKind, Heap};
+
+/// A write to the heap, as seen by the write barrier
+#[derive(Clone, Copy, Debug)]
+pub enum Mutation {
+    Data { index: usize },                  //The data stored in a cell is being overwritten
+    Reference { from: usize, to: usize },   //`from` is about to reference `to`
+    Unlink { from: usize, to: usize },      //`from` is about to stop referencing `to`
+}
+
+impl Heap {
+    /// Runs before every mutation of the heap.
+    /// Collectors that need to know about writes between (or during) collections hook in here
+    pub(crate) fn write_barrier(&mut self, mutation: Mutation) {
+        if cfg!(debug_assertions) && self.policy.skip_barriers {
+            return;
+        }
+        self.barrier_writes += 1;
+
+        match mutation {
+            Mutation::Reference { from, to } => {
+                self.remember(from, to);
+                self.shade(to);                     //The treadmill can't leave a cell the program is still using ecru
+            }
+            //Removing a reference can only make less reachable, and data isn't traced
+            Mutation::Data { .. } | Mutation::Unlink { .. } => {}
+        }
+    }
+
+    /// Turns the write barrier on or off, see the top of this module (it is only ever off in debug builds).
+    /// Turning it back on works the remembered set out again from the whole heap, so the references missed meanwhile are remembered
+    pub fn set_barriers(&mut self, on: bool) {
+        self.policy.skip_barriers = !on;
+        if on {
+            self.refresh_remembered(CollectionKind::Whole);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, spec_alloc, tests::graph, verify::Violation};
+
+    #[test]
+    fn sweeps_cells_in_use_without_write_barriers() {
+        //Old cell 1 is made to reference young cell 2 with the barrier off
+        let unbarriered = || {
+            let mut heap: Heap = graph(2, &[(0, 1)]);
+            heap.log.echo = false;
+            heap.collect_major();
+            heap.set_barriers(false);
+            spec_alloc(&mut heap, 2, None, 2).unwrap();
+            assign_reference(&mut heap, 1, 2);
+            heap
+        };
+        let mut heap: Heap = unbarriered();
+        assert!(heap.remembered_set().is_empty());
+        assert_eq!(heap.verify(), vec![Violation::Unremembered { from: 1, to: 2 }]);
+        heap.collect_minor();
+        assert!(heap.cells[2].freed);
+
+        //Turned back on, the missed reference is remembered and the young cell survives
+        let mut heap: Heap = unbarriered();
+        heap.set_barriers(true);
+        assert_eq!(heap.remembered_set(), &[1]);
+        heap.collect_minor();
+        assert!(!heap.cells[2].freed && heap.verify().is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//Interior mutability for objects on the heap
+/*
+    An embedder's own Rust values often hold objects on the heap (i.e. a struct with a few handles in it), and need
+    changing from behind a shared reference. GcRefCell<T> does for such a value what RefCell does for any other:
+    any number of shared borrows, or one mutable borrow, checked at runtime rather than by the compiler. Borrowing
+    a value that is mutably borrowed (or mutably borrowing one that is borrowed at all) is refused with an error,
+    rather than a panic.
+
+    Each GcRefCell has an object of its own on the heap (its owner), which references every object the value holds
+    (whatever its Trace implementation reports, see containers.rs). Rooting the owner (or referencing it from something
+    that is) keeps the whole value alive. A mutable borrow holds on to the heap, and once it ends the owner's references
+    are brought in line with what the value holds now -> through `assign_reference()` and `unlink_reference()`, so the
+    write barrier sees every change and collectors that rely on it stay correct. The guard lends the heap back out
+    with `heap()`, so other values can be borrowed (or the heap worked on) while it is held.
+*/
+
+use core::{
+    cell::{Ref, RefCell, RefMut},
+    ops::{Deref, DerefMut},
+};
+
+use crate::{assign_reference, containers::Trace, error::GcError, handle::Handle, roots::RootVisitor, unlink_reference, Heap};
+use crate::prelude::*;
+
+/// A value holding objects on the heap, with borrows checked at runtime, see the top of this module
+pub struct GcRefCell<T: Trace> {
+    owner: Handle,                  //The object on the heap that references everything the value holds
+    value: RefCell<T>,
+}
+
+/// Reasons a value can't be borrowed
+#[derive(Debug, PartialEq, Eq)]
+pub enum BorrowError {
+    Stale,                  //The owner is out of date, it was freed, swept or moved
+    AlreadyBorrowed,        //The value is already borrowed, and can't be mutably borrowed until that ends
+    AlreadyMutablyBorrowed, //The value is mutably borrowed, and can't be borrowed again until that ends
+}
+
+/// A shared borrow of the value in a GcRefCell, from `GcRefCell::borrow()`
+pub struct GcRef<'a, T> {
+    value: Ref<'a, T>,
+}
+
+/// A mutable borrow of the value in a GcRefCell, from `GcRefCell::borrow_mut()`
+pub struct GcRefMut<'a, T: Trace> {
+    value: RefMut<'a, T>,
+    heap: &'a mut Heap,
+    owner: Handle,
+}
+
+impl<T: Trace> GcRefCell<T> {
+    /// Allocates an owner for `value` on the heap, referencing every object it holds.
+    /// Returns `NoFreeMemory` error if there is no room for the owner (see `Heap::alloc()`)
+    pub fn new(heap: &mut Heap, value: T) -> Result<GcRefCell<T>, GcError> {
+        let owner: Handle = heap.alloc(0)?;
+        sync(heap, owner, &value);
+        Ok(GcRefCell { owner, value: RefCell::new(value) })
+    }
+
+    /// A handle to the owner, i.e. to root it
+    pub fn handle(&self) -> Handle {
+        self.owner
+    }
+
+    /// Borrows the value to read it. Any number of shared borrows can be held at once
+    pub fn borrow(&self) -> Result<GcRef<'_, T>, BorrowError> {
+        match self.value.try_borrow() {
+            Ok(value) => Ok(GcRef { value }),
+            Err(_) => Err(BorrowError::AlreadyMutablyBorrowed),
+        }
+    }
+
+    /// Borrows the value to change it. Nothing else can borrow it until the returned guard is dropped,
+    /// at which point the owner's references are brought in line with it (see the top of this module)
+    pub fn borrow_mut<'a>(&'a self, heap: &'a mut Heap) -> Result<GcRefMut<'a, T>, BorrowError> {
+        let value: RefMut<'_, T> = match self.value.try_borrow_mut() {
+            Ok(value) => value,
+            Err(_) if self.value.try_borrow().is_err() => return Err(BorrowError::AlreadyMutablyBorrowed),
+            Err(_) => return Err(BorrowError::AlreadyBorrowed),
+        };
+        heap.resolve(self.owner).map_err(|_| BorrowError::Stale)?;
+
+        Ok(GcRefMut { value, heap, owner: self.owner })
+    }
+
+    /// The value itself, once every borrow has ended
+    pub fn into_inner(self) -> T {
+        self.value.into_inner()
+    }
+}
+
+impl<T: Trace> Trace for GcRefCell<T> {
+    fn trace(&self, visitor: &mut dyn RootVisitor) {
+        self.owner.trace(visitor);                  //The value is reached through the owner's references
+    }
+}
+
+impl<T: Trace> GcRefMut<'_, T> {
+    /// The heap, i.e. to borrow another value while this one is borrowed
+    pub fn heap(&mut self) -> &mut Heap {
+        self.heap
+    }
+}
+
+impl<T> Deref for GcRef<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Trace> Deref for GcRefMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+impl<T: Trace> DerefMut for GcRefMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
+}
+
+impl<T: Trace> Drop for GcRefMut<'_, T> {
+    fn drop(&mut self) {
+        sync(self.heap, self.owner, &*self.value);
+    }
+}
+
+//Makes the owner reference exactly the objects in use that the value holds, through the write barrier
+fn sync<T: Trace>(heap: &mut Heap, owner: Handle, value: &T) {
+    let Ok(owner) = heap.resolve(owner) else {
+        return;
+    };
+    let mut held: Vec<usize> = Vec::new();
+    value.trace(&mut held);
+    held.retain(|&index| heap.cells.get(index).is_some_and(|cell| !cell.freed));
+
+    for to in heap.cells[owner].will_ref.clone() {
+        if !held.contains(&to) {
+            unlink_reference(heap, owner, to);
+        }
+    }
+    for to in held {
+        if !heap.cells[owner].will_ref.contains(&to) {
+            assign_reference(heap, owner, to);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, roots::GLOBALS};
+
+    #[test]
+    fn refuses_a_second_mutable_borrow() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        heap.log.echo = false;
+        let value: GcRefCell<Vec<Handle>> = GcRefCell::new(&mut heap, Vec::new()).unwrap();
+
+        let mut guard = value.borrow_mut(&mut heap).unwrap();
+        assert_eq!(value.borrow_mut(guard.heap()).err(), Some(BorrowError::AlreadyMutablyBorrowed));
+        assert_eq!(value.borrow().err(), Some(BorrowError::AlreadyMutablyBorrowed));
+    }
+
+    #[test]
+    fn releases_borrows_as_their_guards_are_dropped() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        heap.log.echo = false;
+        let value: GcRefCell<Vec<Handle>> = GcRefCell::new(&mut heap, Vec::new()).unwrap();
+
+        let (first, second) = (value.borrow().unwrap(), value.borrow().unwrap());
+        assert_eq!(value.borrow_mut(&mut heap).err(), Some(BorrowError::AlreadyBorrowed));
+        drop(first);
+        assert_eq!(value.borrow_mut(&mut heap).err(), Some(BorrowError::AlreadyBorrowed));
+        drop(second);
+
+        drop(value.borrow_mut(&mut heap).unwrap());
+        assert!(value.borrow().is_ok() && value.borrow_mut(&mut heap).is_ok());
+    }
+
+    #[test]
+    fn keeps_what_the_value_holds_through_its_owner() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        heap.log.echo = false;
+        let value: GcRefCell<Vec<Handle>> = GcRefCell::new(&mut heap, Vec::new()).unwrap();
+        heap.add_root(GLOBALS, value.handle().index).unwrap();
+
+        let held: Handle = heap.alloc(7).unwrap();
+        value.borrow_mut(&mut heap).unwrap().push(held);
+        assert_eq!(heap.get(value.handle()).unwrap().will_ref, [held.index]);
+        collect(&mut heap);
+        assert_eq!(heap.get(held).unwrap().data, Some(7));
+
+        value.borrow_mut(&mut heap).unwrap().clear();
+        collect(&mut heap);
+        assert!(heap.get(held).is_err() && heap.verify().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_change_a_value_whose_owner_is_gone() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        heap.log.echo = false;
+        let value: GcRefCell<Vec<Handle>> = GcRefCell::new(&mut heap, Vec::new()).unwrap();
+
+        collect(&mut heap);
+        assert_eq!(value.borrow_mut(&mut heap).err(), Some(BorrowError::Stale));
+    }
+}
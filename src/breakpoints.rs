@@ -0,0 +1,152 @@
+//Breakpoints
+/*
+    A collection runs start to finish in one go, so by the time anything can be looked at the marks have been cleared
+    and the garbage is gone. A breakpoint pauses a collection when something is about to happen, and hands the heap
+    (as it is at that moment) to the break handler registered with `Heap::on_break()` -> the REPL uses it to drop into
+    a prompt where the heap can be inspected before carrying on.
+
+        on-mark-phase       -> before a tracing collection (mark-sweep or copying) starts marking, once the old marks are cleared
+        on-sweep <cell>     -> before the mark-sweep collector sweeps that cell, with every cell before it already swept
+
+    Breakpoints that nothing is about to happen at are passed over -> on-sweep for a cell that is live (or free),
+    or either of them under reference counting, which neither marks nor sweeps. Time spent paused isn't counted
+    in the collection's pause times.
+*/
+
+use core::fmt;
+
+use crate::{log::Event, Heap};
+use crate::prelude::*;
+
+/// Something a collection can be paused at, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    MarkPhase,          //The mark phase is about to begin
+    Sweep(usize),       //This cell is about to be swept
+}
+
+/// Told when a collection reaches a breakpoint, given the heap as it is at that moment
+pub type BreakHandler = Box<dyn FnMut(&Heap, Breakpoint) + Send>;
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::MarkPhase => write!(f, "on-mark-phase"),
+            Breakpoint::Sweep(index) => write!(f, "on-sweep {}", index),
+        }
+    }
+}
+
+impl Heap {
+    /// Sets a breakpoint, returning false if it was already set
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        if self.breakpoints.contains(&breakpoint) {
+            return false;
+        }
+        self.breakpoints.push(breakpoint);
+        true
+    }
+
+    /// Removes a breakpoint, returning false if it wasn't set
+    pub fn remove_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        let before: usize = self.breakpoints.len();
+        self.breakpoints.retain(|&set| set != breakpoint);
+        self.breakpoints.len() != before
+    }
+
+    /// Removes every breakpoint
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Every breakpoint that is set, in the order they were set
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Registers the break handler (replacing any there was), which is called whenever a collection reaches a breakpoint.
+    /// Without one, breakpoints are still reported in the event log but nothing pauses
+    pub fn on_break(&mut self, handler: impl FnMut(&Heap, Breakpoint) + Send + 'static) {
+        self.break_handler = Some(Box::new(handler));
+    }
+
+    //Pauses at `breakpoint` if it is set, handing the heap to the break handler until it returns
+    pub(crate) fn reach(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            return;
+        }
+        self.log.emit(Event::BreakpointHit { breakpoint });
+
+        //The handler is taken out while it runs, so it can be handed the heap
+        if let Some(mut handler) = self.break_handler.take() {
+            handler(self, breakpoint);
+            self.break_handler = Some(handler);
+        }
+    }
+
+    //Cells with an on-sweep breakpoint that a sweep would reclaim (in use and unmarked), in the order it reaches them
+    pub(crate) fn sweep_stops(&self) -> Vec<usize> {
+        let mut stops: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .filter_map(|&breakpoint| match breakpoint {
+                Breakpoint::Sweep(index) => Some(index),
+                Breakpoint::MarkPhase => None,
+            })
+            .filter(|&index| self.cells.get(index).is_some_and(|cell| !cell.freed && !cell.marked))
+            .collect();
+        stops.sort();
+        stops
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{collect, tests::graph};
+
+    #[test]
+    fn pauses_at_breakpoints() {
+        //Which breakpoints were reached, and which cells were free at the time
+        let stops: Arc<Mutex<Vec<_>>> = Arc::new(Mutex::new(Vec::new()));
+        let mut heap: Heap = graph(4, &[(0, 1)]);
+        heap.log.echo = false;
+        let seen = Arc::clone(&stops);
+        heap.on_break(move |heap, breakpoint| {
+            let free: Vec<usize> = heap.iter_free().map(|(index, _)| index).filter(|&index| index < 4).collect();
+            seen.lock().unwrap().push((breakpoint, free));
+        });
+        heap.add_breakpoint(Breakpoint::MarkPhase);
+        heap.add_breakpoint(Breakpoint::Sweep(3));
+        heap.add_breakpoint(Breakpoint::Sweep(1));         //Live, so never swept
+        collect(&mut heap);
+
+        let stops = stops.lock().unwrap();
+        assert_eq!(*stops, vec![(Breakpoint::MarkPhase, vec![]), (Breakpoint::Sweep(3), vec![2])]);
+        assert!(heap.cells[3].freed);
+    }
+
+    #[test]
+    fn passes_over_breakpoints_nothing_happens_at() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        assert!(heap.add_breakpoint(Breakpoint::Sweep(1)) && !heap.add_breakpoint(Breakpoint::Sweep(1)));
+        heap.add_breakpoint(Breakpoint::Sweep(40));                     //Past the end of the heap
+        heap.add_breakpoint(Breakpoint::MarkPhase);
+        assert!(heap.remove_breakpoint(Breakpoint::MarkPhase) && !heap.remove_breakpoint(Breakpoint::MarkPhase));
+
+        //Without a handler nothing pauses, but the log still says when one is reached
+        heap.add_breakpoint(Breakpoint::Sweep(2));
+        collect(&mut heap);
+        let hits: Vec<Breakpoint> = heap.log.since(0).filter_map(|event| match event {
+            Event::BreakpointHit { breakpoint } => Some(*breakpoint),
+            _ => None,
+        }).collect();
+        assert_eq!(hits, [Breakpoint::Sweep(2)]);
+
+        heap.clear_breakpoints();
+        assert!(heap.breakpoints().is_empty());
+    }
+}
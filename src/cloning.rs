@@ -0,0 +1,134 @@
+//Cloning cells
+/*
+    Building a big test graph a cell at a time is slow going, so an object already built can be copied into free cells
+    (`--copy <src> [dst] [deep]`) in one of two ways, as with Python's copy module:
+
+        shallow -> the copy has the same data, and references the same cells as the original. Nothing else is copied,
+                   so the two share whatever they reference
+        deep -> everything reachable from the original is copied along with it, and the references among the copies
+                point at each other. The copy is a whole new subgraph, the same shape as the first, sharing nothing with it
+
+    Either way every copy is allocated like any other (the original's kind, record fields and tags go with it, but
+    not its name, which has to be unique), and its references are made with assign_reference(), so the edge lists on
+    both ends, the reference counts and the write barrier see them as they would any other reference.
+
+    The first copy goes in the cell asked for (or the first free cell), the rest in the first free cells after that.
+    If there isn't room for all of it nothing is copied, so a deep copy is never left half done.
+*/
+
+use crate::{assign_reference, error::{ErrorKind, GcError, Operation}, free_alloc, spec_alloc, Heap};
+use crate::prelude::*;
+
+impl Heap {
+    /// Copies the cell at `src` into `dst` (or the first free cell), along with everything it reaches if `deep` is set
+    /// (see the top of this module). Returns every cell copied paired with its copy, `src` first.
+    /// Returns `DataIsFree` error if `src` isn't in use, `Occupied` error if `dst` is, `OutOfBounds` error if either isn't
+    /// on the heap, or `NoFreeMemory` error if there aren't enough free cells for every copy (in which case nothing is copied)
+    pub fn copy_cell(&mut self, src: usize, dst: Option<usize>, deep: bool) -> Result<Vec<(usize, usize)>, GcError> {
+        let error = |kind: ErrorKind, cell: usize| GcError::new(kind, Operation::Copy, Some(cell));
+        match self.cells.get(src) {
+            None => return Err(error(ErrorKind::OutOfBounds, src)),
+            Some(cell) if cell.freed => return Err(error(ErrorKind::DataIsFree, src)),
+            Some(_) => {}
+        }
+        match dst.map(|dst| (dst, self.cells.get(dst))) {
+            Some((dst, None)) => return Err(error(ErrorKind::OutOfBounds, dst)),
+            Some((dst, Some(cell))) if !cell.freed || cell.forward.is_some() => return Err(error(ErrorKind::Occupied, dst)),
+            _ => {}
+        }
+
+        //The original first, then (if deep) the rest of what it reaches in order of index
+        let originals: Vec<usize> = match deep {
+            true => {
+                let reached: Vec<bool> = self.reachable_from(vec![src]);
+                core::iter::once(src).chain((0..self.cells.len()).filter(|&i| reached[i] && i != src)).collect()
+            }
+            false => vec![src],
+        };
+        if self.room() < originals.len() {
+            return Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Copy, None));
+        }
+
+        //Allocate every copy first, so the references between them can be made
+        let mut copies: Vec<(usize, usize)> = Vec::new();
+        for &original in &originals {
+            let data: i32 = self.cells[original].data.unwrap_or(0);
+            let copy: usize = match (copies.is_empty(), dst) {
+                (true, Some(dst)) => spec_alloc(self, data, None, dst)?,
+                _ => free_alloc(self, data, None)?,
+            };
+            let from = &self.cells[original];
+            let (data, kind, record, tags) = (from.data, from.kind, from.record.clone(), from.tags.clone());
+            let to = &mut self.cells[copy];
+            (to.data, to.kind, to.record, to.tags) = (data, kind, record, tags);
+            copies.push((original, copy));
+        }
+
+        //References to a cell that was copied go to its copy, the rest to the same cell as the original's
+        let mut forwarding: Vec<Option<usize>> = (0..self.cells.len()).map(Some).collect();
+        for &(original, copy) in &copies {
+            forwarding[original] = Some(copy);
+        }
+        for &(original, copy) in &copies {
+            for target in self.cells[original].will_ref.clone() {
+                assign_reference(self, copy, forwarding[target].expect("every position is forwarded"));
+            }
+            if let Some(record) = &mut self.cells[copy].record {
+                record.relocate(&forwarding);
+            }
+        }
+        Ok(copies)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_cells_shallow_and_deep() {
+        let mut heap: Heap = Heap::with_seed(9, 0);
+        heap.log.echo = false;
+        for data in 0..3 {
+            spec_alloc(&mut heap, data, None, data as usize).unwrap();
+        }
+        assign_reference(&mut heap, 0, 1);
+        assign_reference(&mut heap, 1, 2);
+        assign_reference(&mut heap, 2, 0);                              //A cycle back to the start
+        heap.tag_cell(0, "graph", true).unwrap();
+
+        //Shallow -> the copy references the original's target, which gains a referrer
+        assert_eq!(heap.copy_cell(0, Some(5), false).unwrap(), vec![(0, 5)]);
+        assert_eq!((heap.cells[5].data, heap.cells[5].will_ref.clone(), heap.cells[5].has_tag("graph")), (Some(0), vec![1], true));
+        assert_eq!((heap.cells[1].by_ref.clone(), heap.cells[1].reference_count), (vec![0, 5], 3));
+
+        //Deep -> the cycle is copied whole, and points back into itself
+        assert_eq!(heap.copy_cell(0, None, true).unwrap(), vec![(0, 3), (1, 4), (2, 6)]);
+        assert_eq!((heap.cells[3].will_ref.clone(), heap.cells[4].will_ref.clone(), heap.cells[6].will_ref.clone()), (vec![4], vec![6], vec![3]));
+        assert_eq!(heap.cells[3].by_ref, vec![6]);
+
+        //Nothing is copied unless all of it fits
+        assert_eq!(heap.copy_cell(0, None, true).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        assert_eq!(heap.room(), 2);
+        assert_eq!(heap.copy_cell(7, None, false).unwrap_err().kind, ErrorKind::DataIsFree);
+    }
+
+    #[test]
+    fn says_which_cell_a_copy_was_refused_for() {
+        let mut heap: Heap = Heap::with_seed(3, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 1, None, 0).unwrap();
+        spec_alloc(&mut heap, 2, None, 1).unwrap();
+        let refused = |heap: &mut Heap, src: usize, dst: Option<usize>| {
+            let error: GcError = heap.copy_cell(src, dst, false).unwrap_err();
+            (error.kind, error.operation, error.cell)
+        };
+
+        assert_eq!(refused(&mut heap, 5, None), (ErrorKind::OutOfBounds, Operation::Copy, Some(5)));
+        assert_eq!(refused(&mut heap, 0, Some(5)), (ErrorKind::OutOfBounds, Operation::Copy, Some(5)));
+        assert_eq!(refused(&mut heap, 0, Some(1)), (ErrorKind::Occupied, Operation::Copy, Some(1)));
+        assert_eq!(refused(&mut heap, 2, Some(1)), (ErrorKind::DataIsFree, Operation::Copy, Some(2)));      //The source is checked first
+        assert_eq!(heap.copy_cell(0, Some(2), false).unwrap(), vec![(0, 2)]);
+        assert_eq!(refused(&mut heap, 0, None), (ErrorKind::NoFreeMemory, Operation::Copy, None));
+    }
+}
@@ -0,0 +1,275 @@
+//Containers on the heap
+/*
+    Lists and records are the heap's building blocks, but using them from Rust means juggling cell indexes.
+    GcVec and GcMap wrap them in the API a Rust programmer expects, so an embedder can build a realistic object graph
+    (i.e. a vector of records, or a map from names to strings) in a few lines:
+
+        let names = GcMap::new(&mut heap)?;
+        let alice = heap.alloc_str("alice", None)?;
+        names.insert(&mut heap, "first", heap.handle(alice).unwrap())?;
+
+    Everything lives on the heap -> a GcVec is a list cell whose references are its elements, and a GcMap is a record
+    (called "map") with a field per key, each referencing its value. So the collectors trace them like any other object,
+    and an element is garbage once no container (or anything else) references it. The container itself is only a
+    handle, so it goes stale like one if its cell is freed, swept or moved.
+
+    Anything holding objects from Rust can implement Trace, reporting the cells it keeps alive. `Heap::add_traced_root()`
+    makes one a root (through a root provider, see roots.rs), i.e. a container the program holds on to outside the heap.
+*/
+
+use crate::{
+    error::{ErrorKind, GcError, Operation},
+    handle::Handle,
+    lists::ListError,
+    records::RecordError,
+    roots::RootVisitor,
+    Heap,
+};
+use crate::prelude::*;
+
+/// Anything that holds objects on the heap, and can report the cells it keeps alive
+pub trait Trace {
+    fn trace(&self, visitor: &mut dyn RootVisitor);
+}
+
+/// A growable array of objects on the heap, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcVec {
+    handle: Handle,                 //The list cell backing it
+}
+
+/// A map from names to objects on the heap, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcMap {
+    handle: Handle,                 //The record cell backing it, with a field per key
+}
+
+impl Trace for Handle {
+    fn trace(&self, visitor: &mut dyn RootVisitor) {
+        visitor.visit(self.index);
+    }
+}
+
+impl Trace for GcVec {
+    fn trace(&self, visitor: &mut dyn RootVisitor) {
+        self.handle.trace(visitor);                 //The elements are reached through the list's references
+    }
+}
+
+impl Trace for GcMap {
+    fn trace(&self, visitor: &mut dyn RootVisitor) {
+        self.handle.trace(visitor);
+    }
+}
+
+impl<T: Trace> Trace for Vec<T> {
+    fn trace(&self, visitor: &mut dyn RootVisitor) {
+        for item in self {
+            item.trace(visitor);
+        }
+    }
+}
+
+impl<T: Trace> Trace for Option<T> {
+    fn trace(&self, visitor: &mut dyn RootVisitor) {
+        if let Some(item) = self {
+            item.trace(visitor);
+        }
+    }
+}
+
+impl Heap {
+    /// Keeps everything `traced` reports alive from now on, as roots (see `add_root_provider()`).
+    /// A copying collection moves them and leaves `traced` holding stale handles, so this suits the other algorithms
+    pub fn add_traced_root(&mut self, traced: impl Trace + Send + 'static) {
+        self.add_root_provider(move |visitor| traced.trace(visitor));
+    }
+}
+
+impl GcVec {
+    /// Allocates an empty vector (collecting first if the heap is full, like `Heap::alloc()`)
+    pub fn new(heap: &mut Heap) -> Result<GcVec, GcError> {
+        let handle: Handle = heap.alloc(0)?;
+        heap.make_list(handle.index);
+        Ok(GcVec { handle })
+    }
+
+    /// The list cell backing the vector
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Adds an object to the end of the vector.
+    /// Returns `Occupied` error if it is already in the vector (a list references each cell at most once)
+    pub fn push(&self, heap: &mut Heap, element: Handle) -> Result<(), GcError> {
+        let (list, element) = (heap.resolve(self.handle)?, heap.resolve(element)?);
+        heap.list_push(list, element).map(|_| ()).map_err(|why| match why {
+            ListError::AlreadyInList => GcError::new(ErrorKind::Occupied, Operation::Link, Some(element)),
+            ListError::NotAList | ListError::ElementIsFree => unreachable!("both handles were just resolved"),
+        })
+    }
+
+    /// Removes the last object from the vector, returning it (None if the vector was empty, or if reference counting
+    /// reclaimed it straight away because nothing else referenced it)
+    pub fn pop(&self, heap: &mut Heap) -> Result<Option<Handle>, GcError> {
+        let list: usize = heap.resolve(self.handle)?;
+        let popped: Option<usize> = heap.list_pop(list).expect("the handle was just resolved to a list");
+        Ok(popped.and_then(|index| heap.handle(index)))
+    }
+
+    /// The object at position `i`, or None if the vector isn't that long
+    pub fn get(&self, heap: &Heap, i: usize) -> Result<Option<Handle>, GcError> {
+        Ok(self.elements(heap)?.get(i).and_then(|&index| heap.handle(index)))
+    }
+
+    /// How many objects are in the vector
+    pub fn len(&self, heap: &Heap) -> Result<usize, GcError> {
+        Ok(self.elements(heap)?.len())
+    }
+
+    pub fn is_empty(&self, heap: &Heap) -> Result<bool, GcError> {
+        Ok(self.len(heap)? == 0)
+    }
+
+    /// Every object in the vector, in order
+    pub fn iter<'h>(&self, heap: &'h Heap) -> Result<impl Iterator<Item = Handle> + 'h, GcError> {
+        Ok(self.elements(heap)?.iter().filter_map(|&index| heap.handle(index)))
+    }
+
+    fn elements<'h>(&self, heap: &'h Heap) -> Result<&'h [usize], GcError> {
+        let list: usize = heap.resolve(self.handle)?;
+        Ok(heap.list(list).expect("the handle was just resolved to a list"))
+    }
+}
+
+impl GcMap {
+    /// Allocates an empty map (collecting first if the heap is full, like `Heap::alloc()`)
+    pub fn new(heap: &mut Heap) -> Result<GcMap, GcError> {
+        let handle: Handle = heap.alloc(0)?;
+        heap.make_record(handle.index, "map", &[]);
+        Ok(GcMap { handle })
+    }
+
+    /// The record cell backing the map
+    pub fn handle(&self) -> Handle {
+        self.handle
+    }
+
+    /// Maps `key` to an object, returning the object it was mapped to before (if any)
+    pub fn insert(&self, heap: &mut Heap, key: &str, value: Handle) -> Result<Option<Handle>, GcError> {
+        let (map, value) = (heap.resolve(self.handle)?, heap.resolve(value)?);
+        let old: Option<Handle> = self.get(heap, key)?;
+        if old.is_none() {
+            heap.add_field(map, key);
+        }
+
+        heap.field_set(map, key, Some(value)).map_err(|why| unexpected(why, map))?;
+        Ok(old.filter(|&old| heap.resolve(old).is_ok()))
+    }
+
+    /// The object `key` is mapped to, if any
+    pub fn get(&self, heap: &Heap, key: &str) -> Result<Option<Handle>, GcError> {
+        let map: usize = heap.resolve(self.handle)?;
+        let record = heap.record(map).expect("the handle was just resolved to a record");
+        Ok(record.fields.iter().find(|field| field.name == key).and_then(|field| field.to).and_then(|index| heap.handle(index)))
+    }
+
+    /// Removes `key` from the map, returning the object it was mapped to (if any).
+    /// Like dropping any other reference, the object is garbage afterwards if nothing else references it
+    pub fn remove(&self, heap: &mut Heap, key: &str) -> Result<Option<Handle>, GcError> {
+        let old: Option<Handle> = self.get(heap, key)?;
+        let map: usize = heap.resolve(self.handle)?;
+        if heap.record(map).is_some_and(|record| record.fields.iter().any(|field| field.name == key)) {
+            heap.field_set(map, key, None).map_err(|why| unexpected(why, map))?;
+            heap.remove_field(map, key);
+        }
+        Ok(old.filter(|&old| heap.resolve(old).is_ok()))
+    }
+
+    /// How many keys are in the map
+    pub fn len(&self, heap: &Heap) -> Result<usize, GcError> {
+        let map: usize = heap.resolve(self.handle)?;
+        Ok(heap.record(map).map_or(0, |record| record.fields.len()))
+    }
+
+    pub fn is_empty(&self, heap: &Heap) -> Result<bool, GcError> {
+        Ok(self.len(heap)? == 0)
+    }
+
+    /// Every key and the object it is mapped to, in the order the keys were first inserted
+    pub fn iter<'h>(&self, heap: &'h Heap) -> Result<impl Iterator<Item = (&'h str, Handle)> + 'h, GcError> {
+        let map: usize = heap.resolve(self.handle)?;
+        let record = heap.record(map).expect("the handle was just resolved to a record");
+        Ok(record
+            .fields
+            .iter()
+            .filter_map(|field| Some((field.name.as_str(), heap.handle(field.to?)?))))
+    }
+}
+
+//The map and value were both resolved first, so the record can't refuse the change
+fn unexpected(why: RecordError, map: usize) -> GcError {
+    unreachable!("map {} refused a change to one of its fields: {:?}", map, why)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect;
+
+    #[test]
+    fn keeps_what_heap_backed_containers_hold() {
+        let mut heap: Heap = Heap::with_seed(16, 1);
+        heap.log.echo = false;
+
+        let people = GcVec::new(&mut heap).unwrap();
+        for age in [30, 40] {
+            let person: usize = heap.record_new("person", &[("age", None)], None).unwrap();
+            let age: Handle = heap.alloc(age).unwrap();
+            heap.field_set(person, "age", Some(age.index)).unwrap();
+            let person: Handle = heap.handle(person).unwrap();
+            people.push(&mut heap, person).unwrap();
+        }
+        let names = GcMap::new(&mut heap).unwrap();
+        for (key, name) in [("first", "alice"), ("last", "smith")] {
+            let name: usize = heap.alloc_str(name, None).unwrap();
+            let name: Handle = heap.handle(name).unwrap();
+            assert_eq!(names.insert(&mut heap, key, name).unwrap(), None);
+        }
+        heap.add_traced_root(vec![people.handle(), names.handle()]);
+
+        assert_eq!((people.len(&heap).unwrap(), names.len(&heap).unwrap()), (2, 2));
+        let first: Handle = people.get(&heap, 0).unwrap().unwrap();
+        assert!(people.push(&mut heap, first).is_err_and(|e| e.kind == ErrorKind::Occupied));
+        let last: Handle = names.get(&heap, "last").unwrap().unwrap();
+        assert_eq!(names.iter(&heap).unwrap().map(|(key, _)| key).collect::<Vec<_>>(), ["first", "last"]);
+        collect(&mut heap);
+        assert_eq!(heap.history.last().unwrap().swept, 0);
+
+        //Whatever a container lets go of is garbage, unless something else still holds it
+        let popped: Handle = people.pop(&mut heap).unwrap().unwrap();
+        assert_eq!(names.remove(&mut heap, "last").unwrap(), Some(last));
+        assert_eq!(names.get(&heap, "last").unwrap(), None);
+        collect(&mut heap);
+        assert_eq!(heap.history.last().unwrap().swept, 5);          //The record and its age, and "smith" (a head and 2 cells of bytes)
+        assert!(heap.resolve(popped).is_err() && heap.resolve(last).is_err());
+        assert_eq!(people.iter(&heap).unwrap().count() + names.len(&heap).unwrap(), 2);
+    }
+
+    #[test]
+    fn goes_stale_like_a_handle_once_collected() {
+        let mut heap: Heap = Heap::with_seed(3, 1);
+        heap.log.echo = false;
+        let (vec, map) = (GcVec::new(&mut heap).unwrap(), GcMap::new(&mut heap).unwrap());
+        let element: Handle = heap.alloc(1).unwrap();
+        vec.push(&mut heap, element).unwrap();
+        map.insert(&mut heap, "one", element).unwrap();
+
+        //Neither is rooted, so both go, along with the element
+        collect(&mut heap);
+        assert_eq!(vec.len(&heap).unwrap_err().kind, ErrorKind::Stale);
+        assert_eq!(map.get(&heap, "one").unwrap_err().kind, ErrorKind::Stale);
+        let fresh: GcVec = GcVec::new(&mut heap).unwrap();
+        assert_eq!(fresh.push(&mut heap, element).unwrap_err().kind, ErrorKind::Stale);
+    }
+}
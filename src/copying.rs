@@ -0,0 +1,378 @@
+//Copying (semi-space) collection
+/*
+    Rather than freeing garbage where it lies, a copying collector evacuates every cell that is still reachable
+    into a fresh "to-space", packed together from the start, then throws the old "from-space" away as a whole.
+
+    Garbage is never even looked at, and the free memory left over is one contiguous block (no fragmentation),
+    but every surviving cell MOVES -> its index changes, and every reference to it has to be updated.
+
+    This uses Cheney's algorithm, where the to-space itself is the worklist: cells are scanned left to right,
+    copying whatever they reference onto the end, until the scan catches up with the end.
+
+    To-space is every cell allocations can use, which leaves out the survivor spaces (see generational.rs). Cells
+    in them are live too, so there can be more to copy than to-space holds. Rather than give up, the collection
+    evacuates what fits and leaves the rest where they are, the way production collectors degrade when to-space is
+    exhausted -> the cells it did copy are packed around them, and the collection is reported as partial.
+*/
+
+use core::{mem, time::Duration};
+
+use crate::{error::{ErrorKind, GcError, Operation}, generational::CollectionKind, log::Event, stats::GcCycle, Algorithm, Cell, Heap, Instant};
+use crate::prelude::*;
+
+/// Runs a copying collection over the heap, returning the cycle (`number` is filled in by `collect()`).
+/// The `mark` phase of the cycle is the time spent evacuating, and the `sweep` phase is the time spent
+/// updating references and flipping the spaces
+pub fn collect(heap: &mut Heap) -> GcCycle {
+    //Find the roots to start evacuating from
+    let start = Instant::now();
+    let roots: Vec<usize> = heap.roots();
+    let root_scan = start.elapsed();
+
+    //Evacuate the roots, then everything reachable from them
+    let start = Instant::now();
+    let size: usize = heap.cells.len();
+    let mut to_space: Vec<Cell> = Vec::with_capacity(size);
+    let mut forwarding: Vec<Option<usize>> = vec![None; size];     //Where each cell was copied to (its forwarding address)
+
+    for root in roots {
+        evacuate(&heap.cells, &mut to_space, &mut forwarding, root);
+    }
+
+    let mut scan: usize = 0;
+    while scan < to_space.len() {
+        for target in to_space[scan].pointers().to_vec() {
+            //A reference to a free cell is dangling, there is nothing there to copy
+            if !heap.cells[target].freed {
+                evacuate(&heap.cells, &mut to_space, &mut forwarding, target);
+            }
+        }
+        scan += 1;
+    }
+    let evacuate_time = start.elapsed();
+
+    //Copy as many as fit into to-space, leaving the rest where they are (see the top of this module)
+    let room: usize = size - heap.reserved().len();
+    let mut copied_from: Vec<usize> = vec![0; to_space.len()];
+    for (old, new) in forwarding.iter().enumerate() {
+        if let Some(new) = *new {
+            copied_from[new] = old;
+        }
+    }
+    let mut fits: usize = to_space.len().min(room);
+    let free: Vec<usize> = loop {
+        let left: &[usize] = &copied_from[fits..];
+        let free: Vec<usize> = (0..room).filter(|position| !left.contains(position)).collect();
+        if free.len() >= fits {
+            break free;                                             //Every cell left behind takes a cell of to-space with it
+        }
+        fits -= 1;
+    };
+    let position: Vec<usize> = (0..to_space.len()).map(|i| if i < fits { free[i] } else { copied_from[i] }).collect();
+    for new in forwarding.iter_mut().flatten() {
+        *new = position[*new];
+    }
+    let left_in_place: usize = to_space.len() - fits;
+
+    for index in (0..size).filter(|&old| forwarding[old].is_some()) {
+        heap.log.emit(Event::Marked { index });                     //Copying a cell is how this collector marks it live
+    }
+
+    //Point every reference at the new copies, dropping any to (or from) cells that weren't copied
+    let start = Instant::now();
+    for cell in to_space.iter_mut() {
+        cell.will_ref = cell.will_ref.iter().filter_map(|&old| forwarding[old]).collect();
+        if let Some(record) = &mut cell.record {
+            record.relocate(&forwarding);
+        }
+        let referrers: usize = cell.by_ref.len();
+        cell.by_ref = cell.by_ref.iter().filter_map(|&old| forwarding[old]).collect();
+        cell.reference_count -= (referrers - cell.by_ref.len()) as i32; //References from garbage go with it
+        cell.marked = true;                                         //Everything that was copied is live
+    }
+
+    let copied: usize = to_space.len();
+    let mut swept: usize = 0;
+    for (old, new) in forwarding.iter().enumerate() {
+        match new {
+            Some(new) if *new != old => heap.log.emit(Event::Moved { from: old, to: *new }),
+            None if !heap.cells[old].freed => {
+                swept += 1;                                         //Left behind in from-space -> reclaimed
+                heap.log.emit(Event::Swept { index: old });
+            }
+            _ => {}
+        }
+    }
+
+    //The rest of to-space is free, and it becomes the heap
+    let mut cells: Vec<Cell> = vec![Cell::new(); size];
+    for (i, cell) in to_space.into_iter().enumerate() {
+        cells[position[i]] = cell;
+    }
+
+    //Each position keeps its generation if the same data is still there, otherwise it is bumped,
+    //so handles to data that moved (or was left behind) go stale rather than pointing at whatever is there now
+    let mut origin: Vec<Option<usize>> = vec![None; size];
+    for (old, new) in forwarding.iter().enumerate() {
+        if let Some(new) = *new {
+            origin[new] = Some(old);
+        }
+    }
+    for (i, cell) in cells.iter_mut().enumerate() {
+        let before: &Cell = &heap.cells[i];
+        let replaced: bool = origin[i] != Some(i) && (!before.freed || origin[i].is_some());
+        cell.generation = before.generation.wrapping_add(u32::from(replaced));
+        if cell.freed && (replaced || before.is_poisoned()) {
+            cell.poison();                                          //Left behind, see poison.rs
+        }
+    }
+    heap.cells = cells;
+    heap.relocate_locals(&forwarding);
+    heap.relocate_roots(&forwarding);
+    heap.relocate_candidates(&forwarding);
+    heap.relocate_finalizers(&forwarding);
+    heap.relocate_weak_map(&forwarding);
+    heap.relocate_interned(&forwarding);
+    heap.relocate_rc_updates(&forwarding);
+    let flip_time = start.elapsed();
+    if left_in_place > 0 {
+        heap.log.emit(Event::EvacuationFailed { left: left_in_place });
+    }
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked: copied,
+        swept,
+        bytes_swept: 0,
+        root_scan,
+        mark: evacuate_time,
+        sweep: flip_time,
+        algorithm: Algorithm::Copying,
+        kind: CollectionKind::Whole,
+        left_in_place,
+    }
+}
+
+impl Heap {
+    /// Moves the object at `src` to the free cell `dst` by hand, the way a compacting collector moves each cell it keeps
+    /// (see `move_cells()`) -> every reference, root and local pointing at it is rewritten to point at `dst` instead,
+    /// `src` is left free, and handles to it go stale.
+    /// Returns `OutOfBounds` error if either isn't on the heap, `DataIsFree` error if `src` isn't in use, or `Occupied` error
+    /// if `dst` isn't free for allocation (including the survivor spaces, and stubs a relocation in progress has left)
+    pub fn move_cell(&mut self, src: usize, dst: usize) -> Result<(), GcError> {
+        let error = |kind: ErrorKind, cell: usize| GcError::new(kind, Operation::Move, Some(cell));
+        for index in [src, dst] {
+            if index >= self.cells.len() {
+                return Err(error(ErrorKind::OutOfBounds, index));
+            }
+        }
+        if self.cells[src].freed {
+            return Err(error(ErrorKind::DataIsFree, src));
+        }
+        if !self.cells[dst].freed || self.cells[dst].forward.is_some() || self.reserved().contains(&dst) {
+            return Err(error(ErrorKind::Occupied, dst));
+        }
+        move_cells(self, &[(src, dst)]);
+        Ok(())
+    }
+
+    /// Exchanges the objects in cells `a` and `b` -> each is moved to where the other was, as in `move_cell()`, and
+    /// every reference, root and local pointing at one is rewritten to point at the other. Handles to either go stale.
+    /// One of them can be free, in which case this is a move of the other (and the same rules apply to the free one).
+    /// Returns `OutOfBounds` error if either isn't on the heap, `DataIsFree` error if neither is in use, or `Occupied` error
+    /// if either is a stub a relocation in progress has left
+    pub fn swap_cells(&mut self, a: usize, b: usize) -> Result<(), GcError> {
+        let error = |kind: ErrorKind, cell: usize| GcError::new(kind, Operation::Swap, Some(cell));
+        for index in [a, b] {
+            if index >= self.cells.len() {
+                return Err(error(ErrorKind::OutOfBounds, index));
+            }
+            if self.cells[index].forward.is_some() {
+                return Err(error(ErrorKind::Occupied, index));
+            }
+        }
+        match (self.cells[a].freed, self.cells[b].freed) {
+            (true, true) => return Err(error(ErrorKind::DataIsFree, a)),
+            _ if a == b => return Ok(()),
+            (true, false) if self.reserved().contains(&a) => return Err(error(ErrorKind::Occupied, a)),
+            (false, true) if self.reserved().contains(&b) => return Err(error(ErrorKind::Occupied, b)),
+            _ => {}
+        }
+
+        let generations: (u32, u32) = (self.cells[a].generation, self.cells[b].generation);
+        self.cells.swap(a, b);
+        self.cells[a].generation = generations.0.wrapping_add(1);
+        self.cells[b].generation = generations.1.wrapping_add(1);
+        self.treadmill_swapped(a, b);
+        self.log.emit(Event::Moved { from: a, to: b });
+        self.log.emit(Event::Moved { from: b, to: a });
+
+        let mut forwarding: Vec<Option<usize>> = (0..self.cells.len()).map(Some).collect();
+        forwarding.swap(a, b);
+        forward_references(self, &forwarding);
+        Ok(())
+    }
+}
+
+/// Moves cells in use to free cells elsewhere in the pool (`moves` pairs each cell with the free cell it goes to),
+/// then points every reference, root and local at where they went. The generational collector copies survivors with it.
+/// As in a copying collection, the generation of every position whose data changed is bumped, and the cells left behind are poisoned
+pub(crate) fn move_cells(heap: &mut Heap, moves: &[(usize, usize)]) {
+    let mut forwarding: Vec<Option<usize>> = (0..heap.cells.len()).map(Some).collect();
+    for &(from, to) in moves {
+        let generation: u32 = heap.cells[to].generation.wrapping_add(1);
+        let mut cell: Cell = mem::take(&mut heap.cells[from]);
+        heap.cells[from].generation = cell.generation.wrapping_add(1);
+        heap.cells[from].poison();
+        cell.generation = generation;
+        heap.cells[to] = cell;
+        forwarding[from] = Some(to);
+        heap.treadmill_moved(from, to);
+        heap.log.emit(Event::Moved { from, to });
+    }
+    forward_references(heap, &forwarding);
+}
+
+//Points every reference, root and local at where `forwarding` says its cell went
+fn forward_references(heap: &mut Heap, forwarding: &[Option<usize>]) {
+    let forward = |index: &usize| forwarding[*index].expect("every position is forwarded");
+    for cell in heap.cells.iter_mut() {
+        cell.will_ref = cell.will_ref.iter().map(forward).collect();
+        cell.by_ref = cell.by_ref.iter().map(forward).collect();
+        if let Some(record) = &mut cell.record {
+            record.relocate(forwarding);
+        }
+    }
+    heap.remembered = heap.remembered.iter().map(forward).collect();
+    heap.relocate_locals(forwarding);
+    heap.relocate_roots(forwarding);
+    heap.relocate_candidates(forwarding);
+    heap.relocate_finalizers(forwarding);
+    heap.relocate_weak_map(forwarding);
+    heap.relocate_interned(forwarding);
+    heap.relocate_rc_updates(forwarding);
+}
+
+//Copies a cell onto the end of to-space, unless it has already been copied
+fn evacuate(from_space: &[Cell], to_space: &mut Vec<Cell>, forwarding: &mut [Option<usize>], index: usize) {
+    if forwarding[index].is_none() {
+        forwarding[index] = Some(to_space.len());
+        to_space.push(from_space[index].clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, configure_roots, roots, spec_alloc, tests::graph};
+
+
+    #[test]
+    fn leaves_what_to_space_has_no_room_for_where_it_is() {
+        //Cells in the survivor spaces are live too, but a copying collection only has the rest of the heap to copy into
+        let mut heap: Heap = graph(10, &[]);
+        heap.log.echo = false;
+        configure_roots(&mut heap, roots::GLOBALS, &(0..10).collect::<Vec<_>>());
+        heap.policy.survivor_size = Some(3);                            //Cells 8 to 13, so 2 of the 10 don't fit
+        heap.policy.algorithm = Algorithm::Copying;
+        crate::collect(&mut heap);
+        assert_eq!(heap.history[0].left_in_place, 2);
+        assert!((0..10).all(|i| !heap.cells[i].freed) && heap.verify().is_empty());
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::EvacuationFailed { left: 2 })));
+
+        //Once they fit, nothing is left behind
+        heap.policy.survivor_size = None;
+        crate::collect(&mut heap);
+        assert!(!heap.history[1].is_partial());
+        assert_eq!(heap.log.since(0).filter(|event| matches!(event, Event::EvacuationFailed { .. })).count(), 1);
+    }
+
+    #[test]
+    fn moves_a_cell_and_everything_pointing_at_it() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        for data in 0..3 {
+            spec_alloc(&mut heap, data, None, data as usize).unwrap();
+        }
+        configure_roots(&mut heap, roots::GLOBALS, &[1]);
+        assign_reference(&mut heap, 0, 1);
+        assign_reference(&mut heap, 1, 2);
+        let handle = heap.handle(1).unwrap();
+
+        heap.move_cell(1, 4).unwrap();
+        assert!(heap.cells[1].freed);
+        assert_eq!((heap.cells[4].data, heap.cells[0].will_ref.clone(), heap.cells[2].by_ref.clone()), (Some(1), vec![4], vec![4]));
+        assert_eq!(heap.roots(), vec![4]);
+        assert!(heap.resolve(handle).is_err());                         //Handles to the old cell go stale
+
+        assert_eq!(heap.move_cell(4, 0).unwrap_err().kind, ErrorKind::Occupied);
+        assert_eq!(heap.move_cell(1, 5).unwrap_err().kind, ErrorKind::DataIsFree);
+    }
+
+    #[test]
+    fn refuses_to_move_into_cells_that_arent_free_for_allocation() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 1, None, 0).unwrap();
+        heap.policy.survivor_size = Some(1);                            //Cells 4 and 5
+        let refused = |heap: &mut Heap, src: usize, dst: usize| {
+            let error: GcError = heap.move_cell(src, dst).unwrap_err();
+            (error.kind, error.operation, error.cell)
+        };
+
+        assert_eq!(refused(&mut heap, 9, 1), (ErrorKind::OutOfBounds, Operation::Move, Some(9)));
+        assert_eq!(refused(&mut heap, 0, 9), (ErrorKind::OutOfBounds, Operation::Move, Some(9)));
+        assert_eq!(refused(&mut heap, 0, 5), (ErrorKind::Occupied, Operation::Move, Some(5)));
+        assert_eq!(refused(&mut heap, 0, 0), (ErrorKind::Occupied, Operation::Move, Some(0)));
+        assert!(!heap.cells[0].freed && !heap.log.since(0).any(|event| matches!(event, Event::Moved { .. })));
+    }
+
+    #[test]
+    fn swaps_two_cells_and_the_references_to_them() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        heap.log.echo = false;
+        for data in 0..3 {
+            spec_alloc(&mut heap, data, None, data as usize).unwrap();
+        }
+        configure_roots(&mut heap, roots::GLOBALS, &[0]);
+        assign_reference(&mut heap, 0, 2);
+        assign_reference(&mut heap, 2, 1);
+        assign_reference(&mut heap, 1, 0);
+
+        heap.swap_cells(0, 2).unwrap();
+        assert_eq!((heap.cells[0].data, heap.cells[2].data), (Some(2), Some(0)));
+        assert_eq!(heap.roots(), vec![2]);
+        assert_eq!((heap.cells[2].will_ref.clone(), heap.cells[0].will_ref.clone(), heap.cells[1].will_ref.clone()), (vec![0], vec![1], vec![2]));
+        assert_eq!((heap.cells[0].by_ref.clone(), heap.cells[2].by_ref.clone()), (vec![2], vec![1]));
+
+        //With a free cell it is a move
+        heap.swap_cells(3, 1).unwrap();
+        assert!(heap.cells[1].freed);
+        assert_eq!((heap.cells[0].will_ref.clone(), heap.cells[3].will_ref.clone()), (vec![3], vec![2]));
+        assert_eq!(heap.swap_cells(1, 1).unwrap_err().kind, ErrorKind::DataIsFree);
+    }
+
+    #[test]
+    fn refuses_to_swap_with_stubs_and_reserved_cells() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 1, None, 0).unwrap();
+        heap.policy.survivor_size = Some(1);                            //Cells 4 and 5
+        heap.cells[2].forward = Some(0);                                //A stub a relocation left
+        let refused = |heap: &mut Heap, a: usize, b: usize| {
+            let error: GcError = heap.swap_cells(a, b).unwrap_err();
+            (error.kind, error.operation, error.cell)
+        };
+
+        assert_eq!(refused(&mut heap, 0, 9), (ErrorKind::OutOfBounds, Operation::Swap, Some(9)));
+        assert_eq!(refused(&mut heap, 0, 2), (ErrorKind::Occupied, Operation::Swap, Some(2)));
+        assert_eq!(refused(&mut heap, 5, 0), (ErrorKind::Occupied, Operation::Swap, Some(5)));
+        assert_eq!(refused(&mut heap, 1, 3), (ErrorKind::DataIsFree, Operation::Swap, Some(1)));
+
+        //Swapping a cell with itself changes nothing, not even its generation
+        let generation: u32 = heap.cells[0].generation;
+        heap.swap_cells(0, 0).unwrap();
+        assert_eq!((heap.cells[0].data, heap.cells[0].generation), (Some(1), generation));
+    }
+}
@@ -0,0 +1,172 @@
+//Comparing two states of the virtual heap
+/*
+    Captures exactly what changed between a "before" and "after" copy of the memory pool,
+    i.e. around a collection, so each GC run has a clear and reviewable outcome
+    (or between any two steps of a session, see --diff).
+*/
+
+use core::fmt;
+
+use crate::Cell;
+use crate::prelude::*;
+
+/// What changed between two states of the heap's memory pool
+#[derive(Clone, Debug, Default)]
+pub struct HeapDiff {
+    pub swept: Vec<usize>,          //Cells that held data before, and are free after (reclaimed)
+    pub allocated: Vec<usize>,      //Cells that were free before, and hold data after
+    pub newly_marked: Vec<usize>,   //Cells that were unmarked (garbage) before, and are marked after (kept)
+    pub unmarked: Vec<usize>,       //Cells that were marked before, and are unmarked after
+    pub free_before: Vec<usize>,    //The free list before
+    pub free_after: Vec<usize>,     //The free list after
+    pub linked: Vec<(usize, usize)>, //References (from, to) made by cells in use after, that weren't there before
+    pub unlinked: Vec<(usize, usize)>, //References removed from cells that are in use before and after (a freed cell's go with it)
+    pub data_changed: Vec<usize>,   //Cells in use before and after, whose data is different
+}
+
+impl HeapDiff {
+    /// Compares two copies of the memory pool.
+    /// If the pools are different sizes, only the cells both of them have are compared
+    pub fn between(before: &[Cell], after: &[Cell]) -> HeapDiff {
+        let mut diff = HeapDiff {
+            free_before: free_list(before),
+            free_after: free_list(after),
+            ..HeapDiff::default()
+        };
+
+        for (i, (old, new)) in before.iter().zip(after.iter()).enumerate() {
+            if !old.freed && new.freed {
+                diff.swept.push(i);
+            }
+            if old.freed && !new.freed {
+                diff.allocated.push(i);
+            }
+            //Free cells are never "kept", so marks only matter on cells in use
+            if !old.marked && new.marked && !new.freed {
+                diff.newly_marked.push(i);
+            }
+            if old.marked && !new.marked && !new.freed {
+                diff.unmarked.push(i);
+            }
+
+            //References only count from cells in use, a free cell's leftovers don't mean anything
+            let before: &[usize] = if old.freed { &[] } else { &old.will_ref };
+            if !new.freed {
+                diff.linked.extend(new.will_ref.iter().filter(|to| !before.contains(to)).map(|&to| (i, to)));
+            }
+            if !old.freed && !new.freed {
+                diff.unlinked.extend(old.will_ref.iter().filter(|to| !new.will_ref.contains(to)).map(|&to| (i, to)));
+                if old.data != new.data {
+                    diff.data_changed.push(i);
+                }
+            }
+        }
+
+        diff
+    }
+
+    /// True if nothing changed
+    pub fn is_empty(&self) -> bool {
+        self.swept.is_empty()
+            && self.allocated.is_empty()
+            && self.newly_marked.is_empty()
+            && self.unmarked.is_empty()
+            && self.linked.is_empty()
+            && self.unlinked.is_empty()
+            && self.data_changed.is_empty()
+            && self.free_before == self.free_after
+    }
+}
+
+//Every free cell, in index order
+fn free_list(cells: &[Cell]) -> Vec<usize> {
+    (0..cells.len()).filter(|&i| cells[i].freed).collect()
+}
+
+//"3: 1, 2, 5" -> amount of cells, then the cells themselves
+fn cell_list(cells: &[usize]) -> String {
+    let listed: Vec<String> = cells.iter().map(|i| i.to_string()).collect();
+    format!("{}: {}", cells.len(), listed.join(", "))
+}
+
+//"2: 0 -> 1, 3 -> 1" -> amount of references, then each of them
+fn edge_list(edges: &[(usize, usize)]) -> String {
+    let listed: Vec<String> = edges.iter().map(|(from, to)| format!("{} -> {}", from, to)).collect();
+    format!("{}: {}", edges.len(), listed.join(", "))
+}
+
+impl fmt::Display for HeapDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "No changes");
+        }
+
+        //Only show the kinds of change that actually happened
+        if !self.swept.is_empty() {
+            writeln!(f, "    Swept ({})", cell_list(&self.swept))?;
+        }
+        if !self.allocated.is_empty() {
+            writeln!(f, "    Allocated ({})", cell_list(&self.allocated))?;
+        }
+        if !self.newly_marked.is_empty() {
+            writeln!(f, "    Marked, previously garbage ({})", cell_list(&self.newly_marked))?;
+        }
+        if !self.unmarked.is_empty() {
+            writeln!(f, "    Unmarked, previously marked ({})", cell_list(&self.unmarked))?;
+        }
+        if !self.linked.is_empty() {
+            writeln!(f, "    Linked ({})", edge_list(&self.linked))?;
+        }
+        if !self.unlinked.is_empty() {
+            writeln!(f, "    Unlinked ({})", edge_list(&self.unlinked))?;
+        }
+        if !self.data_changed.is_empty() {
+            writeln!(f, "    Data changed ({})", cell_list(&self.data_changed))?;
+        }
+
+        let change = self.free_after.len() as isize - self.free_before.len() as isize;
+        writeln!(
+            f,
+            "    Free cells: {} -> {} ({:+})",
+            self.free_before.len(),
+            self.free_after.len(),
+            change
+        )?;
+        writeln!(f, "        Before ({})", cell_list(&self.free_before))?;
+        write!(f, "        After ({})", cell_list(&self.free_after))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, collect, set_data, tests::graph, unlink_reference, Heap};
+
+    #[test]
+    fn reports_what_a_collection_swept_and_what_it_kept() {
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2), (3, 2)]);
+        heap.log.echo = false;
+        let before: Vec<Cell> = heap.cells.clone();
+
+        collect(&mut heap);
+        let diff: HeapDiff = HeapDiff::between(&before, &heap.cells);
+        assert_eq!(diff.swept, [3]);
+        assert_eq!((diff.free_before.len(), diff.free_after.len()), (4, 5));
+        assert!(diff.allocated.is_empty() && diff.unlinked.is_empty());     //Cell 3 was freed, its reference goes with it
+        assert!(diff.to_string().contains("Swept (1: 3)"));
+    }
+
+    #[test]
+    fn reports_references_and_data_that_changed() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        let before: Vec<Cell> = heap.cells.clone();
+        assert_eq!(HeapDiff::between(&before, &heap.cells).to_string(), "No changes");
+
+        unlink_reference(&mut heap, 0, 1);
+        assign_reference(&mut heap, 0, 2);
+        set_data(&mut heap, 1, 9).unwrap();
+        let diff: HeapDiff = HeapDiff::between(&before, &heap.cells);
+        assert_eq!((diff.linked, diff.unlinked, diff.data_changed), (vec![(0, 2)], vec![(0, 1)], vec![1]));
+    }
+}
@@ -0,0 +1,176 @@
+//Dominator tree of the reference graph
+/*
+    Cell a dominates cell b when every chain of references from the roots to b passes through a.
+    Cutting a's incoming references would then leave b unreachable too, so the cells a dominates
+    are exactly what a keeps alive on its own -> its retained size.
+
+    Built with the iterative algorithm from Cooper, Harvey and Kennedy ("A Simple, Fast Dominance Algorithm"),
+    over the graph of cells in use plus a virtual root that references every real root, so the heap
+    always has a single entry point even when it has several roots.
+*/
+
+use crate::Heap;
+use crate::prelude::*;
+
+/// Immediate dominator of every cell reachable from the roots
+#[derive(Clone, Debug)]
+pub struct DominatorTree {
+    idom: Vec<Option<usize>>,       //Immediate dominator of each cell (the virtual root is cells.len()), None if unreachable
+    children: Vec<Vec<usize>>,      //The tree itself -> cells each cell immediately dominates
+}
+
+impl DominatorTree {
+    /// Builds the dominator tree for the heap as it is now. Nothing on the heap is marked or changed
+    pub fn build(heap: &Heap) -> DominatorTree {
+        let size: usize = heap.cells.len();
+        let virtual_root: usize = size;
+
+        //Edges between cells in use, plus the virtual root -> every real root
+        let successors = |node: usize| -> Vec<usize> {
+            let targets: Vec<usize> = match node == virtual_root {
+                true => heap.roots(),
+                false => heap.cells[node].pointers().to_vec(),
+            };
+            targets.into_iter().filter(|&target| !heap.cells[target].freed).collect()
+        };
+
+        //Depth first from the virtual root for a postorder numbering (iteratively, as the graph can be deep)
+        let mut postorder: Vec<usize> = Vec::new();
+        let mut number: Vec<Option<usize>> = vec![None; size + 1];
+        let mut visited: Vec<bool> = vec![false; size + 1];
+        let mut stack: Vec<(usize, Vec<usize>)> = vec![(virtual_root, successors(virtual_root))];
+        visited[virtual_root] = true;
+
+        while let Some((node, remaining)) = stack.last_mut() {
+            match remaining.pop() {
+                Some(next) if !visited[next] => {
+                    visited[next] = true;
+                    stack.push((next, successors(next)));
+                }
+                Some(_) => {}
+                None => {
+                    number[*node] = Some(postorder.len());
+                    postorder.push(*node);
+                    stack.pop();
+                }
+            }
+        }
+
+        //Predecessors, only between nodes the traversal reached
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); size + 1];
+        for &node in &postorder {
+            for target in successors(node) {
+                predecessors[target].push(node);
+            }
+        }
+
+        //Refine the dominators in reverse postorder until nothing changes
+        let mut idom: Vec<Option<usize>> = vec![None; size + 1];
+        idom[virtual_root] = Some(virtual_root);
+        let mut changed: bool = true;
+
+        while changed {
+            changed = false;
+
+            for &node in postorder.iter().rev().skip(1) {
+                let mut processed = predecessors[node].iter().copied().filter(|&p| idom[p].is_some());
+                let first: usize = match processed.next() {
+                    Some(first) => first,
+                    None => continue,
+                };
+                let new_idom: usize = processed.fold(first, |a, b| intersect(&idom, &number, a, b));
+
+                if idom[node] != Some(new_idom) {
+                    idom[node] = Some(new_idom);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); size + 1];
+        for (node, parent) in idom.iter().enumerate().take(size) {
+            if let Some(parent) = parent {
+                children[*parent].push(node);
+            }
+        }
+
+        DominatorTree { idom, children }
+    }
+
+    /// The cell that immediately dominates `index` -> the last cell every chain from the roots has to pass through.
+    /// None for roots (only the roots as a whole dominate them), unreachable cells, and out of range indexes
+    pub fn immediate_dominator(&self, index: usize) -> Option<usize> {
+        let virtual_root: usize = self.idom.len() - 1;
+        match self.idom.get(index).copied().flatten() {
+            Some(parent) if index != virtual_root && parent != virtual_root => Some(parent),
+            _ => None,
+        }
+    }
+
+    /// Every cell that would become unreachable if `index`'s incoming references were cut, including `index` itself.
+    /// Empty if `index` isn't reachable in the first place
+    pub fn retained(&self, index: usize) -> Vec<usize> {
+        let virtual_root: usize = self.idom.len() - 1;
+        if index >= virtual_root || self.idom[index].is_none() {
+            return Vec::new();
+        }
+
+        let mut retained: Vec<usize> = Vec::new();
+        let mut stack: Vec<usize> = vec![index];
+        while let Some(node) = stack.pop() {
+            retained.push(node);
+            stack.extend(&self.children[node]);
+        }
+
+        retained.sort_unstable();
+        retained
+    }
+}
+
+//Walks both fingers up the tree until they meet at the closest common dominator
+fn intersect(idom: &[Option<usize>], number: &[Option<usize>], mut a: usize, mut b: usize) -> usize {
+    while a != b {
+        while number[a] < number[b] {
+            a = idom[a].expect("processed nodes have a dominator");
+        }
+        while number[b] < number[a] {
+            b = idom[b].expect("processed nodes have a dominator");
+        }
+    }
+
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{roots::GLOBALS, tests::graph};
+
+    #[test]
+    fn retains_only_what_every_chain_passes_through() {
+        //0 -> 1 -> {2, 3}, 3 -> 4 and 0 -> 4, so 4 can be reached without 1
+        let mut heap: Heap = graph(6, &[(0, 1), (1, 2), (1, 3), (3, 4), (0, 4)]);
+        heap.log.echo = false;
+        let tree: DominatorTree = DominatorTree::build(&heap);
+
+        assert_eq!(tree.retained(1), [1, 2, 3]);
+        assert_eq!(tree.retained(0), [0, 1, 2, 3, 4]);
+        assert_eq!((tree.immediate_dominator(2), tree.immediate_dominator(4)), (Some(1), Some(0)));
+
+        //Roots, garbage (cell 5) and cells off the heap dominate nothing and have no dominator
+        assert_eq!(tree.immediate_dominator(0), None);
+        assert!(tree.retained(5).is_empty() && tree.immediate_dominator(5).is_none());
+        assert!(tree.retained(40).is_empty() && tree.immediate_dominator(40).is_none());
+    }
+
+    #[test]
+    fn shares_what_several_roots_reach() {
+        let mut heap: Heap = graph(3, &[(0, 2), (1, 2)]);
+        heap.log.echo = false;
+        heap.add_root(GLOBALS, 1).unwrap();
+        let tree: DominatorTree = DominatorTree::build(&heap);
+
+        assert_eq!((tree.retained(0), tree.retained(1)), (vec![0], vec![1]));
+        assert_eq!(tree.immediate_dominator(2), None);
+    }
+}
@@ -0,0 +1,125 @@
+//Errors
+/*
+    Everything that can go wrong on the heap itself is a GcError -> what went wrong (its kind), what was being done
+    at the time, and which cell it happened at (if there was one). It displays as a sentence the REPL can print
+    as it is, i.e. "Couldn't free cell 3, roots can't be freed, unless forced".
+
+    Callers that want to handle one kind of error in particular match on `kind`, and everything else can be
+    passed on with `?` -> GcError implements `core::error::Error` (the same trait as `std::error::Error`), so it fits in a `Box<dyn Error>` too.
+*/
+
+use core::{error, fmt};
+
+/// What went wrong, see `GcError`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    Occupied,           //Target space is occupied
+    NoFreeMemory,       //No free space was found to allocate memory
+    DataIsFree,         //The data is not in use, cannot mutate it
+    FreedRoot,          //Roots can't be freed (unless forced)
+    DoubleFree,         //The cell is already free, freeing it again would reset memory that may be handed out to something else
+    Stale,              //A handle to data that has since been freed, swept or moved (see handle.rs)
+    UseAfterFree,       //The cell was freed after being in use, and is poisoned until it is allocated again (see poison.rs)
+    OutOfBounds,        //There is no cell at that index
+}
+
+/// What was being done when an error happened
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    Alloc,              //Allocating data, at a given cell or the first free one
+    Link,               //Making a reference from one cell to another
+    Unlink,             //Removing a reference
+    Free,               //Freeing a cell, by hand or while sweeping
+    Write,              //Overwriting the data in a cell
+    Resolve,            //Following a handle to its cell
+    Copy,               //Cloning a cell (and what it reaches) into free cells
+    Move,               //Moving a cell to a free cell, by hand
+    Swap,               //Exchanging the objects in two cells, by hand
+    WeakMap,            //Putting an entry in the weak map
+    Finalize,           //Registering a finalizer on a cell
+}
+
+/// An error from the heap, with the operation that failed and the cell it failed at (if any)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GcError {
+    pub kind: ErrorKind,
+    pub operation: Operation,
+    pub cell: Option<usize>,
+}
+
+impl GcError {
+    pub fn new(kind: ErrorKind, operation: Operation, cell: Option<usize>) -> GcError {
+        GcError { kind, operation, cell }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ErrorKind::Occupied => "space is occupied",
+            ErrorKind::NoFreeMemory => "no free memory avaliable",
+            ErrorKind::DataIsFree => "the memory was free, not suitable for use",
+            ErrorKind::FreedRoot => "roots can't be freed, unless forced",
+            ErrorKind::DoubleFree => "the memory was already free, it can't be freed twice",
+            ErrorKind::Stale => "the handle is out of date, the data it referred to is gone",
+            ErrorKind::UseAfterFree => "use after free, the memory was freed and is poisoned",
+            ErrorKind::OutOfBounds => "there is no cell at that index",
+        })
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Alloc => "allocate",
+            Operation::Link => "link",
+            Operation::Unlink => "unlink",
+            Operation::Free => "free",
+            Operation::Write => "write to",
+            Operation::Resolve => "follow a handle to",
+            Operation::Copy => "copy",
+            Operation::Move => "move",
+            Operation::Swap => "swap",
+            Operation::WeakMap => "weakly map",
+            Operation::Finalize => "register a finalizer on",
+        })
+    }
+}
+
+impl fmt::Display for GcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.cell {
+            Some(cell) => write!(f, "Couldn't {} cell {}, {}", self.operation, cell, self.kind),
+            None => write!(f, "Couldn't {}, {}", self.operation, self.kind),
+        }
+    }
+}
+
+impl error::Error for GcError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{free_cell, tests::graph, Heap};
+
+    #[test]
+    fn says_what_failed_and_at_which_cell() {
+        let error: GcError = GcError::new(ErrorKind::FreedRoot, Operation::Free, Some(3));
+        assert_eq!(error.to_string(), "Couldn't free cell 3, roots can't be freed, unless forced");
+        let error: GcError = GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None);
+        assert_eq!(error.to_string(), "Couldn't allocate, no free memory avaliable");
+    }
+
+    #[test]
+    fn passes_on_with_the_question_mark_operator() {
+        fn free_root() -> Result<usize, Box<dyn error::Error>> {
+            let mut heap: Heap = graph(1, &[]);
+            heap.log.echo = false;
+            Ok(free_cell(&mut heap, 0, false)?)
+        }
+
+        let error: Box<dyn error::Error> = free_root().unwrap_err();
+        let error: &GcError = error.downcast_ref().unwrap();
+        assert_eq!((error.kind, error.operation, error.cell), (ErrorKind::FreedRoot, Operation::Free, Some(0)));
+    }
+}
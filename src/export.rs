@@ -0,0 +1,223 @@
+//Exporting the virtual heap to formats other tools can read
+/*
+    view_state() is great for a human at the prompt, but scraping it from a script is painful.
+    These exports write the whole pool (data, flags, edges and roots) out in a stable format,
+    so grading scripts, visualisers etc. can consume the heap directly.
+
+    Either can be cut down to the cells with a tag (see tags.rs), to export one part of a large scenario on its own.
+*/
+
+use core::fmt::Write;
+use serde::Serialize;
+#[cfg(feature = "std")]
+use std::{fs::File, io};
+
+use crate::{records::Record, Heap, Kind};
+use crate::prelude::*;
+
+/// A single cell of the heap, as it appears in the JSON export
+#[derive(Serialize)]
+struct JsonCell<'a> {
+    index: usize,                   //Position of the cell in the pool (its "address")
+    name: Option<&'a str>,          //Label given with --name, null if the cell is unnamed
+    tags: &'a [String],             //Tags given with --tag
+    data: Option<i32>,              //Stored value, null if the cell is empty
+    kind: Kind,                     //What the data is, i.e. part of a string
+    record: Option<&'a Record>,     //A record's type and fields, null for every other kind
+    freed: bool,
+    root: bool,
+    marked: bool,
+    reference_count: i32,
+    will_ref: &'a [usize],          //Outgoing edges -> the cells this cell references
+    by_ref: &'a [usize],            //Incoming edges -> the cells that reference this cell
+}
+
+/// The whole heap, as it appears in the JSON export
+#[derive(Serialize)]
+struct JsonHeap<'a> {
+    capacity: usize,
+    roots: Vec<usize>,
+    cells: Vec<JsonCell<'a>>,
+}
+
+impl Heap {
+    /// Serializes the entire memory pool to a (pretty printed) JSON string
+    /// #### Format
+    /// ```text
+    /// {
+    ///   "capacity": 20,
+    ///   "roots": [0, 19],
+    ///   "cells": [
+    ///     { "index": 0, "name": "cache", "tags": ["config"], "data": 7, "kind": "Int", "record": null, "freed": false,
+    ///       "root": true, "marked": true, "reference_count": 1, "will_ref": [3], "by_ref": [] },
+    ///     { "index": 3, "name": null, "tags": [], "data": null, "kind": "Record",
+    ///       "record": { "name": "node", "fields": [{ "name": "next", "to": 5 }] }, ... },
+    ///     ...
+    ///   ]
+    /// }
+    /// ```
+    pub fn to_json(&self) -> String {
+        self.to_json_tagged(None)
+    }
+
+    /// `to_json()` of only the cells with a tag (every cell if it is None). Capacity is still that of the whole heap
+    pub fn to_json_tagged(&self, tag: Option<&str>) -> String {
+        let shown = |i: &usize| tag.is_none_or(|tag| self.cells[*i].has_tag(tag));
+        let export = JsonHeap {
+            capacity: self.cells.len(),
+            roots: (0..self.cells.len()).filter(|&i| self.cells[i].is_root()).filter(shown).collect(),
+            cells: (0..self.cells.len())
+                .filter(shown)
+                .map(|index| (index, &self.cells[index]))
+                .map(|(index, cell)| JsonCell {
+                    index,
+                    name: cell.name.as_deref(),
+                    tags: &cell.tags,
+                    data: cell.data,
+                    kind: cell.kind,
+                    record: cell.record.as_ref(),
+                    freed: cell.freed,
+                    root: cell.is_root(),
+                    marked: cell.marked,
+                    reference_count: cell.reference_count,
+                    will_ref: &cell.will_ref,
+                    by_ref: &cell.by_ref,
+                })
+                .collect(),
+        };
+
+        //Only plain data is being serialized, so this can't fail
+        serde_json::to_string_pretty(&export).expect("heap export is always valid JSON")
+    }
+
+    /// Writes `to_json()` out to `sink`, i.e. stdout or a buffer
+    #[cfg(feature = "std")]
+    pub fn write_json(&self, sink: &mut dyn io::Write) -> io::Result<()> {
+        writeln!(sink, "{}", self.to_json())
+    }
+
+    /// Writes `to_json()` out to the file at `path`
+    #[cfg(feature = "std")]
+    pub fn export_json(&self, path: &str) -> io::Result<()> {
+        self.write_json(&mut File::create(path)?)
+    }
+
+    /// Renders the reference graph in Graphviz DOT format, render it with i.e. `dot -Tpng heap.dot -o heap.png`
+    /// #### Styling
+    /// - Every `will_ref` edge is drawn as an arrow (referencing cell -> referenced cell), labelled with the field's name for a record
+    /// - Records show their type in place of data
+    /// - Roots are drawn as gold double circles
+    /// - Marked cells are solid, unmarked cells (what the next sweep would reclaim) are dashed and red
+    /// - Free cells are left out, unless an edge still points at them
+    pub fn to_dot(&self) -> String {
+        self.to_dot_tagged(None)
+    }
+
+    /// `to_dot()` of only the cells with a tag (every cell if it is None), and the references between them
+    pub fn to_dot_tagged(&self, tag: Option<&str>) -> String {
+        let shown = |i: usize| tag.is_none_or(|tag| self.cells[i].has_tag(tag));
+        let mut dot = String::new();
+
+        //Writing to a String can't fail, so the results of writeln! are ignored
+        let _ = writeln!(dot, "digraph heap {{");
+        let _ = writeln!(dot, "    node [shape=circle, fontname=\"monospace\"];");
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            //Skip free cells that nothing points at, they aren't part of the graph
+            let pointed_at = self.cells.iter().any(|other| other.will_ref.contains(&i));
+            if (cell.freed && !pointed_at) || !shown(i) {
+                continue;
+            }
+
+            let data = match (&cell.record, cell.data) {
+                (Some(record), _) => record.name.replace('"', "\\\""),
+                (None, Some(data)) => data.to_string(),
+                (None, None) => "-".to_string(),
+            };
+            //Named cells show their name above the index, i.e. "cache\n3\n42"
+            let label = match &cell.name {
+                Some(name) => format!("{}\\n{}\\n{}", name.replace('"', "\\\""), i, data),
+                None => format!("{}\\n{}", i, data),
+            };
+
+            let style = if cell.is_root() {
+                "shape=doublecircle, style=filled, fillcolor=gold"
+            } else if cell.freed {
+                "style=dotted, color=gray"
+            } else if cell.marked {
+                "style=solid"
+            } else {
+                "style=dashed, color=red, fontcolor=red"
+            };
+
+            let _ = writeln!(dot, "    c{} [label=\"{}\", {}];", i, label, style);
+        }
+
+        //Edges, following will_ref (the direction the mark phase traverses)
+        for (i, cell) in self.cells.iter().enumerate() {
+            for target in cell.will_ref.iter().filter(|&&target| shown(i) && shown(target)) {
+                //A reference made through a record's field is labelled with the field's name (or names)
+                let fields: Vec<&str> = match &cell.record {
+                    Some(record) => record.fields.iter().filter(|field| field.to == Some(*target)).map(|field| field.name.as_str()).collect(),
+                    None => Vec::new(),
+                };
+                if fields.is_empty() {
+                    let _ = writeln!(dot, "    c{} -> c{};", i, target);
+                } else {
+                    let _ = writeln!(dot, "    c{} -> c{} [label=\"{}\"];", i, target, fields.join(", ").replace('"', "\\\""));
+                }
+            }
+        }
+
+        let _ = writeln!(dot, "}}");
+        dot
+    }
+
+    /// Writes `to_dot()` out to `sink`, i.e. stdout or a buffer
+    #[cfg(feature = "std")]
+    pub fn write_dot(&self, sink: &mut dyn io::Write) -> io::Result<()> {
+        sink.write_all(self.to_dot().as_bytes())
+    }
+
+    /// Writes `to_dot()` out to the file at `path`
+    #[cfg(feature = "std")]
+    pub fn export_dot(&self, path: &str) -> io::Result<()> {
+        self.write_dot(&mut File::create(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use crate::{collect, spec_alloc, tests::graph, Heap};
+
+    #[test]
+    fn exports_every_cell_with_its_edges_as_json() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.name_cell(1, Some("head")).unwrap();
+
+        let json: Value = serde_json::from_str(&heap.to_json()).unwrap();
+        assert_eq!((json["capacity"].as_u64(), json["roots"].clone()), (Some(7), Value::from(vec![0])));
+        assert_eq!(json["cells"].as_array().unwrap().len(), 7);
+        let head: &Value = &json["cells"][1];
+        assert_eq!((head["name"].as_str(), head["data"].as_i64(), head["by_ref"].clone()), (Some("head"), Some(1), Value::from(vec![0])));
+        assert_eq!((json["cells"][5]["freed"].as_bool(), json["cells"][5]["data"].is_null()), (Some(true), true));
+    }
+
+    #[test]
+    fn draws_the_reference_graph_in_dot() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        collect(&mut heap);
+        spec_alloc(&mut heap, 9, None, 2).unwrap();          //Not marked yet, so the next sweep would reclaim it
+
+        let dot: String = heap.to_dot();
+        assert!(dot.starts_with("digraph heap {") && dot.ends_with("}\n"));
+        assert!(dot.contains("c0 [label=\"0\\n0\", shape=doublecircle, style=filled, fillcolor=gold];"));
+        assert!(dot.contains("c1 [label=\"1\\n1\", style=solid];") && dot.contains("c0 -> c1;"));
+        assert!(dot.contains("c2 [label=\"2\\n9\", style=dashed, color=red, fontcolor=red];"));
+        assert!(!dot.contains("c3 "));                                 //Free cells nothing points at are left out
+    }
+}
@@ -0,0 +1,266 @@
+//C API
+/*
+    With the `ffi` feature, the library exports a C API so the collector can be driven from a C program -> i.e. to put
+    a malloc/free version of something next to a garbage collected version of it, in the same codebase.
+
+        cargo rustc --lib --release --crate-type cdylib --features ffi    (builds target/release/libgc_rust.so, or .dylib/.dll)
+        cc demo.c -Iinclude -Ltarget/release -lgc_rust
+
+    The header is include/gc_rust.h, generated from this file by cbindgen (regenerate it after changing anything here):
+
+        cbindgen --config cbindgen.toml --output include/gc_rust.h
+
+    A heap is handed to C as an opaque pointer from gc_heap_create(), and must be given back to gc_heap_destroy().
+    Objects are referred to by cell index (as at the prompt). Anything that can go wrong returns a GcStatus,
+    which gc_status_message() describes. Events aren't printed, C gets the numbers from gc_stats() instead.
+*/
+
+use std::ffi::{c_char, CStr};
+
+use crate::{
+    collect,
+    error::{ErrorKind, GcError, Operation},
+    handle::Handle,
+    poison::check_access,
+    roots::GLOBALS,
+    stats::HeapStats,
+    Heap,
+};
+
+/// Whether a call succeeded, and if not why not (see gc_status_message())
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GcStatus {
+    Ok = 0,
+    NullHeap,           //The heap pointer was null
+    Occupied,           //The cell is already in use
+    NoFreeMemory,       //Nothing could be allocated, even after collecting
+    DataIsFree,         //The cell isn't in use
+    FreedRoot,          //Roots can't be freed by hand
+    DoubleFree,         //The cell was already free
+    Stale,              //The object has since been freed, swept or moved
+    UseAfterFree,       //The cell was freed, and is poisoned until it is allocated again
+    OutOfBounds,        //There is no cell at that index
+}
+
+/// Numbers about the heap, see HeapStats
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcStats {
+    pub capacity: usize,            //Total amount of cells in the memory pool
+    pub live: usize,                //Cells currently in use
+    pub free: usize,                //Cells avaliable for allocation
+    pub roots: usize,               //Cells currently designated as roots
+    pub allocations: usize,         //Total successful allocations over the lifetime of the heap
+    pub collections: usize,         //Total garbage collection cycles that have run
+    pub high_water: usize,          //The most cells that have ever been in use at once
+    pub bytes_capacity: usize,      //Total bytes the memory pool has room for
+    pub bytes_used: usize,          //Bytes taken up by objects in use
+    pub bytes_reclaimed: usize,     //Total bytes swept by every collection
+}
+
+impl From<ErrorKind> for GcStatus {
+    fn from(kind: ErrorKind) -> GcStatus {
+        match kind {
+            ErrorKind::Occupied => GcStatus::Occupied,
+            ErrorKind::NoFreeMemory => GcStatus::NoFreeMemory,
+            ErrorKind::DataIsFree => GcStatus::DataIsFree,
+            ErrorKind::FreedRoot => GcStatus::FreedRoot,
+            ErrorKind::DoubleFree => GcStatus::DoubleFree,
+            ErrorKind::Stale => GcStatus::Stale,
+            ErrorKind::UseAfterFree => GcStatus::UseAfterFree,
+            ErrorKind::OutOfBounds => GcStatus::OutOfBounds,
+        }
+    }
+}
+
+impl From<Result<(), GcError>> for GcStatus {
+    fn from(result: Result<(), GcError>) -> GcStatus {
+        match result {
+            Ok(()) => GcStatus::Ok,
+            Err(error) => error.kind.into(),
+        }
+    }
+}
+
+impl From<HeapStats> for GcStats {
+    fn from(stats: HeapStats) -> GcStats {
+        GcStats {
+            capacity: stats.capacity,
+            live: stats.live,
+            free: stats.free,
+            roots: stats.roots,
+            allocations: stats.allocations,
+            collections: stats.collections,
+            high_water: stats.high_water,
+            bytes_capacity: stats.bytes_capacity,
+            bytes_used: stats.bytes_used,
+            bytes_reclaimed: stats.bytes_reclaimed,
+        }
+    }
+}
+
+/// Creates a heap of `size` cells, whose arbitrary data comes from `seed` (the same seed always gives the same heap).
+/// Returns null if `size` is 0. The heap must be given back to gc_heap_destroy() once finished with
+#[no_mangle]
+pub extern "C" fn gc_heap_create(size: usize, seed: u64) -> *mut Heap {
+    if size == 0 {
+        return std::ptr::null_mut();
+    }
+
+    let mut heap: Heap = Heap::with_seed(size, seed);
+    heap.log.echo = false;
+    Box::into_raw(Box::new(heap))
+}
+
+/// Destroys a heap made by gc_heap_create() (does nothing if it is null)
+///
+/// # Safety
+/// `heap` must be null or from gc_heap_create(), and not used again afterwards
+#[no_mangle]
+pub unsafe extern "C" fn gc_heap_destroy(heap: *mut Heap) {
+    if !heap.is_null() {
+        drop(Box::from_raw(heap));
+    }
+}
+
+/// Allocates `data` in the first free cell (collecting first if there are none), writing its index to `index`
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create(), and `index` null or valid to write to
+#[no_mangle]
+pub unsafe extern "C" fn gc_alloc(heap: *mut Heap, data: i32, index: *mut usize) -> GcStatus {
+    let Some(heap) = heap.as_mut() else {
+        return GcStatus::NullHeap;
+    };
+
+    match heap.alloc(data) {
+        Ok(handle) => {
+            if let Some(index) = index.as_mut() {
+                *index = handle.index;
+            }
+            GcStatus::Ok
+        }
+        Err(error) => error.kind.into(),
+    }
+}
+
+/// Makes cell `from` reference cell `to`
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_link(heap: *mut Heap, from: usize, to: usize) -> GcStatus {
+    let Some(heap) = heap.as_mut() else {
+        return GcStatus::NullHeap;
+    };
+
+    match (handle(heap, from, Operation::Link), handle(heap, to, Operation::Link)) {
+        (Ok(from), Ok(to)) => heap.link(from, to).into(),
+        (Err(status), _) | (_, Err(status)) => status,
+    }
+}
+
+/// Removes the reference from cell `from` to cell `to`
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_unlink(heap: *mut Heap, from: usize, to: usize) -> GcStatus {
+    let Some(heap) = heap.as_mut() else {
+        return GcStatus::NullHeap;
+    };
+
+    match (handle(heap, from, Operation::Unlink), handle(heap, to, Operation::Unlink)) {
+        (Ok(from), Ok(to)) => heap.unlink(from, to).into(),
+        (Err(status), _) | (_, Err(status)) => status,
+    }
+}
+
+/// Frees a cell by hand, the way free() would (roots can't be freed)
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_free(heap: *mut Heap, index: usize) -> GcStatus {
+    let Some(heap) = heap.as_mut() else {
+        return GcStatus::NullHeap;
+    };
+
+    match handle(heap, index, Operation::Free) {
+        Ok(handle) => heap.free(handle, false).into(),
+        Err(status) => status,
+    }
+}
+
+/// Makes a cell a root (in the "globals" root set, like --root)
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_root(heap: *mut Heap, index: usize) -> GcStatus {
+    let Some(heap) = heap.as_mut() else {
+        return GcStatus::NullHeap;
+    };
+
+    match heap.add_root(GLOBALS, index) {
+        Ok(()) => GcStatus::Ok,
+        Err(_) => GcStatus::OutOfBounds,
+    }
+}
+
+/// Stops a cell being a root, returning false if it wasn't one (or the heap is null)
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_unroot(heap: *mut Heap, index: usize) -> bool {
+    heap.as_mut().is_some_and(|heap| heap.remove_root(index))
+}
+
+/// Runs a collection with the current algorithm, returning how many cells were swept
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_collect(heap: *mut Heap) -> usize {
+    let Some(heap) = heap.as_mut() else {
+        return 0;
+    };
+
+    collect(heap);
+    heap.history.last().map_or(0, |cycle| cycle.swept)
+}
+
+/// Numbers about the heap as it is now (all 0 if the heap is null)
+///
+/// # Safety
+/// `heap` must be null or a live heap from gc_heap_create()
+#[no_mangle]
+pub unsafe extern "C" fn gc_stats(heap: *const Heap) -> GcStats {
+    heap.as_ref().map(|heap| heap.stats().into()).unwrap_or_default()
+}
+
+/// Describes a status, as a string that lives for the whole program (so it must not be freed)
+#[no_mangle]
+pub extern "C" fn gc_status_message(status: GcStatus) -> *const c_char {
+    let message: &CStr = match status {
+        GcStatus::Ok => c"ok",
+        GcStatus::NullHeap => c"the heap is null",
+        GcStatus::Occupied => c"the cell is already in use",
+        GcStatus::NoFreeMemory => c"no free memory, even after collecting",
+        GcStatus::DataIsFree => c"the cell is free, not suitable for use",
+        GcStatus::FreedRoot => c"roots can't be freed",
+        GcStatus::DoubleFree => c"the cell is already free",
+        GcStatus::Stale => c"the object has since been freed, swept or moved",
+        GcStatus::UseAfterFree => c"the cell was freed (use after free)",
+        GcStatus::OutOfBounds => c"there is no cell at that index",
+    };
+    message.as_ptr()
+}
+
+//A handle to the object in a cell, refusing cells that are free or out of bounds the way the heap would
+fn handle(heap: &Heap, index: usize, operation: Operation) -> Result<Handle, GcStatus> {
+    check_access(&heap.cells, index, operation).map_err(|error| GcStatus::from(error.kind))?;
+    Ok(heap.handle(index).expect("check_access() only passes cells in use"))
+}
@@ -0,0 +1,218 @@
+//Finalization
+/*
+    A finalizer is code that runs when its object is found to be garbage, before the memory is reclaimed, i.e. to
+    close a file the object owned. Running it means the object has to still be there, so a collection that finds
+    a finalizable object dead doesn't reclaim it. Instead (as in Java):
+
+        1. The object is moved to the finalization queue, which is a root set of its own. So it survives this
+           collection, along with everything it references, as its finalizer may use them
+        2. Once the collection is over, each finalizer in the queue runs, and the object leaves the queue
+        3. A finalizer can resurrect its object, by storing it somewhere reachable. Here a resurrecting finalizer
+           (`--finalizer <cell> resurrect`) roots it in the "resurrected" root set, as if saved into a global
+        4. A finalizer only ever runs once. If the object dies again (or was never resurrected), the next
+           collection reclaims it like any other garbage
+
+    So a finalizable object always takes at least two collections to reclaim. Every step is reported as it
+    happens, and `--why-live` shows what is keeping a resurrected object alive.
+
+    When several finalizable objects die together, their finalizers run referrers first -> if A references B
+    (directly or through other objects), A's finalizer runs before B's, so A can still use B, which hasn't been
+    finalized yet. The order comes from the object graph, at the time they are run. A cycle of finalizable objects
+    has no such order (each is a referrer of the others), so its finalizers run in index order, and the log says so.
+
+    Under reference counting an object is found dead the moment its count drops to zero, but it is left for the
+    next collection to queue rather than reclaimed there and then.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::{ErrorKind, GcError, Operation}, log::Event, Heap};
+use crate::prelude::*;
+
+/// The root set the finalization queue is kept in, for the length of a collection
+pub const QUEUE: &str = "finalization queue";
+
+/// The root set resurrecting finalizers save their objects into
+pub const RESURRECTED: &str = "resurrected";
+
+/// A finalizer registered on a cell, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finalizer {
+    pub index: usize,
+    pub resurrect: bool,            //Does it save its object somewhere reachable again?
+}
+
+impl Heap {
+    /// Registers a finalizer on a cell in use, replacing any it already has
+    /// Returns an `OutOfBounds` error if there is no such cell, or a `DataIsFree` error if it isn't in use
+    pub fn register_finalizer(&mut self, index: usize, resurrect: bool) -> Result<(), GcError> {
+        match self.cells.get(index) {
+            None => return Err(GcError::new(ErrorKind::OutOfBounds, Operation::Finalize, Some(index))),
+            Some(cell) if cell.freed => return Err(GcError::new(ErrorKind::DataIsFree, Operation::Finalize, Some(index))),
+            Some(_) => {}
+        }
+        self.finalizers.retain(|finalizer| finalizer.index != index);
+        self.finalizers.push(Finalizer { index, resurrect });
+        Ok(())
+    }
+
+    /// Every finalizer that hasn't run yet
+    pub fn finalizers(&self) -> &[Finalizer] {
+        &self.finalizers
+    }
+
+    /// Does the cell have a finalizer that hasn't run yet?
+    pub fn has_finalizer(&self, index: usize) -> bool {
+        self.finalizers.iter().any(|finalizer| finalizer.index == index)
+    }
+
+    //Before a collection -> every finalizable object that is garbage goes into the queue, which keeps it alive
+    pub(crate) fn queue_finalizers(&mut self) {
+        let cells = &self.cells;
+        self.finalizers.retain(|finalizer| !cells[finalizer.index].freed);       //Reclaimed some other way, i.e. by the treadmill
+        let reachable: Vec<bool> = self.reachable();
+        let dead: Vec<usize> = self.finalizers.iter().map(|finalizer| finalizer.index).filter(|&index| !reachable[index]).collect();
+        for index in dead {
+            self.add_root(QUEUE, index).expect("finalizers are on cells in the heap");
+            self.log.emit(Event::FinalizerQueued { index });
+        }
+    }
+
+    //After a collection -> runs the finalizer of every object in the queue (once), then empties it
+    pub(crate) fn run_finalizers(&mut self) {
+        let Some(position) = self.root_sets().iter().position(|root_set| root_set.name == QUEUE) else {
+            return;
+        };
+        let queue: Vec<usize> = self.root_sets()[position].cells.clone();
+        for index in self.finalization_order(&queue) {
+            let Some(at) = self.finalizers.iter().position(|finalizer| finalizer.index == index) else {
+                continue;
+            };
+            let finalizer: Finalizer = self.finalizers.remove(at);
+            if finalizer.resurrect {
+                self.add_root(RESURRECTED, index).expect("the queue is of cells in the heap");
+            }
+            self.log.emit(Event::Finalized { index, resurrected: finalizer.resurrect });
+        }
+        self.drop_root_set(QUEUE);
+    }
+
+    //Orders the queue referrers first (see the top of this module). Each cell is put after every queued cell that
+    //reaches it, unless it reaches that cell back (a cycle, whose cells are kept together in index order)
+    fn finalization_order(&mut self, queue: &[usize]) -> Vec<usize> {
+        let reaches: Vec<Vec<bool>> = queue.iter().map(|&from| self.reachable_from(vec![from])).collect();
+        let reached = |a: usize, b: usize| reaches[a][queue[b]];           //Does queue[a] reach queue[b]?
+        let cycle = |a: usize| -> Vec<usize> { (0..queue.len()).filter(|&b| b == a || (reached(a, b) && reached(b, a))).collect() };
+
+        //Whatever reaches a cell (from outside its cycle) also reaches everything it does, so it has fewer queued referrers.
+        //Sorting on that count puts referrers first
+        let mut order: Vec<(usize, usize, usize)> = (0..queue.len())
+            .map(|a| {
+                let referrers: usize = (0..queue.len()).filter(|&b| reached(b, a) && !reached(a, b)).count();
+                let first: usize = cycle(a).into_iter().map(|b| queue[b]).min().expect("a cell is in its own cycle");
+                (referrers, first, queue[a])
+            })
+            .collect();
+        order.sort();
+
+        let mut noted: Vec<usize> = Vec::new();
+        for a in 0..queue.len() {
+            let mut cells: Vec<usize> = cycle(a).into_iter().map(|b| queue[b]).collect();
+            cells.sort();
+            if cells.len() > 1 && !noted.contains(&cells[0]) {
+                noted.push(cells[0]);
+                self.log.emit(Event::FinalizerCycle { cells });
+            }
+        }
+        order.into_iter().map(|(_, _, index)| index).collect()
+    }
+
+    //Cells were moved (see copying.rs), so their finalizers go with them. A finalizer on a cell that wasn't copied is dropped
+    pub(crate) fn relocate_finalizers(&mut self, forwarding: &[Option<usize>]) {
+        self.finalizers = self
+            .finalizers
+            .iter()
+            .filter_map(|finalizer| Some(Finalizer { index: forwarding.get(finalizer.index).copied().flatten()?, ..*finalizer }))
+            .collect();
+    }
+
+    //A cell freed by hand never runs its finalizer
+    pub(crate) fn forget_finalizer(&mut self, index: usize) {
+        self.finalizers.retain(|finalizer| finalizer.index != index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph};
+
+    #[test]
+    fn finalizes_dead_cells_once_before_reclaiming_them() {
+        let mut heap: Heap = graph(5, &[(1, 2), (3, 4)]);
+        heap.log.echo = false;
+        heap.register_finalizer(1, false).unwrap();
+        heap.register_finalizer(3, true).unwrap();
+
+        //The queue keeps both (and what they reference) alive for their finalizers, which then run once
+        collect(&mut heap);
+        assert!((1..5).all(|i| !heap.cells[i].freed) && heap.finalizers().is_empty());
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::Finalized { index: 3, resurrected: true })));
+        assert!(heap.root_sets().iter().all(|root_set| root_set.name != QUEUE));
+
+        //Only the one left dead is reclaimed next time
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed);
+        assert!(!heap.cells[3].freed && !heap.cells[4].freed && heap.verify().is_empty());
+    }
+
+    #[test]
+    fn only_finalizes_cells_in_use_that_died() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        assert_eq!(heap.register_finalizer(9, false).unwrap_err(), GcError::new(ErrorKind::OutOfBounds, Operation::Finalize, Some(9)));
+        assert_eq!(heap.register_finalizer(4, false).unwrap_err().to_string(), "Couldn't register a finalizer on cell 4, the memory was free, not suitable for use");
+        heap.register_finalizer(1, true).unwrap();
+        heap.register_finalizer(1, false).unwrap();                     //Replaces the first
+        assert_eq!(heap.finalizers(), [Finalizer { index: 1, resurrect: false }]);
+
+        //A live cell keeps its finalizer for when it dies
+        collect(&mut heap);
+        assert!(heap.has_finalizer(1) && heap.cells[2].freed);
+        assert!(!heap.log.since(0).any(|event| matches!(event, Event::FinalizerQueued { .. } | Event::Finalized { .. })));
+    }
+
+    #[test]
+    fn finalizes_referrers_before_their_referents() {
+        let mut heap: Heap = graph(6, &[(1, 2), (2, 3), (4, 5), (5, 4)]);
+        heap.log.echo = false;
+        for index in (1..6).rev() {
+            heap.register_finalizer(index, false).unwrap();
+        }
+
+        collect(&mut heap);
+        let order: Vec<usize> = heap.log.since(0).filter_map(|event| match event {
+            Event::Finalized { index, .. } => Some(*index),
+            _ => None,
+        }).collect();
+        assert_eq!(order, [1, 4, 5, 2, 3]);         //Each after its referrers, and the cycle (with no order of its own) by index
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::FinalizerCycle { cells } if cells == &[4, 5])));
+    }
+
+    #[test]
+    fn only_reports_cycles_of_more_than_one_finalizable_cell() {
+        let mut heap: Heap = graph(4, &[(1, 1), (1, 2), (2, 3), (3, 2)]);
+        heap.log.echo = false;
+        heap.register_finalizer(2, false).unwrap();
+        heap.register_finalizer(1, false).unwrap();                     //References itself, but it is alone in that
+
+        //Cell 3 has no finalizer, so cell 2 is in no cycle of finalizable cells either
+        collect(&mut heap);
+        let order: Vec<usize> = heap.log.since(0).filter_map(|event| match event {
+            Event::Finalized { index, .. } => Some(*index),
+            _ => None,
+        }).collect();
+        assert_eq!(order, [1, 2]);
+        assert!(!heap.log.since(0).any(|event| matches!(event, Event::FinalizerCycle { .. })));
+    }
+}
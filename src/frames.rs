@@ -0,0 +1,142 @@
+//Simulated stack frames
+/*
+    Real runtimes don't have a fixed set of roots. Most roots are the local variables of the functions
+    currently running, found by walking the call stack, and they stop being roots the moment their function returns.
+
+    This models that with a stack of frames. While a frame is live, every allocation and every cell registered
+    as a local (--local) is a root. Popping the frame unroots them, unless an outer frame still holds them,
+    so whatever only they kept alive becomes garbage for the next collection.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{log::Event, Heap};
+use crate::prelude::*;
+
+/// A simulated call stack frame
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Frame {
+    pub locals: Vec<usize>,         //Cells this frame keeps alive, as roots, for as long as it is live
+}
+
+/// Reasons a cell can't be made a local
+#[derive(Debug)]
+pub enum FrameError {
+    NoFrame,            //There is no live frame to add it to (--push_frame first)
+    OutOfBounds,        //There is no cell at that index
+    DataIsFree,         //Only cells in use can be locals
+}
+
+impl Heap {
+    /// Pushes a new frame onto the stack, returning the stack's depth
+    pub fn push_frame(&mut self) -> usize {
+        self.frames.push(Frame::default());
+        self.log.emit(Event::FramePushed { depth: self.frames.len() });
+        self.frames.len()
+    }
+
+    /// Pops the innermost frame, unrooting each of its locals that no outer frame (or root set) holds.
+    /// Returns the frame that was popped, or None if the stack was empty
+    pub fn pop_frame(&mut self) -> Option<Frame> {
+        let frame: Frame = self.frames.pop()?;
+
+        for &local in &frame.locals {
+            self.refresh_root(local);
+        }
+
+        self.log.emit(Event::FramePopped { depth: self.frames.len() + 1, locals: frame.locals.len() });
+        Some(frame)
+    }
+
+    /// Makes a cell a local of the innermost frame, so it is a root until that frame is popped.
+    /// A cell that a root set holds too (i.e. from --root) stays a root after the frame is popped
+    pub fn add_local(&mut self, index: usize) -> Result<(), FrameError> {
+        if self.frames.is_empty() {
+            return Err(FrameError::NoFrame);
+        }
+        match self.cells.get(index) {
+            None => return Err(FrameError::OutOfBounds),
+            Some(cell) if cell.freed => return Err(FrameError::DataIsFree),
+            Some(_) => {}
+        }
+
+        self.root_in_frame(index);
+        Ok(())
+    }
+
+    /// The live frames, outermost first
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    //Roots a cell for the lifetime of the innermost frame (if there is one) -> every allocation inside a frame goes through here
+    pub(crate) fn root_in_frame(&mut self, index: usize) {
+        let frame: &mut Frame = match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => return,
+        };
+
+        if !frame.locals.contains(&index) {
+            frame.locals.push(index);
+        }
+        if !self.cells[index].is_root() {
+            self.cells[index].make_root();
+            self.log.emit(Event::Rooted { index });
+        }
+    }
+
+    //A cell that was freed is no longer anyone's local (its index may be reused for something else)
+    pub(crate) fn forget_local(&mut self, index: usize) {
+        for frame in self.frames.iter_mut() {
+            frame.locals.retain(|&local| local != index);
+        }
+    }
+
+    //Follows cells that a copying collection moved, so each frame still holds the same data
+    pub(crate) fn relocate_locals(&mut self, forwarding: &[Option<usize>]) {
+        for frame in self.frames.iter_mut() {
+            frame.locals = frame.locals.iter().filter_map(|&old| forwarding[old]).collect();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, free_alloc, roots::GLOBALS};
+
+    #[test]
+    fn keeps_a_frames_locals_alive_until_it_is_popped() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        assert_eq!(heap.push_frame(), 1);
+        let outer: usize = free_alloc(&mut heap, 1, None).unwrap();    //Allocated inside a frame, so a local of it
+        assert_eq!(heap.push_frame(), 2);
+        let inner: usize = free_alloc(&mut heap, 2, None).unwrap();
+        heap.add_local(outer).unwrap();
+        heap.add_root(GLOBALS, inner).unwrap();
+        let temporary: usize = free_alloc(&mut heap, 3, None).unwrap();
+
+        //The outer frame still holds `outer`, and the root set `inner`
+        assert_eq!(heap.pop_frame().unwrap().locals, [inner, outer, temporary]);
+        collect(&mut heap);
+        assert!(!heap.cells[outer].freed && !heap.cells[inner].freed && heap.cells[temporary].freed);
+
+        heap.pop_frame().unwrap();
+        collect(&mut heap);
+        assert!(heap.cells[outer].freed && heap.pop_frame().is_none());
+    }
+
+    #[test]
+    fn refuses_locals_it_cannot_hold() {
+        let mut heap: Heap = Heap::with_seed(3, 0);
+        heap.log.echo = false;
+        let cell: usize = free_alloc(&mut heap, 1, None).unwrap();
+        assert!(matches!(heap.add_local(cell), Err(FrameError::NoFrame)));
+
+        heap.push_frame();
+        assert!(matches!(heap.add_local(2), Err(FrameError::DataIsFree)));
+        assert!(matches!(heap.add_local(3), Err(FrameError::OutOfBounds)));
+        assert!(heap.frames()[0].locals.is_empty());
+    }
+}
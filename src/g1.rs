@@ -0,0 +1,240 @@
+//Region-based collection (garbage first)
+/*
+    A heap of gigabytes can't be swept or compacted in one pause, so G1 splits it into regions of a fixed size, and
+    only collects some of them each time. Marking still traces the whole heap, but as it goes it tallies the live
+    bytes of every region, so the collector knows how much garbage each one holds:
+
+        live bytes -> bytes of the objects marked in the region
+        liveness -> the share of the region's used bytes that are live. The lower it is, the more there is to gain
+        garbage -> used bytes that aren't live
+
+    The collection set is then chosen garbage first -> the regions with the most garbage, for as long as the pause
+    they are predicted to take fits the pause budget (`--set-policy pause-budget`). Each region in the set is evacuated
+    (its live cells moved out to free cells in other regions, see copying::move_cells) and left completely free.
+    Garbage in the regions that weren't chosen stays where it is until a later collection finds it worth the time.
+
+    The free cells outside the set can run out partway through an evacuation. The live cells that haven't been
+    copied yet are then left where they are, and the garbage around them is reclaimed in place, so the regions they
+    are in aren't left free -> an evacuation failure, which makes the collection partial rather than a crash.
+
+    A region's pause is predicted from its live bytes (the work of evacuating it is copying them), at the rate
+    the previous evacuations copied at. The first collection has nothing to go on, so it collects every region with garbage.
+    At least one region is always collected, whatever the budget, so every collection gets something done.
+*/
+
+use core::{ops::Range, time::Duration};
+
+use crate::{copying::move_cells, drop_swept_referrers, generational::CollectionKind, log::Event, mark, reclaim, stats::GcCycle, Algorithm, Heap, Instant};
+use crate::prelude::*;
+
+/// Cells in each region, unless `--set-policy region-size` says otherwise
+pub const REGION_SIZE: usize = 8;
+
+/// One region of the heap, from `Heap::regions()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Region {
+    pub index: usize,
+    pub cells: Range<usize>,
+    pub used: usize,                //Bytes taken up by objects in use, right now
+    pub live: Option<usize>,        //Bytes that were live as of the last marking (None if it hasn't been marked)
+    pub collected: bool,            //Was it in the last collection set?
+}
+
+/// What the last garbage first collection found, kept for the region table and the next collection's predictions
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RegionMarking {
+    size: usize,                    //Cells per region when it was marked
+    live: Vec<usize>,               //Live bytes in each region
+    collected: Vec<usize>,          //The collection set, most garbage first
+    nanos_per_byte: Option<f64>,    //How fast the evacuations have copied so far
+}
+
+impl Region {
+    /// Percentage of the region's used bytes that were live at the last marking (None if it is empty, or hasn't been marked)
+    pub fn liveness(&self) -> Option<usize> {
+        match (self.live, self.used) {
+            (Some(_), 0) | (None, _) => None,
+            (Some(live), used) => Some(live.min(used) * 100 / used),
+        }
+    }
+
+    /// Used bytes that weren't live at the last marking
+    pub fn garbage(&self) -> usize {
+        self.used.saturating_sub(self.live.unwrap_or(self.used))
+    }
+}
+
+impl Heap {
+    /// The heap split into regions of `Policy::region_size` cells, with how much of each is live as of the last marking
+    pub fn regions(&self) -> Vec<Region> {
+        let size: usize = self.policy.region_size.max(1);
+        let marking: Option<&RegionMarking> = self.region_marking.as_ref().filter(|marking| marking.size == size);
+        (0..self.cells.len().div_ceil(size))
+            .map(|index| {
+                let cells: Range<usize> = index * size..((index + 1) * size).min(self.cells.len());
+                Region {
+                    index,
+                    used: self.cells[cells.clone()].iter().map(|cell| cell.size()).sum(),
+                    cells,
+                    live: marking.map(|marking| marking.live[index]),
+                    collected: marking.is_some_and(|marking| marking.collected.contains(&index)),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Marks the whole heap, tallying each region's live bytes, then evacuates the collection set (see the top of this module).
+/// Returns the cycle (`number` is filled in by `collect()`). The `sweep` phase of the cycle is the time spent evacuating
+pub fn collect(heap: &mut Heap) -> GcCycle {
+    let start = Instant::now();
+    let roots: Vec<usize> = heap.roots();
+    let root_scan = start.elapsed();
+
+    let size: usize = heap.policy.region_size.max(1);
+    let order = heap.policy.mark_order;
+    heap.log.emit(Event::MarkStart { roots: roots.len(), order });
+    let start = Instant::now();
+    let visited: Vec<usize> = mark(&mut heap.cells, roots, order, heap.policy.mark_stack).visited;
+    let mut live: Vec<usize> = vec![0; heap.cells.len().div_ceil(size)];
+    for &index in &visited {
+        live[index / size] += heap.cells[index].size();
+    }
+    let mark_time = start.elapsed();
+    for &index in &visited {
+        heap.log.emit(Event::Marked { index });
+    }
+    heap.log.emit(Event::MarkEnd { marked: visited.len() });
+
+    //Garbage first, for as long as the predicted pause fits the budget
+    let nanos_per_byte: Option<f64> = heap.region_marking.as_ref().and_then(|marking| marking.nanos_per_byte);
+    let predict = |live: usize| Duration::from_nanos(nanos_per_byte.map_or(0.0, |rate| live as f64 * rate) as u64);
+    heap.region_marking = Some(RegionMarking { size, live, collected: Vec::new(), nanos_per_byte });
+    let mut regions: Vec<Region> = heap.regions().into_iter().filter(|region| region.garbage() > 0).collect();
+    regions.sort_by(|a, b| b.garbage().cmp(&a.garbage()).then_with(|| a.index.cmp(&b.index)));
+
+    let reserved: Range<usize> = heap.reserved();
+    let mut chosen: Vec<Region> = Vec::new();
+    let mut predicted: Duration = Duration::ZERO;
+    for region in regions {
+        let cost: Duration = predict(region.live.unwrap_or(0));
+        if !chosen.is_empty() && heap.policy.pause_budget.is_some_and(|budget| predicted + cost > budget) {
+            break;
+        }
+        predicted += cost;
+        chosen.push(region);
+    }
+    let collected: Vec<usize> = chosen.iter().map(|region| region.index).collect();
+    heap.log.emit(Event::CollectionSet { regions: collected.clone(), predicted, budget: heap.policy.pause_budget });
+
+    //Evacuate -> copy the live cells out to free cells in other regions, for as long as there are any (see the top of this module)
+    let start = Instant::now();
+    let in_set = |i: usize| chosen.iter().any(|region| region.cells.contains(&i));
+    let destinations: Vec<usize> = (0..heap.cells.len())
+        .filter(|&i| heap.cells[i].freed && heap.cells[i].forward.is_none() && !reserved.contains(&i) && !in_set(i))
+        .collect();
+    let sources: Vec<usize> = (0..heap.cells.len()).filter(|&i| in_set(i) && !heap.cells[i].freed && heap.cells[i].marked).collect();
+    let left_in_place: usize = sources.len().saturating_sub(destinations.len());
+    let moves: Vec<(usize, usize)> = sources.into_iter().zip(destinations).collect();
+    let copied: usize = moves.iter().map(|&(from, _)| heap.cells[from].size()).sum();
+    move_cells(heap, &moves);
+    if left_in_place > 0 {
+        heap.log.emit(Event::EvacuationFailed { left: left_in_place });
+    }
+    let mut swept: usize = 0;
+    for index in (0..heap.cells.len()).filter(|&i| in_set(i)) {
+        if !heap.cells[index].freed && !heap.cells[index].marked && reclaim(&mut heap.cells, &mut heap.log, index) {
+            swept += 1;
+        }
+    }
+    drop_swept_referrers(&mut heap.cells);
+    let evacuate_time = start.elapsed();
+
+    //The live bytes went with the cells, so the table shows what is left in each region
+    let marking: &mut RegionMarking = heap.region_marking.as_mut().expect("set above");
+    for &(from, to) in &moves {
+        let bytes: usize = heap.cells[to].size();
+        marking.live[from / size] -= bytes;
+        marking.live[to / size] += bytes;
+    }
+    marking.collected = collected;
+    if copied > 0 {
+        marking.nanos_per_byte = Some(evacuate_time.as_nanos() as f64 / copied as f64);
+    }
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked: visited.len(),
+        swept,
+        bytes_swept: 0,
+        root_scan,
+        mark: mark_time,
+        sweep: evacuate_time,
+        algorithm: Algorithm::GarbageFirst,
+        kind: CollectionKind::Whole,
+        left_in_place,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{spec_alloc, tests::graph};
+
+    #[test]
+    fn evacuates_the_regions_with_the_most_garbage_first() {
+        let mut heap: Heap = graph(12, &[(0, 1), (0, 2), (0, 4)]);      //Region 2 is all garbage, and region 3 free
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::GarbageFirst;
+        heap.policy.region_size = 4;
+
+        //The live cells of the set are copied into region 3, leaving the regions empty
+        crate::collect(&mut heap);
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::CollectionSet { regions, .. } if regions == &[2, 1, 0])));
+        let liveness = |heap: &Heap| heap.regions().iter().map(|region| region.liveness()).collect::<Vec<_>>();
+        assert_eq!(liveness(&heap), [None, None, None, Some(100)]);
+        assert!((0..12).all(|i| heap.cells[i].freed) && heap.verify().is_empty());
+        assert_eq!(heap.cells[heap.roots()[0]].will_ref.len(), 3);
+        assert!(!heap.history[0].is_partial());
+    }
+
+    #[test]
+    fn leaves_regions_without_garbage_out_of_the_collection_set() {
+        let mut heap: Heap = graph(4, &[(0, 1), (0, 2), (0, 3)]);         //Region 0 all live, and region 1 free
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::GarbageFirst;
+        heap.policy.region_size = 4;
+        assert!(heap.regions().iter().all(|region| region.live.is_none() && region.liveness().is_none()));
+
+        crate::collect(&mut heap);
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::CollectionSet { regions, .. } if regions.is_empty())));
+        assert!((0..4).all(|i| !heap.cells[i].freed) && heap.history[0].swept == 0);
+        assert_eq!(heap.regions()[0].liveness(), Some(100));
+
+        //Marking in regions of another size says nothing about these, and a size of 0 is taken as 1
+        heap.policy.region_size = 2;
+        assert!(heap.regions().iter().all(|region| region.live.is_none()));
+        heap.policy.region_size = 0;
+        assert_eq!(heap.regions().len(), 8);
+    }
+
+
+    #[test]
+    fn leaves_cells_where_they_are_when_evacuation_runs_out_of_room() {
+        //A full heap leaves garbage first nowhere to copy to, so it reclaims the garbage around the live cells
+        let mut heap: Heap = graph(12, &[(0, 1), (0, 2), (0, 4)]);
+        heap.log.echo = false;
+        for i in 12..16 {
+            spec_alloc(&mut heap, i as i32, None, i).unwrap();
+        }
+        heap.policy.algorithm = Algorithm::GarbageFirst;
+        heap.policy.region_size = 4;
+        crate::collect(&mut heap);
+        assert_eq!(heap.history[0].left_in_place, 4);
+        assert!((0..16).all(|i| heap.cells[i].freed != [0, 1, 2, 4].contains(&i)) && heap.verify().is_empty());
+        let liveness: Vec<Option<usize>> = heap.regions().iter().map(|region| region.liveness()).collect();
+        assert_eq!(liveness, [Some(100), Some(100), None, None]);
+        assert!(heap.history[0].is_partial() && heap.log.since(0).any(|event| matches!(event, Event::EvacuationFailed { left: 4 })));
+    }
+}
@@ -0,0 +1,436 @@
+//Generational collection
+/*
+    Most objects die young (the generational hypothesis, see --histogram age), so a generational collector splits
+    the heap by age and collects the young part far more often than the rest:
+
+        young (the nursery) -> cells that have survived fewer collections than the tenuring threshold
+        old (tenured) -> every other cell in use, promoted by surviving that many
+
+    A cell's generation is its age, so cells don't have to move to be promoted (as with "sticky" mark bits).
+    With survivor spaces (`Policy::survivor_size`), young cells that survive do move, as they would in a real nursery.
+    The last cells of the pool are set aside as two survivor spaces of that many cells each, which allocations don't use.
+    Every minor collection copies the young cells it keeps into the survivor space that is empty (the to-space),
+    out of eden (the rest of the pool) and the other survivor space, then the two spaces swap roles:
+
+        eden -> survivor space -> the other survivor space -> ... -> tenured, once a cell reaches the tenuring threshold
+
+    A survivor that reaches the threshold in a survivor space moves back down to the first free cell below them.
+    One that doesn't fit in the to-space is promoted early instead, as it has nowhere else to go. If the tenured space
+    is full as well, the promoted cell is left where it is in its survivor space, and the collection is partial.
+
+    There are three kinds of collection:
+
+        minor -> collects the young cells only. Old cells are taken to be live without being traced, so the collection
+                 only looks at the nursery, but garbage that has been promoted stays until a major collection.
+                 A young cell can be kept alive by an old cell rather than a root, so every old cell that references
+                 a young one is kept in the remembered set, and traced from as if it were a root. The write barrier
+                 (see barrier.rs) adds to it whenever an old cell is made to reference a young one
+        major -> mark and sweep over the whole heap, old cells included
+        full -> a copying collection over the whole heap, which compacts it as well (see copying.rs)
+
+    `collect()` under `Algorithm::Generational` runs a minor collection, then a major one straight away if the minor
+    one leaves the heap over its threshold (or full, without one). Embedders with a policy of their own can call
+    `Heap::collect_minor()`, `collect_major()` and `collect_full()` directly, whatever the algorithm, and each kind
+    is counted separately in `HeapStats`.
+*/
+
+use core::{fmt, ops::Range, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{collect_as, copying, log::Event, mark, stats::GcCycle, Algorithm, Heap, Instant, Marking, MarkOrder};
+use crate::prelude::*;
+
+/// How many collections a cell has to survive before it is old (tenured), unless the policy says otherwise
+pub const TENURING_THRESHOLD: usize = 1;
+
+/// Which kind of collection a cycle was, see the top of this module
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CollectionKind {
+    #[default]
+    Whole,              //The policy's algorithm over the whole heap, not generational
+    Minor,              //The young cells only, tracing from the roots and the remembered set
+    Major,              //Mark and sweep over the whole heap
+    Full,               //A copying collection over the whole heap, compacting it
+}
+
+impl CollectionKind {
+    /// Names accepted by `from_name()`, for help and error messages
+    pub const NAMES: [&'static str; 3] = ["minor", "major", "full"];
+
+    /// Parses a kind of collection from its name, i.e. `"minor"` -> `CollectionKind::Minor`
+    pub fn from_name(name: &str) -> Option<CollectionKind> {
+        match name {
+            "minor" => Some(CollectionKind::Minor),
+            "major" => Some(CollectionKind::Major),
+            "full" => Some(CollectionKind::Full),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CollectionKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CollectionKind::Whole => "whole heap",
+            CollectionKind::Minor => "minor",
+            CollectionKind::Major => "major",
+            CollectionKind::Full => "full",
+        })
+    }
+}
+
+/// The part of a generational heap a cell in use lives in, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Space {
+    Eden,               //Young, and not in a survivor space (where every allocation starts)
+    Survivor(usize),    //In survivor space 0 or 1
+    Tenured,            //Old, and not in a survivor space
+}
+
+impl fmt::Display for Space {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Space::Eden => f.write_str("eden"),
+            Space::Survivor(space) => write!(f, "survivor {}", space),
+            Space::Tenured => f.write_str("tenured"),
+        }
+    }
+}
+
+/// How many cells are in use in one space, from `Heap::space_usage()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpaceUsage {
+    pub space: Space,
+    pub used: usize,
+    pub capacity: usize,            //Eden and the tenured space share every cell outside the survivor spaces
+}
+
+impl Heap {
+    /// Is the cell at `index` in use, and still in the nursery?
+    pub fn is_young(&self, index: usize) -> bool {
+        !self.cells[index].freed && self.cells[index].age < self.policy.tenuring_threshold
+    }
+
+    /// Is the cell at `index` in use, and tenured?
+    pub fn is_old(&self, index: usize) -> bool {
+        !self.cells[index].freed && self.cells[index].age >= self.policy.tenuring_threshold
+    }
+
+    /// Sets how many collections a cell has to survive before it is old.
+    /// Cells can change generation without a write, so the remembered set is worked out again from the cells
+    pub fn set_tenuring_threshold(&mut self, threshold: usize) {
+        self.policy.tenuring_threshold = threshold.max(1);
+        self.refresh_remembered(CollectionKind::Whole);
+    }
+
+    /// The cells of the two survivor spaces, if the policy sets them aside (and the heap is big enough for them)
+    pub fn survivor_spaces(&self) -> Option<[Range<usize>; 2]> {
+        let size: usize = self.policy.survivor_size?;
+        let end: usize = self.cells.len();
+        (size > 0 && 2 * size < end).then(|| [end - 2 * size..end - size, end - size..end])
+    }
+
+    /// Which survivor space the next minor collection copies survivors into (the other is the one they come from)
+    pub fn to_space(&self) -> usize {
+        self.to_space
+    }
+
+    /// The space the cell at `index` lives in, or None if it is free
+    pub fn space(&self, index: usize) -> Option<Space> {
+        if self.cells[index].freed {
+            return None;
+        }
+        match self.survivor_spaces() {
+            Some(spaces) if spaces[0].contains(&index) => Some(Space::Survivor(0)),
+            Some(spaces) if spaces[1].contains(&index) => Some(Space::Survivor(1)),
+            _ if self.is_young(index) => Some(Space::Eden),
+            _ => Some(Space::Tenured),
+        }
+    }
+
+    /// How full each space is -> eden, each survivor space (if there are any) and the tenured space, in the order cells move through them
+    pub fn space_usage(&self) -> Vec<SpaceUsage> {
+        let spaces: Option<[Range<usize>; 2]> = self.survivor_spaces();
+        let shared: usize = spaces.as_ref().map_or(self.cells.len(), |spaces| spaces[0].start);
+        let mut usage: Vec<SpaceUsage> = vec![SpaceUsage { space: Space::Eden, used: 0, capacity: shared }];
+        if let Some(spaces) = &spaces {
+            for (space, cells) in spaces.iter().enumerate() {
+                usage.push(SpaceUsage { space: Space::Survivor(space), used: 0, capacity: cells.len() });
+            }
+        }
+        usage.push(SpaceUsage { space: Space::Tenured, used: 0, capacity: shared });
+
+        for index in 0..self.cells.len() {
+            if let Some(space) = self.space(index) {
+                usage.iter_mut().find(|usage| usage.space == space).expect("every space is counted").used += 1;
+            }
+        }
+        usage
+    }
+
+    /// Free cells that an allocation can use -> every free cell outside the survivor spaces (and not a relocation's stub)
+    pub fn room(&self) -> usize {
+        let reserved: Range<usize> = self.reserved();
+        (0..self.cells.len()).filter(|&i| self.cells[i].freed && self.cells[i].forward.is_none() && !reserved.contains(&i)).count()
+    }
+
+    //The cells allocations don't use (the survivor spaces), empty if there are none
+    pub(crate) fn reserved(&self) -> Range<usize> {
+        match self.survivor_spaces() {
+            Some(spaces) => spaces[0].start..spaces[1].end,
+            None => 0..0,
+        }
+    }
+
+    /// Collects the young cells only, see the top of this module
+    pub fn collect_minor(&mut self) {
+        collect_as(self, CollectionKind::Minor);
+    }
+
+    /// Collects the whole heap with mark and sweep, old cells included
+    pub fn collect_major(&mut self) {
+        collect_as(self, CollectionKind::Major);
+    }
+
+    /// Collects the whole heap with a copying collection, compacting it
+    pub fn collect_full(&mut self) {
+        collect_as(self, CollectionKind::Full);
+    }
+
+    /// Old cells that may reference young ones, traced from as roots by a minor collection
+    pub fn remembered_set(&self) -> &[usize] {
+        &self.remembered
+    }
+
+    //Called by the write barrier before `from` references `to` -> an old cell about to reference a young one is remembered
+    pub(crate) fn remember(&mut self, from: usize, to: usize) {
+        if self.is_old(from) && self.is_young(to) && !self.remembered.contains(&from) {
+            self.remembered.push(from);
+        }
+    }
+
+    //Brings the remembered set up to date once a collection has aged the cells that survived it.
+    //A whole heap collection has looked at every cell (and may have moved them), so the set is worked out again from scratch.
+    //A minor one has only looked at the nursery -> the set keeps the old cells that still reference young ones,
+    //along with any cell that has just been promoted while referencing a cell that is still young
+    pub(crate) fn refresh_remembered(&mut self, kind: CollectionKind) {
+        let candidates: Vec<usize> = match kind {
+            CollectionKind::Minor => {
+                let threshold: usize = self.policy.tenuring_threshold;
+                let promoted = (0..self.cells.len()).filter(|&i| !self.cells[i].freed && self.cells[i].age == threshold);
+                let mut candidates: Vec<usize> = self.remembered.clone();
+                candidates.extend(promoted.filter(|i| !self.remembered.contains(i)));
+                candidates
+            }
+            CollectionKind::Whole | CollectionKind::Major | CollectionKind::Full => (0..self.cells.len()).collect(),
+        };
+
+        self.remembered = candidates
+            .into_iter()
+            .filter(|&i| self.is_old(i) && self.cells[i].pointers().iter().any(|&to| self.is_young(to)))
+            .collect();
+    }
+}
+
+/// Minor collection -> marks the young cells reachable from the roots and the remembered set, and sweeps the rest
+/// of the nursery. Old cells are marked up front, so the trace stops as soon as it reaches one
+pub(crate) fn minor(heap: &mut Heap) -> GcCycle {
+    //Every reference the remembered set should hold but doesn't, found before the collection trusts it (see verify.rs)
+    let missed: Vec<(usize, usize)> = match heap.policy.verify_remembered {
+        true => heap.unremembered(),
+        false => Vec::new(),
+    };
+
+    let start = Instant::now();
+    let mut roots: Vec<usize> = heap.roots().into_iter().filter(|&root| heap.is_young(root)).collect();
+    let remembered: Vec<usize> = heap.remembered.iter().copied().filter(|&old| heap.is_old(old)).collect();
+    for old in remembered {
+        roots.extend(heap.cells[old].pointers().iter().copied().filter(|&to| heap.is_young(to)));
+    }
+    for index in 0..heap.cells.len() {
+        if heap.is_old(index) {
+            heap.cells[index].marked = true;
+        }
+    }
+    let root_scan = start.elapsed();
+
+    let order: MarkOrder = heap.policy.mark_order;
+    heap.log.emit(Event::MarkStart { roots: roots.len(), order });
+    let start = Instant::now();
+    let marking: Marking = mark(&mut heap.cells, roots, order, heap.policy.mark_stack);
+    let mark_time = start.elapsed();
+    for &index in &marking.visited {
+        heap.log.emit(Event::Marked { index });
+    }
+    if let Some(limit) = heap.policy.mark_stack.filter(|_| marking.rescans > 0) {
+        heap.log.emit(Event::MarkOverflow { limit, rescans: marking.rescans });
+    }
+    let marked: usize = marking.visited.len();
+    heap.log.emit(Event::MarkEnd { marked });
+
+    //Every old cell is marked, so only the nursery is swept. What it keeps is then copied into a survivor space (if there are any)
+    let (swept, mut sweep_time) = heap.sweep_phase();
+    for (from, to) in missed {
+        heap.log.emit(Event::BarrierMiss { from, to, lost: heap.cells[to].freed });
+    }
+    let start = Instant::now();
+    let survivors: Vec<usize> = marking.visited.into_iter().filter(|&index| !heap.cells[index].freed).collect();
+    let left_in_place: usize = match heap.survivor_spaces() {
+        Some(spaces) => copy_survivors(heap, survivors, spaces),
+        None => 0,
+    };
+    sweep_time += start.elapsed();
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked,
+        swept,
+        bytes_swept: 0,
+        root_scan,
+        mark: mark_time,
+        sweep: sweep_time,
+        algorithm: Algorithm::Generational,
+        kind: CollectionKind::Minor,
+        left_in_place,
+    }
+}
+
+//Copies the young cells a minor collection kept into the to-space, or promotes them, see the top of this module.
+//Returns how many were promoted out of a survivor space with no room in the tenured space, and left where they were
+fn copy_survivors(heap: &mut Heap, survivors: Vec<usize>, spaces: [Range<usize>; 2]) -> usize {
+    let threshold: usize = heap.policy.tenuring_threshold;
+    let to: usize = heap.to_space;
+    let mut to_space = spaces[to].clone().filter(|&i| heap.cells[i].freed).collect::<Vec<usize>>().into_iter();
+    let mut tenured = (0..spaces[0].start).filter(|&i| heap.cells[i].freed && heap.cells[i].forward.is_none()).collect::<Vec<usize>>().into_iter();
+
+    let mut moves: Vec<(usize, usize)> = Vec::new();
+    let (mut copied, mut promoted, mut early, mut left) = (0, 0, 0, 0);
+    for index in survivors {
+        let in_survivor_space: bool = spaces[0].contains(&index) || spaces[1].contains(&index);
+        let destination: Option<usize> = if heap.cells[index].age + 1 >= threshold {
+            promoted += 1;
+            if in_survivor_space { tenured.next() } else { None }           //Promoted out of eden where it lies
+        } else if spaces[to].contains(&index) {
+            None                                                            //Already in the to-space (i.e. after a restore)
+        } else if let Some(free) = to_space.next() {
+            copied += 1;
+            Some(free)
+        } else {
+            heap.cells[index].age = threshold - 1;                          //Old once this collection ages it
+            promoted += 1;
+            early += 1;
+            if in_survivor_space { tenured.next() } else { None }
+        };
+
+        match destination {
+            Some(destination) => moves.push((index, destination)),
+            None if in_survivor_space && heap.cells[index].age + 1 >= threshold => left += 1, //Promoted, but the tenured space is full
+            None => {}
+        }
+    }
+
+    copying::move_cells(heap, &moves);
+    heap.to_space = 1 - to;
+    heap.log.emit(Event::Survivors { copied, to, promoted, early });
+    if left > 0 {
+        heap.log.emit(Event::EvacuationFailed { left });
+    }
+    left
+}
+
+/// Does the heap need a major collection after a minor one? Yes if it is still over its threshold (or full, without one)
+pub(crate) fn needs_major(heap: &Heap) -> bool {
+    match heap.policy.gc_threshold {
+        Some(threshold) => heap.occupancy() > threshold,
+        None => heap.cells.iter().all(|cell| !cell.freed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, collect, configure_roots, free_alloc, roots, spec_alloc, stats::HeapStats, tests::graph, unlink_reference};
+
+    #[test]
+    fn collects_young_cells_in_minor_collections() {
+        let mut heap: Heap = graph(2, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.collect_major();                                           //0 and 1 are old from now on
+        spec_alloc(&mut heap, 2, None, 2).unwrap();
+        spec_alloc(&mut heap, 3, None, 3).unwrap();
+        assign_reference(&mut heap, 1, 2);
+        assert_eq!(heap.remembered_set(), &[1]);
+
+        //1 is garbage, but a minor collection takes it to be live, so 2 is kept through the remembered set
+        unlink_reference(&mut heap, 0, 1);
+        heap.collect_minor();
+        assert!(!heap.cells[1].freed && !heap.cells[2].freed && heap.cells[3].freed);
+        assert!(heap.remembered_set().is_empty());
+
+        heap.collect_major();
+        assert!(heap.cells[1].freed && heap.cells[2].freed);
+        heap.collect_full();
+        heap.policy.algorithm = Algorithm::Generational;
+        collect(&mut heap);                                             //Minor only, the heap has plenty of room
+        let stats: HeapStats = heap.stats();
+        assert_eq!((stats.minor_collections, stats.major_collections, stats.full_collections), (2, 2, 1));
+    }
+
+    #[test]
+    fn names_each_kind_of_collection_it_can_be_asked_for() {
+        for name in CollectionKind::NAMES {
+            assert_eq!(CollectionKind::from_name(name).unwrap().to_string(), name);
+        }
+        assert_eq!(CollectionKind::from_name("whole heap"), None);      //Only the policy's algorithm runs those
+        assert_eq!(CollectionKind::from_name("young"), None);
+    }
+
+    #[test]
+    fn copies_survivors_through_the_survivor_spaces() {
+        let mut heap: Heap = Heap::with_seed(12, 0);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Generational;
+        heap.policy.survivor_size = Some(2);                            //Cells 8-9 and 10-11
+        heap.set_tenuring_threshold(3);
+        let kept: usize = free_alloc(&mut heap, 1, None).unwrap();
+        configure_roots(&mut heap, roots::GLOBALS, &[kept]);
+        free_alloc(&mut heap, 2, None).unwrap();                        //Garbage
+        let used = |heap: &Heap| heap.space_usage().iter().map(|usage| usage.used).collect::<Vec<usize>>();
+
+        //Eden -> survivor 0 -> survivor 1 -> tenured, the root set following the cell wherever it goes
+        collect(&mut heap);
+        assert_eq!((used(&heap), heap.root_sets()[0].cells.clone()), (vec![0, 1, 0, 0], vec![8]));
+        collect(&mut heap);
+        assert_eq!((used(&heap), heap.root_sets()[0].cells.clone()), (vec![0, 0, 1, 0], vec![10]));
+        collect(&mut heap);
+        assert_eq!((used(&heap), heap.root_sets()[0].cells.clone()), (vec![0, 0, 0, 1], vec![0]));
+        assert_eq!(heap.space(0), Some(Space::Tenured));
+
+        //Three survivors don't fit in a survivor space of two, so one is promoted early
+        for data in 3..6 {
+            let young: usize = free_alloc(&mut heap, data, None).unwrap();
+            assign_reference(&mut heap, 0, young);
+        }
+        assert!(heap.cells.iter().skip(8).all(|cell| cell.freed));     //Allocations never go in a survivor space
+        heap.collect_minor();
+        assert_eq!(used(&heap), vec![0, 0, 2, 2]);
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn does_without_survivor_spaces_that_leave_no_room() {
+        let mut heap: Heap = Heap::with_seed(8, 0);
+        heap.log.echo = false;
+        heap.set_tenuring_threshold(0);
+        assert_eq!(heap.policy.tenuring_threshold, 1);                  //A cell has to survive at least one collection
+
+        //Two spaces of 4 would take the whole heap, so there are none, and every cell is free for allocation
+        heap.policy.survivor_size = Some(4);
+        assert!(heap.survivor_spaces().is_none() && heap.room() == 8);
+        heap.policy.survivor_size = Some(3);
+        assert_eq!((heap.survivor_spaces(), heap.room()), (Some([2..5, 5..8]), 2));
+        assert_eq!((heap.space(0), heap.space(6)), (None, None));       //Free cells aren't in any space
+    }
+}
@@ -0,0 +1,138 @@
+//Generational handles
+/*
+    A cell's index alone doesn't say which object it means. Once the object is freed (or swept) the index
+    goes back into the pool, and the next allocation there is a different object at the same index,
+    so an old index silently points at whatever happens to be there now.
+
+    A Handle carries the index along with the cell's generation, which is bumped every time the cell is freed.
+    Every function here checks the generation before touching the cell, and refuses a handle that is out of date
+    with a `Stale` error, rather than acting on the wrong object.
+
+    Handles don't follow objects that a copying collection moves (see copying.rs) -> a moved object's old handles
+    go stale, the same as if it had been freed. The exception is a relocation in progress (see relocation.rs), where
+    handles follow the forwarding pointer left behind until it is over.
+*/
+
+use core::fmt;
+
+use crate::{assign_reference, error::{ErrorKind, GcError, Operation}, free_cell, set_data, unlink_reference, Cell, Heap};
+
+/// A reference to whatever object is in a cell right now, that knows when that object is gone
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Handle {
+    pub index: usize,
+    pub generation: u32,            //The cell's generation when the handle was made
+}
+
+impl fmt::Display for Handle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}#{}", self.index, self.generation)
+    }
+}
+
+impl Heap {
+    /// A handle to the object in a cell, or None if the cell is free (or outside the heap)
+    pub fn handle(&self, index: usize) -> Option<Handle> {
+        self.cells
+            .get(index)
+            .filter(|cell| !cell.freed)
+            .map(|cell| Handle { index, generation: cell.generation })
+    }
+
+    /// The index of the object a handle refers to, through the read barrier while a relocation is moving it.
+    /// Returns `Stale` error if the object has since been freed, swept or moved (or the heap reset)
+    pub fn resolve(&self, handle: Handle) -> Result<usize, GcError> {
+        match self.cells.get(handle.index) {
+            Some(cell) if cell.forward.is_some() && cell.generation == handle.generation => Ok(self.read_barrier(handle.index)),
+            Some(cell) if !cell.freed && cell.generation == handle.generation => Ok(handle.index),
+            _ => Err(GcError::new(ErrorKind::Stale, Operation::Resolve, Some(handle.index))),
+        }
+    }
+
+    /// The cell holding the object a handle refers to, see `resolve()`
+    pub fn get(&self, handle: Handle) -> Result<&Cell, GcError> {
+        Ok(&self.cells[self.resolve(handle)?])
+    }
+
+    /// Allocates data in the first free cell, see `free_alloc()`.
+    /// If there are none the heap is collected, then the out of memory handler is asked what to do (see oom.rs)
+    pub fn alloc(&mut self, data: i32) -> Result<Handle, GcError> {
+        let index: usize = self.alloc_or_collect(data)?;
+        Ok(Handle { index, generation: self.cells[index].generation })
+    }
+
+    /// Makes `from` reference `to`, see `assign_reference()`.
+    /// Returns `Occupied` error if the reference is already there
+    pub fn link(&mut self, from: Handle, to: Handle) -> Result<(), GcError> {
+        let (from, to) = (self.resolve(from)?, self.resolve(to)?);
+        if self.cells[from].will_ref.contains(&to) {
+            return Err(GcError::new(ErrorKind::Occupied, Operation::Link, Some(from)));
+        }
+
+        assign_reference(self, from, to);
+        Ok(())
+    }
+
+    /// Removes the reference from `from` to `to`, see `unlink_reference()`.
+    /// Returns `DataIsFree` error if there is no such reference
+    pub fn unlink(&mut self, from: Handle, to: Handle) -> Result<(), GcError> {
+        let (from, to) = (self.resolve(from)?, self.resolve(to)?);
+        if !self.cells[from].will_ref.contains(&to) {
+            return Err(GcError::new(ErrorKind::DataIsFree, Operation::Unlink, Some(from)));
+        }
+
+        unlink_reference(self, from, to);
+        Ok(())
+    }
+
+    /// The data in the object a handle refers to (None for a root that was never given any)
+    pub fn read(&self, handle: Handle) -> Result<Option<i32>, GcError> {
+        Ok(self.get(handle)?.data)
+    }
+
+    /// Overwrites the data in the object a handle refers to, see `set_data()`
+    pub fn write(&mut self, handle: Handle, value: i32) -> Result<(), GcError> {
+        set_data(self, self.resolve(handle)?, value).map(|_| ())
+    }
+
+    /// Frees the object a handle refers to, see `free_cell()`. Every handle to it is stale afterwards
+    pub fn free(&mut self, handle: Handle, force: bool) -> Result<(), GcError> {
+        free_cell(self, self.resolve(handle)?, force).map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph};
+
+    #[test]
+    fn refuses_a_handle_once_its_object_is_gone() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        let garbage: Handle = heap.handle(1).unwrap();
+        assert_eq!((heap.read(garbage).unwrap(), heap.handle(2)), (Some(1), None));
+
+        //Swept, then reused by the next allocation at the same index
+        collect(&mut heap);
+        let reused: Handle = heap.alloc(9).unwrap();
+        assert_eq!((reused.index, reused.generation), (1, garbage.generation + 1));
+        for error in [heap.read(garbage).unwrap_err(), heap.write(garbage, 3).unwrap_err(), heap.free(garbage, false).unwrap_err()] {
+            assert_eq!((error.kind, error.cell), (ErrorKind::Stale, Some(1)));
+        }
+        assert_eq!(heap.read(reused).unwrap(), Some(9));
+    }
+
+    #[test]
+    fn links_and_unlinks_through_handles() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        let (root, other) = (heap.handle(0).unwrap(), heap.handle(1).unwrap());
+
+        heap.link(root, other).unwrap();
+        assert_eq!(heap.link(root, other).unwrap_err().kind, ErrorKind::Occupied);
+        heap.unlink(root, other).unwrap();
+        assert_eq!(heap.unlink(root, other).unwrap_err().kind, ErrorKind::DataIsFree);
+        assert!(heap.get(root).unwrap().will_ref.is_empty() && heap.verify().is_empty());
+    }
+}
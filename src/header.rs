@@ -0,0 +1,111 @@
+//Object headers
+/*
+    A real runtime puts a header in front of every object, so the collector can work out what it is looking at
+    without knowing anything about the program -> the object's type (and so where its pointers are), its size,
+    and a few bits of state such as the mark bit.
+
+    `Cell::header()` reads a cell's header. The tracers go through `Cell::pointers()`, which uses the tag to decide
+    how to scan an object -> the bytes of a string can't hold references, so they are never scanned for any.
+*/
+
+use core::fmt;
+
+use crate::{Cell, Kind};
+use crate::prelude::*;
+
+/// What the collector knows about an object without looking at its data, from `Cell::header()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub tag: Kind,                  //The type of object, which says how to scan it
+    pub size: usize,                //In bytes, see sizes.rs
+    pub age: usize,                 //Collections survived
+    pub flags: Flags,
+}
+
+/// The state bits of a header
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+    pub root: bool,
+    pub marked: bool,
+    pub free: bool,
+}
+
+impl Kind {
+    /// Whether objects of this type can hold references, and so need scanning by the tracer
+    pub fn has_pointers(self) -> bool {
+        !matches!(self, Kind::Bytes)
+    }
+}
+
+impl Cell {
+    /// The header of the object in this cell
+    pub fn header(&self) -> Header {
+        Header {
+            tag: self.kind,
+            size: self.size(),
+            age: self.age,
+            flags: Flags { root: self.is_root(), marked: self.marked, free: self.freed },
+        }
+    }
+
+    /// The references the tracer follows out of this object, according to its tag
+    pub fn pointers(&self) -> &[usize] {
+        match self.kind.has_pointers() {
+            true => &self.will_ref,
+            false => &[],
+        }
+    }
+}
+
+impl fmt::Display for Header {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}, {} bytes, age {}, flags {}", self.tag, self.size, self.age, self.flags)
+    }
+}
+
+impl fmt::Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let flags: Vec<&str> = [(self.root, "root"), (self.marked, "marked"), (self.free, "free")]
+            .into_iter()
+            .filter_map(|(set, name)| set.then_some(name))
+            .collect();
+
+        match flags.is_empty() {
+            true => f.write_str("none"),
+            false => f.write_str(&flags.join(" ")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph, Heap};
+
+    #[test]
+    fn describes_an_object_from_its_header() {
+        let mut heap: Heap = graph(2, &[(0, 1)]);
+        heap.log.echo = false;
+        collect(&mut heap);
+
+        let header: Header = heap.cells[0].header();
+        assert_eq!((header.tag, header.size, header.age), (Kind::Int, 8, 1));
+        assert!(header.flags.root && !header.flags.free);
+        assert_eq!(heap.cells[3].header().to_string(), "int, 0 bytes, age 0, flags free");
+        assert_eq!(Flags::default().to_string(), "none");
+    }
+
+    #[test]
+    fn never_scans_the_bytes_of_a_string_for_references() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.cells[1].kind = Kind::Bytes;
+        heap.cells[1].will_ref.push(2);                                 //Not a pointer, whatever the edge lists say
+        heap.cells[2].by_ref.push(1);
+
+        assert!(heap.cells[1].pointers().is_empty() && heap.cells[0].pointers() == [1]);
+        assert!(!Kind::Bytes.has_pointers() && Kind::Record.has_pointers());
+        assert_eq!(heap.reachable()[..3], [true, true, false]);
+        assert_eq!(heap.root_paths(2), Vec::<Vec<usize>>::new());
+    }
+}
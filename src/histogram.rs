@@ -0,0 +1,180 @@
+//Heap demographics
+/*
+    A heap of thousands of cells can't be read cell by cell, so heap analyzers summarise it instead -> how many
+    live objects there are of each age, fan-in, size or type. `Heap::histogram()` counts the cells in use along
+    one of those dimensions:
+
+        age -> collections survived, which shows whether objects die young (the generational hypothesis)
+        refs -> fan-in, how many cells reference each object. Objects with a large fan-in are hard to get rid of
+        size -> bytes, as measured by sizes.rs
+        type -> what the object is (int, string, list, ...), records by their type name
+
+    Ages, fan-in and sizes are counted in buckets that double in width (0, 1, 2-3, 4-7, ...), so a few outliers
+    don't spread the rest out over a long, mostly empty axis. Types are counted one bucket each, most common first.
+*/
+
+use core::fmt;
+
+use crate::{Heap, Kind};
+use crate::prelude::*;
+
+/// What a histogram counts the live cells by, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dimension {
+    Age,
+    Refs,
+    Size,
+    Type,
+}
+
+/// Live cells counted along one dimension, from `Heap::histogram()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Histogram {
+    pub dimension: Dimension,
+    pub buckets: Vec<Bucket>,       //In order of the values they hold, or most common first for types
+}
+
+/// How many live cells fall in one range of values (or have one type)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bucket {
+    pub label: String,              //i.e. "4-7", or "list"
+    pub count: usize,
+}
+
+impl Dimension {
+    /// Names accepted by `from_name()`, for help and error messages
+    pub const NAMES: [&'static str; 4] = ["age", "refs", "size", "type"];
+
+    /// Parses a dimension from its name, i.e. `"refs"` -> `Dimension::Refs`
+    pub fn from_name(name: &str) -> Option<Dimension> {
+        match name {
+            "age" => Some(Dimension::Age),
+            "refs" => Some(Dimension::Refs),
+            "size" => Some(Dimension::Size),
+            "type" => Some(Dimension::Type),
+            _ => None,
+        }
+    }
+
+    /// What the values being counted are, for a histogram's title
+    pub fn unit(self) -> &'static str {
+        match self {
+            Dimension::Age => "collections survived",
+            Dimension::Refs => "referenced by",
+            Dimension::Size => "bytes",
+            Dimension::Type => "what each object is",
+        }
+    }
+}
+
+impl fmt::Display for Dimension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Dimension::Age => "age",
+            Dimension::Refs => "refs",
+            Dimension::Size => "size",
+            Dimension::Type => "type",
+        })
+    }
+}
+
+impl Histogram {
+    /// How many cells were counted, across every bucket
+    pub fn total(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.count).sum()
+    }
+}
+
+impl Heap {
+    /// Counts every cell in use along `dimension`, see the top of this module
+    pub fn histogram(&self, dimension: Dimension) -> Histogram {
+        let live = self.cells.iter().filter(|cell| !cell.freed);
+        let buckets: Vec<Bucket> = match dimension {
+            Dimension::Age => doubling(live.map(|cell| cell.age)),
+            Dimension::Refs => doubling(live.map(|cell| cell.by_ref.len())),
+            Dimension::Size => doubling(live.map(|cell| cell.size())),
+            Dimension::Type => {
+                let mut buckets: Vec<Bucket> = Vec::new();
+                for cell in live {
+                    let label: String = match (&cell.record, cell.kind) {
+                        (Some(record), _) => format!("{} {}", Kind::Record, record.name),
+                        (None, kind) => kind.to_string(),
+                    };
+                    match buckets.iter_mut().find(|bucket| bucket.label == label) {
+                        Some(bucket) => bucket.count += 1,
+                        None => buckets.push(Bucket { label, count: 1 }),
+                    }
+                }
+                buckets.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.label.cmp(&b.label)));
+                buckets
+            }
+        };
+
+        Histogram { dimension, buckets }
+    }
+}
+
+//Counts values into buckets that double in width -> 0, 1, 2-3, 4-7, ... from the bucket holding the smallest value
+//up to the one holding the largest. Buckets in between that nothing falls in are kept (with a count of 0), so the gaps show
+fn doubling(values: impl Iterator<Item = usize>) -> Vec<Bucket> {
+    let mut counts: Vec<usize> = Vec::new();
+    for value in values {
+        let bucket: usize = match value {
+            0 => 0,
+            value => value.ilog2() as usize + 1,
+        };
+        if counts.len() <= bucket {
+            counts.resize(bucket + 1, 0);
+        }
+        counts[bucket] += 1;
+    }
+
+    counts
+        .into_iter()
+        .enumerate()
+        .skip_while(|&(_, count)| count == 0)
+        .map(|(bucket, count)| {
+            let label: String = match bucket {
+                0 => "0".to_string(),
+                1 => "1".to_string(),
+                bucket => format!("{}-{}", 1usize << (bucket - 1), (1usize << bucket) - 1),
+            };
+            Bucket { label, count }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, free_alloc, tests::graph};
+
+    #[test]
+    fn counts_live_cells_into_histograms() {
+        let counts = |heap: &Heap, dimension: Dimension| -> Vec<(String, usize)> {
+            heap.histogram(dimension).buckets.into_iter().map(|Bucket { label, count }| (label, count)).collect()
+        };
+
+        //Cell 0 references the rest, and cell 3 is referenced by all three others
+        let mut heap: Heap = graph(4, &[(0, 1), (0, 2), (0, 3), (1, 3), (2, 3)]);
+        heap.log.echo = false;
+        assert_eq!(counts(&heap, Dimension::Refs), [("0".to_string(), 1), ("1".to_string(), 2), ("2-3".to_string(), 1)]);
+        assert_eq!(counts(&heap, Dimension::Size), [("4-7".to_string(), 1), ("8-15".to_string(), 2), ("16-31".to_string(), 1)]);
+
+        collect(&mut heap);
+        free_alloc(&mut heap, 7, None).unwrap();
+        assert_eq!(counts(&heap, Dimension::Age), [("0".to_string(), 1), ("1".to_string(), 4)]);
+        assert_eq!(counts(&heap, Dimension::Type), [("int".to_string(), 5)]);
+        assert_eq!(heap.histogram(Dimension::Type).total(), heap.live());
+    }
+
+    #[test]
+    fn counts_nothing_on_an_empty_heap() {
+        let heap: Heap = Heap::with_seed(4, 0);
+        for name in Dimension::NAMES {
+            let histogram: Histogram = heap.histogram(Dimension::from_name(name).unwrap());
+            assert!(histogram.buckets.is_empty() && histogram.total() == 0);
+        }
+        assert_eq!(Dimension::from_name("colour"), None);
+    }
+}
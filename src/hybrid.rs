@@ -0,0 +1,171 @@
+//Hybrid reference counting (with a backup cycle tracer)
+/*
+    CPython's answer to the cycles reference counting can't reclaim -> keep reference counting for everything it
+    can reclaim, the moment it becomes garbage, and back it up with a tracing pass that only looks for garbage cycles.
+    Most garbage isn't cyclic, so the tracer has little to do, and it only ever looks at part of the heap.
+
+    The tracer starts from candidate cycle roots, the cells that could have become the way into a garbage cycle:
+
+        a cell allocated since the last pass -> it may have been built into a cycle, like CPython's youngest generation
+        a cell whose count dropped, but not to zero -> what is left of its count may come from a cycle it is in
+                                                       (Bacon and Rajan's "possible roots")
+        a cell that stopped being a root -> likewise, a cycle may be all that keeps it alive now
+
+    Each pass (at every collection under `Algorithm::Hybrid`) runs the way CPython's does. It takes every cell
+    reachable from the candidates, and subtracts the references they make to each other from their counts. Whatever
+    has a count left over is referenced from outside them (or is a root), so it is live, along with everything it
+    reaches. The rest only keep each other alive -> garbage cycles, reclaimed by the tracer.
+
+    `Heap::hybrid()` counts what each half reclaimed -> reference counting, either straight away when a reference is
+    removed or at a collection (a count of zero), and the tracer.
+*/
+
+use core::{mem, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{drop_swept_referrers, log::Event, reclaim, refcount, stats::GcCycle, Algorithm, Heap, Instant};
+use crate::prelude::*;
+
+/// The cycle tracer's candidates, and what each half of the hybrid has reclaimed (see the top of this module)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hybrid {
+    candidates: Vec<usize>,         //Candidate cycle roots for the next pass
+    pub by_rc: usize,               //Total cells reference counting reclaimed
+    pub by_tracer: usize,           //Total cells the cycle tracer reclaimed
+}
+
+impl Hybrid {
+    /// Candidate cycle roots waiting for the next pass
+    pub fn candidates(&self) -> &[usize] {
+        &self.candidates
+    }
+}
+
+impl Heap {
+    /// The hybrid's candidates and totals
+    pub fn hybrid(&self) -> &Hybrid {
+        &self.hybrid
+    }
+
+    //Makes a cell a candidate cycle root, if the heap is collected by the hybrid
+    pub(crate) fn suspect_cycle(&mut self, index: usize) {
+        if self.policy.algorithm == Algorithm::Hybrid && !self.hybrid.candidates.contains(&index) {
+            self.hybrid.candidates.push(index);
+        }
+    }
+
+    //Cells were moved (see copying.rs), so the candidates go with them. A candidate that wasn't copied was garbage
+    pub(crate) fn relocate_candidates(&mut self, forwarding: &[Option<usize>]) {
+        self.hybrid.candidates = self.hybrid.candidates.iter().filter_map(|&index| forwarding.get(index).copied().flatten()).collect();
+    }
+}
+
+/// Called once a reference to `index` is removed -> reclaims it straight away if its count is zero (see
+/// `refcount::release()`), or makes it a candidate cycle root if it is still in use
+pub(crate) fn release(heap: &mut Heap, index: usize) {
+    let freed: usize = refcount::release(heap, index);
+    heap.hybrid.by_rc += freed;
+    if freed == 0 && !heap.cells[index].freed {
+        heap.suspect_cycle(index);
+    }
+}
+
+/// Reclaims every cell whose count is zero (as `refcount::collect()` does), then runs the cycle tracer over the candidates,
+/// returning the cycle (`number` is filled in by `collect()`). The `mark` phase of the cycle is the tracer's pass,
+/// and the cells it reclaimed are counted in `swept` along with the rest
+pub fn collect(heap: &mut Heap) -> GcCycle {
+    let mut cycle: GcCycle = refcount::collect(heap);
+    heap.hybrid.by_rc += cycle.swept;
+
+    //Every cell in use reachable from the candidates
+    let start = Instant::now();
+    let size: usize = heap.cells.len();
+    let candidates: Vec<usize> = mem::take(&mut heap.hybrid.candidates).into_iter().filter(|&i| i < size && !heap.cells[i].freed).collect();
+    let mut traced: Vec<bool> = vec![false; size];
+    let mut stack: Vec<usize> = candidates.clone();
+    while let Some(i) = stack.pop() {
+        if !mem::replace(&mut traced[i], true) {
+            stack.extend(heap.cells[i].will_ref.iter().copied().filter(|&to| !heap.cells[to].freed && !traced[to]));
+        }
+    }
+
+    //Take the references they make to each other off their counts. Whatever is left comes from outside
+    let mut outside: Vec<usize> = refcount::count(&heap.cells);
+    for i in (0..size).filter(|&i| traced[i]) {
+        for &to in heap.cells[i].will_ref.iter().filter(|&&to| traced[to]) {
+            outside[to] -= 1;
+        }
+    }
+    let rooted: Vec<bool> = heap.root_flags();
+    let mut live: Vec<bool> = vec![false; size];
+    let mut stack: Vec<usize> = (0..size).filter(|&i| traced[i] && (outside[i] > 0 || rooted[i])).collect();
+    while let Some(i) = stack.pop() {
+        if !mem::replace(&mut live[i], true) {
+            stack.extend(heap.cells[i].will_ref.iter().copied().filter(|&to| traced[to] && !live[to]));
+        }
+    }
+
+    //The rest only keep each other alive
+    let mut reclaimed: usize = 0;
+    for i in (0..size).filter(|&i| traced[i] && !live[i]) {
+        if reclaim(&mut heap.cells, &mut heap.log, i) {
+            reclaimed += 1;
+        }
+    }
+    drop_swept_referrers(&mut heap.cells);
+    let trace_time: Duration = start.elapsed();
+    heap.log.emit(Event::CycleScan {
+        candidates: candidates.len(),
+        traced: traced.iter().filter(|&&traced| traced).count(),
+        reclaimed,
+    });
+
+    heap.hybrid.by_tracer += reclaimed;
+    cycle.marked -= reclaimed;
+    cycle.swept += reclaimed;
+    cycle.mark = trace_time;
+    cycle.algorithm = Algorithm::Hybrid;
+    cycle
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stats::HeapStats, tests::graph, unlink_reference};
+
+    #[test]
+    fn backs_reference_counting_up_with_a_cycle_tracer() {
+        let mut heap: Heap = graph(6, &[(0, 1), (1, 2), (2, 1), (0, 3), (3, 4)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Hybrid;
+
+        //Acyclic garbage goes straight away, a cycle is left to the tracer
+        unlink_reference(&mut heap, 0, 3);
+        unlink_reference(&mut heap, 0, 1);
+        assert!(heap.cells[3].freed && heap.cells[4].freed && !heap.cells[1].freed);
+        assert_eq!(heap.hybrid().candidates(), [1]);
+
+        crate::collect(&mut heap);
+        assert!((1..6).all(|i| heap.cells[i].freed) && heap.verify().is_empty());
+        let stats: HeapStats = heap.stats();
+        assert_eq!((stats.reclaimed_by_rc, stats.reclaimed_by_tracer), (3, 2));        //Cell 5 had a count of zero
+    }
+
+    #[test]
+    fn keeps_a_cycle_referenced_from_outside_it() {
+        let mut heap: Heap = graph(5, &[(0, 1), (1, 2), (2, 1), (0, 3), (3, 1), (0, 4)]);
+        heap.log.echo = false;
+        unlink_reference(&mut heap, 0, 4);
+        assert!(heap.hybrid().candidates().is_empty());                 //Only suspected under the hybrid
+
+        //Cell 3 still references the cycle, so the tracer finds it live
+        heap.policy.algorithm = Algorithm::Hybrid;
+        unlink_reference(&mut heap, 0, 1);
+        assert_eq!(heap.hybrid().candidates(), [1]);
+        crate::collect(&mut heap);
+        assert!(!heap.cells[1].freed && !heap.cells[2].freed && heap.cells[4].freed);
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::CycleScan { candidates: 1, traced: 2, reclaimed: 0 })));
+        assert_eq!((heap.hybrid().by_tracer, heap.hybrid().candidates()), (0, &[][..]));
+    }
+}
@@ -0,0 +1,240 @@
+//Importing an object graph from an edge list
+/*
+    Lets exact heap shapes be written by hand (or by another tool) and loaded in one go,
+    rather than built up one --alloc_at and --link_ref at a time.
+
+    Two formats are accepted, chosen by a .json extension (or content that starts with '{'):
+
+    text -> one directive per line, '#' for comments
+        capacity 20         (optional, every cell used has to fit in it. Without one the pool grows to fit the highest cell used)
+        node 3 42           (cell 3 holds the value 42)
+        edge 3 7            (cell 3 references cell 7)
+        root 3              (cell 3 is a root)
+
+    json -> the same, as one object
+        { "capacity": 20, "nodes": [{ "index": 3, "value": 42 }], "edges": [[3, 7]], "roots": [3] }
+
+    Either way the pool can't be made bigger than MAX_CAPACITY, so a typo in an index can't ask for more memory than there is.
+*/
+
+use serde::Deserialize;
+use std::{fs, io};
+
+use crate::{assign_reference, roots::GLOBALS, spec_alloc, Heap};
+
+/// The most cells an edge list can make the pool
+pub const MAX_CAPACITY: usize = 1 << 20;
+
+/// An object graph read from an edge list, before it is built on the heap
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct EdgeList {
+    #[serde(default)]
+    pub capacity: Option<usize>,            //Size of the pool, if given
+    #[serde(default)]
+    pub nodes: Vec<Node>,                   //Cells in use, and the data they hold
+    #[serde(default)]
+    pub edges: Vec<(usize, usize)>,         //(from, to) -> `from` references `to`
+    #[serde(default)]
+    pub roots: Vec<usize>,
+}
+
+/// A cell in use, in an edge list
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct Node {
+    pub index: usize,
+    pub value: i32,
+}
+
+impl EdgeList {
+    /// Parses the text format, see the top of this module
+    pub fn parse_text(text: &str) -> io::Result<EdgeList> {
+        let mut list: EdgeList = EdgeList::default();
+
+        for (number, line) in text.lines().enumerate().map(|(i, line)| (i + 1, line.trim())) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let number_at = |i: usize| -> io::Result<i64> {
+                words
+                    .get(i)
+                    .and_then(|word| word.parse::<i64>().ok())
+                    .ok_or_else(|| invalid(format!("line {}: expected a number after '{}'", number, words[..i].join(" "))))
+            };
+            let index_at = |i: usize| -> io::Result<usize> {
+                usize::try_from(number_at(i)?).map_err(|_| invalid(format!("line {}: cell indexes can't be negative", number)))
+            };
+
+            match (words[0], words.len()) {
+                ("capacity", 2) => list.capacity = Some(index_at(1)?),
+                ("node", 3) => list.nodes.push(Node {
+                    index: index_at(1)?,
+                    value: i32::try_from(number_at(2)?).map_err(|_| invalid(format!("line {}: value doesn't fit in an i32", number)))?,
+                }),
+                ("edge", 3) => list.edges.push((index_at(1)?, index_at(2)?)),
+                ("root", 2) => list.roots.push(index_at(1)?),
+                _ => return Err(invalid(format!(
+                    "line {}: expected 'capacity <n>', 'node <cell> <value>', 'edge <from> <to>' or 'root <cell>', found '{}'",
+                    number, line
+                ))),
+            }
+        }
+
+        Ok(list)
+    }
+
+    /// Parses the JSON format, see the top of this module
+    pub fn parse_json(json: &str) -> io::Result<EdgeList> {
+        serde_json::from_str(json).map_err(|why| invalid(why.to_string()))
+    }
+
+    /// Size of pool the graph needs -> its capacity, or just enough for the highest cell it uses if it doesn't give one
+    fn size(&self) -> usize {
+        self.capacity.unwrap_or_else(|| self.cells().max().map_or(0, |highest| highest + 1))
+    }
+
+    //Every cell the graph uses, as a node, either end of an edge or a root
+    fn cells(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes
+            .iter()
+            .map(|node| node.index)
+            .chain(self.edges.iter().flat_map(|&(from, to)| [from, to]))
+            .chain(self.roots.iter().copied())
+    }
+
+    //Every cell has to fit in the pool, every edge has to be between cells in use, and each cell declared once
+    fn check(&self) -> io::Result<()> {
+        match self.capacity {
+            Some(capacity) if capacity > MAX_CAPACITY => {
+                return Err(invalid(format!("capacity {} is more than an edge list can ask for ({} cells)", capacity, MAX_CAPACITY)));
+            }
+            Some(capacity) => {
+                if let Some(bad) = self.cells().find(|&cell| cell >= capacity) {
+                    return Err(invalid(format!("cell {} is past the end of the pool (capacity {})", bad, capacity)));
+                }
+            }
+            None => {
+                if let Some(bad) = self.cells().find(|&cell| cell >= MAX_CAPACITY) {
+                    return Err(invalid(format!("cell {} is past the most cells an edge list can use ({})", bad, MAX_CAPACITY)));
+                }
+            }
+        }
+
+
+        let mut declared: Vec<usize> = Vec::new();
+        for node in &self.nodes {
+            if declared.contains(&node.index) {
+                return Err(invalid(format!("cell {} is declared as a node more than once", node.index)));
+            }
+            declared.push(node.index);
+        }
+
+        for &(from, to) in &self.edges {
+            if let Some(missing) = [from, to].into_iter().find(|cell| !declared.contains(cell)) {
+                return Err(invalid(format!("edge {} -> {} uses cell {}, which isn't declared as a node", from, to, missing)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Heap {
+    /// Replaces the heap with an object graph read from an edge list file (see the top of this module for the formats).
+    /// The pool is reset first (keeping the current size, unless the graph needs more or gives a capacity, up to `MAX_CAPACITY`),
+    /// then built through the usual allocation and reference functions, so every step is logged as normal.
+    /// Nothing changes if the file can't be read or doesn't make sense
+    pub fn import(&mut self, path: &str) -> io::Result<()> {
+        let content: String = fs::read_to_string(path)?;
+        let list: EdgeList = match path.ends_with(".json") || content.trim_start().starts_with('{') {
+            true => EdgeList::parse_json(&content)?,
+            false => EdgeList::parse_text(&content)?,
+        };
+        list.check()?;
+
+        let size: usize = match list.capacity {
+            Some(_) => list.size(),
+            None => list.size().max(self.cells.len()),
+        };
+        if size == 0 {
+            return Err(invalid("the edge list is empty, and doesn't give a capacity".to_string()));
+        }
+        self.reset(Some(size));
+
+        for node in &list.nodes {
+            //Can't fail, the pool was just emptied and each cell is only declared once
+            let _ = spec_alloc(self, node.value, None, node.index);
+        }
+        for &root in &list.roots {
+            //Can't fail, the pool was sized to fit every root
+            let _ = self.add_root(GLOBALS, root);
+        }
+        for &(from, to) in &list.edges {
+            assign_reference(self, from, to);
+        }
+
+        Ok(())
+    }
+}
+
+//Shorthand for an edge list that was read fine, but doesn't make sense
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    //Writes `content` to a scratch file named `name`, imports it and removes it again
+    fn import(heap: &mut Heap, name: &str, content: &str) -> io::Result<()> {
+        let path = env::temp_dir().join(format!("gc-rust-import-{}-{}", process::id(), name));
+        fs::write(&path, content).unwrap();
+        let imported: io::Result<()> = heap.import(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        imported
+    }
+
+    #[test]
+    fn builds_the_graph_from_either_format() {
+        let text: &str = "# a root holding a pair\ncapacity 4\nnode 0 1\nnode 2 5\n\nnode 3 6\nedge 0 2\nedge 2 3\nroot 0\n";
+        let json: &str = r#"{ "capacity": 4, "nodes": [{ "index": 0, "value": 1 }, { "index": 2, "value": 5 }, { "index": 3, "value": 6 }], "edges": [[0, 2], [2, 3]], "roots": [0] }"#;
+
+        for (name, content) in [("graph.txt", text), ("graph.json", json)] {
+            let mut heap: Heap = Heap::with_seed(10, 0);
+            heap.log.echo = false;
+            import(&mut heap, name, content).unwrap();
+            assert_eq!(heap.cells.len(), 4);
+            assert_eq!(heap.cells.iter().map(|cell| cell.data).collect::<Vec<_>>(), [Some(1), None, Some(5), Some(6)]);
+            assert_eq!((heap.roots(), heap.cells[2].will_ref.clone()), (vec![0], vec![3]));
+            assert!(heap.verify().is_empty());
+        }
+    }
+
+    #[test]
+    fn leaves_the_heap_alone_when_the_edge_list_makes_no_sense() {
+        let mut heap: Heap = Heap::with_seed(3, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 7, None, 1).unwrap();
+
+        for (content, why) in [
+            ("node 0 1\nedge 0 2\n", "edge 0 -> 2 uses cell 2, which isn't declared as a node"),
+            ("node 0 1\nnode 0 2\n", "cell 0 is declared as a node more than once"),
+            ("node -1 4\n", "line 1: cell indexes can't be negative"),
+            ("node 0\n", "line 1: expected 'capacity <n>', 'node <cell> <value>', 'edge <from> <to>' or 'root <cell>', found 'node 0'"),
+            ("node 0 99999999999\n", "line 1: value doesn't fit in an i32"),
+            ("capacity 2\nnode 0 1\nroot 2\n", "cell 2 is past the end of the pool (capacity 2)"),
+            ("node 99999999999 1\n", "cell 99999999999 is past the most cells an edge list can use (1048576)"),
+            ("capacity 99999999999\n", "capacity 99999999999 is more than an edge list can ask for (1048576 cells)"),
+        ] {
+            let error: io::Error = import(&mut heap, "bad.txt", content).unwrap_err();
+            assert_eq!((error.kind(), error.to_string()), (io::ErrorKind::InvalidData, why.to_string()));
+        }
+        assert_eq!(import(&mut heap, "empty.json", r#"{ "capacity": 0 }"#).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(heap.import("/nonexistent/graph.txt").is_err());
+        assert_eq!((heap.cells.len(), heap.cells[1].data), (3, Some(7)));
+    }
+}
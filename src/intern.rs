@@ -0,0 +1,168 @@
+//Interning
+/*
+    Immutable values that are equal can share one copy, rather than each allocation taking up a cell of its own ->
+    the JVM's string pool, or the symbols of Lisp and Ruby. Interning a value looks it up in the intern table first,
+    and only allocates it if it isn't there, so every equal value interned while the first is still alive is the same cell.
+
+        strings -> any string is interned, the saving grows with its length (see strings.rs)
+        ints -> only those at least `Policy::intern_threshold` away from zero. A small int takes one cell either way,
+                so the lookup isn't worth it (`--set-policy intern-threshold <n>`)
+
+    The table holds its cells weakly -> it doesn't keep them alive, so an interned value nothing else references is
+    garbage like any other, and is collected. Every entry remembers the generation of its cell (see handle.rs), and is
+    only used if the cell still holds the same object with the same value. So an entry whose cell was reclaimed
+    (or overwritten with --set) is dropped from the table the next time it is looked in, rather than handing out the
+    wrong cell. The table follows cells a copying collection moves.
+
+    `Heap::interner()` counts the allocations interning saved, and the bytes they would have taken up.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::GcError, free_alloc, Cell, Heap, IndexResult, Kind};
+use crate::prelude::*;
+
+/// Ints closer to zero than this aren't interned, unless `--set-policy intern-threshold` says otherwise
+pub const INTERN_THRESHOLD: i32 = 128;
+
+/// A value the intern table can share
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Payload {
+    Int(i32),
+    Str(String),
+}
+
+/// The intern table, and what interning has saved (see the top of this module)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Interner {
+    entries: Vec<(Payload, usize, u32)>,    //Each value with the cell it is in, and that cell's generation when it was interned
+    pub deduplicated: usize,                //Total allocations interning didn't have to make, as the value was already there
+    pub bytes_saved: usize,                 //...and the bytes they would have taken up
+}
+
+impl Interner {
+    /// How many values are in the table (including any whose cells have been reclaimed since it was last looked in)
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Is the table empty?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Heap {
+    /// The intern table and its totals
+    pub fn interner(&self) -> &Interner {
+        &self.interner
+    }
+
+    /// The cell an int is in, allocating it in the first free cell unless an equal int has already been interned
+    /// (ints under `Policy::intern_threshold` in size are always allocated). Returns the cell, and whether it was shared.
+    /// Returns `NoFreeMemory` error if it has to be allocated and there are no free cells
+    pub fn intern_int(&mut self, value: i32) -> Result<(usize, bool), GcError> {
+        if value.unsigned_abs() < self.policy.intern_threshold.unsigned_abs() {
+            return Ok((free_alloc(self, value, None)?, false));
+        }
+        self.intern(Payload::Int(value), |heap| free_alloc(heap, value, None))
+    }
+
+    /// The head of a string, allocating it in the first free cells unless an equal string has already been interned.
+    /// Returns the head, and whether it was shared.
+    /// Returns `NoFreeMemory` error if it has to be allocated and there aren't enough free cells (see `alloc_str()`)
+    pub fn intern_str(&mut self, text: &str) -> Result<(usize, bool), GcError> {
+        self.intern(Payload::Str(text.to_string()), |heap| heap.alloc_str(text, None))
+    }
+
+    //Looks a value up in the table (dropping the entries that have gone stale), allocating it if it isn't there
+    fn intern(&mut self, payload: Payload, alloc: impl FnOnce(&mut Heap) -> IndexResult) -> Result<(usize, bool), GcError> {
+        let cells: &[Cell] = &self.cells;
+        self.interner.entries.retain(|(payload, index, generation)| holds(cells, payload, *index, *generation));
+        if let Some(&(_, index, _)) = self.interner.entries.iter().find(|(interned, ..)| *interned == payload) {
+            self.interner.deduplicated += 1;
+            self.interner.bytes_saved += footprint(&self.cells, index);
+            return Ok((index, true));
+        }
+
+        let index: usize = alloc(self)?;
+        self.interner.entries.push((payload, index, self.cells[index].generation));
+        Ok((index, false))
+    }
+
+    //Cells were moved (see copying.rs), so the entries go with them. An entry whose cell wasn't copied is dropped
+    pub(crate) fn relocate_interned(&mut self, forwarding: &[Option<usize>]) {
+        let cells: &[Cell] = &self.cells;
+        self.interner.entries = self
+            .interner
+            .entries
+            .drain(..)
+            .filter_map(|(payload, index, _)| {
+                let index: usize = forwarding.get(index).copied().flatten()?;
+                Some((payload, index, cells[index].generation))
+            })
+            .collect();
+    }
+}
+
+//Is the object interned in a cell still there, holding the value it was interned with?
+fn holds(cells: &[Cell], payload: &Payload, index: usize, generation: u32) -> bool {
+    let Some(cell) = cells.get(index).filter(|cell| !cell.freed && cell.generation == generation) else {
+        return false;
+    };
+    match payload {
+        Payload::Int(value) => cell.kind == Kind::Int && cell.data == Some(*value),
+        Payload::Str(text) => crate::strings::read(cells, index).is_some_and(|read| read == *text),
+    }
+}
+
+//Bytes an object takes up, including the cells holding a string's bytes
+fn footprint(cells: &[Cell], index: usize) -> usize {
+    let bytes: usize = match cells[index].kind {
+        Kind::Str => cells[index].will_ref.iter().map(|&to| cells[to].size()).sum(),
+        _ => 0,
+    };
+    cells[index].size() + bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, configure_roots, error::ErrorKind, roots, sizes, stats::HeapStats};
+
+    #[test]
+    fn shares_interned_values_until_they_are_collected() {
+        let mut heap: Heap = Heap::with_seed(12, 0);
+        heap.log.echo = false;
+        let (number, _) = heap.intern_int(1000).unwrap();
+        let (text, _) = heap.intern_str("interned").unwrap();
+        assert_eq!(heap.intern_int(1000).unwrap(), (number, true));
+        assert_eq!(heap.intern_str("interned").unwrap(), (text, true));
+        assert_ne!(heap.intern_int(5).unwrap().0, heap.intern_int(5).unwrap().0);       //Under the threshold
+        let stats: HeapStats = heap.stats();
+        assert_eq!((stats.interned, stats.bytes_interned), (2, sizes::WORD * 6));          //The int, then the string's head (and its references) and bytes
+
+        //The table doesn't keep them alive, so once they are collected the next one is allocated again
+        configure_roots(&mut heap, roots::GLOBALS, &[text]);
+        collect(&mut heap);
+        assert!(heap.cells[number].freed);
+        assert!(!heap.intern_int(1000).unwrap().1);
+        assert_eq!(heap.intern_str("interned").unwrap(), (text, true));
+    }
+
+    #[test]
+    fn allocates_again_rather_than_share_a_value_that_changed() {
+        let mut heap: Heap = Heap::with_seed(2, 0);
+        heap.log.echo = false;
+        let (number, _) = heap.intern_int(1000).unwrap();
+        heap.cells[number].data = Some(7);                              //Overwritten, as --set does
+
+        assert_eq!(heap.intern_int(1000).unwrap(), (1, false));
+        assert_eq!(heap.interner().len(), 1);                           //The stale entry was dropped
+
+        //Sharing needs no room, but allocating does
+        assert_eq!(heap.intern_int(1000).unwrap(), (1, true));
+        assert_eq!(heap.intern_int(2000).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        assert_eq!(heap.intern_str("full").unwrap_err().kind, ErrorKind::NoFreeMemory);
+    }
+}
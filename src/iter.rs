@@ -0,0 +1,71 @@
+//Iterating the heap
+/*
+    Walking the heap cell by cell means knowing which cells hold an object, and which of those are still reachable.
+    These iterators do that once, so anything that wants to look at every object (a library user, or a REPL command)
+    doesn't have to reach into `heap.cells` and repeat the checks.
+
+    `iter_live()` and `iter_garbage()` hand out a `Handle` with each object, so it can be held on to and used after the
+    walk is done. `iter_free()` hands out bare indexes instead -> a free cell holds no object, so there is nothing for
+    a handle to refer to (and one made now would resolve to whatever is allocated there next).
+*/
+
+use crate::{handle::Handle, Cell, Heap};
+use crate::prelude::*;
+
+impl Heap {
+    /// Every object on the heap (every cell in use), in order of index
+    pub fn iter_live(&self) -> impl Iterator<Item = (Handle, &Cell)> + '_ {
+        self.cells
+            .iter()
+            .enumerate()
+            .filter(|(_, cell)| !cell.freed)
+            .map(|(index, cell)| (Handle { index, generation: cell.generation }, cell))
+    }
+
+    /// Every object that can't be reached from any root, see `garbage()`.
+    /// Reachability is worked out once, when this is called
+    pub fn iter_garbage(&self) -> impl Iterator<Item = (Handle, &Cell)> + '_ {
+        let reached: Vec<bool> = self.reachable();
+        self.iter_live().filter(move |(handle, _)| !reached[handle.index])
+    }
+
+    /// Every free cell, by index (free cells have no object to hand out a handle to)
+    pub fn iter_free(&self) -> impl Iterator<Item = (usize, &Cell)> + '_ {
+        self.cells.iter().enumerate().filter(|(_, cell)| cell.freed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, free_cell, spec_alloc, tests::graph};
+
+    #[test]
+    fn iterates_live_garbage_and_free_cells() {
+        let mut heap: Heap = graph(5, &[(0, 1), (1, 2)]);
+        heap.log.echo = false;
+        free_cell(&mut heap, 3, false).unwrap();
+
+        let live: Vec<usize> = heap.iter_live().map(|(handle, _)| handle.index).collect();
+        let garbage: Vec<usize> = heap.iter_garbage().map(|(handle, _)| handle.index).collect();
+        let free: Vec<usize> = heap.iter_free().map(|(index, _)| index).collect();
+        assert_eq!((live, garbage, free), (vec![0, 1, 2, 4], vec![4], vec![3, 5, 6, 7, 8]));
+
+        //Handles from the walk can be used after it
+        let (handle, _) = heap.iter_live().nth(1).unwrap();
+        assert_eq!(heap.resolve(handle), Ok(1));
+    }
+
+    #[test]
+    fn hands_out_handles_that_go_stale_with_their_cells() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        let garbage: Vec<Handle> = heap.iter_garbage().map(|(handle, _)| handle).collect();
+        assert_eq!(garbage.iter().map(|handle| handle.index).collect::<Vec<usize>>(), [2]);
+
+        collect(&mut heap);
+        assert!(heap.resolve(garbage[0]).is_err() && heap.iter_garbage().next().is_none());
+        spec_alloc(&mut heap, 9, None, 2).unwrap();
+        assert!(heap.resolve(garbage[0]).is_err());                     //Its cell holds something else now
+    }
+}
@@ -0,0 +1,491 @@
+//A tiny language running on the heap
+/*
+    The REPL pokes at the heap one cell at a time. A real managed runtime doesn't -> a program runs, every value it
+    makes is allocated on the heap, and its variables are what keeps those values alive. This is a minimal language
+    that works that way:
+
+        let xs = cons(1, cons(2, nil))      -> binds xs to a list of two pairs
+        xs.tail.head + 40                   -> 42
+        let xs = xs.tail                    -> the first pair is garbage now (nothing can reach it)
+
+    Values are integers, pairs (made with cons, their fields read with .head and .tail) and nil. Every integer and every
+    pair is a cell on the heap, a pair is a record called "pair" (so fields of records made at the prompt can be read the
+    same way), and nil is no cell at all. `+` and `-` work on integers, and make a new cell for their result.
+
+    The variables are the root set -> each is a root set called "let <name>" holding the cell it is bound to
+    (see --roots), so rebinding a variable lets go of its old value. While a statement runs, every value it has made so
+    far is held on the heap's stack of scoped roots (the same one Rooted guards use, see rooted.rs) the way a runtime's
+    shadow stack holds temporaries. An allocation part way through that collects can then neither sweep them
+    nor leave them behind if the copying collector moves them.
+*/
+
+use core::fmt;
+
+use crate::{error::GcError, log::Event, records::RecordError, Heap, Kind};
+use crate::prelude::*;
+
+/// Runs statements against a heap, keeping track of the variables they bind
+#[derive(Debug, Default)]
+pub struct Interpreter {
+    variables: Vec<String>,         //Every name bound so far, in the order they were first bound (values are in root sets)
+}
+
+/// Reasons a statement couldn't be run
+#[derive(Debug)]
+pub enum LangError {
+    Syntax(String),                 //The statement couldn't be parsed, and why
+    Unbound(String),                //There is no variable with that name
+    NotARecord(String),             //A field was read from something that isn't a pair (or record), (field)
+    NoSuchField(String),            //The record doesn't have that field
+    NotAnInt,                       //Only integers can be added or subtracted
+    Overflow,                       //The result doesn't fit in a cell
+    Alloc(GcError),                 //A value couldn't be allocated
+}
+
+impl fmt::Display for LangError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LangError::Syntax(why) => write!(f, "{}", why),
+            LangError::Unbound(name) => write!(f, "there is no variable called '{}'", name),
+            LangError::NotARecord(field) => write!(f, "only pairs (and records) have fields, so .{} can't be read", field),
+            LangError::NoSuchField(field) => write!(f, "there is no field called '{}'", field),
+            LangError::NotAnInt => write!(f, "only integers can be added or subtracted"),
+            LangError::Overflow => write!(f, "the result is too big for a cell"),
+            LangError::Alloc(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<GcError> for LangError {
+    fn from(error: GcError) -> LangError {
+        LangError::Alloc(error)
+    }
+}
+
+impl From<RecordError> for LangError {
+    fn from(error: RecordError) -> LangError {
+        match error {
+            RecordError::Alloc(error) => LangError::Alloc(error),
+            _ => unreachable!("pairs are only made with fields that exist, pointing at cells in use"),
+        }
+    }
+}
+
+/// A parsed statement
+#[derive(Clone, Debug, PartialEq)]
+pub enum Statement {
+    Let(String, Expr),              //let <name> = <expr>
+    Expr(Expr),                     //An expression on its own, whose value is shown
+}
+
+/// A parsed expression
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expr {
+    Int(i32),
+    Nil,
+    Var(String),
+    Cons(Box<Expr>, Box<Expr>),
+    Field(Box<Expr>, String),       //<expr>.<field>
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+}
+
+/// Root sets holding variables are called this, followed by the variable's name
+const VARIABLE_SET: &str = "let ";
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::default()
+    }
+
+    /// Parses and runs a statement, returning its value (the cell it is, or None for nil)
+    pub fn run(&mut self, heap: &mut Heap, source: &str) -> Result<Option<usize>, LangError> {
+        let statement: Statement = Statement::parse(source)?;
+
+        //Everything held while the statement runs is let go of afterwards, even if it fails part way
+        let base: usize = heap.scoped.len();
+        let value = evaluate(heap, statement.expr(), &self.variables).map(|held| heap.scoped[held]);
+        if let (Ok(value), Statement::Let(name, _)) = (&value, &statement) {
+            self.bind(heap, name, *value);
+        }
+        release(heap, base);
+
+        value
+    }
+
+    /// Every variable and the cell it is bound to (None for nil), in the order they were first bound
+    pub fn variables(&self, heap: &Heap) -> Vec<(String, Option<usize>)> {
+        self.variables.iter().map(|name| (name.clone(), lookup(heap, name))).collect()
+    }
+
+    //Binds a variable, letting go of whatever it was bound to before
+    fn bind(&mut self, heap: &mut Heap, name: &str, value: Option<usize>) {
+        let set: String = format!("{}{}", VARIABLE_SET, name);
+        heap.drop_root_set(&set);
+        if let Some(index) = value {
+            heap.add_root(&set, index).expect("values are always cells on the heap");
+        }
+        if !self.variables.iter().any(|bound| bound == name) {
+            self.variables.push(name.to_string());
+        }
+    }
+}
+
+/// Shows a value the way the language writes it -> integers as themselves, pairs as (head, tail), nil as nil,
+/// and anything else (made at the prompt) as its cell, i.e. #4
+pub fn show(heap: &Heap, value: Option<usize>) -> String {
+    let mut seen: Vec<usize> = Vec::new();
+    show_value(heap, value, &mut seen)
+}
+
+//Pairs linked into a cycle at the prompt are shown as ... the second time round
+fn show_value(heap: &Heap, value: Option<usize>, seen: &mut Vec<usize>) -> String {
+    let Some(index) = value else {
+        return String::from("nil");
+    };
+    if seen.contains(&index) {
+        return String::from("...");
+    }
+
+    let cell = &heap.cells[index];
+    match (&cell.record, cell.kind, cell.data) {
+        (Some(record), _, _) if record.name == "pair" => {
+            seen.push(index);
+            let field = |name: &str| record.fields.iter().find(|field| field.name == name).and_then(|field| field.to);
+            let shown = format!("({}, {})", show_value(heap, field("head"), seen), show_value(heap, field("tail"), seen));
+            seen.pop();
+            shown
+        }
+        (None, Kind::Int, Some(data)) => data.to_string(),
+        _ => format!("#{}", index),
+    }
+}
+
+//The cell a variable is bound to, None for nil (or if its root was taken away at the prompt)
+fn lookup(heap: &Heap, name: &str) -> Option<usize> {
+    let set: String = format!("{}{}", VARIABLE_SET, name);
+    heap.root_sets().iter().find(|root_set| root_set.name == set).and_then(|root_set| root_set.cells.first().copied())
+}
+
+//Evaluates an expression, returning where its value is held on the stack of scoped roots.
+//Whatever it held along the way is let go of once its value is made, so each expression leaves only its value held
+fn evaluate(heap: &mut Heap, expr: &Expr, variables: &[String]) -> Result<usize, LangError> {
+    let mark: usize = heap.scoped.len();
+
+    let value: Option<usize> = match expr {
+        Expr::Int(n) => Some(heap.alloc(*n)?.index),
+        Expr::Nil => None,
+        //Variables bound to nil have no root set, so whether a name is bound at all is down to the interpreter
+        Expr::Var(name) if !variables.contains(name) => return Err(LangError::Unbound(name.clone())),
+        Expr::Var(name) => lookup(heap, name),
+        Expr::Cons(head, tail) => {
+            let (head, tail) = (evaluate(heap, head, variables)?, evaluate(heap, tail, variables)?);
+
+            //The pair is made empty and filled in after, as making it may collect (and move the head and tail)
+            let pair: usize = heap.alloc(0)?.index;
+            heap.make_record(pair, "pair", &["head", "tail"]);
+            heap.field_set(pair, "head", heap.scoped[head])?;
+            heap.field_set(pair, "tail", heap.scoped[tail])?;
+            Some(pair)
+        }
+        Expr::Field(record, field) => {
+            let held: usize = evaluate(heap, record, variables)?;
+            let record = heap.scoped[held].and_then(|index| heap.record(index)).ok_or(LangError::NotARecord(field.clone()))?;
+            record.fields.iter().find(|f| f.name == *field).ok_or(LangError::NoSuchField(field.clone()))?.to
+        }
+        Expr::Add(left, right) | Expr::Sub(left, right) => {
+            let (left, right) = (evaluate(heap, left, variables)?, evaluate(heap, right, variables)?);
+            let (left, right) = (int(heap, heap.scoped[left])?, int(heap, heap.scoped[right])?);
+            let result: i32 = match expr {
+                Expr::Add(..) => left.checked_add(right),
+                _ => left.checked_sub(right),
+            }
+            .ok_or(LangError::Overflow)?;
+            Some(heap.alloc(result)?.index)
+        }
+    };
+
+    Ok(keep(heap, mark, value))
+}
+
+//The integer in a cell
+fn int(heap: &Heap, value: Option<usize>) -> Result<i32, LangError> {
+    value
+        .map(|index| &heap.cells[index])
+        .filter(|cell| cell.record.is_none() && cell.kind == Kind::Int)
+        .and_then(|cell| cell.data)
+        .ok_or(LangError::NotAnInt)
+}
+
+//Keeps a value alive until the statement is finished, returning where it is held
+fn hold(heap: &mut Heap, value: Option<usize>) -> usize {
+    heap.scoped.push(value);
+    if let Some(index) = value {
+        if !heap.cells[index].is_root() {
+            heap.cells[index].make_root();
+            heap.log.emit(Event::Rooted { index });
+        }
+    }
+    heap.scoped.len() - 1
+}
+
+//Holds `value` in place of everything held since `mark`, returning where it is held.
+//It is held before the rest are let go of, so it stays a root throughout even if it was one of them
+fn keep(heap: &mut Heap, mark: usize, value: Option<usize>) -> usize {
+    let held: usize = hold(heap, value);
+    let released: Vec<Option<usize>> = heap.scoped.drain(mark..held).collect();
+    for index in released.into_iter().flatten() {
+        heap.refresh_root(index);
+    }
+    mark
+}
+
+//Lets go of everything held since `base`, newest first
+fn release(heap: &mut Heap, base: usize) {
+    while heap.scoped.len() > base {
+        if let Some(Some(index)) = heap.scoped.pop() {
+            heap.refresh_root(index);
+        }
+    }
+}
+
+impl Statement {
+    /// Parses a statement, i.e. `let xs = cons(1, nil)` or `xs.head + 1`
+    pub fn parse(source: &str) -> Result<Statement, LangError> {
+        let mut parser = Parser { tokens: tokenize(source)?, at: 0 };
+
+        let statement: Statement = if parser.eat(&Token::Let) {
+            let name: String = match parser.next() {
+                Some(Token::Name(name)) => name,
+                _ => return Err(LangError::Syntax(String::from("expected a name after let"))),
+            };
+            parser.expect(Token::Equals, "'=' after the name")?;
+            Statement::Let(name, parser.expr()?)
+        } else {
+            Statement::Expr(parser.expr()?)
+        };
+
+        match parser.next() {
+            None => Ok(statement),
+            Some(token) => Err(LangError::Syntax(format!("unexpected {} after the end of the statement", token))),
+        }
+    }
+
+    //The expression the statement evaluates
+    fn expr(&self) -> &Expr {
+        match self {
+            Statement::Let(_, expr) | Statement::Expr(expr) => expr,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Int(i32),
+    Name(String),
+    Let,
+    Nil,
+    Cons,
+    Equals,
+    Open,
+    Close,
+    Comma,
+    Dot,
+    Plus,
+    Minus,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Int(n) => write!(f, "{}", n),
+            Token::Name(name) => write!(f, "'{}'", name),
+            Token::Let => write!(f, "let"),
+            Token::Nil => write!(f, "nil"),
+            Token::Cons => write!(f, "cons"),
+            Token::Equals => write!(f, "'='"),
+            Token::Open => write!(f, "'('"),
+            Token::Close => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+            Token::Dot => write!(f, "'.'"),
+            Token::Plus => write!(f, "'+'"),
+            Token::Minus => write!(f, "'-'"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, LangError> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        //A word (name or keyword), or a number
+        if c.is_alphanumeric() || c == '_' {
+            let mut word: String = String::new();
+            while let Some(&c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(match word.as_str() {
+                "let" => Token::Let,
+                "nil" => Token::Nil,
+                "cons" => Token::Cons,
+                _ if c.is_ascii_digit() => Token::Int(word.parse().map_err(|_| LangError::Syntax(format!("'{}' isn't a number a cell can hold", word)))?),
+                _ => Token::Name(word),
+            });
+            continue;
+        }
+
+        tokens.push(match c {
+            '=' => Token::Equals,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            _ => return Err(LangError::Syntax(format!("'{}' isn't part of the language", c))),
+        });
+        chars.next();
+    }
+
+    Ok(tokens)
+}
+
+//Recursive descent over the tokens:
+//  expr  := term (('+' | '-') term)*
+//  term  := atom ('.' name)*
+//  atom  := int | '-' int | nil | name | cons '(' expr ',' expr ')' | '(' expr ')'
+struct Parser {
+    tokens: Vec<Token>,
+    at: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token: Option<Token> = self.tokens.get(self.at).cloned();
+        self.at += 1;
+        token
+    }
+
+    //Moves past the next token if it is `token`
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.at) == Some(token) {
+            self.at += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expect(&mut self, token: Token, what: &str) -> Result<(), LangError> {
+        if self.eat(&token) {
+            return Ok(());
+        }
+        Err(LangError::Syntax(match self.tokens.get(self.at) {
+            Some(found) => format!("expected {}, found {}", what, found),
+            None => format!("expected {}, but the statement ended", what),
+        }))
+    }
+
+    fn expr(&mut self) -> Result<Expr, LangError> {
+        let mut expr: Expr = self.term()?;
+        loop {
+            if self.eat(&Token::Plus) {
+                expr = Expr::Add(Box::new(expr), Box::new(self.term()?));
+            } else if self.eat(&Token::Minus) {
+                expr = Expr::Sub(Box::new(expr), Box::new(self.term()?));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, LangError> {
+        let mut expr: Expr = self.atom()?;
+        while self.eat(&Token::Dot) {
+            match self.next() {
+                Some(Token::Name(field)) => expr = Expr::Field(Box::new(expr), field),
+                _ => return Err(LangError::Syntax(String::from("expected a field name after '.'"))),
+            }
+        }
+        Ok(expr)
+    }
+
+    fn atom(&mut self) -> Result<Expr, LangError> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Int(n)),
+            Some(Token::Minus) => match self.next() {
+                Some(Token::Int(n)) => Ok(Expr::Int(-n)),
+                _ => Err(LangError::Syntax(String::from("expected a number after '-'"))),
+            },
+            Some(Token::Nil) => Ok(Expr::Nil),
+            Some(Token::Name(name)) => Ok(Expr::Var(name)),
+            Some(Token::Cons) => {
+                self.expect(Token::Open, "'(' after cons")?;
+                let head: Expr = self.expr()?;
+                self.expect(Token::Comma, "',' between the head and tail")?;
+                let tail: Expr = self.expr()?;
+                self.expect(Token::Close, "')' after the tail")?;
+                Ok(Expr::Cons(Box::new(head), Box::new(tail)))
+            }
+            Some(Token::Open) => {
+                let expr: Expr = self.expr()?;
+                self.expect(Token::Close, "')'")?;
+                Ok(expr)
+            }
+            Some(token) => Err(LangError::Syntax(format!("expected a value, found {}", token))),
+            None => Err(LangError::Syntax(String::from("expected a value, but the statement ended"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Algorithm;
+
+    #[test]
+    fn runs_the_tiny_language_with_its_variables_as_roots() {
+        let mut heap: Heap = Heap::with_seed(8, 1);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Copying;
+        let mut interpreter: Interpreter = Interpreter::new();
+        let mut run = |heap: &mut Heap, source: &str| interpreter.run(heap, source).map(|value| show(heap, value));
+
+        assert_eq!(run(&mut heap, "let xs = cons(1, cons(2, nil))").unwrap(), "(1, (2, nil))");
+        assert_eq!(run(&mut heap, "let n = xs.tail.head + 40").unwrap(), "42");
+        assert!(heap.scoped.is_empty());
+
+        //Filling the heap collects part way through the statement, moving the values it is still using
+        assert_eq!(run(&mut heap, "let xs = cons(n - 1, cons(n, xs.tail))").unwrap(), "(41, (42, (2, nil)))");
+        assert!(heap.history.len() == 1 && heap.live() == 8);
+        assert_eq!(heap.iter_garbage().count(), 2);                 //The old head of the list, and the pair holding it
+        assert!(matches!(run(&mut heap, "ys"), Err(LangError::Unbound(_))));
+        assert!(matches!(run(&mut heap, "let n = (1"), Err(LangError::Syntax(_))));
+    }
+
+    #[test]
+    fn says_why_a_statement_could_not_run() {
+        let mut heap: Heap = Heap::with_seed(4, 1);
+        heap.log.echo = false;
+        let mut interpreter: Interpreter = Interpreter::new();
+        let mut run = |heap: &mut Heap, source: &str| interpreter.run(heap, source).map_err(|error| error.to_string());
+
+        run(&mut heap, "let n = 1").unwrap();
+        assert_eq!(run(&mut heap, "n.head").unwrap_err(), "only pairs (and records) have fields, so .head can't be read");
+        assert_eq!(run(&mut heap, "cons(n, nil).left").unwrap_err(), "there is no field called 'left'");
+        assert_eq!(run(&mut heap, "cons(n, nil) + 1").unwrap_err(), "only integers can be added or subtracted");
+        assert_eq!(run(&mut heap, "2147483647 + n").unwrap_err(), "the result is too big for a cell");
+        assert!(run(&mut heap, "let n = n $ 2").unwrap_err().contains("'$' isn't part of the language"));
+
+        //Running out of room part way through lets go of whatever the statement had made so far
+        assert!(matches!(interpreter.run(&mut heap, "cons(1, cons(2, cons(3, nil)))"), Err(LangError::Alloc(_))));
+        assert!(heap.scoped.is_empty());
+        assert_eq!(interpreter.run(&mut heap, "n").map(|value| show(&heap, value)).unwrap(), "1");
+    }
+}
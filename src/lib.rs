@@ -0,0 +1,1990 @@
+/*
+    This is a barebones "virtual" garbage collector (mark and sweep) for Rust, demonstrating the
+    advantages of Rust's ownership and borrowing features compared against a garbage collector implementation.
+
+    What does a garbage collector do?
+        - Manages memory for us semi-automatically (using explicit rules)
+        - It tracks which objects are still being used and referenced
+        - It reclaims memory from objects that no longer accessible
+        - All done automatically without explicitly freeing memory manually
+
+    This is a functional, albeit very small garbage collector that manages memory 
+    through the use of a memory pool (implemented as a Vec<Cell>). It tracks which memory 
+    is in use and reclaims memory that is no longer referenced. However, think of this more 
+    as an educational demonstration than a standalone working implementation of a 
+    mark-and-sweep garbage collector, as this GC operates on top of Rust's memory management 
+    rather than directly managing allocations to the physical heap. Instead, we abstract 
+    this away and use a Vector as a "virtual heap."
+
+    It is mostly a demonstration that operates within Rust, with controlled memory and
+    only with a primitive data type (i32).
+
+    This is a essentially a working memory management system that is existing alongside
+    Rust's already established memory system. This garabage collector manages its own
+    'universe' of memory (Vec<Cell>)
+
+    The heap and its collectors only need `alloc`, so with the `std` feature off (--no-default-features) the library
+    builds `no_std`, for embedding in a bare-metal or WASM-without-WASI teaching target. What needs an operating system
+    goes with std -> the REPL, anything to do with files, sockets or threads (metrics, traces, snapshots on disk,
+    the live view, stress tests, safepoints, SharedHeap), printing events, and seeding a heap at random (`Heap::new()`).
+    Without a clock collections aren't timed either, so every pause reads as 0 (see clock.rs).
+
+    Authored by Jarred Jenkins
+    https://github.com/jerdbeenbo
+*/
+
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+pub mod barrier;
+pub mod borrow;
+pub mod breakpoints;
+#[cfg(not(feature = "std"))]
+mod clock;
+mod cloning;
+pub mod containers;
+pub mod copying;
+pub mod diff;
+pub mod dominators;
+pub mod error;
+pub mod export;
+pub mod finalize;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod frames;
+pub mod g1;
+pub mod generational;
+pub mod handle;
+pub mod header;
+pub mod histogram;
+pub mod hybrid;
+#[cfg(feature = "std")]
+pub mod import;
+pub mod intern;
+pub mod iter;
+pub mod lang;
+pub mod lists;
+#[cfg(feature = "std")]
+pub mod live;
+pub mod log;
+#[cfg(feature = "std")]
+pub mod metrics;
+pub mod mmu;
+pub mod mutator;
+pub mod named_regions;
+pub mod observer;
+pub mod oom;
+pub mod ownership;
+#[cfg(feature = "std")]
+pub mod periodic;
+pub mod poison;
+pub mod pressure;
+pub mod profile;
+mod prelude;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod query;
+pub mod records;
+pub mod refcount;
+pub mod relocation;
+pub mod rooted;
+pub mod roots;
+#[cfg(feature = "std")]
+pub mod safepoint;
+pub mod scenario;
+#[cfg(feature = "std")]
+pub mod shared;
+mod shrink;
+pub mod simulation;
+pub mod sites;
+pub mod sizes;
+pub mod snapshot;
+pub mod stats;
+pub mod strings;
+#[cfg(feature = "std")]
+pub mod stress;
+pub mod tags;
+#[cfg(feature = "std")]
+pub mod trace;
+pub mod treadmill;
+pub mod verify;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+pub mod weakmap;
+
+use alloc::collections::VecDeque;
+use core::{
+    fmt,
+    ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use rand::prelude::*;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::{io, net::{IpAddr, Ipv4Addr, SocketAddr}};
+
+use prelude::*;
+
+//std's Instant panics in the browser, where web-time's reads the clock through JS instead (see wasm.rs)
+#[cfg(all(feature = "std", not(target_arch = "wasm32")))]
+pub(crate) use std::time::Instant;
+#[cfg(all(feature = "std", target_arch = "wasm32"))]
+pub(crate) use web_time::Instant;
+#[cfg(not(feature = "std"))]
+pub(crate) use clock::Instant;
+
+use barrier::Mutation;
+use breakpoints::{BreakHandler, Breakpoint};
+use error::{ErrorKind, GcError, Operation};
+use frames::Frame;
+use finalize::Finalizer;
+use g1::RegionMarking;
+use hybrid::Hybrid;
+use intern::Interner;
+use refcount::UpdateBuffer;
+use generational::CollectionKind;
+use roots::{RootProvider, RootSet};
+use sites::Site;
+#[cfg(feature = "std")]
+use live::LiveServer;
+use log::{Event, EventLog};
+use named_regions::NamedRegion;
+#[cfg(feature = "std")]
+use metrics::{MetricsCsv, MetricsEndpoint};
+use oom::OomHandler;
+use relocation::Relocation;
+use records::Record;
+use stats::{GcCycle, HeapStats};
+use treadmill::Treadmill;
+use weakmap::WeakMap;
+
+//Structures
+/// #### The 'Virtual Heap' is a collection of these Cell structures.
+/// A cell of memory that will be stored in a vector -> making up a greater "memory pool"
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cell {
+    pub data: Option<i32>, //Actual data within the memory pool...
+    //  ...stored as an option as the default data value should be None
+    pub reference_count: i32,           //Is this object still being referenced? (amount of references)
+    pub freed: bool,                    //False || in use (referenced), True || not in use (de-referenced)
+    pub is_root: bool,                  //Declares whether or not this is a root (static) entrance variable
+    pub by_ref: Vec<usize>,             //Determins what cell(s) reference this cell
+    pub will_ref: Vec<usize>,           //The index of a cell this cell calls reference to
+    pub marked: bool,                   //Flag to signal if the cell has been marked for keeping. Any cell that is not marked will be sweeped
+    #[serde(default)]
+    pub name: Option<String>,           //Optional label given with --name, so the cell can be referred to by name instead of index
+    #[serde(default)]
+    pub tags: Vec<String>,              //Labels given with --tag, grouping the cell with others (see tags.rs)
+    #[serde(default)]
+    pub age: usize,                     //How many collections the data in this cell has survived
+    #[serde(default)]
+    pub garbage_for: usize,             //...and how many of those it survived while unreachable (a leak, if it keeps growing)
+    #[serde(default)]
+    pub generation: u32,                //Bumped each time the cell is freed, so a Handle to what used to be here can tell (see handle.rs)
+    #[serde(default)]
+    pub kind: Kind,                     //What the data in this cell is, i.e. part of a string (see strings.rs)
+    #[serde(default)]
+    pub record: Option<Record>,         //The type and fields of a record, None for every other kind (see records.rs)
+    #[serde(default)]
+    pub site: Option<String>,           //Where the data was allocated, i.e. the command that made it (see sites.rs)
+    #[serde(default)]
+    pub forward: Option<usize>,         //Where the object has been copied to by a relocation in progress (None = it is here, see relocation.rs)
+}
+
+///Implementation for a Cell
+impl Cell {
+    //Creates a new cell with default values
+    pub fn new() -> Cell {  //called with Cell::new()
+        Cell {
+            data: None,                 //Cell starts with no data
+            reference_count: 0,         //Cell starts with no references
+            freed: true,                //Cell starts as free, avaliable for use
+            is_root: false,             //By default, cell is not a root
+            by_ref: Vec::new(),         //This cell is referenced by
+            will_ref: Vec::new(),       //References None cell
+            marked: false,              //If the cell has been marked for keeping. Any cell that is not marked will be sweeped
+            name: None,                 //Cell starts unnamed
+            tags: Vec::new(),           //...and untagged
+            age: 0,                     //...and hasn't survived any collections
+            garbage_for: 0,
+            generation: 0,
+            kind: Kind::Int,
+            record: None,
+            site: None,
+            forward: None,
+        }
+    }
+
+    //Takes parameter &mut self to allow it to be called on cells[n].make_root
+    pub fn make_root(&mut self) {
+        if self.freed {
+            self.data = None;           //A free cell holds nothing, not even poison (see poison.rs)
+        }
+        self.is_root = true;
+        self.marked = true;
+        self.freed = false;
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.is_root
+    }
+}
+
+impl Default for Cell {
+    fn default() -> Cell {
+        Cell::new()
+    }
+}
+
+/// What the data in a cell is. Plain cells hold a single `i32`, the others are parts of bigger objects
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Kind {
+    #[default]
+    Int,                //A single i32
+    Str,                //The head of a string -> its data is the length in bytes, and it references the cells holding them (see strings.rs)
+    Bytes,              //Up to 4 bytes of a string, packed into the data
+    List,               //A list -> its elements are the cells it references, in order (see lists.rs)
+    Record,             //A record -> named fields, each referencing a cell (see records.rs)
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Kind::Int => "int",
+            Kind::Str => "string",
+            Kind::Bytes => "string bytes",
+            Kind::List => "list",
+            Kind::Record => "record",
+        })
+    }
+}
+
+/// Which garbage collection algorithm `collect()` runs.
+/// Chosen at startup with `--algorithm`, or at runtime with `--set-policy algorithm <name>`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    #[default]
+    MarkSweep,          //Trace from the roots, then free every cell that wasn't reached, where it lies
+    Copying,            //Trace from the roots, copying every cell reached to the start of a fresh pool (compacting it)
+    RefCount,           //No tracing, free cells that nothing references any more (can't reclaim cycles)
+    Generational,       //Collect the young cells often and the whole heap only when that isn't enough (see generational.rs)
+    Treadmill,          //Trace a few cells at every allocation, then reclaim what wasn't reached, where it lies (see treadmill.rs)
+    GarbageFirst,       //Mark the whole heap, then evacuate only the regions with the most garbage (see g1.rs)
+    BufferedRc,         //Reference counting, with the updates logged and applied in batches at each collection (see refcount.rs)
+    Hybrid,             //Reference counting, backed up by a tracer that looks for garbage cycles (see hybrid.rs)
+}
+
+impl Algorithm {
+    /// Names accepted by `from_name()`, for help and error messages
+    pub const NAMES: [&'static str; 8] = ["mark-sweep", "copying", "rc", "generational", "treadmill", "g1", "buffered-rc", "hybrid"];
+
+    /// Parses an algorithm from its name, i.e. `"rc"` -> `Algorithm::RefCount`
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name {
+            "mark-sweep" => Some(Algorithm::MarkSweep),
+            "copying" => Some(Algorithm::Copying),
+            "rc" => Some(Algorithm::RefCount),
+            "generational" => Some(Algorithm::Generational),
+            "treadmill" => Some(Algorithm::Treadmill),
+            "g1" => Some(Algorithm::GarbageFirst),
+            "buffered-rc" => Some(Algorithm::BufferedRc),
+            "hybrid" => Some(Algorithm::Hybrid),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Algorithm::MarkSweep => "mark-sweep",
+            Algorithm::Copying => "copying",
+            Algorithm::RefCount => "rc",
+            Algorithm::Generational => "generational",
+            Algorithm::Treadmill => "treadmill",
+            Algorithm::GarbageFirst => "g1",
+            Algorithm::BufferedRc => "buffered-rc",
+            Algorithm::Hybrid => "hybrid",
+        })
+    }
+}
+
+/// The order `mark()` visits cells in. Either way the same cells are marked (reachability doesn't depend on the order),
+/// but the worklist grows differently -> deep and narrow for DFS, wide (a whole level of the graph at once) for BFS.
+/// Chosen at runtime with `--set-policy mark-order <dfs|bfs>`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MarkOrder {
+    #[default]
+    Dfs,                //Depth first -> the worklist is a stack, the most recently found cell is visited next
+    Bfs,                //Breadth first -> the worklist is a queue, cells are visited in the order they were found
+}
+
+impl MarkOrder {
+    /// Names accepted by `from_name()`, for help and error messages
+    pub const NAMES: [&'static str; 2] = ["dfs", "bfs"];
+
+    /// Parses a mark order from its name, i.e. `"bfs"` -> `MarkOrder::Bfs`
+    pub fn from_name(name: &str) -> Option<MarkOrder> {
+        match name {
+            "dfs" => Some(MarkOrder::Dfs),
+            "bfs" => Some(MarkOrder::Bfs),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for MarkOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            MarkOrder::Dfs => "dfs",
+            MarkOrder::Bfs => "bfs",
+        })
+    }
+}
+
+/// Tunable heuristics that decide when (and how) the collector runs.
+/// Configured at runtime with `--set-policy <name> <value>`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Policy {
+    pub gc_threshold: Option<usize>, //Occupancy percentage (of cells) that triggers a collection after an allocation (None = manual --gc only)
+    #[serde(default)]
+    pub algorithm: Algorithm,        //Which algorithm collects the heap
+    #[serde(default)]
+    pub mark_order: MarkOrder,       //The order mark and sweep visits cells in
+    #[serde(default)]
+    pub mark_stack: Option<usize>,   //The most cells the mark worklist can hold before it overflows (None = as many as it needs)
+    #[serde(default)]
+    pub periodic_gc: Option<Duration>, //How often a timer asks for a collection, whatever the occupancy (None = never, see periodic.rs)
+    #[serde(default = "tenuring_threshold")]
+    pub tenuring_threshold: usize,   //Collections a cell survives before it is old, set with Heap::set_tenuring_threshold() (see generational.rs)
+    #[serde(default)]
+    pub survivor_size: Option<usize>, //Cells in each of the two survivor spaces (None = survivors stay where they were allocated)
+    #[serde(default)]
+    pub verify_remembered: bool,     //Check the remembered set against the whole heap in every minor collection (see verify.rs)
+    #[serde(default = "region_size")]
+    pub region_size: usize,          //Cells in each region the garbage first collector splits the heap into (see g1.rs)
+    #[serde(default)]
+    pub pause_budget: Option<Duration>, //How long a garbage first collection aims to pause for (None = collect every region with garbage)
+    #[serde(default = "intern_threshold")]
+    pub intern_threshold: i32,       //How far from zero an int has to be for interning to share it (see intern.rs)
+    #[serde(default)]
+    pub max_free: Option<usize>,     //Percentage of the heap that can be free after a collection before its free tail is released (None = never, see shrink.rs)
+    #[serde(default)]
+    pub skip_barriers: bool,         //Let writes past the write barrier unseen, set with Heap::set_barriers() (debug builds only, see barrier.rs)
+}
+
+//Policies saved before generations existed promote straight out of the nursery
+fn tenuring_threshold() -> usize {
+    generational::TENURING_THRESHOLD
+}
+
+//...and before regions existed, split the heap the default way
+fn region_size() -> usize {
+    g1::REGION_SIZE
+}
+
+//...and before interning existed, intern the default ints
+fn intern_threshold() -> i32 {
+    intern::INTERN_THRESHOLD
+}
+
+impl Policy {
+    //Default policy is fully manual mark and sweep, the user runs --gc themselves
+    pub fn new() -> Policy {
+        Policy {
+            gc_threshold: None,
+            algorithm: Algorithm::MarkSweep,
+            mark_order: MarkOrder::Dfs,
+            mark_stack: None,
+            periodic_gc: None,
+            tenuring_threshold: generational::TENURING_THRESHOLD,
+            survivor_size: None,
+            verify_remembered: false,
+            region_size: g1::REGION_SIZE,
+            pause_budget: None,
+            intern_threshold: intern::INTERN_THRESHOLD,
+            max_free: None,
+            skip_barriers: false,
+        }
+    }
+}
+
+impl Default for Policy {
+    fn default() -> Policy {
+        Policy::new()
+    }
+}
+
+/// #### The 'Virtual Heap' as a whole.
+/// Owns the memory pool (`Vec<Cell>`) alongside the policies that govern how it is collected.
+pub struct Heap {
+    pub cells: Vec<Cell>,           //The memory pool itself
+    pub policy: Policy,             //When (and how) the garbage collector is triggered
+    pub history: Vec<GcCycle>,      //Every collection that has run on this heap, oldest first
+    started: Instant,               //When the heap was created (or reset), collections are timed from here (see mmu.rs)
+    pub log: EventLog,              //Where everything that happens on the heap is reported
+    pub rng: ChaCha8Rng,            //Source of the arbitrary data and references (seeded, so runs can be reproduced)
+    seed: u64,                      //What the rng was last seeded with
+    allocations: usize,             //Total successful allocations over the lifetime of the heap
+    high_water: usize,              //The most cells that have ever been in use at once
+    barrier_writes: usize,          //Total mutations that have passed through the write barrier
+    read_barrier_hits: AtomicUsize, //Total reads the read barrier forwarded to a relocated object (counted through &self, see relocation.rs)
+    mark_epoch: usize,              //How many times the marks have been cleared, one epoch per collection (see clear_marks())
+    frames: Vec<Frame>,             //Simulated call stack, innermost frame last (see frames.rs)
+    root_sets: Vec<RootSet>,        //Named groups of roots, i.e. "globals" (see roots.rs)
+    scoped: Vec<Option<usize>>,     //Roots held by Rooted guards, innermost last (None once freed by hand, see rooted.rs)
+    #[cfg(feature = "std")]
+    metrics_csv: Option<MetricsCsv>, //Optional CSV file that each collection is appended to
+    #[cfg(feature = "std")]
+    metrics_endpoint: Option<MetricsEndpoint>, //Optional Prometheus endpoint the heap's metrics are served from
+    #[cfg(feature = "std")]
+    live_server: Option<LiveServer>, //Optional live view of the heap in the browser (see live.rs)
+    oom_handler: Option<OomHandler>, //Decides what happens when a collection doesn't free enough memory (see oom.rs)
+    root_providers: Vec<RootProvider>, //Report roots kept outside the heap, at the start of every collection (see roots.rs)
+    breakpoints: Vec<Breakpoint>,   //Where collections pause, handing the heap to the break handler (see breakpoints.rs)
+    break_handler: Option<BreakHandler>,
+    site: Option<String>,           //What allocations are tagged with (see sites.rs)
+    sites: Vec<Site>,               //Counts for every site that has allocated, in the order they first did
+    remembered: Vec<usize>,         //Old cells that may reference young ones (see generational.rs)
+    to_space: usize,                //Which survivor space the next minor collection copies into
+    relocation: Option<Relocation>, //The concurrent relocation in progress, if there is one (see relocation.rs)
+    treadmill: Option<Treadmill>,   //Baker's treadmill, once it has collected the heap (see treadmill.rs)
+    region_marking: Option<RegionMarking>, //What the last garbage first collection found in each region (see g1.rs)
+    rc_updates: UpdateBuffer,       //Reference count updates logged by the buffered variant (see refcount.rs)
+    hybrid: Hybrid,                 //The hybrid's candidate cycle roots, and what it has reclaimed (see hybrid.rs)
+    finalizers: Vec<Finalizer>,     //Finalizers that haven't run yet (see finalize.rs)
+    weak_map: WeakMap,              //Entries that don't keep their keys alive (see weakmap.rs)
+    interner: Interner,             //The intern table, which shares equal values (see intern.rs)
+    named_regions: Vec<NamedRegion>, //Ranges of the pool set aside with --region (see named_regions.rs)
+}
+
+impl Heap {
+    //Creates a heap of n cells with the default (manual) policy, and a randomly chosen seed
+    #[cfg(feature = "std")]
+    pub fn new(size: usize) -> Heap {
+        Heap::with_seed(size, rand::rng().random())
+    }
+
+    /// Creates a heap of n cells whose arbitrary data and references come from `seed`,
+    /// so the same commands always produce the same heap
+    pub fn with_seed(size: usize, seed: u64) -> Heap {
+        Heap {
+            cells: init_pool(size),
+            policy: Policy::new(),
+            history: Vec::new(),
+            started: Instant::now(),
+            log: EventLog::new(),
+            rng: ChaCha8Rng::seed_from_u64(seed),
+            seed,
+            allocations: 0,
+            high_water: 0,
+            barrier_writes: 0,
+            read_barrier_hits: AtomicUsize::new(0),
+            mark_epoch: 0,
+            frames: Vec::new(),
+            root_sets: Vec::new(),
+            scoped: Vec::new(),
+            #[cfg(feature = "std")]
+            metrics_csv: None,
+            #[cfg(feature = "std")]
+            metrics_endpoint: None,
+            #[cfg(feature = "std")]
+            live_server: None,
+            oom_handler: None,
+            root_providers: Vec::new(),
+            breakpoints: Vec::new(),
+            break_handler: None,
+            site: None,
+            sites: Vec::new(),
+            remembered: Vec::new(),
+            to_space: 0,
+            relocation: None,
+            treadmill: None,
+            region_marking: None,
+            rc_updates: UpdateBuffer::default(),
+            hybrid: Hybrid::default(),
+            finalizers: Vec::new(),
+            weak_map: WeakMap::default(),
+            interner: Interner::default(),
+            named_regions: Vec::new(),
+        }
+    }
+
+    /// The seed the heap's rng was last seeded with (report it, and the run can be reproduced with `reseed()`)
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Restarts the heap's rng from `seed`
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self.seed = seed;
+    }
+
+    /// Puts the heap back the way it was created -> a fresh pool (of `size` cells, or the same size as before),
+    /// no collection history and no counts, with the rng restarted from the same seed.
+    /// Policies, the event log and any metrics outputs are kept, as they belong to the session rather than the pool
+    pub fn reset(&mut self, size: Option<usize>) {
+        let size: usize = size.unwrap_or(self.cells.len());
+
+        //Every handle from before the reset is stale, even where the new pool has a cell in use at the same index
+        let generations: Vec<u32> = self.cells.iter().map(|cell| cell.generation.wrapping_add(1)).collect();
+        self.cells = init_pool(size);
+        for (cell, generation) in self.cells.iter_mut().zip(generations) {
+            cell.generation = generation;
+        }
+        self.history.clear();
+        self.started = Instant::now();
+        self.allocations = 0;
+        self.high_water = 0;
+        self.barrier_writes = 0;
+        self.read_barrier_hits = AtomicUsize::new(0);
+        self.mark_epoch = 0;
+        self.frames.clear();
+        self.root_sets.clear();
+        self.sites.clear();
+        self.remembered.clear();
+        self.to_space = 0;
+        self.relocation = None;
+        self.treadmill = None;
+        self.region_marking = None;
+        self.rc_updates = UpdateBuffer::default();
+        self.hybrid = Hybrid::default();
+        self.finalizers.clear();
+        self.weak_map = WeakMap::default();
+        self.interner = Interner::default();
+        self.named_regions.clear();
+        self.forget_scoped();
+        self.reseed(self.seed);
+
+        self.log.emit(Event::Reset { capacity: size });
+        self.publish();
+    }
+
+    /// Which cells can be reached from the roots by following references (`true` = reachable).
+    /// A plain traversal that leaves the mark bits alone, so it can be asked at any time
+    pub fn reachable(&self) -> Vec<bool> {
+        self.reachable_from(self.roots())
+    }
+
+    /// Which roots `index` can be reached from, answering "would this cell survive a collection, and why?".
+    /// Empty if the cell is garbage (or free)
+    pub fn roots_reaching(&self, index: usize) -> Vec<usize> {
+        if index >= self.cells.len() || self.cells[index].freed {
+            return Vec::new();
+        }
+
+        self.roots()
+            .into_iter()
+            .filter(|&root| self.reachable_from(vec![root])[index])
+            .collect()
+    }
+
+    /// Shortest chain of references from each root that reaches `index` to the cell itself (root first, `index` last).
+    /// Sorted shortest first, so the first chain is the most direct reason the cell is still alive.
+    /// Empty if the cell is garbage (or free)
+    pub fn root_paths(&self, index: usize) -> Vec<Vec<usize>> {
+        let mut paths: Vec<Vec<usize>> = self
+            .roots_reaching(index)
+            .into_iter()
+            .filter_map(|root| self.shortest_path(root, index))
+            .collect();
+
+        paths.sort_by_key(|path| path.len());          //Stable, so equal lengths stay in root order
+        paths
+    }
+
+    //Breadth first from `from`, remembering how each cell was first reached, then walking back from `to`
+    fn shortest_path(&self, from: usize, to: usize) -> Option<Vec<usize>> {
+        let mut reached_by: Vec<Option<usize>> = vec![None; self.cells.len()];
+        let mut visited: Vec<bool> = vec![false; self.cells.len()];
+        let mut queue: VecDeque<usize> = VecDeque::from([from]);
+        visited[from] = true;
+
+        while let Some(i) = queue.pop_front() {
+            if i == to {
+                let mut path: Vec<usize> = vec![to];
+                while let Some(previous) = reached_by[*path.last().unwrap()] {
+                    path.push(previous);
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            for &target in self.cells[i].pointers() {
+                if !visited[target] && !self.cells[target].freed {
+                    visited[target] = true;
+                    reached_by[target] = Some(i);
+                    queue.push_back(target);
+                }
+            }
+        }
+
+        None
+    }
+
+    //Traversal behind reachable(), starting from any set of cells rather than just the roots
+    fn reachable_from(&self, start: Vec<usize>) -> Vec<bool> {
+        let mut reached: Vec<bool> = vec![false; self.cells.len()];
+        let mut worklist: Vec<usize> = start;
+
+        while let Some(i) = worklist.pop() {
+            if reached[i] {
+                continue;
+            }
+            reached[i] = true;
+
+            for &target in self.cells[i].pointers() {
+                //References to free cells are dangling, there is nothing there to reach
+                if !reached[target] && !self.cells[target].freed {
+                    worklist.push(target);
+                }
+            }
+        }
+
+        reached
+    }
+
+    /// Cells in use that can't be reached from any root -> what a (correct) collection would reclaim
+    pub fn garbage(&self) -> Vec<usize> {
+        self.iter_garbage().map(|(handle, _)| handle.index).collect()
+    }
+
+    /// Finds the cell with the given name, if there is one
+    pub fn find(&self, name: &str) -> Option<usize> {
+        self.cells.iter().position(|cell| cell.name.as_deref() == Some(name))
+    }
+
+    /// Gives the cell at `index` a name, so it can be referred to by name instead of index.
+    /// Names belong to the data in the cell -> they move with it, and are dropped when it is freed.
+    /// Passing `None` removes the cell's name
+    pub fn name_cell(&mut self, index: usize, name: Option<&str>) -> Result<(), NameError> {
+        match self.cells.get(index) {
+            None => return Err(NameError::OutOfBounds),
+            Some(cell) if cell.freed => return Err(NameError::DataIsFree),
+            Some(_) => {}
+        }
+
+        if let Some(name) = name {
+            //Names can't look like an index or a command, or they couldn't be told apart
+            if name.is_empty() || name.starts_with('-') || name.parse::<usize>().is_ok() {
+                return Err(NameError::Invalid);
+            }
+            match self.find(name) {
+                Some(other) if other != index => return Err(NameError::Taken(other)),
+                _ => {}
+            }
+        }
+
+        self.cells[index].name = name.map(|name| name.to_string());
+        self.log.emit(Event::Named { index, name: name.map(|name| name.to_string()) });
+        Ok(())
+    }
+
+    /// Every cell in use that references the cell at `index` (its fan-in), found by following the references themselves
+    pub fn referrers(&self, index: usize) -> Vec<usize> {
+        (0..self.cells.len())
+            .filter(|&i| !self.cells[i].freed && self.cells[i].will_ref.contains(&index))
+            .collect()
+    }
+
+    /// Amount of cells on the heap that are currently in use (not free)
+    pub fn live(&self) -> usize {
+        self.iter_live().count()
+    }
+
+    /// Percentage of the heap's cells in use. An object bigger than its slot still takes up a single cell, so this is
+    /// never over 100 (see sizes.rs for the bytes they take up)
+    pub fn occupancy(&self) -> usize {
+        (self.live() * 100).checked_div(self.cells.len()).unwrap_or(0)
+    }
+
+    /// Takes a snapshot of the heap's current statistics
+    /// #### Example usage
+    /// ```ignore
+    /// let stats: HeapStats = heap.stats();
+    /// println!("{} of {} cells in use", stats.live, stats.capacity);
+    /// ```
+    pub fn stats(&self) -> HeapStats {
+        let live = self.live();
+
+        HeapStats {
+            capacity: self.cells.len(),
+            live,
+            free: self.cells.len() - live,
+            roots: self.cells.iter().filter(|cell| cell.is_root()).count(),
+            allocations: self.allocations,
+            collections: self.history.len(),
+            minor_collections: self.history.iter().filter(|cycle| cycle.kind == CollectionKind::Minor).count(),
+            major_collections: self.history.iter().filter(|cycle| cycle.kind == CollectionKind::Major).count(),
+            full_collections: self.history.iter().filter(|cycle| cycle.kind == CollectionKind::Full).count(),
+            high_water: self.high_water.max(live),   //Roots can be made without allocating, so check the current amount too
+            barrier_writes: self.barrier_writes,
+            read_barrier_hits: self.read_barrier_hits.load(Ordering::Relaxed),
+            rc_updates: self.rc_updates.logged,
+            rc_updates_elided: self.rc_updates.elided(),
+            reclaimed_by_rc: self.hybrid.by_rc,
+            reclaimed_by_tracer: self.hybrid.by_tracer,
+            interned: self.interner.deduplicated,
+            bytes_interned: self.interner.bytes_saved,
+            mark_epoch: self.mark_epoch,
+            frames: self.frames.len(),
+            bytes_capacity: self.bytes_capacity(),
+            bytes_used: self.bytes_used(),
+            bytes_reclaimed: self.history.iter().map(|cycle| cycle.bytes_swept).sum(),
+        }
+    }
+
+    /// Starts appending a row to the CSV file at `path` after every collection (see `metrics::CSV_HEADER` for the columns)
+    #[cfg(feature = "std")]
+    pub fn attach_metrics_csv(&mut self, path: &str) -> io::Result<()> {
+        self.metrics_csv = Some(MetricsCsv::open(path)?);
+        Ok(())
+    }
+
+    /// Stops writing collections to a CSV file
+    #[cfg(feature = "std")]
+    pub fn detach_metrics_csv(&mut self) {
+        self.metrics_csv = None;
+    }
+
+    /// Starts serving the heap's metrics over HTTP (Prometheus text format) at `http://127.0.0.1:<port>/metrics`, on
+    /// this machine only (see `serve_metrics_on()` to serve them to the network). Returns where it is listening.
+    /// The endpoint can only be started once, and keeps running for as long as the program does
+    #[cfg(feature = "std")]
+    pub fn serve_metrics(&mut self, port: u16) -> io::Result<SocketAddr> {
+        self.serve_metrics_on(IpAddr::V4(Ipv4Addr::LOCALHOST), port)
+    }
+
+    /// Starts serving the heap's metrics on the interface at `ip` (i.e. 0.0.0.0 for every interface, so a Prometheus
+    /// server elsewhere can scrape them), see `serve_metrics()`
+    #[cfg(feature = "std")]
+    pub fn serve_metrics_on(&mut self, ip: IpAddr, port: u16) -> io::Result<SocketAddr> {
+        if let Some(endpoint) = &self.metrics_endpoint {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("already serving metrics at http://{}/metrics", endpoint.address),
+            ));
+        }
+
+        let endpoint: MetricsEndpoint = MetricsEndpoint::serve(ip, port, self.allocations)?;
+        let address: SocketAddr = endpoint.address;
+        self.metrics_endpoint = Some(endpoint);
+        self.publish_metrics();
+        Ok(address)
+    }
+
+    /// Refreshes what the metrics endpoint serves (does nothing if it isn't running)
+    #[cfg(feature = "std")]
+    pub fn publish_metrics(&self) {
+        if let Some(endpoint) = &self.metrics_endpoint {
+            endpoint.publish(self);
+        }
+    }
+
+    /// Refreshes everything served over HTTP -> the metrics endpoint and the live view (if they are running).
+    /// Called after every collection, front ends should also call it after anything else that changes the heap.
+    /// Without std nothing is served, so it does nothing
+    pub fn publish(&self) {
+        #[cfg(feature = "std")]
+        {
+            self.publish_metrics();
+            self.publish_live();
+        }
+    }
+
+    //Bookkeeping after every successful allocation -> counts it, raises the high-water mark if needed and logs it
+    fn record_alloc(&mut self, index: usize, data: i32) {
+        self.allocations += 1;
+        self.high_water = self.high_water.max(self.live());
+
+        self.tag_allocation(index);
+        self.log.emit(Event::Alloc { index, data });
+        self.root_in_frame(index);                  //Allocations inside a frame are its locals
+        self.treadmill_alloc(index);
+        self.suspect_cycle(index);                  //It may be built into a cycle before the hybrid's next pass
+        self.buffer_new(index);                     //...or never referenced before the buffered variant's next batch
+    }
+}
+
+///Reasons a cell can't be given a name
+#[derive(Debug)]
+pub enum NameError {
+    OutOfBounds,        //There is no cell at that index
+    DataIsFree,         //Only cells in use can be named (a free cell has nothing to name)
+    Taken(usize),       //Another cell already has this name
+    Invalid,            //Names can't be empty, start with '-', or be a number
+}
+
+///Index Result which is the return type for allocation functions
+/// Either, it was successful and it returns the index position <usize>
+/// Otherwise, it was unsuccessful -> where we return a GcError (see error.rs)
+pub type IndexResult = Result<usize, GcError>;
+
+/// Macro to abstract away what allocation function to actually use, just pass in parameters and the macro will decide which arm to match
+/// Allocates memory in the memory pool with different patterns:
+///
+/// # Patterns
+///
+/// ## Pattern 0: Just data
+/// ```ignore
+/// malloc!(heap, data)
+/// ```
+/// Allocates data in the first available cell with no references.
+/// This value would be swept by the garbage collector if unreferenced.
+///
+/// ## Pattern 1: Automatic free allocation
+/// ```ignore
+/// malloc!(heap, data, reference_to)
+/// ```
+/// Allocates data with a reference to another cell.
+///
+/// ## Pattern 2: Specific allocation
+/// ```ignore
+/// malloc!(heap, data, reference, pos)
+/// ```
+/// Allocates data at a specific position with a reference to another cell.
+///
+/// # Arguments
+///
+/// * `heap` - A mutable reference to the virtual heap
+/// * `data` - The value to store in the cell
+/// * `reference_to` - Optional reference to another cell index
+/// * `pos` - Optional specific position to allocate at
+///
+/// # Returns
+///
+/// * `IndexResult` - Result containing either the allocated index or an allocation error
+///
+/// # Examples
+///
+/// ```ignore
+/// // Allocate data with no references
+/// let index = malloc!(heap, 42);
+///
+/// // Allocate data with a reference to cell at index 0
+/// let index = malloc!(heap, 42, Some(0));
+///
+/// // Allocate data at position 5 with a reference to cell at index 0
+/// let index = malloc!(heap, 42, Some(0), 5);
+/// ```
+#[macro_export]
+macro_rules! malloc {
+    // Pattern 0 Just data - find first available cell with no reference
+    ($heap:expr, $data:expr) => {
+        $crate::free_alloc($heap, $data, None)    //Allocate data in memory that has no references
+                                                //... this value would be sweeped by the garbage collector
+    };
+
+    //Pattern 1 (Automatic, first free-allocation)
+    ($heap:expr, $data:expr, $reference_to:expr) => {
+        //Three parameters, call free_alloc
+        $crate::free_alloc($heap, $data, $reference_to)
+    };
+
+    //Pattern 2 (specific-allocation)
+    ($heap:expr, $data:expr, $reference:ident, $pos:expr) => {
+        //Four parameters, call spec_alloc
+        $crate::spec_alloc($heap, $data, $reference, $pos)
+    };
+}
+
+///Run once at the start during of the program to create a memory pool "The Virtual Heap" ->
+///which is essentially just a Vec of Cell, with size n specified when the function is called.
+fn init_pool(size: usize) -> Vec<Cell> {
+    //Create instance of a default cell
+    let default_cell = Cell::new();
+
+    //Set up memory pool with just default implementations of cells
+    let cells: Vec<Cell> = vec![default_cell; size];
+
+    cells //Return cells
+}
+
+///Searches through the cells vec and finds a cell that is not in use, and assigns it the memory that is requested
+///to be stored here. (At this stage, only supports storing `i32` primitive values)
+///Return an index that points to the location in memory that the data is stored.
+///Takes a mutable reference to the heap so it can update and iterate on the memory pool.
+#[allow(clippy::needless_range_loop, clippy::bool_comparison, clippy::unnecessary_unwrap)]
+pub fn free_alloc(heap: &mut Heap, req_data: i32, ref_to: Option<usize>) -> IndexResult {    
+    let reserved: Range<usize> = heap.reserved();                  //Survivor spaces, only a collection copies into them
+
+    //Find first avaliable cell to be used
+    for i in 0..heap.cells.len() {
+        if heap.cells[i].freed == true && heap.cells[i].forward.is_none() && !reserved.contains(&i) {
+            //Store the data at the index position i
+            heap.cells[i] = Cell {
+                data: Some(req_data),
+                reference_count: i32::from(ref_to.is_some()), //Counts the reference made at allocation (if any), as spec_alloc() does
+                freed: false,
+                is_root: false,
+                by_ref: vec![],                     //Initially, no cells will reference this cell
+                will_ref: if ref_to.is_some() {
+                    vec![ref_to.unwrap()]           //Reference was provided at allocation            
+                }
+                else {
+                    vec![]                          //Empty vector, no reference was provided at allocation
+                },                                          
+                marked: false,
+                name: None,
+                tags: Vec::new(),
+                age: 0,
+                garbage_for: 0,
+                generation: heap.cells[i].generation,
+                kind: Kind::Int,
+                record: None,
+                site: None,                         //Tagged by record_alloc()
+                forward: None,
+            };
+
+            heap.record_alloc(i, req_data);
+            return Ok(i); //If successful, return index I as position stored
+        }
+    }
+    Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None)) //-> Retern no free memory as an error
+}
+
+/// Allocates at a specific memory position.
+/// #### Params
+/// ```text
+/// heap: &mut Heap //-> a mutable reference to the virtual heap
+/// req_data: i32 //-> requesting data to be store in the pos parsed
+/// reference: Option<usize> //-> Optionally choose a cell that this cell will reference
+/// store_pos: usize //-> what memory cell position will it be stored on?
+/// ```
+/// 
+/// Returns `Occupied` error if you try to write over data that is already stored in memory in the requested position,
+/// or `OutOfBounds` error if there is no such position.
+#[allow(unused_mut, clippy::needless_late_init, clippy::bool_comparison, clippy::unnecessary_unwrap)]
+pub fn spec_alloc(heap: &mut Heap, req_data: i32, reference: Option<usize>, store_pos: usize) -> IndexResult {
+    if store_pos >= heap.cells.len() {
+        return Err(GcError::new(ErrorKind::OutOfBounds, Operation::Alloc, Some(store_pos)));
+    }
+   
+   let mut ref_amt: i32;
+   //derive reference amt
+   if reference.is_some() {
+        ref_amt = 1;
+   } else {
+        ref_amt = 0;
+   }
+    
+    //check if memory is allocated (a relocation's forwarding stubs count as allocated until it is over)
+    if heap.cells[store_pos].freed == true && heap.cells[store_pos].forward.is_none() {
+        //the memory is free for use
+        //store the data
+        heap.cells[store_pos] = Cell {
+            data: Some(req_data),
+            reference_count: ref_amt,
+            freed: false,
+            is_root: false,
+            will_ref: if reference.is_some() {
+                vec![reference.unwrap()]            //Reference was provided at allocation
+            } else {
+                vec![]                              //No reference was provided at allocation
+            },
+            by_ref: vec![],                         //Start with no cell referencing this cell
+            marked: false,
+            name: None,
+            tags: Vec::new(),
+            age: 0,
+            garbage_for: 0,
+            generation: heap.cells[store_pos].generation,
+            kind: Kind::Int,
+            record: None,
+            site: None,                             //Tagged by record_alloc()
+            forward: None,
+        };
+
+        heap.record_alloc(store_pos, req_data);
+        return Ok(store_pos);
+    }
+
+    Err(GcError::new(ErrorKind::Occupied, Operation::Alloc, Some(store_pos))) //Return none as the memory position is not free, handle this by freeing pos at call
+}
+
+/// Frees the data at the pointer index position
+/// by deleting the stored information there, and replaces it with a default cell value.
+/// The cell's generation is bumped and it is poisoned, so handles to the data that was there go stale
+/// and any other access to it is caught (see poison.rs).
+/// Returns `DoubleFree` error if the cell is already free, or `FreedRoot` error if it is a root (it has to be unrooted first)
+#[allow(clippy::ptr_arg)]
+fn free(cells: &mut Vec<Cell>, pointer: usize) -> Result<(), GcError> {
+    if cells[pointer].freed {
+        return Err(GcError::new(ErrorKind::DoubleFree, Operation::Free, Some(pointer)));
+    }
+    if cells[pointer].is_root() {
+        return Err(GcError::new(ErrorKind::FreedRoot, Operation::Free, Some(pointer)));
+    }
+
+    let generation: u32 = cells[pointer].generation.wrapping_add(1);
+    cells[pointer] = Cell::new(); //Use new impl for cell to create a default cell (default state for a free cell awaiting assignment)
+    cells[pointer].generation = generation;
+    cells[pointer].poison();
+    Ok(())
+}
+
+/// Frees a cell the collector found to be garbage, reporting it as swept. Returns whether it was freed ->
+/// a cell `free()` refuses (already free, or a root) is left as it is, with a warning, as the collector shouldn't have picked it
+#[allow(clippy::ptr_arg)]
+pub(crate) fn reclaim(cells: &mut Vec<Cell>, log: &mut EventLog, index: usize) -> bool {
+    match free(cells, index) {
+        Ok(()) => {
+            log.emit(Event::Swept { index });
+            true
+        }
+        Err(why) => {
+            log.emit(Event::Warning(format!("Cell {} wasn't swept, {}", index, why.kind)));
+            false
+        }
+    }
+}
+
+/// Adds any number of cells to the named root set (see `roots::GLOBALS` for the usual one), as roots for the garbage collector.
+/// Cells that are out of bounds are skipped with a warning
+pub fn configure_roots(heap: &mut Heap, set: &str, cells: &[usize]) {
+    for &root in cells {
+        //Unfrees the cell, as it'll have a value (soon)
+        if heap.add_root(set, root).is_err() {
+            heap.log.emit(Event::Warning(format!(
+                "Cell {} is out of bounds (the heap has {} cells), it wasn't made a root",
+                root,
+                heap.cells.len()
+            )));
+        }
+    }
+}
+
+/// Unroots all cells in the virtual memory heap, by removing every root set.
+/// Locals of live stack frames stay roots until their frame is popped
+pub fn unroot(heap: &mut Heap) {
+    let names: Vec<String> = heap.root_sets().iter().map(|root_set| root_set.name.clone()).collect();
+    for name in names {
+        heap.drop_root_set(&name);
+    }
+}
+
+/// Populates any remaining cells with data that is not referencing anything (these will be sweeped)
+/// I.e. fill each remaining free cell with arbitrary `i32` data that is not being referenced or making references.
+/// This is soley for the purpose of demonstrating that the Mark and Sweep part of the garbage collector works.
+#[allow(clippy::bool_comparison)]
+pub fn populate_remaining(heap: &mut Heap) {
+    //loop through and populate all free cells
+    let random_val: i32 = heap.rng.random_range(0..1000);   //Generate a random arbitrary int value
+
+    for i in 0..heap.cells.len() {
+        if heap.cells[i].freed == true {
+            //Cell is free
+            heap.cells[i].data = Some(random_val);      //Assign some arbitrary data (exact val, not important)
+            heap.cells[i].freed = false;                //This cell now has data occupying it
+            heap.record_alloc(i, random_val);
+        }
+    }
+}
+
+/// Function that is used to handle cell viability on creating references -> i.e are these cells in use? If they are free return error.
+/// Can handle `n` number of cells as `_cells` is a `&Vec<usize>`
+/// Returns `DataIsFree` error if the cell isn't in use, `UseAfterFree` error if it was freed, or `OutOfBounds` error if there is no such cell.
+/// (Can't make a reference to a free cell)
+#[allow(clippy::ptr_arg, clippy::duplicate_underscore_argument)]
+fn cell_viability(cells: &Vec<Cell>, _cells: &Vec<usize>) -> IndexResult {
+
+    //Check if the cells are free (i.e. not in use)
+    for cell_index in _cells {
+        //If the cell IS free, then we shouldn't be returning a reference
+        poison::check_access(cells, *cell_index, Operation::Link)?;
+    }
+
+    //If no errors were found, return 1
+    Ok(1)
+}
+
+/// Assigns a reference between two stated cells
+/// #### c1pos will reference c2pos and c2pos will be referenced by c1pos
+/// makes external call to ```cell_viability()``` here to check if parsed cell positions are valid
+/// ```ignore
+/// let result: IndexResult = cell_viability(&heap.cells, &cells_to_check);
+/// ```
+#[allow(unused_variables, clippy::assign_op_pattern)]
+pub fn assign_reference(heap: &mut Heap, c1pos: usize, c2pos: usize) {
+
+    //Assign reference between two cells
+    /*
+        -> c1pos WILL REFERENCE c2pos
+        therefore, c2pos will be referenced BY c1pos
+     */
+
+    //Check if the data can be used
+    let cells_to_check: Vec<usize> = vec![c1pos, c2pos];
+    let result: IndexResult = cell_viability(&heap.cells, &cells_to_check);
+
+    //Boolean flag
+    let mut check: bool = false;
+
+    //Perform action or report error
+    match result {
+        Ok(val) => check = true,                        //Boolean flag to progress the function
+        Err(why) => heap.log.emit(Event::Warning(why.to_string())),
+    }
+
+    //Going by its header, the tracer never looks for references in a cell of string bytes, so it can't make any
+    if check && !heap.cells[c1pos].kind.has_pointers() {
+        heap.log.emit(Event::Warning(format!("Cell {} holds {}, which can't reference other cells", c1pos, heap.cells[c1pos].kind)));
+        check = false;
+    }
+
+    //Only create references if allowed
+    if check {
+        heap.write_barrier(Mutation::Reference { from: c1pos, to: c2pos });
+        let cells = &mut heap.cells;
+
+        //A reference that already exists isn't counted again, or unlinking it once would leave a count behind
+        if !cells[c1pos].will_ref.contains(&c2pos) {
+            //Cell 1
+            cells[c1pos].reference_count = cells[c1pos].reference_count + 1;    //Increase reference count
+            cells[c1pos].will_ref.push(c2pos);                                  //Push c2pos into vector of references
+
+            //Cell 2
+            cells[c2pos].reference_count = cells[c2pos].reference_count + 1;    //Increase reference count
+            cells[c2pos].by_ref.push(c1pos);                                    //Push c1pos into vector of references
+
+            heap.buffer_rc(c2pos, 1);
+        }
+
+        heap.log.emit(Event::RefCreated { from: c1pos, to: c2pos });
+    }
+
+}
+
+/// Frees a cell by hand, safely -> every reference to and from it is removed first (along with the reference counts
+/// they added), so no other cell is left pointing at memory that could be reused for something else.
+/// Returns `DoubleFree` error if the cell is already free, `FreedRoot` error if it is a root and `force` isn't set,
+/// or `OutOfBounds` error if there is no such cell
+pub fn free_cell(heap: &mut Heap, index: usize, force: bool) -> IndexResult {
+    if index >= heap.cells.len() {
+        return Err(GcError::new(ErrorKind::OutOfBounds, Operation::Free, Some(index)));
+    }
+    if heap.cells[index].freed {
+        return Err(GcError::new(ErrorKind::DoubleFree, Operation::Free, Some(index)));
+    }
+    if heap.cells[index].is_root() && !force {
+        return Err(GcError::new(ErrorKind::FreedRoot, Operation::Free, Some(index)));
+    }
+    heap.cells[index].is_root = false;                                          //Forced, it stops being a root first
+
+    //Every reference (in both directions) counted once on each end, see assign_reference()
+    for target in heap.cells[index].will_ref.clone() {
+        heap.buffer_rc(target, -1);
+        heap.suspect_cycle(target);
+    }
+    for _ in 0..heap.cells[index].by_ref.len() {
+        heap.buffer_rc(index, -1);                                              //Its own count goes with the references to it
+    }
+    for (i, cell) in heap.cells.iter_mut().enumerate() {
+        if i == index {
+            continue;
+        }
+        if let Some(pos) = cell.will_ref.iter().position(|&to| to == index) {
+            cell.will_ref.remove(pos);
+            cell.reference_count -= 1;
+            if let Some(record) = &mut cell.record {
+                record.forget(index);                                           //...along with any fields it was in
+            }
+        }
+        if let Some(pos) = cell.by_ref.iter().position(|&by| by == index) {
+            cell.by_ref.remove(pos);
+            cell.reference_count -= 1;
+        }
+    }
+
+    heap.tally_free(index);
+    free(&mut heap.cells, index)?;
+    heap.forget_local(index);
+    heap.forget_root(index);
+    heap.forget_finalizer(index);
+    heap.forget_weak_entry(index);
+    heap.log.emit(Event::Freed { index });
+    Ok(index)
+}
+
+/// Overwrites the data stored in a cell that is in use, passing the write through the heap's write barrier first.
+/// Returns `DataIsFree` error if the cell is free (there is no data there to overwrite), `UseAfterFree` error if it was freed,
+/// or `OutOfBounds` error if there is no such cell
+pub fn set_data(heap: &mut Heap, index: usize, value: i32) -> IndexResult {
+    poison::check_access(&heap.cells, index, Operation::Write)?;
+
+    heap.write_barrier(Mutation::Data { index });
+
+    let old: Option<i32> = heap.cells[index].data.replace(value);
+    heap.log.emit(Event::DataSet { index, old, new: value });
+    Ok(index)
+}
+
+/// Removes the reference from c1pos to c2pos, undoing `assign_reference()` (both reference counts drop by one).
+/// Under reference counting (`Algorithm::RefCount`), c2pos is reclaimed straight away if nothing references it any more,
+/// while the buffered variant only logs the decrement for its next batch (and the hybrid makes it a candidate cycle root, if it isn't)
+pub fn unlink_reference(heap: &mut Heap, c1pos: usize, c2pos: usize) {
+    if c1pos >= heap.cells.len() {
+        heap.log.emit(Event::Warning(GcError::new(ErrorKind::OutOfBounds, Operation::Unlink, Some(c1pos)).to_string()));
+        return;
+    }
+    if !heap.cells[c1pos].will_ref.contains(&c2pos) {
+        heap.log.emit(Event::Warning(format!("Cell {} doesn't reference cell {}", c1pos, c2pos)));
+        return;
+    }
+
+    heap.write_barrier(Mutation::Unlink { from: c1pos, to: c2pos });
+    let cells = &mut heap.cells;
+
+    //Cell 1
+    cells[c1pos].reference_count -= 1;                                          //Decrease reference count
+    cells[c1pos].will_ref.retain(|&to| to != c2pos);                            //No longer references c2pos
+    if let Some(record) = &mut cells[c1pos].record {
+        record.forget(c2pos);                                                   //...through any of its fields either
+    }
+
+    //Cell 2
+    cells[c2pos].reference_count -= 1;                                          //Decrease reference count
+    cells[c2pos].by_ref.retain(|&by| by != c1pos);                              //No longer referenced by c1pos
+
+    heap.log.emit(Event::RefRemoved { from: c1pos, to: c2pos });
+
+    //Reference counting reclaims garbage the moment it is created (unless the update is buffered, see refcount.rs)
+    match heap.policy.algorithm {
+        Algorithm::RefCount => {
+            refcount::release(heap, c2pos);
+        }
+        Algorithm::Hybrid => hybrid::release(heap, c2pos),
+        _ => {}
+    }
+    heap.buffer_rc(c2pos, -1);
+}
+
+/// Root scan -> the first step of a collection. Finds the index position of every root cell on the virtual heap,
+/// which are the starting points for the marking traversal.
+#[allow(clippy::ptr_arg, clippy::needless_range_loop)]
+pub fn scan_roots(cells: &Vec<Cell>) -> Vec<usize> {
+    //get root index position
+    let mut roots: Vec<usize> = Vec::new();
+    for i in 0..cells.len() {
+        if cells[i].is_root() {
+            roots.push(i);
+        }
+    }
+
+    roots
+}
+
+/// What a mark phase did, returned by `mark()`
+#[derive(Clone, Debug, Default)]
+pub struct Marking {
+    pub visited: Vec<usize>,        //Every cell marked (kept), in the order they were visited
+    pub deepest: usize,             //The most cells the worklist held at once
+    pub rescans: usize,             //How many times the worklist overflowed, each costing a rescan of the heap
+}
+
+///Runs the marking (Non-recursive worklist) algorithm on all cells of memory on the virtual heap.
+/// #### Parameters
+/// `cells` -> requires a mutable reference to the cells vector of type `Vec<Cell>`
+/// `roots` -> the root index positions found by `scan_roots()`
+/// `order` -> whether to visit cells depth first or breadth first
+/// `limit` -> the most cells the worklist can hold (None for no limit), see below
+/// #### Example usage
+/// ```ignore
+/// mark(cells, roots, MarkOrder::Dfs, None);
+/// ```
+/// Mutates the cells directly and marks their `marked` boolean flag, returning every cell it marked (kept) in the order it visited them.
+/// Marks are only ever set here, so they have to be cleared first with `clear_marks()` (as `collect()` does)
+/// #### Overflow
+/// A real collector can't let its worklist grow without bound while memory is short. Once the worklist is full,
+/// a newly found cell is only marked, not pushed, and an overflow flag is set. When the worklist runs dry with the flag set,
+/// the heap is rescanned for cells that are marked but haven't been scanned, and they are pushed to carry on from.
+/// Every reachable cell is still marked, at the cost of a pass over the heap per overflow
+#[allow(clippy::ptr_arg)]
+pub fn mark(cells: &mut Vec<Cell>, roots: Vec<usize>, order: MarkOrder, limit: Option<usize>) -> Marking {
+    //The worklist holds cells that are marked, but whose references haven't been followed yet.
+    //A cell is marked as it is pushed (never again after), so each cell is pushed and scanned at most once,
+    //however many cells reference it (a diamond) and even if the references lead back round to it (a cycle)
+    let limit: usize = limit.unwrap_or(usize::MAX).max(1);
+    let mut worklist: VecDeque<usize> = VecDeque::new();
+    let mut scanned: Vec<bool> = vec![false; cells.len()];
+    let mut overflowed: bool = false;
+    let mut marking: Marking = Marking::default();
+
+    //Every root is live, whether or not it references anything
+    for root in roots {
+        if !cells[root].marked {
+            cells[root].marked = true;
+            overflowed |= !push(&mut worklist, root, limit);
+        }
+    }
+
+    loop {
+        marking.deepest = marking.deepest.max(worklist.len());
+
+        //DFS takes the most recently pushed cell, so the traversal goes deep before it goes wide.
+        //BFS takes the longest waiting, finishing each level of the graph before starting the next
+        while let Some(current) = match order {
+            MarkOrder::Dfs => worklist.pop_back(),
+            MarkOrder::Bfs => worklist.pop_front(),
+        } {
+            scanned[current] = true;
+            marking.visited.push(current);
+
+            for n in 0..cells[current].pointers().len() {
+                let next: usize = cells[current].pointers()[n];
+
+                //Already marked -> already on the worklist, already scanned, or waiting for a rescan
+                if !cells[next].marked {
+                    cells[next].marked = true;
+                    overflowed |= !push(&mut worklist, next, limit);
+                    marking.deepest = marking.deepest.max(worklist.len());
+                }
+            }
+        }
+
+        if !overflowed {
+            break;
+        }
+
+        //Overflow recovery -> pick up every cell that was marked but dropped, as many as fit (the rest wait for the next rescan)
+        overflowed = false;
+        marking.rescans += 1;
+        for i in 0..cells.len() {
+            if cells[i].marked && !scanned[i] && !push(&mut worklist, i, limit) {
+                overflowed = true;
+                break;
+            }
+        }
+    }
+
+    //Every cell that survives this collection
+    marking
+}
+
+//Pushes a cell onto the mark worklist, unless it is full. Returns whether it was pushed
+fn push(worklist: &mut VecDeque<usize>, index: usize, limit: usize) -> bool {
+    if worklist.len() >= limit {
+        return false;
+    }
+
+    worklist.push_back(index);
+    true
+}
+
+/// The sweeping phase of the garbage collector (free any memory cell that isn't referencing anything or is being referenced)
+/// #### Example Cell To Be Swept (Freed)
+/// ```text
+/// Cell 
+/// {
+///     data: <...>
+///     reference_count: <...>
+///     freed: <...>
+///     is_root: <...>
+///     by_ref: <...>
+///     will_ref: <...>
+///     marked: false,      // <- This cell is not marked to keep, and therefore it is determined to not be in use anymore          
+/// }
+/// ```
+///
+/// Returns the amount of cells that were holding data when they were swept (i.e. memory that was actually reclaimed)
+#[allow(clippy::ptr_arg)]
+pub fn sweep(cells: &mut Vec<Cell>, log: &mut EventLog) -> usize {
+    let swept: usize = sweep_cells(cells, log, 0..cells.len());
+    drop_swept_referrers(cells);
+    swept
+}
+
+//The first half of sweep(), over some of the cells -> so a sweep can be paused part way through (see breakpoints.rs)
+#[allow(clippy::ptr_arg)]
+fn sweep_cells(cells: &mut Vec<Cell>, log: &mut EventLog, range: Range<usize>) -> usize {
+    //free (sweep) all the cells are position usize
+    let mut swept: usize = 0;
+
+    //run the free function on each cell that is not marked
+    for i in range {
+        //Only cells that are actually in use are reclaimed (and reported, and counted), free cells are left as they are
+        if !cells[i].marked && !cells[i].freed && reclaim(cells, log, i) {
+            swept += 1;
+        }
+    }
+
+    swept
+}
+
+//The second half of sweep(), once every cell has been swept
+pub(crate) fn drop_swept_referrers(cells: &mut [Cell]) {
+    //Garbage can still reference live cells (just not the other way round) -> those references go with it,
+    //or the live cell would be left counting a referrer that no longer exists (and that its index may be reused for)
+    for i in 0..cells.len() {
+        let referrers: usize = cells[i].by_ref.len();
+        let by_ref: Vec<usize> = cells[i].by_ref.iter().copied().filter(|&by| !cells[by].freed).collect();
+        cells[i].reference_count -= (referrers - by_ref.len()) as i32;
+        cells[i].by_ref = by_ref;
+    }
+}
+
+/// Clear phase -> unmarks every cell (roots included) and starts a new mark epoch, so each collection starts from a clean slate.
+/// A mark left over from an earlier collection would otherwise keep a cell alive after it became garbage
+#[allow(clippy::needless_range_loop)]
+pub fn clear_marks(heap: &mut Heap) {
+    for i in 0..heap.cells.len() {
+        heap.cells[i].marked = false;
+    }
+    heap.mark_epoch += 1;
+}
+
+/// This function runs the entire garbage collection algorithm chosen by the heap's policy
+/// (mark and sweep by default, see `copying`, `refcount`, `generational`, `treadmill` and `g1` for the others).
+/// Records the cycle in the heap's history and logs a one line summary, i.e.
+/// `GC #3: marked 12, swept 5 (60 bytes), root scan 2µs, mark 80µs, sweep 40µs`
+pub fn collect(heap: &mut Heap) {
+    match heap.policy.algorithm {
+        Algorithm::Generational => {
+            collect_as(heap, CollectionKind::Minor);
+            if generational::needs_major(heap) {
+                collect_as(heap, CollectionKind::Major);
+            }
+        }
+        _ => collect_as(heap, CollectionKind::Whole),
+    }
+}
+
+/// Runs one collection of the given kind, see `collect()`
+pub(crate) fn collect_as(heap: &mut Heap, kind: CollectionKind) {
+    heap.log.emit(Event::CollectStart { number: heap.history.len() + 1 });
+    heap.hold_weak_values();                        //Entries go with their keys, the values of live keys are kept
+    heap.queue_finalizers();                        //Finalizable garbage is kept alive until its finalizer has run
+    clear_marks(heap);
+    if kind != CollectionKind::Whole || !matches!(heap.policy.algorithm, Algorithm::RefCount | Algorithm::BufferedRc | Algorithm::Hybrid) {
+        heap.reach(Breakpoint::MarkPhase);
+    }
+    let at: Duration = heap.started.elapsed();
+    let used: usize = heap.bytes_used();
+    //Any other collection changes the heap under the treadmill, which starts again from scratch next time
+    if kind != CollectionKind::Whole || heap.policy.algorithm != Algorithm::Treadmill {
+        heap.treadmill = None;
+    }
+    //...and frees cells behind the buffered variant's counts, which are taken from the graph again at its next batch
+    if kind != CollectionKind::Whole || heap.policy.algorithm != Algorithm::BufferedRc {
+        heap.rc_updates.forget_counts();
+    }
+    let mut cycle: GcCycle = match (kind, heap.policy.algorithm) {
+        (CollectionKind::Minor, _) => generational::minor(heap),
+        (CollectionKind::Major, _) | (CollectionKind::Whole, Algorithm::MarkSweep | Algorithm::Generational) => mark_sweep(heap),
+        (CollectionKind::Full, _) | (CollectionKind::Whole, Algorithm::Copying) => copying::collect(heap),
+        (CollectionKind::Whole, Algorithm::RefCount) => refcount::collect(heap),
+        (CollectionKind::Whole, Algorithm::Treadmill) => treadmill::collect(heap),
+        (CollectionKind::Whole, Algorithm::GarbageFirst) => g1::collect(heap),
+        (CollectionKind::Whole, Algorithm::BufferedRc) => refcount::collect_buffered(heap),
+        (CollectionKind::Whole, Algorithm::Hybrid) => hybrid::collect(heap),
+    };
+    cycle.kind = kind;
+    cycle.number = heap.history.len() + 1;
+    cycle.at = at;
+    cycle.bytes_swept = used.saturating_sub(heap.bytes_used());
+
+    //Everything still in use has survived another collection
+    for cell in heap.cells.iter_mut().filter(|cell| !cell.freed) {
+        cell.age += 1;
+    }
+    heap.tally_survivors();
+    heap.refresh_remembered(kind);
+
+    //...and anything the collector left behind while unreachable has been garbage for one more (see --leaks)
+    let reached: Vec<bool> = heap.reachable();
+    for (cell, reached) in heap.cells.iter_mut().zip(reached) {
+        match cell.freed || reached {
+            true => cell.garbage_for = 0,
+            false => cell.garbage_for += 1,
+        }
+    }
+
+    heap.log.emit(Event::Collected {
+        number: cycle.number,
+        kind,
+        marked: cycle.marked,
+        swept: cycle.swept,
+        bytes: cycle.bytes_swept,
+        root_scan: cycle.root_scan,
+        mark: cycle.mark,
+        sweep: cycle.sweep,
+    });
+
+    //Append the cycle to the metrics CSV, if one is attached
+    #[cfg(feature = "std")]
+    let (live, occupancy) = (heap.live(), heap.occupancy());
+    #[cfg(feature = "std")]
+    if let Some(csv) = &mut heap.metrics_csv {
+        if let Err(why) = csv.record(&cycle, live, occupancy) {
+            heap.metrics_csv = None;
+            heap.log.emit(Event::Warning(format!(
+                "Unable to write to the metrics CSV, it has been detached: {}",
+                why
+            )));
+        }
+    }
+
+    heap.history.push(cycle);
+    heap.run_finalizers();
+    heap.release_weak_values();
+    heap.publish();
+    heap.shrink_to_policy();
+}
+
+/// Mark and sweep collection, running these three phases and timing each of them.
+/// ```ignore
+/// scan_roots() -> mark() -> sweep();
+/// ```
+fn mark_sweep(heap: &mut Heap) -> GcCycle {
+    //Find the roots to start marking from
+    let start = Instant::now();
+    let roots: Vec<usize> = heap.roots();
+    let root_scan = start.elapsed();
+
+    //'mark' cells to be freed (sweeped)
+    let order: MarkOrder = heap.policy.mark_order;
+    heap.log.emit(Event::MarkStart { roots: roots.len(), order });
+    let start = Instant::now();
+    let marking: Marking = mark(&mut heap.cells, roots, order, heap.policy.mark_stack);
+    let mark_time = start.elapsed();
+    //...reported in the order they were visited, which is all the mark order changes
+    for &index in &marking.visited {
+        heap.log.emit(Event::Marked { index });
+    }
+    if let Some(limit) = heap.policy.mark_stack.filter(|_| marking.rescans > 0) {
+        heap.log.emit(Event::MarkOverflow { limit, rescans: marking.rescans });
+    }
+    let marked: usize = marking.visited.len();
+    heap.log.emit(Event::MarkEnd { marked });
+
+    let (swept, sweep_time) = heap.sweep_phase();
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked,
+        swept,
+        bytes_swept: 0,
+        root_scan,
+        mark: mark_time,
+        sweep: sweep_time,
+        algorithm: Algorithm::MarkSweep,
+        kind: CollectionKind::Whole,
+        left_in_place: 0,
+    }
+}
+
+impl Heap {
+    //Sweeps every cell that wasn't marked, a stretch at a time if it has to pause at breakpoints along the way.
+    //Returns how many cells were swept, and how long it took
+    pub(crate) fn sweep_phase(&mut self) -> (usize, Duration) {
+        let mut swept: usize = 0;
+        let mut sweep_time: Duration = Duration::ZERO;
+        let mut from: usize = 0;
+        for stop in self.sweep_stops().into_iter().chain([self.cells.len()]) {
+            let start = Instant::now();
+            swept += sweep_cells(&mut self.cells, &mut self.log, from..stop);
+            sweep_time += start.elapsed();
+
+            if stop < self.cells.len() {
+                self.reach(Breakpoint::Sweep(stop));
+            }
+            from = stop;
+        }
+        let start = Instant::now();
+        drop_swept_referrers(&mut self.cells);
+        sweep_time += start.elapsed();
+
+        (swept, sweep_time)
+    }
+}
+
+/// Checks the heap's occupancy against the `gc-threshold` policy, and runs a collection if it is exceeded.
+/// Called after every command that allocates memory, so the collector is triggered by allocation pressure
+/// rather than only when the user asks for it.
+pub fn check_gc_threshold(heap: &mut Heap) {
+    //Only applies when a threshold has been configured
+    if let Some(threshold) = heap.policy.gc_threshold {
+        let occupancy = heap.occupancy();
+
+        if occupancy > threshold {
+            heap.log.emit(Event::GcTriggered { occupancy, threshold });
+            collect(heap);
+        }
+    }
+}
+
+/// Allocates arbitrary data in cells that are reachable from the roots, as a random tree hanging off them (see `scenario::graph()`).
+/// This function holds little 'real-world' value to the functionality of a garbage collector, but it helps populate memory
+/// with references to aid in the demonstration of the functionality. It also populates arbitrary data into the root cells.
+///
+/// Allocates as many cells as there is room for, warning if that is fewer than `times_to_run`.
+/// For more control over the graph (shape, extra references, garbage), use `scenario::graph()` directly
+#[allow(clippy::needless_range_loop)]
+pub fn create_free_ref(heap: &mut Heap, times_to_run: usize) {
+    //set data of root memory cells
+    for i in 0..heap.cells.len() {
+        if heap.cells[i].is_root {
+            heap.cells[i].data = Some(heap.rng.random_range(1..50));
+        }
+    }
+
+    //A root is created by the generator if there are none, so leave room for it
+    let room: usize = heap.room().saturating_sub(usize::from(scan_roots(&heap.cells).is_empty()));
+    if room < times_to_run {
+        heap.log.emit(Event::Warning("No avaliable memory found".to_string()));
+    }
+
+    //Can't fail, there is room for every cell
+    let _ = scenario::graph(heap, times_to_run.min(room), 0, 0.0, scenario::Shape::Tree);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A heap with `n` cells in use (and a few free ones), cell 0 as its only root, and a reference for each (from, to)
+    pub(crate) fn graph(n: usize, edges: &[(usize, usize)]) -> Heap {
+        let mut heap: Heap = Heap::with_seed(n + 4, 0);
+        for i in 0..n {
+            spec_alloc(&mut heap, i as i32, None, i).unwrap();
+        }
+        configure_roots(&mut heap, roots::GLOBALS, &[0]);
+        for &(from, to) in edges {
+            assign_reference(&mut heap, from, to);
+        }
+        heap
+    }
+
+    //Runs a mark phase from a clean slate, returning how many cells it visited and which cells are marked
+    fn marked(heap: &mut Heap) -> (usize, Vec<usize>) {
+        clear_marks(heap);
+        let roots: Vec<usize> = scan_roots(&heap.cells);
+        let count: usize = mark(&mut heap.cells, roots, MarkOrder::Dfs, None).visited.len();
+        (count, (0..heap.cells.len()).filter(|&i| heap.cells[i].marked).collect())
+    }
+
+    #[test]
+    fn marks_a_chain() {
+        let mut heap: Heap = graph(5, &[(0, 1), (1, 2), (2, 3)]);
+        assert_eq!(marked(&mut heap), (4, vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn marks_a_diamond_once() {
+        let mut heap: Heap = graph(5, &[(0, 1), (0, 2), (1, 3), (2, 3), (3, 4)]);
+        assert_eq!(marked(&mut heap), (5, vec![0, 1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn marks_cycles() {
+        //A cycle back through the root, and a cell that references itself
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2), (2, 0), (2, 3), (3, 3)]);
+        assert_eq!(marked(&mut heap), (4, vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn marks_roots_without_references() {
+        let mut heap: Heap = graph(3, &[(1, 2)]);
+        assert_eq!(marked(&mut heap), (1, vec![0]));
+    }
+
+    #[test]
+    fn leaves_garbage_unmarked() {
+        //Garbage (a cycle, with a reference into the live cells) that nothing live references
+        let mut heap: Heap = graph(5, &[(0, 1), (2, 3), (3, 2), (3, 1), (4, 4)]);
+        assert_eq!(marked(&mut heap), (2, vec![0, 1]));
+    }
+
+    #[test]
+    fn marks_nothing_without_roots() {
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2), (2, 3), (3, 1)]);
+        heap.log.echo = false;
+        unroot(&mut heap);
+        assert_eq!(marked(&mut heap), (0, vec![]));
+
+        collect(&mut heap);
+        assert!(heap.cells.iter().all(|cell| cell.freed) && heap.verify().is_empty());
+    }
+
+    #[test]
+    fn visits_in_mark_order() {
+        //Both orders keep the same cells, only the order they are visited in differs
+        let edges: [(usize, usize); 4] = [(0, 1), (0, 2), (1, 3), (2, 4)];
+        for (order, visits) in [(MarkOrder::Dfs, [0, 2, 4, 1, 3]), (MarkOrder::Bfs, [0, 1, 2, 3, 4])] {
+            let mut heap: Heap = graph(5, &edges);
+            clear_marks(&mut heap);
+            let roots: Vec<usize> = scan_roots(&heap.cells);
+            assert_eq!(mark(&mut heap.cells, roots, order, None).visited, visits);
+        }
+    }
+
+    #[test]
+    fn recovers_from_mark_stack_overflow() {
+        //A wide root (more children than the worklist holds), a diamond and a cycle, with garbage on the side
+        let edges: [(usize, usize); 9] = [(0, 1), (0, 2), (0, 3), (0, 4), (1, 5), (2, 5), (5, 6), (6, 1), (7, 8)];
+        for order in [MarkOrder::Dfs, MarkOrder::Bfs] {
+            let mut heap: Heap = graph(9, &edges);
+            clear_marks(&mut heap);
+            let roots: Vec<usize> = scan_roots(&heap.cells);
+            let marking: Marking = mark(&mut heap.cells, roots, order, Some(2));
+
+            let mut visited: Vec<usize> = marking.visited.clone();
+            visited.sort();
+            assert_eq!(visited, vec![0, 1, 2, 3, 4, 5, 6]);
+            assert!(marking.rescans > 0);
+            assert!(marking.deepest <= 2);
+        }
+    }
+
+    #[test]
+    fn clears_marks_from_the_last_collection() {
+        let mut heap: Heap = graph(3, &[(0, 1), (1, 2)]);
+        assert_eq!(marked(&mut heap).1, vec![0, 1, 2]);
+
+        unlink_reference(&mut heap, 0, 1);
+        assert_eq!(marked(&mut heap).1, vec![0]);
+    }
+
+    #[test]
+    fn triggers_a_collection_on_cell_occupancy() {
+        //Every cell holds a single int, a quarter of its slot in bytes, but the heap is still full
+        let mut heap: Heap = Heap::with_seed(10, 0);
+        heap.log.echo = false;
+        heap.policy.gc_threshold = Some(80);
+        for data in 0..8 {
+            free_alloc(&mut heap, data, None).unwrap();
+            check_gc_threshold(&mut heap);
+        }
+        assert!(heap.history.is_empty());                                //80% isn't over the threshold
+        free_alloc(&mut heap, 8, None).unwrap();
+        check_gc_threshold(&mut heap);
+        assert_eq!((heap.history.len(), heap.live()), (1, 0));
+
+        //A cell with more references than fit in its slot still counts once
+        let heap: Heap = graph(5, &[(0, 1), (0, 2), (0, 3), (0, 4)]);
+        assert!(heap.bytes_used() > 0 && heap.cells[0].size() > sizes::CELL_BYTES);
+        assert_eq!(heap.occupancy(), 5 * 100 / 9);
+    }
+
+    #[test]
+    fn finds_cells_by_name() {
+        let mut heap: Heap = graph(3, &[]);
+        heap.log.echo = false;
+        assert_eq!(heap.find("head"), None);
+        heap.name_cell(1, Some("head")).unwrap();
+        assert_eq!(heap.find("head"), Some(1));
+        assert_eq!(heap.find("typo"), None);
+
+        //Names go with the data -> renaming frees the old name, freeing the cell drops it
+        heap.name_cell(1, Some("first")).unwrap();
+        assert_eq!((heap.find("head"), heap.find("first")), (None, Some(1)));
+        free_cell(&mut heap, 1, true).unwrap();
+        assert_eq!(heap.find("first"), None);
+    }
+
+    #[test]
+    fn refuses_names_that_could_not_be_told_apart() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        heap.name_cell(0, Some("head")).unwrap();
+
+        assert!(matches!(heap.name_cell(1, Some("head")), Err(NameError::Taken(0))));
+        for name in ["", "-x", "12"] {
+            assert!(matches!(heap.name_cell(1, Some(name)), Err(NameError::Invalid)));
+        }
+        assert!(matches!(heap.name_cell(2, Some("tail")), Err(NameError::DataIsFree)));
+        assert!(matches!(heap.name_cell(9, Some("tail")), Err(NameError::OutOfBounds)));
+        heap.name_cell(0, Some("head")).unwrap();                       //Naming a cell what it is already called is fine
+        heap.name_cell(0, None).unwrap();
+        assert_eq!((heap.find("head"), heap.cells[1].name.as_deref()), (None, None));
+    }
+
+    #[test]
+    fn overwrites_only_the_data_of_a_cell_in_use() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        assert_eq!(set_data(&mut heap, 1, -4).unwrap(), 1);
+        assert_eq!(heap.cells[1].data, Some(-4));
+        assert!(matches!(heap.log.since(0).last(), Some(Event::DataSet { index: 1, old: Some(1), new: -4 })));
+
+        free_cell(&mut heap, 1, false).unwrap();
+        assert_eq!(set_data(&mut heap, 1, 2).unwrap_err().kind, ErrorKind::UseAfterFree);
+        assert_eq!(set_data(&mut heap, 2, 2).unwrap_err().kind, ErrorKind::DataIsFree);
+        assert_eq!(set_data(&mut heap, 6, 2).unwrap_err().kind, ErrorKind::OutOfBounds);
+        assert!(heap.cells[1].is_poisoned() && heap.cells[2].data.is_none());
+    }
+
+    #[test]
+    fn frees_a_cell_in_use_along_with_the_references_to_it() {
+        let mut heap: Heap = graph(3, &[(0, 1), (1, 2)]);
+        heap.log.echo = false;
+
+        assert_eq!(free_cell(&mut heap, 1, false).unwrap(), 1);
+        assert!(heap.cells[1].freed && heap.cells[0].will_ref.is_empty() && heap.cells[2].by_ref.is_empty());
+        assert_eq!((heap.cells[0].reference_count, heap.cells[2].reference_count), (0, 0));
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn refuses_to_free_a_cell_that_is_already_free() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+
+        free_cell(&mut heap, 1, false).unwrap();
+        assert_eq!(free_cell(&mut heap, 1, false).unwrap_err().kind, ErrorKind::DoubleFree);
+        assert_eq!(free_cell(&mut heap, 3, true).unwrap_err().kind, ErrorKind::DoubleFree);     //Never used
+        assert_eq!(free_cell(&mut heap, 6, true).unwrap_err().kind, ErrorKind::OutOfBounds);
+    }
+
+    #[test]
+    fn sweeps_garbage_marked_by_an_earlier_collection() {
+        let mut heap: Heap = graph(3, &[(0, 1), (1, 2)]);
+        heap.log.echo = false;
+        collect(&mut heap);
+        assert!(heap.cells[1].marked && heap.cells[2].marked);
+
+        //Cells 1 and 2 are still marked from the first collection, but nothing reaches them any more
+        unlink_reference(&mut heap, 0, 1);
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed && !heap.cells[0].freed);
+        assert_eq!(heap.stats().mark_epoch, 2);
+    }
+
+    #[test]
+    fn refuses_cells_past_the_end_of_the_heap_without_panicking() {
+        let mut heap: Heap = graph(2, &[(0, 1)]);
+        heap.log.echo = false;
+        let seen: usize = heap.log.sequence();
+
+        let error: GcError = spec_alloc(&mut heap, 1, None, 6).unwrap_err();
+        assert_eq!((error.kind, error.operation, error.cell), (ErrorKind::OutOfBounds, Operation::Alloc, Some(6)));
+        assert_eq!(free_cell(&mut heap, 99, true).unwrap_err().kind, ErrorKind::OutOfBounds);
+        assign_reference(&mut heap, 0, 99);
+        unlink_reference(&mut heap, 99, 0);
+        configure_roots(&mut heap, roots::GLOBALS, &[99]);
+
+        //Each is only warned about, and the heap is as it was
+        assert_eq!(heap.log.since(seen).filter(|event| matches!(event, Event::Warning(_))).count(), 3);
+        assert_eq!((heap.cells.len(), heap.cells[0].will_ref.clone(), heap.roots()), (6, vec![1], vec![0]));
+    }
+
+    #[test]
+    fn leaves_a_cell_the_sweep_should_not_have_picked_alone() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        let (generation, seen) = (heap.cells[3].generation, heap.log.sequence());
+
+        assert!(!reclaim(&mut heap.cells, &mut heap.log, 3));            //Already free
+        assert!(!reclaim(&mut heap.cells, &mut heap.log, 0));            //A root
+        assert!(heap.cells[3].generation == generation && !heap.cells[0].freed);
+        let warnings: Vec<String> = heap.log.since(seen).map(|event| event.to_string()).collect();
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains(ErrorKind::DoubleFree.to_string().as_str()) && warnings[1].contains(ErrorKind::FreedRoot.to_string().as_str()));
+
+        assert!(reclaim(&mut heap.cells, &mut heap.log, 1) && heap.cells[1].is_poisoned());
+    }
+
+    #[test]
+    fn only_frees_a_referenced_root_when_forced() {
+        let mut heap: Heap = graph(2, &[(1, 0), (0, 1)]);
+        heap.log.echo = false;
+
+        assert_eq!(free_cell(&mut heap, 0, false).unwrap_err().kind, ErrorKind::FreedRoot);
+        assert!(!heap.cells[0].freed && heap.cells[1].will_ref == [0]);
+
+        free_cell(&mut heap, 0, true).unwrap();
+        assert!(heap.cells[0].freed && heap.cells[1].will_ref.is_empty() && heap.cells[1].by_ref.is_empty());
+        assert!(heap.roots().is_empty() && heap.root_sets().is_empty() && heap.verify().is_empty());
+    }
+
+    #[test]
+    fn only_collects_past_the_gc_threshold_when_one_is_set() {
+        let mut heap: Heap = graph(6, &[]);                             //6 of 10 cells in use, 5 of them garbage
+        heap.log.echo = false;
+        check_gc_threshold(&mut heap);
+        assert!(heap.history.is_empty());                               //Without a threshold collecting is left to the user
+        heap.policy.gc_threshold = Some(60);
+        check_gc_threshold(&mut heap);
+        assert!(heap.history.is_empty());
+
+        heap.policy.gc_threshold = Some(50);
+        let seen: usize = heap.log.sequence();
+        check_gc_threshold(&mut heap);
+        assert!(heap.log.since(seen).any(|event| matches!(event, Event::GcTriggered { occupancy: 60, threshold: 50 })));
+        assert_eq!((heap.history.len(), heap.live()), (1, 1));
+    }
+
+    #[test]
+    fn makes_the_same_heap_from_the_same_seed() {
+        let fill = |heap: &mut Heap| {
+            configure_roots(heap, roots::GLOBALS, &[0]);
+            create_free_ref(heap, 6);
+            populate_remaining(heap);
+            heap.cells.iter().map(|cell| (cell.data, cell.will_ref.clone())).collect::<Vec<_>>()
+        };
+        let build = |seed: u64| {
+            let mut heap: Heap = Heap::with_seed(12, seed);
+            heap.log.echo = false;
+            let filled = fill(&mut heap);
+            (heap, filled)
+        };
+        let (mut heap, first) = build(7);
+        assert_eq!(first, build(7).1);
+        assert_ne!(first, build(8).1);
+
+        //A reset starts the rng over, so the run can be repeated on the same heap
+        heap.reset(None);
+        assert_eq!((fill(&mut heap), heap.seed()), (first, 7));
+    }
+
+    #[test]
+    fn starts_the_pool_over_keeping_the_policies() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.policy.gc_threshold = Some(80);
+        let stale: handle::Handle = heap.handle(1).unwrap();
+        collect(&mut heap);
+
+        heap.reset(Some(5));
+        assert!(heap.cells.len() == 5 && heap.cells.iter().all(|cell| cell.freed && !cell.is_root()));
+        assert!(heap.history.is_empty() && heap.root_sets().is_empty() && heap.allocations == 0);
+        assert_eq!(heap.policy.gc_threshold, Some(80));
+
+        //A handle from before the reset is stale, even once its index is in use again
+        spec_alloc(&mut heap, 1, None, 1).unwrap();
+        assert!(heap.resolve(stale).is_err());
+        heap.reset(None);
+        assert_eq!(heap.cells.len(), 5);
+    }
+
+    #[test]
+    fn finds_which_roots_reach_a_cell_without_marking_it() {
+        let mut heap: Heap = graph(5, &[(0, 1), (1, 2), (3, 2), (4, 4)]);
+        heap.log.echo = false;
+        heap.add_root(roots::GLOBALS, 3).unwrap();
+        let marks: Vec<bool> = heap.cells.iter().map(|cell| cell.marked).collect();
+
+        assert_eq!(heap.reachable()[..5], [true, true, true, true, false]);
+        assert_eq!((heap.roots_reaching(2), heap.roots_reaching(1)), (vec![0, 3], vec![0]));
+        assert!(heap.roots_reaching(4).is_empty());                     //Referencing itself doesn't keep it alive
+        assert!(heap.roots_reaching(5).is_empty() && heap.roots_reaching(20).is_empty());
+        assert_eq!(heap.cells.iter().map(|cell| cell.marked).collect::<Vec<bool>>(), marks);
+    }
+
+    #[test]
+    fn explains_what_keeps_a_cell_alive_shortest_chain_first() {
+        let mut heap: Heap = graph(5, &[(0, 1), (1, 2), (2, 4), (0, 4), (3, 2)]);
+        heap.log.echo = false;
+        heap.add_root(roots::GLOBALS, 3).unwrap();
+
+        assert_eq!(heap.root_paths(4), [vec![0, 4], vec![3, 2, 4]]);
+        assert_eq!(heap.root_paths(0), [vec![0]]);
+        unlink_reference(&mut heap, 0, 1);
+        assert!(heap.root_paths(1).is_empty() && heap.root_paths(6).is_empty());
+    }
+}
@@ -0,0 +1,113 @@
+//Lists
+/*
+    A list is a cell whose references are its elements, in the order they were pushed. Because elements are
+    ordinary references, every collector already traces a list element by element (and the write barrier sees
+    every push and pop), and popping the last reference to an element leaves it as garbage like any other unlink.
+
+    A cell references another at most once, so a list holds each cell at most once too.
+*/
+
+use crate::{assign_reference, free_alloc, spec_alloc, unlink_reference, Heap, IndexResult, Kind};
+
+/// Reasons a list couldn't be changed
+#[derive(Debug)]
+pub enum ListError {
+    NotAList,           //There is no list at that index
+    ElementIsFree,      //Only cells in use can be pushed (there is nothing at a free cell, or outside the heap, to keep)
+    AlreadyInList,      //The cell is already one of the list's elements
+}
+
+impl Heap {
+    /// Allocates an empty list at `at` (or the first free cell).
+    /// Returns `Occupied` error if `at` is in use, or `NoFreeMemory` error if there are no free cells
+    pub fn list_new(&mut self, at: Option<usize>) -> IndexResult {
+        let index: usize = match at {
+            Some(at) => spec_alloc(self, 0, None, at)?,
+            None => free_alloc(self, 0, None)?,
+        };
+
+        self.make_list(index);
+        Ok(index)
+    }
+
+    /// The elements of the list at `index`, first pushed first, or None if there isn't a list there
+    pub fn list(&self, index: usize) -> Option<&[usize]> {
+        self.cells
+            .get(index)
+            .filter(|cell| !cell.freed && cell.kind == Kind::List)
+            .map(|cell| cell.will_ref.as_slice())
+    }
+
+    /// Pushes a cell onto the end of a list, returning how many elements the list has now
+    pub fn list_push(&mut self, list: usize, element: usize) -> Result<usize, ListError> {
+        let elements: &[usize] = self.list(list).ok_or(ListError::NotAList)?;
+        if elements.contains(&element) {
+            return Err(ListError::AlreadyInList);
+        }
+        if self.cells.get(element).is_none_or(|cell| cell.freed) {
+            return Err(ListError::ElementIsFree);
+        }
+
+        assign_reference(self, list, element);
+        Ok(self.cells[list].will_ref.len())
+    }
+
+    /// Pops the last element off a list, returning it (None if the list was empty).
+    /// Under reference counting the element is reclaimed straight away if nothing else references it
+    pub fn list_pop(&mut self, list: usize) -> Result<Option<usize>, ListError> {
+        let last: Option<usize> = self.list(list).ok_or(ListError::NotAList)?.last().copied();
+
+        if let Some(element) = last {
+            unlink_reference(self, list, element);
+        }
+        Ok(last)
+    }
+
+    //Turns a newly allocated cell into an empty list
+    pub(crate) fn make_list(&mut self, index: usize) {
+        self.cells[index].data = None;              //The elements are all a list holds
+        self.cells[index].kind = Kind::List;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, error::ErrorKind, tests::graph, Algorithm};
+
+    #[test]
+    fn holds_its_elements_in_the_order_they_were_pushed() {
+        let mut heap: Heap = graph(3, &[]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::RefCount;
+        let list: usize = heap.list_new(Some(4)).unwrap();
+        assign_reference(&mut heap, 0, list);
+
+        assert_eq!((heap.list_push(list, 2).unwrap(), heap.list_push(list, 1).unwrap()), (1, 2));
+        assert_eq!(heap.list(list), Some(&[2, 1][..]));
+        collect(&mut heap);
+        assert!(!heap.cells[1].freed && !heap.cells[2].freed);
+
+        //Popping the only reference to an element reclaims it straight away under reference counting
+        assert_eq!(heap.list_pop(list).unwrap(), Some(1));
+        assert!(heap.cells[1].freed && heap.list(list) == Some(&[2][..]));
+        heap.list_pop(list).unwrap();
+        assert_eq!(heap.list_pop(list).unwrap(), None);
+    }
+
+    #[test]
+    fn refuses_what_a_list_cannot_hold() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        let list: usize = heap.list_new(None).unwrap();
+        heap.list_push(list, 1).unwrap();
+
+        assert!(matches!(heap.list_push(list, 1), Err(ListError::AlreadyInList)));
+        assert!(matches!(heap.list_push(list, 4), Err(ListError::ElementIsFree)));
+        assert!(matches!(heap.list_push(list, 9), Err(ListError::ElementIsFree)));
+        assert!(matches!(heap.list_push(1, 0), Err(ListError::NotAList)));
+        assert!(matches!(heap.list_pop(0), Err(ListError::NotAList)));
+        assert_eq!(heap.list_new(Some(0)).unwrap_err().kind, ErrorKind::Occupied);
+        assert_eq!(heap.list(list), Some(&[1][..]));
+    }
+}
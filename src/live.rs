@@ -0,0 +1,311 @@
+//Live view of the heap over HTTP
+/*
+    For a demo at the terminal, a browser tab alongside it can draw the heap as it changes. `Heap::serve_live()` starts
+    a small HTTP server (on background threads, so the heap itself is never shared) that answers:
+
+        GET  /          -> a page drawing the heap as a force-directed graph, with a box to send commands from
+        GET  /state     -> the whole heap as JSON (the same as --export json), as of the latest publish
+        GET  /events    -> server-sent events, `event` for every event on the heap as it happens (its kind and message)
+                           and `state` with the whole heap again whenever a publish finds it changed
+        POST /command   -> the body is a line of commands, answered with whatever running them printed
+
+    The server can't run commands itself (it doesn't have the heap), so POSTed commands are handed over the channel
+    `serve_live()` returns, to whoever owns the heap -> they run it and `reply()` with the output. A request waits
+    up to COMMAND_TIMEOUT for that, in case nothing is reading the channel at the moment.
+
+    Anything that can reach the server can run commands on the heap, so it is kept to this machine:
+
+        it only listens on the loopback interface (127.0.0.1), never the network
+        a request has to name the server itself as its Host, and as its Origin if it has one -> a web page open in
+        the browser can't POST commands to it (or read the heap through a DNS rebinding)
+        bodies over MAX_BODY bytes are refused, and no more than MAX_CONNECTIONS are answered at once
+
+    Whoever runs the commands decides which of them the page is allowed (the REPL refuses any that touch files).
+*/
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde_json::json;
+
+use crate::{log::Event, observer::HeapObserver, Heap};
+
+/// How long a POSTed command waits to be run before giving up on it
+pub const COMMAND_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest line of commands that can be POSTed, in bytes
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// The most connections answered at once (each event stream holds one open), any more are turned away
+pub const MAX_CONNECTIONS: usize = 16;
+
+/// The page served at `/`
+const PAGE: &str = include_str!("live.html");
+
+/// Serves the live view, see the top of this module
+pub struct LiveServer {
+    pub address: SocketAddr,        //Where it is listening (on the loopback interface)
+    shared: Arc<Shared>,            //What the server threads answer with
+}
+
+/// A line of commands POSTed to `/command`, waiting to be run
+pub struct Command {
+    pub line: String,
+    reply: Sender<String>,          //The request waiting on the output
+}
+
+//Everything the server threads share with the heap
+struct Shared {
+    state: Mutex<String>,                   //The heap as JSON, as of the latest publish
+    clients: Mutex<Vec<Sender<String>>>,    //One per /events stream, each sent frames ready to be written out
+    commands: Mutex<Sender<Command>>,       //Where POSTed commands are handed over
+    address: SocketAddr,                    //Where the server is listening, the only Host (and Origin) it answers to
+    connections: AtomicUsize,               //Connections being answered right now
+}
+
+/// Hands every event to the /events streams, as it happens
+struct EventStream {
+    shared: Arc<Shared>,
+}
+
+impl Command {
+    /// Answers the request with the output of running the command
+    pub fn reply(self, output: String) {
+        let _ = self.reply.send(output);        //The client may have given up waiting, that's fine
+    }
+}
+
+impl LiveServer {
+    /// Binds to `port` on the loopback interface (0 for any free port) and starts answering requests.
+    /// Commands POSTed to `/command` arrive on the channel that is returned alongside the server
+    pub fn serve(port: u16) -> io::Result<(LiveServer, Receiver<Command>)> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        let address: SocketAddr = listener.local_addr()?;
+        let (commands, received) = mpsc::channel();
+        let shared: Arc<Shared> = Arc::new(Shared {
+            state: Mutex::new(String::new()),
+            clients: Mutex::new(Vec::new()),
+            commands: Mutex::new(commands),
+            address,
+            connections: AtomicUsize::new(0),
+        });
+
+        let server = Arc::clone(&shared);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if server.connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    server.connections.fetch_sub(1, Ordering::SeqCst);
+                    let _ = respond(stream, "503 Service Unavailable", "text/plain", "Too many connections, try again later\n");
+                    continue;
+                }
+                //Event streams stay open and commands wait to be run, so every connection gets a thread of its own
+                let shared = Arc::clone(&server);
+                thread::spawn(move || {
+                    let _ = answer(stream, &shared);    //A client hanging up early is its own problem
+                    shared.connections.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Ok((LiveServer { address, shared }, received))
+    }
+
+    /// Replaces the state that is served, sending it to every event stream if it has changed
+    pub fn publish(&self, heap: &Heap) {
+        let json: String = heap.to_json();
+        if let Ok(mut state) = self.shared.state.lock() {
+            if *state != json {
+                self.shared.broadcast(&frame("state", &json));
+                *state = json;
+            }
+        }
+    }
+}
+
+impl Shared {
+    //Sends a frame to every event stream, forgetting the ones that have closed
+    fn broadcast(&self, frame: &str) {
+        if let Ok(mut clients) = self.clients.lock() {
+            clients.retain(|client| client.send(frame.to_string()).is_ok());
+        }
+    }
+}
+
+impl HeapObserver for EventStream {
+    fn on_event(&mut self, event: &Event) {
+        let data = json!({ "kind": event.kind(), "message": event.to_string() });
+        self.shared.broadcast(&frame("event", &data.to_string()));
+    }
+}
+
+impl Heap {
+    /// Starts serving the live view on `port` (see the top of live.rs), returning where it is listening and where POSTed
+    /// commands arrive. Fails if the port can't be bound, or the live view is already being served
+    pub fn serve_live(&mut self, port: u16) -> io::Result<(SocketAddr, Receiver<Command>)> {
+        if let Some(server) = &self.live_server {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("already serving the live view at http://{}/", server.address),
+            ));
+        }
+
+        let (server, commands) = LiveServer::serve(port)?;
+        let address: SocketAddr = server.address;
+        self.observe(Box::new(EventStream { shared: Arc::clone(&server.shared) }));
+        self.live_server = Some(server);
+        self.publish();
+        Ok((address, commands))
+    }
+
+    /// Refreshes the state the live view serves (does nothing if it isn't running)
+    pub fn publish_live(&self) {
+        if let Some(server) = &self.live_server {
+            server.publish(self);
+        }
+    }
+}
+
+//A server-sent event, with every line of the data on a data: line of its own
+fn frame(name: &str, data: &str) -> String {
+    let mut frame = format!("event: {}\n", name);
+    for line in data.lines() {
+        frame.push_str(&format!("data: {}\n", line));
+    }
+    frame.push('\n');
+    frame
+}
+
+//Answers a single HTTP request, see the top of this module
+fn answer(stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    //The request line, i.e. "POST /command HTTP/1.1", then the headers (only the length of the body, and who it is from, matter)
+    let mut request: String = String::new();
+    reader.read_line(&mut request)?;
+    let (mut length, mut host, mut origin) = (0, None, None);
+    loop {
+        let mut header: String = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value: String = value.trim().to_string();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => length = value.parse().unwrap_or(0),
+                "host" => host = Some(value),
+                "origin" => origin = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    //Only pages served from here (or tools run on this machine) get an answer, see the top of this module
+    let local: Vec<String> = [format!("{}", shared.address), format!("localhost:{}", shared.address.port())].into();
+    let from_here: bool = host.is_some_and(|host| local.contains(&host))
+        && origin.is_none_or(|origin| local.iter().any(|local| origin == format!("http://{}", local)));
+    if !from_here {
+        return respond(stream, "403 Forbidden", "text/plain", "The live view only answers pages it served itself\n");
+    }
+
+    let mut words = request.split_whitespace();
+    match (words.next().unwrap_or(""), words.next().unwrap_or("")) {
+        ("GET", "/") => respond(stream, "200 OK", "text/html; charset=utf-8", PAGE),
+        ("GET", "/state") => match shared.state.lock() {
+            Ok(state) => respond(stream, "200 OK", "application/json", &state),
+            Err(_) => respond(stream, "500 Internal Server Error", "text/plain", "The heap's state is unavaliable\n"),
+        },
+        ("GET", "/events") => stream_events(stream, shared),
+        ("POST", "/command") if length > MAX_BODY => {
+            respond(stream, "413 Payload Too Large", "text/plain", &format!("Commands can be at most {} bytes\n", MAX_BODY))
+        }
+        ("POST", "/command") => {
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body)?;
+            let line: String = String::from_utf8_lossy(&body).trim().to_string();
+
+            match run(line, shared) {
+                Some(output) => respond(stream, "200 OK", "text/plain; charset=utf-8", &output),
+                None => respond(stream, "503 Service Unavailable", "text/plain", "The heap is busy, the command wasn't run\n"),
+            }
+        }
+        _ => respond(stream, "404 Not Found", "text/plain", "Try GET /, /state or /events, or POST /command\n"),
+    }
+}
+
+//Hands a command to whoever owns the heap and waits for its output, None if it isn't run in time
+fn run(line: String, shared: &Shared) -> Option<String> {
+    let (reply, output) = mpsc::channel();
+    shared.commands.lock().ok()?.send(Command { line, reply }).ok()?;
+    output.recv_timeout(COMMAND_TIMEOUT).ok()
+}
+
+//Keeps the connection open, writing out every frame broadcast from now on (starting with the current state)
+fn stream_events(mut stream: TcpStream, shared: &Shared) -> io::Result<()> {
+    let (client, frames) = mpsc::channel::<String>();
+    let state: String = shared.state.lock().map(|state| state.clone()).unwrap_or_default();
+    if let Ok(mut clients) = shared.clients.lock() {
+        clients.push(client);
+    }
+
+    write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n")?;
+    stream.write_all(frame("state", &state).as_bytes())?;
+    for frame in frames {
+        stream.write_all(frame.as_bytes())?;    //Fails once the tab is closed, which ends the stream
+    }
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, status: &str, content_type: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Sends a raw request to the server, returning the status line of the answer
+    fn send(address: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut status: String = String::new();
+        BufReader::new(stream).read_line(&mut status).unwrap();
+        status.trim().to_string()
+    }
+
+    #[test]
+    fn only_answers_requests_from_this_machine() {
+        let (server, _commands) = LiveServer::serve(0).unwrap();
+        let address: SocketAddr = server.address;
+        assert!(address.ip().is_loopback());
+
+        let host: String = format!("Host: {}\r\n", address);
+        assert_eq!(send(address, &format!("GET /state HTTP/1.1\r\n{}\r\n", host)), "HTTP/1.1 200 OK");
+        assert_eq!(send(address, "GET /state HTTP/1.1\r\nHost: evil.example\r\n\r\n"), "HTTP/1.1 403 Forbidden");
+        assert_eq!(send(address, "GET /state HTTP/1.1\r\n\r\n"), "HTTP/1.1 403 Forbidden");
+        let posted: String = format!("POST /command HTTP/1.1\r\n{}Origin: http://evil.example\r\nContent-Length: 6\r\n\r\n--gc\r\n", host);
+        assert_eq!(send(address, &posted), "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn refuses_bodies_over_the_limit() {
+        let (server, _commands) = LiveServer::serve(0).unwrap();
+        let request: String = format!("POST /command HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n", server.address, MAX_BODY + 1);
+        assert_eq!(send(server.address, &request), "HTTP/1.1 413 Payload Too Large");
+    }
+}
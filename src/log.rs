@@ -0,0 +1,477 @@
+//Event log for the virtual heap
+/*
+    Everything interesting that happens on the heap (allocations, references, the phases of a collection...)
+    is reported here as an Event, rather than each function printing its own messages.
+
+    The log decides what is worth showing based on its verbosity Level, and can optionally
+    tee every event out to a file so a session can be analysed after the fact.
+*/
+
+use alloc::collections::VecDeque;
+use core::{fmt, time::Duration};
+#[cfg(feature = "std")]
+use std::{
+    fs::File,
+    io::{self, Write},
+};
+
+#[cfg(feature = "std")]
+use crate::trace::Trace;
+use crate::{
+    breakpoints::Breakpoint,
+    finalize::RESURRECTED,
+    generational::CollectionKind,
+    observer::{self, HeapObserver},
+    MarkOrder,
+};
+use crate::prelude::*;
+
+/// How much the event log prints to the console.
+/// Ordered from least to most verbose, so `event.level() <= log.level` decides if an event is shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Quiet,              //Only warnings are shown
+    Info,               //Allocations, references, swept cells and collection summaries (default)
+    Debug,              //Everything, including the start and end of each GC phase
+}
+
+impl Level {
+    /// Parses a level from its name, i.e. `"debug"` -> `Level::Debug`
+    pub fn from_name(name: &str) -> Option<Level> {
+        match name {
+            "quiet" => Some(Level::Quiet),
+            "info" => Some(Level::Info),
+            "debug" => Some(Level::Debug),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", match self {
+            Level::Quiet => "quiet",
+            Level::Info => "info",
+            Level::Debug => "debug",
+        })
+    }
+}
+
+/// Something that happened on the virtual heap
+#[derive(Clone, Debug)]
+pub enum Event {
+    Alloc { index: usize, data: i32 },              //Data was stored in a free cell
+    RefCreated { from: usize, to: usize },          //`from` now references `to`
+    RefRemoved { from: usize, to: usize },          //`from` no longer references `to`
+    DataSet { index: usize, old: Option<i32>, new: i32 }, //The data stored in a cell was overwritten
+    Rooted { index: usize },                        //Cell was made a root
+    Unrooted { index: usize },                      //Cell is no longer a root
+    Named { index: usize, name: Option<String> },   //Cell was given a name (or had it removed)
+    Tagged { index: usize, tag: String, on: bool }, //Cell was given a tag (or had it taken off, see tags.rs)
+    GcTriggered { occupancy: usize, threshold: usize }, //A policy decided to run the collector
+    PeriodicGc { interval: Duration, time_to_safepoint: Duration }, //The periodic timer ran the collector, once every mutator had parked
+    CollectStart { number: usize },                 //A collection is beginning
+    MarkStart { roots: usize, order: MarkOrder },   //Mark phase is beginning from this many roots, visiting cells in this order
+    Marked { index: usize },                        //Cell was found to be live by a tracing collection
+    MarkOverflow { limit: usize, rescans: usize },  //The mark worklist (of `limit` cells) overflowed, and the heap was rescanned this many times
+    MarkEnd { marked: usize },                      //Mark phase finished, this many cells are being kept
+    BreakpointHit { breakpoint: Breakpoint },       //A collection paused at a breakpoint (see breakpoints.rs)
+    Swept { index: usize },                         //Cell was reclaimed by the sweep phase
+    Freed { index: usize },                         //Cell was freed by hand (--free)
+    Moved { from: usize, to: usize },               //Cell was relocated by a copying collection (or --move, --swap)
+    Survivors { copied: usize, to: usize, promoted: usize, early: usize }, //A minor collection copied its survivors into survivor space `to`, and promoted the rest
+    CollectionSet { regions: Vec<usize>, predicted: Duration, budget: Option<Duration> }, //A garbage first collection chose these regions to evacuate, most garbage first
+    CycleScan { candidates: usize, traced: usize, reclaimed: usize }, //The hybrid's tracer looked through this many cells from its candidates, reclaiming garbage cycles
+    FinalizerQueued { index: usize },               //A finalizable cell was found dead, and moved to the finalization queue (see finalize.rs)
+    Finalized { index: usize, resurrected: bool },  //A cell's finalizer ran, saving it somewhere reachable again if resurrected
+    WeakEntryDropped { key: usize, value: usize },  //The key of a weak map entry was found to be garbage, so the entry was dropped (see weakmap.rs)
+    FinalizerCycle { cells: Vec<usize> },           //Queued finalizable cells reference each other, so their finalizers have no order to run in
+    RcBatch { logged: usize, applied: usize },      //The buffered reference count updates were applied, this many of them once coalesced
+    EvacuationFailed { left: usize },               //A collection ran out of room to copy into, and left this many cells in use where they were
+    Relocated { moved: usize },                     //A relocation finished, having copied this many cells (see relocation.rs)
+    BarrierMiss { from: usize, to: usize, lost: bool }, //A minor collection found old `from` referencing young `to` without being remembered (and swept `to` if lost)
+    Collected {                                     //A full collection cycle finished
+        number: usize,
+        kind: CollectionKind,                       //Minor, major or full, for a generational collection
+        marked: usize,
+        swept: usize,
+        bytes: usize,                               //How many bytes the swept cells took up
+        root_scan: Duration,
+        mark: Duration,
+        sweep: Duration,
+    },
+    Reset { capacity: usize },                      //The heap was put back the way it was created
+    Grown { from: usize, to: usize },               //Free cells were added to the heap
+    Shrunk { from: usize, to: usize, bytes: usize }, //Free cells were released from the end of the heap, returning their bytes to the OS
+    FramePushed { depth: usize },                   //A stack frame was pushed, the stack is now this deep
+    FramePopped { depth: usize, locals: usize },    //The frame at this depth was popped, releasing this many locals
+    Warning(String),                                //Something went wrong, always shown
+}
+
+impl Event {
+    /// The least verbose level that this event is shown at
+    pub fn level(&self) -> Level {
+        match self {
+            Event::Warning(_) | Event::BarrierMiss { .. } => Level::Quiet,
+            Event::CollectStart { .. } | Event::MarkStart { .. } | Event::Marked { .. } | Event::MarkEnd { .. } => Level::Debug,
+            _ => Level::Info,
+        }
+    }
+
+    /// Short, machine friendly name for the kind of event (used in log files)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Event::Alloc { .. } => "alloc",
+            Event::RefCreated { .. } => "ref",
+            Event::RefRemoved { .. } => "unref",
+            Event::DataSet { .. } => "set",
+            Event::Rooted { .. } => "root",
+            Event::Unrooted { .. } => "unroot",
+            Event::Named { .. } => "name",
+            Event::Tagged { .. } => "tag",
+            Event::GcTriggered { .. } => "gc_trigger",
+            Event::PeriodicGc { .. } => "gc_periodic",
+            Event::CollectStart { .. } => "gc_start",
+            Event::MarkStart { .. } => "mark_start",
+            Event::Marked { .. } => "marked",
+            Event::MarkOverflow { .. } => "mark_overflow",
+            Event::MarkEnd { .. } => "mark_end",
+            Event::BreakpointHit { .. } => "break",
+            Event::Swept { .. } => "swept",
+            Event::Freed { .. } => "free",
+            Event::Moved { .. } => "moved",
+            Event::Survivors { .. } => "survivors",
+            Event::BarrierMiss { .. } => "barrier_miss",
+            Event::Relocated { .. } => "relocated",
+            Event::EvacuationFailed { .. } => "evacuation_failed",
+            Event::RcBatch { .. } => "rc_batch",
+            Event::FinalizerQueued { .. } => "finalizer_queued",
+            Event::Finalized { .. } => "finalized",
+            Event::FinalizerCycle { .. } => "finalizer_cycle",
+            Event::WeakEntryDropped { .. } => "weak_entry_dropped",
+            Event::CycleScan { .. } => "cycle_scan",
+            Event::CollectionSet { .. } => "collection_set",
+            Event::Collected { .. } => "gc",
+            Event::Reset { .. } => "reset",
+            Event::Grown { .. } => "grow",
+            Event::Shrunk { .. } => "shrink",
+            Event::FramePushed { .. } => "push_frame",
+            Event::FramePopped { .. } => "pop_frame",
+            Event::Warning(_) => "warning",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::Alloc { index, data } => write!(f, "Cell at position {} was used (data: {})", index, data),
+            Event::RefCreated { from, to } => write!(f, "Cell {} now references cell {}", from, to),
+            Event::RefRemoved { from, to } => write!(f, "Cell {} no longer references cell {}", from, to),
+            Event::DataSet { index, old: Some(old), new } => write!(f, "Cell {} data changed from {} to {}", index, old, new),
+            Event::DataSet { index, old: None, new } => write!(f, "Cell {} data set to {}", index, new),
+            Event::Rooted { index } => write!(f, "Cell {} is now a root", index),
+            Event::Unrooted { index } => write!(f, "cell {} unrooted", index),
+            Event::Named { index, name: Some(name) } => write!(f, "Cell {} is now named '{}'", index, name),
+            Event::Named { index, name: None } => write!(f, "Cell {} is no longer named", index),
+            Event::Tagged { index, tag, on: true } => write!(f, "Cell {} is now tagged '{}'", index, tag),
+            Event::Tagged { index, tag, on: false } => write!(f, "Cell {} is no longer tagged '{}'", index, tag),
+            Event::GcTriggered { occupancy, threshold } => write!(
+                f,
+                "Heap occupancy ({}%) exceeds the {}% threshold, running the garbage collector...",
+                occupancy, threshold
+            ),
+            Event::PeriodicGc { interval, time_to_safepoint } => write!(
+                f,
+                "Periodic collection (every {:?}), the world stopped after {:?}, running the garbage collector...",
+                interval, time_to_safepoint
+            ),
+            Event::CollectStart { number } => write!(f, "GC #{} starting", number),
+            Event::MarkStart { roots, order } => write!(f, "Mark phase started from {} root(s), visiting cells {}", roots, match order {
+                MarkOrder::Dfs => "depth first (dfs)",
+                MarkOrder::Bfs => "breadth first (bfs)",
+            }),
+            Event::Marked { index } => write!(f, "Cell {} is live", index),
+            Event::MarkOverflow { limit, rescans } => write!(
+                f,
+                "Mark stack overflowed its {} cell(s), the heap was rescanned {} time(s) for cells marked but not scanned",
+                limit, rescans
+            ),
+            Event::MarkEnd { marked } => write!(f, "Mark phase finished, {} cell(s) marked", marked),
+            Event::BreakpointHit { breakpoint: Breakpoint::MarkPhase } => write!(f, "Breakpoint on-mark-phase hit, the mark phase is about to begin"),
+            Event::BreakpointHit { breakpoint: Breakpoint::Sweep(index) } => {
+                write!(f, "Breakpoint on-sweep {} hit, cell {} is about to be swept", index, index)
+            }
+            Event::Swept { index } => write!(f, "Cell {} was freed, and is now ready for use again", index),
+            Event::Freed { index } => write!(f, "Cell {} was freed by hand, and every reference to it removed", index),
+            Event::Moved { from, to } => write!(f, "Cell {} was moved to cell {}", from, to),
+            Event::CollectionSet { regions, .. } if regions.is_empty() => write!(f, "Collection set is empty, no region has garbage (that there is room to evacuate)"),
+            Event::CollectionSet { regions, predicted, budget } => {
+                let regions: Vec<String> = regions.iter().map(|region| region.to_string()).collect();
+                write!(f, "Collection set: region(s) {} (most garbage first), predicted pause {:?}", regions.join(", "), predicted)?;
+                match budget {
+                    Some(budget) => write!(f, " of a {:?} budget", budget),
+                    None => Ok(()),
+                }
+            }
+            Event::CycleScan { candidates, traced, reclaimed } => write!(
+                f,
+                "Cycle tracer: {} cell(s) reachable from {} candidate cycle root(s), {} of them reclaimed in garbage cycles",
+                traced, candidates, reclaimed
+            ),
+            Event::FinalizerQueued { index } => write!(f, "Cell {} is garbage, but has a finalizer -> moved to the finalization queue, which keeps it (and what it references) alive for this collection", index),
+            Event::Finalized { index, resurrected: true } => write!(f, "Ran the finalizer of cell {}, which resurrected it (saved in the '{}' root set)", index, RESURRECTED),
+            Event::WeakEntryDropped { key, value } => write!(f, "Cell {} is garbage, so its weak map entry was dropped -> value cell {} is no longer kept alive by it", key, value),
+            Event::FinalizerCycle { cells } => {
+                let cells: Vec<String> = cells.iter().map(|index| index.to_string()).collect();
+                write!(f, "Cells {} are finalizable and reference each other (a cycle), so none has to go first -> their finalizers run in index order", cells.join(", "))
+            }
+            Event::Finalized { index, resurrected: false } => write!(f, "Ran the finalizer of cell {}, the next collection reclaims it if it is still garbage", index),
+            Event::RcBatch { logged, applied } => write!(
+                f,
+                "Applied a batch of {} reference count update(s) as {} once coalesced ({} elided)",
+                logged, applied, logged - applied
+            ),
+            Event::EvacuationFailed { left } => write!(
+                f,
+                "Evacuation failed: there was no room left to copy {} cell(s) into, so they were left where they are (the collection was partial)",
+                left
+            ),
+            Event::Relocated { moved } => write!(f, "Relocation finished, {} cell(s) copied. Their forwarding stubs are free again", moved),
+            Event::BarrierMiss { from, to, lost: true } => write!(
+                f,
+                "Barrier miss: old cell {} references young cell {} but wasn't in the remembered set, so the minor collection swept cell {} while it was in use",
+                from, to, to
+            ),
+            Event::BarrierMiss { from, to, lost: false } => write!(
+                f,
+                "Barrier miss: old cell {} references young cell {} but wasn't in the remembered set (cell {} survived, as something else keeps it alive)",
+                from, to, to
+            ),
+            Event::Survivors { copied, to, promoted, early: 0 } => {
+                write!(f, "{} survivor(s) copied into survivor space {}, {} promoted to the tenured space", copied, to, promoted)
+            }
+            Event::Survivors { copied, to, promoted, early } => write!(
+                f,
+                "{} survivor(s) copied into survivor space {}, {} promoted to the tenured space ({} early, as survivor space {} was full)",
+                copied, to, promoted, early, to
+            ),
+            Event::Collected { number, kind, marked, swept, bytes, root_scan, mark, sweep } => write!(
+                f,
+                "GC #{}{}: marked {}, swept {} ({} bytes), root scan {}µs, mark {}µs, sweep {}µs",
+                number,
+                match kind {
+                    CollectionKind::Whole => String::new(),
+                    kind => format!(" ({})", kind),
+                },
+                marked,
+                swept,
+                bytes,
+                root_scan.as_micros(),
+                mark.as_micros(),
+                sweep.as_micros(),
+            ),
+            Event::Reset { capacity } => write!(f, "Heap reset to {} free cells", capacity),
+            Event::Grown { from, to } => write!(f, "Heap grown from {} to {} cells", from, to),
+            Event::Shrunk { from, to, bytes } => write!(f, "Heap shrunk from {} to {} cells, returning {} bytes to the OS", from, to, bytes),
+            Event::FramePushed { depth } => write!(f, "Pushed frame #{}", depth),
+            Event::FramePopped { depth, locals } => write!(f, "Popped frame #{}, releasing {} local(s)", depth, locals),
+            Event::Warning(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+/// How many of the most recent events the log holds on to (for displays such as the TUI)
+const RECENT_EVENTS: usize = 1000;
+
+/// Collects the events of a heap, printing those at or below its verbosity level.
+/// If a file is attached, EVERY event is also written there regardless of level, one per line:
+/// ```text
+/// <sequence number>    <kind>    <message>
+/// ```
+pub struct EventLog {
+    pub level: Level,               //How much is printed to the console
+    pub echo: bool,                 //Print events to the console? (turned off while something else draws the screen, and without std there is no console)
+    #[cfg(feature = "std")]
+    file: Option<File>,             //Optional file that every event is tee'd to
+    #[cfg(feature = "std")]
+    trace: Option<Trace>,           //Optional Chrome trace that every event is recorded in
+    sequence: usize,                //How many events have been emitted so far
+    warnings: usize,                //How many of them were warnings
+    recent: VecDeque<(usize, Event)>, //The latest events (and their sequence numbers), oldest first
+    observers: Vec<Box<dyn HeapObserver + Send>>, //Told about every event as it happens (see observer.rs)
+}
+
+impl EventLog {
+    //Creates a log at the default (info) level, with no file attached
+    pub fn new() -> EventLog {
+        EventLog {
+            level: Level::Info,
+            echo: true,
+            #[cfg(feature = "std")]
+            file: None,
+            #[cfg(feature = "std")]
+            trace: None,
+            sequence: 0,
+            warnings: 0,
+            recent: VecDeque::new(),
+            observers: Vec::new(),
+        }
+    }
+
+    /// Records an event -> prints it if the verbosity allows, and tees it to the log file (if there is one)
+    pub fn emit(&mut self, event: Event) {
+        self.sequence += 1;
+        if let Event::Warning(_) | Event::BarrierMiss { .. } = event {
+            self.warnings += 1;
+        }
+
+        #[cfg(feature = "std")]
+        if self.echo && event.level() <= self.level {
+            println!("{}", event);
+        }
+
+        for observer in self.observers.iter_mut() {
+            observer::notify(observer.as_mut(), &event);
+        }
+
+        let failed: Option<&str> = self.tee(&event);
+
+        //Keep hold of the latest events, dropping the oldest once full
+        if self.recent.len() == RECENT_EVENTS {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((self.sequence, event));
+
+        if let Some(what) = failed {
+            self.emit(Event::Warning(format!("Unable to write to the {}, it has been detached", what)));
+        }
+    }
+
+    //Writes an event to the log file and the trace (if they are attached), returning which of them failed.
+    //A failing log file (or trace) shouldn't take the heap down with it, so it is detached to carry on without it
+    #[cfg(feature = "std")]
+    fn tee(&mut self, event: &Event) -> Option<&'static str> {
+        let mut failed: Option<&str> = None;
+        if let Some(file) = &mut self.file {
+            if writeln!(file, "{}\t{}\t{}", self.sequence, event.kind(), event).is_err() {
+                self.file = None;
+                failed = Some("log file");
+            }
+        }
+        if let Some(trace) = &mut self.trace {
+            if trace.record(event).is_err() {
+                self.trace = None;
+                failed = Some("trace file");
+            }
+        }
+        failed
+    }
+
+    //Without std there are no files to write to
+    #[cfg(not(feature = "std"))]
+    fn tee(&mut self, _event: &Event) -> Option<&'static str> {
+        None
+    }
+
+    /// Registers an observer, which is told about every event from now on
+    pub fn observe(&mut self, observer: Box<dyn HeapObserver + Send>) {
+        self.observers.push(observer);
+    }
+
+    /// Sequence number of the latest event (0 if nothing has happened yet)
+    pub fn sequence(&self) -> usize {
+        self.sequence
+    }
+
+    /// How many warnings have been emitted so far (something asked of the heap went wrong)
+    pub fn warnings(&self) -> usize {
+        self.warnings
+    }
+
+    /// The latest events that were emitted after sequence number `after`, oldest first.
+    /// Only the most recent events are held on to, so older ones may no longer be avaliable
+    pub fn since(&self, after: usize) -> impl Iterator<Item = &Event> {
+        self.recent
+            .iter()
+            .filter(move |(sequence, _)| *sequence > after)
+            .map(|(_, event)| event)
+    }
+
+    /// Starts teeing every event to the file at `path` (the file is created, or truncated if it already exists)
+    #[cfg(feature = "std")]
+    pub fn attach_file(&mut self, path: &str) -> io::Result<()> {
+        self.file = Some(File::create(path)?);
+        Ok(())
+    }
+
+    /// Stops teeing events to a file
+    #[cfg(feature = "std")]
+    pub fn detach_file(&mut self) {
+        self.file = None;
+    }
+
+    /// Starts recording every event to a Chrome trace at `path`, finishing any trace already in progress
+    #[cfg(feature = "std")]
+    pub fn attach_trace(&mut self, path: &str) -> io::Result<()> {
+        self.detach_trace()?;
+        self.trace = Some(Trace::create(path)?);
+        Ok(())
+    }
+
+    /// Finishes the current Chrome trace (if there is one), so the file is complete
+    #[cfg(feature = "std")]
+    pub fn detach_trace(&mut self) -> io::Result<()> {
+        match self.trace.take() {
+            Some(trace) => trace.finish(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> EventLog {
+        EventLog::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shows_each_event_from_its_level_up() {
+        assert!(Level::Quiet < Level::Info && Level::Info < Level::Debug);
+        assert_eq!((Level::from_name("debug"), Level::from_name("loud")), (Some(Level::Debug), None));
+        assert_eq!(Event::Warning(String::new()).level(), Level::Quiet);
+        assert_eq!(Event::Alloc { index: 0, data: 1 }.level(), Level::Info);
+        assert_eq!(Event::Marked { index: 0 }.level(), Level::Debug);
+    }
+
+    #[test]
+    fn keeps_every_event_whatever_it_shows() {
+        let mut log: EventLog = EventLog::new();
+        (log.echo, log.level) = (false, Level::Quiet);
+        log.emit(Event::Marked { index: 3 });
+        log.emit(Event::Warning("full".to_string()));
+
+        assert_eq!((log.sequence(), log.warnings()), (2, 1));
+        assert_eq!(log.since(1).map(Event::kind).collect::<Vec<_>>(), ["warning"]);
+        assert_eq!(log.since(0).count(), 2);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn writes_every_event_to_the_log_file() {
+        let path = std::env::temp_dir().join(format!("gc-rust-log-{}.txt", std::process::id()));
+        let mut log: EventLog = EventLog::new();
+        (log.echo, log.level) = (false, Level::Quiet);
+        log.attach_file(path.to_str().unwrap()).unwrap();
+        log.emit(Event::Marked { index: 3 });
+        log.detach_file();
+
+        let written: String = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(written, format!("1\tmarked\t{}\n", Event::Marked { index: 3 }));
+    }
+}
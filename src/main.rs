@@ -1,343 +1,294 @@
 /*
-    This is a barebones "virtual" garbage collector (mark and sweep) for Rust, demonstrating the
-    advantages of Rust's ownership and borrowing features compared against a garbage collector implementation.
-
-    What does a garbage collector do?
-        - Manages memory for us semi-automatically (using explicit rules)
-        - It tracks which objects are still being used and referenced
-        - It reclaims memory from objects that no longer accessible
-        - All done automatically without explicitly freeing memory manually
-
-    This is a functional, albeit very small garbage collector that manages memory 
-    through the use of a memory pool (implemented as a Vec<Cell>). It tracks which memory 
-    is in use and reclaims memory that is no longer referenced. However, think of this more 
-    as an educational demonstration than a standalone working implementation of a 
-    mark-and-sweep garbage collector, as this GC operates on top of Rust's memory management 
-    rather than directly managing allocations to the physical heap. Instead, we abstract 
-    this away and use a Vector as a "virtual heap."
-
-    It is mostly a demonstration that operates within Rust, with controlled memory and
-    only with a primitive data type (i32).
-
-    This is a essentially a working memory management system that is existing alongside
-    Rust's already established memory system. This garabage collector manages its own
-    'universe' of memory (Vec<Cell>)
-
-    Authored by Jarred Jenkins
-    https://github.com/jerdbeenbo
+    Interactive front end for the gc-rust virtual heap.
+
+    Reads commands from the user (i.e. --root, --arb_ref, --gc) and runs them against
+    a heap created by the gc-rust library, printing the results as it goes.
 */
 
-//TODO: Need to update references to support a DFS Mark traversal system
+mod prompt;
+#[cfg(feature = "tui")]
+mod tui;
 
 //For collecting arguments from the user
+use clap::Parser;
+use gc_rust::{breakpoints::Breakpoint, diff::HeapDiff, lang::{self, Interpreter}, live::Command, dominators::DominatorTree, error::ErrorKind, finalize, tags::TagError, named_regions::{NamedRegion, NamedRegionError, Occupancy}, frames::{Frame, FrameError}, g1::Region, generational::{CollectionKind, Space}, histogram::{Dimension, Histogram}, lists::ListError, records::{Record, RecordError}, log::{Event, Level}, mmu::{Utilization, DEFAULT_WINDOWS}, observer::HeapObserver, ownership::{Comparison, Run}, profile::{Profile, Tradeoff}, pressure::PressureReport, query::Query, roots::GLOBALS, snapshot::Checkpoint, safepoint::{Idle, Mutator, Safepoint, StoppedWorld}, shared::SharedHeap, scenario::{Built, Shape}, sites::SiteProfile, stress::StressReport, treadmill::Colour, verify::Violation, *};
+use prompt::{Completions, Prompt};
 use rand::prelude::*;
-use std::{collections::VecDeque, io::{self}, vec};
+use std::{fs, io::{self, IsTerminal, Write}, net::{IpAddr, Ipv4Addr}, path::PathBuf, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc}, thread, time::{Duration, Instant}};
+
+/// Writes a line of command output to `out` -> stdout at the prompt, or the log panel in the TUI.
+/// Failing to write to the console isn't worth stopping the program over, so the result is ignored
+macro_rules! respond {
+    ($out:expr) => {{
+        let _ = writeln!($out);
+    }};
+    ($out:expr, $($arg:tt)*) => {{
+        let _ = writeln!($out, $($arg)*);
+    }};
+}
 
-//Structures
-/// #### The 'Virtual Heap' is a collection of these Cell structures.
-/// A cell of memory that will be stored in a vector -> making up a greater "memory pool"
-#[derive(Clone)]
-struct Cell {
-    data: Option<i32>, //Actual data within the memory pool...
-    //  ...stored as an option as the default data value should be None
-    reference_count: i32,           //Is this object still being referenced? (amount of references)
-    freed: bool,                    //False || in use (referenced), True || not in use (de-referenced)
-    is_root: bool,                  //Declares whether or not this is a root (static) entrance variable
-    by_ref: Vec<usize>,             //Determins what cell(s) reference this cell
-    will_ref: Vec<usize>,           //The index of a cell this cell calls reference to
-    marked: bool,                   //Flag to signal if the cell has been marked for keeping. Any cell that is not marked will be sweeped
+/// Like `respond!`, for when a command couldn't do what was asked.
+/// Also counts the failure, so the process exits with `EXIT_FAILED` once the session ends
+macro_rules! fail {
+    ($out:expr, $($arg:tt)*) => {{
+        FAILURES.fetch_add(1, Ordering::Relaxed);
+        respond!($out, $($arg)*);
+    }};
 }
 
-///Implementation for a Cell
-impl Cell {
-    //Creates a new cell with default values
-    fn new() -> Cell {      //called with Cell::new()
-        Cell {
-            data: None,                 //Cell starts with no data
-            reference_count: 0,         //Cell starts with no references
-            freed: true,                //Cell starts as free, avaliable for use
-            is_root: false,             //By default, cell is not a root
-            by_ref: Vec::new(),         //This cell is referenced by
-            will_ref: Vec::new(),       //References None cell
-            marked: false,              //If the cell has been marked for keeping. Any cell that is not marked will be sweeped
+static FAILURES: AtomicUsize = AtomicUsize::new(0);    //Commands that failed this session, see `fail!`
+
+/// Counts every warning the heap reports as a failure too, for the exit code
+struct FailureCounter;
+
+impl HeapObserver for FailureCounter {
+    fn on_event(&mut self, event: &Event) {
+        if let Event::Warning(_) | Event::BarrierMiss { .. } = event {
+            FAILURES.fetch_add(1, Ordering::Relaxed);
         }
     }
+}
 
-    //Takes parameter &mut self to allow it to be called on cells[n].make_root
-    fn make_root(&mut self) {
-        self.is_root = true;
-        self.marked = true;
-        self.freed = false;
-    }
+/// Startup options, i.e. `gc-rust --heap-size 100 --algorithm copying --script demo.txt`
+#[derive(Parser)]
+#[command(version, about = "An interactive garbage collector demonstration, running on a virtual heap")]
+struct Args {
+    /// Amount of cells in the memory pool
+    #[arg(long, value_name = "N", default_value_t = 20, value_parser = parse_heap_size)]
+    heap_size: usize,
+
+    /// Garbage collection algorithm: mark-sweep, copying, rc, generational, treadmill, g1, buffered-rc or hybrid
+    #[arg(long, default_value = "mark-sweep", value_parser = parse_algorithm)]
+    algorithm: Algorithm,
+
+    /// Seed for the random data and references, so a run can be reproduced exactly
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Run the commands in this file (one per line, # for comments) before the prompt
+    #[arg(long, value_name = "FILE")]
+    script: Option<PathBuf>,
+
+    /// Tune the collection policy for throughput or low-latency
+    #[arg(long, value_parser = parse_profile)]
+    profile: Option<Profile>,
+}
 
-    fn is_root(&self) -> bool {
-        self.is_root
+//The pool needs at least one cell, as commands default to the last cell
+fn parse_heap_size(value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(String::from("expected a number of cells, greater than 0")),
     }
 }
 
-///Enum to define error behaviour
-#[derive(Debug)]
-enum AllocError {
-    Occupied,           //Target space is occupied
-    NoFreeMemory,       //No free space was found to allocate memory
-    DataIsFree,         //The data is not in use, cannot mutate it
+fn parse_algorithm(value: &str) -> Result<Algorithm, String> {
+    Algorithm::from_name(value).ok_or_else(|| format!("expected one of: {}", Algorithm::NAMES.join(", ")))
 }
 
-///Index Result which is the return type for allocation functions
-/// Either, it was successful and it returns the index position <usize>
-/// Otherwise, it was unsuccessful -> where we return an Allocation Error specified enum above.
-type IndexResult = Result<usize, AllocError>;
-
-/// Macro to abstract away what allocation function to actually use, just pass in parameters and the macro will decide which arm to match
-/// Allocates memory in the memory pool with different patterns:
-///
-/// # Patterns
-///
-/// ## Pattern 0: Just data
-/// ```
-/// malloc!(cells, data)
-/// ```
-/// Allocates data in the first available cell with no references.
-/// This value would be swept by the garbage collector if unreferenced.
-///
-/// ## Pattern 1: Automatic free allocation
-/// ```
-/// malloc!(cells, data, reference_to)
-/// ```
-/// Allocates data with a reference to another cell.
-///
-/// ## Pattern 2: Specific allocation
-/// ```
-/// malloc!(cells, data, reference, pos)
-/// ```
-/// Allocates data at a specific position with a reference to another cell.
-///
-/// # Arguments
-///
-/// * `cells` - A mutable reference to the memory pool vector
-/// * `data` - The value to store in the cell
-/// * `reference_to` - Optional reference to another cell index
-/// * `pos` - Optional specific position to allocate at
-///
-/// # Returns
-///
-/// * `IndexResult` - Result containing either the allocated index or an allocation error
-///
-/// # Examples
-///
-/// ```
-/// // Allocate data with no references
-/// let index = malloc!(cells, 42);
-///
-/// // Allocate data with a reference to cell at index 0
-/// let index = malloc!(cells, 42, Some(0));
-///
-/// // Allocate data at position 5 with a reference to cell at index 0
-/// let index = malloc!(cells, 42, Some(0), 5);
-/// ```
-macro_rules! malloc {
-    // Pattern 0 Just data - find first available cell with no reference
-    ($cells:expr, $data:expr) => {
-        free_alloc($cells, $data, None)   //Allocate data in memory that has no references
-                                                //... this value would be sweeped by the garbage collector
-    };
-
-    //Pattern 1 (Automatic, first free-allocation)
-    ($cells:expr, $data:expr, $reference_to:expr) => {
-        //Three parameters, call free_alloc
-        free_alloc($cells, $data, $reference_to)
-    };
-
-    //Pattern 2 (specific-allocation)
-    ($cells:expr, $data:expr, $reference:ident, $pos:expr) => {
-        //Four parameters, call spec_alloc
-        spec_alloc($cells, $data, $reference, $pos)
-    };
-}
-
-///Run once at the start during of the program to create a memory pool "The Virtual Heap" ->
-///which is essentially just a Vec of Cell, with size n specified when the function is called.
-fn init_pool(size: usize) -> Vec<Cell> {
-    //Create instance of a default cell
-    let default_cell = Cell::new();
-
-    //Set up memory pool with just default implementations of cells
-    let cells: Vec<Cell> = vec![default_cell; size];
-
-    cells //Return cells
-}
-
-///Searches through the cells vec and finds a cell that is not in use, and assigns it the memory that is requested
-///to be stored here. (At this stage, only supports storing `i32` primitive values)
-///Return an index that points to the location in memory that the data is stored.
-///Takes a mutable reference to the memory pool so it can update and iterate on it.
-fn free_alloc(cells: &mut Vec<Cell>, req_data: i32, ref_to: Option<usize>) -> IndexResult {    
-    
-    //Find first avaliable cell to be used
-    for i in 0..cells.len() {
-        if cells[i].freed == true {
-            //Store the data at the index position i
-            cells[i] = Cell {
-                data: Some(req_data),
-                reference_count: 1,
-                freed: false,
-                is_root: false,
-                by_ref: vec![],                     //Initially, no cells will reference this cell
-                will_ref: if ref_to.is_some() {
-                    vec![ref_to.unwrap()]           //Reference was provided at allocation            
-                }
-                else {
-                    vec![]                          //Empty vector, no reference was provided at allocation
-                },                                          
-                marked: false,
-            };
+fn parse_profile(value: &str) -> Result<Profile, String> {
+    Profile::from_name(value).ok_or_else(|| format!("expected one of: {}", Profile::NAMES.join(", ")))
+}
 
-            return Ok(i); //If successful, return index I as position stored
-        }
-    }
-    Err(AllocError::NoFreeMemory) //-> Retern no free memory as an error
+/// Settings that belong to this session at the prompt, rather than to the heap itself
+struct Session {
+    color: bool,                    //Use ANSI colours in output? (--no-color turns this off for piping)
+    interactive: bool,              //Is someone typing the commands? (rather than a piped or scripted run)
+    confirm_exit: bool,             //--exit was held back because of garbage, so the next --exit goes ahead
+    watches: Vec<usize>,            //Cells whose events are reported after each command, from --watch
+    timeline: Vec<Step>,            //The heap after each command of the session, oldest first (see --history)
+    position: usize,                //Where in the timeline the heap is now (the last step, unless --goto went back)
+    commands: Option<mpsc::Receiver<Command>>, //Commands sent from the live view's page, once --serve has started it
+    interpreter: Interpreter,       //Runs --eval, keeping its variables between statements
+    site: Option<String>,           //Label allocations are tagged with, from --site (otherwise the command that made them)
 }
 
-/// Allocates at a specific memory position.
-/// #### Params
-/// ```
-/// cells: &mut Vec<Cell> //-> a mutable reference to the virtual heap
-/// req_data: i32 //-> requesting data to be store in the pos parsed
-/// reference: Option<usize> //-> Optionally choose a cell that this cell will reference
-/// store_pos: usize //-> what memory cell position will it be stored on?
-/// ```
-/// 
-/// Returns `Occupied` error if you try to write over data that is already stored in memory in the requested position.
-fn spec_alloc(cells: &mut Vec<Cell>, req_data: i32, reference: Option<usize>, store_pos: usize) -> IndexResult {
-   
-   let mut ref_amt: i32;
-   //derive reference amt
-   if reference.is_some() {
-        ref_amt = 1;
-   } else {
-        ref_amt = 0;
-   }
-    
-    //check if memory is allocated
-    if cells[store_pos].freed == true {
-        //the memory is free for use
-        //store the data
-        cells[store_pos] = Cell {
-            data: Some(req_data),
-            reference_count: ref_amt,
-            freed: false,
-            is_root: false,
-            will_ref: if reference.is_some() {
-                vec![reference.unwrap()]            //Reference was provided at allocation
-            } else {
-                vec![]                              //No reference was provided at allocation
-            },
-            by_ref: vec![],                         //Start with no cell referencing this cell
-            marked: false,
-        };
+/// The heap as one command of the session left it, see `record_step()`
+struct Step {
+    number: usize,                  //Counted from 0 (the heap before the first command), and never reused
+    command: String,
+    checkpoint: Checkpoint,
+}
 
-        return Ok(store_pos);
+//How many steps the timeline holds on to, the oldest are dropped after that
+const TIMELINE_STEPS: usize = 500;
+
+
+impl Session {
+    //Colour is on by default, unless stdout is being piped somewhere
+    fn new() -> Session {
+        Session {
+            color: io::stdout().is_terminal(),
+            interactive: io::stdin().is_terminal(),
+            confirm_exit: false,
+            watches: Vec::new(),
+            timeline: Vec::new(),
+            position: 0,
+            commands: None,
+            interpreter: Interpreter::new(),
+            site: None,
+        }
     }
 
-    Err(AllocError::Occupied) //Return none as the memory position is not free, handle this by freeing pos at call
+    //What the process exits with when the session ends without an explicit code
+    fn exit_code(&self) -> i32 {
+        if FAILURES.load(Ordering::Relaxed) == 0 {
+            EXIT_OK
+        } else {
+            EXIT_FAILED
+        }
+    }
 }
 
-/// Frees the data at the pointer index position
-/// by deleting the stored information there, and replaces it with a default cell value
-fn free(cells: &mut Vec<Cell>, pointer: usize) {
-    cells[pointer] = Cell::new(); //Use new impl for cell to create a default cell (default state for a free cell awaiting assignment)
+//Process exit codes, for scripted use
+const EXIT_OK: i32 = 0;             //Every command ran
+const EXIT_FAILED: i32 = 1;         //At least one command failed (unknown, or the heap reported a warning)
+const EXIT_STARTUP: i32 = 2;        //Couldn't start (bad arguments, or an unreadable script)
 
-    println!("Cell {} was freed, and is now ready for use again", pointer);
+/// What should happen after a command has run
+enum Flow {
+    Continue,                       //Carry on reading commands
+    Exit(i32),                      //End the program, with this exit code
 }
 
-/// Sets 2 cells to configure as roots for the Mark and Sweep algorithm.
-/// If invalid cells are parsed, used the default of `0` and `19`
-fn configure_roots(cells: &mut Vec<Cell>, a: usize, b: usize) {
-    //error handle
-    if a > 19 || b > 19 {
-        //set values to default
-        //Unfree them as they'll have values (soon)
-        println!("One value was out of bounds, using defaults...");
-        cells[0].make_root();
-        cells[1].make_root();
+//ANSI escape codes used by --state
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// Function to view the current state of the memory cells, as a compact table (one row per cell)
+/// #### Columns
+/// - Cell -> index position of the cell
+/// - Name -> label given with `--name` (if any)
+/// - Data -> stored value (`-` if empty)
+/// - Flags -> `R` root, `M` marked, `F` free
+/// - Refs -> reference amount
+/// - References -> cells this cell references (`will_ref`)
+/// - Referenced by -> cells that reference this cell (`by_ref`)
+///
+/// With colour on, marked (live) cells are green, unmarked cells (garbage, if a collection ran now) are red,
+/// roots are bold and free cells are dim.
+///
+/// Only the cells in `rows` are shown (every cell for --state, or those matching a --query)
+#[allow(clippy::ptr_arg)]
+fn view_state(cells: &Vec<Cell>, rows: &[usize], color: bool, out: &mut dyn Write) {
+    //The name column is as wide as the longest name
+    let width: usize = rows.iter().filter_map(|&i| cells[i].name.as_ref()).map(|name| name.len()).max().unwrap_or(0).max(4);
+
+    respond!(out, " Cell | {:<width$} |   Data | Flags | Refs | References      | Referenced by", "Name");
+    respond!(out, "------+-{:-<width$}-+--------+-------+------+-----------------+-----------------", "");
+
+    for &i in rows {
+        let cell: &Cell = &cells[i];
+        let data: String = match (cell.kind, cell.data) {
+            _ if cell.is_poisoned() => format!("{:#x}", poison::POISON),
+            (Kind::List, _) => format!("[{}]", cell.will_ref.len()),
+            (Kind::Record, _) => fit(cell.record.as_ref().map_or("", |record| record.name.as_str()), 6),
+            (_, None) => "-".to_string(),
+            (Kind::Str, Some(_)) => quote(&strings::read(cells, i).unwrap_or_default(), 6),
+            (Kind::Bytes, Some(_)) => format!("'{}'", chunk(cell)),
+            (Kind::Int, Some(data)) => data.to_string(),
+        };
+        let flags = format!(
+            "{}{}{}",
+            if cell.is_root() { "R" } else { "." },
+            if cell.marked { "M" } else { "." },
+            if cell.freed { "F" } else { "." },
+        );
 
-        println!("cells {} and {} are now the roots", 0, 19);
-    } else {
-        //Assign the cells as roots that were chosen by the user
-        //Unfree them as they'll have values (soon)
-        cells[a].make_root();
-        cells[b].make_root();
+        let row = format!(
+            "{:>5} | {:<width$} | {:>6} | {:>5} | {:>4} | {:<15} | {}",
+            i,                              //Cell position
+            cell.name.as_deref().unwrap_or(""), //Label given with --name (if any)
+            data,                           //What this cell stores (if anything)
+            flags,                          //Root, marked and free flags
+            cell.reference_count,           //How many references does this cell have <inclusive>
+            list_references(cell),          //Displays what cells this cell references
+            list_indexes(&cell.by_ref),     //Displays what other cells reference this one
+        );
+
+        if !color {
+            respond!(out, "{}", row);
+            continue;
+        }
+
+        //Pick the colour for the state of the cell, roots are bold on top of that
+        let mut style: String = if cell.freed {
+            DIM.to_string()
+        } else if cell.marked {
+            GREEN.to_string()
+        } else {
+            RED.to_string()
+        };
+        if cell.is_root() {
+            style.push_str(BOLD);
+        }
+
+        respond!(out, "{}{}{}", style, row, RESET);
+    }
 
-        println!("cells {} and {} are now the roots", a, b);
+    if color {
+        respond!(out, "{}marked{}  {}unmarked (garbage){}  {}root{}  {}free{}", GREEN, RESET, RED, RESET, BOLD, RESET, DIM, RESET);
     }
 }
 
-/// Unroots all cells in the virtual memory heap.
-fn unroot(cells: &mut Vec<Cell>) {
-    //loop over cells and unroot all
-    for i in 0..cells.len() {
-        if cells[i].is_root == true {
-            cells[i].is_root = false;
+/// Shows the heap table from `--state`, or only the cells with a tag from `--state <tag>` (see tags.rs)
+fn state(heap: &Heap, tag: Option<&&str>, color: bool, out: &mut dyn Write) {
+    match tag.map(|tag| tag.trim()).filter(|tag| !tag.is_empty()) {
+        None => view_state(&heap.cells, &(0..heap.cells.len()).collect::<Vec<usize>>(), color, out),
+        Some(tag) => match heap.tagged(tag) {
+            rows if rows.is_empty() => respond!(out, "No cell is tagged '{}'", tag),
+            rows => view_state(&heap.cells, &rows, color, out),
+        },
+    }
+}
 
-            println!("cell {} unrooted", i);
-        }
+//Cuts text short (with …) if it would be wider than `width`
+fn fit(text: &str, width: usize) -> String {
+    match text.chars().count() > width {
+        true => format!("{}…", text.chars().take(width.saturating_sub(1)).collect::<String>()),
+        false => text.to_string(),
     }
+}
 
-    println!();         //Print a blank line at the end of the func
+//Quotes a string's text, cut short if it would be wider than `width`
+fn quote(text: &str, width: usize) -> String {
+    format!("\"{}\"", fit(&text.escape_debug().collect::<String>(), width.saturating_sub(2)))
 }
 
-/// Populates any remaining cells with data that is not referencing anything (these will be sweeped)
-/// I.e. fill each remaining free cell with arbitrary `i32` data that is not being referenced or making references.
-/// This is soley for the purpose of demonstrating that the Mark and Sweep part of the garbage collector works.
-fn populate_remaining(cells: &mut Vec<Cell>) {
-    //loop through and populate all free cells
-    let mut rng = rand::rng();
-    let random_val: i32 = rng.random_range(0..1000);    //Generate a random arbitrary int value
+//The part of a string held by one of its cells, without the padding
+fn chunk(cell: &Cell) -> String {
+    String::from_utf8_lossy(&strings::unpack(cell.data)).trim_end_matches('\0').escape_debug().collect()
+}
 
-    for i in 0..cells.len() {
-        if cells[i].freed == true {
-            //Cell is free
-            cells[i].data = Some(random_val);           //Assign some arbitrary data (exact val, not important)
-            cells[i].freed = false;                     //This cell now has data occupying it
+//Formats a record's fields, i.e. "x: 3, y: 5, next: -"
+fn list_fields(record: &Record) -> String {
+    record.fields
+        .iter()
+        .map(|field| format!("{}: {}", field.name, field.to.map_or("-".to_string(), |to| to.to_string())))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
 
-            println!("Cell {} has been populated", i);
-        }
-    }
+//Formats the cells a cell references -> through a record's fields by name first, i.e. "next=3, 7"
+fn list_references(cell: &Cell) -> String {
+    let Some(record) = &cell.record else {
+        return list_indexes(&cell.will_ref);
+    };
 
-    println!();         //Print a blank line at the end of the func
+    let fields = record.fields.iter().filter_map(|field| field.to.map(|to| format!("{}={}", field.name, to)));
+    let others = cell.will_ref
+        .iter()
+        .filter(|&&to| !record.fields.iter().any(|field| field.to == Some(to)))
+        .map(|to| to.to_string());
+    fields.chain(others).collect::<Vec<String>>().join(", ")
 }
 
-/// Function to view the current state of the memory cells
-/// #### Output
-/// - Has data? -> `boolean`
-/// - Is free? -> `boolean`
-/// - Is Root? -> `boolean`
-/// - Reference Amount -> `usize`
-/// - Reference to Others -> `Vec<usize>`
-/// - Reference by Others -> `Vec<usize>`
-/// - Marked -> `boolean`
-fn view_state(cells: &Vec<Cell>) {
-    //just print each cell
-    for i in 0..cells.len() {
-        print!(
-"Cell |{}|:
-    1. Has data?: {}
-    2. Is free?: {}
-    3. Is root?: {}
-    4. Ref amt: {}
-    5. Ref Other?: {:?}
-    6. Ref By?: {:?}
-    7. MARKED: {}\n",
-            i,                              //Cell position
-            cells[i].data.is_some(),        //Does this cell currently store any data?
-            cells[i].freed,                 //Is this cell free?
-            cells[i].is_root,               //Is this cell a root?
-            cells[i].reference_count,       //How many references does this cell have <inclusive>
-            cells[i].will_ref.iter(),       //Displays what cells this cell references
-            cells[i].by_ref.iter(),         //Displays what other cells reference this one
-            cells[i].marked,
-        );
-    }
+//Formats a list of cell indexes for the --state table, i.e. "3, 7"
+fn list_indexes(indexes: &[usize]) -> String {
+    indexes.iter().map(|i| i.to_string()).collect::<Vec<String>>().join(", ")
 }
 
 //Processes messages
@@ -359,378 +310,2840 @@ fn show_message(a: Option<usize>, b: Option<String>) {
     }
 }
 
+/// Runs the garbage collector (`--gc`) -> whatever the policy chooses, or a minor, major or full collection
+/// if one is named (see generational.rs). With --diff, shows exactly what the collection changed
+fn gc(heap: &mut Heap, args: &[&str], out: &mut dyn Write) {
+    let mut kind: Option<CollectionKind> = None;
+    let mut diff: bool = false;
+    for arg in args.iter().map(|arg| arg.trim()) {
+        match (arg, CollectionKind::from_name(arg)) {
+            ("--diff", _) => diff = true,
+            (_, Some(named)) => kind = Some(named),
+            _ => {
+                fail!(out, "Unknown collection '{}', expected one of: {} (or --diff)", arg, CollectionKind::NAMES.join(", "));
+                return;
+            }
+        }
+    }
+
+    //Capture the pool before collecting, so exactly what changed can be shown
+    let before: Option<Vec<Cell>> = diff.then(|| heap.cells.clone());
+    match kind {
+        None | Some(CollectionKind::Whole) => collect(heap),
+        Some(CollectionKind::Minor) => heap.collect_minor(),
+        Some(CollectionKind::Major) => heap.collect_major(),
+        Some(CollectionKind::Full) => heap.collect_full(),
+    }
+    if let Some(before) = before {
+        respond!(out, "GC diff:\n{}", HeapDiff::between(&before, &heap.cells));
+    }
+}
 
-/// Function that is used to handle cell viability on creating references -> i.e are these cells in use? If they are free return error.
-/// Can handle `n` number of cells as `_cells` is a `&Vec<usize>`
-/// Returns `DataIsFree` error if the cell isn't in use. (Can't make a reference to a free cell)
-fn cell_viability(cells: &Vec<Cell>, _cells: &Vec<usize>) -> IndexResult {
+/// Starts compacting the heap concurrently, copying `cells` cells (1 by default) after every command from now on,
+/// from `--relocate [cells]`. `--relocate finish` copies whatever is left in one go (see relocation.rs)
+fn relocate(heap: &mut Heap, param: Option<&&str>, out: &mut dyn Write) {
+    match param.map(|p| p.trim()) {
+        Some("finish") => match heap.relocation() {
+            Some(_) => heap.finish_relocation(),
+            None => fail!(out, "No relocation is in progress, start one with --relocate [cells]"),
+        },
+        param => match param.map_or(Ok(1), |p| p.parse::<usize>()) {
+            Ok(cells) if cells > 0 => {
+                heap.start_relocation(cells);
+                respond!(out, "Relocating {} cell(s) after every command -> cells typed at the prompt (and handles) follow the forwarding pointers left behind, through the read barrier", cells);
+            }
+            _ => fail!(out, "Invalid number of cells '{}', expected a number greater than 0 or 'finish'", param.unwrap_or("")),
+        },
+    }
+}
 
-    //Check if the cells are free (i.e. not in use)
-    for cell_index in _cells {
-        if cells[*cell_index].freed {
-            //If the cell IS free, then we shouldn't be returning a reference
-            return Err(AllocError::DataIsFree);
+/// Releases free cells from the end of the heap from `--shrink [cells]`, returning their bytes to the OS -> up to `cells` of them,
+/// or as many as the max-free policy would after a collection. Nothing is moved to make room (see shrink.rs)
+fn shrink(heap: &mut Heap, param: Option<&&str>, out: &mut dyn Write) {
+    if heap.survivor_spaces().is_some() {
+        fail!(out, "The survivor spaces sit at the end of the heap, so it can't shrink while there are any (--set-policy survivor-size off)");
+        return;
+    }
+    let released: usize = match (param, heap.policy.max_free) {
+        (Some(param), _) => match param.trim().parse::<usize>() {
+            Ok(cells) if cells > 0 => heap.shrink(cells),
+            _ => {
+                fail!(out, "Invalid number of cells '{}', expected a number greater than 0", param);
+                return;
+            }
+        },
+        (None, Some(_)) => heap.shrink_to_policy(),
+        (None, None) => {
+            respond!(out, "The max-free policy is off, so the heap keeps its size -> give --shrink a number of cells, or set one with --set-policy max-free <percent>");
+            return;
         }
+    };
+    if released == 0 {
+        respond!(out, "There are no free cells at the end of the heap to release (it is full, they are part of a named region, or no more are free than the policy allows)");
     }
+}
 
-    //If no errors were found, return 1
-    Ok(1)
+/// Prints the region table (`--regions`) -> each region's cells, the bytes in use, and how many of them were live as of the
+/// last garbage first collection, as a percentage. The regions in its collection set are marked (see g1.rs)
+fn regions(heap: &Heap, out: &mut dyn Write) {
+    let regions: Vec<Region> = heap.regions();
+    let marked: bool = regions.iter().any(|region| region.live.is_some());
+    respond!(out, "{} region(s) of {} cell(s){}:", regions.len(), heap.policy.region_size, match marked {
+        true => ", liveness as of the last garbage first collection",
+        false => " (not marked yet, collect with --set-policy algorithm g1)",
+    });
+    for region in &regions {
+        let cells: String = format!("cells {}-{}", region.cells.start, region.cells.end - 1);
+        let liveness: String = match (region.live, region.liveness()) {
+            (_, Some(percent)) => format!(", {} live ({}%), {} garbage", region.live.unwrap_or(0), percent, region.garbage()),
+            (Some(_), None) => String::from(", empty"),
+            (None, None) => String::new(),
+        };
+        let collected: &str = if region.collected { " (collected)" } else { "" };
+        respond!(out, "    Region {}, {}: {} bytes used{}{}", region.index, cells, region.used, liveness, collected);
+    }
 }
 
-/// Assigns a reference between two stated cells
-/// #### c1pos will reference c2pos and c2pos will be referenced by c1pos
-/// makes external call to ```cell_viability()``` here to check if parsed cell positions are valid
-/// ```
-/// let result: IndexResult = cell_viability(&cells, &cells_to_check);
-/// ```
-fn assign_reference(cells: &mut Vec<Cell>, c1pos: usize, c2pos: usize) {
+/// Prints the cumulative garbage collection history of the heap (`--stats`)
+/// One line per cycle, followed by the totals across every cycle
+fn view_stats(heap: &Heap, out: &mut dyn Write) {
+    let stats = heap.stats();
+
+    respond!(out,
+"Heap:
+    Seed: {}
+    Algorithm: {}
+    Capacity: {} cells ({} bytes)
+    Live: {} ({} bytes, {}%)
+    Free: {}
+    Roots: {}
+    Allocations: {}
+    High-water mark: {}
+    Barrier writes: {}
+    Read barrier hits: {}
+    Mark epoch: {}
+    Stack frames: {}",
+        heap.seed(),
+        heap.policy.algorithm,
+        stats.capacity,
+        stats.bytes_capacity,
+        stats.live,
+        stats.bytes_used,
+        heap.occupancy(),
+        stats.free,
+        stats.roots,
+        stats.allocations,
+        stats.high_water,
+        stats.barrier_writes,
+        stats.read_barrier_hits,
+        stats.mark_epoch,
+        stats.frames,
+    );
+
+    //What coalescing the buffered reference count updates has saved, once there are any
+    let updates = heap.rc_updates();
+    if updates.logged + updates.pending() > 0 {
+        respond!(out, "RC updates: {} batched, {} elided by coalescing ({}%), {} pending", updates.logged, updates.elided(), (updates.elided() * 100).checked_div(updates.logged).unwrap_or(0), updates.pending());
+    }
 
-    //Assign reference between two cells
-    /*
-        -> c1pos WILL REFERENCE c2pos
-        therefore, c2pos will be referenced BY c1pos
-     */
+    //How full each named region is
+    for region in heap.named_regions() {
+        respond!(out, "{}", region_line(heap, region));
+    }
 
-    //Check if the data can be used
-    let cells_to_check: Vec<usize> = vec![c1pos, c2pos];
-    let result: IndexResult = cell_viability(&cells, &cells_to_check);
+    //What interning has shared, once anything has been interned
+    let interner = heap.interner();
+    if !interner.is_empty() || interner.deduplicated > 0 {
+        respond!(out, "Interning: {} allocation(s) shared an existing cell, saving {} bytes, {} value(s) in the table", interner.deduplicated, interner.bytes_saved, interner.len());
+    }
 
-    //Boolean flag
-    let mut check: bool = false;
+    //Which half of the hybrid reclaimed what, once it has run
+    let hybrid = heap.hybrid();
+    if heap.policy.algorithm == Algorithm::Hybrid || hybrid.by_rc + hybrid.by_tracer > 0 {
+        respond!(out, "Hybrid: {} reclaimed by reference counting, {} by the cycle tracer, {} candidate cycle root(s) waiting", hybrid.by_rc, hybrid.by_tracer, hybrid.candidates().len());
+    }
 
-    //Perform action or report error
-    match result {
-        Ok(val) => check = true,                        //Boolean flag to progress the function
-        Err(why) => println!("{}", match why {
-            AllocError::Occupied
-                => "Space is occupied",                         //Report error
-            AllocError::NoFreeMemory
-                => "No free memory avaliable",
-            AllocError::DataIsFree
-                => "The memory was free, not suitable for use",
-        }),
+    //How far the treadmill's cycle has got, once it has one
+    if let Some(treadmill) = heap.treadmill() {
+        let colours: Vec<String> = Colour::ALL.iter().map(|&colour| format!("{} {}", colour, treadmill.count(colour))).collect();
+        respond!(out, "Treadmill: {}", colours.join(", "));
     }
 
-    //Only create references if allowed
-    if check {
-        //Cell 1
-        cells[c1pos].reference_count = cells[c1pos].reference_count + 1;        //Increase reference count
-        if !cells[c1pos].will_ref.contains(&c2pos) {                            //...only add reference if it doesn't already exist
-            cells[c1pos].will_ref.push(c2pos);                                  //Push c2pos into vector of references
-        }
+    //Where cells are in the promotion pipeline, once there is one
+    if heap.policy.algorithm == Algorithm::Generational || heap.policy.survivor_size.is_some() {
+        let spaces: Vec<String> = heap.space_usage().iter().map(|usage| match usage.space {
+            Space::Survivor(space) if space == heap.to_space() => format!("{} {}/{} (to)", usage.space, usage.used, usage.capacity),
+            Space::Survivor(_) => format!("{} {}/{}", usage.space, usage.used, usage.capacity),
+            _ => format!("{} {}", usage.space, usage.used),
+        }).collect();
+        respond!(out, "    Generations: {} (tenuring threshold {})", spaces.join(" -> "), heap.policy.tenuring_threshold);
+    }
 
-        //Cell 2
-        cells[c2pos].reference_count = cells[c2pos].reference_count + 1;        //Increase reference count
-        if !cells[c2pos].by_ref.contains(&c1pos) {                              //...only add reference if it doesn't already exist
-            cells[c2pos].by_ref.push(c1pos);                                    //Push c1pos into vector of references
-        }
+    //Innermost frame last, like a stack trace read upwards
+    for (depth, frame) in heap.frames().iter().enumerate() {
+        respond!(out, "        #{}: locals {}", depth + 1, match frame.locals.is_empty() {
+            true => "none".to_string(),
+            false => list_indexes(&frame.locals),
+        });
     }
 
+    match stats.minor_collections + stats.major_collections + stats.full_collections {
+        0 => respond!(out, "Collections: {}", stats.collections),
+        _ => respond!(out, "Collections: {} (minor {}, major {}, full {})", stats.collections, stats.minor_collections, stats.major_collections, stats.full_collections),
+    }
+
+    //Nothing else to report until the collector has run
+    if heap.history.is_empty() {
+        respond!(out);
+        return;
+    }
+
+    for cycle in &heap.history {
+        respond!(out,
+            "    GC #{} ({}): marked {}, swept {} ({} bytes), pause {}µs (root scan {}µs, mark {}µs, sweep {}µs){}",
+            cycle.number,
+            match cycle.kind {
+                CollectionKind::Whole => cycle.algorithm.to_string(),
+                kind => format!("{}, {}", cycle.algorithm, kind),
+            },
+            cycle.marked,
+            cycle.swept,
+            cycle.bytes_swept,
+            cycle.pause().as_micros(),
+            cycle.root_scan.as_micros(),
+            cycle.mark.as_micros(),
+            cycle.sweep.as_micros(),
+            match cycle.is_partial() {
+                true => format!(", partial -> {} cell(s) left in place", cycle.left_in_place),
+                false => String::new(),
+            },
+        );
+    }
+
+    //Totals across all cycles
+    let swept: usize = heap.history.iter().map(|cycle| cycle.swept).sum();
+    let pause: Duration = heap.history.iter().map(|cycle| cycle.pause()).sum();
+    let longest: Duration = heap.history.iter().map(|cycle| cycle.pause()).max().unwrap_or_default();
+
+    respond!(out,
+        "Total swept: {} ({} bytes), total pause: {}µs, average pause: {}µs, longest pause: {}µs\n",
+        swept,
+        stats.bytes_reclaimed,
+        pause.as_micros(),
+        pause.as_micros() / heap.history.len() as u128,
+        longest.as_micros(),
+    );
 }
 
-///Runs the marking (Non-recursive stack-based DFS) algorithm on all cells of memory on the virtual heap.
-/// #### Parameters
-/// `cells` -> requires a mutable reference to the cells vector of type `Vec<Cell>`
-/// #### Example usage
-/// ```
-/// mark(cells);
-/// ```
-/// Does not return anything, as it mutates the cells directly and marks their `marked` boolean flag
-fn mark(cells: &mut Vec<Cell>) {
-    //get root index position
-    let mut roots: Vec<usize> = Vec::new();
-    for i in 0..cells.len() {
-        if cells[i].is_root {
-            roots.push(i);
+/// Collects while simulated mutator threads are running, from `--safepoint [threads] [max-poll-gap µs]`.
+/// Each thread does a random amount of work (up to the gap) between safepoint polls, so the collection
+/// can't start until the slowest of them notices it was requested. Reports how long that took (time-to-safepoint)
+fn safepoint(heap: &mut Heap, threads: Option<&&str>, gap: Option<&&str>, out: &mut dyn Write) {
+    let threads: usize = parse_param_to_usize(threads, 4, out).max(1);
+    let gap: Duration = Duration::from_micros(parse_param_to_usize(gap, 500, out) as u64);
+    let safepoint: Safepoint = Safepoint::new();
+    let done: AtomicBool = AtomicBool::new(false);
+
+    let (time_to_safepoint, arrivals, pause) = thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                let mutator = safepoint.attach();
+                let mut rng = rand::rng();
+
+                while !done.load(Ordering::Acquire) {
+                    //Work with no safepoint in it, i.e. a tight loop the compiler didn't put a poll in
+                    let work: Duration = gap.mul_f64(rng.random::<f64>());
+                    let start: Instant = Instant::now();
+                    while start.elapsed() < work {
+                        std::hint::spin_loop();
+                    }
+                    mutator.poll();
+                }
+            });
         }
-    }
 
-    //Reset all cells in the heap to be not marked, so we don't get any incorrect sweeping
-    for i in 0..cells.len() {
-        if !cells[i].is_root {
-            cells[i].marked = false;
+        //Give every thread the chance to attach and get going, so there is something to wait for
+        while safepoint.attached() < threads {
+            thread::yield_now();
         }
+        thread::sleep(gap);
+
+        let world: StoppedWorld = safepoint.stop_the_world();
+        collect(heap);
+        let stopped = (world.time_to_safepoint, world.arrivals.clone(), world.elapsed());
+
+        done.store(true, Ordering::Release);
+        drop(world);                                //Restart the world, so the threads see they are done
+        stopped
+    });
+
+    let first: Duration = arrivals.iter().copied().min().unwrap_or_default();
+    let last: Duration = arrivals.iter().copied().max().unwrap_or_default();
+    respond!(out,
+        "{} mutator thread(s) reached the safepoint in {}µs (first parked after {}µs, last after {}µs)",
+        arrivals.len(),
+        time_to_safepoint.as_micros(),
+        first.as_micros(),
+        last.as_micros(),
+    );
+    respond!(out,
+        "Collection paused them for {}µs, {}µs including time-to-safepoint",
+        pause.as_micros(),
+        (time_to_safepoint + pause).as_micros(),
+    );
+}
+
+/// Allocates `cells-per-tick` cells a tick for `ticks` ticks, from `--pressure <cells-per-tick> <ticks>`, then reports
+/// how the collection policy kept up -> collections the threshold started, against those a full heap forced (see pressure.rs).
+/// The log is quietened for the run, as it is for --stress
+fn pressure(heap: &mut Heap, per_tick: Option<&&str>, ticks: Option<&&str>, out: &mut dyn Write) {
+    let parse = |param: Option<&&str>| param.and_then(|p| p.trim().parse::<usize>().ok()).filter(|&n| n > 0);
+    let (Some(per_tick), Some(ticks)) = (parse(per_tick), parse(ticks)) else {
+        fail!(out, "Usage: --pressure <cells-per-tick> <ticks>, where both are numbers greater than 0");
+        return;
+    };
+
+    let threshold: String = heap.policy.gc_threshold.map_or("off".to_string(), |percent| format!("{}%", percent));
+    let level: Level = heap.log.level;
+    heap.log.level = Level::Quiet;
+    let report: PressureReport = pressure::run(heap, per_tick, ticks);
+    heap.log.level = level;
+
+    respond!(out, "{} cell(s) a tick for {} of {} tick(s), {} with gc-threshold {}:", per_tick, report.ticks, ticks, heap.policy.algorithm, threshold);
+    respond!(out, "    allocated: {} cell(s), peak occupancy {}%", report.allocations, report.peak_occupancy);
+    respond!(out, "    collections: {} triggered by the threshold, {} forced by a full heap", report.triggered, report.forced);
+    respond!(out, "    pauses: {:?} in total, longest {:?}", report.total_pause, report.longest_pause);
+
+    match report.out_of_memory {
+        Some(tick) => fail!(out,
+            "Out of memory in tick {}, a tick's {} cell(s) don't fit alongside the {} cell(s) the heap keeps alive",
+            tick,
+            per_tick,
+            heap.live() - heap.garbage().len(),
+        ),
+        None if report.kept_up() => respond!(out, "Kept up, every collection started before the heap filled"),
+        None if heap.policy.gc_threshold.is_none() => respond!(out,
+            "Fell behind, with no gc-threshold every collection waits for the heap to fill (set one with --set-policy gc-threshold <percent>)"
+        ),
+        None => respond!(out, "Fell behind, {} allocation(s) found the heap full before the threshold was reached, try a lower gc-threshold", report.forced),
     }
+}
 
-    //Traverse the graph (DFS) and mark them with a mark bit flag
-    //Left->Right traversal Vertical first horizontal next
-    
-    //Start at left-most root (index 0 of the roots vector), then sequentially move along roots until all cells are marked as traversed
-    //The by_ref field will be how we fallback recursively
-    //Follow the will_ref until a dead end
+/// Runs mutator threads against the heap while it is collected, from `--stress threads=<n> ops=<m>`.
+/// Each of the n threads (4 by default) does m random allocations, links, unlinks and reads (1000 by default),
+/// then the heap is verified. The log is quietened for the run, there would be thousands of events
+fn stress(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let (mut threads, mut ops) = (4, 1000);
+
+    for param in params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        let (setting, value) = match param.split_once('=') {
+            Some(("threads", value)) => (&mut threads, value),
+            Some(("ops", value)) => (&mut ops, value),
+            _ => {
+                fail!(out, "Usage: --stress threads=<n> ops=<m>");
+                return;
+            }
+        };
+        match value.parse::<usize>() {
+            Ok(value) if value > 0 => *setting = value,
+            _ => {
+                fail!(out, "Usage: --stress threads=<n> ops=<m>, where n and m are numbers greater than 0");
+                return;
+            }
+        }
+    }
 
-    //TODO: Handle Reference BY, if the value is still being referenced by another cell BUT it itself
-    //doesnt reference a cell, it shouldn't be swept. (currently it is)
+    let level: Level = heap.log.level;
+    heap.log.level = Level::Quiet;
+    let report: StressReport = stress::run(heap, threads, ops);
+    heap.log.level = level;
+
+    let counts = report.ops;
+    respond!(out,
+        "{} threads x {} ops: {} allocations ({} out of memory), {} links, {} unlinks, {} reads, {} lost a race and did nothing",
+        report.threads,
+        ops,
+        counts.allocs,
+        counts.out_of_memory,
+        counts.links,
+        counts.unlinks,
+        counts.reads,
+        counts.raced,
+    );
+    respond!(out,
+        "{} collections ran alongside them (longest time-to-safepoint {}µs)",
+        report.collections,
+        report.longest_time_to_safepoint.as_micros(),
+    );
+
+    match report.violations.is_empty() {
+        true => respond!(out, "Heap verified, no invariants were broken"),
+        false => {
+            for violation in &report.violations {
+                fail!(out, "{}", violation);
+            }
+        }
+    }
+}
 
-    let mut stack: VecDeque<usize> = VecDeque::new();
+/// Reports how much of the run the mutator got to use, from `--mmu [window...]`.
+/// Windows are given as i.e. `500us`, `10ms` or `2s` (a plain number is in milliseconds), 100µs to 1s by default
+fn mmu(heap: &Heap, params: &[&str], out: &mut dyn Write) {
+    let mut windows: Vec<Duration> = Vec::new();
+    for param in params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match parse_duration(param) {
+            Some(window) if !window.is_zero() => windows.push(window),
+            _ => {
+                fail!(out, "Usage: --mmu [window...], where each window is a length of time such as 500us, 10ms or 2s");
+                return;
+            }
+        }
+    }
+    if windows.is_empty() {
+        windows = DEFAULT_WINDOWS.to_vec();
+    }
 
-    for root in roots {
-        //Beginning at the root cell, begin updating cells
-        //Root <usize> is our index link into the cells heap memory pool
-        if cells[root].will_ref.is_empty() {
-            //Cell doesn't reference anything
-            continue;           //Specifically specifiy to continue for readability...
+    let utilization: Utilization = heap.utilization(&windows);
+    respond!(out,
+        "Run so far: {:.3?}, paused for {:?} by {} collection(s) -> mutator utilization {:.2}%",
+        utilization.elapsed,
+        utilization.gc,
+        heap.history.len(),
+        utilization.overall() * 100.0,
+    );
+
+    for window in &utilization.windows {
+        match window.mmu {
+            Some(mmu) if mmu < 1.0 => respond!(out,
+                "    MMU over {:?}: {:.2}% (the worst window starts {:.3?} in)",
+                window.size,
+                mmu * 100.0,
+                window.worst_at,
+            ),
+            Some(_) => respond!(out, "    MMU over {:?}: 100% (no collections)", window.size),
+            None => respond!(out, "    MMU over {:?}: the run isn't that long yet", window.size),
         }
-        else {
-            //-> traverse its references
+    }
+}
 
-            //Initialise variables for current and next position
-            let mut current_pos: usize = root;
+//Parses a length of time such as 500us (or 500µs), 10ms or 2s. A plain number is in milliseconds
+fn parse_duration(text: &str) -> Option<Duration> {
+    let split: usize = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse::<f64>().ok().filter(|n| n.is_finite())?;
+
+    let seconds: f64 = match unit {
+        "us" | "µs" => number / 1_000_000.0,
+        "ms" | "" => number / 1_000.0,
+        "s" => number,
+        _ => return None,
+    };
+    Duration::try_from_secs_f64(seconds).ok()
+}
 
-            //Ensure root is marked (Roots should be marked when they are made)
-            if !cells[current_pos].marked {
-                //if it is not marked, fix and mark here
-                cells[current_pos].marked = true;
+fn parse_param_to_usize(param: Option<&&str>, default: usize, out: &mut dyn Write) -> usize {
+    match param {
+        Some(value) => {
+            // Try to parse the string to a number
+            match value.trim().parse::<usize>() {
+                Ok(number) => number, // Successfully parsed
+                Err(_) => {
+                    fail!(out,
+                        "Warning: Could not parse '{}' as a number. Using default: {}",
+                        value, default
+                    );
+                    default // Use default if parsing fails
+                }
             }
+        }
+        None => {
+            default // Use default if no parameter provided
+        }
+    }
+}
 
-            //Add adjacent nodes into stack
-            for node in 0..cells[current_pos].will_ref.len() {
-                
-                //Record the nodes
-                stack.push_back(cells[current_pos].will_ref[node]);
+/// Parses a parameter that refers to a cell, either by index or by a name given with `--name`.
+/// The cell is read through the read barrier, so a cell a relocation has moved is found where it is now (see relocation.rs).
+/// Fails (returning None, so the command isn't run) if the parameter is missing, or is neither
+fn parse_cell(heap: &Heap, param: Option<&&str>, out: &mut dyn Write) -> Option<usize> {
+    match param.map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        None => {
+            fail!(out, "Missing a cell, expected its index or name");
+            None
+        }
+        Some(value) => match value.parse::<usize>().ok().or_else(|| heap.find(value)) {
+            Some(index) => Some(heap.read_barrier(index)),
+            None => {
+                fail!(out, "'{}' isn't the index or name of a cell, so nothing was done", value);
+                None
             }
+        },
+    }
+}
 
-            //Start traversing along the stack nodes
-            while !stack.is_empty() {             //will_ref is a vector of cells that current_pos references
-
-                //Get front reference
-                let i = stack.front().unwrap(); //Don't need to error handle as this code is not executed if the stack is empty anyway
+/// Parses a list of cells, each by index or by name, skipping (with a warning) any that are neither
+fn parse_cells(heap: &Heap, params: &[&str], out: &mut dyn Write) -> Vec<usize> {
+    let mut cells: Vec<usize> = Vec::new();
 
+    for param in params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match param.parse::<usize>().ok().or_else(|| heap.find(param)) {
+            Some(index) => cells.push(heap.read_barrier(index)),
+            None => fail!(out, "Warning: '{}' isn't a cell index or name, skipping it", param),
+        }
+    }
 
-                //This cell is still in use (is still being referenced)
-                //mark as safe to keep
-                cells[*i].marked = true;
+    cells
+}
 
-                //Now check if the cell also has its OWN list of referenced cells
-                if !cells[*i].will_ref.is_empty() {
-                    // This cell has it's own list of references, continue further down the graph
+//Splits `--set <name>` off the end of a command's parameters, giving the parameters before it and the set's name
+fn split_root_set<'a>(params: &'a [&'a str]) -> (&'a [&'a str], Option<&'a str>) {
+    match params.iter().position(|p| p.trim() == "--set") {
+        Some(at) => (&params[..at], params.get(at + 1).map(|name| name.trim())),
+        None => (params, None),
+    }
+}
 
-                    //move cell position
-                    current_pos = *i;
+/// Makes cells roots, from `--root [cell...] [--set <name>]`.
+/// Roots go in the "globals" root set unless another is named, and with no cells given, the first and last cells are rooted
+fn root(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let (params, set) = split_root_set(params);
+    let set: &str = match set {
+        Some(set) if !set.is_empty() && !set.starts_with('-') => set,
+        Some(_) => {
+            fail!(out, "Usage: --root [cell...] [--set <name>]");
+            return;
+        }
+        None => GLOBALS,
+    };
 
-                    //Add adjacent nodes into stack
-                    for node in 0..cells[current_pos].will_ref.len() {
-                        
-                        //Record the nodes
-                        stack.push_back(cells[current_pos].will_ref[node]);
-                    }
-                }
+    let cells: Vec<usize> = match params.iter().all(|p| p.trim().is_empty()) {
+        true => vec![0, heap.cells.len() - 1],
+        false => parse_cells(heap, params, out),
+    };
+    configure_roots(heap, set, &cells);
+}
 
-                //After it is marked, and any other computation is finalised, pop it from the stack
-                //as it is visited, and we don't need to revisit
-                stack.pop_front();
-            }
+/// Unroots cells, from `--unroot [cell... | --set <name>]`.
+/// With no parameters every root set is removed (locals of live stack frames stay roots until their frame is popped)
+fn unroot_cells(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let (params, set) = split_root_set(params);
 
+    if let Some(set) = set {
+        if heap.drop_root_set(set).is_none() {
+            fail!(out, "There is no root set named '{}'", set);
         }
+        return;
     }
-}   
 
-/// The sweeping phase of the garbage collector (free any memory cell that isn't referencing anything or is being referenced)
-/// #### Example Cell To Be Swept (Freed)
-/// ```
-/// Cell 
-/// {
-///     data: <...>
-///     reference_count: <...>
-///     freed: <...>
-///     is_root: <...>
-///     by_ref: <...>
-///     will_ref: <...>
-///     marked: false,      // <- This cell is not marked to keep, and therefore it is determined to not be in use anymore          
-/// }
-/// ```
-fn sweep(cells: &mut Vec<Cell>) {
-    //free (sweep) all the cells are position usize
+    if params.iter().all(|p| p.trim().is_empty()) {
+        unroot(heap);
+        return;
+    }
 
-    //run the free function on each cell that is not marked
-    for i in 0..cells.len() {
-        if !cells[i].marked {
-            free(cells, i);        //pass in cell index position
+    for index in parse_cells(heap, params, out) {
+        if !heap.remove_root(index) {
+            match heap.cells.get(index).map(|cell| cell.is_root()) {
+                Some(true) => respond!(out, "Cell {} is a local of a stack frame, it stays a root until the frame is popped", index),
+                _ => fail!(out, "Cell {} isn't a root", index),
+            }
+        } else if heap.cells[index].is_root() {
+            respond!(out, "Cell {} is still a local of a stack frame, it stays a root until the frame is popped", index);
         }
     }
 }
 
-/// This function runs the entire garbage collection algorithm.
-/// ### Logic flow
-/// This function runs these two commands.
-/// ```
-/// mark() -> sweep();
-/// ```
-/// And does not return anything, allowing it to be called within a matching arm during the user input phase.
-fn collect(cells: &mut Vec<Cell>) {
-    //'mark' cells to be freed (sweeped)
-    mark(cells);
+/// Lists every root set and the locals of each live stack frame, from `--roots`
+fn list_roots(heap: &Heap, out: &mut dyn Write) {
+    if heap.root_sets().is_empty() && heap.frames().iter().all(|frame| frame.locals.is_empty()) {
+        respond!(out, "There are no roots, so everything on the heap is garbage");
+        return;
+    }
 
-    //Sweep unreferenced and no longer in use cells
-    sweep(cells);
+    let describe = |cells: &[usize]| cells.iter().map(|&i| describe_cell(heap, i)).collect::<Vec<String>>().join(", ");
+    for root_set in heap.root_sets() {
+        respond!(out, "{} ({}): {}", root_set.name, root_set.cells.len(), describe(&root_set.cells));
+    }
+    for (depth, frame) in heap.frames().iter().enumerate().filter(|(_, frame)| !frame.locals.is_empty()) {
+        respond!(out, "frame #{} ({}): {}", depth + 1, frame.locals.len(), describe(&frame.locals));
+    }
 }
 
-/// Allocates arbitrary data WITH references to a root that is chosen randomly. This function holds little 'real-world' value to the functionality of
-/// a garbage collector, but it helps populate memory with reference to aid in the demonstration of the functionality. It also populates arbitrary data
-/// into the root cells.
-/// 
-/// #### Uses malloc! macro pattern matching
-/// `malloc!(cells, (data[root] as i32) * (data[root] as i32), Some(roots[root]));` -> will match with arm #1 (first free allocation)
-fn create_free_ref(cells: &mut Vec<Cell>, times_to_run: usize) {
-    let mut rng = rand::rng();
+/// Names a cell, from `--name <cell> <name>`. Passing `off` as the name removes it
+fn name_cell(heap: &mut Heap, index: usize, name: Option<&&str>, out: &mut dyn Write) {
+    let name: Option<&str> = match name.map(|n| n.trim()) {
+        Some("off") => None,
+        Some(name) if !name.is_empty() => Some(name),
+        _ => {
+            fail!(out, "Usage: --name <cell> <name|off>");
+            return;
+        }
+    };
 
-    //keep track of what cells are roots
-    let mut roots: Vec<usize> = Vec::new();
+    if let Err(why) = heap.name_cell(index, name) {
+        match why {
+            NameError::OutOfBounds => fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len()),
+            NameError::DataIsFree => fail!(out, "Cell {} is free, only cells in use can be named", index),
+            NameError::Taken(other) => fail!(out, "Cell {} already has that name", other),
+            NameError::Invalid => fail!(out, "Names can't be a number, or start with '-'"),
+        }
+    }
+}
 
-    //keep track of the data stored in them
-    let mut data: Vec<i32> = Vec::new();
+/// Sets a range of the pool aside under a name, from `--region <name> <start>..<end>`, removes it again from
+/// `--region <name> off`, or lists every region with how full it is from `--region` (see named_regions.rs)
+fn region(heap: &mut Heap, name: Option<&&str>, range: Option<&&str>, out: &mut dyn Write) {
+    let (name, range) = (name.map(|n| n.trim()).unwrap_or(""), range.map(|r| r.trim()).unwrap_or(""));
+    if name.is_empty() {
+        match heap.named_regions().is_empty() {
+            true => respond!(out, "No regions are named, set one aside with --region <name> <start>..<end>"),
+            false => {
+                for region in heap.named_regions() {
+                    respond!(out, "{}", region_line(heap, region));
+                }
+            }
+        }
+        return;
+    }
+    if range == "off" {
+        match heap.drop_region(name) {
+            Some(region) => respond!(out, "Region '{}' ({}..{}) removed, its cells are left as they are", name, region.cells.start, region.cells.end),
+            None => fail!(out, "There is no region called '{}'", name),
+        }
+        return;
+    }
 
-    //set data of root memory cells
-    for i in 0..cells.len() {
-        if cells[i].is_root {
-            //Create and store data
-            let _data = rng.random_range(1..50);
-            data.push(_data);
+    //A Rust range, i.e. 10..15 (cells 10 to 14) or 10..=15
+    let end = |end: &str| match end.strip_prefix('=') {
+        Some(end) => end.parse::<usize>().map(|end| end + 1),
+        None => end.parse::<usize>(),
+    };
+    let cells = match range.split_once("..") {
+        Some((start, last)) => match (start.parse::<usize>(), end(last)) {
+            (Ok(start), Ok(end)) => start..end,
+            _ => {
+                fail!(out, "Invalid range '{}', expected <start>..<end>, i.e. 10..15", range);
+                return;
+            }
+        },
+        None => {
+            fail!(out, "Usage: --region [<name> <start>..<end> | <name> off]");
+            return;
+        }
+    };
+    match heap.define_region(name, cells.clone()) {
+        Ok(()) => respond!(out, "Region '{}' is cells {} to {}, allocate in it with --alloc_in {}", name, cells.start, cells.end - 1, name),
+        Err(NamedRegionError::OutOfBounds) => fail!(out, "Cells {}..{} aren't a range of the heap, which has {} cells", cells.start, cells.end, heap.cells.len()),
+        Err(NamedRegionError::Invalid) => fail!(out, "Region names can't be a number, or start with '-'"),
+        Err(NamedRegionError::Overlaps(other)) => fail!(out, "Region '{}' already has some of cells {}..{}, regions can't overlap", other, cells.start, cells.end),
+    }
+}
 
-            //Assign data to mem cell
-            cells[i].data = Some(_data);
+//One line about a named region, for --region and --stats
+fn region_line(heap: &Heap, region: &NamedRegion) -> String {
+    let occupancy: Occupancy = heap.region_occupancy(&region.name).expect("only regions on the heap are listed");
+    format!(
+        "Region '{}' ({}..{}): {}/{} cells in use ({}%), {} bytes",
+        region.name, region.cells.start, region.cells.end, occupancy.used, occupancy.capacity, occupancy.percent(), occupancy.bytes,
+    )
+}
 
-            //store index of root
-            roots.push(i);
+/// Allocates in the first free cell of a named region, from `--alloc_in <region>`. Fails if the region is full,
+/// whatever room there is elsewhere (see named_regions.rs)
+fn alloc_in(heap: &mut Heap, name: Option<&&str>, out: &mut dyn Write) {
+    let name: &str = name.map(|n| n.trim()).unwrap_or("");
+    if heap.named_region(name).is_none() {
+        fail!(out, "There is no region called '{}', name one with --region <name> <start>..<end>", name);
+        return;
+    }
+    let data: i32 = heap.rng.random_range(0..50);
+    if let Err(why) = heap.alloc_in(name, data) {
+        match why.kind {
+            ErrorKind::NoFreeMemory => fail!(out, "Region '{}' is full, allocations in it have to wait for a collection to free some of its cells", name),
+            _ => fail!(out, "{}", why),
         }
     }
-    //assign a new value that is a product (makes reference to) one of the root cells
-    //choose which root
-    let root = rng.random_range(0..roots.len());
+}
 
-    //TODO: This currently just spams the same value in multiple memory cells, change this up
-    //for now and for pure demonstration purposes, it is fine and will work, but is predictable and boring
-    for i in 0..times_to_run {
-        let index = malloc!(cells, (data[root] as i32) * (data[root] as i32), Some(roots[root]));   //First free allocation
+/// Copies a cell from `--copy <src> [dst] [deep]`, into the cell given (or the first free cell). A shallow copy references
+/// the same cells as the original, a deep one copies everything the original reaches along with it (see cloning.rs)
+fn copy(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let params: Vec<&str> = params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+    let (src, dst, deep) = match params[..] {
+        [src] => (src, None, false),
+        [src, "deep"] => (src, None, true),
+        [src, dst] => (src, Some(dst), false),
+        [src, dst, "deep"] => (src, Some(dst), true),
+        _ => {
+            fail!(out, "Usage: --copy <src> [dst] [deep]");
+            return;
+        }
+    };
+    let Some(src) = parse_cell(heap, Some(&src), out) else {
+        return;
+    };
+    let dst: Option<usize> = match dst {
+        Some(dst) => match parse_cell(heap, Some(&dst), out) {
+            Some(dst) => Some(dst),
+            None => return,
+        },
+        None => None,
+    };
 
-        match index {
-            Ok(index) => println!("Cell at position {} was used", index),   //Report to the console what index was used
-            Err(why) => println!("{}", match why {
-                AllocError::Occupied
-                    => "Space is occupied",     //Report error
-                AllocError::NoFreeMemory
-                    => "No avaliable memory found",
-                AllocError::DataIsFree
-                    => "The memory was free, not suitable for use",
-            }),
+    match heap.copy_cell(src, dst, deep) {
+        Ok(copies) if deep => {
+            let pairs: Vec<String> = copies.iter().map(|(original, copy)| format!("{}->{}", original, copy)).collect();
+            respond!(out, "Copied {} cell(s) reachable from cell {}: {}", copies.len(), src, pairs.join(", "));
         }
+        Ok(copies) => respond!(out, "Cell {} copied to cell {}, sharing its references", src, copies[0].1),
+        Err(why) => fail!(out, "{}", why),
     }
-    println!(); //Add a line
 }
 
-fn parse_param_to_usize(param: Option<&&str>, default: usize) -> usize {
-    match param {
-        Some(value) => {
-            // Try to parse the string to a number
-            match value.trim().parse::<usize>() {
-                Ok(number) => number, // Successfully parsed
-                Err(_) => {
-                    println!(
-                        "Warning: Could not parse '{}' as a number. Using default: {}",
-                        value, default
-                    );
-                    default // Use default if parsing fails
+/// Moves a cell to a free cell from `--move <src> <dst>`, pointing every reference, root and local at where it went
+/// (see `Heap::move_cell()`)
+fn move_cell(heap: &mut Heap, src: usize, dst: usize, out: &mut dyn Write) {
+    match heap.move_cell(src, dst) {
+        Ok(()) if heap.cells[dst].is_root => respond!(out, "{} cell(s) referencing it, and its roots, now point at cell {}", heap.cells[dst].by_ref.len(), dst),
+        Ok(()) => respond!(out, "{} cell(s) referencing it now point at cell {}", heap.cells[dst].by_ref.len(), dst),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
+
+/// Exchanges the objects in two cells from `--swap <a> <b>`, pointing everything that referenced one at the other
+/// (see `Heap::swap_cells()`)
+fn swap(heap: &mut Heap, a: usize, b: usize, out: &mut dyn Write) {
+    match heap.swap_cells(a, b) {
+        Ok(()) => respond!(out, "Cells {} and {} swapped, with the references to both", a, b),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
+
+/// Tags a cell, from `--tag <cell> <tag> [off]` (off takes the tag off again), or lists every tag in use from `--tag`
+fn tag(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let params: Vec<&str> = params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+    let (cell, tag, on) = match params[..] {
+        [] => {
+            match heap.tags().is_empty() {
+                true => respond!(out, "No cell is tagged"),
+                false => {
+                    for (tag, count) in heap.tags() {
+                        respond!(out, "{}: {} cell(s)", tag, count);
+                    }
                 }
             }
+            return;
         }
-        None => {
-            default // Use default if no parameter provided
+        [cell, tag] => (cell, tag, true),
+        [cell, tag, "off"] => (cell, tag, false),
+        _ => {
+            fail!(out, "Usage: --tag <cell> <tag> [off]");
+            return;
         }
+    };
+
+    let Some(index) = parse_cell(heap, Some(&cell), out) else {
+        return;
+    };
+    match heap.tag_cell(index, tag, on) {
+        Ok(true) => {}
+        Ok(false) if on => respond!(out, "Cell {} is already tagged '{}'", index, tag),
+        Ok(false) => respond!(out, "Cell {} isn't tagged '{}'", index, tag),
+        Err(TagError::OutOfBounds) => fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len()),
+        Err(TagError::DataIsFree) => fail!(out, "Cell {} is free, only cells in use can be tagged", index),
+        Err(TagError::Invalid) => fail!(out, "Tags can't be empty, start with '-', or contain spaces"),
     }
 }
 
-///Function for handling allocation from prompt
-//TODO: some tasks to expand here
-fn handle_prompt_allocation(cells: &mut Vec<Cell>, index: usize) {
-    let mut rng: ThreadRng = rand::rng();
-    let data: i32 = rng.random_range(0..50);                                    //Generate some arbitrary data TODO: actually handle data
+/// Registers a finalizer on a cell, from `--finalizer <cell> [resurrect]`, or lists the finalizers that haven't run yet
+/// from `--finalizer` (see finalize.rs)
+fn finalizer(heap: &mut Heap, cell: Option<&&str>, mode: Option<&&str>, out: &mut dyn Write) {
+    if cell.is_none_or(|cell| cell.trim().is_empty()) {
+        match heap.finalizers().is_empty() {
+            true => respond!(out, "No finalizers are waiting to run"),
+            false => {
+                for finalizer in heap.finalizers() {
+                    respond!(out, "Cell {}{}", finalizer.index, if finalizer.resurrect { " (resurrects it)" } else { "" });
+                }
+            }
+        }
+        return;
+    }
 
-    let index = malloc!(cells, data, None, index);  //Handle no references TODO: Meanful connection of references
+    let Some(index) = parse_cell(heap, cell, out) else {
+        return;
+    };
+    let resurrect: bool = match mode.map(|m| m.trim()) {
+        None | Some("") => false,
+        Some("resurrect") => true,
+        Some(other) => {
+            fail!(out, "Unknown finalizer '{}', expected 'resurrect' or nothing", other);
+            return;
+        }
+    };
+    match heap.register_finalizer(index, resurrect) {
+        Ok(()) if resurrect => respond!(out, "Cell {} has a finalizer, which will save it in the '{}' root set when it runs", index, finalize::RESURRECTED),
+        Ok(()) => respond!(out, "Cell {} has a finalizer, which runs once a collection finds it dead", index),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
 
-    match index {
-        Ok(index) => println!("Cell at position {} was used", index),   //Report to the console what index was used
-        Err(why) => println!("{}", match why {
-            AllocError::Occupied
-                => "Space is occupied",                                         //Report error
-            AllocError::NoFreeMemory
-                => "No free memory avaliable",
-            AllocError::DataIsFree
-                => "The memory was free, not suitable for use",
-        }),
+/// Maps a key cell to a value cell in the heap's weak map, from `--weakmap_put <key> <value>` (see weakmap.rs)
+fn weakmap_put(heap: &mut Heap, key: usize, value: usize, out: &mut dyn Write) {
+    match heap.weak_map_put(key, value) {
+        Ok(Some(replaced)) => respond!(out, "Weak map: cell {} -> cell {} (was cell {}), kept only while cell {} is reachable some other way", key, value, replaced, key),
+        Ok(None) => respond!(out, "Weak map: cell {} -> cell {}, kept only while cell {} is reachable some other way", key, value, key),
+        Err(why) => fail!(out, "{}", why),
     }
 }
 
-/// Listens for user input
-/// 
-/// #### Accepted commands
-/// ```
-/// "--root" => configure_roots(cells, index1, index2), //Root cells, or default a: 0, b: len-1
-/// "--unroot" => unroot(cells),                        //Unroot all
-/// "--arb_ref" => create_free_ref(cells, index1), //Run as many times as specified
-/// "--gc" => collect(cells), //Run the garbage collector (mark and sweep)
-/// "--state" => view_state(cells),
-/// "--exit" => std::process::exit(0),
-/// "--populate" => populate_remaining(cells),
-/// "--alloc_at" => handle_prompt_allocation(cells, index1),
-/// "--link_ref" => assign_reference(cells, index1, index2),    //Cell 1 references Cell 2
-/// _ => println!("Unknown command. Type 'help' for assistance."), //Default if command doesn't match
-/// ```
-fn listen(listening: bool, cells: &mut Vec<Cell>) {
-    while listening {
-        //while accepting commands
-        let mut input: String = String::new(); //Create a new string variable each iteration to store the users input
-        io::stdin() //access the standard input stream
-            .read_line(&mut input) //Read what the user types and store it in input
-            .expect("Unable to read Stdin"); //On fail, panic with msg
-
-        let input: Vec<&str> = input.split(' ').collect();      //remove whitespace
-                                                                //Get the first command
-        let command: &str = input[0];
-        //Commands can take up to 2 inputs
-        let fparam: Option<&&str> = input.get(1);       //&& reference to a reference
-        let sparam: Option<&&str> = input.get(2);       //&& reference to a reference
-
-        //these parameters will always be cell index position, so make adjustments
-        let index1 = parse_param_to_usize(fparam, 0); // Default to 0 if parameter missing or invalid
-        let index2 = parse_param_to_usize(sparam, cells.len() - 1); // Default to last cell if missing
-
-        //Seperate values
-
-        match command.trim() {
-            "--help" => println!(
-                "\nAvaliable Commands:
-    1. --root <cell_index_pos>(0-19) <cell_index_pos>(0-19)
-    2. --unroot
-    3. --arb_ref <amount_of_times>
-    4. --link_ref <Cell 1> *references...->* <Cell 2>
-    5. --alloc_at <Cell>
-    6. --state
-    7. --populate
-    8. --gc
-    9. --exit"
-            ), //Print a the accepted list of commands
-            "--root" => configure_roots(cells, index1, index2), //Root cells, or default a: 0, b: len-1
-            "--unroot" => unroot(cells),                        //Unroot all
-            "--arb_ref" => create_free_ref(cells, index1), //Run as many times as specified
-            "--gc" => collect(cells), //Run the garbage collector (mark and sweep)
-            "--state" => view_state(cells),
-            "--exit" => std::process::exit(0),
-            "--populate" => populate_remaining(cells),
-            "--alloc_at" => handle_prompt_allocation(cells, index1),
-            "--link_ref" => assign_reference(cells, index1, index2),    //Cell 1 references Cell 2
-            _ => println!("Unknown command. Type 'help' for assistance."), //Default if command doesn't match
+/// Looks a key cell up in the heap's weak map, from `--weakmap_get <key>`, or lists every entry from `--weakmap_get`
+fn weakmap_get(heap: &Heap, key: Option<&&str>, out: &mut dyn Write) {
+    if key.is_none_or(|key| key.trim().is_empty()) {
+        match heap.weak_map().is_empty() {
+            true => respond!(out, "The weak map is empty"),
+            false => {
+                for &(key, value) in heap.weak_map().entries() {
+                    respond!(out, "Cell {} -> cell {}", key, value);
+                }
+            }
         }
+        return;
+    }
+
+    let Some(key) = parse_cell(heap, key, out) else {
+        return;
+    };
+    match heap.weak_map_get(key) {
+        Some(value) => respond!(out, "Cell {} -> cell {}", key, value),
+        None => respond!(out, "Cell {} has no entry in the weak map (entries are dropped when their key is collected)", key),
     }
 }
 
-fn main() {
-    //1. Create a memory pool
-    /*
-        A memory pool, AKA memory allocator or memory management pool, is a
-        software or hardware structure used to manage dynamic memory allocation
-        in a computer program.
-        Used to efficiently allocate and deallocate memory for data structures
-        and objects during program execution. It is a pre-allocated region
+/// Prints everything about a single cell, from `--peek <cell>`
+fn peek(heap: &Heap, index: usize, out: &mut dyn Write) {
+    let cell: &Cell = match heap.cells.get(index) {
+        Some(cell) => cell,
+        None => {
+            fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len());
+            return;
+        }
+    };
+
+    if cell.is_poisoned() {
+        fail!(out, "Use after free, cell {} was freed and is poisoned until it is allocated again (generation {})", index, cell.generation);
+        return;
+    }
+
+    let referrers: Vec<usize> = heap.referrers(index);
+
+    match &cell.name {
+        Some(name) => respond!(out, "Cell {} '{}'", index, name),
+        None => respond!(out, "Cell {}", index),
+    }
+    if !cell.tags.is_empty() {
+        respond!(out, "    Tags: {}", cell.tags.join(", "));
+    }
+    let data: String = match (cell.kind, cell.data) {
+        (Kind::List, _) => format!("{} element(s) [{}]", cell.will_ref.len(), list_indexes(&cell.will_ref)),
+        (Kind::Record, _) => match &cell.record {
+            Some(record) => format!("{} {{ {} }}", record.name, list_fields(record)),
+            None => "-".to_string(),
+        },
+        (_, None) => "-".to_string(),
+        (Kind::Str, Some(length)) => format!(
+            "{} ({} bytes, over {} more cell(s))",
+            quote(&strings::read(&heap.cells, index).unwrap_or_default(), usize::MAX),
+            length,
+            cell.will_ref.iter().filter(|&&i| heap.cells[i].kind == Kind::Bytes).count(),
+        ),
+        (Kind::Bytes, Some(_)) => format!("'{}' (part of a string)", chunk(cell)),
+        (Kind::Int, Some(data)) => data.to_string(),
+    };
+
+    respond!(out,
+"    Data: {}
+    Header: {}
+    References (fan-out {}): {}
+    Referenced by (fan-in {}): {}
+    Generation: {}",
+        data,
+        cell.header(),
+        cell.will_ref.len(),
+        list_references(cell),
+        referrers.len(),
+        list_indexes(&referrers),
+        cell.generation,
+    );
+}
+
+/// Reports whether a cell would survive a collection, from `--reachable <cell>`.
+/// Only follows references from the current roots, nothing on the heap is marked or changed
+fn reachable(heap: &Heap, index: usize, out: &mut dyn Write) {
+    let cell: &Cell = match heap.cells.get(index) {
+        Some(cell) => cell,
+        None => {
+            fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len());
+            return;
+        }
+    };
+
+    if cell.freed {
+        respond!(out, "Cell {} is free, there is nothing there to reach", index);
+        return;
+    }
+
+    let roots: Vec<usize> = heap.roots_reaching(index);
+    if roots.is_empty() {
+        respond!(out, "Cell {} is unreachable, and will be reclaimed by the next collection", index);
+    } else if cell.is_root() && roots.len() == 1 {
+        respond!(out, "Cell {} is reachable, it is a root", index);
+    } else {
+        respond!(out, "Cell {} is reachable from root(s): {}", index, list_indexes(&roots));
+    }
+}
+
+/// Explains why a cell is still alive, from `--why-live <cell> [--all]`.
+/// Prints the shortest chain of references from a root to the cell, or the shortest from every root that reaches it with `--all`
+fn why_live(heap: &Heap, index: usize, all: Option<&&str>, out: &mut dyn Write) {
+    if index >= heap.cells.len() {
+        fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len());
+        return;
+    }
+    if heap.cells[index].freed {
+        respond!(out, "Cell {} is free, nothing is keeping it alive", index);
+        return;
+    }
+
+    let paths: Vec<Vec<usize>> = heap.root_paths(index);
+    if paths.is_empty() {
+        respond!(out, "Cell {} is unreachable, nothing is keeping it alive (it will be reclaimed by the next collection)", index);
+        return;
+    }
+
+    let shown: usize = match all.map(|a| a.trim()) {
+        Some("--all") => paths.len(),
+        _ => 1,
+    };
+    for path in &paths[..shown] {
+        let chain: Vec<String> = path.iter().map(|&i| describe_cell(heap, i)).collect();
+        respond!(out, "{}", chain.join(" -> "));
+    }
+
+    if shown < paths.len() {
+        respond!(out, "({} more root(s) also reach cell {}, use --why-live {} --all to see them)", paths.len() - shown, index, index);
+    }
+}
+
+/// Reports a cell's retained size, from `--retained <cell>` -> how many cells would become unreachable
+/// if the references to it were cut (the cell itself, and every cell it dominates)
+fn retained(heap: &Heap, index: usize, out: &mut dyn Write) {
+    if index >= heap.cells.len() {
+        fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len());
+        return;
+    }
+    if heap.cells[index].freed {
+        respond!(out, "Cell {} is free, it doesn't retain anything", index);
+        return;
+    }
+
+    let tree: DominatorTree = DominatorTree::build(heap);
+    let retained: Vec<usize> = tree.retained(index);
+    if retained.is_empty() {
+        respond!(out, "Cell {} is unreachable already, it doesn't retain anything", index);
+        return;
+    }
+
+    respond!(out, "Cell {} retains {} cell(s): {}", index, retained.len(), list_indexes(&retained));
+    match tree.immediate_dominator(index) {
+        Some(dominator) => respond!(out, "Immediate dominator: {} (every chain from the roots passes through it)", describe_cell(heap, dominator)),
+        None => respond!(out, "Immediate dominator: none, no single cell stands between it and the roots"),
+    }
+}
+
+/// Lists every cell that holds data but can't be reached from any root, from `--leaks`.
+/// Nothing on the heap is marked or changed. Cells that have already survived a collection while unreachable
+/// are leaks -> the collector isn't reclaiming them (i.e. cycles under reference counting)
+fn leaks(heap: &Heap, out: &mut dyn Write) {
+    let garbage: Vec<usize> = heap.garbage();
+    if garbage.is_empty() {
+        respond!(out, "No leaks, every cell in use can be reached from a root");
+        return;
+    }
+
+    respond!(out, "{} cell(s) hold data but can't be reached from any root:", garbage.len());
+    for &index in &garbage {
+        let cell: &Cell = &heap.cells[index];
+        let data: String = cell.data.map(|data| data.to_string()).unwrap_or("-".to_string());
+        match cell.garbage_for {
+            0 => respond!(out, "    {} (data: {}): unreachable since the last collection", describe_cell(heap, index), data),
+            cycles => respond!(out, "    {} (data: {}): garbage through {} collection(s)", describe_cell(heap, index), data, cycles),
+        }
+    }
+
+    let leaked: usize = garbage.iter().filter(|&&index| heap.cells[index].garbage_for > 0).count();
+    if leaked > 0 {
+        respond!(out, "{} of them survived a collection while unreachable, which the {} collector isn't reclaiming", leaked, heap.policy.algorithm);
+    } else {
+        respond!(out, "None of them have been through a collection yet, run --gc to reclaim them");
+    }
+}
+
+/// Sets the label allocations are tagged with, from `--site <label|off>`. Turned off, each allocation is tagged
+/// with the command that made it again
+fn set_site(session: &mut Session, params: &[&str], out: &mut dyn Write) {
+    let label: String = params.join(" ");
+    match label.trim() {
+        "" => fail!(out, "Usage: --site <label|off>"),
+        "off" => {
+            session.site = None;
+            respond!(out, "Allocations are tagged with the command that made them");
+        }
+        label => {
+            session.site = Some(label.to_string());
+            respond!(out, "Allocations are tagged '{}' until --site off", label);
+        }
+    }
+}
+
+/// Reports what every allocation site has done, from `--profile-allocs` -> how many objects it allocated,
+/// how many are still in use, the share that survived a collection, and how many became garbage.
+/// Sites that make lots of short lived garbage are what a generational collector is for
+fn profile_allocs(heap: &Heap, out: &mut dyn Write) {
+    let sites: Vec<SiteProfile> = heap.allocation_sites();
+    if sites.is_empty() {
+        respond!(out, "Nothing has been allocated yet");
+        return;
+    }
+
+    let width: usize = sites.iter().map(|site| site.site.len()).max().unwrap_or(0).max(4);
+    respond!(out, "    {:<width$}  {:>6}  {:>6}  {:>8}  {:>7}", "site", "allocs", "live", "survived", "garbage");
+    for site in &sites {
+        respond!(
+            out,
+            "    {:<width$}  {:>6}  {:>6}  {:>7.0}%  {:>7}",
+            site.site,
+            site.allocations,
+            site.live,
+            site.survival() * 100.0,
+            site.garbage()
+        );
+    }
+
+    let waiting: usize = sites.iter().map(|site| site.unreachable).sum();
+    if waiting > 0 {
+        respond!(out, "Garbage includes {} unreachable cell(s) the next collection will reclaim", waiting);
+    }
+}
+
+/// Prints a text histogram of the live cells, from `--histogram <age|refs|size|type>`, one bar per bucket
+/// (see histogram.rs). Bars are scaled so the biggest bucket is BAR_WIDTH long
+fn histogram(heap: &Heap, dimension: Option<&&str>, out: &mut dyn Write) {
+    const BAR_WIDTH: usize = 40;
+
+    let name: &str = dimension.map(|d| d.trim()).unwrap_or("");
+    let Some(dimension) = Dimension::from_name(name) else {
+        fail!(out, "Usage: --histogram <{}>", Dimension::NAMES.join("|"));
+        return;
+    };
+    let histogram: Histogram = heap.histogram(dimension);
+    if histogram.total() == 0 {
+        respond!(out, "There are no cells in use");
+        return;
+    }
+
+    respond!(out, "{} live cell(s) by {} ({}):", histogram.total(), dimension, dimension.unit());
+    let width: usize = histogram.buckets.iter().map(|bucket| bucket.label.len()).max().unwrap_or(0);
+    let most: usize = histogram.buckets.iter().map(|bucket| bucket.count).max().unwrap_or(1);
+    for bucket in &histogram.buckets {
+        let bar: usize = (bucket.count * BAR_WIDTH).div_ceil(most);        //Anything counted shows at least one #
+        respond!(out, "    {:>width$} |{:<BAR_WIDTH$} {}", bucket.label, "#".repeat(bar), bucket.count);
+    }
+}
+
+/// Shows the cells matching a query, from `--query "<expr>"`, in the same table as --state.
+/// See query.rs for what a query can test, i.e. "freed==false && ref_count>1 && marked==false"
+fn query(heap: &Heap, params: &[&str], color: bool, out: &mut dyn Write) {
+    let text: String = params.join(" ");
+    let text: &str = text.trim();
+    let text: &str = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(text);
+
+    let query: Query = match Query::parse(text) {
+        Ok(query) => query,
+        Err(why) => {
+            fail!(out, "Couldn't read the query, {}", why);
+            return;
+        }
+    };
+
+    let rows: Vec<usize> = heap.query(&query);
+    if rows.is_empty() {
+        respond!(out, "No cells match {}", text);
+        return;
+    }
+
+    view_state(&heap.cells, &rows, color, out);
+    respond!(out, "{} of {} cells match", rows.len(), heap.cells.len());
+}
+
+/// Runs a statement of the tiny language (see lang.rs in the library), from `--eval <statement>`, showing its value.
+/// Without a statement, lists the variables bound so far
+fn eval(heap: &mut Heap, session: &mut Session, params: &[&str], out: &mut dyn Write) {
+    let source: String = params.join(" ");
+    if source.trim().is_empty() {
+        let variables = session.interpreter.variables(heap);
+        if variables.is_empty() {
+            respond!(out, "No variables are bound yet, i.e. --eval let xs = cons(1, cons(2, nil))");
+        }
+        for (name, value) in variables {
+            respond!(out, "{} = {}", name, lang::show(heap, value));
+        }
+        return;
+    }
+
+    match session.interpreter.run(heap, &source) {
+        Ok(value) => match source.trim().strip_prefix("let ").and_then(|rest| rest.split('=').next()) {
+            Some(name) => respond!(out, "{} = {}", name.trim(), lang::show(heap, value)),
+            None => respond!(out, "{}", lang::show(heap, value)),
+        },
+        Err(why) => fail!(out, "Unable to run that, {}", why),
+    }
+}
+
+/// Checks the heap's structural invariants, from `--verify`, listing anything that is broken.
+/// Broken invariants count as a failure, so scripts can use it as an assertion
+fn verify(heap: &Heap, out: &mut dyn Write) {
+    let violations: Vec<Violation> = heap.verify();
+    if violations.is_empty() {
+        respond!(out, "Heap verified, all {} cells are consistent", heap.cells.len());
+        return;
+    }
+
+    fail!(out, "{} invariant violation(s) found:", violations.len());
+    for violation in &violations {
+        respond!(out, "    {}", violation);
+    }
+}
+
+//"root 0", "3 'cache'" -> how a cell is shown in a chain of references
+fn describe_cell(heap: &Heap, index: usize) -> String {
+    let cell: &Cell = &heap.cells[index];
+    let mut described: String = match cell.is_root() {
+        true => format!("root {}", index),
+        false => index.to_string(),
+    };
+    if let Some(name) = &cell.name {
+        described.push_str(&format!(" '{}'", name));
+    }
+
+    described
+}
+
+/// Frees a cell by hand, from `--free <cell> [--force]` (roots are only freed with --force)
+fn free_cell_by_hand(heap: &mut Heap, index: usize, force: Option<&&str>, out: &mut dyn Write) {
+    let force: bool = matches!(force.map(|f| f.trim()), Some("--force"));
+
+    //Successful frees are reported by the heap's event log, so only report errors here
+    match free_cell(heap, index, force) {
+        Ok(_) => {}
+        Err(why) if why.kind == ErrorKind::FreedRoot => fail!(out, "{} (use --free {} --force to free it anyway)", why, index),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
+
+/// Overwrites the data in a cell, from `--set <cell> <value>`
+fn set_cell_data(heap: &mut Heap, index: usize, value: Option<&&str>, out: &mut dyn Write) {
+    let value: i32 = match value.map(|v| v.trim().parse::<i32>()) {
+        Some(Ok(value)) => value,
+        _ => {
+            fail!(out, "Usage: --set <cell> <value>");
+            return;
+        }
+    };
+
+    //Successful writes are reported by the heap's event log, so only report errors here
+    if let Err(why) = set_data(heap, index, value) {
+        fail!(out, "{}", why);
+    }
+}
+
+///Function for handling allocation from prompt
+//TODO: some tasks to expand here
+fn handle_prompt_allocation(heap: &mut Heap, index: usize, out: &mut dyn Write) {
+    let data: i32 = heap.rng.random_range(0..50);                                    //Generate some arbitrary data TODO: actually handle data
+
+    let index = malloc!(heap, data, None, index);  //Handle no references TODO: Meanful connection of references
+
+    //Successful allocations are reported by the heap's event log, so only report errors here
+    if let Err(why) = index {
+        fail!(out, "{}", why);
+    }
+}
+
+/// Allocates a string from `--alloc_str [Cell] "text"`, its head at the cell given (or the first free cell).
+/// The text takes up another cell for every 4 bytes of it (see strings.rs)
+fn alloc_str(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    //The cell is optional -> anything before the text that isn't quoted
+    let (at, text) = match params.first().map(|p| p.trim()) {
+        Some(first) if !first.is_empty() && !first.starts_with('"') && params.len() > 1 => match parse_cell(heap, params.first(), out) {
+            Some(at) => (Some(at), &params[1..]),
+            None => return,
+        },
+        _ => (None, params),
+    };
+    let text: String = text.join(" ");
+    let text: &str = text.trim();
+    let text: &str = text.strip_prefix('"').and_then(|text| text.strip_suffix('"')).unwrap_or(text);
+
+    let cells: usize = 1 + text.len().div_ceil(strings::BYTES_PER_CELL);
+    match heap.alloc_str(text, at) {
+        Ok(head) => respond!(out, "String of {} bytes allocated at cell {}, taking up {} cell(s)", text.len(), head, cells),
+        Err(why) if why.kind == ErrorKind::NoFreeMemory => fail!(out, "{}, a string of {} bytes needs {} free cells", why, text.len(), cells),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
+
+/// Interns an int or a string from `--intern <int | "text">`, sharing the cell of an equal value if one is still
+/// alive (see intern.rs)
+fn intern(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let text: String = params.join(" ");
+    let text: &str = text.trim();
+    let interned = match (text.parse::<i32>(), text.strip_prefix('"').and_then(|text| text.strip_suffix('"'))) {
+        (Ok(value), _) => heap.intern_int(value),
+        (_, Some(text)) => heap.intern_str(text),
+        _ => {
+            fail!(out, "Usage: --intern <int | \"text\">");
+            return;
+        }
+    };
+    match interned {
+        Ok((index, true)) => respond!(out, "{} was already interned at cell {}, which is shared rather than allocating again", text, index),
+        Ok((index, false)) if text.parse::<i32>().is_ok_and(|value| value.unsigned_abs() < heap.policy.intern_threshold.unsigned_abs()) => {
+            respond!(out, "{} is under the intern threshold ({}), so it was allocated at cell {} without being interned", text, heap.policy.intern_threshold, index)
+        }
+        Ok((index, false)) => respond!(out, "{} was interned at cell {}, equal values interned from now on share it while it is alive", text, index),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
+
+/// Allocates an empty list from `--list_new [Cell]`, at the cell given (or the first free cell)
+fn list_new(heap: &mut Heap, param: Option<&&str>, out: &mut dyn Write) {
+    let at: Option<usize> = match param.map(|p| p.trim()) {
+        Some(param) if !param.is_empty() => match parse_cell(heap, Some(&param), out) {
+            Some(at) => Some(at),
+            None => return,
+        },
+        _ => None,
+    };
+
+    match heap.list_new(at) {
+        Ok(index) => respond!(out, "Empty list allocated at cell {}", index),
+        Err(why) => fail!(out, "{}", why),
+    }
+}
+
+/// Pushes a cell onto the end of a list, from `--list_push <List> <Cell>`
+fn list_push(heap: &mut Heap, list: usize, element: usize, out: &mut dyn Write) {
+    match heap.list_push(list, element) {
+        Ok(length) => respond!(out, "Pushed cell {} onto the list at cell {}, which now has {} element(s)", element, list, length),
+        Err(ListError::NotAList) => fail!(out, "Cell {} isn't a list, make one with --list_new", list),
+        Err(ListError::ElementIsFree) => fail!(out, "Cell {} is free, only cells in use can be pushed", element),
+        Err(ListError::AlreadyInList) => fail!(out, "Cell {} is already in the list at cell {}", element, list),
+    }
+}
+
+/// Pops the last element off a list, from `--list_pop <List>`. The element is left for the collector, unless something else references it
+fn list_pop(heap: &mut Heap, list: usize, out: &mut dyn Write) {
+    match heap.list_pop(list) {
+        Ok(Some(element)) => respond!(out, "Popped cell {} off the list at cell {}", element, list),
+        Ok(None) => respond!(out, "The list at cell {} is empty, there is nothing to pop", list),
+        Err(_) => fail!(out, "Cell {} isn't a list, make one with --list_new", list),
+    }
+}
+
+/// Allocates a record from `--record_new <type> [field[=Cell]...]`, i.e. `--record_new node value=3 next`.
+/// Fields given without a cell don't reference anything yet
+fn record_new(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let params: Vec<&str> = params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+    let Some((name, params)) = params.split_first() else {
+        fail!(out, "Usage: --record_new <type> [field[=Cell]...]");
+        return;
+    };
+
+    let mut fields: Vec<(&str, Option<usize>)> = Vec::new();
+    for param in params {
+        let (field, to) = match param.split_once('=') {
+            Some((field, cell)) => match cell.parse::<usize>().ok().or_else(|| heap.find(cell)) {
+                Some(to) => (field, Some(to)),
+                None => {
+                    fail!(out, "'{}' isn't a cell index or name, the record wasn't allocated", cell);
+                    return;
+                }
+            },
+            None => (*param, None),
+        };
+        if field.is_empty() {
+            fail!(out, "Fields need a name, i.e. next=3");
+            return;
+        }
+        fields.push((field, to));
+    }
+
+    match heap.record_new(name, &fields, None) {
+        Ok(index) => respond!(out, "Record '{}' allocated at cell {}", name, index),
+        Err(RecordError::DuplicateField) => fail!(out, "Each field needs a different name"),
+        Err(RecordError::TargetIsFree) => fail!(out, "Fields can only reference cells in use, the record wasn't allocated"),
+        Err(RecordError::Alloc(why)) => fail!(out, "{}", why),
+        Err(_) => fail!(out, "Unable to allocate the record"),
+    }
+}
+
+/// Points a record's field at a cell (or at nothing, with off), from `--field_set <Cell> <field> <Cell|off>`
+fn field_set(heap: &mut Heap, index: usize, field: Option<&&str>, to: Option<&&str>, out: &mut dyn Write) {
+    let field: &str = field.map(|f| f.trim()).unwrap_or("");
+    let to: Option<usize> = match to.map(|t| t.trim()) {
+        Some("off") => None,
+        Some(to) if !to.is_empty() && !field.is_empty() => match to.parse::<usize>().ok().or_else(|| heap.find(to)) {
+            Some(to) => Some(to),
+            None => {
+                fail!(out, "'{}' isn't a cell index or name", to);
+                return;
+            }
+        },
+        _ => {
+            fail!(out, "Usage: --field_set <Cell> <field> <Cell|off>");
+            return;
+        }
+    };
+
+    match heap.field_set(index, field, to) {
+        Ok(()) => match to {
+            Some(to) => respond!(out, "Field '{}' of cell {} now references cell {}", field, index, to),
+            None => respond!(out, "Field '{}' of cell {} no longer references anything", field, index),
+        },
+        Err(RecordError::NotARecord) => fail!(out, "Cell {} isn't a record, make one with --record_new", index),
+        Err(RecordError::NoSuchField) => fail!(out, "The record at cell {} doesn't have a field called '{}'", index, field),
+        Err(_) => fail!(out, "Cell {} is free, fields can only reference cells in use", to.unwrap_or_default()),
+    }
+}
+
+/// Sets one of the heap's policies from the prompt, i.e. `--set-policy gc-threshold 80`
+/// #### Supported policies
+/// - `gc-threshold <percent>` -> Automatically collect when occupancy (of cells) exceeds `percent` after an allocation
+/// - `gc-threshold off` -> Return to manual collection (only run on `--gc`)
+fn set_policy(heap: &mut Heap, name: Option<&&str>, value: Option<&&str>, out: &mut dyn Write) {
+    let name: &str = name.map(|n| n.trim()).unwrap_or("");
+    let value: &str = value.map(|v| v.trim()).unwrap_or("");
+
+    match name {
+        "gc-threshold" => {
+            if value == "off" {
+                heap.policy.gc_threshold = None;
+                respond!(out, "Automatic collection disabled, run --gc to collect");
+                return;
+            }
+
+            //Threshold must be a valid percentage
+            match value.parse::<usize>() {
+                Ok(percent) if percent <= 100 => {
+                    heap.policy.gc_threshold = Some(percent);
+                    respond!(out, "Garbage collector will run when heap occupancy exceeds {}%", percent);
+                }
+                _ => fail!(out, "Invalid threshold '{}', expected a percentage (0-100) or 'off'", value),
+            }
+        }
+        "algorithm" => match Algorithm::from_name(value) {
+            Some(algorithm) => {
+                heap.policy.algorithm = algorithm;
+                respond!(out, "Garbage collector will use {}", algorithm);
+            }
+            None => fail!(
+                out,
+                "Unknown algorithm '{}', expected one of: {}",
+                value,
+                Algorithm::NAMES.join(", ")
+            ),
+        },
+        "mark-order" => match MarkOrder::from_name(value) {
+            Some(order) => {
+                heap.policy.mark_order = order;
+                respond!(out, "Mark and sweep will visit cells in {} order (--verbose shows the order)", order);
+            }
+            None => fail!(out, "Unknown mark order '{}', expected one of: {}", value, MarkOrder::NAMES.join(", ")),
+        },
+        "mark-stack" => match value {
+            "off" => {
+                heap.policy.mark_stack = None;
+                respond!(out, "The mark worklist can hold as many cells as it needs");
+            }
+            _ => match value.parse::<usize>() {
+                Ok(limit) if limit > 0 => {
+                    heap.policy.mark_stack = Some(limit);
+                    respond!(out, "The mark worklist holds at most {} cell(s), overflowing into a rescan of the heap", limit);
+                }
+                _ => fail!(out, "Invalid mark stack size '{}', expected a number of cells greater than 0 or 'off'", value),
+            },
+        },
+        "periodic-gc" => match value {
+            "off" => {
+                heap.policy.periodic_gc = None;
+                respond!(out, "Periodic collection disabled");
+            }
+            _ => match parse_duration(value) {
+                Some(interval) if !interval.is_zero() => {
+                    heap.policy.periodic_gc = Some(interval);
+                    respond!(out, "A timer will collect the heap every {:?}, once the prompt reaches a safepoint (straight away while it waits for input)", interval);
+                }
+                _ => fail!(out, "Invalid interval '{}', expected a length of time such as 500ms or 5s, or 'off'", value),
+            },
+        },
+        "tenuring-threshold" => match value.parse::<usize>() {
+            Ok(threshold) if threshold > 0 => {
+                heap.set_tenuring_threshold(threshold);
+                respond!(out, "Cells are promoted to the tenured space once they have survived {} collection(s)", threshold);
+            }
+            _ => fail!(out, "Invalid tenuring threshold '{}', expected a number of collections greater than 0", value),
+        },
+        "survivor-size" => match value {
+            "off" => {
+                heap.policy.survivor_size = None;
+                respond!(out, "No survivor spaces, young cells stay where they were allocated until they are promoted");
+            }
+            _ => match value.parse::<usize>() {
+                Ok(size) if size > 0 && 2 * size < heap.cells.len() => {
+                    heap.policy.survivor_size = Some(size);
+                    let spaces = heap.survivor_spaces().expect("the heap has room for them");
+                    respond!(out, "Survivor spaces of {} cell(s) each -> cells {}-{} and {}-{}, which allocations don't use", size, spaces[0].start, spaces[0].end - 1, spaces[1].start, spaces[1].end - 1);
+                }
+                _ => fail!(out, "Invalid survivor space size '{}', expected a number of cells greater than 0 (with room for both in fewer than the heap's {} cells) or 'off'", value, heap.cells.len()),
+            },
+        },
+        "verify-remembered" => match value {
+            "on" => {
+                heap.policy.verify_remembered = true;
+                respond!(out, "Every minor collection will check its remembered set against the whole heap, and report any reference the write barrier missed");
+            }
+            "off" => {
+                heap.policy.verify_remembered = false;
+                respond!(out, "Minor collections will trust their remembered set");
+            }
+            _ => fail!(out, "Invalid value '{}' for verify-remembered, expected 'on' or 'off'", value),
+        },
+        "region-size" => match value.parse::<usize>() {
+            Ok(size) if size > 0 => {
+                heap.policy.region_size = size;
+                respond!(out, "The garbage first collector splits the heap into {} region(s) of {} cell(s)", heap.cells.len().div_ceil(size), size);
+            }
+            _ => fail!(out, "Invalid region size '{}', expected a number of cells greater than 0", value),
+        },
+        "pause-budget" => match value {
+            "off" => {
+                heap.policy.pause_budget = None;
+                respond!(out, "Garbage first collections will evacuate every region with garbage");
+            }
+            _ => match parse_duration(value) {
+                Some(budget) if !budget.is_zero() => {
+                    heap.policy.pause_budget = Some(budget);
+                    respond!(out, "Garbage first collections will evacuate the regions with the most garbage for as long as they are predicted to take under {:?}", budget);
+                }
+                _ => fail!(out, "Invalid pause budget '{}', expected a length of time such as 50us or 2ms, or 'off'", value),
+            },
+        },
+        "intern-threshold" => match value.parse::<i32>() {
+            Ok(threshold) if threshold >= 0 => {
+                heap.policy.intern_threshold = threshold;
+                respond!(out, "Interning shares ints at least {} away from zero, and every string", threshold);
+            }
+            _ => fail!(out, "Invalid intern threshold '{}', expected a number of 0 or more", value),
+        },
+        "max-free" => match value {
+            "off" => {
+                heap.policy.max_free = None;
+                respond!(out, "The heap keeps its size after collections, --shrink releases its free cells by hand");
+            }
+            _ => match value.parse::<usize>() {
+                Ok(percent) if percent < 100 => {
+                    heap.policy.max_free = Some(percent);
+                    respond!(out, "After every collection, free cells at the end of the heap will be released until no more than {}% of it is free", percent);
+                }
+                _ => fail!(out, "Invalid percentage '{}', expected a number under 100 or 'off'", value),
+            },
+        },
+        "barriers" if !cfg!(debug_assertions) => fail!(out, "Write barriers can only be turned off in debug builds"),
+        "barriers" => match value {
+            "on" => {
+                heap.set_barriers(true);
+                respond!(out, "Write barriers are on, the remembered set has been worked out again from the whole heap");
+            }
+            "off" => {
+                heap.set_barriers(false);
+                respond!(out, "Write barriers are OFF -> an old cell made to reference a young one isn't remembered, so a minor collection can sweep a cell still in use. --verify shows the references missed");
+            }
+            _ => fail!(out, "Invalid value '{}' for barriers, expected 'on' or 'off'", value),
+        },
+        _ => fail!(out, "Unknown policy '{}'. Avaliable policies: gc-threshold, algorithm, mark-order, mark-stack, periodic-gc, tenuring-threshold, survivor-size, verify-remembered, region-size, pause-budget, intern-threshold, max-free, barriers", name),
+    }
+}
+
+/// Sets the heap's policy from a tuning profile, from `--profile <throughput|low-latency>`,
+/// or runs the same workload under each profile and compares them, from `--profile compare [allocations]`
+fn profile(heap: &mut Heap, name: Option<&&str>, allocations: Option<&&str>, out: &mut dyn Write) {
+    let name: &str = name.map(|n| n.trim()).unwrap_or("");
+    if name != "compare" {
+        match Profile::from_name(name) {
+            Some(profile) => {
+                heap.set_profile(profile);
+                let threshold: String = heap.policy.gc_threshold.map_or("off".to_string(), |percent| format!("{}%", percent));
+                let stack: String = heap.policy.mark_stack.map_or("off".to_string(), |size| size.to_string());
+                respond!(out, "Tuned for {} -> gc-threshold {}, mark-order {}, mark-stack {} (algorithm is still {})", profile, threshold, heap.policy.mark_order, stack, heap.policy.algorithm);
+            }
+            None => fail!(out, "Unknown profile '{}'. Avaliable profiles: {}, or compare", name, Profile::NAMES.join(", ")),
+        }
+        return;
+    }
+
+    let allocations: usize = match allocations.map(|a| a.trim().parse::<usize>()) {
+        None => 1000,
+        Some(Ok(allocations)) if allocations > 0 => allocations,
+        Some(_) => {
+            fail!(out, "Usage: --profile compare [allocations], where allocations is greater than 0");
+            return;
+        }
+    };
+    let (cells, algorithm) = (heap.cells.len(), heap.policy.algorithm);
+    let tradeoffs: Vec<Tradeoff> = match Profile::ALL.iter().map(|&profile| profile::run(profile, cells, algorithm, allocations)).collect() {
+        Ok(tradeoffs) => tradeoffs,
+        Err(_) => {
+            fail!(out, "Not enough memory, a heap of {} cells is too small for the workload (it keeps a quarter of the heap)", cells);
+            return;
+        }
+    };
+
+    respond!(out, "Workload: {} allocations on a fresh heap of {} cells ({}), every fourth kept (up to a quarter of the heap)", allocations, cells, algorithm);
+    let row = |label: &str, value: &dyn Fn(&Tradeoff) -> String| {
+        tradeoffs.iter().fold(format!("    {:<16}", label), |row, tradeoff| format!("{}{:<16}", row, value(tradeoff)))
+    };
+    let lines: [String; 6] = [
+        row("", &|tradeoff| tradeoff.profile.to_string()),
+        row("collections", &|tradeoff| tradeoff.collections.to_string()),
+        row("total pause", &|tradeoff| format!("{:?}", tradeoff.total_pause)),
+        row("longest pause", &|tradeoff| format!("{:?}", tradeoff.longest_pause)),
+        row("mean pause", &|tradeoff| format!("{:?}", tradeoff.mean_pause())),
+        row("mutator time", &|tradeoff| format!("{:.1}%", tradeoff.mutator_share() * 100.0)),
+    ];
+    for line in lines {
+        respond!(out, "{}", line.trim_end());
+    }
+    respond!(out, "Throughput spends less of the run collecting, low-latency keeps each pause shorter. Apply one with --profile <name>");
+}
+
+/// Sets how much the heap's event log prints, from `--verbose` or `--quiet`.
+/// Passing `off` to either of them returns to the default (info) level.
+fn set_verbosity(heap: &mut Heap, level: Level, param: Option<&&str>, out: &mut dyn Write) {
+    let level: Level = match param.map(|p| p.trim()) {
+        Some("off") => Level::Info,
+        _ => level,
+    };
+
+    heap.log.level = level;
+    respond!(out, "Log level set to {}", level);
+}
+
+/// Starts (or stops) teeing the event log to a file, from `--log-file <path|off>`
+fn set_log_file(heap: &mut Heap, param: Option<&&str>, out: &mut dyn Write) {
+    match param.map(|p| p.trim()) {
+        Some("off") => {
+            heap.log.detach_file();
+            respond!(out, "No longer writing events to a log file");
+        }
+        Some(path) if !path.is_empty() => match heap.log.attach_file(path) {
+            Ok(()) => respond!(out, "Writing every event to {}", path),
+            Err(why) => fail!(out, "Unable to open log file '{}': {}", path, why),
+        },
+        _ => fail!(out, "Usage: --log-file <path|off>"),
+    }
+}
+
+/// Starts (or finishes) recording a Chrome trace of the session, from `--trace <path|off>`
+fn set_trace(heap: &mut Heap, param: Option<&&str>, out: &mut dyn Write) {
+    match param.map(|p| p.trim()) {
+        Some("off") => match heap.log.detach_trace() {
+            Ok(()) => respond!(out, "Trace finished"),
+            Err(why) => fail!(out, "Unable to finish the trace: {}", why),
+        },
+        Some(path) if !path.is_empty() => match heap.log.attach_trace(path) {
+            Ok(()) => respond!(out, "Tracing to {} (finished by --trace off or --exit, then load it in chrome://tracing or Perfetto)", path),
+            Err(why) => fail!(out, "Unable to open trace file '{}': {}", path, why),
+        },
+        _ => fail!(out, "Usage: --trace <path|off>"),
+    }
+}
+
+/// Starts (or stops) appending a row per collection to a CSV file, from `--metrics-csv <path|off>`
+fn set_metrics_csv(heap: &mut Heap, param: Option<&&str>, out: &mut dyn Write) {
+    match param.map(|p| p.trim()) {
+        Some("off") => {
+            heap.detach_metrics_csv();
+            respond!(out, "No longer writing collections to a metrics CSV");
+        }
+        Some(path) if !path.is_empty() => match heap.attach_metrics_csv(path) {
+            Ok(()) => respond!(out, "Appending a row to {} after every collection", path),
+            Err(why) => fail!(out, "Unable to open metrics CSV '{}': {}", path, why),
+        },
+        _ => fail!(out, "Usage: --metrics-csv <path|off>"),
+    }
+}
+
+/// Starts serving Prometheus metrics over HTTP, from `--serve-metrics <port> [address]`. They are only served to this
+/// machine unless an address to listen on is given (i.e. 0.0.0.0 for every interface)
+fn serve_metrics(heap: &mut Heap, param: Option<&&str>, address: Option<&&str>, out: &mut dyn Write) {
+    let port: u16 = match param.map(|p| p.trim().parse::<u16>()) {
+        Some(Ok(port)) => port,
+        _ => {
+            fail!(out, "Usage: --serve-metrics <port> [address]");
+            return;
+        }
+    };
+    let ip: IpAddr = match address.map(|a| a.trim()).filter(|a| !a.is_empty()) {
+        None => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        Some(address) => match address.parse::<IpAddr>() {
+            Ok(ip) => ip,
+            Err(_) => {
+                fail!(out, "Invalid address '{}', expected an IP address to listen on, such as 0.0.0.0", address);
+                return;
+            }
+        },
+    };
+
+    match heap.serve_metrics_on(ip, port) {
+        Ok(address) => respond!(out, "Serving metrics at http://{}/metrics", address),
+        Err(why) => fail!(out, "Unable to serve metrics on port {}: {}", port, why),
+    }
+}
+
+/// Starts serving a live view of the heap for the browser, from `--serve <port>` (see live.rs in the library).
+/// Commands sent from its page are run by `listen()`, alongside the ones typed at the prompt
+fn serve(heap: &mut Heap, session: &mut Session, param: Option<&&str>, out: &mut dyn Write) {
+    let port: u16 = match param.map(|p| p.trim().parse::<u16>()) {
+        Some(Ok(port)) => port,
+        _ => {
+            fail!(out, "Usage: --serve <port>");
+            return;
+        }
+    };
+
+    match heap.serve_live(port) {
+        Ok((address, commands)) => {
+            session.commands = Some(commands);
+            respond!(out, "Serving the live view at http://{}/ (state at /state, events at /events, POST commands to /command)", address);
+        }
+        Err(why) => fail!(out, "Unable to serve the live view on port {}: {}", port, why),
+    }
+}
+
+/// Exports the heap to a file for other tools to consume, from `--export <format> <path> [tag]` (a path of `-` writes it to
+/// the output instead). Given a tag, only the cells with it are exported (see tags.rs)
+/// #### Supported formats
+/// - `json` -> the whole pool (data, flags, edges and roots)
+/// - `dot` -> the reference graph, for rendering with Graphviz
+fn export(heap: &Heap, format: Option<&&str>, path: Option<&&str>, tag: Option<&&str>, out: &mut dyn Write) {
+    let format: &str = format.map(|f| f.trim()).unwrap_or("");
+    let path: &str = match path.map(|p| p.trim()) {
+        Some(path) if !path.is_empty() => path,
+        _ => {
+            fail!(out, "Usage: --export <format> <path> [tag]");
+            return;
+        }
+    };
+
+    let tag: Option<&str> = tag.map(|tag| tag.trim()).filter(|tag| !tag.is_empty());
+    let result = match (format, path, tag) {
+        ("json", "-", None) => heap.write_json(out),
+        ("dot", "-", None) => heap.write_dot(out),
+        ("json", _, None) => heap.export_json(path),
+        ("dot", _, None) => heap.export_dot(path),
+        ("json", "-", Some(_)) => writeln!(out, "{}", heap.to_json_tagged(tag)),
+        ("dot", "-", Some(_)) => out.write_all(heap.to_dot_tagged(tag).as_bytes()),
+        ("json", _, Some(_)) => fs::write(path, heap.to_json_tagged(tag) + "\n"),
+        ("dot", _, Some(_)) => fs::write(path, heap.to_dot_tagged(tag)),
+        _ => {
+            fail!(out, "Unknown export format '{}'. Avaliable formats: json, dot", format);
+            return;
+        }
+    };
+
+    match result {
+        Ok(()) if path == "-" => {}
+        Ok(()) => respond!(out, "Heap exported as {} to {}", format, path),
+        Err(why) => fail!(out, "Unable to export to '{}': {}", path, why),
+    }
+}
+
+/// Ends the session, from `--exit [code|--force]`.
+/// At an interactive prompt, leaving with uncollected garbage on the heap has to be confirmed (by --exit again, or --force).
+/// Without an explicit code, the exit code reports whether any command failed this session
+fn exit(heap: &Heap, session: &mut Session, param: Option<&&str>, out: &mut dyn Write) -> Flow {
+    let param: &str = param.map(|p| p.trim()).unwrap_or("");
+
+    if let Ok(code) = param.parse::<i32>() {
+        return Flow::Exit(code);
+    }
+
+    let garbage: Vec<usize> = heap.garbage();
+    if !garbage.is_empty() && param != "--force" && session.interactive && !session.confirm_exit {
+        session.confirm_exit = true;
+        respond!(out,
+            "{} cell(s) of garbage haven't been collected yet ({}). Run --gc first, or --exit again to leave anyway",
+            garbage.len(),
+            list_indexes(&garbage)
+        );
+        return Flow::Continue;
+    }
+
+    Flow::Exit(session.exit_code())
+}
+
+/// Builds a ready made scenario on the heap, from `--scenario <name> <n>`
+/// #### Supported scenarios
+/// - `cycle <n>` -> an n cell reference cycle detached from the roots, plus a reachable n cell cycle as a control group
+fn scenario(heap: &mut Heap, name: Option<&&str>, size: Option<&&str>, out: &mut dyn Write) {
+    let name: &str = name.map(|n| n.trim()).unwrap_or("");
+    let n: usize = match size.map(|s| s.trim().parse::<usize>()) {
+        None => 3,
+        Some(Ok(n)) if n > 0 => n,
+        Some(_) => {
+            fail!(out, "Usage: --scenario <name> <n>, where n is a number of cells greater than 0");
+            return;
+        }
+    };
+
+    let built: Built = match name {
+        "cycle" => match scenario::cycle(heap, n) {
+            Ok(built) => built,
+            Err(_) => {
+                fail!(out, "Not enough free memory, a {} cell cycle needs {} free cells (and one more if there are no roots)", n, 2 * n);
+                return;
+            }
+        },
+        _ => {
+            fail!(out, "Unknown scenario '{}'. Avaliable scenarios: cycle", name);
+            return;
+        }
+    };
+
+    respond!(out, "Reachable cycle (control group): {}", list_indexes(&built.reachable));
+    respond!(out, "Detached cycle (garbage): {}", list_indexes(&built.garbage));
+    match heap.policy.algorithm {
+        algorithm @ (Algorithm::RefCount | Algorithm::BufferedRc) => respond!(out, "Run --gc: {} never reclaims the detached cycle (compare with --set-policy algorithm mark-sweep)", algorithm),
+        algorithm => respond!(out, "Run --gc: {} reclaims the detached cycle (compare with --set-policy algorithm rc, which never will)", algorithm),
+    }
+    check_gc_threshold(heap);
+}
+
+/// Runs one of the ownership programs twice, with Rust ownership and then on a fresh heap of `cells` cells (6 by default)
+/// collected by the current algorithm, and compares where each model reclaimed memory. The session's heap is untouched
+fn compare_ownership(heap: &Heap, name: Option<&&str>, cells: Option<&&str>, out: &mut dyn Write) {
+    let name: &str = name.map(|n| n.trim()).unwrap_or("");
+    let Some(program) = ownership::program(name) else {
+        fail!(out, "Unknown program '{}'. Avaliable programs: {}", name, ownership::PROGRAMS.join(", "));
+        return;
+    };
+    let cells: usize = match cells.map(|c| c.trim().parse::<usize>()) {
+        None => 6,
+        Some(Ok(cells)) if cells > 0 => cells,
+        Some(_) => {
+            fail!(out, "Usage: --compare-ownership <program> [cells], where cells is the size of the heap (greater than 0)");
+            return;
+        }
+    };
+
+    let comparison: Comparison = match ownership::compare(&program, cells, heap.policy.algorithm) {
+        Ok(comparison) => comparison,
+        Err(_) => {
+            fail!(out, "Not enough memory, '{}' holds more objects at once than a heap of {} cells can free up room for under {}", name, cells, heap.policy.algorithm);
+            return;
+        }
+    };
+
+    let (owned, collected) = (&comparison.ownership, &comparison.gc);
+    let gc: String = format!("gc ({}, {} cells)", comparison.algorithm, comparison.cells);
+    respond!(out, "Program '{}': {}", program.name, program.about);
+    respond!(out, "    {:<14}{:<18}{}", "", "ownership (Rc)", gc);
+    let leaked = |run: &Run| if run.leaked.is_empty() { "-".to_string() } else { run.leaked.join(" ") };
+    let rows: [(&str, String, String); 6] = [
+        ("allocations", owned.allocations.to_string(), collected.allocations.to_string()),
+        ("frees", owned.frees.to_string(), collected.frees.to_string()),
+        ("peak objects", owned.peak_objects.to_string(), collected.peak_objects.to_string()),
+        ("peak bytes", owned.peak_bytes.to_string(), collected.peak_bytes.to_string()),
+        ("collections", owned.collections.to_string(), collected.collections.to_string()),
+        ("leaked", leaked(owned), leaked(collected)),
+    ];
+    for (label, owned, collected) in rows {
+        respond!(out, "    {:<14}{:<18}{}", label, owned, collected);
+    }
+
+    //Every step, and what each model freed there
+    respond!(out, "Where memory is reclaimed:");
+    let steps: Vec<String> = program.steps.iter().map(|step| step.to_string()).chain(["(end of scope)".to_string()]).collect();
+    for (number, step) in steps.iter().enumerate() {
+        let freed = |run: &Run| match run.reclaimed[number].as_slice() {
+            [] => String::new(),
+            freed => format!("frees {}", freed.join(" ")),
+        };
+        let line: String = format!("    {:>2}. {:<16}{:<18}{}", number + 1, step, freed(owned), freed(collected));
+        respond!(out, "{}", line.trim_end());
+    }
+    respond!(out, "Ownership frees at the step that drops the last owner, gc waits for the next collection");
+    respond!(out, "The gc run also reserves its whole pool up front: {} bytes for {} cells", comparison.cells * sizes::CELL_BYTES, comparison.cells);
+}
+
+/// Generates a random workload on the heap, from `--gen graph <nodes> <edges> <garbage-ratio> [tree|dag|graph]`.
+/// The garbage ratio is the fraction (0.0 - 1.0) of the generated cells that can't be reached from the roots
+fn generate(heap: &mut Heap, params: &[&str], out: &mut dyn Write) {
+    let params: Vec<&str> = params.iter().map(|p| p.trim()).collect();
+    let usage = "Usage: --gen graph <nodes> <edges> <garbage-ratio> [tree|dag|graph]";
+
+    if params.first() != Some(&"graph") {
+        fail!(out, "Unknown generator '{}'. Avaliable generators: graph", params.first().unwrap_or(&""));
+        return;
+    }
+
+    let nodes: Option<usize> = params.get(1).and_then(|n| n.parse::<usize>().ok());
+    let edges: Option<usize> = params.get(2).and_then(|e| e.parse::<usize>().ok());
+    let ratio: Option<f64> = params.get(3).and_then(|r| r.parse::<f64>().ok()).filter(|r| (0.0..=1.0).contains(r));
+    let shape: Option<Shape> = match params.get(4) {
+        None => Some(Shape::default()),
+        Some(name) => Shape::from_name(name),
+    };
+
+    let (nodes, edges, ratio, shape) = match (nodes, edges, ratio, shape) {
+        (Some(nodes), Some(edges), Some(ratio), Some(shape)) => (nodes, edges, ratio, shape),
+        (_, _, _, None) => {
+            fail!(out, "Unknown shape '{}', expected one of: {}", params[4], Shape::NAMES.join(", "));
+            return;
+        }
+        _ => {
+            fail!(out, "{}", usage);
+            return;
+        }
+    };
+
+    let built: Built = match scenario::graph(heap, nodes, edges, ratio, shape) {
+        Ok(built) => built,
+        Err(_) => {
+            fail!(out, "Not enough free memory, {} cells are needed (and one more if there are no roots)", nodes);
+            return;
+        }
+    };
+
+    respond!(out,
+        "Generated a {} of {} cells with {} references: {} reachable, {} garbage ({})",
+        shape,
+        nodes,
+        built.edges,
+        built.reachable.len(),
+        built.garbage.len(),
+        list_indexes(&built.garbage)
+    );
+    check_gc_threshold(heap);
+}
+
+/// Pops the innermost stack frame, from `--pop_frame`. Its locals stop being roots (unless an outer frame holds them)
+fn pop_frame(heap: &mut Heap, out: &mut dyn Write) {
+    let frame: Frame = match heap.pop_frame() {
+        Some(frame) => frame,
+        None => {
+            fail!(out, "There are no frames to pop, use --push_frame first");
+            return;
+        }
+    };
+
+    //Whatever only the frame kept alive is now garbage
+    let garbage: Vec<usize> = heap.garbage();
+    let released: Vec<usize> = frame.locals.iter().copied().filter(|local| garbage.contains(local)).collect();
+    if !released.is_empty() {
+        respond!(out, "Now unreachable (collected by the next --gc): {}", list_indexes(&released));
+    }
+}
+
+/// Makes a cell a local of the innermost stack frame, from `--local <cell> [name]`, so it is a root until the frame is popped.
+/// Giving a name also names the cell, like --name
+fn add_local(heap: &mut Heap, index: usize, name: Option<&&str>, out: &mut dyn Write) {
+    if let Err(why) = heap.add_local(index) {
+        match why {
+            FrameError::NoFrame => fail!(out, "There is no frame to add locals to, use --push_frame first"),
+            FrameError::OutOfBounds => fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len()),
+            FrameError::DataIsFree => fail!(out, "Cell {} is free, only cells in use can be locals", index),
+        }
+        return;
+    }
+
+    match name.map(|n| n.trim()) {
+        Some(name) if !name.is_empty() => name_cell(heap, index, Some(&name), out),
+        _ => {}
+    }
+    respond!(out, "Cell {} is a local of frame #{}", index, heap.frames().len());
+}
+
+/// Puts the heap back the way it was created, from `--reset [size]`
+fn reset(heap: &mut Heap, size: Option<&&str>, out: &mut dyn Write) {
+    let size: Option<usize> = match size.map(|s| s.trim()) {
+        None | Some("") => None,
+        Some(size) => match size.parse::<usize>() {
+            Ok(size) if size > 0 => Some(size),
+            _ => {
+                fail!(out, "Invalid size '{}', expected a number of cells greater than 0", size);
+                return;
+            }
+        },
+    };
+
+    heap.reset(size);
+}
+
+/// Saves the heap to a snapshot file, from `--save <path>`
+fn save(heap: &Heap, path: Option<&&str>, out: &mut dyn Write) {
+    match path.map(|p| p.trim()) {
+        Some(path) if !path.is_empty() => match heap.save(path) {
+            Ok(()) => respond!(out, "Heap saved to {}", path),
+            Err(why) => fail!(out, "Unable to save to '{}': {}", path, why),
+        },
+        _ => fail!(out, "Usage: --save <path>"),
+    }
+}
+
+/// Replaces the heap with an object graph from an edge list file, from `--import <path>`
+fn import(heap: &mut Heap, path: Option<&&str>, out: &mut dyn Write) {
+    match path.map(|p| p.trim()) {
+        Some(path) if !path.is_empty() => match heap.import(path) {
+            Ok(()) => {
+                let edges: usize = heap.cells.iter().map(|cell| cell.will_ref.len()).sum();
+                let roots: usize = scan_roots(&heap.cells).len();
+                respond!(out, "Imported {} ({} cells in use, {} references, {} roots)", path, heap.live(), edges, roots);
+            }
+            Err(why) => fail!(out, "Unable to import '{}': {}", path, why),
+        },
+        _ => fail!(out, "Usage: --import <path>"),
+    }
+}
+
+/// Replaces the heap with a previously saved snapshot, from `--load <path>`
+fn load(heap: &mut Heap, path: Option<&&str>, out: &mut dyn Write) {
+    match path.map(|p| p.trim()) {
+        Some(path) if !path.is_empty() => match heap.load(path) {
+            Ok(()) => respond!(out, "Heap loaded from {} ({} cells)", path, heap.cells.len()),
+            Err(why) => fail!(out, "Unable to load '{}': {}", path, why),
+        },
+        _ => fail!(out, "Usage: --load <path>"),
+    }
+}
+
+/// Every command `run_command()` accepts (used for tab completion at the prompt)
+#[cfg(feature = "readline")]
+const COMMANDS: [&str; 77] = [
+    "--help", "--root", "--unroot", "--roots", "--arb_ref", "--gc", "--stats", "--state", "--query", "--eval", "--watch", "--break", "--history", "--goto", "--diff", "--peek", "--reachable", "--why-live", "--retained", "--leaks", "--site", "--profile-allocs", "--histogram", "--verify", "--relocate", "--shrink", "--regions", "--safepoint", "--stress", "--pressure", "--mmu", "--push_frame", "--pop_frame", "--local", "--scenario", "--compare-ownership", "--gen", "--no-color", "--exit",
+    "--populate", "--alloc_at", "--alloc_str", "--list_new", "--alloc_in", "--intern", "--copy", "--move", "--swap", "--list_push", "--list_pop", "--record_new", "--field_set", "--free", "--link_ref", "--unlink_ref", "--set", "--name", "--region", "--tag", "--finalizer", "--weakmap_put", "--weakmap_get", "--set-policy", "--profile", "--verbose", "--quiet", "--log-file", "--trace",
+    "--metrics-csv", "--serve-metrics", "--serve", "--export", "--reset", "--save", "--load", "--import", "--tui",
+];
+
+/// Runs a line of input against the heap, writing any output to `out`.
+/// Several commands can be chained with `;` (i.e. `--root 0 19; --populate; --gc`), which run in order until one of them exits.
+/// Returns whether the program should carry on, or exit (and with what code)
+fn run_command(heap: &mut Heap, session: &mut Session, line: &str, out: &mut dyn Write) -> Flow {
+    let commands: Vec<&str> = line.split(';').map(|command| command.trim()).filter(|command| !command.is_empty()).collect();
+
+    //The heap as the session found it is step 0
+    if session.timeline.is_empty() {
+        session.timeline.push(Step { number: 0, command: "(start)".to_string(), checkpoint: heap.checkpoint() });
+    }
+
+    for command in &commands {
+        //Label each result when there are several, so it's clear which command said what
+        if commands.len() > 1 {
+            respond!(out, "> {}", command);
+        }
+
+        let before: usize = heap.log.sequence();
+        let flow: Flow = run_single(heap, session, command, out);
+        heap.relocate_step();                                       //Carrying on with the relocation in progress, if there is one
+        report_watches(heap, session, before, out);
+        record_step(heap, session, command);
+        if let Flow::Exit(code) = flow {
+            return Flow::Exit(code);
+        }
+    }
+
+    Flow::Continue
+}
+
+/// Adds the heap as `command` left it to the end of the timeline, if it changed anything in the memory pool
+/// (commands that only look at the heap, or move through the timeline with --goto, aren't steps).
+/// If --goto went back to an earlier step, the steps after it are dropped first -> the session carries on from there
+fn record_step(heap: &Heap, session: &mut Session, command: &str) {
+    if session.timeline.get(session.position).is_some_and(|step| step.checkpoint.cells() == heap.cells.as_slice()) {
+        return;
+    }
+
+    session.timeline.truncate(session.position + 1);
+    let number: usize = session.timeline.last().map_or(0, |step| step.number + 1);
+    session.timeline.push(Step { number, command: command.to_string(), checkpoint: heap.checkpoint() });
+    if session.timeline.len() > TIMELINE_STEPS {
+        session.timeline.remove(0);
+    }
+    session.position = session.timeline.len() - 1;
+}
+
+//Finds a step of the timeline by its number, failing if it is no longer (or not yet) held
+fn find_step(session: &Session, param: Option<&&str>, out: &mut dyn Write) -> Option<usize> {
+    let (first, last) = match (session.timeline.first(), session.timeline.last()) {
+        (Some(first), Some(last)) => (first.number, last.number),
+        _ => {
+            fail!(out, "There are no steps yet, run a command first");
+            return None;
+        }
+    };
+
+    match param.map(|p| p.trim().trim_start_matches('#').parse::<usize>()) {
+        Some(Ok(number)) if (first..=last).contains(&number) => Some(number - first),
+        Some(Ok(number)) => {
+            fail!(out, "There is no step {}, the history holds steps {} to {}", number, first, last);
+            None
+        }
+        _ => {
+            fail!(out, "Expected a step number, see --history");
+            None
+        }
+    }
+}
+
+/// Lists every step of the session's timeline, from `--history` -> each command, and what it changed on the heap.
+/// The step the heap is at now is marked with `>`
+fn history(session: &Session, out: &mut dyn Write) {
+    let mut previous: Option<&Step> = None;
+    for (i, step) in session.timeline.iter().enumerate() {
+        let changed: String = match previous {
+            None => String::new(),
+            Some(previous) => {
+                let diff: HeapDiff = HeapDiff::between(previous.checkpoint.cells(), step.checkpoint.cells());
+                let changes: Vec<String> = [
+                    (diff.allocated.len(), "allocated"),
+                    (diff.swept.len(), "freed"),
+                    (diff.linked.len(), "linked"),
+                    (diff.unlinked.len(), "unlinked"),
+                    (diff.data_changed.len(), "changed"),
+                ]
+                .into_iter()
+                .filter(|&(count, _)| count > 0)
+                .map(|(count, change)| format!("{} {}", count, change))
+                .collect();
+                match changes.is_empty() {
+                    true => String::new(),
+                    false => format!("  ({})", changes.join(", ")),
+                }
+            }
+        };
+        let current: &str = if i == session.position { ">" } else { " " };
+        respond!(out, "{} #{:<4} {}{}", current, step.number, step.command, changed);
+        previous = Some(step);
+    }
+
+    if session.timeline.is_empty() {
+        respond!(out, "Nothing has happened yet");
+    }
+}
+
+/// Puts the heap back as it was after a step of the timeline, from `--goto <step>`.
+/// Later steps are kept until the heap is changed, so --goto can move forward again too
+fn goto(heap: &mut Heap, session: &mut Session, param: Option<&&str>, out: &mut dyn Write) {
+    let Some(position) = find_step(session, param, out) else {
+        return;
+    };
+
+    let step: &Step = &session.timeline[position];
+    heap.restore(&step.checkpoint);
+    session.position = position;
+    respond!(out, "The heap is back at step #{} (after {})", step.number, step.command);
+
+    let later: usize = session.timeline.len() - 1 - position;
+    if later > 0 {
+        respond!(out, "Changing the heap from here drops the {} step(s) after it, until then --goto one of them to go forward again", later);
+    }
+}
+
+/// Shows what changed on the heap between two steps of the timeline, from `--diff <step> [step]`.
+/// Without a second step, compares against the step the heap is at now
+fn diff_steps(session: &Session, first: Option<&&str>, second: Option<&&str>, out: &mut dyn Write) {
+    let Some(from) = find_step(session, first, out) else {
+        return;
+    };
+    let to: usize = match second.map(|p| p.trim()) {
+        Some(param) if !param.is_empty() => match find_step(session, second, out) {
+            Some(to) => to,
+            None => return,
+        },
+        _ => session.position,
+    };
+
+    let (from, to): (&Step, &Step) = (&session.timeline[from], &session.timeline[to]);
+    respond!(out, "Step #{} ({}) -> step #{} ({}):", from.number, from.command, to.number, to.command);
+    respond!(out, "{}", HeapDiff::between(from.checkpoint.cells(), to.checkpoint.cells()));
+}
+
+/// Starts or stops watching cells, from `--watch [Cell|off] [off]`.
+/// Every event touching a watched cell is reported after the command it happened in (see `report_watches()`).
+/// With no cell given, lists the cells being watched
+fn watch(session: &mut Session, heap: &Heap, params: &[&str], out: &mut dyn Write) {
+    let params: Vec<&str> = params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+
+    match params.as_slice() {
+        [] if session.watches.is_empty() => respond!(out, "No cells are being watched, use --watch <Cell>"),
+        [] => respond!(out, "Watching cell(s): {}", list_indexes(&session.watches)),
+        ["off"] => {
+            session.watches.clear();
+            respond!(out, "Stopped watching every cell");
+        }
+        [cell] | [cell, "off"] => {
+            let index: usize = match parse_cells(heap, &[cell], out).first() {
+                Some(&index) if index < heap.cells.len() => index,
+                Some(&index) => {
+                    fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len());
+                    return;
+                }
+                None => return,
+            };
+
+            let watched: bool = session.watches.contains(&index);
+            match (params.len() == 2, watched) {
+                (false, false) => {
+                    session.watches.push(index);
+                    respond!(out, "Watching cell {}, everything that happens to it will be reported", index);
+                }
+                (false, true) => respond!(out, "Cell {} is already being watched", index),
+                (true, true) => {
+                    session.watches.retain(|&watched| watched != index);
+                    respond!(out, "Stopped watching cell {}", index);
+                }
+                (true, false) => fail!(out, "Cell {} isn't being watched", index),
+            }
+        }
+        _ => fail!(out, "Usage: --watch [Cell|off] [off]"),
+    }
+}
+
+/// Reports every event since sequence number `before` that touched a watched cell.
+/// A watch follows its cell when a copying collection moves it
+fn report_watches(heap: &Heap, session: &mut Session, before: usize, out: &mut dyn Write) {
+    if session.watches.is_empty() {
+        return;
+    }
+
+    let mut swept: Vec<usize> = Vec::new();             //Watched cells reclaimed by the collection in progress
+    for event in heap.log.since(before) {
+        for watched in session.watches.iter_mut() {
+            let index: usize = *watched;
+            let notice: String = match *event {
+                Event::Alloc { index: i, data } if i == index => format!("was allocated (data: {})", data),
+                Event::RefCreated { from, to } if to == index => format!("was linked to, from cell {}", from),
+                Event::RefCreated { from, to } if from == index => format!("now references cell {}", to),
+                Event::RefRemoved { from, to } if to == index => format!("was unlinked, cell {} no longer references it", from),
+                Event::RefRemoved { from, to } if from == index => format!("no longer references cell {}", to),
+                Event::DataSet { index: i, old: Some(old), new } if i == index => format!("was mutated, its data changed from {} to {}", old, new),
+                Event::DataSet { index: i, old: None, new } if i == index => format!("was mutated, its data set to {}", new),
+                Event::Rooted { index: i } if i == index => "was made a root".to_string(),
+                Event::Unrooted { index: i } if i == index => "is no longer a root".to_string(),
+                Event::Named { index: i, name: Some(ref name) } if i == index => format!("was named '{}'", name),
+                Event::Marked { index: i } if i == index => "was marked live".to_string(),
+                Event::Swept { index: i } if i == index => {
+                    swept.push(index);
+                    "was swept".to_string()
+                }
+                Event::Freed { index: i } if i == index => "was freed by hand".to_string(),
+                Event::Moved { from, to } if from == index => {
+                    *watched = to;
+                    format!("was moved to cell {}, the watch follows it there", to)
+                }
+                Event::CollectStart { .. } => {
+                    swept.clear();
+                    continue;
+                }
+                Event::Collected { number, .. } if !swept.contains(&index) => match heap.cells.get(index) {
+                    Some(cell) if !cell.freed => format!("survived GC #{} (age {})", number, cell.age),
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            respond!(out, "Watch: cell {} {}", index, notice);
+        }
+    }
+}
+
+/// Sets or removes a breakpoint, from `--break [on-mark-phase | on-sweep <Cell>] [off]`.
+/// A collection that reaches one pauses in `pause()`. With nothing given, lists the breakpoints that are set,
+/// and `--break off` removes them all
+fn set_breakpoint(heap: &mut Heap, session: &Session, params: &[&str], out: &mut dyn Write) {
+    let params: Vec<&str> = params.iter().map(|p| p.trim()).filter(|p| !p.is_empty()).collect();
+    let (params, off): (&[&str], bool) = match params.split_last() {
+        Some((&"off", rest)) => (rest, true),
+        _ => (&params, false),
+    };
+
+    let breakpoint: Breakpoint = match params {
+        [] if off => {
+            heap.clear_breakpoints();
+            respond!(out, "Every breakpoint removed");
+            return;
+        }
+        [] => {
+            match heap.breakpoints() {
+                [] => respond!(out, "No breakpoints are set, use --break on-mark-phase or --break on-sweep <Cell>"),
+                set => respond!(out, "Breakpoints: {}", set.iter().map(|b| b.to_string()).collect::<Vec<String>>().join(", ")),
+            }
+            return;
+        }
+        ["on-mark-phase"] => Breakpoint::MarkPhase,
+        ["on-sweep", cell] => match parse_cells(heap, &[cell], out).first() {
+            Some(&index) if index < heap.cells.len() => Breakpoint::Sweep(index),
+            Some(&index) => {
+                fail!(out, "There is no cell {}, the heap has {} cells", index, heap.cells.len());
+                return;
+            }
+            None => return,
+        },
+        _ => {
+            fail!(out, "Usage: --break [on-mark-phase | on-sweep <Cell>] [off]");
+            return;
+        }
+    };
+
+    match off {
+        true if heap.remove_breakpoint(breakpoint) => respond!(out, "Breakpoint {} removed", breakpoint),
+        true => fail!(out, "There is no breakpoint {}", breakpoint),
+        false if !heap.add_breakpoint(breakpoint) => respond!(out, "Breakpoint {} is already set", breakpoint),
+        false => {
+            let color: bool = session.color;
+            heap.on_break(move |heap, breakpoint| pause(heap, breakpoint, color));
+            respond!(out, "Breakpoint {} set, the next collection to reach it will pause", breakpoint);
+        }
+    }
+}
+
+/// The prompt a collection drops into when it reaches a breakpoint, see `set_breakpoint()`.
+/// The heap can be looked at but not changed (the collection is still part way through), until --continue
+fn pause(heap: &Heap, breakpoint: Breakpoint, color: bool) {
+    //Nothing can be typed while the TUI has the screen, so it carries straight on
+    if !heap.log.echo {
+        return;
+    }
+
+    let out: &mut dyn Write = &mut io::stdout();
+    respond!(out, "Collection paused at {}. Inspect the heap with --state, --query, --peek, --reachable, --why-live, --retained, --leaks, --verify, --roots or --stats, then --continue", breakpoint);
+
+    let mut prompt: Prompt = Prompt::new();
+    while let Some(line) = prompt.read_line(heap) {
+        let input: Vec<&str> = line.trim().split(' ').collect();
+        let (fparam, sparam) = (input.get(1), input.get(2));
+        let index1 = |out: &mut dyn Write| parse_cell(heap, fparam, out);
+
+        match input[0] {
+            "" => {}
+            "--continue" => return,
+            "--state" => state(heap, fparam, color, out),
+            "--query" => query(heap, &input[1..], color, out),
+            "--peek" => if let Some(index) = index1(out) {
+                peek(heap, index, out)
+            },
+            "--reachable" => if let Some(index) = index1(out) {
+                reachable(heap, index, out)
+            },
+            "--why-live" => if let Some(index) = index1(out) {
+                why_live(heap, index, sparam, out)
+            },
+            "--retained" => if let Some(index) = index1(out) {
+                retained(heap, index, out)
+            },
+            "--leaks" => leaks(heap, out),
+            "--verify" => verify(heap, out),
+            "--roots" => list_roots(heap, out),
+            "--stats" => view_stats(heap, out),
+            _ => fail!(out, "The collection is paused at {}, only commands that look at the heap can run until --continue", breakpoint),
+        }
+    }
+}
+
+/// Runs a single command against the heap, writing any output to `out`
+/// 
+/// #### Accepted commands
+/// ```
+/// "--root" => root(heap, input[1..]),                        //Root any number of cells (in a named root set), or default 0 and the last cell
+/// "--unroot" => unroot_cells(heap, input[1..]),               //Unroot a cell, a whole root set, or everything
+/// "--roots" => list_roots(heap),                              //List every root set, and the frames' locals
+/// "--arb_ref" => create_free_ref(cells, index1), //Run as many times as specified
+/// "--gc" => gc(heap, input[1..]), //Run the garbage collector (or a minor, major or full collection), --gc --diff shows what changed
+/// "--relocate" => relocate(heap, fparam),                   //Compact the heap concurrently, a few cells after every command
+/// "--shrink" => shrink(heap, fparam),                        //Release free cells at the end of the heap
+/// "--regions" => regions(heap),                              //The region table, with each region's liveness
+/// "--stats" => view_stats(heap), //GC history (pause times per phase)
+/// "--safepoint" => safepoint(heap, fparam, sparam),          //Collect once simulated mutator threads park at a safepoint
+/// "--stress" => stress(heap, input[1..]),                     //Random mutator threads against the heap while it is collected, then verify
+/// "--pressure" => pressure(heap, fparam, sparam),           //Allocate at a steady rate, to see whether the policy keeps up
+/// "--mmu" => mmu(heap, input[1..]),                           //Minimum mutator utilization over windows of time
+/// "--state" => state(heap, fparam, color),                 //The heap table (only the cells with a tag, if one is given)
+/// "--query" => query(heap, input[1..], color),               //Show only the cells matching a query, i.e. "freed==false && marked==false"
+/// "--eval" => eval(heap, session, input[1..]),                //Run a statement of the tiny language, i.e. let xs = cons(1, nil)
+/// "--watch" => watch(session, heap, input[1..]),          //Report everything that happens to a cell after each command
+/// "--break" => set_breakpoint(heap, session, input[1..]),  //Pause collections on-mark-phase or on-sweep <Cell>, to inspect the heap
+/// "--history" => history(session),                         //Every command of the session, and what it changed
+/// "--goto" => goto(heap, session, fparam),                  //Put the heap back as it was after a step of --history
+/// "--diff" => diff_steps(session, fparam, sparam),          //What changed on the heap between two steps
+/// "--no-color" => session.color = false,                    //Plain output for piping (--no-color off to undo)
+/// "--exit" => exit(heap, session, fparam),                  //End the session (returns Flow::Exit)
+/// "--populate" => populate_remaining(cells),
+/// "--alloc_at" => handle_prompt_allocation(cells, index1),
+/// "--alloc_str" => alloc_str(heap, input[1..]),              //Allocate a string, a cell for every 4 bytes of it
+/// "--list_new" => list_new(heap, fparam),                   //Allocate an empty list
+/// "--alloc_in" => alloc_in(heap, fparam),                   //Allocate in a named region, which may be full
+/// "--intern" => intern(heap, input[1..]),                    //Allocate an int or string, sharing an equal one already interned
+/// "--copy" => copy(heap, input[1..]),                        //Copy a cell (and, deep, what it reaches) into free cells
+/// "--move" => move_cell(heap, index1, index2),              //Move a cell to a free cell, rewriting what points at it
+/// "--swap" => swap(heap, index1, index2),                   //Exchange the objects in two cells, and what points at them
+/// "--list_push" => list_push(heap, index1, index2),        //Push cell 2 onto the end of list 1
+/// "--list_pop" => list_pop(heap, index1),                  //Pop the last element off a list
+/// "--record_new" => record_new(heap, input[1..]),            //Allocate a record with named fields
+/// "--field_set" => field_set(heap, index1, sparam, input[3]), //Point a record's field at a cell
+/// "--link_ref" => assign_reference(cells, index1, index2),    //Cell 1 references Cell 2
+/// "--unlink_ref" => unlink_reference(heap, index1, index2),  //Cell 1 no longer references Cell 2
+/// "--name" => name_cell(heap, index1, sparam),              //Name a cell, so it can be used in place of its index
+/// "--region" => region(heap, fparam, sparam),               //Set a range of the pool aside under a name
+/// "--tag" => tag(heap, input[1..]),                        //Group cells under a label, to pick them out in --query, --state and --export
+/// "--finalizer" => finalizer(heap, fparam, sparam),         //Run code when a cell is found dead, which may resurrect it
+/// "--weakmap_put" => weakmap_put(heap, index1, index2),      //Map a key cell to a value cell, without keeping the key alive
+/// "--weakmap_get" => weakmap_get(heap, fparam),              //Look a key up in the weak map (or list every entry)
+/// "--set" => set_cell_data(heap, index1, sparam),           //Overwrite the data in a cell
+/// "--peek" => peek(heap, index1),                            //Print everything about a single cell
+/// "--reachable" => reachable(heap, index1),                //Would the cell survive a collection? (and via which roots)
+/// "--why-live" => why_live(heap, index1, sparam),          //Shortest chain(s) of references from a root to the cell
+/// "--retained" => retained(heap, index1),                  //How many cells would go if the references to the cell were cut
+/// "--leaks" => leaks(heap),                                 //List cells in use that no root can reach
+/// "--site" => set_site(session, input[1..]),                //Tag allocations with a label rather than the command that made them
+/// "--profile-allocs" => profile_allocs(heap),               //Allocations, survival and garbage per allocation site
+/// "--histogram" => histogram(heap, fparam),                 //Text histogram of the live cells' ages, fan-in, sizes or types
+/// "--verify" => verify(heap),                               //Check the heap's structural invariants
+/// "--free" => free_cell_by_hand(heap, index1, sparam),      //Free a cell by hand, removing every reference to it
+/// "--set-policy" => set_policy(heap, fparam, sparam),       //Configure when the collector runs
+/// "--profile" => profile(heap, fparam, sparam),             //Tune the policy for throughput or latency, or compare the two
+/// "--verbose" => set_verbosity(heap, Level::Debug, fparam), //Show every event (including GC phases)
+/// "--quiet" => set_verbosity(heap, Level::Quiet, fparam),   //Only show warnings
+/// "--log-file" => set_log_file(heap, fparam),               //Tee every event to a file
+/// "--trace" => set_trace(heap, fparam),                     //Record a Chrome trace of the session
+/// "--metrics-csv" => set_metrics_csv(heap, fparam),         //Append a row per collection to a CSV file
+/// "--serve-metrics" => serve_metrics(heap, fparam, sparam), //Serve Prometheus metrics over HTTP (to this machine, unless given an address)
+/// "--serve" => serve(heap, session, fparam),               //Serve a live view of the heap to the browser
+/// "--export" => export(heap, fparam, sparam, input[3]),     //Write the heap out as json or dot (only the cells with a tag, if one is given)
+/// "--push_frame" => heap.push_frame(),                     //Start a stack frame, allocations inside it are roots until it is popped
+/// "--pop_frame" => pop_frame(heap),                         //Pop the innermost frame, unrooting its locals
+/// "--local" => add_local(heap, index1, sparam),             //Make a cell a root for the lifetime of the innermost frame
+/// "--scenario" => scenario(heap, fparam, sparam),           //Build a ready made scenario, i.e. a garbage cycle
+/// "--compare-ownership" => compare_ownership(heap, fparam, sparam), //Run a program with Rust ownership and on a heap, side by side
+/// "--gen" => generate(heap, input[1..]),                      //Generate a random object graph
+/// "--reset" => reset(heap, fparam),                         //Start again with a fresh pool
+/// "--save" => save(heap, fparam),                           //Snapshot the heap to a file
+/// "--load" => load(heap, fparam),                           //Restore a snapshot
+/// "--import" => import(heap, fparam),                       //Build the heap from an edge list file
+/// "--tui" => tui::run(heap, session),                                //Switch to the full screen dashboard
+/// _ => println!("Unknown command. Type 'help' for assistance."), //Default if command doesn't match
+/// ```
+/// Commands that allocate memory are followed by a `check_gc_threshold()`, which may trigger a collection.
+fn run_single(heap: &mut Heap, session: &mut Session, line: &str, out: &mut dyn Write) -> Flow {
+    let input: Vec<&str> = line.split(' ').collect();       //remove whitespace
+                                                            //Get the first command
+    let command: &str = input[0];
+    let mut flow: Flow = Flow::Continue;
+
+    //A held back --exit only goes ahead if it is the very next command
+    if command.trim() != "--exit" {
+        session.confirm_exit = false;
+    }
+    //Commands can take up to 2 inputs
+    let fparam: Option<&&str> = input.get(1);       //&& reference to a reference
+    let sparam: Option<&&str> = input.get(2);       //&& reference to a reference
+
+    //these parameters are cell index positions for most commands, so make adjustments
+    //...parsed lazily, as commands such as --set-policy take names rather than indexes
+    //...and can also be the name of a cell (given with --name). A command isn't run if its cells can't be parsed
+    let index1 = |heap: &Heap, out: &mut dyn Write| parse_cell(heap, fparam, out);
+    let index2 = |heap: &Heap, out: &mut dyn Write| parse_cell(heap, sparam, out);
+    let last: usize = heap.cells.len() - 1;            //The last cell, for --help
+
+    //Allocations are tagged with the command that made them, unless --site gave a label (see sites.rs)
+    heap.set_site(Some(session.site.as_deref().unwrap_or(command.trim())));
+
+    //Seperate values
+
+    match command.trim() {
+        "--help" => respond!(out,
+            "\nAvaliable Commands:
+    1. --root [Cell...](0-{last}) [--set <name>]
+    2. --unroot [Cell... | --set <name>]
+    3. --roots
+    4. --push_frame
+    5. --pop_frame
+    6. --local <Cell> [name]
+    7. --arb_ref <amount_of_times>
+    8. --link_ref <Cell 1> *references...->* <Cell 2>
+    9. --unlink_ref <Cell 1> *no longer references...->* <Cell 2>
+    10. --alloc_at <Cell>
+    11. --alloc_str [Cell] \"text\"
+    12. --list_new [Cell]
+    13. --alloc_in <region>
+    14. --intern <int | \"text\">
+    15. --copy <src> [dst] [deep]
+    16. --move <src> <dst>
+    17. --swap <a> <b>
+    18. --list_push <List> <Cell>
+    19. --list_pop <List>
+    20. --record_new <type> [field[=Cell]...]
+    21. --field_set <Cell> <field> <Cell|off>
+    22. --free <Cell> [--force]
+    23. --set <Cell> <value>
+    24. --name <Cell> <name|off>
+    25. --region [<name> <start>..<end> | <name> off]
+    26. --tag [<Cell> <tag> [off]]
+    27. --finalizer [<Cell> [resurrect]]
+    28. --weakmap_put <Key> <Value>
+    29. --weakmap_get [Key]
+    30. --state [tag]
+    31. --query \"<expr>\"
+    32. --eval [statement]
+    33. --watch [Cell|off] [off]
+    34. --break [on-mark-phase | on-sweep <Cell>] [off]
+    35. --history
+    36. --goto <step>
+    37. --diff <step> [step]
+    38. --peek <Cell>
+    39. --reachable <Cell>
+    40. --why-live <Cell> [--all]
+    41. --retained <Cell>
+    42. --leaks
+    43. --site <label|off>
+    44. --profile-allocs
+    45. --histogram <age|refs|size|type>
+    46. --verify
+    47. --populate
+    48. --gc [minor|major|full] [--diff]
+    49. --relocate [cells|finish]
+    50. --shrink [cells]
+    51. --regions
+    52. --stats
+    53. --safepoint [threads] [max-poll-gap µs]
+    54. --stress [threads=<n>] [ops=<m>]
+    55. --pressure <cells-per-tick> <ticks>
+    56. --mmu [window...]
+    57. --set-policy <gc-threshold <percent|off> | algorithm <mark-sweep|copying|rc|generational|treadmill|g1|buffered-rc|hybrid> | mark-order <dfs|bfs> | mark-stack <size|off> | periodic-gc <interval|off> | tenuring-threshold <collections> | survivor-size <cells|off> | verify-remembered <on|off> | region-size <cells> | pause-budget <duration|off> | intern-threshold <n> | max-free <percent|off> | barriers <on|off>>
+    58. --profile <throughput|low-latency | compare [allocations]>
+    59. --verbose [off]
+    60. --quiet [off]
+    61. --log-file <path|off>
+    62. --trace <path|off>
+    63. --metrics-csv <path|off>
+    64. --serve-metrics <port> [address]
+    65. --serve <port>
+    66. --export <json|dot> <path|-> [tag]
+    67. --reset [size]
+    68. --scenario cycle [n]
+    69. --compare-ownership <list|shared|cycle> [cells]
+    70. --gen graph <nodes> <edges> <garbage-ratio> [tree|dag|graph]
+    71. --save <path>
+    72. --load <path>
+    73. --import <path>
+    74. --tui
+    75. --no-color [off]
+    76. --exit [code|--force]
+
+Chain several commands on one line with ';', i.e. --root 0 {last}; --populate; --gc"
+        ), //Print a the accepted list of commands
+        "--root" => root(heap, &input[1..], out),              //Root cells, or default 0 and the last cell
+        "--unroot" => unroot_cells(heap, &input[1..], out),   //Unroot a cell, a root set, or everything
+        "--roots" => list_roots(heap, out),
+        "--arb_ref" => {
+            let times = parse_param_to_usize(fparam, 0, out);
+            create_free_ref(heap, times);                   //Run as many times as specified
+            check_gc_threshold(heap);
+        }
+        "--gc" => gc(heap, &input[1..], out),
+        "--relocate" => relocate(heap, fparam, out),
+        "--shrink" => shrink(heap, fparam, out),
+        "--regions" => regions(heap, out),                          //The region table, with each region's liveness
+        "--stats" => view_stats(heap, out),
+        "--safepoint" => safepoint(heap, fparam, sparam, out),
+        "--stress" => stress(heap, &input[1..], out),
+        "--pressure" => pressure(heap, fparam, sparam, out),
+        "--mmu" => mmu(heap, &input[1..], out),
+        "--state" => state(heap, fparam, session.color, out),
+        "--query" => query(heap, &input[1..], session.color, out),
+        "--eval" => eval(heap, session, &input[1..], out),
+        "--watch" => watch(session, heap, &input[1..], out),
+        "--break" => set_breakpoint(heap, session, &input[1..], out),
+        "--history" => history(session, out),
+        "--goto" => goto(heap, session, fparam, out),
+        "--diff" => diff_steps(session, fparam, sparam, out),
+        "--no-color" => {
+            session.color = matches!(fparam.map(|p| p.trim()), Some("off"));
+            respond!(out, "Colour output {}", if session.color { "enabled" } else { "disabled" });
+        }
+        "--exit" => flow = exit(heap, session, fparam, out),
+        "--populate" => {
+            populate_remaining(heap);
+            check_gc_threshold(heap);
+        }
+        "--alloc_at" => {
+            if let Some(index) = index1(heap, out) {
+                handle_prompt_allocation(heap, index, out);
+            }
+            check_gc_threshold(heap);
+        }
+        "--alloc_str" => {
+            alloc_str(heap, &input[1..], out);
+            check_gc_threshold(heap);
+        }
+        "--list_new" => {
+            list_new(heap, fparam, out);
+            check_gc_threshold(heap);
+        }
+        "--alloc_in" => {
+            alloc_in(heap, fparam, out);
+            check_gc_threshold(heap);
+        }
+        "--intern" => {
+            intern(heap, &input[1..], out);
+            check_gc_threshold(heap);
+        }
+        "--copy" => {
+            copy(heap, &input[1..], out);
+            check_gc_threshold(heap);
+        }
+        "--move" => {
+            if let Some((src, dst)) = index1(heap, out).zip(index2(heap, out)) {
+                move_cell(heap, src, dst, out);
+            }
+        }
+        "--swap" => {
+            if let Some((a, b)) = index1(heap, out).zip(index2(heap, out)) {
+                swap(heap, a, b, out);
+            }
+        }
+        "--list_push" => {
+            if let Some((list, element)) = index1(heap, out).zip(index2(heap, out)) {
+                list_push(heap, list, element, out);
+            }
+        }
+        "--list_pop" => {
+            if let Some(list) = index1(heap, out) {
+                list_pop(heap, list, out);
+            }
+        }
+        "--record_new" => {
+            record_new(heap, &input[1..], out);
+            check_gc_threshold(heap);
+        }
+        "--field_set" => {
+            if let Some(index) = index1(heap, out) {
+                field_set(heap, index, sparam, input.get(3), out);
+            }
+        }
+        "--link_ref" => {
+            if let Some((a, b)) = index1(heap, out).zip(index2(heap, out)) {
+                assign_reference(heap, a, b);                   //Cell 1 references Cell 2
+            }
+        }
+        "--reachable" => {
+            if let Some(index) = index1(heap, out) {
+                reachable(heap, index, out);
+            }
+        }
+        "--why-live" => {
+            if let Some(index) = index1(heap, out) {
+                why_live(heap, index, sparam, out);
+            }
+        }
+        "--retained" => {
+            if let Some(index) = index1(heap, out) {
+                retained(heap, index, out);
+            }
+        }
+        "--leaks" => leaks(heap, out),
+        "--site" => set_site(session, &input[1..], out),
+        "--profile-allocs" => profile_allocs(heap, out),
+        "--histogram" => histogram(heap, fparam, out),
+        "--verify" => verify(heap, out),
+        "--push_frame" => {
+            heap.push_frame();
+        }
+        "--pop_frame" => pop_frame(heap, out),
+        "--local" => {
+            if let Some(index) = index1(heap, out) {
+                add_local(heap, index, sparam, out);
+            }
+        }
+        "--scenario" => scenario(heap, fparam, sparam, out),
+        "--compare-ownership" => compare_ownership(heap, fparam, sparam, out),
+        "--gen" => generate(heap, &input[1..], out),
+        "--free" => {
+            if let Some(index) = index1(heap, out) {
+                free_cell_by_hand(heap, index, sparam, out);
+            }
+        }
+        "--peek" => {
+            if let Some(index) = index1(heap, out) {
+                peek(heap, index, out);
+            }
+        }
+        "--set" => {
+            if let Some(index) = index1(heap, out) {
+                set_cell_data(heap, index, sparam, out);
+            }
+        }
+        "--unlink_ref" => {
+            if let Some((a, b)) = index1(heap, out).zip(index2(heap, out)) {
+                unlink_reference(heap, a, b);                   //Cell 1 no longer references Cell 2
+            }
+        }
+        "--name" => {
+            if let Some(index) = index1(heap, out) {
+                name_cell(heap, index, sparam, out);
+            }
+        }
+        "--region" => region(heap, fparam, sparam, out),             //Set a range of the pool aside under a name
+        "--tag" => tag(heap, &input[1..], out),                      //Group cells under a label, to pick them out in --query, --state and --export
+        "--finalizer" => finalizer(heap, fparam, sparam, out),       //Run code when a cell is found dead, which may resurrect it
+        "--weakmap_put" => {
+            if let Some((key, value)) = index1(heap, out).zip(index2(heap, out)) {
+                weakmap_put(heap, key, value, out);
+            }
+        }
+        "--weakmap_get" => weakmap_get(heap, fparam, out),
+        "--set-policy" => set_policy(heap, fparam, sparam, out),    //Configure when the collector runs
+        "--profile" => profile(heap, fparam, sparam, out),
+        "--verbose" => set_verbosity(heap, Level::Debug, fparam, out),
+        "--quiet" => set_verbosity(heap, Level::Quiet, fparam, out),
+        "--log-file" => set_log_file(heap, fparam, out),
+        "--trace" => set_trace(heap, fparam, out),
+        "--metrics-csv" => set_metrics_csv(heap, fparam, out),
+        "--serve-metrics" => serve_metrics(heap, fparam, sparam, out),
+        "--serve" => serve(heap, session, fparam, out),
+        "--export" => export(heap, fparam, sparam, input.get(3), out),
+        "--reset" => reset(heap, fparam, out),
+        "--save" => save(heap, fparam, out),
+        "--load" => load(heap, fparam, out),
+        "--import" => import(heap, fparam, out),
+        #[cfg(feature = "tui")]
+        "--tui" => match tui::run(heap, session) {
+            Ok(tui::Leave::Prompt) => respond!(out, "Left the TUI, back at the prompt"),
+            Ok(tui::Leave::Exit(code)) => flow = Flow::Exit(code),
+            Err(why) => fail!(out, "Unable to start the TUI: {}", why),
+        },
+        #[cfg(not(feature = "tui"))]
+        "--tui" => respond!(out, "This build doesn't include the TUI, rebuild with the 'tui' feature enabled"),
+        _ => {
+            fail!(out, "Unknown command. Type 'help' for assistance."); //Default if command doesn't match
+        }
+    }
+
+    heap.publish();                                         //Keep the metrics endpoint and live view (if running) up to date
+    flow
+}
+
+/// Runs every command in a script file, echoing each one to `out` (along with its output) so it can be followed.
+/// Blank lines and lines starting with `#` are skipped. Stops early if the script runs --exit
+fn run_script(heap: &mut Heap, session: &mut Session, path: &PathBuf, out: &mut dyn Write) -> io::Result<Flow> {
+    let script: String = fs::read_to_string(path)?;
+
+    for line in script.lines().map(|line| line.trim()) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        respond!(out, "> {}", line);
+        if let Flow::Exit(code) = run_command(heap, session, line, out) {
+            return Ok(Flow::Exit(code));
+        }
+    }
+
+    Ok(Flow::Continue)
+}
+
+/// Listens for user input, running each line with `run_command()` and printing the results to stdout.
+/// Stops at --exit, or once there is no more input (Ctrl+D, or the end of a piped script), returning the exit code
+fn listen(heap: &mut Heap, session: &mut Session) -> i32 {
+    let mut prompt: Prompt = Prompt::new();
+
+    //while accepting commands
+    while session.commands.is_none() {
+        if heap.policy.periodic_gc.is_some() {
+            match listen_periodic(heap, session, &mut prompt) {
+                Some(code) => return code,
+                None => continue,
+            }
+        }
+
+        let Some(input) = prompt.read_line(heap) else {
+            return session.exit_code();
+        };
+        if let Flow::Exit(code) = run_command(heap, session, &input, &mut io::stdout()) {
+            return code;
+        }
+    }
+
+    serve_commands(heap, session, prompt)
+}
+
+/// `listen()` while --set-policy periodic-gc is on -> a timer thread collects the heap every interval (see periodic.rs).
+/// This thread is the only mutator it stops the world for. It reaches a safepoint after every command,
+/// and is idle (so holds nothing up) while it waits at the prompt. Returns the exit code once the session ends,
+/// or None once periodic collection is turned off or --serve starts (which reads commands its own way)
+fn listen_periodic(heap: &mut Heap, session: &mut Session, prompt: &mut Prompt) -> Option<i32> {
+    let mut interval: Duration = heap.policy.periodic_gc?;
+    let (changes, changed) = mpsc::channel::<Duration>();
+    let shared: SharedHeap = SharedHeap::new(heap);
+
+    let first: Duration = interval;
+
+    thread::scope(|scope| {
+        scope.spawn(|| periodic::run(&shared, first, changed));
+        let mutator: Mutator = shared.attach();
+
+        let code: Option<i32> = loop {
+            let completions: Completions = shared.with(|heap| Completions::of(heap));
+            let idle: Idle = mutator.idle();
+            let line: Option<String> = prompt.read_completing(completions);
+            drop(idle);
+            let Some(line) = line else {
+                break Some(session.exit_code());
+            };
+
+            let flow: Flow = shared.with(|heap| {
+                record_step(heap, session, "(periodic collection)");        //Anything collected while waiting for the line
+                run_command(heap, session, &line, &mut io::stdout())
+            });
+            if let Flow::Exit(code) = flow {
+                break Some(code);
+            }
+            match shared.with(|heap| heap.policy.periodic_gc) {
+                Some(now) if session.commands.is_none() => {
+                    if now != interval {
+                        interval = now;
+                        let _ = changes.send(now);
+                    }
+                }
+                _ => break None,
+            }
+            mutator.poll();
+        };
+
+        //The timer may be waiting for this thread to park, so it has to detach before the timer is stopped
+        drop(mutator);
+        drop(changes);
+        code
+    })
+}
+
+/// Where the next command came from, once the live view is being served
+enum Input {
+    Typed(Option<String>),          //A line from the prompt (None once there is no more input)
+    Sent(Command),                  //A command sent from the live view's page
+}
+
+/// `listen()` once --serve has started the live view -> commands can come from the prompt or the page, whichever is first.
+/// The prompt is read on a thread of its own so the main thread (which owns the heap) can wait for both,
+/// and is only asked for a line once the last one has run (so a paused collection can still read from it)
+fn serve_commands(heap: &mut Heap, session: &mut Session, mut prompt: Prompt) -> i32 {
+    let (sender, inputs) = mpsc::channel::<Input>();
+    let (ask, asked) = mpsc::channel::<Completions>();
+
+    if let Some(commands) = session.commands.take() {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for command in commands {
+                if sender.send(Input::Sent(command)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    thread::spawn(move || {
+        for completions in asked {
+            let line: Option<String> = prompt.read_completing(completions);
+            let ended: bool = line.is_none();
+            if sender.send(Input::Typed(line)).is_err() || ended {
+                break;
+            }
+        }
+    });
+
+    let _ = ask.send(Completions::of(heap));
+    for input in inputs {
+        match input {
+            Input::Typed(Some(line)) => {
+                if let Flow::Exit(code) = run_command(heap, session, &line, &mut io::stdout()) {
+                    return code;
+                }
+                let _ = ask.send(Completions::of(heap));
+            }
+            //The page can still send commands, so the session carries on until it is stopped
+            Input::Typed(None) => println!("There is no more input, still serving the live view (Ctrl+C to stop)"),
+            Input::Sent(command) => run_sent(heap, session, command),
+        }
+    }
+
+    session.exit_code()
+}
+
+/// Commands the live view's page can't run -> they end the session, take over the terminal, start another server,
+/// or read or write files
+const TERMINAL_ONLY: [&str; 11] = ["--exit", "--tui", "--serve", "--serve-metrics", "--save", "--load", "--import", "--export", "--log-file", "--trace", "--metrics-csv"];
+
+/// Runs a command sent from the live view's page, replying with its output (also shown at the terminal, so it can be followed).
+/// The page can't run the TERMINAL_ONLY commands, and collections don't pause for it (nothing could --continue)
+fn run_sent(heap: &mut Heap, session: &mut Session, command: Command) {
+    println!("[live view] > {}", command.line);
+
+    let mut output: Vec<u8> = Vec::new();
+    let refused: Option<&str> = command
+        .line
+        .split(';')
+        .filter_map(|single| single.split_whitespace().next())
+        .find(|name| TERMINAL_ONLY.contains(name));
+    if let Some(name) = refused {
+        fail!(&mut output, "{} can only be run from the terminal, the live view can't end the session or touch files", name);
+    } else {
+        let (before, echo) = (heap.log.sequence(), heap.log.echo);
+        heap.log.echo = false;
+        run_command(heap, session, &command.line, &mut output);
+        heap.log.echo = echo;
+
+        //The page gets events from /events, the terminal gets them here (as they would have been printed)
+        for event in heap.log.since(before).filter(|event| event.level() <= heap.log.level) {
+            println!("{}", event);
+        }
+    }
+
+    let output: String = String::from_utf8_lossy(&output).into_owned();
+    print!("{}", output);
+    command.reply(output);
+}
+
+/// Ends the program with `code`, finishing anything that would otherwise be left incomplete
+fn finish(heap: &mut Heap, code: i32) -> ! {
+    let _ = heap.log.detach_trace();                        //Don't leave a trace unfinished
+    std::process::exit(code)
+}
+
+fn main() {
+    //1. Create a memory pool
+    /*
+        A memory pool, AKA memory allocator or memory management pool, is a
+        software or hardware structure used to manage dynamic memory allocation
+        in a computer program.
+        Used to efficiently allocate and deallocate memory for data structures
+        and objects during program execution. It is a pre-allocated region
         of memory that is divided into fixed-size blocks. Memory pools are a form
         of dynamic memory allocation that offers a number of advantages over
         traditional methods such as malloc and free found in C systems programming.
@@ -743,14 +3156,355 @@ fn main() {
     This implementation is a simulation of heap behavior within Rust's safe memory model.
     Therefore we handle 'pointers' as just index positions of this vector <usize>
      */
-    let mut cells: Vec<Cell> = init_pool(20);
+    let args: Args = Args::parse();
+
+    let mut heap: Heap = match args.seed {
+        Some(seed) => Heap::with_seed(args.heap_size, seed),
+        None => Heap::new(args.heap_size),
+    };
+    heap.policy.algorithm = args.algorithm;
+    if let Some(profile) = args.profile {
+        heap.set_profile(profile);
+    }
+    heap.observe(Box::new(FailureCounter));
+    let mut session: Session = Session::new();
 
     let msg: usize = 1; //Welcome message
     show_message(Some(msg), None); //Run the initial message
 
+    //Run the script (if one was given) before handing over to the user
+    if let Some(path) = &args.script {
+        match run_script(&mut heap, &mut session, path, &mut io::stdout()) {
+            Ok(Flow::Exit(code)) => finish(&mut heap, code),
+            Ok(Flow::Continue) => {}
+            Err(why) => {
+                eprintln!("Unable to run script '{}': {}", path.display(), why);
+                std::process::exit(EXIT_STARTUP);
+            }
+        }
+    }
+
     //Listen for user input, and act based on commands
-    //Stop listening when the user signals to run the mark-and-sweep collection
-    let mut listening: bool = true;
     //main loop of the program | listen for commands from the user
-    listen(listening, &mut cells);
-}
\ No newline at end of file
+    let code: i32 = listen(&mut heap, &mut session);
+    finish(&mut heap, code);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //Runs a line at the prompt, returning what it printed
+    fn run(heap: &mut Heap, session: &mut Session, line: &str) -> String {
+        let mut out: Vec<u8> = Vec::new();
+        run_command(heap, session, line, &mut out);
+        String::from_utf8(out).unwrap()
+    }
+
+    //A fresh heap and session, with colour off and the log kept quiet
+    fn setup(size: usize) -> (Heap, Session) {
+        let mut heap: Heap = Heap::with_seed(size, 1);
+        heap.log.echo = false;
+        let mut session: Session = Session::new();
+        session.color = false;
+        (heap, session)
+    }
+
+    #[test]
+    fn colours_each_cell_by_its_state() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0; --alloc_at 1; --root 0");
+        heap.cells[0].marked = true;                                //As a collection would leave it, part way through
+
+        let mut out: Vec<u8> = Vec::new();
+        view_state(&heap.cells, &[0, 1, 2], true, &mut out);
+        let rows: Vec<String> = String::from_utf8(out).unwrap().lines().skip(2).map(str::to_string).collect();
+        assert!(rows[0].starts_with(&format!("{}{}", GREEN, BOLD)) && rows[0].ends_with(RESET));
+        assert!(rows[1].starts_with(RED) && rows[2].starts_with(DIM));
+        assert!(rows[3].contains("unmarked (garbage)"));
+
+        //Without colour, nothing but the table is printed
+        let mut out: Vec<u8> = Vec::new();
+        view_state(&heap.cells, &[0, 1, 2], false, &mut out);
+        let plain: String = String::from_utf8(out).unwrap();
+        assert!(!plain.contains('\x1b') && plain.lines().count() == 5);
+    }
+
+    #[test]
+    fn reads_the_startup_options() {
+        let args: Args = Args::try_parse_from(["gc-rust", "--heap-size", "50", "--algorithm", "copying", "--seed", "7"]).unwrap();
+        assert_eq!((args.heap_size, args.algorithm, args.seed), (50, Algorithm::Copying, Some(7)));
+        assert!(args.script.is_none() && args.profile.is_none());
+
+        let args: Args = Args::try_parse_from(["gc-rust"]).unwrap();
+        assert_eq!((args.heap_size, args.algorithm, args.seed), (20, Algorithm::MarkSweep, None));
+    }
+
+    #[test]
+    fn refuses_startup_options_it_cannot_use() {
+        assert!(Args::try_parse_from(["gc-rust", "--heap-size", "0"]).is_err());
+        assert!(Args::try_parse_from(["gc-rust", "--heap-size", "many"]).is_err());
+        assert!(Args::try_parse_from(["gc-rust", "--algorithm", "magic"]).is_err());
+        assert!(Args::try_parse_from(["gc-rust", "--profile", "fast"]).is_err());
+        assert!(Args::try_parse_from(["gc-rust", "--colour"]).is_err());
+    }
+
+    #[test]
+    fn peeks_at_a_cell_and_what_references_it() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0; --alloc_at 1; --link_ref 0 1; --set 1 42; --name 1 head");
+
+        let shown: String = run(&mut heap, &mut session, "--peek head");
+        assert!(shown.starts_with("Cell 1 'head'\n    Data: 42\n"), "{}", shown);
+        assert!(shown.contains("References (fan-out 0): \n") && shown.contains("Referenced by (fan-in 1): 0\n"), "{}", shown);
+
+        assert_eq!(run(&mut heap, &mut session, "--peek 9"), "There is no cell 9, the heap has 4 cells\n");
+        run(&mut heap, &mut session, "--free 1");
+        assert!(run(&mut heap, &mut session, "--peek 1").starts_with("Use after free, cell 1 was freed"));
+    }
+
+    #[test]
+    fn resets_the_heap_to_the_size_asked_for() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0; --reset 6");
+        assert!(heap.cells.len() == 6 && heap.live() == 0);
+
+        for size in ["0", "lots"] {
+            let said: String = run(&mut heap, &mut session, &format!("--reset {}", size));
+            assert_eq!(said, format!("Invalid size '{}', expected a number of cells greater than 0\n", size));
+        }
+        assert_eq!(heap.cells.len(), 6);
+    }
+
+    #[test]
+    fn holds_back_an_exit_that_would_leave_garbage_once() {
+        let (mut heap, mut session) = setup(4);
+        session.interactive = true;
+        run(&mut heap, &mut session, "--alloc_at 1");
+
+        let mut out: Vec<u8> = Vec::new();
+        assert!(matches!(exit(&heap, &mut session, None, &mut out), Flow::Continue));
+        assert!(String::from_utf8(out).unwrap().starts_with("1 cell(s) of garbage haven't been collected yet (1)"));
+        assert!(matches!(exit(&heap, &mut session, None, &mut io::sink()), Flow::Exit(code) if code == session.exit_code()));
+
+        session.confirm_exit = false;
+        assert!(matches!(exit(&heap, &mut session, Some(&"--force"), &mut io::sink()), Flow::Exit(_)));
+        assert!(matches!(exit(&heap, &mut session, Some(&"4"), &mut io::sink()), Flow::Exit(4)));
+    }
+
+    #[test]
+    fn runs_each_command_chained_on_a_line_in_turn() {
+        let (mut heap, mut session) = setup(4);
+        let said: String = run(&mut heap, &mut session, "--alloc_at 0 ;; --peek 9;");
+        assert_eq!(said, "> --alloc_at 0\n> --peek 9\nThere is no cell 9, the heap has 4 cells\n");
+        assert!(!heap.cells[0].freed);
+
+        //A single command isn't labelled, and nothing after an --exit is run
+        assert_eq!(run(&mut heap, &mut session, "--peek 9"), "There is no cell 9, the heap has 4 cells\n");
+        let mut out: Vec<u8> = Vec::new();
+        assert!(matches!(run_command(&mut heap, &mut session, "--exit 2; --alloc_at 1", &mut out), Flow::Exit(2)));
+        assert!(heap.cells[1].freed);
+    }
+
+    #[test]
+    fn explains_why_a_cell_is_still_alive() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0; --alloc_at 1; --alloc_at 2; --root 0; --root 2; --link_ref 0 1; --link_ref 2 1; --name 1 leaf");
+
+        assert_eq!(run(&mut heap, &mut session, "--why-live leaf"), "root 0 -> 1 'leaf'\n(1 more root(s) also reach cell 1, use --why-live 1 --all to see them)\n");
+        assert_eq!(run(&mut heap, &mut session, "--why-live 1 --all"), "root 0 -> 1 'leaf'\nroot 2 -> 1 'leaf'\n");
+        assert!(run(&mut heap, &mut session, "--why-live 3").starts_with("Cell 3 is free"));
+        assert_eq!(run(&mut heap, &mut session, "--why-live 7"), "There is no cell 7, the heap has 4 cells\n");
+    }
+
+    #[test]
+    fn reports_garbage_the_collector_keeps_missing_as_leaks() {
+        let (mut heap, mut session) = setup(4);
+        heap.policy.algorithm = Algorithm::RefCount;
+        run(&mut heap, &mut session, "--alloc_at 0; --root 0; --alloc_at 1; --alloc_at 2; --link_ref 1 2; --link_ref 2 1");
+        let said: String = run(&mut heap, &mut session, "--leaks");
+        assert!(said.starts_with("2 cell(s) hold data but can't be reached from any root:\n"), "{}", said);
+        assert!(said.ends_with("None of them have been through a collection yet, run --gc to reclaim them\n"), "{}", said);
+
+        //Reference counting can't reclaim the cycle, so it is still there after a collection
+        run(&mut heap, &mut session, "--gc");
+        let said: String = run(&mut heap, &mut session, "--leaks");
+        assert!(said.contains("    1 (data: ") && said.contains("): garbage through 1 collection(s)\n"), "{}", said);
+        assert!(said.ends_with("2 of them survived a collection while unreachable, which the rc collector isn't reclaiming\n"), "{}", said);
+
+        run(&mut heap, &mut session, "--unlink_ref 1 2");
+        assert_eq!(run(&mut heap, &mut session, "--leaks"), "No leaks, every cell in use can be reached from a root\n");
+    }
+
+    #[test]
+    fn sets_the_order_cells_are_marked_in() {
+        let (mut heap, mut session) = setup(4);
+        assert!(run(&mut heap, &mut session, "--set-policy mark-order bfs").contains("in bfs order"));
+        assert_eq!(heap.policy.mark_order, MarkOrder::Bfs);
+
+        let refused: String = run(&mut heap, &mut session, "--set-policy mark-order sideways");
+        assert_eq!(refused, "Unknown mark order 'sideways', expected one of: dfs, bfs\n");
+        assert_eq!(heap.policy.mark_order, MarkOrder::Bfs);
+    }
+
+    #[test]
+    fn limits_the_mark_worklist_to_a_size_greater_than_zero() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--set-policy mark-stack 2");
+        assert_eq!(heap.policy.mark_stack, Some(2));
+
+        for size in ["0", "-1", "lots"] {
+            let refused: String = run(&mut heap, &mut session, &format!("--set-policy mark-stack {}", size));
+            assert!(refused.starts_with(&format!("Invalid mark stack size '{}'", size)), "{}", refused);
+        }
+        assert_eq!(heap.policy.mark_stack, Some(2));
+        run(&mut heap, &mut session, "--set-policy mark-stack off");
+        assert_eq!(heap.policy.mark_stack, None);
+    }
+
+    #[test]
+    fn reports_what_happens_to_a_watched_cell() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0; --root 0; --watch 1");
+
+        let shown: String = run(&mut heap, &mut session, "--alloc_at 1");
+        assert!(shown.contains("Watch: cell 1 was allocated"), "{}", shown);
+        assert!(run(&mut heap, &mut session, "--link_ref 0 1").contains("Watch: cell 1 was linked to, from cell 0"));
+        assert!(!run(&mut heap, &mut session, "--alloc_at 2").contains("Watch:"));              //Only the cells being watched
+        run(&mut heap, &mut session, "--unlink_ref 0 1");
+        assert!(run(&mut heap, &mut session, "--gc").contains("Watch: cell 1 was swept"));
+
+        assert_eq!(run(&mut heap, &mut session, "--watch 9"), "There is no cell 9, the heap has 4 cells\n");
+        assert_eq!(run(&mut heap, &mut session, "--watch 2 off"), "Cell 2 isn't being watched\n");
+        run(&mut heap, &mut session, "--watch off");
+        assert!(session.watches.is_empty() && !run(&mut heap, &mut session, "--alloc_at 1").contains("Watch:"));
+    }
+
+    #[test]
+    fn goes_back_to_an_earlier_step_of_the_session() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0");
+        run(&mut heap, &mut session, "--alloc_at 1");
+        run(&mut heap, &mut session, "--free 0");
+
+        let shown: String = run(&mut heap, &mut session, "--goto 1");
+        assert!(shown.starts_with("The heap is back at step #1 (after --alloc_at 0)"), "{}", shown);
+        assert!(!heap.cells[0].freed && heap.cells[1].freed);
+
+        assert_eq!(run(&mut heap, &mut session, "--goto 7"), "There is no step 7, the history holds steps 0 to 3\n");
+        assert_eq!(run(&mut heap, &mut session, "--goto last"), "Expected a step number, see --history\n");
+        assert!(!heap.cells[0].freed && heap.cells[1].freed);
+    }
+
+    #[test]
+    fn tags_allocations_with_the_site_given() {
+        let (mut heap, mut session) = setup(4);
+        assert_eq!(run(&mut heap, &mut session, "--profile-allocs"), "Nothing has been allocated yet\n");
+        run(&mut heap, &mut session, "--site parser; --alloc_at 0; --site off; --alloc_at 1");
+        assert_eq!((heap.cells[0].site.as_deref(), heap.cells[1].site.as_deref()), (Some("parser"), Some("--alloc_at")));
+
+        assert_eq!(run(&mut heap, &mut session, "--site"), "Usage: --site <label|off>\n");
+        assert_eq!(session.site, None);
+    }
+
+    #[test]
+    fn draws_a_histogram_of_the_live_cells() {
+        let (mut heap, mut session) = setup(4);
+        assert_eq!(run(&mut heap, &mut session, "--histogram age"), "There are no cells in use\n");
+        run(&mut heap, &mut session, "--alloc_at 0; --alloc_at 1");
+
+        let shown: String = run(&mut heap, &mut session, "--histogram type");
+        assert!(shown.starts_with("2 live cell(s) by type") && shown.contains("int |"), "{}", shown);
+        assert_eq!(run(&mut heap, &mut session, "--histogram colour"), "Usage: --histogram <age|refs|size|type>\n");
+    }
+
+    #[test]
+    fn puts_the_heap_under_pressure_it_can_take() {
+        let (mut heap, mut session) = setup(8);
+        let usage: &str = "Usage: --pressure <cells-per-tick> <ticks>, where both are numbers greater than 0\n";
+        for params in ["", "4", "0 2", "4 many"] {
+            assert_eq!(run(&mut heap, &mut session, &format!("--pressure {}", params)), usage);
+        }
+
+        let shown: String = run(&mut heap, &mut session, "--pressure 10 1");
+        assert!(shown.starts_with("10 cell(s) a tick for 0 of 1 tick(s)"), "{}", shown);
+        assert!(shown.ends_with("Out of memory in tick 1, a tick's 10 cell(s) don't fit alongside the 0 cell(s) the heap keeps alive\n"), "{}", shown);
+        assert_eq!(heap.log.level, Level::Info);                        //Quiet only for the run
+    }
+
+    #[test]
+    fn collects_only_the_kind_asked_for() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--alloc_at 0; --root 0; --alloc_at 1; --gc major; --alloc_at 2");
+        run(&mut heap, &mut session, "--unroot 0; --gc minor");
+        assert!(!heap.cells[0].freed && heap.cells[2].freed);           //Old cells are left to a major collection
+
+        let refused: String = run(&mut heap, &mut session, "--gc sideways");
+        assert_eq!(refused, "Unknown collection 'sideways', expected one of: minor, major, full (or --diff)\n");
+        assert_eq!(heap.history.len(), 2);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    fn turns_write_barriers_off_only_when_told_plainly() {
+        let (mut heap, mut session) = setup(4);
+        assert!(run(&mut heap, &mut session, "--set-policy barriers off").starts_with("Write barriers are OFF"));
+        assert!(heap.policy.skip_barriers);
+
+        assert_eq!(run(&mut heap, &mut session, "--set-policy barriers maybe"), "Invalid value 'maybe' for barriers, expected 'on' or 'off'\n");
+        assert!(heap.policy.skip_barriers);
+        run(&mut heap, &mut session, "--set-policy barriers on");
+        assert!(!heap.policy.skip_barriers);
+    }
+
+    #[test]
+    fn says_when_a_collection_left_cells_in_place() {
+        let (mut heap, mut session) = setup(10);
+        heap.policy.survivor_size = Some(3);                            //Leaves 4 cells to copy 8 into
+        for i in 0..8 {
+            run(&mut heap, &mut session, &format!("--root {}", i));
+        }
+        run(&mut heap, &mut session, "--set-policy algorithm copying");
+        run(&mut heap, &mut session, "--gc");
+        assert!(run(&mut heap, &mut session, "--stats").contains("partial -> 4 cell(s) left in place"));
+    }
+
+    #[test]
+    fn says_why_a_cell_was_not_tagged() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--root 0");
+        assert_eq!(run(&mut heap, &mut session, "--tag 1 cache"), "Cell 1 is free, only cells in use can be tagged\n");
+        assert_eq!(run(&mut heap, &mut session, "--tag 0"), "Usage: --tag <cell> <tag> [off]\n");
+
+        run(&mut heap, &mut session, "--tag 0 cache");
+        assert_eq!(run(&mut heap, &mut session, "--tag 0 cache"), "Cell 0 is already tagged 'cache'\n");
+        assert_eq!(run(&mut heap, &mut session, "--tag"), "cache: 1 cell(s)\n");
+        run(&mut heap, &mut session, "--tag 0 cache off");
+        assert_eq!(run(&mut heap, &mut session, "--tag 0 cache off"), "Cell 0 isn't tagged 'cache'\n");
+        assert_eq!(run(&mut heap, &mut session, "--tag"), "No cell is tagged\n");
+    }
+
+    #[test]
+    fn collects_past_the_gc_threshold_after_building_a_scenario() {
+        let (mut heap, mut session) = setup(8);
+        run(&mut heap, &mut session, "--set-policy gc-threshold 50");
+        run(&mut heap, &mut session, "--scenario cycle");
+        assert_eq!(heap.history.len(), 1);
+        assert_eq!(heap.occupancy(), 50);                               //The root and its reachable cycle
+    }
+
+    #[test]
+    fn says_which_cell_could_not_be_weakly_mapped() {
+        let (mut heap, mut session) = setup(4);
+        run(&mut heap, &mut session, "--root 0");
+        assert_eq!(run(&mut heap, &mut session, "--weakmap_put 0 2"), "Couldn't weakly map cell 2, the memory was free, not suitable for use\n");
+        assert!(heap.weak_map().is_empty());
+    }
+
+    #[test]
+    fn says_which_cell_could_not_have_a_finalizer() {
+        let (mut heap, mut session) = setup(4);
+        assert_eq!(run(&mut heap, &mut session, "--finalizer 2"), "Couldn't register a finalizer on cell 2, the memory was free, not suitable for use\n");
+        assert!(heap.finalizers().is_empty());
+    }
+}
@@ -0,0 +1,242 @@
+//Metrics exported from the virtual heap
+/*
+    Numbers about each collection, written out in formats other tools can consume
+    (i.e. a CSV file to plot in a spreadsheet when comparing policies, or a Prometheus endpoint to graph in Grafana).
+*/
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use crate::{
+    stats::{GcCycle, HeapStats},
+    Heap, Instant,
+};
+
+/// Column names of the metrics CSV, in order
+pub const CSV_HEADER: &str = "cycle,live,swept,pause_us,occupancy,swept_bytes";
+
+/// Appends one row per collection to a CSV file
+pub struct MetricsCsv {
+    file: File,
+}
+
+impl MetricsCsv {
+    /// Opens the file at `path` for appending (creating it if needed).
+    /// The header row is only written if the file is empty, so several runs can be appended to the same file
+    pub fn open(path: &str) -> io::Result<MetricsCsv> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            writeln!(file, "{}", CSV_HEADER)?;
+        }
+
+        Ok(MetricsCsv { file })
+    }
+
+    /// Writes the row for a finished collection.
+    /// `live` and `occupancy` (percent of cells in use) are measured after the collection
+    pub fn record(&mut self, cycle: &GcCycle, live: usize, occupancy: usize) -> io::Result<()> {
+        writeln!(
+            self.file,
+            "{},{},{},{},{},{}",
+            cycle.number,
+            live,
+            cycle.swept,
+            cycle.pause().as_micros(),
+            occupancy,
+            cycle.bytes_swept
+        )
+    }
+}
+
+/// Upper bounds (in seconds) of the GC pause histogram buckets
+const PAUSE_BUCKETS: [f64; 8] = [0.00001, 0.00005, 0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05];
+
+/// Serves the heap's metrics over HTTP in the Prometheus text format, from a background thread.
+/// The thread only ever sees the latest metrics handed to `publish()`, so the heap itself is never shared
+pub struct MetricsEndpoint {
+    pub address: SocketAddr,           //Where it is listening
+    published: Arc<Mutex<Published>>,  //The latest metrics, read by the server thread
+}
+
+//What the server thread answers requests with
+struct Published {
+    text: String,                       //Every metric other than the allocation rate, already rendered
+    allocations: usize,                 //Lifetime allocations as of the latest publish
+    started: Instant,                   //When serving began (allocation rate is measured from here)...
+    allocations_at_start: usize,        //...and the lifetime allocations at that point
+}
+
+impl Published {
+    //Allocations per second since serving began, measured at the time of the request so it falls off when idle
+    fn allocation_rate(&self) -> f64 {
+        let elapsed: f64 = self.started.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            (self.allocations - self.allocations_at_start) as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+}
+
+impl MetricsEndpoint {
+    /// Binds to `port` on the interface at `ip` (127.0.0.1 keeps the metrics to this machine) and starts answering
+    /// `GET /metrics` requests.
+    /// `allocations` is the heap's current lifetime allocation count, so the rate only covers what happens from now on
+    pub fn serve(ip: IpAddr, port: u16, allocations: usize) -> io::Result<MetricsEndpoint> {
+        let listener = TcpListener::bind((ip, port))?;
+        let address: SocketAddr = listener.local_addr()?;
+        let published: Arc<Mutex<Published>> = Arc::new(Mutex::new(Published {
+            text: String::new(),
+            allocations,
+            started: Instant::now(),
+            allocations_at_start: allocations,
+        }));
+
+        let shared = Arc::clone(&published);
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                //A client hanging up early is its own problem, carry on with the next one
+                let _ = answer(stream, &shared);
+            }
+        });
+
+        Ok(MetricsEndpoint { address, published })
+    }
+
+    /// Renders the heap's current metrics, replacing what the endpoint serves
+    pub fn publish(&self, heap: &Heap) {
+        let text = render(heap);
+        if let Ok(mut published) = self.published.lock() {
+            published.text = text;
+            published.allocations = heap.stats().allocations;
+        }
+    }
+}
+
+//The Prometheus text exposition format, one HELP/TYPE block per metric
+fn render(heap: &Heap) -> String {
+    let stats: HeapStats = heap.stats();
+
+    let mut text = String::new();
+    gauge(&mut text, "gc_heap_capacity_cells", "Total cells in the memory pool", stats.capacity as f64);
+    gauge(&mut text, "gc_heap_live_cells", "Cells currently in use", stats.live as f64);
+    gauge(&mut text, "gc_heap_roots", "Cells currently designated as roots", stats.roots as f64);
+    gauge(&mut text, "gc_heap_capacity_bytes", "Total bytes the pool has room for", stats.bytes_capacity as f64);
+    gauge(&mut text, "gc_heap_used_bytes", "Bytes taken up by objects in use", stats.bytes_used as f64);
+    gauge(&mut text, "gc_heap_occupancy_ratio", "Fraction of the pool's cells in use", stats.live as f64 / stats.capacity as f64);
+    counter(&mut text, "gc_allocations_total", "Successful allocations over the lifetime of the heap", stats.allocations);
+    counter(&mut text, "gc_collections_total", "Garbage collection cycles that have run", stats.collections);
+    counter(&mut text, "gc_minor_collections_total", "Minor collections of the young cells", stats.minor_collections);
+    counter(&mut text, "gc_major_collections_total", "Major collections of the whole heap", stats.major_collections);
+    counter(&mut text, "gc_full_collections_total", "Full (compacting) collections of the whole heap", stats.full_collections);
+    counter(&mut text, "gc_read_barrier_hits_total", "Reads forwarded to a relocated object by the read barrier", stats.read_barrier_hits);
+    counter(&mut text, "gc_rc_updates_total", "Reference count updates applied in batches by the buffered variant", stats.rc_updates);
+    counter(&mut text, "gc_rc_updates_elided_total", "Buffered reference count updates that cancelled out when coalesced", stats.rc_updates_elided);
+    counter(&mut text, "gc_hybrid_rc_reclaimed_total", "Cells the hybrid reclaimed by reference counting", stats.reclaimed_by_rc);
+    counter(&mut text, "gc_hybrid_tracer_reclaimed_total", "Cells the hybrid reclaimed with its cycle tracer", stats.reclaimed_by_tracer);
+    counter(&mut text, "gc_interned_total", "Allocations interning shared an existing cell for", stats.interned);
+    counter(&mut text, "gc_interned_bytes_total", "Bytes the allocations interning shared would have taken up", stats.bytes_interned);
+    counter(&mut text, "gc_reclaimed_bytes_total", "Bytes swept by every collection", stats.bytes_reclaimed);
+
+    //Pause histogram -> cumulative count of pauses at or under each bucket's bound
+    let pauses: Vec<f64> = heap.history.iter().map(|cycle| cycle.pause().as_secs_f64()).collect();
+    text.push_str("# HELP gc_pause_seconds Time the program was paused for each collection\n");
+    text.push_str("# TYPE gc_pause_seconds histogram\n");
+    for bound in PAUSE_BUCKETS {
+        let count = pauses.iter().filter(|&&pause| pause <= bound).count();
+        text.push_str(&format!("gc_pause_seconds_bucket{{le=\"{}\"}} {}\n", bound, count));
+    }
+    text.push_str(&format!("gc_pause_seconds_bucket{{le=\"+Inf\"}} {}\n", pauses.len()));
+    text.push_str(&format!("gc_pause_seconds_sum {}\n", pauses.iter().sum::<f64>()));
+    text.push_str(&format!("gc_pause_seconds_count {}\n", pauses.len()));
+
+    text
+}
+
+fn gauge(text: &mut String, name: &str, help: &str, value: f64) {
+    text.push_str(&format!("# HELP {} {}\n# TYPE {} gauge\n{} {}\n", name, help, name, name, value));
+}
+
+fn counter(text: &mut String, name: &str, help: &str, value: usize) {
+    text.push_str(&format!("# HELP {} {}\n# TYPE {} counter\n{} {}\n", name, help, name, name, value));
+}
+
+//Answers a single HTTP request -> the metrics for /metrics, 404 for anything else
+fn answer(mut stream: TcpStream, published: &Mutex<Published>) -> io::Result<()> {
+    let mut request = [0; 1024];
+    let read = stream.read(&mut request)?;
+    let request = String::from_utf8_lossy(&request[..read]);
+
+    //Only the request line matters, i.e. "GET /metrics HTTP/1.1"
+    let path: &str = request.split_whitespace().nth(1).unwrap_or("");
+
+    let (status, body) = match path {
+        "/metrics" => match published.lock() {
+            Ok(published) => {
+                let mut body = published.text.clone();
+                gauge(
+                    &mut body,
+                    "gc_allocation_rate",
+                    "Allocations per second since metrics began being served",
+                    published.allocation_rate(),
+                );
+                ("200 OK", body)
+            }
+            Err(_) => ("500 Internal Server Error", String::from("Metrics are unavaliable\n")),
+        },
+        _ => ("404 Not Found", String::from("Metrics are served at /metrics\n")),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, net::Ipv4Addr, process};
+
+    use super::*;
+    use crate::{collect, tests::graph};
+
+    #[test]
+    fn appends_a_row_for_each_collection_under_one_header() {
+        let path = env::temp_dir().join(format!("gc-rust-metrics-{}.csv", process::id()));
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        heap.attach_metrics_csv(path.to_str().unwrap()).unwrap();
+        collect(&mut heap);
+        heap.attach_metrics_csv(path.to_str().unwrap()).unwrap();      //A second run appends to the same file
+        collect(&mut heap);
+        heap.detach_metrics_csv();
+
+        let written: String = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let rows: Vec<Vec<&str>> = written.lines().map(|line| line.split(',').collect()).collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0].join(","), CSV_HEADER);
+        assert_eq!((rows[1][0], rows[1][1], rows[1][2], rows[1][4]), ("1", "1", "1", "16"));
+        assert_eq!((rows[2][0], rows[2][2]), ("2", "0"));
+    }
+
+    #[test]
+    fn serves_this_machine_unless_told_otherwise() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        let address: SocketAddr = heap.serve_metrics(0).unwrap();
+        assert!(address.ip().is_loopback());
+        assert_eq!(heap.serve_metrics_on(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+
+        let endpoint: MetricsEndpoint = MetricsEndpoint::serve(IpAddr::V4(Ipv4Addr::UNSPECIFIED), 0, 0).unwrap();
+        assert!(endpoint.address.ip().is_unspecified());
+    }
+}
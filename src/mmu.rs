@@ -0,0 +1,129 @@
+//Mutator utilization
+/*
+    Total pause time says how much of the program's time the collector took, but not how it was spread out.
+    A hundred 1ms pauses spread over a minute are barely noticed, the same hundred back to back freeze the program for 100ms.
+
+    Minimum mutator utilization (MMU) captures the difference. For a window size w, it is the smallest fraction of
+    any w long stretch of the run that the mutator (the program, rather than the collector) got to use.
+    i.e. an MMU of 40% at 10ms means that somewhere in the run there was a 10ms stretch where the collector took 6ms of it,
+    and nowhere was worse. Collectors that split their work into small pieces (incremental, concurrent) keep it high
+    for small windows, where a stop-the-world collector drops to 0% for any window shorter than its longest pause.
+
+    Time is measured from when the heap was created (or reset), using when each collection started and how long it paused for.
+*/
+
+use core::time::Duration;
+
+use crate::Heap;
+use crate::prelude::*;
+
+/// Windows reported when none are asked for
+pub const DEFAULT_WINDOWS: [Duration; 5] = [
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+
+/// How the run has been split between the mutator and the collector, from `Heap::utilization()`
+#[derive(Clone, Debug)]
+pub struct Utilization {
+    pub elapsed: Duration,          //Since the heap was created (or reset)
+    pub gc: Duration,               //...and how much of it was spent paused for collections
+    pub windows: Vec<Window>,       //MMU for each window size asked for, in the same order
+}
+
+/// Minimum mutator utilization for one window size
+#[derive(Clone, Copy, Debug)]
+pub struct Window {
+    pub size: Duration,
+    pub mmu: Option<f64>,           //0.0 - 1.0, or None if the run isn't that long yet
+    pub worst_at: Duration,         //Start of the window the minimum was found in
+}
+
+impl Utilization {
+    /// Fraction (0.0 - 1.0) of the whole run the mutator got to use
+    pub fn overall(&self) -> f64 {
+        match self.elapsed.is_zero() {
+            true => 1.0,
+            false => 1.0 - (self.gc.as_secs_f64() / self.elapsed.as_secs_f64()).min(1.0),
+        }
+    }
+}
+
+impl Heap {
+    /// Works out the minimum mutator utilization for each window size, over the whole run so far
+    pub fn utilization(&self, windows: &[Duration]) -> Utilization {
+        let elapsed: Duration = self.started.elapsed();
+        let pauses: Vec<(Duration, Duration)> = self.history.iter().map(|cycle| (cycle.at, cycle.at + cycle.pause())).collect();
+
+        Utilization {
+            elapsed,
+            gc: self.history.iter().map(|cycle| cycle.pause()).sum(),
+            windows: windows.iter().map(|&size| window(&pauses, elapsed, size)).collect(),
+        }
+    }
+}
+
+//The worst window of a size -> it always starts where a pause starts, or ends where one ends,
+//so only those positions (and the start and end of the run) need to be tried
+fn window(pauses: &[(Duration, Duration)], elapsed: Duration, size: Duration) -> Window {
+    if size.is_zero() || size > elapsed {
+        return Window { size, mmu: None, worst_at: Duration::ZERO };
+    }
+
+    let last: Duration = elapsed - size;
+    let candidates = pauses
+        .iter()
+        .flat_map(|&(start, end)| [Some(start), end.checked_sub(size)])
+        .flatten()
+        .chain([Duration::ZERO, last])
+        .map(|at| at.min(last));
+
+    let (mut worst_gc, mut worst_at) = (Duration::ZERO, Duration::ZERO);
+    for at in candidates {
+        let gc: Duration = pauses
+            .iter()
+            .map(|&(start, end)| end.min(at + size).saturating_sub(start.max(at)))
+            .sum();
+        if gc > worst_gc {
+            (worst_gc, worst_at) = (gc, at);
+        }
+    }
+
+    Window {
+        size,
+        mmu: Some(1.0 - (worst_gc.as_secs_f64() / size.as_secs_f64()).min(1.0)),
+        worst_at,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn finds_the_window_the_collector_took_most_of() {
+        let pauses: [(Duration, Duration); 2] = [(10 * MS, 12 * MS), (20 * MS, 23 * MS)];
+        let mmu = |size: Duration| window(&pauses, 100 * MS, size);
+
+        assert_eq!(mmu(MS).mmu, Some(0.0));                             //Shorter than the longest pause
+        let ten: Window = mmu(10 * MS);
+        assert!((ten.mmu.unwrap() - 0.7).abs() < 1e-9 && ten.worst_at == 20 * MS);
+        assert!((mmu(100 * MS).mmu.unwrap() - 0.95).abs() < 1e-9);
+        assert_eq!((mmu(200 * MS).mmu, mmu(Duration::ZERO).mmu), (None, None));   //Longer than the run, or no window at all
+    }
+
+    #[test]
+    fn gives_the_mutator_everything_before_any_collection() {
+        let heap: Heap = Heap::with_seed(4, 0);
+        let utilization: Utilization = heap.utilization(&[Duration::ZERO]);
+        assert_eq!((utilization.gc, utilization.overall()), (Duration::ZERO, 1.0));
+
+        let busy: Utilization = Utilization { elapsed: 10 * MS, gc: 4 * MS, windows: Vec::new() };
+        assert!((busy.overall() - 0.6).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,112 @@
+//Mutator operations
+/*
+    The mutator is the program a collector runs alongside -> it allocates objects, links and unlinks them, and roots
+    and unroots them, while the collector reclaims whatever it leaves unreachable. An Op is one such step as data,
+    so a sequence of them can be generated, printed and replayed against any heap (see simulation.rs, and the property
+    tests in tests/invariants.rs, which throw random sequences at every collector and check the heap after each step).
+
+    An op picks its cells by position among the cells in use (wrapping around) rather than by index, so every op
+    means something on any heap -> `Link(7, 2)` on a heap with three cells in use makes the second reference the third.
+    An op with nothing to act on (a link on an empty heap, an unroot with no roots) does nothing.
+*/
+
+use core::fmt;
+
+use crate::{collect, error::GcError, Heap};
+use crate::prelude::*;
+
+/// The root set that `Op::Root` adds cells to
+pub const ROOT_SET: &str = "mutator";
+
+/// One step of a mutator, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Op {
+    Alloc(i32),                 //Allocates the data in the first free cell (collecting if there are none, see `Heap::alloc()`)
+    Link(usize, usize),         //The first cell in use references the second
+    Unlink(usize, usize),       //A cell in use that references others drops one of its references
+    Root(usize),                //A cell in use becomes a root, in the ROOT_SET root set
+    Unroot(usize),              //A cell in a root set is removed from every root set
+    Collect,                    //A collection runs, see `collect()`
+}
+
+impl Op {
+    /// Parses an operation written the way it is displayed, i.e. `link 3 1` -> `Op::Link(3, 1)`
+    pub fn parse(text: &str) -> Option<Op> {
+        let words: Vec<&str> = text.split_whitespace().collect();
+        let number = |i: usize| words.get(i).and_then(|word| word.parse::<usize>().ok());
+        let op: Op = match words.first().copied()? {
+            "alloc" => Op::Alloc(words.get(1)?.parse().ok()?),
+            "link" => Op::Link(number(1)?, number(2)?),
+            "unlink" => Op::Unlink(number(1)?, number(2)?),
+            "root" => Op::Root(number(1)?),
+            "unroot" => Op::Unroot(number(1)?),
+            "collect" => Op::Collect,
+            _ => return None,
+        };
+
+        let arguments: usize = match op {
+            Op::Alloc(_) | Op::Root(_) | Op::Unroot(_) => 1,
+            Op::Link(..) | Op::Unlink(..) => 2,
+            Op::Collect => 0,
+        };
+        (words.len() == arguments + 1).then_some(op)
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Op::Alloc(data) => write!(f, "alloc {}", data),
+            Op::Link(from, to) => write!(f, "link {} {}", from, to),
+            Op::Unlink(from, to) => write!(f, "unlink {} {}", from, to),
+            Op::Root(index) => write!(f, "root {}", index),
+            Op::Unroot(index) => write!(f, "unroot {}", index),
+            Op::Collect => write!(f, "collect"),
+        }
+    }
+}
+
+impl Heap {
+    /// Applies a mutator operation to the heap.
+    /// Returns `NoFreeMemory` error if an allocation found no free cell even after collecting,
+    /// or `Occupied` error if a link is already there
+    pub fn step(&mut self, op: Op) -> Result<(), GcError> {
+        let in_use: Vec<usize> = (0..self.cells.len()).filter(|&i| !self.cells[i].freed).collect();
+        let pick = |cells: &[usize], n: usize| (!cells.is_empty()).then(|| cells[n % cells.len()]);
+
+        match op {
+            Op::Alloc(data) => {
+                self.alloc(data)?;
+            }
+            Op::Link(from, to) => {
+                if let (Some(from), Some(to)) = (pick(&in_use, from), pick(&in_use, to)) {
+                    let (from, to) = (self.handle(from).expect("the cell is in use"), self.handle(to).expect("the cell is in use"));
+                    self.link(from, to)?;
+                }
+            }
+            Op::Unlink(from, to) => {
+                let referencing: Vec<usize> = in_use.iter().copied().filter(|&i| !self.cells[i].will_ref.is_empty()).collect();
+                if let Some(from) = pick(&referencing, from) {
+                    let to: usize = pick(&self.cells[from].will_ref, to).expect("the cell references others");
+                    let (from, to) = (self.handle(from).expect("the cell is in use"), self.handle(to).expect("references are to cells in use"));
+                    self.unlink(from, to)?;
+                }
+            }
+            Op::Root(index) => {
+                if let Some(index) = pick(&in_use, index) {
+                    self.add_root(ROOT_SET, index).expect("the cell is inside the heap");
+                }
+            }
+            Op::Unroot(index) => {
+                let mut rooted: Vec<usize> = self.root_sets().iter().flat_map(|root_set| root_set.cells.iter().copied()).collect();
+                rooted.sort_unstable();
+                rooted.dedup();
+                if let Some(index) = pick(&rooted, index) {
+                    self.remove_root(index);
+                }
+            }
+            Op::Collect => collect(self),
+        }
+        Ok(())
+    }
+}
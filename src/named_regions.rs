@@ -0,0 +1,170 @@
+//Named regions
+/*
+    Region-based memory management (an arena, or an MLKit region) puts objects that live and die together side by
+    side, so the whole lot can be accounted for, and done away with, at once. A named region carves a stretch of the
+    pool out for one purpose, i.e. `--region cache 10..15` for cells 10 to 14 (the end isn't included, as in Rust):
+
+        occupancy -> how many of its cells are in use, and the bytes they take up (`--region` lists every region)
+        allocation -> `--alloc_in cache` allocates in the first free cell of the region, and fails if it is full,
+                      even if there is room elsewhere. Nothing else is stopped from allocating in it
+
+    A region is only a range of positions, not something the collector knows about -> the cells in it are traced and
+    reclaimed like any other, which is how it shows region-style lifetimes alongside tracing. Regions can't overlap,
+    and a collection that moves cells (see copying.rs) can move them out of their region.
+
+    These are nothing to do with the regions the garbage first collector splits the heap into (see g1.rs), which are
+    all the same size and unnamed.
+*/
+
+use core::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::{ErrorKind, GcError, Operation}, spec_alloc, Heap, IndexResult};
+use crate::prelude::*;
+
+/// A named range of the pool, see the top of this module
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NamedRegion {
+    pub name: String,
+    pub cells: Range<usize>,
+}
+
+/// How full a named region is, from `Heap::region_occupancy()`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Occupancy {
+    pub capacity: usize,            //Cells in the region
+    pub used: usize,                //...of which in use
+    pub bytes: usize,               //Bytes taken up by the objects in it
+}
+
+/// Reasons a region can't be defined
+#[derive(Debug, PartialEq, Eq)]
+pub enum NamedRegionError {
+    OutOfBounds,                    //The range is empty, or goes past the end of the heap
+    Invalid,                        //Names can't be empty, start with '-', or be a number
+    Overlaps(String),               //Another region already has some of its cells
+}
+
+impl Occupancy {
+    /// Percentage of the region's cells in use
+    pub fn percent(&self) -> usize {
+        (self.used * 100).checked_div(self.capacity).unwrap_or(0)
+    }
+}
+
+impl Heap {
+    /// Every named region, in the order they were defined
+    pub fn named_regions(&self) -> &[NamedRegion] {
+        &self.named_regions
+    }
+
+    /// The named region called `name`, if there is one
+    pub fn named_region(&self, name: &str) -> Option<&NamedRegion> {
+        self.named_regions.iter().find(|region| region.name == name)
+    }
+
+    /// Names a range of the pool, replacing the region of that name if there already is one.
+    /// Returns `OutOfBounds` error if the range is empty or past the end of the heap, `Invalid` error if the name couldn't be
+    /// told apart from an index or command, or `Overlaps` error if another region already has some of the cells
+    pub fn define_region(&mut self, name: &str, cells: Range<usize>) -> Result<(), NamedRegionError> {
+        check_region(name, &cells, self.cells.len(), &self.named_regions)?;
+
+        let region: NamedRegion = NamedRegion { name: name.to_string(), cells };
+        match self.named_regions.iter_mut().find(|other| other.name == name) {
+            Some(other) => *other = region,
+            None => self.named_regions.push(region),
+        }
+        Ok(())
+    }
+
+    /// Removes a named region (its cells are left as they are), returning it if there was one
+    pub fn drop_region(&mut self, name: &str) -> Option<NamedRegion> {
+        let position: usize = self.named_regions.iter().position(|region| region.name == name)?;
+        Some(self.named_regions.remove(position))
+    }
+
+    /// How full the named region called `name` is (None if there is no such region)
+    pub fn region_occupancy(&self, name: &str) -> Option<Occupancy> {
+        let region: &NamedRegion = self.named_region(name)?;
+        let cells = self.cells[region.cells.clone()].iter().filter(|cell| !cell.freed);
+        Some(Occupancy {
+            capacity: region.cells.len(),
+            used: cells.clone().count(),
+            bytes: cells.map(|cell| cell.size()).sum(),
+        })
+    }
+
+    /// Allocates data in the first free cell of the named region, see `spec_alloc()`.
+    /// Returns `OutOfBounds` error if there is no region called `name`, or `NoFreeMemory` error if the region is full
+    /// (whatever room there is elsewhere)
+    pub fn alloc_in(&mut self, name: &str, data: i32) -> IndexResult {
+        let Some(region) = self.named_region(name) else {
+            return Err(GcError::new(ErrorKind::OutOfBounds, Operation::Alloc, None));
+        };
+        let reserved: Range<usize> = self.reserved();
+        let free: Option<usize> = region.cells.clone().find(|&i| self.cells[i].freed && self.cells[i].forward.is_none() && !reserved.contains(&i));
+        match free {
+            Some(index) => spec_alloc(self, data, None, index),
+            None => Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None)),
+        }
+    }
+}
+
+//The rules a region has to keep on a heap of `size` cells, alongside the `others` already there (see `Heap::define_region()`)
+pub(crate) fn check_region(name: &str, cells: &Range<usize>, size: usize, others: &[NamedRegion]) -> Result<(), NamedRegionError> {
+    if cells.is_empty() || cells.end > size {
+        return Err(NamedRegionError::OutOfBounds);
+    }
+    if name.is_empty() || name.starts_with('-') || name.parse::<usize>().is_ok() {
+        return Err(NamedRegionError::Invalid);
+    }
+    if let Some(other) = others.iter().find(|other| other.name != name && other.cells.start < cells.end && cells.start < other.cells.end) {
+        return Err(NamedRegionError::Overlaps(other.name.clone()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, sizes};
+
+    #[test]
+    fn allocates_within_a_named_region() {
+        let mut heap: Heap = Heap::with_seed(10, 0);
+        heap.log.echo = false;
+        heap.define_region("cache", 4..6).unwrap();
+        assert_eq!(heap.define_region("other", 5..8), Err(NamedRegionError::Overlaps("cache".to_string())));
+        assert_eq!(heap.define_region("past", 8..11), Err(NamedRegionError::OutOfBounds));
+
+        assert_eq!((heap.alloc_in("cache", 1).unwrap(), heap.alloc_in("cache", 2).unwrap()), (4, 5));
+        assert_eq!(heap.alloc_in("cache", 3).unwrap_err().kind, ErrorKind::NoFreeMemory);      //Full, with room elsewhere
+        let occupancy: Occupancy = heap.region_occupancy("cache").unwrap();
+        assert_eq!((occupancy.used, occupancy.percent(), occupancy.bytes), (2, 100, 2 * sizes::WORD));
+
+        //Its cells are collected like any other
+        collect(&mut heap);
+        assert_eq!(heap.region_occupancy("cache").unwrap().used, 0);
+    }
+
+    #[test]
+    fn refuses_names_and_ranges_that_make_no_region() {
+        let mut heap: Heap = Heap::with_seed(10, 0);
+        heap.log.echo = false;
+        assert_eq!(heap.define_region("empty", 3..3), Err(NamedRegionError::OutOfBounds));
+        for name in ["", "-cache", "4"] {
+            assert_eq!(heap.define_region(name, 0..2), Err(NamedRegionError::Invalid));
+        }
+        assert_eq!(heap.alloc_in("cache", 1).unwrap_err().kind, ErrorKind::OutOfBounds);
+        assert!(heap.region_occupancy("cache").is_none());
+
+        //Defining a region again moves it, rather than overlapping itself
+        heap.define_region("cache", 0..4).unwrap();
+        heap.define_region("cache", 2..6).unwrap();
+        assert_eq!(heap.named_regions().len(), 1);
+        assert_eq!(heap.alloc_in("cache", 1).unwrap(), 2);
+        assert_eq!(heap.drop_region("cache").map(|region| region.cells), Some(2..6));
+        assert!(heap.drop_region("cache").is_none() && !heap.cells[2].freed);
+    }
+}
@@ -0,0 +1,111 @@
+//Observer hooks
+/*
+    Everything that happens on the heap is already reported as an Event (see log.rs). Observers are how code outside
+    the crate hears about them as they happen, to build its own logging, metrics or visualisation on top,
+    without parsing the console output or the log file.
+
+    Register one with `Heap::observe()`. Each hook has an empty default, so an observer only implements the ones it needs,
+    and `on_event()` sees every event (including the kinds without a hook of their own).
+*/
+
+use core::time::Duration;
+
+use crate::{log::Event, Heap};
+use crate::prelude::*;
+
+/// Hooks called as things happen on the heap, see the top of this module
+pub trait HeapObserver {
+    /// Data was stored in a free cell
+    fn on_alloc(&mut self, _index: usize, _data: i32) {}
+
+    /// A cell's data was reclaimed, either by a collection or by hand
+    fn on_free(&mut self, _index: usize) {}
+
+    /// A tracing collection found a cell to be live (the index is where the cell was before the collection, if it moved)
+    fn on_mark(&mut self, _index: usize) {}
+
+    /// A collection is about to begin
+    fn on_collect_start(&mut self, _number: usize) {}
+
+    /// A collection finished, after pausing the program for `pause`
+    fn on_collect_end(&mut self, _number: usize, _marked: usize, _swept: usize, _pause: Duration) {}
+
+    /// Any event at all, after the hook for its kind (if it has one)
+    fn on_event(&mut self, _event: &Event) {}
+}
+
+impl Heap {
+    /// Registers an observer, which is told about everything that happens on the heap from now on
+    pub fn observe(&mut self, observer: Box<dyn HeapObserver + Send>) {
+        self.log.observe(observer);
+    }
+}
+
+//Calls whichever hook matches the event, then on_event
+pub(crate) fn notify(observer: &mut dyn HeapObserver, event: &Event) {
+    match *event {
+        Event::Alloc { index, data } => observer.on_alloc(index, data),
+        Event::Swept { index } | Event::Freed { index } => observer.on_free(index),
+        Event::Marked { index } => observer.on_mark(index),
+        Event::CollectStart { number } => observer.on_collect_start(number),
+        Event::Collected { number, marked, swept, root_scan, mark, sweep, .. } => {
+            observer.on_collect_end(number, marked, swept, root_scan + mark + sweep)
+        }
+        _ => {}
+    }
+    observer.on_event(event);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::{collect, free_cell, tests::graph};
+
+    //Writes down each hook it hears, and how many events it saw in all
+    #[derive(Default)]
+    struct Recorder {
+        heard: Arc<Mutex<(Vec<String>, usize)>>,
+    }
+
+    impl HeapObserver for Recorder {
+        fn on_alloc(&mut self, index: usize, data: i32) {
+            self.heard.lock().unwrap().0.push(format!("alloc {} {}", index, data));
+        }
+
+        fn on_free(&mut self, index: usize) {
+            self.heard.lock().unwrap().0.push(format!("free {}", index));
+        }
+
+        fn on_collect_start(&mut self, number: usize) {
+            self.heard.lock().unwrap().0.push(format!("start {}", number));
+        }
+
+        fn on_collect_end(&mut self, number: usize, marked: usize, swept: usize, _pause: Duration) {
+            self.heard.lock().unwrap().0.push(format!("end {} {} {}", number, marked, swept));
+        }
+
+        fn on_event(&mut self, _event: &Event) {
+            self.heard.lock().unwrap().1 += 1;
+        }
+    }
+
+    #[test]
+    fn tells_observers_about_each_event_as_it_happens() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        let recorder: Recorder = Recorder::default();
+        let heard = Arc::clone(&recorder.heard);
+        heap.observe(Box::new(recorder));
+        let seen: usize = heap.log.sequence();
+
+        heap.alloc(7).unwrap();
+        free_cell(&mut heap, 1, false).unwrap();
+        collect(&mut heap);
+
+        let (hooks, events) = heard.lock().unwrap().clone();
+        assert_eq!(hooks, ["alloc 3 7", "free 1", "start 1", "free 2", "free 3", "end 1 1 2"]);
+        assert_eq!(events, heap.log.sequence() - seen);
+    }
+}
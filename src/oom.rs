@@ -0,0 +1,133 @@
+//Out of memory handling
+/*
+    When an allocation finds no free cell, the heap runs a full collection and tries again. If that still doesn't
+    free a cell, a real runtime has to decide what happens next -> ask the OS for more memory, drop caches it
+    can rebuild later, or give up. That decision belongs to whoever is embedding the heap, so it is made by
+    an out of memory handler registered with `Heap::on_out_of_memory()`.
+
+    The handler is given the heap (to free or unroot whatever it can spare) and how many times it has been asked
+    during this allocation, and returns what to do next. Without a handler, the allocation fails with `NoFreeMemory`.
+*/
+
+use crate::{collect, error::{ErrorKind, GcError, Operation}, free_alloc, log::Event, Cell, Heap, IndexResult};
+use crate::prelude::*;
+
+/// What to do about an allocation that failed even after a full collection, decided by the out of memory handler
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OomAction {
+    Grow(usize),        //Add this many free cells to the heap, then try again
+    Retry,              //The handler freed something itself (i.e. dropped cached data), collect again and try again
+    Fail,               //Give up, the allocation returns `NoFreeMemory`
+    Abort,              //Stop the process, as a runtime with no way to carry on would (without std, panic instead)
+}
+
+/// Decides what to do when the heap runs out of memory, given the heap and how many times it has been asked for this allocation
+pub type OomHandler = Box<dyn FnMut(&mut Heap, usize) -> OomAction + Send>;
+
+impl Heap {
+    /// Registers the out of memory handler (replacing any there was), see the top of this module.
+    /// The handler is asked again after every `Grow` or `Retry` that still leaves no free cell, so it has to give up eventually
+    pub fn on_out_of_memory(&mut self, handler: impl FnMut(&mut Heap, usize) -> OomAction + Send + 'static) {
+        self.oom_handler = Some(Box::new(handler));
+    }
+
+    /// Removes the out of memory handler, so allocations fail as soon as a collection doesn't free a cell
+    pub fn remove_out_of_memory_handler(&mut self) {
+        self.oom_handler = None;
+    }
+
+    /// Adds free cells to the end of the memory pool
+    pub fn grow(&mut self, cells: usize) {
+        let from: usize = self.cells.len();
+        self.cells.resize(from + cells, Cell::new());
+        self.log.emit(Event::Grown { from, to: self.cells.len() });
+        self.publish();
+    }
+
+    //Allocates in the first free cell -> collecting if there are none, and asking the out of memory handler if that doesn't help
+    pub(crate) fn alloc_or_collect(&mut self, data: i32) -> IndexResult {
+        if let Ok(index) = free_alloc(self, data, None) {
+            return Ok(index);
+        }
+        collect(self);
+
+        let mut attempt: usize = 0;
+        loop {
+            if let Ok(index) = free_alloc(self, data, None) {
+                return Ok(index);
+            }
+            attempt += 1;
+
+            //The handler is taken out while it runs, so it can be handed the heap
+            let mut handler: OomHandler = match self.oom_handler.take() {
+                Some(handler) => handler,
+                None => return Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None)),
+            };
+            let action: OomAction = handler(self, attempt);
+            if self.oom_handler.is_none() {
+                self.oom_handler = Some(handler);       //...unless it registered a new one while it ran
+            }
+
+            match action {
+                OomAction::Grow(cells) => self.grow(cells),
+                OomAction::Retry => collect(self),
+                OomAction::Fail => return Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None)),
+                OomAction::Abort => {
+                    self.log.emit(Event::Warning("Out of memory, the out of memory handler aborted the process".to_string()));
+                    #[cfg(feature = "std")]
+                    std::process::abort();
+                    #[cfg(not(feature = "std"))]
+                    panic!("out of memory, the out of memory handler aborted");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::roots::GLOBALS;
+
+    //A heap whose every cell is in use and rooted, so a collection frees nothing
+    fn full(size: usize) -> Heap {
+        let mut heap: Heap = Heap::with_seed(size, 0);
+        heap.log.echo = false;
+        for i in 0..size {
+            heap.alloc(i as i32).unwrap();
+            heap.add_root(GLOBALS, i).unwrap();
+        }
+        heap
+    }
+
+    #[test]
+    fn fails_without_a_handler_once_a_collection_frees_nothing() {
+        let mut heap: Heap = full(2);
+        assert_eq!(heap.alloc(9).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        assert_eq!((heap.cells.len(), heap.history.len()), (2, 1));
+    }
+
+    #[test]
+    fn does_what_the_handler_decides() {
+        let mut heap: Heap = full(2);
+        heap.on_out_of_memory(|heap, attempt| match attempt {
+            1 => {
+                heap.remove_root(0);                                    //Not enough on its own, cell 0 is only freed by the next collection
+                OomAction::Retry
+            }
+            _ => OomAction::Fail,
+        });
+        assert_eq!(heap.alloc(9).unwrap().index, 0);
+
+        //Asked again until it gives up, growing the heap by 1 cell at a time
+        heap.on_out_of_memory(|_, attempt| if attempt < 3 { OomAction::Grow(0) } else { OomAction::Grow(1) });
+        heap.add_root(GLOBALS, 0).unwrap();
+        assert_eq!((heap.alloc(10).unwrap().index, heap.cells.len()), (2, 3));
+        heap.add_root(GLOBALS, 2).unwrap();
+
+        heap.on_out_of_memory(|_, _| OomAction::Fail);
+        assert_eq!(heap.alloc(11).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        heap.remove_out_of_memory_handler();
+        assert!(heap.alloc(11).is_err() && heap.cells.len() == 3);
+    }
+}
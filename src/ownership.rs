@@ -0,0 +1,277 @@
+//Ownership vs garbage collection
+/*
+    The same program, run twice -> once on the heap, where nothing is freed until the collector finds it unreachable,
+    and once with plain Rust ownership, where each object is freed the moment its last owner lets go of it.
+    Comparing the two shows where each model reclaims memory, and what that costs:
+
+        ownership -> frees happen at the step that drops the last owner (so the peak is as low as it can be),
+                     but objects that own each other in a cycle are never freed (Rc leaks them)
+        gc -> frees wait for a collection (when the heap fills up, and once more at the end), so garbage piles up
+              in the meantime, but cycles are reclaimed like anything else
+
+    A program is a list of steps over named variables (`let a`, `a -> b`, `drop a`...), and ends by dropping whatever
+    variables are left, as Rust does at the end of a scope. On the heap each variable is a root set of its own,
+    and each object's data is its number, so it can be told which object is which even after the copying collector moves it.
+    With ownership each object is an `Rc<Node>`, owning the objects it references. An object that only ever has
+    one owner is freed at exactly the same step a `Box` would be, so Rc stands in for both.
+*/
+
+use alloc::rc::{Rc, Weak};
+use core::{cell::RefCell, fmt};
+
+use crate::{assign_reference, collect, error::GcError, sizes::WORD, unlink_reference, Algorithm, Heap};
+use crate::prelude::*;
+
+/// Names of the built in programs, see `program()`
+pub const PROGRAMS: [&str; 3] = ["list", "shared", "cycle"];
+
+/// One step of a program, on variables named by a single letter
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    Let(&'static str),                      //Allocates a new object, held by a new variable
+    Link(&'static str, &'static str),       //The first variable's object references (owns) the second's
+    Unlink(&'static str, &'static str),     //...and no longer does
+    Drop(&'static str),                     //The variable goes out of scope
+}
+
+/// A program that can be run under both models
+#[derive(Clone, Debug)]
+pub struct Program {
+    pub name: &'static str,
+    pub about: &'static str,        //What the program shows, in a sentence
+    pub steps: Vec<Step>,
+}
+
+/// How a program went under one of the models
+#[derive(Clone, Debug, Default)]
+pub struct Run {
+    pub allocations: usize,
+    pub frees: usize,
+    pub peak_objects: usize,        //The most objects held at once (garbage included, until it is reclaimed)
+    pub peak_bytes: usize,          //The most memory held at once, measured the same way for both (see sizes.rs)
+    pub collections: usize,         //Collections that ran (always 0 with ownership)
+    pub reclaimed: Vec<Vec<String>>,    //The objects freed at each step, and at the end of the program (the last entry)
+    pub leaked: Vec<String>,        //Objects never freed
+}
+
+/// A program run under both models, see `compare()`
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub program: Program,
+    pub algorithm: Algorithm,       //The collector the gc run used
+    pub cells: usize,               //Size of the heap the gc run used
+    pub ownership: Run,
+    pub gc: Run,
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::Let(var) => write!(f, "let {}", var),
+            Step::Link(from, to) => write!(f, "{} -> {}", from, to),
+            Step::Unlink(from, to) => write!(f, "{} -/-> {}", from, to),
+            Step::Drop(var) => write!(f, "drop {}", var),
+        }
+    }
+}
+
+/// A built in program by name (see PROGRAMS), or None if there isn't one called that
+pub fn program(name: &str) -> Option<Program> {
+    use Step::*;
+    let (about, steps): (&str, Vec<Step>) = match name {
+        "list" => (
+            "a linked list is built, then dropped from its head while more objects are allocated",
+            vec![Let("a"), Let("b"), Link("a", "b"), Let("c"), Link("b", "c"), Drop("b"), Drop("c"), Drop("a"), Let("d"), Let("e"), Let("f"), Let("g")],
+        ),
+        "shared" => (
+            "an object shared by two owners outlives the first of them",
+            vec![Let("a"), Let("b"), Let("s"), Link("a", "s"), Link("b", "s"), Drop("s"), Drop("a"), Let("c"), Let("d"), Drop("b"), Let("e"), Let("f"), Let("g")],
+        ),
+        "cycle" => (
+            "two objects that own each other are dropped, leaving a cycle nothing else can reach",
+            vec![Let("a"), Let("b"), Link("a", "b"), Link("b", "a"), Drop("a"), Drop("b"), Let("c"), Let("d"), Let("e"), Let("f"), Let("g")],
+        ),
+        _ => return None,
+    };
+    Some(Program { name: PROGRAMS.into_iter().find(|&known| known == name)?, about, steps })
+}
+
+/// Runs a program with ownership, then on a heap of `cells` cells collected by `algorithm`.
+/// Returns `NoFreeMemory` error if the program holds more objects at once than the heap has cells
+pub fn compare(program: &Program, cells: usize, algorithm: Algorithm) -> Result<Comparison, GcError> {
+    Ok(Comparison {
+        program: program.clone(),
+        algorithm,
+        cells,
+        ownership: run_owned(program),
+        gc: run_collected(program, cells, algorithm)?,
+    })
+}
+
+//What the owned objects tell as they are freed
+#[derive(Default)]
+struct Tracker {
+    live: usize,
+    freed: Vec<String>,             //Freed during the current step
+}
+
+//An object with an owner, which owns the objects it references
+struct Node {
+    name: &'static str,
+    references: RefCell<Vec<Rc<Node>>>,
+    tracker: Rc<RefCell<Tracker>>,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut tracker = self.tracker.borrow_mut();
+        tracker.live -= 1;
+        tracker.freed.push(self.name.to_string());
+    }
+}
+
+fn run_owned(program: &Program) -> Run {
+    let tracker: Rc<RefCell<Tracker>> = Rc::default();
+    let mut variables: Vec<(&str, Rc<Node>)> = Vec::new();
+    let mut every: Vec<Weak<Node>> = Vec::new();
+    let mut run: Run = Run::default();
+
+    for step in program.steps.iter().copied().map(Some).chain([None]) {
+        match step {
+            Some(Step::Let(var)) => {
+                let node: Rc<Node> = Rc::new(Node { name: var, references: RefCell::default(), tracker: tracker.clone() });
+                every.push(Rc::downgrade(&node));
+                variables.push((var, node));
+                tracker.borrow_mut().live += 1;
+                run.allocations += 1;
+            }
+            Some(Step::Link(from, to)) => {
+                if let (Some(from), Some(to)) = (owned(&variables, from), owned(&variables, to)) {
+                    from.references.borrow_mut().push(to.clone());
+                }
+            }
+            Some(Step::Unlink(from, to)) => {
+                if let (Some(from), Some(to)) = (owned(&variables, from), owned(&variables, to)) {
+                    from.references.borrow_mut().retain(|reference| !Rc::ptr_eq(reference, to));
+                }
+            }
+            Some(Step::Drop(var)) => {
+                let position: Option<usize> = variables.iter().position(|(name, _)| *name == var);
+                drop(position.map(|position| variables.remove(position)));
+            }
+            None => variables.clear(),          //The end of the scope
+        }
+
+        let mut tracker = tracker.borrow_mut();
+        let bytes: usize = every.iter().filter_map(Weak::upgrade).map(|node| WORD * (1 + node.references.borrow().len())).sum();
+        run.peak_objects = run.peak_objects.max(tracker.live);
+        run.peak_bytes = run.peak_bytes.max(bytes);
+        run.frees += tracker.freed.len();
+        run.reclaimed.push(core::mem::take(&mut tracker.freed));
+    }
+
+    //Whatever is left owns itself, in a cycle. Breaking the cycles frees it, so the demo doesn't leak for real
+    let leaked: Vec<Rc<Node>> = every.iter().filter_map(Weak::upgrade).collect();
+    run.leaked = leaked.iter().map(|node| node.name.to_string()).collect();
+    for node in &leaked {
+        node.references.borrow_mut().clear();
+    }
+    run
+}
+
+//The object a variable holds, with ownership
+fn owned<'v>(variables: &'v [(&str, Rc<Node>)], var: &str) -> Option<&'v Rc<Node>> {
+    variables.iter().find(|(name, _)| *name == var).map(|(_, node)| node)
+}
+
+fn run_collected(program: &Program, cells: usize, algorithm: Algorithm) -> Result<Run, GcError> {
+    let mut heap: Heap = Heap::with_seed(cells, 0);
+    heap.log.echo = false;
+    heap.policy.algorithm = algorithm;
+    let mut names: Vec<&str> = Vec::new();             //Each object's name, by its number (the data in its cell)
+    let mut held: Vec<usize> = Vec::new();             //Numbers of the objects on the heap after the last step
+    let mut run: Run = Run::default();
+
+    for step in program.steps.iter().copied().map(Some).chain([None]) {
+        match step {
+            Some(Step::Let(var)) => {
+                let index: usize = heap.alloc(names.len() as i32)?.index;
+                heap.add_root(var, index).expect("the cell was just allocated");
+                names.push(var);
+                run.allocations += 1;
+            }
+            Some(Step::Link(from, to)) => {
+                if let (Some(from), Some(to)) = (variable(&heap, from), variable(&heap, to)) {
+                    assign_reference(&mut heap, from, to);
+                }
+            }
+            Some(Step::Unlink(from, to)) => {
+                if let (Some(from), Some(to)) = (variable(&heap, from), variable(&heap, to)) {
+                    unlink_reference(&mut heap, from, to);
+                }
+            }
+            Some(Step::Drop(var)) => {
+                heap.drop_root_set(var);
+            }
+            None => {
+                for var in &names {
+                    heap.drop_root_set(var);
+                }
+                collect(&mut heap);
+            }
+        }
+
+        let mut now: Vec<usize> = objects(&heap);
+        now.sort_unstable();                        //In the order they were allocated, as with ownership
+        run.peak_objects = run.peak_objects.max(heap.live());
+        run.peak_bytes = run.peak_bytes.max(heap.bytes_used());
+        run.reclaimed.push(held.iter().filter(|number| !now.contains(number)).map(|&number| names[number].to_string()).collect());
+        run.frees += run.reclaimed.last().map_or(0, Vec::len);
+        held = now;
+    }
+
+    run.collections = heap.history.len();
+    run.leaked = held.iter().map(|&number| names[number].to_string()).collect();
+    Ok(run)
+}
+
+//The cell a variable's object is in on the heap (wherever a copying collection has moved it)
+fn variable(heap: &Heap, var: &str) -> Option<usize> {
+    heap.root_sets().iter().find(|root_set| root_set.name == var).and_then(|root_set| root_set.cells.first().copied())
+}
+
+//The number of every object on the heap
+fn objects(heap: &Heap) -> Vec<usize> {
+    heap.cells.iter().filter(|cell| !cell.freed).filter_map(|cell| cell.data).map(|data| data as usize).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn compares_ownership_with_collection() {
+        let list = program("list").unwrap();
+        let comparison = compare(&list, 6, Algorithm::MarkSweep).unwrap();
+        let drop_a: usize = list.steps.iter().position(|&step| step == Step::Drop("a")).unwrap();
+        assert_eq!(comparison.ownership.reclaimed[drop_a], ["a", "b", "c"]);
+        assert!(comparison.gc.reclaimed[drop_a].is_empty());            //Garbage until the heap fills up and is collected
+        assert_eq!(comparison.gc.reclaimed.iter().position(|freed| freed == &["a", "b", "c"]), Some(list.steps.len() - 1));
+        assert!(comparison.ownership.peak_objects < comparison.gc.peak_objects);
+
+        //Rc leaks a cycle, a tracing collector doesn't
+        let cycle = compare(&program("cycle").unwrap(), 6, Algorithm::MarkSweep).unwrap();
+        assert_eq!(cycle.ownership.leaked, ["a", "b"]);
+        assert!(cycle.gc.leaked.is_empty());
+        assert_eq!((cycle.ownership.frees, cycle.gc.frees), (5, 7));
+    }
+
+    #[test]
+    fn refuses_a_heap_too_small_for_the_program() {
+        assert!(program("tree").is_none());
+        let shared: Program = program("shared").unwrap();
+        assert_eq!(compare(&shared, 4, Algorithm::MarkSweep).unwrap_err().kind, ErrorKind::NoFreeMemory);   //c to g are all held by the end
+        assert!(compare(&shared, 5, Algorithm::MarkSweep).is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+//Periodic collection
+/*
+    Runtimes don't only collect when memory runs short. Many also collect on a timer, so garbage is cleaned up while
+    the program is idle (i.e. waiting for input) rather than in the middle of its next burst of work.
+
+    With `Policy::periodic_gc` set, `run()` is that timer -> a thread of its own that asks for a collection every interval.
+    It stops the world like any other collection on a SharedHeap (see safepoint.rs), so a mutator busy with the heap
+    finishes what it is doing and parks at its next safepoint first, while one that is idle holds nothing up.
+
+    Periodic collections don't stop at breakpoints, as nobody is at a prompt to --continue them.
+*/
+
+use std::{
+    mem,
+    sync::mpsc::{Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use crate::{collect, log::Event, shared::SharedHeap};
+
+/// Collects `heap` every `interval`, until `changes` is disconnected. An interval sent on `changes` replaces the current one
+/// (and starts counting from when it arrived)
+pub fn run(heap: &SharedHeap, mut interval: Duration, changes: Receiver<Duration>) {
+    loop {
+        match changes.recv_timeout(interval) {
+            Ok(changed) => interval = changed,
+            Err(RecvTimeoutError::Timeout) => heap.stopped(|heap, time_to_safepoint| {
+                heap.log.emit(Event::PeriodicGc { interval, time_to_safepoint });
+                let breakpoints = mem::take(&mut heap.breakpoints);
+                collect(heap);
+                heap.breakpoints = breakpoints;
+            }),
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread};
+
+    use super::*;
+    use crate::{breakpoints::Breakpoint, tests::graph, Heap};
+
+    #[test]
+    fn collects_on_a_timer_while_the_mutator_is_idle() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.add_breakpoint(Breakpoint::MarkPhase);
+        heap.on_break(|_, _| panic!("periodic collections don't stop at breakpoints"));
+        let shared: SharedHeap = SharedHeap::new(&mut heap);
+        let (changes, changed) = mpsc::channel::<Duration>();
+        thread::scope(|scope| {
+            let shared: &SharedHeap = &shared;
+            scope.spawn(move || run(shared, Duration::from_millis(1), changed));
+            let mutator = shared.attach();
+            let idle = mutator.idle();
+            while shared.with(|heap| heap.history.is_empty()) {
+                thread::yield_now();
+            }
+            drop(idle);
+            drop(mutator);
+            drop(changes);
+        });
+        drop(shared);
+
+        assert!(heap.cells[2].freed && !heap.cells[1].freed);
+        assert_eq!(heap.breakpoints, vec![Breakpoint::MarkPhase]);
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::PeriodicGc { .. })));
+    }
+
+    #[test]
+    fn follows_a_changed_interval_and_stops_once_disconnected() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        let shared: SharedHeap = SharedHeap::new(&mut heap);
+        let (changes, changed) = mpsc::channel::<Duration>();
+
+        //An hour would outlast the test, so only the changed interval can collect
+        thread::scope(|scope| {
+            let shared: &SharedHeap = &shared;
+            let timer = scope.spawn(move || run(shared, Duration::from_secs(3600), changed));
+            changes.send(Duration::from_millis(1)).unwrap();
+            while shared.with(|heap| heap.history.is_empty()) {
+                thread::yield_now();
+            }
+            drop(changes);
+            timer.join().unwrap();
+        });
+        drop(shared);
+        assert!(heap.cells[2].freed);
+
+        //Disconnected before the first interval is up, so it returns without collecting
+        let collections: usize = heap.history.len();
+        let shared: SharedHeap = SharedHeap::new(&mut heap);
+        let (changes, changed) = mpsc::channel::<Duration>();
+        drop(changes);
+        run(&shared, Duration::from_secs(3600), changed);
+        drop(shared);
+        assert_eq!(heap.history.len(), collections);
+    }
+}
@@ -0,0 +1,67 @@
+//Poisoning
+/*
+    Once a cell is freed its index can be handed out again, so anything still holding on to that index
+    (a stale variable in the program, or a reference that should have gone with it) would quietly read and write
+    whatever ends up there next -> a use after free.
+
+    So every cell that is freed after being in use is poisoned until it is allocated again -> its edges are cleared,
+    its generation is bumped (so handles to it go stale, see handle.rs) and its data is set to POISON. A free cell
+    never holds data otherwise, so the sentinel can't be mistaken for anything real. Reaching a poisoned cell through
+    `assign_reference()`, `set_data()` or `--peek` is reported as a `UseAfterFree` error.
+*/
+
+use crate::{error::{ErrorKind, GcError, Operation}, Cell, IndexResult};
+
+/// The data a cell is poisoned with when it is freed, shown as 0xdead
+pub const POISON: i32 = 0xDEAD;
+
+impl Cell {
+    /// Whether this cell was freed after being in use, and hasn't been allocated since
+    pub fn is_poisoned(&self) -> bool {
+        self.freed && self.data == Some(POISON)
+    }
+
+    //Marks a free cell as poisoned, see free()
+    pub(crate) fn poison(&mut self) {
+        self.data = Some(POISON);
+    }
+}
+
+/// Checks a cell can be used -> returns `UseAfterFree` error if it is poisoned,
+/// `DataIsFree` error if it is free and never was in use, or `OutOfBounds` error if there is no such cell
+pub fn check_access(cells: &[Cell], index: usize, operation: Operation) -> IndexResult {
+    let kind: ErrorKind = match cells.get(index) {
+        None => ErrorKind::OutOfBounds,
+        Some(cell) if cell.is_poisoned() => ErrorKind::UseAfterFree,
+        Some(cell) if cell.freed => ErrorKind::DataIsFree,
+        Some(_) => return Ok(index),
+    };
+    Err(GcError::new(kind, operation, Some(index)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, collect, free_alloc, log::Event, tests::graph, Heap};
+
+    #[test]
+    fn poisons_a_cell_from_when_it_is_freed_until_it_is_reused() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        assert_eq!(check_access(&heap.cells, 2, Operation::Write), Ok(2));
+
+        collect(&mut heap);                                             //Sweeps cell 2
+        assert!(heap.cells[2].is_poisoned() && !heap.cells[4].is_poisoned());
+        assert_eq!(check_access(&heap.cells, 2, Operation::Write).unwrap_err().kind, ErrorKind::UseAfterFree);
+        assert_eq!(check_access(&heap.cells, 4, Operation::Write).unwrap_err().kind, ErrorKind::DataIsFree);
+        assert_eq!(check_access(&heap.cells, 7, Operation::Write).unwrap_err().kind, ErrorKind::OutOfBounds);
+
+        //A reference to it is refused, rather than made to whatever is there next
+        let seen: usize = heap.log.sequence();
+        assign_reference(&mut heap, 0, 2);
+        assert!(heap.cells[0].will_ref == [1] && heap.log.since(seen).any(|event| matches!(event, Event::Warning(_))));
+
+        assert_eq!(free_alloc(&mut heap, 5, None).unwrap(), 2);
+        assert!(!heap.cells[2].is_poisoned() && heap.cells[2].data == Some(5));
+    }
+}
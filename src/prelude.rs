@@ -0,0 +1,13 @@
+//The parts of std's prelude that come from alloc
+/*
+    Without std (see the top of lib.rs) nothing from alloc is in scope by default, not even Vec or format!.
+    Every module that needs them starts with `use crate::prelude::*;`, which is the same items std would give it anyway.
+*/
+
+pub(crate) use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
@@ -0,0 +1,118 @@
+//Memory pressure
+/*
+    The collection policy (see `Policy::gc_threshold`) is a guess at when to collect -> too late and the program stalls
+    on a full heap, too early and it collects more often than it needs to. `run()` puts the guess under load, allocating
+    a steady number of cells per tick for a number of ticks, on top of whatever the heap already holds.
+
+    Each tick's cells are kept alive until the tick ends (as a request handler keeps its objects until it has replied),
+    then become garbage. Each cell references the (up to) three allocated before it in the same tick, so it fills
+    its slot (see sizes.rs) and the threshold sees the heap as full when it is. After every allocation the threshold
+    is checked, as it is after an allocating command. So:
+
+        kept up -> every collection was triggered by the threshold, before the heap filled
+        fell behind -> some allocations found the heap full, and had to stop for a collection then and there
+        out of memory -> even that collection couldn't make room, the tick's cells and the live heap don't fit together
+
+    With no threshold set, every collection is one the heap was forced into.
+*/
+
+use core::time::Duration;
+
+use crate::{assign_reference, check_gc_threshold, Heap};
+use crate::prelude::*;
+
+/// Name of the root set holding the cells allocated during the current tick
+pub const ROOT_SET: &str = "pressure";
+
+/// What happened during `run()`
+#[derive(Clone, Debug, Default)]
+pub struct PressureReport {
+    pub per_tick: usize,
+    pub ticks: usize,               //Ticks that were finished (fewer than asked for if the heap ran out of memory)
+    pub allocations: usize,
+    pub triggered: usize,           //Collections started by the threshold
+    pub forced: usize,              //Collections an allocation had to wait for, as the heap was full
+    pub peak_occupancy: usize,      //The fullest the heap got, as a percentage of its cells
+    pub total_pause: Duration,
+    pub longest_pause: Duration,
+    pub out_of_memory: Option<usize>, //The tick an allocation failed in, if one did (counted from 1)
+}
+
+impl PressureReport {
+    /// Did every collection start before the heap filled up?
+    pub fn kept_up(&self) -> bool {
+        self.forced == 0 && self.out_of_memory.is_none()
+    }
+}
+
+/// Allocates `per_tick` cells per tick for `ticks` ticks, see the top of this module.
+/// Stops at the first allocation that fails, leaving that tick's cells as garbage like every other tick's
+pub fn run(heap: &mut Heap, per_tick: usize, ticks: usize) -> PressureReport {
+    let collections: usize = heap.history.len();
+    let mut report: PressureReport = PressureReport { per_tick, peak_occupancy: heap.occupancy(), ..PressureReport::default() };
+
+    'ticks: for tick in 1..=ticks {
+        for _ in 0..per_tick {
+            let before: usize = heap.history.len();
+            let index: usize = match heap.alloc_or_collect(tick as i32) {
+                Ok(index) => index,
+                Err(_) => {
+                    report.forced += heap.history.len() - before;
+                    report.out_of_memory = Some(tick);
+                    break 'ticks;
+                }
+            };
+            heap.add_root(ROOT_SET, index).expect("the cell was just allocated");
+            //Found through the root set every time, as a copying collection moves the tick's cells
+            let earlier: Vec<usize> = heap
+                .root_sets()
+                .iter()
+                .filter(|root_set| root_set.name == ROOT_SET)
+                .flat_map(|root_set| root_set.cells.iter().rev().skip(1).take(3).copied())
+                .collect();
+            for to in earlier {
+                assign_reference(heap, index, to);
+            }
+            report.allocations += 1;
+            report.forced += heap.history.len() - before;
+            report.peak_occupancy = report.peak_occupancy.max(heap.occupancy());
+
+            let before: usize = heap.history.len();
+            check_gc_threshold(heap);
+            report.triggered += heap.history.len() - before;
+        }
+
+        heap.drop_root_set(ROOT_SET);
+        report.ticks = tick;
+    }
+    heap.drop_root_set(ROOT_SET);
+
+    let pauses = heap.history[collections..].iter().map(|cycle| cycle.pause());
+    report.total_pause = pauses.clone().sum();
+    report.longest_pause = pauses.max().unwrap_or_default();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::collect;
+
+    #[test]
+    fn reports_whether_the_threshold_keeps_up_under_pressure() {
+        let mut heap: Heap = Heap::with_seed(20, 0);
+        heap.log.echo = false;
+        let late = run(&mut heap, 5, 20);
+        assert_eq!((late.ticks, late.allocations, late.triggered), (20, 100, 0));
+        assert!(late.forced > 0 && !late.kept_up());
+
+        heap.policy.gc_threshold = Some(40);
+        collect(&mut heap);                                             //The last tick's garbage, so the next run starts with room
+        let early = run(&mut heap, 5, 20);
+        assert!(early.kept_up() && early.triggered > 0, "{:?}", early);
+        assert!(heap.root_sets().is_empty() && heap.verify().is_empty());
+
+        let too_much = run(&mut heap, 30, 2);
+        assert_eq!((too_much.ticks, too_much.out_of_memory), (0, Some(1)));
+    }
+}
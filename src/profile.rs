@@ -0,0 +1,191 @@
+//Tuning profiles
+/*
+    Every collector trades throughput (how much of the run goes to the program rather than to collecting) against
+    latency (how long the program is stopped for at any one time). A profile sets the heap's whole policy for one
+    side of that trade at once, rather than one --set-policy at a time:
+
+        throughput -> collect only once the heap is nearly full. Few collections run and each finds plenty of garbage,
+                      so little time goes on collecting overall, but every pause sweeps a lot
+        low-latency -> collect as soon as a quarter of the heap is in use. Collections run far more often,
+                       but each has less garbage to sweep, so the longest pause is shorter
+
+    The heap has no incremental collection, and no nursery size for a profile to set (see generational.rs), so a profile
+    sets what it does have -> when collections are triggered, and how the mark phase walks the heap. The algorithm is left as it is.
+
+    `run()` puts a profile through the same workload every time (a stream of allocations, a quarter of them kept
+    for a while by a root), reporting the trade-off it made.
+*/
+
+use core::{fmt, time::Duration};
+
+use crate::{check_gc_threshold, error::GcError, Algorithm, Heap, Instant, MarkOrder, Policy};
+
+/// A named set of policies, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Profile {
+    Throughput,
+    LowLatency,
+}
+
+/// How a profile did on the workload, from `run()`
+#[derive(Clone, Copy, Debug)]
+pub struct Tradeoff {
+    pub profile: Profile,
+    pub allocations: usize,
+    pub collections: usize,
+    pub total_pause: Duration,
+    pub longest_pause: Duration,
+    pub elapsed: Duration,          //The whole run, collections included
+}
+
+impl Profile {
+    /// Names accepted by `from_name()`, for help and error messages
+    pub const NAMES: [&'static str; 2] = ["throughput", "low-latency"];
+
+    /// Every profile, in the order of NAMES
+    pub const ALL: [Profile; 2] = [Profile::Throughput, Profile::LowLatency];
+
+    /// Parses a profile from its name, i.e. `"low-latency"` -> `Profile::LowLatency`
+    pub fn from_name(name: &str) -> Option<Profile> {
+        match name {
+            "throughput" => Some(Profile::Throughput),
+            "low-latency" => Some(Profile::LowLatency),
+            _ => None,
+        }
+    }
+
+    /// The policy the profile sets, collecting with `algorithm`
+    pub fn policy(self, algorithm: Algorithm) -> Policy {
+        match self {
+            Profile::Throughput => Policy {
+                gc_threshold: Some(90),         //Wait until the heap is nearly full
+                algorithm,
+                mark_order: MarkOrder::Dfs,
+                mark_stack: None,               //Never rescan the heap because the worklist overflowed
+                ..Policy::new()
+            },
+            Profile::LowLatency => Policy {
+                gc_threshold: Some(25),         //Collect early, while there is little to sweep
+                algorithm,
+                mark_order: MarkOrder::Dfs,     //The worklist only holds a path through the graph, not a whole level of it
+                mark_stack: None,
+                ..Policy::new()
+            },
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Profile::Throughput => "throughput",
+            Profile::LowLatency => "low-latency",
+        })
+    }
+}
+
+impl Tradeoff {
+    /// Average pause per collection
+    pub fn mean_pause(&self) -> Duration {
+        self.total_pause.checked_div(self.collections as u32).unwrap_or_default()
+    }
+
+    /// The share of the run the program got, rather than the collector (1.0 if nothing was timed)
+    pub fn mutator_share(&self) -> f64 {
+        match self.elapsed.is_zero() {
+            true => 1.0,
+            false => 1.0 - self.total_pause.as_secs_f64() / self.elapsed.as_secs_f64(),
+        }
+    }
+}
+
+impl Heap {
+    /// Sets the heap's policy to a profile's, keeping the algorithm it collects with (along with any periodic collection,
+    /// and how its generations are sized)
+    pub fn set_profile(&mut self, profile: Profile) {
+        self.policy = Policy {
+            periodic_gc: self.policy.periodic_gc,
+            tenuring_threshold: self.policy.tenuring_threshold,
+            survivor_size: self.policy.survivor_size,
+            verify_remembered: self.policy.verify_remembered,
+            region_size: self.policy.region_size,
+            pause_budget: self.policy.pause_budget,
+            intern_threshold: self.policy.intern_threshold,
+            max_free: self.policy.max_free,
+            skip_barriers: self.policy.skip_barriers,
+            ..profile.policy(self.policy.algorithm)
+        };
+    }
+}
+
+/// Runs the workload on a fresh heap of `cells` cells under a profile, collecting with `algorithm`.
+/// Each allocation is followed by the threshold check the REPL makes, and every fourth object is kept (referenced by
+/// a root) until a quarter of the heap is kept, when the oldest is let go.
+/// Returns `NoFreeMemory` error if the heap is too small for the objects kept
+pub fn run(profile: Profile, cells: usize, algorithm: Algorithm, allocations: usize) -> Result<Tradeoff, GcError> {
+    let mut heap: Heap = Heap::with_seed(cells, 0);
+    heap.log.echo = false;
+    heap.policy = profile.policy(algorithm);
+    let keep: usize = (cells / 4).max(1);
+
+    let start = Instant::now();
+    let holder: usize = heap.alloc(0)?.index;
+    heap.add_root("profile", holder).expect("the cell was just allocated");
+    for i in 1..allocations {
+        let object: usize = heap.alloc(i as i32)?.index;
+        if i % 4 == 0 {
+            let holder: usize = heap.root_sets()[0].cells[0];           //Wherever a copying collection has moved it
+            heap.link(heap.handle(holder).expect("roots are in use"), heap.handle(object).expect("the cell was just allocated"))?;
+            if heap.cells[holder].will_ref.len() > keep {
+                let oldest: usize = heap.cells[holder].will_ref[0];
+                heap.unlink(heap.handle(holder).expect("roots are in use"), heap.handle(oldest).expect("references are to cells in use"))?;
+            }
+        }
+        check_gc_threshold(&mut heap);
+    }
+    let elapsed = start.elapsed();
+
+    Ok(Tradeoff {
+        profile,
+        allocations,
+        collections: heap.history.len(),
+        total_pause: heap.history.iter().map(|cycle| cycle.pause()).sum(),
+        longest_pause: heap.history.iter().map(|cycle| cycle.pause()).max().unwrap_or_default(),
+        elapsed,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn tunes_for_throughput_or_latency() {
+        let mut heap: Heap = Heap::with_seed(8, 0);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Copying;
+        heap.set_profile(Profile::LowLatency);
+        assert_eq!((heap.policy.gc_threshold, heap.policy.algorithm), (Some(25), Algorithm::Copying));
+
+        //The same workload, collected more often but with less to sweep each time
+        let throughput = run(Profile::Throughput, 100, Algorithm::MarkSweep, 1000).unwrap();
+        let low_latency = run(Profile::LowLatency, 100, Algorithm::MarkSweep, 1000).unwrap();
+        assert!(low_latency.collections > throughput.collections);
+    }
+
+    #[test]
+    fn only_changes_what_a_profile_is_about() {
+        assert_eq!(Profile::from_name("fast"), None);
+        assert_eq!(Profile::ALL.map(|profile| Profile::from_name(&profile.to_string())), Profile::ALL.map(Some));
+
+        let mut heap: Heap = Heap::with_seed(8, 0);
+        heap.log.echo = false;
+        (heap.policy.max_free, heap.policy.mark_stack) = (Some(50), Some(2));
+        heap.set_profile(Profile::Throughput);
+        assert_eq!((heap.policy.max_free, heap.policy.mark_stack, heap.policy.gc_threshold), (Some(50), None, Some(90)));
+
+        //The root holding the objects kept, and the one it keeps, leave no room for the next
+        assert_eq!(run(Profile::Throughput, 2, Algorithm::MarkSweep, 10).unwrap_err().kind, ErrorKind::NoFreeMemory);
+    }
+}
@@ -0,0 +1,303 @@
+//Reading commands at the prompt
+/*
+    With the `readline` feature (on by default) the prompt is a line editor: arrow keys to move through
+    and edit the history, Ctrl+R to search it, and history that is kept between sessions in ~/.gc_rust_history.
+    Tab completes command names, their keyword arguments (i.e. --export <json|dot>), and cell indexes
+    based on what is currently on the heap (i.e. only free cells for --alloc_at).
+
+    Without it, lines are read straight from stdin.
+*/
+
+use std::io;
+
+use gc_rust::Heap;
+#[cfg(feature = "readline")]
+use gc_rust::{histogram::Dimension, ownership, query, scenario::Shape, Algorithm, MarkOrder};
+
+#[cfg(feature = "readline")]
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, history::FileHistory,
+    validate::Validator, CompletionType, Config, Context, Editor, Helper,
+};
+
+/// Name of the history file, kept in the user's home directory
+#[cfg(feature = "readline")]
+const HISTORY_FILE: &str = ".gc_rust_history";
+
+/// Where commands typed by the user come from
+pub struct Prompt {
+    #[cfg(feature = "readline")]
+    editor: Option<Editor<Completions, FileHistory>>, //None if the terminal couldn't be set up for editing
+    #[cfg(feature = "readline")]
+    history: Option<std::path::PathBuf>, //None if there is no home directory to keep history in
+}
+
+impl Prompt {
+    //Sets up the line editor, loading the history of previous sessions (if there is any)
+    #[cfg(feature = "readline")]
+    pub fn new() -> Prompt {
+        Prompt::with_history(std::env::var_os("HOME").map(|home| std::path::Path::new(&home).join(HISTORY_FILE)))
+    }
+
+    //The line editor, with its history kept in the file at `history` rather than the home directory
+    #[cfg(feature = "readline")]
+    fn with_history(history: Option<std::path::PathBuf>) -> Prompt {
+        //List every candidate at once, so the cell indexes on offer can all be seen
+        let config = Config::builder().completion_type(CompletionType::List).build();
+
+        let editor = match Editor::with_config(config) {
+            Ok(mut editor) => {
+                editor.set_helper(Some(Completions::default()));
+                if let Some(path) = &history {
+                    let _ = editor.load_history(path); //No history yet is fine
+                }
+                Some(editor)
+            }
+            Err(why) => {
+                println!("Line editing is unavaliable ({}), falling back to plain input", why);
+                None
+            }
+        };
+
+        Prompt { editor, history }
+    }
+
+    #[cfg(not(feature = "readline"))]
+    pub fn new() -> Prompt {
+        Prompt {}
+    }
+
+    /// Waits for the next line from the user, completing cell indexes from the current state of `heap`.
+    /// Returns None once there is no more input (Ctrl+D, or the end of a piped script)
+    pub fn read_line(&mut self, heap: &Heap) -> Option<String> {
+        //The heap may have changed since the last line, so refresh what can be completed
+        self.read_completing(Completions::of(heap))
+    }
+
+    /// `read_line()`, with what can be completed already taken from the heap -> for reading on another thread
+    #[cfg_attr(not(feature = "readline"), allow(unused_variables))]
+    pub fn read_completing(&mut self, completions: Completions) -> Option<String> {
+        #[cfg(feature = "readline")]
+        if let Some(editor) = &mut self.editor {
+            editor.set_helper(Some(completions));
+
+            loop {
+                match editor.readline("> ") {
+                    Ok(line) => {
+                        if !line.trim().is_empty() {
+                            let _ = editor.add_history_entry(line.as_str());
+                            //Saved as we go, as --exit ends the program without unwinding
+                            if let Some(path) = &self.history {
+                                let _ = editor.save_history(path);
+                            }
+                        }
+                        return Some(line);
+                    }
+                    //Ctrl+C just abandons the current line, like a shell
+                    Err(ReadlineError::Interrupted) => continue,
+                    Err(ReadlineError::Eof) => return None,
+                    Err(why) => {
+                        println!("Unable to read input: {}", why);
+                        return None;
+                    }
+                }
+            }
+        }
+
+        read_stdin()
+    }
+}
+
+//Plain line reading, without any editing
+fn read_stdin() -> Option<String> {
+    let mut input: String = String::new(); //Create a new string variable each time to store the users input
+    match io::stdin().read_line(&mut input) {
+        Ok(0) => None, //End of input
+        Ok(_) => Some(input),
+        Err(why) => {
+            println!("Unable to read Stdin: {}", why);
+            None
+        }
+    }
+}
+
+/// What tab can complete to, taken from the heap just before the prompt is shown
+#[cfg(feature = "readline")]
+#[derive(Default)]
+pub struct Completions {
+    all: Vec<String>,                   //Every cell index (and name)
+    live: Vec<String>,                  //Cells in use (can be referenced, or named)
+    free: Vec<String>,                  //Free cells (can be allocated into)
+    regions: Vec<String>,               //Named regions (see named_regions.rs)
+}
+
+/// Without line editing there is nothing to complete
+#[cfg(not(feature = "readline"))]
+pub struct Completions;
+
+#[cfg(not(feature = "readline"))]
+impl Completions {
+    pub fn of(_heap: &Heap) -> Completions {
+        Completions
+    }
+}
+
+#[cfg(feature = "readline")]
+impl Completions {
+    /// Every cell index (and name) on the heap right now, sorted by what they can be used for
+    pub fn of(heap: &Heap) -> Completions {
+        let mut completions = Completions::default();
+
+        for (i, cell) in heap.cells.iter().enumerate() {
+            completions.all.push(i.to_string());
+            if cell.freed {
+                completions.free.push(i.to_string());
+            } else {
+                completions.live.push(i.to_string());
+            }
+
+            //Named cells can be completed by name wherever their index can
+            if let Some(name) = &cell.name {
+                completions.all.push(name.clone());
+                completions.live.push(name.clone());
+            }
+        }
+
+        completions.regions = heap.named_regions().iter().map(|region| region.name.clone()).collect();
+        completions
+    }
+
+    //Candidates for the next argument of a command, given the words typed before it (command first)
+    fn arguments(&self, before: &[&str]) -> Vec<String> {
+        let keywords = |words: &[&str]| words.iter().map(|word| word.to_string()).collect();
+
+        match (before[0], before.len()) {
+            ("--root" | "--unroot", _) if before.contains(&"--set") => Vec::new(),
+            ("--root", _) => [self.all.clone(), keywords(&["--set"])].concat(),
+            ("--unroot", 1) => [self.live.clone(), keywords(&["--set"])].concat(),
+            ("--peek" | "--reachable" | "--why-live" | "--retained", 1) => self.all.clone(),
+            ("--alloc_at" | "--alloc_str" | "--list_new", 1) => self.free.clone(),
+            ("--link_ref" | "--unlink_ref" | "--list_push" | "--weakmap_put" | "--swap", 1 | 2) | ("--name" | "--set" | "--free" | "--local" | "--list_pop" | "--field_set" | "--finalizer" | "--weakmap_get" | "--tag" | "--copy" | "--move", 1) => self.live.clone(),
+            ("--field_set", 3) => [self.live.clone(), keywords(&["off"])].concat(),
+            ("--free", 2) | ("--exit", 1) => keywords(&["--force"]),
+            ("--why-live", 2) => keywords(&["--all"]),
+            ("--query", _) => keywords(&query::FIELDS),
+            ("--watch", 1) => [self.all.clone(), keywords(&["off"])].concat(),
+            ("--break", 1) => keywords(&["on-mark-phase", "on-sweep", "off"]),
+            ("--break", 2) if before[1] == "on-sweep" => self.live.clone(),
+            ("--break", 2 | 3) => keywords(&["off"]),
+            ("--name" | "--watch", 2) => keywords(&["off"]),
+            ("--gc", 1) => keywords(&["minor", "major", "full", "--diff"]),
+            ("--gc", 2) => keywords(&["--diff"]),
+            ("--relocate", 1) => keywords(&["finish"]),
+            ("--finalizer", 2) => keywords(&["resurrect"]),
+            ("--move", 2) => self.free.clone(),
+            ("--copy", 2) => [self.free.clone(), keywords(&["deep"])].concat(),
+            ("--copy", 3) => keywords(&["deep"]),
+            ("--tag", 3) => keywords(&["off"]),
+            ("--region", 2) => keywords(&["off"]),
+            ("--alloc_in" | "--region", 1) => self.regions.clone(),
+            ("--set-policy", 1) => keywords(&["gc-threshold", "algorithm", "mark-order", "mark-stack", "periodic-gc", "tenuring-threshold", "survivor-size", "verify-remembered", "region-size", "pause-budget", "intern-threshold", "max-free", "barriers"]),
+            ("--set-policy", 2) if before[1] == "algorithm" => keywords(&Algorithm::NAMES),
+            ("--set-policy", 2) if before[1] == "mark-order" => keywords(&MarkOrder::NAMES),
+            ("--set-policy", 2) => keywords(&["off"]),
+            ("--profile", 1) => keywords(&["throughput", "low-latency", "compare"]),
+            ("--site", 1) => keywords(&["off"]),
+            ("--histogram", 1) => keywords(&Dimension::NAMES),
+            ("--scenario", 1) => keywords(&["cycle"]),
+            ("--compare-ownership", 1) => keywords(&ownership::PROGRAMS),
+            ("--gen", 1) => keywords(&["graph"]),
+            ("--gen", 5) => keywords(&Shape::NAMES),
+            ("--export", 1) => keywords(&["json", "dot"]),
+            ("--verbose" | "--quiet" | "--no-color" | "--log-file" | "--trace" | "--metrics-csv", 1) => {
+                keywords(&["off"])
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(feature = "readline")]
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        //Only the word being typed is completed
+        let start: usize = line[..pos].rfind([' ', ';']).map(|i| i + 1).unwrap_or(0);
+        let word: &str = &line[start..pos];
+        //...as part of the last command, when several are chained with ';'
+        let command: usize = line[..start].rfind(';').map(|i| i + 1).unwrap_or(0);
+        let before: Vec<&str> = line[command..start].split_whitespace().collect();
+
+        let candidates: Vec<String> = match before.first() {
+            None => crate::COMMANDS.iter().map(|command| command.to_string()).collect(),
+            Some(_) => self.arguments(&before),
+        };
+
+        Ok((start, candidates.into_iter().filter(|candidate| candidate.starts_with(word)).collect()))
+    }
+}
+
+//Nothing beyond completion is needed, the rest use their defaults
+#[cfg(feature = "readline")]
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+#[cfg(feature = "readline")]
+impl Highlighter for Completions {}
+
+#[cfg(feature = "readline")]
+impl Validator for Completions {}
+
+#[cfg(feature = "readline")]
+impl Helper for Completions {}
+
+#[cfg(all(test, feature = "readline"))]
+mod tests {
+    use std::{env, fs, process};
+
+    use gc_rust::spec_alloc;
+    use rustyline::history::{DefaultHistory, History};
+
+    use super::*;
+
+    //What tab offers at the end of `line`
+    fn complete(heap: &Heap, line: &str) -> Vec<String> {
+        let history: DefaultHistory = DefaultHistory::new();
+        Completions::of(heap).complete(line, line.len(), &Context::new(&history)).unwrap().1
+    }
+
+    #[test]
+    fn picks_up_the_history_of_previous_sessions() {
+        let path = env::temp_dir().join(format!("gc-rust-history-{}", process::id()));
+        fs::write(&path, "--alloc_at 0\n--gc\n").unwrap();
+        let prompt: Prompt = Prompt::with_history(Some(path.clone()));
+        fs::remove_file(&path).unwrap();
+
+        let history: &FileHistory = prompt.editor.as_ref().unwrap().history();
+        assert_eq!(history.iter().map(String::as_str).collect::<Vec<&str>>(), ["--alloc_at 0", "--gc"]);
+    }
+
+    #[test]
+    fn starts_with_no_history_when_there_is_none_yet() {
+        let path = env::temp_dir().join("gc-rust-missing").join(HISTORY_FILE);
+        let prompt: Prompt = Prompt::with_history(Some(path));
+        assert!(prompt.editor.as_ref().unwrap().history().is_empty());
+    }
+
+    #[test]
+    fn completes_cells_by_what_the_command_can_use() {
+        let mut heap: Heap = Heap::with_seed(3, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 1, None, 1).unwrap();
+        heap.name_cell(1, Some("head")).unwrap();
+
+        assert_eq!(complete(&heap, "--alloc_at "), ["0", "2"]);
+        assert_eq!(complete(&heap, "--link_ref "), ["1", "head"]);
+        assert_eq!(complete(&heap, "--gc; --peek h"), ["head"]);
+        assert_eq!(complete(&heap, "--free 1 "), ["--force"]);
+        assert_eq!(complete(&heap, "--no-c"), ["--no-color"]);
+        assert!(complete(&heap, "--unknown ").is_empty());
+    }
+}
@@ -0,0 +1,217 @@
+//Python API
+/*
+    With the `python` feature, the library builds to a Python extension module (through PyO3), so the collector can be
+    driven from Python -> i.e. from a Jupyter notebook, with the numbers of each collection plotted in matplotlib.
+
+        pip install maturin
+        maturin develop --release               (builds and installs `gc_rust` into the current virtualenv, see pyproject.toml)
+
+    Python gets a `Heap` class, whose objects are referred to by cell index (as at the prompt). Anything that goes
+    wrong raises ValueError (or MemoryError when nothing could be allocated) with the same message the REPL would print.
+    `state()` is the same JSON as --export json, and `stats()` / `history()` hand over numbers as dicts, ready to plot:
+
+        heap = gc_rust.Heap(100, seed=1)
+        ...
+        cycles = heap.history()
+        plt.plot([c["number"] for c in cycles], [c["pause_us"] for c in cycles])
+*/
+
+use pyo3::{
+    exceptions::{PyMemoryError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use crate::{
+    collect,
+    error::{ErrorKind, GcError, Operation},
+    handle::Handle,
+    poison::check_access,
+    roots::GLOBALS,
+    spec_alloc, Algorithm, Heap,
+};
+
+/// The virtual heap, as Python sees it (as a class called Heap).
+/// Not shared between threads, like anything else in a notebook
+#[pyclass(name = "Heap", unsendable)]
+pub struct PyHeap {
+    heap: Heap,
+}
+
+#[pymethods]
+impl PyHeap {
+    /// Creates a heap of `size` cells, seeded with `seed` (or a random seed) for its arbitrary data
+    #[new]
+    #[pyo3(signature = (size, seed = None))]
+    fn new(size: usize, seed: Option<u64>) -> PyResult<PyHeap> {
+        if size == 0 {
+            return Err(PyValueError::new_err("The heap needs at least one cell"));
+        }
+
+        let mut heap: Heap = match seed {
+            Some(seed) => Heap::with_seed(size, seed),
+            None => Heap::new(size),
+        };
+        heap.log.echo = false;
+        Ok(PyHeap { heap })
+    }
+
+    /// Amount of cells in the heap
+    #[getter]
+    fn size(&self) -> usize {
+        self.heap.cells.len()
+    }
+
+    /// The collection algorithm, by name: mark-sweep, copying or rc
+    #[getter]
+    fn algorithm(&self) -> String {
+        self.heap.policy.algorithm.to_string()
+    }
+
+    #[setter]
+    fn set_algorithm(&mut self, name: &str) -> PyResult<()> {
+        match Algorithm::from_name(name) {
+            Some(algorithm) => {
+                self.heap.policy.algorithm = algorithm;
+                Ok(())
+            }
+            None => Err(PyValueError::new_err(format!("Unknown algorithm '{}', expected one of: {}", name, Algorithm::NAMES.join(", ")))),
+        }
+    }
+
+    /// Allocates data in the first free cell (collecting first if there are none), returning its index
+    fn alloc(&mut self, data: i32) -> PyResult<usize> {
+        Ok(self.heap.alloc(data).map_err(raise)?.index)
+    }
+
+    /// Allocates data in a given free cell, returning its index
+    fn alloc_at(&mut self, index: usize, data: i32) -> PyResult<usize> {
+        spec_alloc(&mut self.heap, data, None, index).map_err(raise)
+    }
+
+    /// Makes cell `source` reference cell `target`
+    fn link(&mut self, source: usize, target: usize) -> PyResult<()> {
+        let (from, to) = (self.handle(source, Operation::Link)?, self.handle(target, Operation::Link)?);
+        self.heap.link(from, to).map_err(raise)
+    }
+
+    /// Removes the reference from cell `source` to cell `target`
+    fn unlink(&mut self, source: usize, target: usize) -> PyResult<()> {
+        let (from, to) = (self.handle(source, Operation::Unlink)?, self.handle(target, Operation::Unlink)?);
+        self.heap.unlink(from, to).map_err(raise)
+    }
+
+    /// Overwrites the data in a cell
+    fn write(&mut self, index: usize, data: i32) -> PyResult<()> {
+        let handle: Handle = self.handle(index, Operation::Write)?;
+        self.heap.write(handle, data).map_err(raise)
+    }
+
+    /// Frees a cell by hand (roots only with `force`)
+    #[pyo3(signature = (index, force = false))]
+    fn free(&mut self, index: usize, force: bool) -> PyResult<()> {
+        let handle: Handle = self.handle(index, Operation::Free)?;
+        self.heap.free(handle, force).map_err(raise)
+    }
+
+    /// Makes a cell a root (in the "globals" root set, like --root)
+    fn root(&mut self, index: usize) -> PyResult<()> {
+        self.heap
+            .add_root(GLOBALS, index)
+            .map_err(|_| PyValueError::new_err(format!("There is no cell {}, the heap has {} cells", index, self.heap.cells.len())))
+    }
+
+    /// Stops a cell being a root, returning False if it wasn't one
+    fn unroot(&mut self, index: usize) -> bool {
+        self.heap.remove_root(index)
+    }
+
+    /// Runs a collection with the current algorithm, returning how many cells were swept
+    fn collect(&mut self) -> usize {
+        collect(&mut self.heap);
+        self.heap.history.last().map_or(0, |cycle| cycle.swept)
+    }
+
+    /// The whole heap as JSON, the same as --export json (`json.loads()` it for a dict)
+    fn state(&self) -> String {
+        self.heap.to_json()
+    }
+
+    /// Numbers about the heap as it is now, as a dict (the same as --stats)
+    fn stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = self.heap.stats();
+        let dict = PyDict::new(py);
+        dict.set_item("capacity", stats.capacity)?;
+        dict.set_item("live", stats.live)?;
+        dict.set_item("free", stats.free)?;
+        dict.set_item("roots", stats.roots)?;
+        dict.set_item("allocations", stats.allocations)?;
+        dict.set_item("collections", stats.collections)?;
+        dict.set_item("high_water", stats.high_water)?;
+        dict.set_item("bytes_capacity", stats.bytes_capacity)?;
+        dict.set_item("bytes_used", stats.bytes_used)?;
+        dict.set_item("bytes_reclaimed", stats.bytes_reclaimed)?;
+        Ok(dict)
+    }
+
+    /// Every collection so far, oldest first, as a dict of its numbers (times in microseconds)
+    fn history<'py>(&self, py: Python<'py>) -> PyResult<Vec<Bound<'py, PyDict>>> {
+        self.heap
+            .history
+            .iter()
+            .map(|cycle| {
+                let dict = PyDict::new(py);
+                dict.set_item("number", cycle.number)?;
+                dict.set_item("algorithm", cycle.algorithm.to_string())?;
+                dict.set_item("at_us", cycle.at.as_micros())?;
+                dict.set_item("marked", cycle.marked)?;
+                dict.set_item("swept", cycle.swept)?;
+                dict.set_item("bytes_swept", cycle.bytes_swept)?;
+                dict.set_item("root_scan_us", cycle.root_scan.as_micros())?;
+                dict.set_item("mark_us", cycle.mark.as_micros())?;
+                dict.set_item("sweep_us", cycle.sweep.as_micros())?;
+                dict.set_item("pause_us", cycle.pause().as_micros())?;
+                Ok(dict)
+            })
+            .collect()
+    }
+
+    /// Sequence number of the latest event, to pass to `events()` later
+    fn sequence(&self) -> usize {
+        self.heap.log.sequence()
+    }
+
+    /// Every event (as its message) since sequence number `after`, oldest first
+    #[pyo3(signature = (after = 0))]
+    fn events(&self, after: usize) -> Vec<String> {
+        self.heap.log.since(after).map(|event| event.to_string()).collect()
+    }
+
+    fn __repr__(&self) -> String {
+        let stats = self.heap.stats();
+        format!("Heap(size={}, live={}, algorithm='{}')", stats.capacity, stats.live, self.heap.policy.algorithm)
+    }
+}
+
+impl PyHeap {
+    //A handle to the object in a cell, refusing cells that are free or out of bounds the way the heap would
+    fn handle(&self, index: usize, operation: Operation) -> PyResult<Handle> {
+        check_access(&self.heap.cells, index, operation).map_err(raise)?;
+        Ok(self.heap.handle(index).expect("check_access() only passes cells in use"))
+    }
+}
+
+//The exception an error from the heap is raised as
+fn raise(error: GcError) -> PyErr {
+    match error.kind {
+        ErrorKind::NoFreeMemory => PyMemoryError::new_err(error.to_string()),
+        _ => PyValueError::new_err(error.to_string()),
+    }
+}
+
+/// The `gc_rust` module Python imports
+#[pymodule]
+fn gc_rust(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyHeap>()?;
+    Ok(())
+}
@@ -0,0 +1,262 @@
+//Heap queries
+/*
+    On a big heap the full --state dump is more than anyone can read. A query picks out the cells that matter,
+    with predicates over their fields joined by && (both) and || (either, binding looser than &&):
+
+        freed==false && ref_count>1 && marked==false
+        kind==list || kind==record
+        root || age>=3
+        tag==cache && marked==false
+
+    Each predicate compares a field with ==, !=, <, <=, > or >=, or names a yes/no field on its own (`root` is the
+    same as `root==true`). Fields are listed in FIELDS -> numbers, yes/no flags, a cell's kind, its name, or one of its
+    tags (`tag==cache` matches a cell with that tag among its others, see tags.rs).
+*/
+
+use core::fmt;
+
+use crate::{Cell, Heap, Kind};
+use crate::prelude::*;
+
+/// Every field a query can test, see `Field`
+pub const FIELDS: [&str; 14] = [
+    "index", "data", "ref_count", "refs", "referrers", "age", "garbage_for", "generation",
+    "freed", "root", "marked", "kind", "name", "tag",
+];
+
+/// A parsed query, ready to be matched against cells
+#[derive(Clone, Debug, PartialEq)]
+pub struct Query {
+    any: Vec<Vec<Predicate>>,       //Matches if every predicate of any one group does (the groups were joined by ||)
+}
+
+/// Reasons a query couldn't be parsed
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueryError {
+    Empty,                          //There was nothing to match on (i.e. a dangling && or ||)
+    UnknownField(String),           //The field isn't in FIELDS
+    BadValue(String, String),       //The value doesn't suit the field, (field, value)
+    BadOperator(String),            //Only numbers can be compared with <, <=, > or >=
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QueryError::Empty => write!(f, "part of the query is empty"),
+            QueryError::UnknownField(field) => write!(f, "there is no field called '{}', try one of: {}", field, FIELDS.join(", ")),
+            QueryError::BadValue(field, value) => write!(f, "'{}' isn't a value {} can have", value, field),
+            QueryError::BadOperator(predicate) => write!(f, "'{}' can't be used that way, only numbers can be compared with < or >", predicate),
+        }
+    }
+}
+
+//One field compared against one value
+#[derive(Clone, Debug, PartialEq)]
+struct Predicate {
+    field: Field,
+    op: Op,
+    value: Value,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    Index,              //Position of the cell on the heap
+    Data,               //The data in the cell (cells without any only match !=)
+    RefCount,           //reference_count
+    Refs,               //How many references the cell makes
+    Referrers,          //How many cells reference it
+    Age,
+    GarbageFor,
+    Generation,
+    Freed,
+    Root,
+    Marked,
+    Kind,
+    Name,               //Compared with the cell's name, unnamed cells only match !=
+    Tag,                //Whether the cell has the tag (or doesn't, for !=)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(i64),
+    Flag(bool),
+    Kind(Kind),
+    Text(String),
+}
+
+//Longest first, so <= isn't read as <
+const OPS: [(&str, Op); 6] = [("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge), ("<", Op::Lt), (">", Op::Gt)];
+
+impl Query {
+    /// Parses a query, i.e. `freed==false && ref_count>1`
+    pub fn parse(text: &str) -> Result<Query, QueryError> {
+        let any: Vec<Vec<Predicate>> = text
+            .split("||")
+            .map(|group| group.split("&&").map(Predicate::parse).collect())
+            .collect::<Result<_, _>>()?;
+        Ok(Query { any })
+    }
+
+    /// Whether the cell at `index` matches the query
+    pub fn matches(&self, index: usize, cell: &Cell) -> bool {
+        self.any.iter().any(|every| every.iter().all(|predicate| predicate.matches(index, cell)))
+    }
+}
+
+impl Heap {
+    /// Every cell (free or not) that matches a query, in order of index
+    pub fn query(&self, query: &Query) -> Vec<usize> {
+        (0..self.cells.len()).filter(|&i| query.matches(i, &self.cells[i])).collect()
+    }
+}
+
+impl Predicate {
+    fn parse(text: &str) -> Result<Predicate, QueryError> {
+        let text: &str = text.trim();
+        if text.is_empty() {
+            return Err(QueryError::Empty);
+        }
+
+        //The first operator in the predicate splits it, a field on its own is a yes/no field that must be set
+        let split: Option<(usize, &str, Op)> = OPS
+            .iter()
+            .filter_map(|&(symbol, op)| text.find(symbol).map(|at| (at, symbol, op)))
+            .min_by_key(|&(at, symbol, _)| (at, usize::MAX - symbol.len()));
+        let (field, op, value) = match split {
+            Some((at, symbol, op)) => (text[..at].trim(), op, text[at + symbol.len()..].trim()),
+            None => (text, Op::Eq, "true"),
+        };
+
+        let name: &str = field;
+        let field: Field = Field::from_name(name).ok_or(QueryError::UnknownField(name.to_string()))?;
+        let value: Value = field.parse_value(value).ok_or(QueryError::BadValue(name.to_string(), value.to_string()))?;
+        if !matches!(value, Value::Number(_)) && !matches!(op, Op::Eq | Op::Ne) {
+            return Err(QueryError::BadOperator(text.to_string()));
+        }
+
+        Ok(Predicate { field, op, value })
+    }
+
+    fn matches(&self, index: usize, cell: &Cell) -> bool {
+        if let (Field::Tag, Value::Text(tag)) = (self.field, &self.value) {
+            return cell.has_tag(tag) == (self.op == Op::Eq);            //A cell has any number of tags, rather than one value
+        }
+        let (found, wanted) = match (self.field.read(index, cell), &self.value) {
+            (None, _) => return self.op == Op::Ne,         //Nothing to compare, i.e. a cell without data
+            (Some(Value::Number(found)), Value::Number(wanted)) => (found, *wanted),
+            (Some(found), wanted) => return (found == *wanted) == (self.op == Op::Eq),
+        };
+
+        match self.op {
+            Op::Eq => found == wanted,
+            Op::Ne => found != wanted,
+            Op::Lt => found < wanted,
+            Op::Le => found <= wanted,
+            Op::Gt => found > wanted,
+            Op::Ge => found >= wanted,
+        }
+    }
+}
+
+impl Field {
+    fn from_name(name: &str) -> Option<Field> {
+        Some(match name {
+            "index" => Field::Index,
+            "data" => Field::Data,
+            "ref_count" | "reference_count" => Field::RefCount,
+            "refs" => Field::Refs,
+            "referrers" => Field::Referrers,
+            "age" => Field::Age,
+            "garbage_for" => Field::GarbageFor,
+            "generation" => Field::Generation,
+            "freed" | "free" => Field::Freed,
+            "root" | "is_root" => Field::Root,
+            "marked" => Field::Marked,
+            "kind" => Field::Kind,
+            "name" => Field::Name,
+            "tag" | "tags" => Field::Tag,
+            _ => return None,
+        })
+    }
+
+    //Reads a value to compare against this field out of the query, None if the field can't have it
+    fn parse_value(self, value: &str) -> Option<Value> {
+        match self {
+            Field::Freed | Field::Root | Field::Marked => value.parse().ok().map(Value::Flag),
+            Field::Kind => match value {
+                "int" => Some(Kind::Int),
+                "string" | "str" => Some(Kind::Str),
+                "bytes" => Some(Kind::Bytes),
+                "list" => Some(Kind::List),
+                "record" => Some(Kind::Record),
+                _ => None,
+            }
+            .map(Value::Kind),
+            Field::Name | Field::Tag => Some(Value::Text(value.trim_matches(|c| c == '\'' || c == '"').to_string())),
+            _ => value.parse().ok().map(Value::Number),
+        }
+    }
+
+    //The value of this field in a cell, None if it doesn't have one (no data, or no name)
+    fn read(self, index: usize, cell: &Cell) -> Option<Value> {
+        let number = |n: usize| Some(Value::Number(n as i64));
+        match self {
+            Field::Index => number(index),
+            Field::Data => cell.data.map(|data| Value::Number(data.into())),
+            Field::RefCount => Some(Value::Number(cell.reference_count.into())),
+            Field::Refs => number(cell.will_ref.len()),
+            Field::Referrers => number(cell.by_ref.len()),
+            Field::Age => number(cell.age),
+            Field::GarbageFor => number(cell.garbage_for),
+            Field::Generation => Some(Value::Number(cell.generation.into())),
+            Field::Freed => Some(Value::Flag(cell.freed)),
+            Field::Root => Some(Value::Flag(cell.is_root())),
+            Field::Marked => Some(Value::Flag(cell.marked)),
+            Field::Kind => Some(Value::Kind(cell.kind)),
+            Field::Name => cell.name.clone().map(Value::Text),
+            Field::Tag => None,                             //Matched on its own, see Predicate::matches()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::graph;
+
+    #[test]
+    fn queries_cells_by_their_fields() {
+        let mut heap: Heap = graph(4, &[(0, 1), (2, 1), (1, 3)]);
+        heap.log.echo = false;
+        let matching = |heap: &Heap, text: &str| heap.query(&Query::parse(text).unwrap());
+        assert_eq!(matching(&heap, "freed==false && ref_count>1"), vec![1]);
+        assert_eq!(matching(&heap, "root || refs>=1 && index!=1"), vec![0, 2]);
+        assert_eq!(matching(&heap, "freed && index<6"), vec![4, 5]);
+
+        heap.name_cell(3, Some("leaf")).unwrap();
+        assert_eq!(matching(&heap, "name==leaf"), vec![3]);
+        assert_eq!(Query::parse("kind>list"), Err(QueryError::BadOperator("kind>list".to_string())));
+        assert_eq!(Query::parse("age==1 &&"), Err(QueryError::Empty));
+    }
+
+    #[test]
+    fn says_why_a_query_makes_no_sense() {
+        assert_eq!(Query::parse("colour==red"), Err(QueryError::UnknownField("colour".to_string())));
+        assert_eq!(Query::parse("freed==maybe"), Err(QueryError::BadValue("freed".to_string(), "maybe".to_string())));
+        assert_eq!(Query::parse("kind==tree"), Err(QueryError::BadValue("kind".to_string(), "tree".to_string())));
+        assert_eq!(Query::parse("root || "), Err(QueryError::Empty));
+
+        let error: QueryError = Query::parse("refs==few").unwrap_err();
+        assert_eq!(error.to_string(), "'few' isn't a value refs can have");
+    }
+}
@@ -0,0 +1,189 @@
+//Records
+/*
+    A record is a cell with named fields, each of which references another cell (or nothing yet), the way a struct
+    in a real program holds pointers to other objects -> i.e. a linked list node with a `next` field,
+    or a tree node with `left` and `right`.
+
+    Each field that is set is an ordinary reference, so the collectors trace records without knowing about them.
+    The fields only give those references names. Two fields can point at the same cell, in which case it is
+    referenced once, until neither field points at it any more.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{assign_reference, error::GcError, free_alloc, spec_alloc, unlink_reference, Heap, Kind};
+use crate::prelude::*;
+
+/// The layout of a record, kept in its cell
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Record {
+    pub name: String,               //The record's type, i.e. "point"
+    pub fields: Vec<Field>,         //In the order they were declared
+}
+
+/// A named reference held by a record
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub to: Option<usize>,          //The cell the field references, None if it hasn't been set
+}
+
+/// Reasons a record couldn't be made or changed
+#[derive(Debug)]
+pub enum RecordError {
+    NotARecord,         //There is no record at that index
+    NoSuchField,        //The record doesn't have a field with that name
+    DuplicateField,     //Two fields were given the same name
+    TargetIsFree,       //Fields can only reference cells in use (not free, or outside the heap)
+    Alloc(GcError),     //The record itself couldn't be allocated
+}
+
+impl Heap {
+    /// Allocates a record called `name` at `at` (or the first free cell), with fields in the order given,
+    /// each referencing the cell given (or nothing yet).
+    /// Nothing is allocated if two fields share a name, or a field would reference a free cell
+    pub fn record_new(&mut self, name: &str, fields: &[(&str, Option<usize>)], at: Option<usize>) -> Result<usize, RecordError> {
+        for (i, (field, to)) in fields.iter().enumerate() {
+            if fields[..i].iter().any(|(other, _)| other == field) {
+                return Err(RecordError::DuplicateField);
+            }
+            if to.is_some_and(|to| self.cells.get(to).is_none_or(|cell| cell.freed)) {
+                return Err(RecordError::TargetIsFree);
+            }
+        }
+
+        let index: usize = match at {
+            Some(at) => spec_alloc(self, 0, None, at),
+            None => free_alloc(self, 0, None),
+        }
+        .map_err(RecordError::Alloc)?;
+        let names: Vec<&str> = fields.iter().map(|&(field, _)| field).collect();
+        self.make_record(index, name, &names);
+
+        for &(field, to) in fields {
+            self.field_set(index, field, to)?;
+        }
+        Ok(index)
+    }
+
+    /// The record at `index`, or None if there isn't one there
+    pub fn record(&self, index: usize) -> Option<&Record> {
+        self.cells.get(index).filter(|cell| !cell.freed).and_then(|cell| cell.record.as_ref())
+    }
+
+    /// Points a record's field at another cell (or at nothing), replacing whatever it referenced before.
+    /// Under reference counting, the cell it referenced before is reclaimed straight away if nothing else references it
+    pub fn field_set(&mut self, index: usize, field: &str, to: Option<usize>) -> Result<(), RecordError> {
+        let record: &Record = self.record(index).ok_or(RecordError::NotARecord)?;
+        let at: usize = record.fields.iter().position(|f| f.name == field).ok_or(RecordError::NoSuchField)?;
+        if to.is_some_and(|to| self.cells.get(to).is_none_or(|cell| cell.freed)) {
+            return Err(RecordError::TargetIsFree);
+        }
+
+        let old: Option<usize> = record.fields[at].to;
+        if old == to {
+            return Ok(());
+        }
+        if let Some(record) = &mut self.cells[index].record {
+            record.fields[at].to = to;
+        }
+
+        //The new reference is made before the old one goes, so a cell moved between fields is never left unreferenced
+        if let Some(to) = to.filter(|to| !self.cells[index].will_ref.contains(to)) {
+            assign_reference(self, index, to);
+        }
+        if let Some(old) = old.filter(|&old| !self.references_by_field(index, old)) {
+            unlink_reference(self, index, old);
+        }
+        Ok(())
+    }
+
+    //Turns a newly allocated cell into a record called `name`, with every field unset
+    pub(crate) fn make_record(&mut self, index: usize, name: &str, fields: &[&str]) {
+        self.cells[index].data = None;                  //The fields are all a record holds
+        self.cells[index].kind = Kind::Record;
+        self.cells[index].record = Some(Record {
+            name: name.to_string(),
+            fields: fields.iter().map(|&name| Field { name: name.to_string(), to: None }).collect(),
+        });
+    }
+
+    //Adds an unset field to the end of a record (see containers.rs, where a map's keys are its fields)
+    pub(crate) fn add_field(&mut self, index: usize, name: &str) {
+        if let Some(record) = &mut self.cells[index].record {
+            record.fields.push(Field { name: name.to_string(), to: None });
+        }
+    }
+
+    //Removes a field from a record, which should be unset first so its reference goes with it
+    pub(crate) fn remove_field(&mut self, index: usize, name: &str) {
+        if let Some(record) = &mut self.cells[index].record {
+            record.fields.retain(|field| field.name != name);
+        }
+    }
+
+    //Whether any of a record's fields still reference a cell
+    fn references_by_field(&self, index: usize, to: usize) -> bool {
+        self.record(index).is_some_and(|record| record.fields.iter().any(|field| field.to == Some(to)))
+    }
+}
+
+impl Record {
+    //Unsets every field referencing a cell, once the reference itself is gone (see unlink_reference() and free_cell())
+    pub(crate) fn forget(&mut self, to: usize) {
+        for field in self.fields.iter_mut().filter(|field| field.to == Some(to)) {
+            field.to = None;
+        }
+    }
+
+    //Moves every field to where its cell was copied, see copying.rs
+    pub(crate) fn relocate(&mut self, forwarding: &[Option<usize>]) {
+        for field in self.fields.iter_mut() {
+            field.to = field.to.and_then(|to| forwarding[to]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, collect, error::ErrorKind, tests::graph};
+
+    //Where each field of the record at `index` points
+    fn fields(heap: &Heap, index: usize) -> Vec<(String, Option<usize>)> {
+        heap.record(index).unwrap().fields.iter().map(|field| (field.name.clone(), field.to)).collect()
+    }
+
+    #[test]
+    fn references_each_cell_its_fields_point_at_once() {
+        let mut heap: Heap = graph(3, &[]);
+        heap.log.echo = false;
+        let node: usize = heap.record_new("node", &[("left", Some(1)), ("right", Some(1)), ("up", None)], Some(3)).unwrap();
+        assign_reference(&mut heap, 0, node);
+        assert_eq!(fields(&heap, node), [("left".to_string(), Some(1)), ("right".to_string(), Some(1)), ("up".to_string(), None)]);
+        assert_eq!(heap.cells[node].will_ref, [1]);
+
+        //Cell 1 is referenced until neither field points at it
+        heap.field_set(node, "left", Some(2)).unwrap();
+        assert_eq!(heap.cells[node].will_ref, [1, 2]);
+        heap.field_set(node, "right", None).unwrap();
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && !heap.cells[2].freed);
+        assert_eq!(fields(&heap, node)[1], ("right".to_string(), None));
+    }
+
+    #[test]
+    fn refuses_fields_it_cannot_hold() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        let node: usize = heap.record_new("node", &[("next", None)], None).unwrap();
+
+        assert!(matches!(heap.record_new("pair", &[("a", None), ("a", None)], None), Err(RecordError::DuplicateField)));
+        assert!(matches!(heap.record_new("pair", &[("a", Some(5))], None), Err(RecordError::TargetIsFree)));
+        assert!(matches!(heap.record_new("pair", &[], Some(0)), Err(RecordError::Alloc(error)) if error.kind == ErrorKind::Occupied));
+        assert!(matches!(heap.field_set(node, "prev", Some(1)), Err(RecordError::NoSuchField)));
+        assert!(matches!(heap.field_set(node, "next", Some(9)), Err(RecordError::TargetIsFree)));
+        assert!(matches!(heap.field_set(1, "next", Some(1)), Err(RecordError::NotARecord)));
+        assert_eq!((heap.live(), heap.cells[node].will_ref.len()), (3, 0));
+    }
+}
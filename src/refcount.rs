@@ -0,0 +1,372 @@
+//Reference counting
+/*
+    Instead of tracing from the roots, reference counting keeps track of how many references point AT each cell.
+    A cell that nothing references (and isn't a root) can't be reached, so it is freed, which drops the count of
+    everything IT referenced, possibly freeing those too (a cascade).
+
+    Its famous weakness is cycles -> cells that only reference each other never drop to zero,
+    so they are never reclaimed, even once nothing else can reach them.
+
+    Reclamation happens the moment a reference is removed (see `release()`), and a collection reclaims anything
+    else whose count is zero. The counts are the ones assign_reference() and unlink_reference() keep in
+    `Cell::reference_count` -> those count both ends of a reference, so the references TO a cell are its count less
+    the references it makes itself. Removing a reference only looks at the cells it frees, never the rest of the heap.
+
+    Updating a count on every pointer write is what makes plain reference counting slow, so modern RC systems
+    buffer the updates instead. The buffered variant (`Algorithm::BufferedRc`) logs an increment or decrement for
+    every reference made or removed, and applies them in one batch at the next collection. Updates to the same
+    cell are coalesced first -> a reference made and removed again before the batch (or removed and made again)
+    cancels out, so neither update is ever applied. The variant keeps counts of its own, which lag behind the
+    reference graph until the batch is applied to them, so nothing is reclaimed in between -> a count only drops to
+    zero once its batch is applied. The batch then reclaims the cells it left at zero (and whatever they cascade to),
+    along with the cells allocated since the last one that nothing has referenced yet. The rest of the heap isn't
+    looked at. `Heap::rc_updates()` counts how many updates the coalescing elided.
+
+    The counts are taken from the graph at the first batch, and again whenever they may have gone stale -> a reference
+    was changed (or a collection ran) while another algorithm was in use.
+*/
+
+use core::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{generational::CollectionKind, log::Event, reclaim, stats::GcCycle, Algorithm, Cell, Heap, Instant};
+use crate::prelude::*;
+
+/// Reference count updates logged by the buffered variant, and what became of them (see the top of this module)
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UpdateBuffer {
+    pending: Vec<(usize, i32)>,     //Updates waiting for the next batch -> (cell, +1 or -1), in the order they were made
+    #[serde(default)]
+    counts: Vec<i32>,               //Each cell's count as of the last batch (empty until the first, or once they are stale)
+    #[serde(default)]
+    zero: Vec<usize>,               //Cells in use that may have a count of zero -> allocated since the last batch, or left by it as a root or to be finalized
+    pub logged: usize,              //Total updates that have gone through a batch
+    pub applied: usize,             //...and how many were left to apply once each batch was coalesced
+}
+
+impl UpdateBuffer {
+    /// Updates waiting for the next collection
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Total updates that cancelled out, so were never applied
+    pub fn elided(&self) -> usize {
+        self.logged - self.applied
+    }
+
+    //The heap was shrunk to `size` cells (see shrink.rs), so updates to the cells past the end are dropped
+    pub(crate) fn truncate(&mut self, size: usize) {
+        self.pending.retain(|&(index, _)| index < size);
+        self.counts.truncate(size);
+        self.zero.retain(|&index| index < size);
+    }
+
+    //The counts no longer follow the reference graph, so they are taken from it again at the next batch
+    pub(crate) fn forget_counts(&mut self) {
+        self.counts.clear();
+    }
+}
+
+impl Heap {
+    /// The reference count updates the buffered variant has logged
+    pub fn rc_updates(&self) -> &UpdateBuffer {
+        &self.rc_updates
+    }
+
+    //Called wherever a reference to `index` is made (+1) or removed (-1). Only the buffered variant logs it
+    pub(crate) fn buffer_rc(&mut self, index: usize, delta: i32) {
+        match self.policy.algorithm {
+            Algorithm::BufferedRc => self.rc_updates.pending.push((index, delta)),
+            _ => self.rc_updates.forget_counts(),
+        }
+    }
+
+    //Called for every allocation -> a new cell starts with a count of zero, so the next batch checks it
+    pub(crate) fn buffer_new(&mut self, index: usize) {
+        if self.policy.algorithm == Algorithm::BufferedRc {
+            self.rc_updates.zero.push(index);
+        }
+    }
+
+    //Points the updates and counts at where `forwarding` says their cells went (see copying.rs)
+    pub(crate) fn relocate_rc_updates(&mut self, forwarding: &[Option<usize>]) {
+        let buffer = &mut self.rc_updates;
+        buffer.pending = buffer.pending.iter().filter_map(|&(index, delta)| Some((forwarding.get(index).copied().flatten()?, delta))).collect();
+        buffer.zero = buffer.zero.iter().filter_map(|&index| forwarding.get(index).copied().flatten()).collect();
+        if !buffer.counts.is_empty() {
+            let mut counts: Vec<i32> = vec![0; self.cells.len()];
+            for (index, &count) in buffer.counts.iter().enumerate() {
+                if let Some(to) = forwarding.get(index).copied().flatten() {
+                    counts[to] = count;
+                }
+            }
+            buffer.counts = counts;
+        }
+    }
+}
+
+/// Applies the buffered reference count updates to the variant's counts as one batch, coalescing them first,
+/// then reclaims the cells left with a count of zero (see the top of this module), returning the cycle like `collect()`.
+/// The `root_scan` phase of the cycle is the time spent applying the batch
+pub fn collect_buffered(heap: &mut Heap) -> GcCycle {
+    let start = Instant::now();
+    let pending: Vec<(usize, i32)> = core::mem::take(&mut heap.rc_updates.pending);
+    let size: usize = heap.cells.len();
+    let mut net: Vec<i32> = vec![0; size];
+    for &(index, delta) in pending.iter().filter(|&&(index, _)| index < size) {
+        net[index] += delta;                                        //Updates to the same cell cancel out
+    }
+    let applied: usize = net.iter().map(|delta| delta.unsigned_abs() as usize).sum();
+    heap.rc_updates.logged += pending.len();
+    heap.rc_updates.applied += applied;
+    heap.log.emit(Event::RcBatch { logged: pending.len(), applied });
+
+    //Stale (or never taken) counts come from the graph, as they were before this batch, and every cell is checked
+    let mut counts: Vec<i32> = core::mem::take(&mut heap.rc_updates.counts);
+    let mut check: Vec<usize> = core::mem::take(&mut heap.rc_updates.zero);
+    if counts.is_empty() {
+        counts = heap.cells.iter().zip(&net).map(|(cell, net)| if cell.freed { 0 } else { referrers(cell) - net }).collect();
+        check = (0..size).collect();
+    }
+    counts.resize(size, 0);                                         //Cells added since the last batch (see oom.rs) start at zero
+
+    //Apply the batch
+    for (index, &delta) in net.iter().enumerate().filter(|&(_, &delta)| delta != 0) {
+        counts[index] += delta;
+        check.push(index);
+    }
+    check.retain(|&i| !heap.cells[i].freed && counts[i] == 0);
+    check.sort_unstable();
+    check.dedup();
+    let batch_time = start.elapsed();
+
+    //Free the cells it left at zero, and whatever drops to zero as a result
+    let start = Instant::now();
+    let (rooted, zero): (Vec<usize>, Vec<usize>) = check.into_iter().partition(|&i| heap.is_rooted(i));
+    let swept: usize = cascade(heap, zero, Some(&mut counts));
+    heap.rc_updates.counts = counts;
+    heap.rc_updates.zero.extend(rooted);                            //Checked again next time, in case they stop being roots
+    let free_time = start.elapsed();
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked: heap.live(),                                        //Everything still in use was kept
+        swept,
+        bytes_swept: 0,
+        root_scan: batch_time,
+        mark: Duration::ZERO,
+        sweep: free_time,
+        algorithm: Algorithm::BufferedRc,
+        kind: CollectionKind::Whole,
+        left_in_place: 0,
+    }
+}
+
+/// Runs a reference counting collection over the heap, returning the cycle (`number` is filled in by `collect()`).
+/// The `root_scan` phase of the cycle is the time spent finding the cells with a count of zero, and the `sweep` phase is the time
+/// spent freeing cells (there is no mark phase)
+pub fn collect(heap: &mut Heap) -> GcCycle {
+    //Find the cells in use that nothing references
+    let start = Instant::now();
+    let rooted: Vec<bool> = heap.root_flags();
+    let cells = &heap.cells;
+    let zero: Vec<usize> = (0..cells.len())
+        .filter(|&i| !cells[i].freed && !rooted[i] && referrers(&cells[i]) == 0)
+        .collect();
+    let count_time = start.elapsed();
+
+    //Free them, and whatever drops to zero as a result
+    let start = Instant::now();
+    let swept: usize = cascade(heap, zero, None);
+    let free_time = start.elapsed();
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked: heap.live(),                                        //Everything still in use was kept
+        swept,
+        bytes_swept: 0,
+        root_scan: count_time,
+        mark: Duration::ZERO,
+        sweep: free_time,
+        algorithm: Algorithm::RefCount,
+        kind: CollectionKind::Whole,
+        left_in_place: 0,
+    }
+}
+
+/// Reclaims the cell at `index` straight away if nothing references it any more (and it isn't a root),
+/// cascading to whatever it referenced -> what reference counting does the moment a count drops to zero.
+/// Returns how many cells were reclaimed
+pub fn release(heap: &mut Heap, index: usize) -> usize {
+    let cell = &heap.cells[index];
+    if cell.freed || referrers(cell) != 0 || heap.is_rooted(index) {
+        return 0;
+    }
+
+    cascade(heap, vec![index], None)
+}
+
+//How many references point at a cell in use, going by its count (see the top of this module)
+fn referrers(cell: &Cell) -> i32 {
+    cell.reference_count - cell.will_ref.len() as i32
+}
+
+//How many references point at each cell, from cells that are in use
+pub(crate) fn count(cells: &[Cell]) -> Vec<usize> {
+    let mut counts: Vec<usize> = vec![0; cells.len()];
+    for cell in cells.iter().filter(|cell| !cell.freed) {
+        for &target in &cell.will_ref {
+            counts[target] += 1;
+        }
+    }
+    counts
+}
+
+//Frees every cell in `zero`, then anything whose count drops to zero because of it, returning how many were freed.
+//The counts are the cells' own, unless the buffered variant's are given
+fn cascade(heap: &mut Heap, mut zero: Vec<usize>, mut counts: Option<&mut Vec<i32>>) -> usize {
+    let mut freed: usize = 0;
+
+    while let Some(i) = zero.pop() {
+        if heap.has_finalizer(i) {
+            if counts.is_some() {
+                heap.rc_updates.zero.push(i);                       //The buffered variant checks it again at its next batch
+            }
+            continue;                                               //Left for the next collection to queue (see finalize.rs)
+        }
+        let targets: Vec<usize> = heap.cells[i].will_ref.clone();
+        if !reclaim(&mut heap.cells, &mut heap.log, i) {
+            continue;
+        }
+        freed += 1;
+
+        for target in targets {
+            heap.cells[target].by_ref.retain(|&by| by != i);       //i no longer references anything...
+            heap.cells[target].reference_count -= 1;               //...which assign_reference() counted on both ends
+            let count: i32 = match counts.as_deref_mut() {
+                Some(counts) => {
+                    counts[target] -= 1;
+                    counts[target]
+                }
+                None => referrers(&heap.cells[target]),
+            };
+
+            let cell = &heap.cells[target];
+            if !cell.freed && count == 0 && !heap.is_rooted(target) {
+                zero.push(target);
+            } else if !cell.freed {
+                heap.suspect_cycle(target);                        //What is left of its count may come from a cycle (see hybrid.rs)
+            }
+        }
+    }
+
+    freed
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{assign_reference, collect, roots::GLOBALS, stats::HeapStats, tests::graph, unlink_reference, Algorithm, Heap};
+
+    #[test]
+    fn reclaims_what_the_last_reference_held_as_it_is_removed() {
+        let mut heap: Heap = graph(5, &[(0, 1), (4, 1), (1, 2), (2, 3), (0, 3)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::RefCount;
+
+        //Cell 4 still references cell 1
+        unlink_reference(&mut heap, 0, 1);
+        assert!(!heap.cells[1].freed);
+
+        //Cell 1 goes with its last reference, and cell 2 with it. Cell 0 still references cell 3, and cell 4 (which
+        //nothing references) is left to the next collection, as no reference to it was removed
+        unlink_reference(&mut heap, 4, 1);
+        assert!(heap.cells[1].freed && heap.cells[2].freed);
+        assert!(!heap.cells[3].freed && !heap.cells[4].freed);
+        assert_eq!((heap.cells[3].reference_count, heap.cells[3].by_ref.clone()), (1, vec![0]));
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn counts_a_reference_made_twice_once() {
+        let mut heap: Heap = graph(2, &[(0, 1), (0, 1)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::RefCount;
+
+        unlink_reference(&mut heap, 0, 1);
+        assert!(heap.cells[1].freed && heap.verify().is_empty());
+    }
+
+    #[test]
+    fn coalesces_buffered_reference_count_updates() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::BufferedRc;
+
+        //Nothing is reclaimed until the batch is applied, and the pair of updates to cell 2 cancels out
+        assign_reference(&mut heap, 0, 2);
+        unlink_reference(&mut heap, 0, 2);
+        unlink_reference(&mut heap, 0, 1);
+        assert!(!heap.cells[1].freed && !heap.cells[2].freed);
+        assert_eq!(heap.rc_updates().pending(), 3);
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed);
+        let stats: HeapStats = heap.stats();
+        assert_eq!((stats.rc_updates, stats.rc_updates_elided, heap.rc_updates().pending()), (3, 2, 0));
+    }
+
+    #[test]
+    fn reclaims_a_cell_at_zero_only_once_its_batch_runs() {
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2), (0, 3), (1, 3)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::BufferedRc;
+        collect(&mut heap);
+        assert_eq!(heap.live(), 4);
+
+        unlink_reference(&mut heap, 0, 1);
+        assert!(!heap.cells[1].freed);
+
+        //Cell 2 goes with cell 1, but cell 0 still references cell 3
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed && !heap.cells[3].freed);
+        assert_eq!(heap.history[1].swept, 2);
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn keeps_a_root_at_zero_until_it_stops_being_one() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::BufferedRc;
+        heap.add_root(GLOBALS, 1).unwrap();
+
+        collect(&mut heap);
+        assert!(!heap.cells[1].freed);
+        heap.remove_root(1);
+        collect(&mut heap);
+        assert!(heap.cells[1].freed);
+    }
+
+    #[test]
+    fn takes_the_counts_again_once_another_algorithm_has_changed_the_graph() {
+        let mut heap: Heap = graph(3, &[(0, 1), (0, 2)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::BufferedRc;
+        collect(&mut heap);
+
+        //Made without being logged, so the counts from the last batch don't know cell 1 references cell 2
+        heap.policy.algorithm = Algorithm::MarkSweep;
+        assign_reference(&mut heap, 1, 2);
+        heap.policy.algorithm = Algorithm::BufferedRc;
+        unlink_reference(&mut heap, 0, 2);
+        collect(&mut heap);
+        assert!(!heap.cells[2].freed && heap.verify().is_empty());
+
+        unlink_reference(&mut heap, 0, 1);
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed && heap.verify().is_empty());
+    }
+}
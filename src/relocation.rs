@@ -0,0 +1,152 @@
+//Concurrent copying (Brooks read barriers)
+/*
+    A copying collection (see copying.rs) moves every cell it keeps in one go, while the program is stopped.
+    A concurrent copying collector moves them a few at a time while the program carries on running, which leaves
+    a window where an object has been copied but the program still holds its old address -> reading (or writing)
+    through that address would find the stale copy.
+
+    Brooks' answer is a forwarding pointer in every object, pointing at the object itself until it is copied, then at
+    the copy. Every read goes through it (the read barrier), so the program always finds the to-space copy whichever
+    address it holds. Here `Cell::forward` is that pointer (None standing in for "itself"), and the cell left behind
+    is a stub -> free, but not given out again until the relocation is over, so the pointer stays where it is.
+
+    `Heap::start_relocation()` compacts the heap this way. Each `relocate_step()` copies the highest cells in use down
+    into the lowest free cells (updating the references the heap holds to them), until every cell in use is below
+    every free one. The program's own addresses are left alone -> handles (see handle.rs) and the cells typed at
+    the prompt follow the forwarding pointers instead, and each time one does it counts as a read barrier hit.
+    Once the relocation is over the stubs are freed for good, and handles to them go stale, as after any copying collection.
+*/
+
+use core::{ops::Range, sync::atomic::Ordering};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{copying::move_cells, log::Event, Heap};
+
+/// A relocation in progress, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Relocation {
+    pub per_step: usize,            //Cells copied by each relocate_step()
+    pub moved: usize,               //Cells copied so far
+}
+
+impl Heap {
+    /// Starts compacting the heap concurrently, `per_step` cells at a time (see `relocate_step()`).
+    /// A relocation already in progress carries on at the new pace
+    pub fn start_relocation(&mut self, per_step: usize) {
+        let moved: usize = self.relocation.map_or(0, |relocation| relocation.moved);
+        self.relocation = Some(Relocation { per_step: per_step.max(1), moved });
+    }
+
+    /// The relocation in progress, if there is one
+    pub fn relocation(&self) -> Option<Relocation> {
+        self.relocation
+    }
+
+    /// Copies the next cells of the relocation in progress, leaving a forwarding stub behind each.
+    /// Returns whether there is more to copy -> once there isn't, the relocation is finished
+    pub fn relocate_step(&mut self) -> bool {
+        let Some(relocation) = self.relocation else {
+            return false;
+        };
+
+        let reserved: Range<usize> = self.reserved();                  //Survivor spaces, only a collection copies into them
+        for _ in 0..relocation.per_step {
+            let to: Option<usize> = (0..self.cells.len())
+                .find(|&i| self.cells[i].freed && self.cells[i].forward.is_none() && !reserved.contains(&i));
+            let from: Option<usize> = (0..self.cells.len()).rev().find(|&i| !self.cells[i].freed && !reserved.contains(&i));
+            match (from, to) {
+                (Some(from), Some(to)) if to < from => {
+                    let generation: u32 = self.cells[from].generation;
+                    move_cells(self, &[(from, to)]);
+                    self.cells[from].generation = generation;          //Handles to the stub still match it, so they can be forwarded
+                    self.cells[from].forward = Some(to);
+                    self.relocation.as_mut().expect("a relocation is in progress").moved += 1;
+                }
+                _ => {
+                    self.end_relocation();
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Runs the relocation in progress to the end
+    pub fn finish_relocation(&mut self) {
+        while self.relocate_step() {}
+    }
+
+    /// The read barrier -> where the object that was at `index` is now, following its forwarding pointers.
+    /// Counts a hit whenever it had moved
+    pub fn read_barrier(&self, index: usize) -> usize {
+        let mut at: usize = index;
+        while let Some(to) = self.cells.get(at).and_then(|cell| cell.forward) {
+            at = to;
+        }
+        if at != index {
+            self.read_barrier_hits.fetch_add(1, Ordering::Relaxed);
+        }
+        at
+    }
+
+    //Every cell in use is below every free one -> the relocation is over
+    fn end_relocation(&mut self) {
+        if let Some(relocation) = self.relocation.take() {
+            self.release_stubs();
+            self.log.emit(Event::Relocated { moved: relocation.moved });
+        }
+    }
+
+    //Frees the stubs for good, so their cells can be allocated again. Handles to them go stale
+    pub(crate) fn release_stubs(&mut self) {
+        for cell in self.cells.iter_mut().filter(|cell| cell.forward.is_some()) {
+            cell.forward = None;
+            cell.generation = cell.generation.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, free_alloc, handle::Handle, spec_alloc, tests::graph};
+
+    #[test]
+    fn reads_relocated_cells_through_the_read_barrier() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 4, None, 4).unwrap();
+        spec_alloc(&mut heap, 5, None, 5).unwrap();
+        assign_reference(&mut heap, 5, 4);
+        let handle: Handle = heap.handle(5).unwrap();
+
+        //5 is copied to 0 first, and its handle follows the forwarding pointer left behind
+        heap.start_relocation(1);
+        assert!(heap.relocate_step());
+        assert_eq!((heap.cells[5].forward, heap.resolve(handle), heap.read(handle)), (Some(0), Ok(0), Ok(Some(5))));
+        assert!(free_alloc(&mut heap, 6, None).is_ok_and(|index| index == 1)); //Stubs aren't given out again
+        assert_eq!(heap.stats().read_barrier_hits, 2);
+
+        heap.finish_relocation();
+        assert_eq!(heap.cells[0].will_ref, vec![2]);                    //4 followed 5 down
+        assert!(heap.relocation().is_none() && heap.resolve(handle).is_err());
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn has_nothing_to_do_on_a_compact_heap() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        assert!(!heap.relocate_step() && heap.relocation().is_none());  //Nothing was started
+
+        heap.start_relocation(0);
+        assert_eq!(heap.relocation(), Some(Relocation { per_step: 1, moved: 0 }));
+        assert!(!heap.relocate_step() && heap.relocation().is_none());
+        assert!(heap.log.since(0).any(|event| matches!(event, Event::Relocated { moved: 0 })));
+
+        //Only a cell that moved counts as a hit, and there is nothing to follow past the end of the heap
+        assert_eq!((heap.read_barrier(1), heap.read_barrier(40)), (1, 40));
+        assert_eq!(heap.stats().read_barrier_hits, 0);
+    }
+}
@@ -0,0 +1,113 @@
+//Scoped roots
+/*
+    Rooting an object by hand means remembering to unroot it again, on every path out of the code that needed it.
+    `heap.root(handle)` instead returns a Rooted guard, which keeps the object (and everything reachable from it)
+    alive until the guard is dropped, the same way a Rust value lives until the end of its scope.
+
+    The guard borrows the heap, and hands it back out through Deref, so work carries on through the guard
+    (including rooting more objects, or collecting). The borrow checker then makes sure guards are dropped in the reverse
+    order they were made, which lets the heap keep them as a simple stack.
+*/
+
+use core::ops::{Deref, DerefMut};
+
+use crate::{error::GcError, handle::Handle, log::Event, Heap};
+
+/// Keeps an object rooted for as long as it is alive, from `Heap::root()`
+pub struct Rooted<'heap> {
+    heap: &'heap mut Heap,
+    depth: usize,                   //Position of this guard's entry on the heap's stack of scoped roots
+}
+
+impl Heap {
+    /// Roots the object a handle refers to until the returned guard is dropped.
+    /// Rooting an object that is already a root is fine, it stays one until every guard on it (and every root set or frame) lets go.
+    /// Returns `Stale` error if the object has since been freed, swept or moved
+    pub fn root(&mut self, handle: Handle) -> Result<Rooted<'_>, GcError> {
+        let index: usize = self.resolve(handle)?;
+
+        self.scoped.push(Some(index));
+        if !self.cells[index].is_root() {
+            self.cells[index].make_root();
+            self.log.emit(Event::Rooted { index });
+        }
+
+        Ok(Rooted { depth: self.scoped.len() - 1, heap: self })
+    }
+}
+
+impl Rooted<'_> {
+    /// A handle to the rooted object. It is kept up to date if a copying collection moves the object,
+    /// so this is None only if it was freed by hand (with force)
+    pub fn handle(&self) -> Option<Handle> {
+        self.heap.scoped[self.depth].and_then(|index| self.heap.handle(index))
+    }
+}
+
+impl Deref for Rooted<'_> {
+    type Target = Heap;
+
+    fn deref(&self) -> &Heap {
+        self.heap
+    }
+}
+
+impl DerefMut for Rooted<'_> {
+    fn deref_mut(&mut self) -> &mut Heap {
+        self.heap
+    }
+}
+
+impl Drop for Rooted<'_> {
+    fn drop(&mut self) {
+        //Anything rooted through this guard was dropped first, so this guard's entry is on top
+        self.heap.scoped.truncate(self.depth + 1);
+        if let Some(Some(index)) = self.heap.scoped.pop() {
+            self.heap.refresh_root(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, error::ErrorKind, roots::GLOBALS, Algorithm};
+
+    #[test]
+    fn keeps_an_object_alive_until_its_guard_is_dropped() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        let (kept, held) = (heap.alloc(1).unwrap(), heap.alloc(2).unwrap());
+        heap.link(kept, held).unwrap();
+
+        {
+            let mut rooted: Rooted = heap.root(kept).unwrap();
+            let inner: Rooted = rooted.root(kept).unwrap();             //Rooted twice, by nested guards
+            drop(inner);
+            collect(&mut rooted);
+            assert!(rooted.get(held).is_ok() && rooted.roots() == [kept.index]);
+        }
+        assert!(heap.roots().is_empty());
+        collect(&mut heap);
+        assert!(heap.get(kept).is_err() && heap.get(held).is_err());
+    }
+
+    #[test]
+    fn follows_its_object_through_a_copying_collection() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Copying;
+        let (garbage, moved) = (heap.alloc(1).unwrap(), heap.alloc(2).unwrap());
+        let other: Handle = heap.alloc(3).unwrap();
+        heap.add_root(GLOBALS, other.index).unwrap();
+
+        let mut rooted: Rooted = heap.root(moved).unwrap();
+        collect(&mut rooted);
+        let handle: Handle = rooted.handle().unwrap();
+        assert!(handle != moved && rooted.read(handle).unwrap() == Some(2));
+        assert_eq!(rooted.read(garbage).unwrap_err().kind, ErrorKind::Stale);
+        drop(rooted);
+
+        assert_eq!(heap.root(moved).err().map(|error| error.kind), Some(ErrorKind::Stale));
+    }
+}
@@ -0,0 +1,256 @@
+//Root sets
+/*
+    Roots are the starting points of every trace -> anything they can't reach is garbage.
+    Rather than a fixed pair of root cells, roots live in any number of named root sets
+    (i.e. "globals", "statics", "jit"), each holding as many cells as it likes.
+
+    A cell is a root while any root set holds it, or a live stack frame does (see frames.rs), or a Rooted guard does (see rooted.rs).
+    `Cell::is_root` mirrors that, so the collectors can keep asking the cell directly.
+
+    A program embedding the heap may already keep track of its roots in its own data structures. Rather than flagging
+    each of those cells (and unflagging them again), it can register a root provider, which is asked for its roots
+    every time a collection starts and reports them to a RootVisitor. `Heap::roots()` is every root from both.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{log::Event, scan_roots, Heap};
+use crate::prelude::*;
+
+/// Name of the root set that `--root` adds to when no set is given
+pub const GLOBALS: &str = "globals";
+
+/// A named group of root cells
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RootSet {
+    pub name: String,
+    pub cells: Vec<usize>,          //The roots in this set, in the order they were added
+}
+
+/// Told about each root a root provider reports, see `Heap::add_root_provider()`
+pub trait RootVisitor {
+    fn visit(&mut self, index: usize);
+}
+
+/// Reports roots kept outside the heap, see `Heap::add_root_provider()`
+pub type RootProvider = Box<dyn Fn(&mut dyn RootVisitor) + Send>;
+
+//Collects the roots reported to it, each once
+impl RootVisitor for Vec<usize> {
+    fn visit(&mut self, index: usize) {
+        if !self.contains(&index) {
+            self.push(index);
+        }
+    }
+}
+
+/// Reasons a cell can't be made a root
+#[derive(Debug)]
+pub enum RootError {
+    OutOfBounds,        //There is no cell at that index
+}
+
+impl Heap {
+    /// Adds a cell to the named root set (creating the set if it doesn't exist yet), making it a root.
+    /// A free cell becomes in use (with no data) when it is rooted
+    pub fn add_root(&mut self, set: &str, index: usize) -> Result<(), RootError> {
+        if index >= self.cells.len() {
+            return Err(RootError::OutOfBounds);
+        }
+
+        let position: usize = match self.root_sets.iter().position(|root_set| root_set.name == set) {
+            Some(position) => position,
+            None => {
+                self.root_sets.push(RootSet { name: set.to_string(), cells: Vec::new() });
+                self.root_sets.len() - 1
+            }
+        };
+        if !self.root_sets[position].cells.contains(&index) {
+            self.root_sets[position].cells.push(index);
+        }
+
+        if !self.cells[index].is_root() {
+            self.cells[index].make_root();
+            self.log.emit(Event::Rooted { index });
+        }
+        Ok(())
+    }
+
+    /// Removes a cell from every root set. Returns false if no root set held it.
+    /// The cell is still a root afterwards if a live stack frame holds it
+    pub fn remove_root(&mut self, index: usize) -> bool {
+        let mut removed: bool = false;
+        for root_set in self.root_sets.iter_mut() {
+            let before: usize = root_set.cells.len();
+            root_set.cells.retain(|&cell| cell != index);
+            removed |= root_set.cells.len() != before;
+        }
+        self.root_sets.retain(|root_set| !root_set.cells.is_empty());
+
+        if removed {
+            self.refresh_root(index);
+        }
+        removed
+    }
+
+    /// Removes a whole root set, unrooting each of its cells that nothing else holds.
+    /// Returns the set that was removed, or None if there is no set with that name
+    pub fn drop_root_set(&mut self, name: &str) -> Option<RootSet> {
+        let position: usize = self.root_sets.iter().position(|root_set| root_set.name == name)?;
+        let root_set: RootSet = self.root_sets.remove(position);
+
+        for &cell in &root_set.cells {
+            self.refresh_root(cell);
+        }
+        Some(root_set)
+    }
+
+    /// Every root set, in the order they were created
+    pub fn root_sets(&self) -> &[RootSet] {
+        &self.root_sets
+    }
+
+    /// Registers a root provider -> a callback that reports roots to the visitor it is given, i.e.
+    /// ```ignore
+    /// heap.add_root_provider(move |visitor| cache.iter().for_each(|&cell| visitor.visit(cell)));
+    /// ```
+    /// It is asked again every time the roots are needed (at the start of every collection, and by `reachable()` and the like),
+    /// so it can report different roots each time. Reported cells that are free or outside the heap are ignored.
+    /// The copying collector moves cells reported this way like any other, so the provider has to follow them (see `Event::Moved`)
+    pub fn add_root_provider(&mut self, provider: impl Fn(&mut dyn RootVisitor) + Send + 'static) {
+        self.root_providers.push(Box::new(provider));
+    }
+
+    /// Removes every root provider
+    pub fn clear_root_providers(&mut self) {
+        self.root_providers.clear();
+    }
+
+    /// Every root the collectors start from -> the cells flagged as roots (see `scan_roots()`),
+    /// then any other cells the root providers report
+    pub fn roots(&self) -> Vec<usize> {
+        let mut roots: Vec<usize> = scan_roots(&self.cells);
+        for provider in &self.root_providers {
+            provider(&mut roots);
+        }
+
+        roots.retain(|&root| self.cells.get(root).is_some_and(|cell| !cell.freed));
+        roots
+    }
+
+    /// Whether each cell is a root, see `roots()`
+    pub fn root_flags(&self) -> Vec<bool> {
+        let mut flags: Vec<bool> = vec![false; self.cells.len()];
+        for root in self.roots() {
+            flags[root] = true;
+        }
+        flags
+    }
+
+    /// Whether the cell at `index` is a root, see `roots()`. Only asks the root providers, rather than scanning the whole heap
+    pub fn is_rooted(&self, index: usize) -> bool {
+        if self.cells.get(index).is_none_or(|cell| cell.freed) {
+            return false;
+        }
+        let mut reported: Vec<usize> = Vec::new();
+        for provider in &self.root_providers {
+            provider(&mut reported);
+        }
+        self.cells[index].is_root() || reported.contains(&index)
+    }
+
+    //Makes is_root agree with the root sets, frames and guards again after one of them let go of a cell
+    pub(crate) fn refresh_root(&mut self, index: usize) {
+        let held: bool = self.root_sets.iter().any(|root_set| root_set.cells.contains(&index))
+            || self.frames().iter().any(|frame| frame.locals.contains(&index))
+            || self.scoped.contains(&Some(index));
+
+        if !held && self.cells[index].is_root() {
+            self.cells[index].is_root = false;
+            self.log.emit(Event::Unrooted { index });
+            self.suspect_cycle(index);                      //Only a cycle it is in may be keeping it alive now
+        }
+    }
+
+    //A cell that was freed (by hand, with --force) isn't a root any more, and its index may be reused
+    pub(crate) fn forget_root(&mut self, index: usize) {
+        for root_set in self.root_sets.iter_mut() {
+            root_set.cells.retain(|&cell| cell != index);
+        }
+        self.root_sets.retain(|root_set| !root_set.cells.is_empty());
+
+        for scoped in self.scoped.iter_mut().filter(|scoped| **scoped == Some(index)) {
+            *scoped = None;
+        }
+    }
+
+    //Guards outlive a reset or a load (they borrow the heap, not the pool), but what they rooted doesn't
+    pub(crate) fn forget_scoped(&mut self) {
+        self.scoped.fill(None);
+    }
+
+    //Follows roots that a copying collection moved
+    pub(crate) fn relocate_roots(&mut self, forwarding: &[Option<usize>]) {
+        for root_set in self.root_sets.iter_mut() {
+            root_set.cells = root_set.cells.iter().filter_map(|&old| forwarding[old]).collect();
+        }
+        for scoped in self.scoped.iter_mut() {
+            *scoped = scoped.and_then(|old| forwarding[old]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph};
+
+    #[test]
+    fn keeps_a_cell_rooted_while_any_set_holds_it() {
+        let mut heap: Heap = graph(3, &[(1, 2)]);
+        heap.log.echo = false;
+        heap.add_root("jit", 1).unwrap();
+        heap.add_root(GLOBALS, 1).unwrap();
+        heap.add_root("jit", 1).unwrap();                               //Once per set
+        let names: Vec<&str> = heap.root_sets().iter().map(|set| set.name.as_str()).collect();
+        assert_eq!((names, heap.root_sets()[0].cells.clone()), (vec![GLOBALS, "jit"], vec![0, 1]));
+
+        //Dropping one set leaves cell 1 to the other, dropping that unroots it
+        assert_eq!(heap.drop_root_set("jit").unwrap().cells, [1]);
+        assert!(heap.cells[1].is_root() && heap.drop_root_set("jit").is_none());
+        assert!(heap.remove_root(1) && !heap.remove_root(1));
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed && !heap.cells[0].freed);
+        assert!(matches!(heap.add_root(GLOBALS, 7), Err(RootError::OutOfBounds)));
+    }
+
+    #[test]
+    fn keeps_cells_reported_by_root_providers() {
+        let mut heap: Heap = graph(4, &[(1, 2)]);
+        heap.log.echo = false;
+        heap.add_root_provider(|visitor| visitor.visit(1));
+        collect(&mut heap);
+        assert!(!heap.cells[1].freed && !heap.cells[2].freed && heap.cells[3].freed);
+
+        heap.clear_root_providers();
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && heap.cells[2].freed && !heap.cells[0].freed);
+    }
+
+
+    #[test]
+    fn asks_the_root_providers_for_their_roots() {
+        let mut heap: Heap = graph(3, &[]);
+        heap.log.echo = false;
+        heap.add_root_provider(|visitor| [2, 2, 5, 40].iter().for_each(|&cell| visitor.visit(cell)));
+
+        //Free cells (5) and cells outside the heap (40) are ignored
+        assert_eq!(heap.roots(), [0, 2]);
+        assert!(heap.is_rooted(2) && !heap.is_rooted(1) && !heap.is_rooted(5) && !heap.is_rooted(40));
+        collect(&mut heap);
+        assert!(heap.cells[1].freed && !heap.cells[2].freed);
+
+        heap.clear_root_providers();
+        assert_eq!(heap.roots(), [0]);
+    }
+}
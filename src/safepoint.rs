@@ -0,0 +1,262 @@
+//Safepoints
+/*
+    A stop-the-world collector can't start while other threads are halfway through changing the heap.
+    Real VMs have each mutator thread poll a flag at safepoints (loop back edges, calls, allocations...),
+    and when a collection is requested every thread parks at its next one. The collector only starts
+    once all of them have, so the time that takes (time-to-safepoint) adds to the pause, on top of the collection itself.
+
+    A thread that goes a long time between polls (i.e. a long loop with no safepoint in it) holds up every other thread.
+    A thread that is blocked (i.e. waiting for input) can't poll at all, so it marks itself idle first -> it won't touch
+    the heap until it is back, so it counts as parked, as a thread blocked in native code does in a real VM.
+    Coming back from idle waits for any collection in progress to finish.
+
+    Mutator threads attach() to take part and poll() as they run. The collector calls stop_the_world(),
+    which returns once every attached thread is parked, and the world restarts when the returned guard is dropped.
+*/
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    time::Duration,
+};
+
+use crate::Instant;
+
+/// Coordinates mutator threads with a stop-the-world collector, see the top of this module
+#[derive(Debug, Default)]
+pub struct Safepoint {
+    requested: AtomicBool,          //Polled by every mutator -> set while a stop is pending or in progress
+    state: Mutex<State>,
+    changed: Condvar,               //Signalled whenever a thread parks, attaches or detaches, and when the world restarts
+}
+
+#[derive(Debug, Default)]
+struct State {
+    attached: usize,                //Mutator threads taking part
+    parked: usize,                  //...and how many of them are parked at a safepoint
+    idle: usize,                    //...or idle, which counts the same (see Mutator::idle())
+    stopping: bool,                 //A stop is pending or in progress (only one at a time)
+    epoch: u64,                     //Bumped each time the world restarts, so parked threads know to carry on
+    requested_at: Option<Instant>,  //When the current stop was requested
+    arrivals: Vec<Duration>,        //How long each parked thread took to reach a safepoint, in the order they arrived
+}
+
+/// A mutator thread's membership of a `Safepoint`, from `Safepoint::attach()`. Detaches when dropped
+#[derive(Debug)]
+pub struct Mutator<'a> {
+    safepoint: &'a Safepoint,
+}
+
+/// A mutator thread being idle, from `Mutator::idle()`. Comes back when dropped
+#[derive(Debug)]
+pub struct Idle<'a> {
+    safepoint: &'a Safepoint,
+}
+
+/// Proof that every mutator is parked, from `Safepoint::stop_the_world()`. The world restarts when it is dropped
+#[derive(Debug)]
+pub struct StoppedWorld<'a> {
+    safepoint: &'a Safepoint,
+    pub time_to_safepoint: Duration,    //From the stop being requested until the last thread parked
+    pub arrivals: Vec<Duration>,        //Each thread's time-to-safepoint, in the order they arrived
+    stopped_at: Instant,
+}
+
+impl Safepoint {
+    pub fn new() -> Safepoint {
+        Safepoint::default()
+    }
+
+    /// Joins the calling thread to the mutators the collector has to wait for.
+    /// If a stop is in progress, this waits for the world to restart first
+    pub fn attach(&self) -> Mutator<'_> {
+        let mut state: MutexGuard<State> = self.lock();
+        while state.stopping {
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        state.attached += 1;
+        Mutator { safepoint: self }
+    }
+
+    /// Requests a stop, and waits for every attached mutator to park at a safepoint.
+    /// The thread calling this must not be attached itself (it would wait for itself forever)
+    pub fn stop_the_world(&self) -> StoppedWorld<'_> {
+        let mut state: MutexGuard<State> = self.lock();
+        while state.stopping {
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        let requested_at: Instant = Instant::now();
+        state.stopping = true;
+        state.requested_at = Some(requested_at);
+        state.arrivals.clear();
+        self.requested.store(true, Ordering::Release);
+
+        while state.parked + state.idle < state.attached {
+            state = self.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+
+        StoppedWorld {
+            safepoint: self,
+            time_to_safepoint: requested_at.elapsed(),
+            arrivals: state.arrivals.clone(),
+            stopped_at: Instant::now(),
+        }
+    }
+
+    /// Mutator threads currently attached
+    pub fn attached(&self) -> usize {
+        self.lock().attached
+    }
+
+    //A panicking mutator shouldn't take the collector down with it, the state is still consistent
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Mutator<'_> {
+    /// A safepoint -> parks here until the world restarts if a stop has been requested, otherwise returns straight away.
+    /// Returns true if the thread parked
+    pub fn poll(&self) -> bool {
+        //The fast path every poll takes when no collection is pending, a single load
+        if !self.safepoint.requested.load(Ordering::Acquire) {
+            return false;
+        }
+
+        let safepoint: &Safepoint = self.safepoint;
+        let mut state: MutexGuard<State> = safepoint.lock();
+        if !state.stopping {
+            return false;               //The world restarted between the load and the lock
+        }
+
+        let waited: Duration = state.requested_at.map(|at| at.elapsed()).unwrap_or_default();
+        state.arrivals.push(waited);
+        state.parked += 1;
+        safepoint.changed.notify_all();
+
+        let epoch: u64 = state.epoch;
+        while state.epoch == epoch {
+            state = safepoint.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        true
+    }
+
+    /// Marks the thread as idle until the guard is dropped, see the top of this module.
+    /// An idle thread must not touch the heap, and doesn't need to poll
+    pub fn idle(&self) -> Idle<'_> {
+        self.safepoint.lock().idle += 1;
+        self.safepoint.changed.notify_all();          //A pending stop may have been waiting on this thread
+        Idle { safepoint: self.safepoint }
+    }
+}
+
+impl Drop for Idle<'_> {
+    fn drop(&mut self) {
+        //Back to the heap, but not while the world is stopped
+        let mut state: MutexGuard<State> = self.safepoint.lock();
+        while state.stopping {
+            state = self.safepoint.changed.wait(state).unwrap_or_else(PoisonError::into_inner);
+        }
+        state.idle -= 1;
+    }
+}
+
+impl Drop for Mutator<'_> {
+    fn drop(&mut self) {
+        //One less thread to wait for, which may be the one a pending stop is waiting on
+        self.safepoint.lock().attached -= 1;
+        self.safepoint.changed.notify_all();
+    }
+}
+
+impl StoppedWorld<'_> {
+    /// Threads that parked for this stop
+    pub fn threads(&self) -> usize {
+        self.arrivals.len()
+    }
+
+    /// How long the world has been stopped for so far (not counting time-to-safepoint)
+    pub fn elapsed(&self) -> Duration {
+        self.stopped_at.elapsed()
+    }
+}
+
+impl Drop for StoppedWorld<'_> {
+    fn drop(&mut self) {
+        let mut state: MutexGuard<State> = self.safepoint.lock();
+        self.safepoint.requested.store(false, Ordering::Release);
+        state.stopping = false;
+        state.requested_at = None;
+        state.parked = 0;               //Cleared here rather than by each thread as it wakes, so the next stop can't count a thread that is already running again
+        state.epoch += 1;
+        self.safepoint.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::{mpsc, Barrier}, thread};
+
+    use super::*;
+
+    #[test]
+    fn stops_the_world_once_every_mutator_has_parked() {
+        let safepoint: Safepoint = Safepoint::new();
+        let done: AtomicBool = AtomicBool::new(false);
+        let attached: Barrier = Barrier::new(3);
+
+        thread::scope(|scope| {
+            let mutators: Vec<_> = (0..2)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mutator: Mutator = safepoint.attach();
+                        attached.wait();
+                        let mut parked: usize = 0;
+                        while !done.load(Ordering::Acquire) {
+                            parked += usize::from(mutator.poll());
+                        }
+                        parked
+                    })
+                })
+                .collect();
+
+            attached.wait();
+            let world: StoppedWorld = safepoint.stop_the_world();
+            assert_eq!((world.threads(), safepoint.attached()), (2, 2));
+            assert!(world.arrivals.iter().all(|&arrival| arrival <= world.time_to_safepoint));
+            drop(world);
+
+            done.store(true, Ordering::Release);
+            assert!(mutators.into_iter().all(|mutator| mutator.join().unwrap() == 1));
+        });
+        assert_eq!(safepoint.attached(), 0);
+    }
+
+    #[test]
+    fn counts_an_idle_mutator_as_parked() {
+        let safepoint: Safepoint = Safepoint::new();
+        let (went_idle, idle) = mpsc::channel::<()>();
+        let (come_back, back) = mpsc::channel::<()>();
+
+        thread::scope(|scope| {
+            let safepoint: &Safepoint = &safepoint;
+            scope.spawn(move || {
+                let mutator: Mutator = safepoint.attach();
+                let waiting: Idle = mutator.idle();
+                went_idle.send(()).unwrap();
+                back.recv().unwrap();
+                drop(waiting);
+                assert!(!mutator.poll());                               //The world restarted before it came back
+            });
+
+            idle.recv().unwrap();
+            let world: StoppedWorld = safepoint.stop_the_world();
+            assert_eq!((world.threads(), safepoint.attached()), (0, 1));
+            come_back.send(()).unwrap();
+        });
+    }
+}
@@ -0,0 +1,237 @@
+//Ready made heap scenarios
+/*
+    Builds reference patterns that demonstrate how the collectors differ, so they don't have to be
+    set up by hand one --alloc_at and --link_ref at a time.
+
+    graph -> A random object graph (tree, DAG or general graph) with a chosen fraction of it unreachable,
+             for realistic inputs to every collector.
+
+    cycle -> Two identical rings of cells that reference each other in a loop. One hangs off a root (the control group),
+    the other is detached from every root. Every cell in the detached ring is still referenced by another,
+    so reference counting never reclaims it, while a tracing collector (mark and sweep, copying) does.
+*/
+
+use rand::{seq::IndexedRandom, Rng};
+use core::fmt;
+
+use crate::{assign_reference, error::{ErrorKind, GcError, Operation}, free_alloc, roots::GLOBALS, scan_roots, Heap};
+use crate::prelude::*;
+
+/// Cells created by a scenario
+#[derive(Clone, Debug, Default)]
+pub struct Built {
+    pub reachable: Vec<usize>,      //Cells that can be reached from a root, and should survive a collection
+    pub garbage: Vec<usize>,        //Cells that can't, and should be reclaimed (by a collector that is able to)
+    pub edges: usize,               //References created (including those from the roots)
+}
+
+/// Shape of a generated object graph, see `graph()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Shape {
+    Tree,               //Every cell has exactly one referrer -> nothing is shared, and there are no cycles
+    Dag,                //Cells can be shared by several referrers, but there are no cycles
+    #[default]
+    Graph,              //Anything goes, including cycles
+}
+
+impl Shape {
+    /// Names accepted by `from_name()`, for help and error messages
+    pub const NAMES: [&'static str; 3] = ["tree", "dag", "graph"];
+
+    /// Parses a shape from its name, i.e. `"dag"` -> `Shape::Dag`
+    pub fn from_name(name: &str) -> Option<Shape> {
+        match name {
+            "tree" => Some(Shape::Tree),
+            "dag" => Some(Shape::Dag),
+            "graph" => Some(Shape::Graph),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Shape {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Shape::Tree => write!(f, "tree"),
+            Shape::Dag => write!(f, "dag"),
+            Shape::Graph => write!(f, "graph"),
+        }
+    }
+}
+
+/// Generates a random object graph of `nodes` cells, with `garbage_ratio` (0.0 - 1.0) of them unreachable from the roots.
+/// #### How it is built
+/// - The reachable cells form a random tree hanging off the roots (a root is created if the heap has none),
+///   and the garbage cells a separate tree that nothing references
+/// - Extra references are then added at random within each group until there are `edges` in total
+///   (a `Shape::Tree` gets none, and there are never fewer than the trees need). Reachable cells never reference garbage, so the ratio holds
+///
+/// Returns `NoFreeMemory` error (without changing the heap) if there aren't enough free cells
+pub fn graph(heap: &mut Heap, nodes: usize, edges: usize, garbage_ratio: f64, shape: Shape) -> Result<Built, GcError> {
+    let roots: Vec<usize> = scan_roots(&heap.cells);
+    let needed: usize = nodes + usize::from(roots.is_empty());
+    if heap.room() < needed {
+        return Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None));
+    }
+
+    let roots: Vec<usize> = match roots.is_empty() {
+        true => vec![create_root(heap)?],
+        false => roots,
+    };
+
+    let cells: Vec<usize> = (0..nodes).map(|_| allocate(heap)).collect::<Result<Vec<usize>, GcError>>()?;
+    let garbage_count: usize = (nodes as f64 * garbage_ratio.clamp(0.0, 1.0) + 0.5) as usize;      //Rounded (core has no f64::round)
+    let (reachable, garbage) = cells.split_at(nodes - garbage_count);
+    let mut created: usize = 0;
+
+    //Spanning trees first -> each cell is referenced by a random cell before it (or a root, if it is reachable)
+    for (k, &cell) in reachable.iter().enumerate() {
+        let parent: usize = match heap.rng.random_range(0..roots.len() + k) {
+            pick if pick < roots.len() => roots[pick],
+            pick => reachable[pick - roots.len()],
+        };
+        assign_reference(heap, parent, cell);
+        created += 1;
+    }
+    for k in 1..garbage.len() {
+        let parent: usize = garbage[heap.rng.random_range(0..k)];
+        assign_reference(heap, parent, garbage[k]);
+        created += 1;
+    }
+
+    //Then extra references until there are enough, giving up after a while if the shape can't fit any more
+    let mut attempts: usize = 0;
+    while shape != Shape::Tree && created < edges && attempts < edges * 20 {
+        attempts += 1;
+
+        //Pick a group, more likely the bigger one, then two cells in it
+        let group: &[usize] = match heap.rng.random_range(0..nodes) < reachable.len() {
+            true => reachable,
+            false => garbage,
+        };
+        let (mut a, mut b) = match (group.choose(&mut heap.rng), group.choose(&mut heap.rng)) {
+            (Some(&a), Some(&b)) if a != b => (a, b),
+            _ => continue,
+        };
+
+        //A DAG only references cells created later, so no reference can lead back round
+        if shape == Shape::Dag && a > b {
+            core::mem::swap(&mut a, &mut b);
+        }
+        if heap.cells[a].will_ref.contains(&b) {
+            continue;
+        }
+
+        assign_reference(heap, a, b);
+        created += 1;
+    }
+
+    Ok(Built {
+        reachable: reachable.to_vec(),
+        garbage: garbage.to_vec(),
+        edges: created,
+    })
+}
+
+/// Builds an `n` cell reference cycle detached from the roots, plus a reachable `n` cell cycle as a control group.
+/// The control group hangs off the first root, and a root is created if the heap has none.
+/// Returns `NoFreeMemory` error (without changing the heap) if there aren't enough free cells for both
+pub fn cycle(heap: &mut Heap, n: usize) -> Result<Built, GcError> {
+    let roots: Vec<usize> = scan_roots(&heap.cells);
+    let needed: usize = 2 * n + usize::from(roots.is_empty());
+    if n == 0 || heap.room() < needed {
+        return Err(GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None));
+    }
+
+    let root: usize = match roots.first() {
+        Some(&root) => root,
+        None => create_root(heap)?,
+    };
+
+    let reachable: Vec<usize> = ring(heap, n)?;
+    assign_reference(heap, root, reachable[0]);         //The only thing keeping the control group alive
+    let garbage: Vec<usize> = ring(heap, n)?;
+
+    Ok(Built { reachable, garbage, edges: 2 * n + 1 })
+}
+
+//Allocates n cells, each referencing the next, with the last referencing the first
+fn ring(heap: &mut Heap, n: usize) -> Result<Vec<usize>, GcError> {
+    let cells: Vec<usize> = (0..n).map(|_| allocate(heap)).collect::<Result<Vec<usize>, GcError>>()?;
+
+    for (i, &cell) in cells.iter().enumerate() {
+        assign_reference(heap, cell, cells[(i + 1) % n]);
+    }
+
+    Ok(cells)
+}
+
+//Allocates a cell of arbitrary data, and makes it a root (for heaps that have none)
+fn create_root(heap: &mut Heap) -> Result<usize, GcError> {
+    let root: usize = allocate(heap)?;
+    let _ = heap.add_root(GLOBALS, root);               //Can't fail, the cell was just allocated
+    Ok(root)
+}
+
+//Allocates a cell of arbitrary data, in the first free position
+fn allocate(heap: &mut Heap) -> Result<usize, GcError> {
+    let data: i32 = heap.rng.random_range(0..50);
+    free_alloc(heap, data, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, Algorithm};
+
+    #[test]
+    fn builds_a_cycle_only_tracing_reclaims() {
+        for (algorithm, reclaimed) in [(Algorithm::MarkSweep, true), (Algorithm::RefCount, false)] {
+            let mut heap: Heap = Heap::with_seed(8, 0);
+            heap.log.echo = false;
+            heap.policy.algorithm = algorithm;
+            let built: Built = cycle(&mut heap, 3).unwrap();
+            assert_eq!((built.reachable, built.garbage.clone(), built.edges), (vec![1, 2, 3], vec![4, 5, 6], 7));
+            assert_eq!(heap.roots(), [0]);
+
+            collect(&mut heap);
+            assert_eq!(built.garbage.iter().all(|&i| heap.cells[i].freed), reclaimed, "{}", algorithm);
+            assert!((1..4).all(|i| !heap.cells[i].freed));
+        }
+    }
+
+    #[test]
+    fn leaves_the_heap_alone_when_a_cycle_does_not_fit() {
+        let mut heap: Heap = Heap::with_seed(6, 0);
+        heap.log.echo = false;
+        assert_eq!(cycle(&mut heap, 3).unwrap_err().kind, ErrorKind::NoFreeMemory);        //6 cells, and a root
+        assert_eq!(cycle(&mut heap, 0).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        assert_eq!(heap.live(), 0);
+    }
+
+    #[test]
+    fn generates_a_graph_of_the_shape_and_garbage_asked_for() {
+        let mut heap: Heap = Heap::with_seed(40, 3);
+        heap.log.echo = false;
+        let built: Built = graph(&mut heap, 20, 30, 0.25, Shape::Dag).unwrap();
+        assert_eq!((built.reachable.len(), built.garbage.len(), built.edges), (15, 5, 30));
+
+        let reached: Vec<bool> = heap.reachable();
+        assert!(built.reachable.iter().all(|&i| reached[i]) && built.garbage.iter().all(|&i| !reached[i]));
+        assert!((0..40).all(|from| heap.cells[from].will_ref.iter().all(|&to| from < to)));      //Nothing leads back round
+
+        //A tree gets no extra references, every cell has exactly one referrer
+        let built: Built = graph(&mut heap, 10, 30, 0.0, Shape::Tree).unwrap();
+        assert_eq!(built.edges, 10);
+        assert!(built.reachable.iter().all(|&i| heap.cells[i].by_ref.len() == 1));
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn leaves_the_heap_alone_when_a_graph_does_not_fit() {
+        let mut heap: Heap = Heap::with_seed(10, 0);
+        heap.log.echo = false;
+        assert_eq!(graph(&mut heap, 10, 0, 0.5, Shape::Graph).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        assert_eq!(heap.live(), 0);
+    }
+}
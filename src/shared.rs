@@ -0,0 +1,90 @@
+//Sharing a heap between threads
+/*
+    Every heap function takes `&mut Heap`, so only one thread can use a heap at a time.
+    SharedHeap lets several mutator threads (and a collector) work on the same heap at once:
+
+    - Each operation locks the heap for only as long as it takes, and takes handles (see handle.rs) checked while holding the lock,
+      so an object another thread freed in the meantime is reported as stale, rather than acting on whatever replaced it
+    - Collections stop the world first (see safepoint.rs), so no mutator is between operations that belong together
+
+    Mutator threads attach() and poll() at safepoints between operations, as they would in a multi-threaded VM.
+*/
+
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Duration,
+};
+
+use crate::{
+    collect,
+    error::GcError,
+    handle::Handle,
+    safepoint::{Mutator, Safepoint, StoppedWorld},
+    Heap,
+};
+
+/// A heap that any number of threads can use at once, for as long as it is borrowed (i.e. inside `std::thread::scope`)
+pub struct SharedHeap<'a> {
+    heap: Mutex<&'a mut Heap>,
+    safepoint: Safepoint,
+}
+
+impl<'a> SharedHeap<'a> {
+    pub fn new(heap: &'a mut Heap) -> SharedHeap<'a> {
+        SharedHeap { heap: Mutex::new(heap), safepoint: Safepoint::new() }
+    }
+
+    /// Joins the calling thread to the mutators that collections wait for. It should `poll()` regularly until the guard is dropped
+    pub fn attach(&self) -> Mutator<'_> {
+        self.safepoint.attach()
+    }
+
+    /// Runs `f` with the heap locked, for anything the other operations don't cover.
+    /// Everything `f` does happens at once as far as other threads can tell
+    pub fn with<R>(&self, f: impl FnOnce(&mut Heap) -> R) -> R {
+        f(&mut self.lock())
+    }
+
+    /// Allocates data in the first free cell, see `Heap::alloc()`
+    pub fn alloc(&self, data: i32) -> Result<Handle, GcError> {
+        self.lock().alloc(data)
+    }
+
+    /// Makes `from` reference `to`, see `Heap::link()`.
+    /// Returns `Stale` error if another thread (or a collection) freed either of them since the handles were made
+    pub fn link(&self, from: Handle, to: Handle) -> Result<(), GcError> {
+        self.lock().link(from, to)
+    }
+
+    /// Removes the reference from `from` to `to`, see `Heap::unlink()`.
+    /// Returns `DataIsFree` error if there is no such reference any more (i.e. another thread removed it)
+    pub fn unlink(&self, from: Handle, to: Handle) -> Result<(), GcError> {
+        self.lock().unlink(from, to)
+    }
+
+    /// The data in the object a handle refers to, see `Heap::read()`
+    pub fn read(&self, handle: Handle) -> Result<Option<i32>, GcError> {
+        self.lock().read(handle)
+    }
+
+    /// Stops the world, then runs the heap's collector. Returns how long every mutator took to reach a safepoint.
+    /// The calling thread must not be attached (it would wait for itself forever)
+    pub fn collect(&self) -> Duration {
+        self.stopped(|heap, time_to_safepoint| {
+            collect(heap);
+            time_to_safepoint
+        })
+    }
+
+    /// Stops the world, then runs `f` with the heap locked and how long every mutator took to reach a safepoint ->
+    /// for collections that do more than `collect()` (see periodic.rs). The calling thread must not be attached
+    pub fn stopped<R>(&self, f: impl FnOnce(&mut Heap, Duration) -> R) -> R {
+        let world: StoppedWorld = self.safepoint.stop_the_world();
+        f(&mut self.lock(), world.time_to_safepoint)
+    }
+
+    //A thread that panicked part way through an operation doesn't stop the others from using the heap
+    fn lock(&self) -> MutexGuard<'_, &'a mut Heap> {
+        self.heap.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,123 @@
+//Shrinking the heap
+/*
+    The heap can grow when it runs out of memory (see oom.rs), but a real runtime also gives memory back once it no
+    longer needs it -> the JVM uncommits heap when more than -XX:MaxHeapFreeRatio of it is free after a collection,
+    and Go's scavenger returns unused spans to the OS. Only free cells at the end of the pool can go, as cutting
+    anywhere else would renumber the cells after the cut behind the program's back:
+
+        --shrink -> releases as many of the free cells at the end as it is told to (`--shrink <cells>`), or as
+                    the max-free policy would after a collection (`--shrink`, which does nothing while it is off)
+        max-free -> after every collection, if more than this percentage of the heap is free, the free cells at
+                    the end are released until it isn't (`--set-policy max-free <percent|off>`, off by default).
+                    Nothing is moved, so after a copying collection the whole free tail can go, but after mark and
+                    sweep only what happens to be free at the end. `--relocate finish` first moves every cell in
+                    use below the free ones
+
+    Releasing a cell takes its bytes off the heap's capacity, reported as memory returned to the OS. The free cells
+    at the end of the pool can't be released if they are part of a named region (see named_regions.rs), a stub a
+    relocation in progress has left behind, or survivor spaces (see generational.rs), which always sit at the end.
+    At least one cell is always kept.
+*/
+
+use crate::{log::Event, sizes::CELL_BYTES, Heap};
+use crate::prelude::*;
+
+impl Heap {
+    /// How many free cells at the end of the pool could be released (see the top of this module)
+    pub fn free_tail(&self) -> usize {
+        if self.survivor_spaces().is_some() {
+            return 0;
+        }
+        let floor: usize = self.named_regions.iter().map(|region| region.cells.end).max().unwrap_or(0).max(1);
+        (floor..self.cells.len()).rev().take_while(|&i| self.cells[i].freed && self.cells[i].forward.is_none()).count()
+    }
+
+    /// Releases up to `cells` free cells from the end of the pool (as many as `free_tail()` allows), dropping whatever
+    /// the heap still held about them. Returns how many were released
+    pub fn shrink(&mut self, cells: usize) -> usize {
+        let released: usize = cells.min(self.free_tail());
+        if released == 0 {
+            return 0;
+        }
+        let from: usize = self.cells.len();
+        let to: usize = from - released;
+
+        //Nothing should be left pointing past the new end, even at a free cell (i.e. an intern entry gone stale)
+        let forwarding: Vec<Option<usize>> = (0..from).map(|i| (i < to).then_some(i)).collect();
+        self.relocate_locals(&forwarding);
+        self.relocate_roots(&forwarding);
+        self.relocate_candidates(&forwarding);
+        self.relocate_finalizers(&forwarding);
+        self.relocate_weak_map(&forwarding);
+        self.relocate_interned(&forwarding);
+        self.remembered.retain(|&index| index < to);
+        self.rc_updates.truncate(to);
+        self.region_marking = None;                     //The regions are marked again by the next garbage first collection
+
+        self.cells.truncate(to);
+        self.log.emit(Event::Shrunk { from, to, bytes: released * CELL_BYTES });
+        self.publish();
+        released
+    }
+
+    /// Releases free cells from the end until no more than `Policy::max_free` percent of the heap is free (as happens after
+    /// every collection), returning how many were released. Nothing is released while the policy is off
+    pub fn shrink_to_policy(&mut self) -> usize {
+        let Some(max_free) = self.policy.max_free else {
+            return 0;
+        };
+        let (size, free) = (self.cells.len(), self.cells.iter().filter(|cell| cell.freed).count());
+        let released: usize = (0..=self.free_tail()).find(|&released| (free - released) * 100 <= max_free * (size - released)).unwrap_or(self.free_tail());
+        self.shrink(released)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{collect, configure_roots, roots, sizes, spec_alloc, Heap};
+
+    #[test]
+    fn releases_the_free_tail_of_the_heap() {
+        let mut heap: Heap = Heap::with_seed(10, 0);
+        heap.log.echo = false;
+        for data in 0..4 {
+            spec_alloc(&mut heap, data, None, data as usize).unwrap();
+        }
+        configure_roots(&mut heap, roots::GLOBALS, &[0]);
+        assert_eq!(heap.free_tail(), 6);
+        heap.define_region("kept", 7..8).unwrap();
+        assert_eq!(heap.free_tail(), 2);                                //Named regions aren't cut into
+        heap.drop_region("kept");
+
+        assert_eq!(heap.shrink(3), 3);
+        assert_eq!((heap.cells.len(), heap.bytes_capacity()), (7, 7 * sizes::CELL_BYTES));
+
+        //Cells 1-3 are garbage, so 6 of 7 cells are free -> released until no more than half the heap is
+        heap.policy.max_free = Some(50);
+        collect(&mut heap);
+        assert_eq!(heap.cells.len(), 2);
+        assert_eq!(heap.free_tail(), 1);
+    }
+
+    #[test]
+    fn leaves_a_fresh_heap_alone_while_the_policy_is_off() {
+        let mut heap: Heap = Heap::with_seed(20, 0);
+        heap.log.echo = false;
+        assert_eq!((heap.shrink_to_policy(), heap.cells.len()), (0, 20));
+
+        //Every cell is free, but the first is always kept
+        heap.policy.max_free = Some(50);
+        assert_eq!((heap.shrink_to_policy(), heap.cells.len()), (19, 1));
+    }
+
+    #[test]
+    fn only_releases_what_is_free_at_the_end() {
+        let mut heap: Heap = Heap::with_seed(8, 0);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 1, None, 4).unwrap();
+
+        assert_eq!((heap.shrink(6), heap.cells.len()), (3, 5));
+        assert_eq!((heap.shrink(1), heap.cells.len()), (0, 5));
+        assert!(!heap.cells[4].freed);
+    }
+}
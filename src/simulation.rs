@@ -0,0 +1,162 @@
+//Deterministic simulations
+/*
+    A Simulation is a heap's starting point (size, seed and collector) and a script of mutator operations
+    (see mutator.rs). Running it builds a fresh heap from the seed and applies every operation in turn, recording
+    what each one returned and the events it caused, so the same simulation always runs the same way:
+
+        let mut simulation = Simulation::new(8, 1);
+        simulation.ops = vec![Op::Alloc(5), Op::Root(0), Op::Alloc(6), Op::Collect];
+        let run = simulation.run();
+        assert!(run.steps[3].events.iter().any(|event| matches!(event, Event::Swept { .. })));
+
+    This is the one engine for driving a heap without a prompt -> tests check the heap between steps with `run_with()`,
+    benchmarks time `run()`, and a script written out with `to_script()` replays exactly with `from_script()`.
+    Pause times are measured as the simulation runs, so the collection events they appear in are the only part
+    of a trace that differs from one run to the next.
+*/
+
+use core::fmt;
+
+use crate::{error::GcError, log::Event, mutator::Op, Algorithm, Heap};
+use crate::prelude::*;
+
+/// A heap's starting point and the operations to run against it, see the top of this module
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Simulation {
+    pub cells: usize,               //Size of the heap
+    pub seed: u64,                  //What the heap's rng is seeded with
+    pub algorithm: Algorithm,       //The collector the heap uses
+    pub ops: Vec<Op>,               //In the order they are applied
+}
+
+/// What one operation of a simulation did
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    pub op: Op,
+    pub result: Result<(), GcError>,
+    pub events: Vec<Event>,         //Everything that happened on the heap during the operation, in order
+    pub live: usize,                //Cells in use afterwards
+}
+
+/// A finished simulation -> the outcome of every operation, and the heap they left behind
+pub struct Simulated {
+    pub steps: Vec<Outcome>,
+    pub heap: Heap,
+}
+
+/// A line of a script that `Simulation::from_script()` couldn't read
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub line: usize,                //Counted from 1
+    pub text: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Line {} isn't a simulation step: '{}'", self.line, self.text)
+    }
+}
+
+impl Simulation {
+    /// A simulation of a heap of `cells` cells seeded with `seed`, collected by mark-sweep, with no operations yet
+    pub fn new(cells: usize, seed: u64) -> Simulation {
+        Simulation { cells, seed, algorithm: Algorithm::default(), ops: Vec::new() }
+    }
+
+    /// Runs every operation against a fresh heap, see `run_with()`
+    pub fn run(&self) -> Simulated {
+        self.run_with(|_, _| {})
+    }
+
+    /// Runs every operation against a fresh heap, handing `inspect` the heap and the outcome after each one.
+    /// Nothing is printed (events are only recorded in the outcomes)
+    pub fn run_with(&self, mut inspect: impl FnMut(&Heap, &Outcome)) -> Simulated {
+        let mut heap: Heap = Heap::with_seed(self.cells, self.seed);
+        heap.log.echo = false;
+        heap.policy.algorithm = self.algorithm;
+
+        let mut steps: Vec<Outcome> = Vec::with_capacity(self.ops.len());
+        for &op in &self.ops {
+            let before: usize = heap.log.sequence();
+            let result: Result<(), GcError> = heap.step(op);
+            let outcome = Outcome { op, result, events: heap.log.since(before).cloned().collect(), live: heap.live() };
+            inspect(&heap, &outcome);
+            steps.push(outcome);
+        }
+        Simulated { steps, heap }
+    }
+
+    /// The simulation as text -> a header line (`heap <cells> seed <seed> <algorithm>`), then one operation per line
+    pub fn to_script(&self) -> String {
+        let mut script: String = format!("heap {} seed {} {}\n", self.cells, self.seed, self.algorithm);
+        for op in &self.ops {
+            script.push_str(&format!("{}\n", op));
+        }
+        script
+    }
+
+    /// Reads a simulation written by `to_script()`. Blank lines and lines starting with `#` are skipped.
+    /// Returns the first line that isn't the header or an operation as a `ScriptError`
+    pub fn from_script(script: &str) -> Result<Simulation, ScriptError> {
+        let mut lines = script
+            .lines()
+            .enumerate()
+            .map(|(i, text)| (i + 1, text.trim()))
+            .filter(|(_, text)| !text.is_empty() && !text.starts_with('#'));
+        let invalid = |line: usize, text: &str| ScriptError { line, text: text.to_string() };
+
+        let (line, header) = lines.next().ok_or_else(|| invalid(1, ""))?;
+        let mut simulation: Simulation = match header.split_whitespace().collect::<Vec<&str>>()[..] {
+            ["heap", cells, "seed", seed, algorithm] => Simulation {
+                cells: cells.parse().map_err(|_| invalid(line, header))?,
+                seed: seed.parse().map_err(|_| invalid(line, header))?,
+                algorithm: Algorithm::from_name(algorithm).ok_or_else(|| invalid(line, header))?,
+                ops: Vec::new(),
+            },
+            _ => return Err(invalid(line, header)),
+        };
+        for (line, text) in lines {
+            simulation.ops.push(Op::parse(text).ok_or_else(|| invalid(line, text))?);
+        }
+        Ok(simulation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn replays_simulations_the_same_way() {
+        let mut simulation: Simulation = Simulation::new(4, 7);
+        simulation.algorithm = Algorithm::Copying;
+        simulation.ops = vec![Op::Alloc(1), Op::Root(0), Op::Alloc(2), Op::Alloc(3), Op::Link(0, 2), Op::Collect, Op::Link(0, 1), Op::Alloc(4), Op::Alloc(5), Op::Alloc(6)];
+        let replayed: Simulation = Simulation::from_script(&simulation.to_script()).unwrap();
+        assert_eq!(replayed, simulation);
+
+        let (first, second) = (simulation.run(), replayed.run());
+        let kinds = |outcome: &Outcome| outcome.events.iter().map(Event::kind).collect::<Vec<&str>>();
+        for (a, b) in first.steps.iter().zip(&second.steps) {
+            assert_eq!((a.result, a.live, kinds(a)), (b.result, b.live, kinds(b)));
+        }
+        assert!(first.heap.cells == second.heap.cells);
+        assert!(kinds(&first.steps[5]).contains(&"moved"));
+        assert_eq!(first.steps[6].result.unwrap_err().kind, ErrorKind::Occupied);   //Cell 0 already references the other survivor
+        assert_eq!(first.steps[9].live, 3);                                  //The heap was full, so the last allocation collected 4 and 5 first
+        assert_eq!(Simulation::from_script("heap 4 seed 7 rc\nlink 1").unwrap_err().line, 2);
+    }
+
+    #[test]
+    fn points_at_the_first_line_it_cannot_read() {
+        let error = |script: &str| Simulation::from_script(script).unwrap_err();
+        assert_eq!(error(""), ScriptError { line: 1, text: String::new() });
+        assert_eq!(error("# only a comment\n\nheap 4 seed 7 magic").line, 3);
+        assert_eq!(error("heap four seed 7 rc").text, "heap four seed 7 rc");
+        assert_eq!(error("heap 4 seed 7 rc\nalloc 1\n\nfly 2").to_string(), "Line 4 isn't a simulation step: 'fly 2'");
+
+        //Comments and blank lines can go anywhere
+        let simulation: Simulation = Simulation::from_script("# a heap\nheap 4 seed 7 rc\n\n# and one step\nalloc 1\n").unwrap();
+        assert_eq!((simulation.cells, simulation.algorithm, simulation.ops), (4, Algorithm::RefCount, vec![Op::Alloc(1)]));
+    }
+}
@@ -0,0 +1,179 @@
+//Allocation sites
+/*
+    Every allocation is tagged with the site it came from -> the heap's current site when it was made (the REPL sets it
+    to the command being run, or to a label given with --site), or "unknown" if none was set. The tag is kept
+    on the cell, so it moves with the data when a copying collection relocates it, and goes when the cell is freed.
+
+    For each site the heap counts what a real allocation profiler would:
+
+        allocations -> how many objects the site has made
+        survived -> how many of them lived through at least one collection (the rest died young)
+        garbage -> how many became garbage -> reclaimed by a collector, or unreachable now and waiting to be
+
+    Only allocations, survivors and cells freed by hand are counted as they happen. Everything else is worked out
+    from the cells when asked for, as an object a site made is either still in use, freed by hand or reclaimed.
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Cell, Heap};
+use crate::prelude::*;
+
+/// What an allocation is tagged with when the heap has no current site
+pub const UNKNOWN: &str = "unknown";
+
+/// The counts kept for one allocation site, as they happen
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Site {
+    pub name: String,
+    pub allocations: usize,
+    pub survived: usize,            //Allocations that lived through at least one collection
+    pub freed: usize,               //...and that were freed by hand rather than collected
+}
+
+/// How one site's allocations have fared, from `Heap::allocation_sites()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SiteProfile {
+    pub site: String,
+    pub allocations: usize,
+    pub live: usize,                //Still in use
+    pub survived: usize,
+    pub reclaimed: usize,           //Freed by a collector
+    pub unreachable: usize,         //Still in use, but garbage the next collection will reclaim
+}
+
+impl SiteProfile {
+    /// Every object from the site that became garbage, reclaimed or not
+    pub fn garbage(&self) -> usize {
+        self.reclaimed + self.unreachable
+    }
+
+    /// The share of the site's allocations that survived a collection (0.0 if it has made none)
+    pub fn survival(&self) -> f64 {
+        match self.allocations {
+            0 => 0.0,
+            allocations => self.survived as f64 / allocations as f64,
+        }
+    }
+}
+
+impl Heap {
+    /// Tags every allocation from now on with `site` (or `UNKNOWN` if None)
+    pub fn set_site(&mut self, site: Option<&str>) {
+        self.site = site.map(|site| site.to_string());
+    }
+
+    /// The site allocations are currently tagged with, if one was set
+    pub fn site(&self) -> Option<&str> {
+        self.site.as_deref()
+    }
+
+    /// How every site's allocations have fared, most allocations first
+    pub fn allocation_sites(&self) -> Vec<SiteProfile> {
+        let reachable: Vec<bool> = self.reachable();
+        let mut profiles: Vec<SiteProfile> = self
+            .sites
+            .iter()
+            .map(|site| {
+                let made_here = |cell: &&Cell| !cell.freed && cell.site.as_deref() == Some(site.name.as_str());
+                let live: usize = self.cells.iter().filter(made_here).count();
+                let unreachable: usize = self.cells.iter().zip(&reachable).filter(|(cell, reached)| made_here(cell) && !**reached).count();
+                SiteProfile {
+                    site: site.name.clone(),
+                    allocations: site.allocations,
+                    live,
+                    survived: site.survived,
+                    reclaimed: site.allocations.saturating_sub(live + site.freed),
+                    unreachable,
+                }
+            })
+            .collect();
+
+        profiles.sort_by(|a, b| b.allocations.cmp(&a.allocations).then_with(|| a.site.cmp(&b.site)));
+        profiles
+    }
+
+    //Tags the cell just allocated at `index` with the current site, and counts it
+    pub(crate) fn tag_allocation(&mut self, index: usize) {
+        let name: String = self.site.clone().unwrap_or_else(|| UNKNOWN.to_string());
+        self.tally(&name).allocations += 1;
+        self.cells[index].site = Some(name);
+    }
+
+    //Counts every cell that has just lived through its first collection
+    pub(crate) fn tally_survivors(&mut self) {
+        let survivors: Vec<String> = self
+            .cells
+            .iter()
+            .filter(|cell| !cell.freed && cell.age == 1)
+            .filter_map(|cell| cell.site.clone())
+            .collect();
+        for name in survivors {
+            self.tally(&name).survived += 1;
+        }
+    }
+
+    //Counts the cell at `index` as freed by hand, before it is
+    pub(crate) fn tally_free(&mut self, index: usize) {
+        if let Some(name) = self.cells[index].site.clone() {
+            self.tally(&name).freed += 1;
+        }
+    }
+
+    //The counts for a site, starting them if it hasn't allocated before
+    fn tally(&mut self, name: &str) -> &mut Site {
+        let position: usize = match self.sites.iter().position(|site| site.name == name) {
+            Some(position) => position,
+            None => {
+                self.sites.push(Site { name: name.to_string(), ..Site::default() });
+                self.sites.len() - 1
+            }
+        };
+        &mut self.sites[position]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, configure_roots, free_alloc, free_cell, roots};
+
+    #[test]
+    fn profiles_allocation_sites() {
+        let mut heap: Heap = Heap::with_seed(8, 0);
+        heap.log.echo = false;
+        heap.set_site(Some("kept"));
+        let kept: usize = free_alloc(&mut heap, 1, None).unwrap();
+        configure_roots(&mut heap, roots::GLOBALS, &[kept]);
+        heap.set_site(Some("temp"));
+        for data in 0..3 {
+            free_alloc(&mut heap, data, None).unwrap();
+        }
+        collect(&mut heap);
+        let freed: usize = free_alloc(&mut heap, 9, None).unwrap();
+        free_cell(&mut heap, freed, false).unwrap();
+        free_alloc(&mut heap, 10, None).unwrap();
+
+        let sites: Vec<SiteProfile> = heap.allocation_sites();
+        assert_eq!((sites[0].site.as_str(), sites[0].allocations, sites[0].live, sites[0].reclaimed, sites[0].unreachable), ("temp", 5, 1, 3, 1));
+        assert_eq!((sites[1].site.as_str(), sites[1].survived, sites[1].garbage()), ("kept", 1, 0));
+        assert_eq!(heap.cells[kept].site.as_deref(), Some("kept"));
+    }
+
+    #[test]
+    fn tags_allocations_without_a_site_as_unknown() {
+        let mut heap: Heap = Heap::with_seed(4, 0);
+        heap.log.echo = false;
+        assert!(heap.allocation_sites().is_empty());
+        heap.set_site(Some("temp"));
+        heap.set_site(None);
+        let index: usize = free_alloc(&mut heap, 1, None).unwrap();
+        free_cell(&mut heap, index, false).unwrap();
+
+        //Freed by hand, so neither reclaimed by a collector nor garbage
+        let sites: Vec<SiteProfile> = heap.allocation_sites();
+        assert_eq!(sites.len(), 1);
+        assert_eq!((sites[0].site.as_str(), sites[0].allocations, sites[0].live, sites[0].garbage()), (UNKNOWN, 1, 0, 0));
+        assert_eq!((heap.site(), sites[0].survival()), (None, 0.0));
+    }
+}
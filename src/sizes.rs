@@ -0,0 +1,67 @@
+//Object sizes
+/*
+    Counting cells treats a cell holding a single i32 the same as a list of twenty elements. Measuring in bytes
+    instead, every object is as big as what it holds -> a word for its data (if it has any), and a word for each
+    reference it makes (or field it has, set or not). A string is the word holding its length, plus a word
+    per reference to each of its cells of bytes, plus those cells themselves.
+
+    The heap's capacity is a fixed slot of CELL_BYTES per cell, which is room for a word of data and 3 references.
+    Anything bigger is counted at its full size all the same, as if it had spilled out of its slot.
+*/
+
+use crate::{Cell, Heap};
+
+/// Size of a word in bytes -> an i32 of data, or a reference (a cell index)
+pub const WORD: usize = 4;
+
+/// Size of the slot each cell has on the heap, in bytes (a word of data and 3 references)
+pub const CELL_BYTES: usize = 4 * WORD;
+
+impl Cell {
+    /// How many bytes the object in this cell takes up (nothing, if the cell is free)
+    pub fn size(&self) -> usize {
+        if self.freed {
+            return 0;
+        }
+
+        //Fields that aren't set yet still have room for their reference
+        let unset: usize = self.record.as_ref().map_or(0, |record| record.fields.iter().filter(|field| field.to.is_none()).count());
+        WORD * (usize::from(self.data.is_some()) + self.will_ref.len() + unset)
+    }
+}
+
+impl Heap {
+    /// Total bytes taken up by every object on the heap
+    pub fn bytes_used(&self) -> usize {
+        self.cells.iter().map(Cell::size).sum()
+    }
+
+    /// Total bytes the heap has room for, see CELL_BYTES
+    pub fn bytes_capacity(&self) -> usize {
+        self.cells.len() * CELL_BYTES
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph};
+
+    #[test]
+    fn measures_each_object_by_what_it_holds() {
+        let mut heap: Heap = graph(3, &[(0, 1), (0, 2)]);
+        heap.log.echo = false;
+        assert_eq!((heap.cells[0].size(), heap.cells[1].size(), heap.cells[5].size()), (3 * WORD, WORD, 0));
+
+        //Unset fields still take up a word each, and a string is its head plus its cells of bytes
+        let record: usize = heap.record_new("pair", &[("a", Some(1)), ("b", None)], None).unwrap();
+        let string: usize = heap.alloc_str("abcde", None).unwrap();
+        assert_eq!(heap.cells[record].size(), 2 * WORD);
+        assert_eq!(heap.cells[string].size() + heap.cells[string].will_ref.iter().map(|&i| heap.cells[i].size()).sum::<usize>(), 5 * WORD);
+        assert_eq!((heap.bytes_used(), heap.bytes_capacity()), (12 * WORD, 7 * CELL_BYTES));
+
+        //What a collection reclaims is counted in bytes too
+        collect(&mut heap);
+        assert_eq!((heap.history[0].bytes_swept, heap.bytes_used()), (7 * WORD, 5 * WORD));
+    }
+}
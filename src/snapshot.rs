@@ -0,0 +1,328 @@
+//Saving and restoring the virtual heap
+/*
+    Lets an interesting heap state be prepared ahead of time (i.e. before a class), saved to a file,
+    and loaded back into a later session exactly as it was left.
+
+    A Checkpoint is the same snapshot kept in memory instead -> `Heap::checkpoint()` takes one and `Heap::restore()`
+    puts the heap back to it, which is how the REPL moves back and forth through a session (see --history).
+*/
+
+use core::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "std")]
+use std::{fs, io};
+
+use crate::{frames::Frame, finalize::Finalizer, generational::CollectionKind, hybrid::Hybrid, intern::Interner, named_regions::NamedRegion, refcount::UpdateBuffer, relocation::Relocation, roots::{RootSet, GLOBALS}, scan_roots, sites::Site, stats::GcCycle, weakmap::WeakMap, Cell, Heap, Instant, Policy};
+use crate::prelude::*;
+#[cfg(feature = "std")]
+use crate::named_regions::{check_region, NamedRegionError};
+
+/// Bumped whenever the snapshot format changes, so old files are rejected rather than misread
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Everything needed to bring a heap back exactly as it was saved.
+/// (The event log is not included, verbosity and log files belong to the session, not the heap)
+#[derive(Clone, Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    cells: Vec<Cell>,               //The memory pool, including roots, edges and marks
+    policy: Policy,
+    history: Vec<GcCycle>,
+    allocations: usize,
+    high_water: usize,
+    #[serde(default)]
+    barrier_writes: usize,
+    #[serde(default)]
+    read_barrier_hits: usize,
+    #[serde(default)]
+    mark_epoch: usize,
+    #[serde(default)]
+    seed: Option<u64>,              //The heap's seed, and its rng part way through that seed...
+    #[serde(default)]
+    #[cfg_attr(not(feature = "std"), serde(skip))]
+    rng: Option<ChaCha8Rng>,        //...so a loaded heap carries on with exactly the same random values (older snapshots have neither)
+    #[serde(default)]
+    frames: Vec<Frame>,             //The simulated call stack, so its locals are unrooted when popped after loading
+    #[serde(default)]
+    root_sets: Option<Vec<RootSet>>, //Older snapshots only flagged roots on the cells, see load()
+    #[serde(default)]
+    sites: Vec<Site>,               //What each allocation site has done so far (see sites.rs)
+    #[serde(default)]
+    relocation: Option<Relocation>, //A relocation part way through, whose stubs are among the cells (see relocation.rs)
+    #[serde(default)]
+    rc_updates: UpdateBuffer,       //The buffered variant's updates still to apply, and its totals (see refcount.rs)
+    #[serde(default)]
+    hybrid: Hybrid,                 //The hybrid's candidate cycle roots, and its totals (see hybrid.rs)
+    #[serde(default)]
+    finalizers: Vec<Finalizer>,     //Finalizers that haven't run yet (see finalize.rs)
+    #[serde(default)]
+    weak_map: WeakMap,              //Entries that don't keep their keys alive (see weakmap.rs)
+    #[serde(default)]
+    interner: Interner,             //The intern table, and what it has saved (see intern.rs)
+    #[serde(default)]
+    named_regions: Vec<NamedRegion>, //Ranges of the pool set aside with --region (see named_regions.rs)
+}
+
+/// A copy of the heap at one moment, held in memory, see `Heap::checkpoint()`
+#[derive(Clone)]
+pub struct Checkpoint(Snapshot);
+
+impl Checkpoint {
+    /// The memory pool as it was when the checkpoint was taken
+    pub fn cells(&self) -> &[Cell] {
+        &self.0.cells
+    }
+}
+
+impl Heap {
+    /// Takes a copy of the complete heap (everything `save()` would write), to `restore()` later
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(Snapshot {
+            version: SNAPSHOT_VERSION,
+            cells: self.cells.clone(),
+            policy: self.policy.clone(),
+            history: self.history.clone(),
+            allocations: self.allocations,
+            high_water: self.high_water,
+            barrier_writes: self.barrier_writes,
+            read_barrier_hits: self.read_barrier_hits.load(Ordering::Relaxed),
+            mark_epoch: self.mark_epoch,
+            seed: Some(self.seed),
+            rng: Some(self.rng.clone()),
+            frames: self.frames.clone(),
+            root_sets: Some(self.root_sets.clone()),
+            sites: self.sites.clone(),
+            relocation: self.relocation,
+            rc_updates: self.rc_updates.clone(),
+            hybrid: self.hybrid.clone(),
+            finalizers: self.finalizers.clone(),
+            weak_map: self.weak_map.clone(),
+            interner: self.interner.clone(),
+            named_regions: self.named_regions.clone(),
+        })
+    }
+
+    /// Puts the heap back exactly as it was when `checkpoint` was taken
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        self.apply(checkpoint.0.clone());
+    }
+
+    /// Saves the complete heap to the file at `path` (as JSON)
+    #[cfg(feature = "std")]
+    pub fn save(&self, path: &str) -> io::Result<()> {
+        fs::write(path, serde_json::to_string(&self.checkpoint().0)?)
+    }
+
+    /// Replaces this heap with the one saved in the file at `path`.
+    /// The heap is left untouched if the file can't be read, or isn't a valid snapshot.
+    #[cfg(feature = "std")]
+    pub fn load(&mut self, path: &str) -> io::Result<()> {
+        let snapshot: Snapshot = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(invalid(format!(
+                "snapshot version {} is not supported (expected {})",
+                snapshot.version, SNAPSHOT_VERSION
+            )));
+        }
+
+        //Every edge has to point somewhere inside the pool, otherwise the first mark would panic
+        let size = snapshot.cells.len();
+        if size == 0 {
+            return Err(invalid("snapshot has an empty memory pool".to_string()));
+        }
+        for (i, cell) in snapshot.cells.iter().enumerate() {
+            if let Some(bad) = cell.will_ref.iter().chain(cell.by_ref.iter()).find(|&&edge| edge >= size) {
+                return Err(invalid(format!("cell {} has a reference to cell {}, which is out of bounds", i, bad)));
+            }
+        }
+        if let Some(bad) = snapshot.frames.iter().flat_map(|frame| frame.locals.iter()).find(|&&local| local >= size) {
+            return Err(invalid(format!("a stack frame holds cell {}, which is out of bounds", bad)));
+        }
+        let sets = snapshot.root_sets.iter().flatten();
+        if let Some(bad) = sets.flat_map(|root_set| root_set.cells.iter()).find(|&&root| root >= size) {
+            return Err(invalid(format!("a root set holds cell {}, which is out of bounds", bad)));
+        }
+        if let Some(bad) = snapshot.finalizers.iter().find(|finalizer| finalizer.index >= size) {
+            return Err(invalid(format!("cell {} has a finalizer, but is out of bounds", bad.index)));
+        }
+        if let Some(bad) = snapshot.weak_map.entries().iter().flat_map(|&(key, value)| [key, value]).find(|&cell| cell >= size) {
+            return Err(invalid(format!("the weak map holds cell {}, which is out of bounds", bad)));
+        }
+        for (i, region) in snapshot.named_regions.iter().enumerate() {
+            let why: &str = match check_region(&region.name, &region.cells, size, &snapshot.named_regions[..i]) {
+                Ok(()) => continue,
+                Err(NamedRegionError::OutOfBounds) => "isn't a range of the pool",
+                Err(NamedRegionError::Invalid) => "has a name that could be taken for an index or command",
+                Err(NamedRegionError::Overlaps(_)) => "overlaps another region",
+            };
+            return Err(invalid(format!("region '{}' ({}..{}) {}", region.name, region.cells.start, region.cells.end, why)));
+        }
+
+        self.apply(snapshot);
+        Ok(())
+    }
+
+    //Replaces this heap with a snapshot that is known to be valid
+    fn apply(&mut self, snapshot: Snapshot) {
+        self.cells = snapshot.cells;
+        self.policy = snapshot.policy;
+        self.history = snapshot.history;
+        //Carry on the loaded run's timeline as if it had only just ended, so collections from now on come after it
+        let ended: Duration = self.history.last().map(|cycle| cycle.at + cycle.pause()).unwrap_or_default();
+        self.started = Instant::now().checked_sub(ended).unwrap_or_else(Instant::now);
+        self.allocations = snapshot.allocations;
+        self.high_water = snapshot.high_water;
+        self.barrier_writes = snapshot.barrier_writes;
+        self.read_barrier_hits = AtomicUsize::new(snapshot.read_barrier_hits);
+        self.mark_epoch = snapshot.mark_epoch;
+        self.frames = snapshot.frames;
+        self.sites = snapshot.sites;
+        self.relocation = snapshot.relocation;
+        self.rc_updates = snapshot.rc_updates;
+        self.hybrid = snapshot.hybrid;
+        self.finalizers = snapshot.finalizers;
+        self.weak_map = snapshot.weak_map;
+        self.interner = snapshot.interner;
+        self.named_regions = snapshot.named_regions;
+        self.treadmill = None;                                  //Started again from the cells by its next collection
+        self.region_marking = None;                             //...and the regions are marked again by theirs
+        self.refresh_remembered(CollectionKind::Whole);        //Not saved, it is worked out from the cells
+        self.forget_scoped();
+        //Roots flagged on the cells of an older snapshot (and not held by a frame) were all made with --root
+        self.root_sets = snapshot.root_sets.unwrap_or_else(|| {
+            let locals: Vec<usize> = self.frames.iter().flat_map(|frame| frame.locals.clone()).collect();
+            let globals: Vec<usize> = scan_roots(&self.cells).into_iter().filter(|root| !locals.contains(root)).collect();
+            match globals.is_empty() {
+                true => Vec::new(),
+                false => vec![RootSet { name: GLOBALS.to_string(), cells: globals }],
+            }
+        });
+        if let (Some(seed), Some(rng)) = (snapshot.seed, snapshot.rng) {
+            self.seed = seed;
+            self.rng = rng;
+        }
+    }
+}
+
+//Shorthand for a snapshot that was read fine, but doesn't make sense
+#[cfg(feature = "std")]
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use std::{env, fs, io, path::PathBuf, process};
+
+    use super::Checkpoint;
+    use crate::{collect, tests::graph, unlink_reference, Heap};
+
+    //A file for one test to save to, removed again when it is done with
+    fn scratch(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("gc-rust-{}-{}.json", name, process::id()))
+    }
+
+    //Saves the heap with `from` changed to `to` in the file, and loads it into another heap, which has to be left as it was
+    fn load_tampered(heap: &Heap, name: &str, from: &str, to: &str) -> io::Error {
+        let path: PathBuf = scratch(name);
+        heap.save(path.to_str().unwrap()).unwrap();
+        let saved: String = fs::read_to_string(&path).unwrap();
+        assert!(saved.contains(from));
+        fs::write(&path, saved.replacen(from, to, 1)).unwrap();
+
+        let mut other: Heap = graph(3, &[]);
+        other.log.echo = false;
+        let before: String = other.to_json();
+        let error: io::Error = other.load(path.to_str().unwrap()).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!((error.kind(), other.to_json()), (io::ErrorKind::InvalidData, before));
+        error
+    }
+
+    #[test]
+    fn loads_the_heap_it_saved() {
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2)]);
+        heap.log.echo = false;
+        collect(&mut heap);
+        let path: PathBuf = scratch("saved");
+        heap.save(path.to_str().unwrap()).unwrap();
+
+        let mut loaded: Heap = Heap::with_seed(2, 1);
+        loaded.log.echo = false;
+        loaded.load(path.to_str().unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.to_json(), heap.to_json());
+        assert_eq!((loaded.history.len(), loaded.stats().allocations, loaded.roots()), (1, 4, vec![0]));
+    }
+
+    #[test]
+    fn leaves_the_heap_alone_when_the_snapshot_is_bad() {
+        let mut heap: Heap = graph(2, &[(0, 1)]);
+        heap.log.echo = false;
+        let path: PathBuf = scratch("bad");
+        heap.save(path.to_str().unwrap()).unwrap();
+        let saved: String = fs::read_to_string(&path).unwrap();
+        fs::write(&path, saved.replacen("\"will_ref\":[1]", "\"will_ref\":[9]", 1)).unwrap();
+
+        let mut other: Heap = graph(3, &[]);
+        other.log.echo = false;
+        let before: String = other.to_json();
+        assert_eq!(other.load(path.to_str().unwrap()).unwrap_err().kind(), io::ErrorKind::InvalidData);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(other.load(path.to_str().unwrap()).unwrap_err().kind(), io::ErrorKind::NotFound);
+        assert_eq!(other.to_json(), before);
+    }
+
+    #[test]
+    fn restores_checkpoints() {
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2)]);
+        heap.log.echo = false;
+        let checkpoint: Checkpoint = heap.checkpoint();
+
+        unlink_reference(&mut heap, 0, 1);
+        collect(&mut heap);
+        assert!(heap.cells[1].freed);
+
+        heap.restore(&checkpoint);
+        assert!(heap.cells.as_slice() == checkpoint.cells());
+        assert_eq!(heap.history.len(), 0);
+    }
+
+    #[test]
+    fn refuses_finalizers_on_cells_outside_the_pool() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        heap.register_finalizer(1, false).unwrap();
+        let error: io::Error = load_tampered(&heap, "finalizer", "\"index\":1", "\"index\":999");
+        assert_eq!(error.to_string(), "cell 999 has a finalizer, but is out of bounds");
+    }
+
+    #[test]
+    fn refuses_weak_map_entries_outside_the_pool() {
+        let mut heap: Heap = graph(3, &[]);
+        heap.log.echo = false;
+        heap.weak_map_put(1, 2).unwrap();
+        let error: io::Error = load_tampered(&heap, "weak-key", "[[1,2]]", "[[999,2]]");
+        assert_eq!(error.to_string(), "the weak map holds cell 999, which is out of bounds");
+        load_tampered(&heap, "weak-value", "[[1,2]]", "[[1,999]]");
+    }
+
+    #[test]
+    fn refuses_named_regions_that_could_not_have_been_defined() {
+        let mut heap: Heap = graph(3, &[]);
+        heap.log.echo = false;
+        heap.define_region("cache", 1..3).unwrap();
+        heap.define_region("spare", 4..6).unwrap();
+        let error: io::Error = load_tampered(&heap, "region-bounds", "\"end\":3", "\"end\":999");
+        assert_eq!(error.to_string(), "region 'cache' (1..999) isn't a range of the pool");
+        load_tampered(&heap, "region-empty", "\"end\":3", "\"end\":1");
+        load_tampered(&heap, "region-name", "\"spare\"", "\"-spare\"");
+        let error: io::Error = load_tampered(&heap, "region-overlap", "\"start\":4", "\"start\":2");
+        assert_eq!(error.to_string(), "region 'spare' (2..6) overlaps another region");
+    }
+}
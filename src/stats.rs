@@ -0,0 +1,101 @@
+//Statistics gathered about the virtual heap and the collections that run on it
+
+use serde::{Deserialize, Serialize};
+use core::time::Duration;
+
+use crate::{generational::CollectionKind, Algorithm};
+
+/// Record of a single garbage collection cycle.
+/// Each run of `collect()` pushes one of these onto the heap's history, which is displayed by `--stats`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GcCycle {
+    pub number: usize,              //Which collection this was (1st, 2nd, ...)
+    #[serde(default)]
+    pub at: Duration,               //When it started, measured from when the heap was created (or reset)
+    pub marked: usize,              //Cells marked as live (kept) during the mark phase
+    pub swept: usize,               //Cells holding data that were reclaimed during the sweep phase
+    #[serde(default)]
+    pub bytes_swept: usize,         //...and how many bytes they took up (see sizes.rs)
+    pub root_scan: Duration,        //Wall-clock time spent finding the roots
+    pub mark: Duration,             //Wall-clock time spent in the mark phase
+    pub sweep: Duration,            //Wall-clock time spent in the sweep phase
+    #[serde(default)]
+    pub algorithm: Algorithm,       //Which algorithm ran (the phases mean slightly different things for each, see `collect()`)
+    #[serde(default)]
+    pub kind: CollectionKind,       //Minor, major or full, for a generational collection (see generational.rs)
+    #[serde(default)]
+    pub left_in_place: usize,       //Cells in use a moving collection had no room to copy, so left where they were
+}
+
+impl GcCycle {
+    /// Total time the "program" was paused for this collection
+    pub fn pause(&self) -> Duration {
+        self.root_scan + self.mark + self.sweep
+    }
+
+    /// Did the collection run out of room to evacuate into, leaving cells it meant to move where they were?
+    pub fn is_partial(&self) -> bool {
+        self.left_in_place > 0
+    }
+}
+
+/// A snapshot of the virtual heap's state, returned by `Heap::stats()`
+/// Used by `--stats`, and by anything else that needs to measure the heap.
+#[derive(Clone, Debug)]
+pub struct HeapStats {
+    pub capacity: usize,            //Total amount of cells in the memory pool
+    pub live: usize,                //Cells currently in use (holding data, or roots)
+    pub free: usize,                //Cells avaliable for allocation
+    pub roots: usize,               //Cells currently designated as roots
+    pub allocations: usize,         //Total successful allocations over the lifetime of the heap
+    pub collections: usize,         //Total garbage collection cycles that have run
+    pub minor_collections: usize,   //...of which minor collections (see generational.rs)
+    pub major_collections: usize,   //...major collections
+    pub full_collections: usize,    //...and full collections
+    pub high_water: usize,          //The most cells that have ever been in use at once
+    pub barrier_writes: usize,      //Total mutations that have passed through the write barrier
+    pub read_barrier_hits: usize,   //Total reads the read barrier forwarded to a relocated object (see relocation.rs)
+    pub rc_updates: usize,          //Total reference count updates the buffered variant has applied in batches (see refcount.rs)
+    pub rc_updates_elided: usize,   //...of which cancelled out when their batch was coalesced
+    pub reclaimed_by_rc: usize,     //Total cells the hybrid reclaimed by reference counting (see hybrid.rs)
+    pub reclaimed_by_tracer: usize, //...and with its cycle tracer
+    pub interned: usize,            //Total allocations interning shared an existing cell for, rather than making (see intern.rs)
+    pub bytes_interned: usize,      //...and the bytes they would have taken up
+    pub mark_epoch: usize,          //How many times the marks have been cleared, once at the start of each collection
+    pub frames: usize,              //Depth of the simulated call stack
+    pub bytes_capacity: usize,      //Total bytes the memory pool has room for (see sizes.rs)
+    pub bytes_used: usize,          //Bytes taken up by objects in use
+    pub bytes_reclaimed: usize,     //Total bytes swept by every collection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph, Heap};
+
+    #[test]
+    fn records_each_collection_with_its_phases() {
+        let mut heap: Heap = graph(4, &[(0, 1)]);
+        heap.log.echo = false;
+        collect(&mut heap);
+        collect(&mut heap);
+
+        let cycles: Vec<(usize, usize, usize)> = heap.history.iter().map(|cycle| (cycle.number, cycle.marked, cycle.swept)).collect();
+        assert_eq!(cycles, [(1, 2, 2), (2, 2, 0)]);
+        let cycle: &GcCycle = &heap.history[0];
+        assert_eq!(cycle.pause(), cycle.root_scan + cycle.mark + cycle.sweep);
+        assert!(cycle.at <= heap.history[1].at && !cycle.is_partial());
+    }
+
+    #[test]
+    fn counts_the_heap_and_what_has_happened_to_it() {
+        let mut heap: Heap = graph(4, &[(0, 1)]);
+        heap.log.echo = false;
+        collect(&mut heap);
+
+        let stats: HeapStats = heap.stats();
+        assert_eq!((stats.capacity, stats.live, stats.free, stats.roots), (8, 2, 6, 1));
+        assert_eq!((stats.allocations, stats.collections, stats.high_water), (4, 1, 4));
+        assert_eq!((stats.mark_epoch, stats.bytes_reclaimed), (1, heap.history[0].bytes_swept));
+    }
+}
@@ -0,0 +1,175 @@
+//Concurrent mutator stress test
+/*
+    Runs several mutator threads against one SharedHeap at once, each doing random allocations, links, unlinks and reads,
+    while a collector keeps stopping the world to collect. The threads race each other (a cell one thread picked
+    may be swept, or unlinked by another thread, before it gets to use it), which every operation has to cope with.
+
+    Once every thread is done the heap is verified (see verify.rs) -> any violation means an operation
+    wasn't as atomic as it should have been.
+*/
+
+use rand::{seq::IndexedRandom, Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::{ops::Add, thread, time::Duration};
+
+use crate::{error::ErrorKind, handle::Handle, shared::SharedHeap, verify::Violation, Heap};
+
+const COLLECT_EVERY: Duration = Duration::from_micros(500);    //How long the collector lets the mutators run between collections
+
+/// What happened during `run()`
+#[derive(Clone, Debug, Default)]
+pub struct StressReport {
+    pub threads: usize,
+    pub ops: Counts,                            //Operations across every thread
+    pub collections: usize,                     //Collections that ran while the threads were
+    pub longest_time_to_safepoint: Duration,    //...and the longest any of them waited for every thread to park
+    pub violations: Vec<Violation>,             //Broken invariants found afterwards (there should be none)
+}
+
+/// Operations done by mutator threads
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Counts {
+    pub allocs: usize,
+    pub links: usize,
+    pub unlinks: usize,
+    pub reads: usize,
+    pub out_of_memory: usize,       //Allocations that found no free cell (the collector hadn't caught up)
+    pub raced: usize,               //Operations that found another thread had got there first (a stale handle, a reference gone), and did nothing
+}
+
+impl Add for Counts {
+    type Output = Counts;
+
+    fn add(self, other: Counts) -> Counts {
+        Counts {
+            allocs: self.allocs + other.allocs,
+            links: self.links + other.links,
+            unlinks: self.unlinks + other.unlinks,
+            reads: self.reads + other.reads,
+            out_of_memory: self.out_of_memory + other.out_of_memory,
+            raced: self.raced + other.raced,
+        }
+    }
+}
+
+/// Runs `threads` mutator threads of `ops` random operations each against the heap, collecting (stopping the world)
+/// every so often until they are all done, then verifies the heap.
+/// Each thread's random choices are seeded from the heap's rng, though how the threads interleave is up to the OS
+pub fn run(heap: &mut Heap, threads: usize, ops: usize) -> StressReport {
+    let seeds: Vec<u64> = (0..threads).map(|_| heap.rng.random()).collect();
+    let shared: SharedHeap = SharedHeap::new(heap);
+
+    let (counts, collections, longest) = thread::scope(|scope| {
+        let shared: &SharedHeap = &shared;
+        let workers: Vec<_> = seeds.into_iter().map(|seed| scope.spawn(move || mutate(shared, seed, ops))).collect();
+
+        //Collect at least once, even if the threads finish straight away
+        let (mut collections, mut longest) = (0, Duration::ZERO);
+        loop {
+            thread::sleep(COLLECT_EVERY);
+            longest = longest.max(shared.collect());
+            collections += 1;
+
+            if workers.iter().all(|worker| worker.is_finished()) {
+                break;
+            }
+        }
+
+        let counts: Counts = workers
+            .into_iter()
+            .map(|worker| worker.join().unwrap_or_default())
+            .fold(Counts::default(), Counts::add);
+        (counts, collections, longest)
+    });
+
+    let violations: Vec<Violation> = shared.with(|heap| heap.verify());
+    StressReport {
+        threads,
+        ops: counts,
+        collections,
+        longest_time_to_safepoint: longest,
+        violations,
+    }
+}
+
+//One mutator thread -> picks what to do (and which cells to do it to) with the heap locked, then lets go before doing it,
+//so another thread (or a collection) can change those cells in between
+fn mutate(shared: &SharedHeap, seed: u64, ops: usize) -> Counts {
+    let mutator = shared.attach();
+    let mut rng: ChaCha8Rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut counts: Counts = Counts::default();
+
+    for _ in 0..ops {
+        mutator.poll();
+
+        let (live, edges) = shared.with(|heap| {
+            let heap: &Heap = heap;
+            let live: Vec<Handle> = (0..heap.cells.len()).filter_map(|i| heap.handle(i)).collect();
+            let edges: Vec<(Handle, Handle)> = live
+                .iter()
+                .flat_map(|&from| heap.cells[from.index].will_ref.iter().filter_map(move |&to| Some((from, heap.handle(to)?))))
+                .collect();
+            (live, edges)
+        });
+
+        match rng.random_range(0..10) {
+            //Allocate, and hang the new cell off a live one so it has a chance of surviving
+            0..=3 => match shared.alloc(rng.random_range(0..50)) {
+                Ok(handle) => {
+                    counts.allocs += 1;
+                    if let Some(&from) = live.choose(&mut rng) {
+                        let _ = shared.link(from, handle);
+                    }
+                }
+                Err(_) => counts.out_of_memory += 1,
+            },
+            4..=6 => match (live.choose(&mut rng), live.choose(&mut rng)) {
+                (Some(&from), Some(&to)) if from != to => match shared.link(from, to) {
+                    Ok(_) => counts.links += 1,
+                    Err(why) if why.kind == ErrorKind::Occupied => {} //Already referenced, nothing to do
+                    Err(_) => counts.raced += 1,
+                },
+                _ => {}
+            },
+            7..=8 => {
+                if let Some(&(from, to)) = edges.choose(&mut rng) {
+                    match shared.unlink(from, to) {
+                        Ok(_) => counts.unlinks += 1,
+                        Err(_) => counts.raced += 1,
+                    }
+                }
+            }
+            _ => {
+                if let Some(&handle) = live.choose(&mut rng) {
+                    match shared.read(handle) {
+                        Ok(_) => counts.reads += 1,
+                        Err(_) => counts.raced += 1,
+                    }
+                }
+            }
+        }
+    }
+
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{roots::GLOBALS, spec_alloc};
+
+    #[test]
+    fn leaves_the_heap_sound_after_racing_mutators() {
+        let mut heap: Heap = Heap::with_seed(64, 5);
+        heap.log.echo = false;
+        spec_alloc(&mut heap, 0, None, 0).unwrap();
+        heap.add_root(GLOBALS, 0).unwrap();
+
+        let report: StressReport = run(&mut heap, 4, 300);
+        let ops: Counts = report.ops;
+        assert!(report.violations.is_empty(), "{:?}", report.violations);
+        assert!(report.threads == 4 && report.collections >= 1 && ops.allocs > 0);
+        assert!(ops.allocs + ops.links + ops.unlinks + ops.reads + ops.out_of_memory + ops.raced <= 4 * 300);
+        assert!(heap.verify().is_empty() && !heap.history.is_empty() && !heap.cells[0].freed);
+    }
+}
@@ -0,0 +1,119 @@
+//Strings
+/*
+    A string's bytes live on the heap like everything else, rather than off to the side in a Rust String ->
+    packed 4 to a cell, in cells the string's head cell references in order. The head holds the length in bytes.
+
+    So a string takes up as much of the heap as its length needs, marking the head keeps every one of its cells alive
+    (they are just references), and sweeping it reclaims all of them at once.
+*/
+
+use crate::{assign_reference, error::{ErrorKind, GcError, Operation}, free_alloc, spec_alloc, Cell, Heap, IndexResult, Kind};
+use crate::prelude::*;
+
+/// How many bytes of a string fit in one cell
+pub const BYTES_PER_CELL: usize = 4;
+
+impl Heap {
+    /// Allocates a string, its head at `at` (or the first free cell) and its bytes in the first free cells after that.
+    /// Returns `Occupied` error if `at` is in use, `OutOfBounds` error if it isn't on the heap, or `NoFreeMemory` error
+    /// if there aren't enough free cells for the whole string (in which case nothing is allocated)
+    pub fn alloc_str(&mut self, text: &str, at: Option<usize>) -> IndexResult {
+        match at.map(|at| (at, self.cells.get(at))) {
+            Some((at, None)) => return Err(GcError::new(ErrorKind::OutOfBounds, Operation::Alloc, Some(at))),
+            Some((at, Some(cell))) if !cell.freed => return Err(GcError::new(ErrorKind::Occupied, Operation::Alloc, Some(at))),
+            _ => {}
+        }
+
+        //Make sure there is room for all of it first, so a string is never left half allocated
+        let chunks: Vec<i32> = text.as_bytes().chunks(BYTES_PER_CELL).map(pack).collect();
+        let room: usize = self.room();
+        let full = GcError::new(ErrorKind::NoFreeMemory, Operation::Alloc, None);
+        let length: i32 = i32::try_from(text.len()).map_err(|_| full)?;
+        if room < chunks.len() + 1 {
+            return Err(full);
+        }
+
+        let head: usize = match at {
+            Some(at) => spec_alloc(self, length, None, at)?,
+            None => free_alloc(self, length, None)?,
+        };
+        self.cells[head].kind = Kind::Str;
+
+        for chunk in chunks {
+            let index: usize = free_alloc(self, chunk, None)?;
+            self.cells[index].kind = Kind::Bytes;
+            assign_reference(self, head, index);
+        }
+        Ok(head)
+    }
+
+    /// The text of the string whose head is at `index`, or None if there isn't one there
+    pub fn read_str(&self, index: usize) -> Option<String> {
+        read(&self.cells, index)
+    }
+}
+
+/// The text of the string whose head is at `index`, or None if there isn't one there.
+/// Bytes that aren't valid UTF-8 (i.e. after `--set` on one of its cells) are replaced with U+FFFD
+pub fn read(cells: &[Cell], index: usize) -> Option<String> {
+    let head: &Cell = cells.get(index).filter(|cell| !cell.freed && cell.kind == Kind::Str)?;
+    let length: usize = usize::try_from(head.data?).unwrap_or(0);
+
+    let bytes: Vec<u8> = head.will_ref
+        .iter()
+        .map(|&i| &cells[i])
+        .filter(|cell| cell.kind == Kind::Bytes)
+        .flat_map(|cell| unpack(cell.data))
+        .take(length)
+        .collect();
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The bytes packed into a cell's data (trailing zeroes are padding, in the last cell of a string)
+pub fn unpack(data: Option<i32>) -> [u8; BYTES_PER_CELL] {
+    data.unwrap_or(0).to_le_bytes()
+}
+
+//Packs up to 4 bytes into a cell's data, padding with zeroes
+fn pack(bytes: &[u8]) -> i32 {
+    let mut packed: [u8; BYTES_PER_CELL] = [0; BYTES_PER_CELL];
+    packed[..bytes.len()].copy_from_slice(bytes);
+    i32::from_le_bytes(packed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, roots::GLOBALS};
+
+    #[test]
+    fn keeps_a_string_in_cells_its_head_references() {
+        let mut heap: Heap = Heap::with_seed(8, 0);
+        heap.log.echo = false;
+        let head: usize = heap.alloc_str("hello, gc", Some(2)).unwrap();
+        assert_eq!((head, heap.cells[2].data, heap.cells[2].will_ref.clone()), (2, Some(9), vec![0, 1, 3]));
+        assert_eq!(heap.read_str(head).as_deref(), Some("hello, gc"));
+        assert_eq!((heap.read_str(0), heap.read_str(20)), (None, None));             //Part of a string, and nothing at all
+
+        //Rooting the head keeps the whole string, dropping it reclaims every cell at once
+        heap.add_root(GLOBALS, head).unwrap();
+        collect(&mut heap);
+        assert_eq!(heap.read_str(head).as_deref(), Some("hello, gc"));
+        heap.remove_root(head);
+        collect(&mut heap);
+        assert_eq!(heap.live(), 0);
+    }
+
+    #[test]
+    fn allocates_nothing_unless_the_whole_string_fits() {
+        let mut heap: Heap = Heap::with_seed(3, 0);
+        heap.log.echo = false;
+        assert_eq!(heap.alloc_str("far too long", None).unwrap_err().kind, ErrorKind::NoFreeMemory);
+        assert_eq!(heap.live(), 0);
+
+        heap.alloc_str("", Some(1)).unwrap();
+        assert_eq!(heap.alloc_str("x", Some(1)).unwrap_err().kind, ErrorKind::Occupied);
+        assert_eq!(heap.alloc_str("x", Some(3)).unwrap_err().kind, ErrorKind::OutOfBounds);
+        assert_eq!((heap.read_str(1).as_deref(), heap.live()), (Some(""), 1));
+    }
+}
@@ -0,0 +1,115 @@
+//Tags
+/*
+    A large scenario is easier to follow in parts -> the cells of a cache, a request, or one data structure.
+    A cell can carry any number of tags (`--tag <cell> cache`), free-form labels that group it with other cells
+    without giving it a type. Unlike a name, a tag isn't unique, so it picks out a group rather than a cell:
+
+        --query tag==cache              cells with the tag (tag!=cache for those without it)
+        --state cache                   the heap table, cut down to them
+        --export dot <path> cache       the reference graph of just them, and the references between them
+
+    Like names, tags belong to the data in a cell -> they move with it, and are dropped when it is freed.
+*/
+
+use crate::{log::Event, Cell, Heap};
+use crate::prelude::*;
+
+/// Reasons a cell can't be tagged
+#[derive(Debug)]
+pub enum TagError {
+    OutOfBounds,        //There is no cell at that index
+    DataIsFree,         //Only cells in use can be tagged
+    Invalid,            //Tags can't be empty, start with '-', or contain spaces
+}
+
+impl Cell {
+    /// Does the cell have a tag?
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t == tag)
+    }
+}
+
+impl Heap {
+    /// Tags the cell at `index` (or takes the tag off, if `on` is false), returning whether that changed anything
+    /// Returns `OutOfBounds` error if there is no such cell, `DataIsFree` error if it isn't in use, or `Invalid` error
+    /// if the tag couldn't be told apart from a command
+    pub fn tag_cell(&mut self, index: usize, tag: &str, on: bool) -> Result<bool, TagError> {
+        match self.cells.get(index) {
+            None => return Err(TagError::OutOfBounds),
+            Some(cell) if cell.freed => return Err(TagError::DataIsFree),
+            Some(_) => {}
+        }
+        if tag.is_empty() || tag.starts_with('-') || tag.contains(char::is_whitespace) {
+            return Err(TagError::Invalid);
+        }
+
+        let cell: &mut Cell = &mut self.cells[index];
+        if cell.has_tag(tag) == on {
+            return Ok(false);
+        }
+        match on {
+            true => cell.tags.push(tag.to_string()),
+            false => cell.tags.retain(|t| t != tag),
+        }
+        self.log.emit(Event::Tagged { index, tag: tag.to_string(), on });
+        Ok(true)
+    }
+
+    /// Every cell with a tag, in order of index
+    pub fn tagged(&self, tag: &str) -> Vec<usize> {
+        (0..self.cells.len()).filter(|&i| self.cells[i].has_tag(tag)).collect()
+    }
+
+    /// Every tag in use, with how many cells have it, in order of name
+    pub fn tags(&self) -> Vec<(String, usize)> {
+        let mut tags: Vec<(String, usize)> = Vec::new();
+        for tag in self.cells.iter().flat_map(|cell| &cell.tags) {
+            match tags.iter_mut().find(|(t, _)| t == tag) {
+                Some((_, count)) => *count += 1,
+                None => tags.push((tag.clone(), 1)),
+            }
+        }
+        tags.sort();
+        tags
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, query::Query, tests::graph, unlink_reference};
+
+    #[test]
+    fn picks_out_cells_by_their_tags() {
+        let mut heap: Heap = graph(4, &[(0, 1), (1, 2), (2, 3)]);
+        heap.log.echo = false;
+        assert!(heap.tag_cell(1, "cache", true).unwrap() && heap.tag_cell(2, "cache", true).unwrap());
+        assert!(!heap.tag_cell(2, "cache", true).unwrap());
+        assert!(matches!(heap.tag_cell(3, "-cache", true), Err(TagError::Invalid)));
+
+        assert_eq!(heap.query(&Query::parse("tag==cache").unwrap()), [1, 2]);
+        assert_eq!(heap.tags(), [("cache".to_string(), 2)]);
+        let dot: String = heap.to_dot_tagged(Some("cache"));
+        assert!(dot.contains("c1 -> c2;") && !dot.contains("c0") && !dot.contains("c3"));
+
+        //Tags go with the data, so a freed cell loses them
+        unlink_reference(&mut heap, 0, 1);
+        collect(&mut heap);
+        assert!(heap.tagged("cache").is_empty());
+    }
+
+    #[test]
+    fn only_tags_cells_in_use_with_tags_that_arent_commands() {
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        assert!(matches!(heap.tag_cell(9, "cache", true), Err(TagError::OutOfBounds)));
+        assert!(matches!(heap.tag_cell(3, "cache", true), Err(TagError::DataIsFree)));
+        for tag in ["", "a cache", "--gc"] {
+            assert!(matches!(heap.tag_cell(1, tag, true), Err(TagError::Invalid)));
+        }
+
+        assert!(!heap.tag_cell(1, "cache", false).unwrap());           //Nothing to take off
+        assert!(heap.tag_cell(1, "cache", true).unwrap() && heap.tag_cell(1, "cache", false).unwrap());
+        assert!(heap.tags().is_empty() && !heap.to_dot_tagged(Some("cache")).contains("c1"));
+    }
+}
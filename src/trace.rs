@@ -0,0 +1,165 @@
+//Chrome trace export
+/*
+    Writes the events of a session out in the Chrome `trace_event` JSON format, so it can be loaded
+    into chrome://tracing or Perfetto (ui.perfetto.dev) and viewed on a timeline.
+
+    Two "threads" are drawn:
+        - mutator -> the time between collections, with an instant event for each allocation, reference...
+        - collector -> one span per GC pause, split into its root scan, mark and sweep phases
+*/
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+
+use crate::{log::Event, Instant};
+
+const MUTATOR: u32 = 0;                 //Thread id the mutator is drawn on
+const COLLECTOR: u32 = 1;               //Thread id the collector is drawn on
+
+/// Streams trace events to a file as they happen.
+/// The file is a JSON array that is only closed by `finish()`, but an unterminated array is still
+/// accepted by the trace viewers, so a session that exits without finishing can still be loaded
+pub struct Trace {
+    file: File,
+    start: Instant,                     //Timestamps are relative to when tracing started
+    written: usize,                     //How many trace events have been written so far
+    mutator_since: Duration,            //When the current stretch of mutator time began
+}
+
+impl Trace {
+    /// Creates (or truncates) the trace file at `path` and writes its header
+    pub fn create(path: &str) -> io::Result<Trace> {
+        let mut trace = Trace {
+            file: File::create(path)?,
+            start: Instant::now(),
+            written: 0,
+            mutator_since: Duration::ZERO,
+        };
+
+        write!(trace.file, "[")?;
+        trace.write(json!({ "name": "process_name", "ph": "M", "pid": 1, "args": { "name": "gc-rust" } }))?;
+        trace.write(json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": MUTATOR, "args": { "name": "mutator" } }))?;
+        trace.write(json!({ "name": "thread_name", "ph": "M", "pid": 1, "tid": COLLECTOR, "args": { "name": "collector" } }))?;
+        Ok(trace)
+    }
+
+    /// Adds an event from the heap's log to the trace
+    pub fn record(&mut self, event: &Event) -> io::Result<()> {
+        let now: Duration = self.start.elapsed();
+
+        match event {
+            //A finished collection becomes a pause on the collector, ending now, split into its phases
+            Event::Collected { number, kind, marked, swept, bytes, root_scan, mark, sweep } => {
+                let pause: Duration = *root_scan + *mark + *sweep;
+                let begin: Duration = now.saturating_sub(pause);
+
+                //Everything since the last collection was mutator time
+                self.span(MUTATOR, "mutator", self.mutator_since, begin.saturating_sub(self.mutator_since), json!({}))?;
+                self.span(
+                    COLLECTOR,
+                    &format!("GC #{}", number),
+                    begin,
+                    pause,
+                    json!({ "kind": kind.to_string(), "marked": marked, "swept": swept, "bytes": bytes }),
+                )?;
+                self.span(COLLECTOR, "root scan", begin, *root_scan, json!({}))?;
+                self.span(COLLECTOR, "mark", begin + *root_scan, *mark, json!({}))?;
+                self.span(COLLECTOR, "sweep", begin + *root_scan + *mark, *sweep, json!({}))?;
+                self.mutator_since = now;
+                Ok(())
+            }
+            //The phase markers are already covered by the spans above
+            Event::CollectStart { .. } | Event::MarkStart { .. } | Event::Marked { .. } | Event::MarkEnd { .. } => Ok(()),
+            //Sweeping and moving happen on the collector, everything else is the mutator's doing
+            Event::Swept { .. } | Event::Moved { .. } | Event::Survivors { .. } | Event::BarrierMiss { .. } | Event::Relocated { .. } | Event::CollectionSet { .. } | Event::EvacuationFailed { .. } | Event::RcBatch { .. } | Event::CycleScan { .. } | Event::FinalizerQueued { .. } | Event::Finalized { .. } | Event::FinalizerCycle { .. } | Event::WeakEntryDropped { .. } => self.instant(COLLECTOR, event, now),
+            _ => self.instant(MUTATOR, event, now),
+        }
+    }
+
+    /// Closes off the trailing stretch of mutator time and the JSON array
+    pub fn finish(mut self) -> io::Result<()> {
+        let now: Duration = self.start.elapsed();
+        self.span(MUTATOR, "mutator", self.mutator_since, now.saturating_sub(self.mutator_since), json!({}))?;
+        writeln!(self.file, "\n]")
+    }
+
+    //A complete ("X") event -> something that took `duration`, starting at `begin`
+    fn span(&mut self, tid: u32, name: &str, begin: Duration, duration: Duration, args: Value) -> io::Result<()> {
+        self.write(json!({
+            "name": name,
+            "ph": "X",
+            "pid": 1,
+            "tid": tid,
+            "ts": micros(begin),
+            "dur": micros(duration),
+            "args": args,
+        }))
+    }
+
+    //An instant ("i") event -> something that happened at a single point in time
+    fn instant(&mut self, tid: u32, event: &Event, at: Duration) -> io::Result<()> {
+        self.write(json!({
+            "name": event.kind(),
+            "ph": "i",
+            "s": "t",
+            "pid": 1,
+            "tid": tid,
+            "ts": micros(at),
+            "args": { "message": event.to_string() },
+        }))
+    }
+
+    //Writes one trace event, one per line, comma separated
+    fn write(&mut self, value: Value) -> io::Result<()> {
+        let seperator: &str = if self.written == 0 { "\n" } else { ",\n" };
+        write!(self.file, "{}{}", seperator, value)?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+//Trace timestamps and durations are in (fractional) microseconds
+fn micros(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use crate::{collect, tests::graph, Heap};
+
+    use super::*;
+
+    #[test]
+    fn draws_each_collection_as_a_pause_on_the_collector() {
+        let path = env::temp_dir().join(format!("gc-rust-trace-{}.json", process::id()));
+        let mut heap: Heap = graph(2, &[]);
+        heap.log.echo = false;
+        heap.log.attach_trace(path.to_str().unwrap()).unwrap();
+        collect(&mut heap);
+        heap.log.detach_trace().unwrap();
+
+        let events: Value = serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        fs::remove_file(&path).unwrap();
+        let named = |name: &str| events.as_array().unwrap().iter().filter(|event| event["name"] == name).cloned().collect::<Vec<Value>>();
+
+        let pause: &Value = &named("GC #1")[0];
+        assert_eq!((pause["ph"].as_str(), pause["tid"].as_u64()), (Some("X"), Some(COLLECTOR as u64)));
+        assert_eq!(pause["args"]["swept"], 1);
+        assert_eq!(named("sweep").len(), 1);
+        assert_eq!(named("mutator").len(), 2);                              //Before the collection, and after it until the trace finished
+        assert!(named("swept").iter().all(|event| event["tid"] == COLLECTOR));
+    }
+
+    #[test]
+    fn refuses_a_trace_file_it_cannot_create() {
+        let path = env::temp_dir().join("gc-rust-missing").join("trace.json");
+        assert!(Trace::create(path.to_str().unwrap()).is_err());
+    }
+}
@@ -0,0 +1,318 @@
+//Baker's treadmill
+/*
+    An incremental collector does its tracing a little at a time, between the program's own work, rather than in one
+    long pause. Baker's treadmill does it without moving anything -> every object is on one of four doubly linked
+    lists, and its list is its colour:
+
+        white -> free, waiting to be allocated
+        ecru -> condemned, not (yet) found to be reachable this cycle. Whatever is still ecru when the cycle ends is garbage
+        gray -> found to be reachable, but not scanned for the cells it references yet
+        black -> reachable and scanned (or allocated since the cycle began), it is kept
+
+    "Copying" an object into the to-space is a snap -> it is unlinked from its list and linked onto another, a handful
+    of pointer updates, wherever it is in the pool. The original runs the four lists end to end around one ring;
+    here each colour has a list of its own, which snaps the same way.
+
+    A cycle starts with every cell in use ecru and the roots snapped to gray. Each allocation then scans a few gray
+    cells (see INCREMENT), snapping the ecru cells they reference to gray and themselves to black, and the new cell is
+    allocated black. The program keeps running meanwhile, so the write barrier (see barrier.rs) snaps any ecru cell
+    it is made to reference to gray -> a black cell never references an ecru one, so nothing reachable is left behind.
+
+    A collection under the treadmill (`--set-policy algorithm treadmill`) finishes the cycle -> the roots are scanned
+    again (for any made since it began), the rest of the gray cells are scanned, and every ecru cell is reclaimed.
+    Then comes the flip, where the black list simply becomes the ecru list of the next cycle, and its roots are
+    snapped to gray for the allocations to carry on with. The more the allocations got done, the less is left for the pause.
+*/
+
+use core::{fmt, time::Duration};
+
+use crate::{drop_swept_referrers, generational::CollectionKind, log::Event, reclaim, stats::GcCycle, Algorithm, Cell, Heap, Instant, MarkOrder};
+use crate::prelude::*;
+
+/// Gray cells each allocation scans, while a cycle is under way
+pub const INCREMENT: usize = 4;
+
+/// Which of the treadmill's lists a cell is on, see the top of this module
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colour {
+    White,
+    Ecru,
+    Gray,
+    Black,
+}
+
+/// The treadmill's lists, with every cell of the heap on exactly one of them
+#[derive(Clone, Debug)]
+pub struct Treadmill {
+    next: Vec<usize>,               //The cell after each one on its list, with the lists' heads after the cells
+    prev: Vec<usize>,               //...and the one before it
+    list: Vec<usize>,               //Which list each cell is on
+    lists: [usize; 4],              //Which list holds each colour, in the order of Colour (the flip swaps two of them)
+    lengths: [usize; 4],            //Cells on each list
+}
+
+impl Colour {
+    /// Every colour, in the order the treadmill's lists are numbered
+    pub const ALL: [Colour; 4] = [Colour::White, Colour::Ecru, Colour::Gray, Colour::Black];
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Colour::White => "white",
+            Colour::Ecru => "ecru",
+            Colour::Gray => "gray",
+            Colour::Black => "black",
+        })
+    }
+}
+
+impl Treadmill {
+    //Starts a treadmill with the cells in use ecru, and the free cells white
+    fn new(cells: &[Cell]) -> Treadmill {
+        let size: usize = cells.len();
+        let heads = size..size + 4;
+        let mut treadmill: Treadmill = Treadmill {
+            next: (0..size).chain(heads.clone()).collect(),         //Every list starts out empty, its head linked to itself
+            prev: (0..size).chain(heads).collect(),
+            list: vec![0; size],
+            lists: [0, 1, 2, 3],
+            lengths: [0; 4],
+        };
+        for (index, cell) in cells.iter().enumerate() {
+            treadmill.link(index, if cell.freed { Colour::White } else { Colour::Ecru });
+        }
+        treadmill
+    }
+
+    /// The colour of a cell
+    pub fn colour(&self, index: usize) -> Colour {
+        Colour::ALL[self.lists.iter().position(|&list| list == self.list[index]).expect("every list holds a colour")]
+    }
+
+    /// How many cells are a colour
+    pub fn count(&self, colour: Colour) -> usize {
+        self.lengths[self.lists[colour as usize]]
+    }
+
+    //Moves a cell onto the end of a colour's list
+    fn snap(&mut self, index: usize, colour: Colour) {
+        let (prev, next) = (self.prev[index], self.next[index]);
+        self.next[prev] = next;
+        self.prev[next] = prev;
+        self.lengths[self.list[index]] -= 1;
+        self.link(index, colour);
+    }
+
+    //Links a cell (on no list) onto the end of a colour's list
+    fn link(&mut self, index: usize, colour: Colour) {
+        let list: usize = self.lists[colour as usize];
+        let head: usize = self.list.len() + list;
+        let last: usize = self.prev[head];
+        self.next[last] = index;
+        self.prev[index] = last;
+        self.next[index] = head;
+        self.prev[head] = index;
+        self.list[index] = list;
+        self.lengths[list] += 1;
+    }
+
+    //The first cell of a colour's list, if it has any
+    fn first(&self, colour: Colour) -> Option<usize> {
+        let head: usize = self.list.len() + self.lists[colour as usize];
+        Some(self.next[head]).filter(|&first| first != head)
+    }
+
+    //The black list becomes the (empty) ecru list, and the other way around. No cell is touched
+    fn flip(&mut self) {
+        self.lists.swap(Colour::Ecru as usize, Colour::Black as usize);
+    }
+}
+
+impl Heap {
+    /// The treadmill, while the heap is collected by it (None until its first collection)
+    pub fn treadmill(&self) -> Option<&Treadmill> {
+        self.treadmill.as_ref().filter(|treadmill| treadmill.list.len() == self.cells.len())
+    }
+
+    //Snaps a cell in use to gray, unless it has already been found this cycle.
+    //A white cell in use was brought into use some other way than an allocation (i.e. made a root while free)
+    pub(crate) fn shade(&mut self, index: usize) {
+        if self.treadmill().is_none() || self.cells[index].freed {
+            return;
+        }
+        let treadmill: &mut Treadmill = self.treadmill.as_mut().expect("checked above");
+        if matches!(treadmill.colour(index), Colour::Ecru | Colour::White) {
+            treadmill.snap(index, Colour::Gray);
+        }
+    }
+
+    //Called for every allocation -> the new cell is black (and whatever it was allocated referencing is shaded),
+    //then the cycle under way is pushed along by scanning a few more gray cells
+    pub(crate) fn treadmill_alloc(&mut self, index: usize) {
+        if self.treadmill().is_none() {
+            return;
+        }
+        self.treadmill.as_mut().expect("checked above").snap(index, Colour::Black);
+        for target in self.cells[index].pointers().to_vec() {
+            self.shade(target);
+        }
+        self.scan(Some(INCREMENT));
+    }
+
+    //A cell was moved from one position to another (see copying::move_cells), so its colour goes with it
+    pub(crate) fn treadmill_moved(&mut self, from: usize, to: usize) {
+        if self.treadmill().is_none() {
+            return;
+        }
+        let treadmill: &mut Treadmill = self.treadmill.as_mut().expect("checked above");
+        let colour: Colour = treadmill.colour(from);
+        treadmill.snap(to, colour);
+        treadmill.snap(from, Colour::White);
+    }
+
+    //The objects in two cells were exchanged (see Heap::swap_cells), so their colours are too
+    pub(crate) fn treadmill_swapped(&mut self, a: usize, b: usize) {
+        if self.treadmill().is_none() {
+            return;
+        }
+        let treadmill: &mut Treadmill = self.treadmill.as_mut().expect("checked above");
+        let colours: (Colour, Colour) = (treadmill.colour(a), treadmill.colour(b));
+        treadmill.snap(a, colours.1);
+        treadmill.snap(b, colours.0);
+    }
+
+    //Scans gray cells (as many as `budget`, or all of them) -> the cells each one references are shaded, and it is
+    //snapped to black. Returns how many were scanned
+    fn scan(&mut self, budget: Option<usize>) -> usize {
+        let mut scanned: usize = 0;
+        while budget.is_none_or(|budget| scanned < budget) {
+            let Some(index) = self.treadmill.as_ref().and_then(|treadmill| treadmill.first(Colour::Gray)) else {
+                break;
+            };
+            for target in self.cells[index].pointers().to_vec() {
+                self.shade(target);
+            }
+            self.treadmill.as_mut().expect("a cell was gray").snap(index, Colour::Black);
+            self.cells[index].marked = true;
+            self.log.emit(Event::Marked { index });
+            scanned += 1;
+        }
+        scanned
+    }
+}
+
+/// Finishes the treadmill's cycle (starting one first if there isn't one under way), reclaims its ecru cells,
+/// then flips and starts the next cycle, returning the cycle (`number` is filled in by `collect()`).
+/// The `mark` phase of the cycle is the scanning the allocations left for the pause, and the `sweep` phase is
+/// reclaiming the garbage and flipping
+pub fn collect(heap: &mut Heap) -> GcCycle {
+    if heap.treadmill().is_none() {
+        heap.treadmill = Some(Treadmill::new(&heap.cells));
+    }
+
+    //Roots made since the cycle began haven't been shaded yet
+    let start = Instant::now();
+    let roots: Vec<usize> = heap.roots();
+    for &root in &roots {
+        heap.shade(root);
+    }
+    let root_scan = start.elapsed();
+
+    let start = Instant::now();
+    heap.log.emit(Event::MarkStart { roots: roots.len(), order: MarkOrder::Bfs }); //The gray list is scanned first in, first out
+    heap.scan(None);
+    let treadmill: &Treadmill = heap.treadmill.as_ref().expect("made above");
+    let marked: usize = treadmill.count(Colour::Black);
+    heap.log.emit(Event::MarkEnd { marked });
+    for index in (0..heap.cells.len()).filter(|&index| treadmill.colour(index) == Colour::Black) {
+        heap.cells[index].marked = true;
+    }
+    let mark_time = start.elapsed();
+
+    //Whatever is still ecru is garbage. A cell freed by hand during the cycle is white already, it only needs snapping
+    let start = Instant::now();
+    let mut swept: usize = 0;
+    while let Some(index) = heap.treadmill.as_ref().and_then(|treadmill| treadmill.first(Colour::Ecru)) {
+        if !heap.cells[index].freed && reclaim(&mut heap.cells, &mut heap.log, index) {
+            swept += 1;
+        }
+        heap.treadmill.as_mut().expect("a cell was ecru").snap(index, Colour::White);
+    }
+    drop_swept_referrers(&mut heap.cells);
+    heap.treadmill.as_mut().expect("made above").flip();
+    let sweep_time: Duration = start.elapsed();
+
+    //The next cycle starts straight away, for the allocations from now on to push along
+    for root in heap.roots() {
+        heap.shade(root);
+    }
+
+    GcCycle {
+        number: 0,
+        at: Duration::ZERO,
+        marked,
+        swept,
+        bytes_swept: 0,
+        root_scan,
+        mark: mark_time,
+        sweep: sweep_time,
+        algorithm: Algorithm::Treadmill,
+        kind: CollectionKind::Whole,
+        left_in_place: 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, free_alloc, tests::graph, unlink_reference};
+
+    #[test]
+    fn traces_on_the_treadmill_as_cells_are_allocated() {
+        let mut heap: Heap = graph(5, &[(0, 1), (1, 2), (2, 3), (3, 4)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Treadmill;
+        free_alloc(&mut heap, 5, None).unwrap();                        //Garbage
+        crate::collect(&mut heap);
+        let colours = |heap: &Heap| Colour::ALL.map(|colour| heap.treadmill().unwrap().count(colour));
+        assert_eq!(colours(&heap), [4, 4, 1, 0]);                       //Cycle 2 has begun, with the root gray
+
+        //Each allocation scans a few gray cells, and is black itself. The barrier shades a cell linked from a black one
+        unlink_reference(&mut heap, 3, 4);
+        free_alloc(&mut heap, 6, None).unwrap();                        //Scans 0 to 3, leaving 4 ecru
+        assign_reference(&mut heap, 0, 4);
+        assert_eq!(heap.treadmill().unwrap().colour(4), Colour::Gray);
+        free_alloc(&mut heap, 7, None).unwrap();
+        assert_eq!(colours(&heap), [2, 0, 0, 7]);
+
+        //The cells allocated this cycle are kept until the next
+        crate::collect(&mut heap);
+        assert!((0..7).all(|i| !heap.cells[i].freed));
+        crate::collect(&mut heap);
+        assert!(heap.cells[5].freed && heap.cells[6].freed && heap.verify().is_empty());
+    }
+
+    #[test]
+    fn starts_over_once_the_heap_changes_under_it() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.policy.algorithm = Algorithm::Treadmill;
+        assert!(heap.treadmill().is_none());                            //Not until its first collection
+        crate::collect(&mut heap);
+        assert_eq!(heap.treadmill().unwrap().count(Colour::White), 5);
+
+        //A grown heap leaves the treadmill behind, and allocating on it pushes no cycle along
+        heap.grow(2);
+        assert!(heap.treadmill().is_none());
+        free_alloc(&mut heap, 5, None).unwrap();                        //Garbage, in cell 2
+        crate::collect(&mut heap);
+        assert_eq!(heap.treadmill().unwrap().count(Colour::White), 7);
+        assert!(heap.cells[2].freed && !heap.cells[1].freed);
+
+        //So does any other collection
+        heap.policy.algorithm = Algorithm::MarkSweep;
+        crate::collect(&mut heap);
+        assert!(heap.treadmill().is_none());
+    }
+}
@@ -0,0 +1,423 @@
+//Full screen terminal dashboard for the virtual heap (--tui)
+/*
+    The line-by-line output of the prompt gets hard to follow once a demo grows past a handful of cells.
+    The dashboard shows everything at once and redraws after every command:
+
+        - Heap map       -> every cell as a coloured block (root / marked / unmarked / free), and how full
+                            each generational space is (survivor space cells are underlined)
+        - Cell inspector -> scrollable list of every cell's data, flags and references
+        - Roots          -> the current root set
+        - GC log         -> events from the heap's event log, plus the output of each command
+
+    Commands are typed into the input line at the bottom, exactly as they would be at the prompt.
+*/
+
+use gc_rust::{generational::Space, poison::POISON, Algorithm, Cell, Heap};
+use ratatui::{
+    crossterm::event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout, Position, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
+    DefaultTerminal, Frame,
+};
+use std::io;
+
+use crate::{run_command, Flow, Session};
+
+/// How many lines the GC log panel holds on to
+const LOG_LINES: usize = 500;
+
+/// Why the dashboard was closed
+pub enum Leave {
+    Prompt,             //Esc / Ctrl+C -> go back to the line-by-line prompt
+    Exit(i32),          //--exit was typed -> end the program with this exit code
+}
+
+/// State of the dashboard that isn't part of the heap itself
+struct Dashboard {
+    input: String,                  //Command currently being typed
+    log: Vec<String>,               //Lines shown in the GC log panel, oldest first
+    log_scroll: usize,              //How many lines the log panel is scrolled back from the bottom
+    inspector: ListState,           //Which cell is selected in the inspector
+    last_event: usize,              //Sequence number of the last heap event copied into the log
+}
+
+impl Dashboard {
+    fn new(heap: &Heap) -> Dashboard {
+        Dashboard {
+            input: String::new(),
+            log: vec!["Type commands below as you would at the prompt. Esc returns to the prompt.".to_string()],
+            log_scroll: 0,
+            inspector: ListState::default().with_selected(Some(0)),
+            last_event: heap.log.sequence(),
+        }
+    }
+
+    //Adds a line to the log panel, dropping the oldest once full
+    fn push_log(&mut self, line: String) {
+        if self.log.len() == LOG_LINES {
+            self.log.remove(0);
+        }
+        self.log.push(line);
+    }
+
+    /// Runs the typed command through the same handler as the prompt, and copies its output
+    /// (and any events it caused on the heap) into the log panel
+    fn submit(&mut self, heap: &mut Heap, session: &mut Session) -> Option<Leave> {
+        let line: String = std::mem::take(&mut self.input);
+
+        match line.trim() {
+            "" => return None,
+            "--tui" => {
+                self.push_log("Already in the TUI".to_string());
+                return None;
+            }
+            _ => {}
+        }
+
+        self.push_log(format!("> {}", line.trim()));
+
+        //Capture what the command writes, rather than letting it print over the dashboard
+        let mut output: Vec<u8> = Vec::new();
+        let flow: Flow = run_command(heap, session, &line, &mut output);
+        if let Flow::Exit(code) = flow {
+            return Some(Leave::Exit(code));
+        }
+
+        //Events from the heap first (they happened while the command ran), respecting the log's verbosity
+        let events: Vec<String> = heap
+            .log
+            .since(self.last_event)
+            .filter(|event| event.level() <= heap.log.level)
+            .map(|event| event.to_string())
+            .collect();
+        self.last_event = heap.log.sequence();
+        for event in events {
+            self.push_log(event);
+        }
+
+        for output_line in String::from_utf8_lossy(&output).lines() {
+            self.push_log(output_line.to_string());
+        }
+
+        //Commands like --load can change the size of the heap, keep the selection inside it
+        let selected = self.inspector.selected().unwrap_or(0).min(heap.cells.len() - 1);
+        self.inspector.select(Some(selected));
+        self.log_scroll = 0;
+
+        None
+    }
+}
+
+/// Takes over the terminal and runs the dashboard until the user leaves it.
+/// The heap's event log stops printing to the console while the dashboard is open (and ANSI colours are turned off,
+/// as the log panel can't display them), both are restored afterwards
+pub fn run(heap: &mut Heap, session: &mut Session) -> io::Result<Leave> {
+    let mut terminal: DefaultTerminal = ratatui::try_init()?;
+
+    let echo: bool = heap.log.echo;
+    let color: bool = session.color;
+    heap.log.echo = false;
+    session.color = false;
+
+    let result = dashboard_loop(&mut terminal, heap, session);
+
+    heap.log.echo = echo;
+    session.color = color;
+    ratatui::restore();
+
+    result
+}
+
+//Draw -> wait for a key -> handle it, until the user leaves
+fn dashboard_loop(terminal: &mut DefaultTerminal, heap: &mut Heap, session: &mut Session) -> io::Result<Leave> {
+    let mut dashboard = Dashboard::new(heap);
+
+    loop {
+        terminal.draw(|frame| draw(frame, heap, &mut dashboard))?;
+
+        //Only key presses matter (not releases, resizes are handled by the next draw)
+        let TermEvent::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        let last_cell: usize = heap.cells.len() - 1;
+        let selected: usize = dashboard.inspector.selected().unwrap_or(0);
+
+        match key.code {
+            KeyCode::Esc => return Ok(Leave::Prompt),
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(Leave::Prompt),
+            KeyCode::Enter => {
+                if let Some(leave) = dashboard.submit(heap, session) {
+                    return Ok(leave);
+                }
+            }
+            KeyCode::Char(c) => dashboard.input.push(c),
+            KeyCode::Backspace => {
+                dashboard.input.pop();
+            }
+            KeyCode::Up => dashboard.inspector.select(Some(selected.saturating_sub(1))),
+            KeyCode::Down => dashboard.inspector.select(Some((selected + 1).min(last_cell))),
+            KeyCode::PageUp => {
+                dashboard.log_scroll = (dashboard.log_scroll + 5).min(dashboard.log.len().saturating_sub(1));
+            }
+            KeyCode::PageDown => dashboard.log_scroll = dashboard.log_scroll.saturating_sub(5),
+            _ => {}
+        }
+    }
+}
+
+/// Style of a cell in the heap map, matching the DOT export's colours
+/// (gold roots, green marked cells, red unmarked cells and grey free cells)
+fn cell_style(cell: &Cell) -> Style {
+    if cell.is_root() {
+        Style::default().fg(Color::Black).bg(Color::Yellow).add_modifier(Modifier::BOLD)
+    } else if cell.freed {
+        Style::default().fg(Color::DarkGray)
+    } else if cell.marked {
+        Style::default().fg(Color::Black).bg(Color::Green)
+    } else {
+        Style::default().fg(Color::Black).bg(Color::Red)
+    }
+}
+
+//Lays out and draws every panel
+fn draw(frame: &mut Frame, heap: &Heap, dashboard: &mut Dashboard) {
+    let [top, middle, bottom, input] = Layout::vertical([
+        Constraint::Length(heap_map_height(heap, frame.area().width)),
+        Constraint::Min(6),
+        Constraint::Length(10),
+        Constraint::Length(3),
+    ])
+    .areas(frame.area());
+
+    let [inspector, roots] = Layout::horizontal([Constraint::Min(40), Constraint::Length(24)]).areas(middle);
+
+    draw_heap_map(frame, top, heap, dashboard.inspector.selected());
+    draw_inspector(frame, inspector, heap, &mut dashboard.inspector);
+    draw_roots(frame, roots, heap);
+    draw_log(frame, bottom, dashboard);
+
+    //Input line, with the cursor at the end of what's been typed
+    let prompt = Paragraph::new(format!("> {}", dashboard.input))
+        .block(Block::default().borders(Borders::ALL).title(" Command (Enter to run, Esc to leave) "));
+    frame.render_widget(prompt, input);
+    frame.set_cursor_position(Position::new(input.x + 3 + dashboard.input.chars().count() as u16, input.y + 1));
+}
+
+//Each cell in the map takes up 4 columns -> "[12]"
+const MAP_CELL_WIDTH: u16 = 4;
+
+//Rows needed to fit every cell into the map (plus the border, legend and generations)
+fn heap_map_height(heap: &Heap, width: u16) -> u16 {
+    let per_row = (width.saturating_sub(2) / MAP_CELL_WIDTH).max(1) as usize;
+    let rows = heap.cells.len().div_ceil(per_row) as u16;
+
+    rows + 3 + u16::from(shows_generations(heap))
+}
+
+//The map only has generations to show if the heap is collected by generation, or has survivor spaces
+fn shows_generations(heap: &Heap) -> bool {
+    heap.policy.algorithm == Algorithm::Generational || heap.survivor_spaces().is_some()
+}
+
+//Every cell as a coloured block, wrapped to the width of the screen
+fn draw_heap_map(frame: &mut Frame, area: Rect, heap: &Heap, selected: Option<usize>) {
+    let per_row = (area.width.saturating_sub(2) / MAP_CELL_WIDTH).max(1) as usize;
+    let survivors: Vec<usize> = heap.survivor_spaces().map_or(Vec::new(), |spaces| spaces.into_iter().flatten().collect());
+
+    let mut lines: Vec<Line> = heap
+        .cells
+        .chunks(per_row)
+        .enumerate()
+        .map(|(row, cells)| {
+            Line::from(
+                cells
+                    .iter()
+                    .enumerate()
+                    .map(|(col, cell)| {
+                        let index = row * per_row + col;
+                        let mut style = cell_style(cell);
+                        if selected == Some(index) {
+                            style = style.add_modifier(Modifier::REVERSED);
+                        }
+                        if survivors.contains(&index) {
+                            style = style.add_modifier(Modifier::UNDERLINED);
+                        }
+                        Span::styled(format!("{:>3} ", index), style)
+                    })
+                    .collect::<Vec<Span>>(),
+            )
+        })
+        .collect();
+
+    lines.push(Line::from(vec![
+        Span::styled(" root ", cell_style(&root_cell())),
+        Span::raw(" "),
+        Span::styled(" marked ", Style::default().fg(Color::Black).bg(Color::Green)),
+        Span::raw(" "),
+        Span::styled(" unmarked ", Style::default().fg(Color::Black).bg(Color::Red)),
+        Span::raw(" "),
+        Span::styled(" free ", Style::default().fg(Color::DarkGray)),
+    ]));
+
+    //How full each space is, in the order cells are promoted through them
+    if shows_generations(heap) {
+        let spaces: Vec<String> = heap
+            .space_usage()
+            .iter()
+            .map(|usage| match usage.space {
+                Space::Survivor(space) if space == heap.to_space() => format!("{} {}/{} (to)", usage.space, usage.used, usage.capacity),
+                Space::Survivor(_) => format!("{} {}/{}", usage.space, usage.used, usage.capacity),
+                _ => format!("{} {}", usage.space, usage.used),
+            })
+            .collect();
+        lines.push(Line::from(format!(" {} ", spaces.join(" -> "))));
+    }
+
+    let stats = heap.stats();
+    let title = format!(
+        " Heap map: {}/{} cells in use ({}% of the heap), {} collections ",
+        stats.live,
+        stats.capacity,
+        heap.occupancy(),
+        stats.collections
+    );
+    frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), area);
+}
+
+//A throwaway root cell, so the legend is styled exactly like the map
+fn root_cell() -> Cell {
+    let mut cell = Cell::new();
+    cell.make_root();
+    cell
+}
+
+//Scrollable list of every cell and its details
+fn draw_inspector(frame: &mut Frame, area: Rect, heap: &Heap, state: &mut ListState) {
+    let items: Vec<ListItem> = heap
+        .cells
+        .iter()
+        .enumerate()
+        .map(|(i, cell)| {
+            let data = match cell.data {
+                Some(_) if cell.is_poisoned() => format!("{:#x}", POISON),
+                Some(data) => data.to_string(),
+                None => "-".to_string(),
+            };
+            let flags = format!(
+                "{}{}{}",
+                if cell.is_root() { "R" } else { "." },
+                if cell.marked { "M" } else { "." },
+                if cell.freed { "F" } else { "." },
+            );
+
+            let name = match &cell.name {
+                Some(name) => format!("  '{}'", name),
+                None => String::new(),
+            };
+
+            ListItem::new(Line::from(vec![
+                Span::styled(format!("{:>3} ", i), cell_style(cell)),
+                Span::raw(format!(
+                    " data {:>6}  {}  refs {:>2}  -> {:?}  <- {:?}{}",
+                    data, flags, cell.reference_count, cell.will_ref, cell.by_ref, name
+                )),
+            ]))
+        })
+        .collect();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(" Cells (Up/Down) - R: root, M: marked, F: free "))
+        .highlight_style(Style::default().add_modifier(Modifier::BOLD))
+        .highlight_symbol("> ");
+
+    frame.render_stateful_widget(list, area, state);
+}
+
+//The current root set
+fn draw_roots(frame: &mut Frame, area: Rect, heap: &Heap) {
+    let items: Vec<ListItem> = heap
+        .cells
+        .iter()
+        .enumerate()
+        .filter(|(_, cell)| cell.is_root())
+        .map(|(i, cell)| {
+            let name = match &cell.name {
+                Some(name) => format!(" '{}'", name),
+                None => String::new(),
+            };
+
+            ListItem::new(match cell.data {
+                Some(data) => format!("cell {:>3}{} = {}", i, name, data),
+                None => format!("cell {:>3}{}", i, name),
+            })
+        })
+        .collect();
+
+    frame.render_widget(List::new(items).block(Block::default().borders(Borders::ALL).title(" Roots ")), area);
+}
+
+//The latest log lines, scrolled back by PageUp/PageDown
+fn draw_log(frame: &mut Frame, area: Rect, dashboard: &Dashboard) {
+    let visible = area.height.saturating_sub(2) as usize;
+    let end = dashboard.log.len() - dashboard.log_scroll.min(dashboard.log.len());
+    let start = end.saturating_sub(visible);
+
+    let lines: Vec<Line> = dashboard.log[start..end].iter().map(|line| Line::raw(line.as_str())).collect();
+
+    let title = if dashboard.log_scroll > 0 {
+        format!(" GC log (scrolled back {} lines, PageUp/PageDown) ", dashboard.log_scroll)
+    } else {
+        " GC log (PageUp/PageDown) ".to_string()
+    };
+    frame.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(title)), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::{backend::TestBackend, Terminal};
+
+    use super::*;
+
+    //Everything drawn on the screen, one line per row
+    fn screen(terminal: &Terminal<TestBackend>) -> String {
+        let buffer = terminal.backend().buffer();
+        let width: usize = buffer.area.width as usize;
+        buffer.content.chunks(width).map(|row| row.iter().map(|cell| cell.symbol()).collect::<String>() + "\n").collect()
+    }
+
+    #[test]
+    fn runs_commands_typed_into_it_and_logs_what_they_did() {
+        let mut heap: Heap = Heap::with_seed(8, 1);
+        heap.log.echo = false;
+        let mut session: Session = Session::new();
+        session.color = false;
+        let mut dashboard: Dashboard = Dashboard::new(&heap);
+
+        dashboard.input = "--alloc_at 3".to_string();
+        assert!(dashboard.submit(&mut heap, &mut session).is_none());
+        assert!(!heap.cells[3].freed && dashboard.input.is_empty());
+        assert_eq!(dashboard.log[1..], ["> --alloc_at 3".to_string(), heap.log.since(0).last().unwrap().to_string()]);
+
+        dashboard.input = "--exit".to_string();
+        assert!(matches!(dashboard.submit(&mut heap, &mut session), Some(Leave::Exit(code)) if code == session.exit_code()));
+    }
+
+    #[test]
+    fn draws_every_panel() {
+        let mut heap: Heap = Heap::with_seed(8, 1);
+        heap.log.echo = false;
+        let mut dashboard: Dashboard = Dashboard::new(&heap);
+        dashboard.input = "--gc".to_string();
+
+        let mut terminal: Terminal<TestBackend> = Terminal::new(TestBackend::new(80, 30)).unwrap();
+        terminal.draw(|frame| draw(frame, &heap, &mut dashboard)).unwrap();
+        let screen: String = screen(&terminal);
+        assert!(screen.contains("Heap map: 0/8 cells in use") && screen.contains(" Roots ") && screen.contains("> --gc"), "{}", screen);
+    }
+}
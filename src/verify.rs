@@ -0,0 +1,194 @@
+//Heap invariant verifier
+/*
+    Checks that the memory pool is structurally sound -> every reference is recorded on both ends,
+    reference counts agree with the edges, and nothing points outside the pool or at freed memory.
+
+    A collector trusts these invariants completely, so a code path that breaks one (i.e. an allocation that
+    references a cell without telling it) shows up here long before it shows up as a cell swept while in use.
+
+    The same goes for the generational collector's remembered set (see generational.rs) -> a minor collection only
+    finds the young cells that old cells reference through it, so an old -> young reference the write barrier missed
+    is a young cell waiting to be swept while still in use. `--set-policy verify-remembered on` makes every minor
+    collection check its remembered set against the whole heap before trusting it, as real generational collectors
+    can in their debug builds.
+*/
+
+use core::fmt;
+
+use crate::Heap;
+use crate::prelude::*;
+
+/// A broken invariant found by `Heap::verify()`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    OutOfRange { index: usize, target: usize },             //A reference to (or from) a cell outside the pool
+    MissingReferrer { from: usize, to: usize },             //`from` references `to`, but `to` doesn't list `from` in by_ref
+    MissingReference { from: usize, to: usize },            //`to` lists `from` in by_ref, but `from` doesn't reference `to`
+    CountMismatch { index: usize, count: i32, edges: usize }, //reference_count disagrees with the edges on the cell
+    Dangling { from: usize, to: usize },                    //A cell in use references a freed cell
+    FreedRoot { index: usize },                             //A root that is marked as free
+    FreeWithEdges { index: usize },                         //A free cell that still has references in or out
+    Unremembered { from: usize, to: usize },                //Old cell `from` references young cell `to`, but isn't in the remembered set
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Violation::OutOfRange { index, target } => write!(f, "Cell {} has a reference to cell {}, which is outside the heap", index, target),
+            Violation::MissingReferrer { from, to } => write!(f, "Cell {} references cell {}, but cell {} doesn't record it in by_ref", from, to, to),
+            Violation::MissingReference { from, to } => write!(f, "Cell {} says it is referenced by cell {}, but cell {} doesn't reference it", to, from, from),
+            Violation::CountMismatch { index, count, edges } => write!(f, "Cell {} has a reference count of {}, but {} reference(s) in and out", index, count, edges),
+            Violation::Dangling { from, to } => write!(f, "Cell {} references cell {}, which is free (a dangling pointer)", from, to),
+            Violation::FreedRoot { index } => write!(f, "Cell {} is a root, but is marked as free", index),
+            Violation::FreeWithEdges { index } => write!(f, "Cell {} is free, but still has references in or out", index),
+            Violation::Unremembered { from, to } => write!(
+                f,
+                "Old cell {} references young cell {}, but isn't in the remembered set (the write barrier missed it)",
+                from, to
+            ),
+        }
+    }
+}
+
+impl Heap {
+    /// Checks every structural invariant of the memory pool, returning each violation found (empty if the heap is sound).
+    /// #### Invariants
+    /// - Every index in `will_ref` / `by_ref` is inside the pool
+    /// - References are symmetric -> `a.will_ref` contains `b` exactly when `b.by_ref` contains `a`
+    /// - A cell's `reference_count` matches its references in and out (`assign_reference()` counts both ends)
+    /// - Cells in use don't reference freed cells
+    /// - Roots aren't free, and free cells have no references
+    /// - Every old cell that references a young one is in the remembered set (see `unremembered()`)
+    pub fn verify(&self) -> Vec<Violation> {
+        let mut violations: Vec<Violation> = Vec::new();
+        let size: usize = self.cells.len();
+
+        for (i, cell) in self.cells.iter().enumerate() {
+            if cell.freed {
+                if cell.is_root() {
+                    violations.push(Violation::FreedRoot { index: i });
+                }
+                if !cell.will_ref.is_empty() || !cell.by_ref.is_empty() {
+                    violations.push(Violation::FreeWithEdges { index: i });
+                }
+                continue;
+            }
+
+            for &to in &cell.will_ref {
+                if to >= size {
+                    violations.push(Violation::OutOfRange { index: i, target: to });
+                    continue;
+                }
+                if self.cells[to].freed {
+                    violations.push(Violation::Dangling { from: i, to });
+                } else if !self.cells[to].by_ref.contains(&i) {
+                    violations.push(Violation::MissingReferrer { from: i, to });
+                }
+            }
+
+            for &from in &cell.by_ref {
+                if from >= size {
+                    violations.push(Violation::OutOfRange { index: i, target: from });
+                    continue;
+                }
+                //A freed referrer has no will_ref left, so this also catches edges a free didn't clear
+                if !self.cells[from].will_ref.contains(&i) {
+                    violations.push(Violation::MissingReference { from, to: i });
+                }
+            }
+
+            let edges: usize = cell.will_ref.len() + cell.by_ref.len();
+            if cell.reference_count != edges as i32 {
+                violations.push(Violation::CountMismatch { index: i, count: cell.reference_count, edges });
+            }
+        }
+
+        for (from, to) in self.unremembered() {
+            violations.push(Violation::Unremembered { from, to });
+        }
+
+        violations
+    }
+
+    /// Every old -> young reference whose old cell isn't in the remembered set, found by scanning the whole heap.
+    /// Empty unless a reference was made without passing through the write barrier
+    pub fn unremembered(&self) -> Vec<(usize, usize)> {
+        let size: usize = self.cells.len();
+        (0..size)
+            .filter(|&from| self.is_old(from) && !self.remembered_set().contains(&from))
+            .flat_map(|from| self.cells[from].pointers().iter().map(move |&to| (from, to)))
+            .filter(|&(_, to)| to < size && self.is_young(to))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assign_reference, log::Event, spec_alloc, tests::graph};
+
+    #[test]
+    fn finds_nothing_wrong_with_a_sound_heap() {
+        let heap: Heap = graph(4, &[(0, 1), (1, 2), (2, 0), (3, 3)]);
+        assert!(heap.verify().is_empty());
+    }
+
+    #[test]
+    fn finds_each_broken_invariant() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.cells[1].will_ref.push(2);                                 //Not recorded on cell 2, or in either count
+        heap.cells[2].by_ref.push(9);
+        heap.cells[2].reference_count = 1;
+        heap.cells[0].will_ref.push(4);                                 //Cell 4 is free
+        heap.cells[0].reference_count += 1;
+        heap.cells[5].is_root = true;
+
+        let violations: Vec<Violation> = heap.verify();
+        assert_eq!(violations, [
+            Violation::Dangling { from: 0, to: 4 },
+            Violation::MissingReferrer { from: 1, to: 2 },
+            Violation::CountMismatch { index: 1, count: 1, edges: 2 },
+            Violation::OutOfRange { index: 2, target: 9 },
+            Violation::FreedRoot { index: 5 },
+        ]);
+        assert_eq!(violations[0].to_string(), "Cell 0 references cell 4, which is free (a dangling pointer)");
+    }
+
+    #[test]
+    fn reports_references_the_write_barrier_missed() {
+        let mut heap: Heap = graph(2, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.collect_major();                                           //0 and 1 are old from now on
+        heap.policy.verify_remembered = true;
+        spec_alloc(&mut heap, 2, None, 2).unwrap();
+        assign_reference(&mut heap, 1, 2);
+        assert!(heap.verify().is_empty());
+
+        //As if the reference had been made without the write barrier
+        heap.remembered.clear();
+        assert_eq!(heap.verify(), vec![Violation::Unremembered { from: 1, to: 2 }]);
+        let sequence: usize = heap.log.sequence();
+        heap.collect_minor();
+        assert!(heap.cells[2].freed);
+        assert!(heap.log.since(sequence).any(|event| matches!(event, Event::BarrierMiss { from: 1, to: 2, lost: true })));
+        assert_eq!(heap.log.warnings(), 1);
+    }
+
+    #[test]
+    fn reports_a_missed_reference_the_young_cell_survives() {
+        let mut heap: Heap = graph(2, &[(0, 1)]);
+        heap.log.echo = false;
+        heap.collect_major();                                           //0 and 1 are old from now on
+        heap.policy.verify_remembered = true;
+        spec_alloc(&mut heap, 2, None, 2).unwrap();
+        assign_reference(&mut heap, 0, 2);                              //Remembered, so it keeps 2 alive
+        assign_reference(&mut heap, 1, 2);
+        heap.remembered.retain(|&from| from != 1);                      //As if this one had been made without the barrier
+        assert_eq!(heap.unremembered(), [(1, 2)]);
+
+        let sequence: usize = heap.log.sequence();
+        heap.collect_minor();
+        assert!(!heap.cells[2].freed && heap.log.warnings() == 1);
+        assert!(heap.log.since(sequence).any(|event| matches!(event, Event::BarrierMiss { from: 1, to: 2, lost: false })));
+    }
+}
@@ -0,0 +1,134 @@
+//WebAssembly API
+/*
+    With the `wasm` feature, the library builds to WebAssembly with a JS API (through wasm-bindgen), so a page in
+    the browser can drive the very same collector -> i.e. an interactive visualisation that doesn't reimplement
+    any of it in JS.
+
+        cargo rustc --lib --release --target wasm32-unknown-unknown --crate-type cdylib --no-default-features --features wasm
+        wasm-bindgen --target web --out-dir pkg target/wasm32-unknown-unknown/release/gc_rust.wasm
+
+    JS gets a `Heap` class, whose objects are referred to by cell index (as at the prompt). Anything that goes wrong
+    is thrown as an Error with the same message the REPL would print, and `state()` is the same JSON as --export json.
+    Events aren't printed (there is no console to print them to), `events()` hands them over instead.
+*/
+
+use wasm_bindgen::prelude::*;
+
+use crate::{collect, error::Operation, handle::Handle, poison::check_access, roots::GLOBALS, spec_alloc, Algorithm, Heap};
+
+/// The virtual heap, as JS sees it (as a class called Heap)
+#[wasm_bindgen(js_name = Heap)]
+pub struct WasmHeap {
+    heap: Heap,
+}
+
+#[wasm_bindgen(js_class = Heap)]
+impl WasmHeap {
+    /// Creates a heap of `size` cells, seeded with `seed` (or a random seed) for its arbitrary data
+    #[wasm_bindgen(constructor)]
+    pub fn new(size: usize, seed: Option<u64>) -> Result<WasmHeap, JsError> {
+        if size == 0 {
+            return Err(JsError::new("The heap needs at least one cell"));
+        }
+
+        let mut heap: Heap = match seed {
+            Some(seed) => Heap::with_seed(size, seed),
+            None => Heap::new(size),
+        };
+        heap.log.echo = false;
+        Ok(WasmHeap { heap })
+    }
+
+    /// Amount of cells in the heap
+    pub fn size(&self) -> usize {
+        self.heap.cells.len()
+    }
+
+    /// Allocates data in the first free cell (collecting first if there are none), returning its index
+    pub fn alloc(&mut self, data: i32) -> Result<usize, JsError> {
+        Ok(self.heap.alloc(data)?.index)
+    }
+
+    /// Allocates data in a given free cell, returning its index
+    #[wasm_bindgen(js_name = allocAt)]
+    pub fn alloc_at(&mut self, index: usize, data: i32) -> Result<usize, JsError> {
+        Ok(spec_alloc(&mut self.heap, data, None, index)?)
+    }
+
+    /// Makes cell `from` reference cell `to`
+    pub fn link(&mut self, from: usize, to: usize) -> Result<(), JsError> {
+        let (from, to) = (self.handle(from, Operation::Link)?, self.handle(to, Operation::Link)?);
+        Ok(self.heap.link(from, to)?)
+    }
+
+    /// Removes the reference from cell `from` to cell `to`
+    pub fn unlink(&mut self, from: usize, to: usize) -> Result<(), JsError> {
+        let (from, to) = (self.handle(from, Operation::Unlink)?, self.handle(to, Operation::Unlink)?);
+        Ok(self.heap.unlink(from, to)?)
+    }
+
+    /// Overwrites the data in a cell
+    pub fn write(&mut self, index: usize, data: i32) -> Result<(), JsError> {
+        let handle: Handle = self.handle(index, Operation::Write)?;
+        Ok(self.heap.write(handle, data)?)
+    }
+
+    /// Frees a cell by hand (roots only with `force`)
+    pub fn free(&mut self, index: usize, force: bool) -> Result<(), JsError> {
+        let handle: Handle = self.handle(index, Operation::Free)?;
+        Ok(self.heap.free(handle, force)?)
+    }
+
+    /// Makes a cell a root (in the "globals" root set, like --root)
+    pub fn root(&mut self, index: usize) -> Result<(), JsError> {
+        self.heap
+            .add_root(GLOBALS, index)
+            .map_err(|_| JsError::new(&format!("There is no cell {}, the heap has {} cells", index, self.heap.cells.len())))
+    }
+
+    /// Stops a cell being a root, returning false if it wasn't one
+    pub fn unroot(&mut self, index: usize) -> bool {
+        self.heap.remove_root(index)
+    }
+
+    /// Runs a collection with the current algorithm, returning how many cells were swept
+    pub fn collect(&mut self) -> usize {
+        collect(&mut self.heap);
+        self.heap.history.last().map_or(0, |cycle| cycle.swept)
+    }
+
+    /// Chooses the collection algorithm, by name: mark-sweep, copying or rc
+    #[wasm_bindgen(js_name = setAlgorithm)]
+    pub fn set_algorithm(&mut self, name: &str) -> Result<(), JsError> {
+        match Algorithm::from_name(name) {
+            Some(algorithm) => {
+                self.heap.policy.algorithm = algorithm;
+                Ok(())
+            }
+            None => Err(JsError::new(&format!("Unknown algorithm '{}', expected one of: {}", name, Algorithm::NAMES.join(", ")))),
+        }
+    }
+
+    /// The whole heap as JSON, the same as --export json
+    pub fn state(&self) -> String {
+        self.heap.to_json()
+    }
+
+    /// Sequence number of the latest event, to pass to `events()` later
+    pub fn sequence(&self) -> usize {
+        self.heap.log.sequence()
+    }
+
+    /// Every event (as its message) since sequence number `after`, oldest first
+    pub fn events(&self, after: usize) -> Vec<String> {
+        self.heap.log.since(after).map(|event| event.to_string()).collect()
+    }
+}
+
+impl WasmHeap {
+    //A handle to the object in a cell, refusing cells that are free or out of bounds the way the heap would
+    fn handle(&self, index: usize, operation: Operation) -> Result<Handle, JsError> {
+        check_access(&self.heap.cells, index, operation)?;
+        Ok(self.heap.handle(index).expect("check_access() only passes cells in use"))
+    }
+}
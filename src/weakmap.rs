@@ -0,0 +1,163 @@
+//Weak-keyed maps
+/*
+    A weak map (JavaScript's WeakMap, Java's WeakHashMap) associates a value with a key object without keeping the
+    key alive -> once nothing else references the key, it is collected and its entry quietly disappears. It is how
+    extra data is attached to objects that belong to someone else, i.e. a cache keyed by the objects it describes.
+
+    Each entry is an ephemeron (Hayes, 1997): its value is kept alive by the entry only for as long as its key is
+    reachable some other way. A reference from the value back to its own key doesn't count, or the entry would keep
+    itself alive forever. So at the start of every collection the heap works out which keys are reachable:
+
+        1. Everything reachable from the roots is found, as usual
+        2. Every entry whose key was found has its value (and what the value reaches) found too, which may in turn
+           reach the key of another entry -> repeated until no more values are found
+        3. The entries whose key is still not found are dropped, and reported. The values of the rest are rooted
+           in the "weak map" root set for the length of the collection, so whichever algorithm runs keeps them
+
+    Entries are dropped before finalizable objects are queued (see finalize.rs), so a key kept alive for its
+    finalizer has already lost its entry, as Java clears weak references before finalizing.
+
+    There is one weak map per heap, with a value per key (`--weakmap_put <key> <value>`, `--weakmap_get <key>`).
+*/
+
+use serde::{Deserialize, Serialize};
+
+use crate::{error::{ErrorKind, GcError, Operation}, log::Event, Heap};
+use crate::prelude::*;
+
+/// The root set the values of live keys are kept in, for the length of a collection
+pub const VALUES: &str = "weak map";
+
+/// A map from key cells to value cells that doesn't keep its keys alive, see the top of this module
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WeakMap {
+    entries: Vec<(usize, usize)>,   //(key, value), in the order they were first put
+}
+
+impl WeakMap {
+    /// The value of a key, if it has one
+    pub fn get(&self, key: usize) -> Option<usize> {
+        self.entries.iter().find(|&&(k, _)| k == key).map(|&(_, value)| value)
+    }
+
+    /// Every entry, as (key, value)
+    pub fn entries(&self) -> &[(usize, usize)] {
+        &self.entries
+    }
+
+    /// How many entries there are
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Are there no entries?
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl Heap {
+    /// The heap's weak map
+    pub fn weak_map(&self) -> &WeakMap {
+        &self.weak_map
+    }
+
+    /// Maps `key` to `value` in the weak map, returning the value it replaced (if any).
+    /// Returns `OutOfBounds` error if either isn't a cell, or `DataIsFree` error if either isn't in use
+    pub fn weak_map_put(&mut self, key: usize, value: usize) -> Result<Option<usize>, GcError> {
+        let error = |kind: ErrorKind, cell: usize| GcError::new(kind, Operation::WeakMap, Some(cell));
+        for index in [key, value] {
+            match self.cells.get(index) {
+                None => return Err(error(ErrorKind::OutOfBounds, index)),
+                Some(cell) if cell.freed => return Err(error(ErrorKind::DataIsFree, index)),
+                Some(_) => {}
+            }
+        }
+        let replaced: Option<usize> = self.weak_map.get(key);
+        self.weak_map.entries.retain(|&(k, _)| k != key);
+        self.weak_map.entries.push((key, value));
+        Ok(replaced)
+    }
+
+    /// The value of a key in the weak map (None if it has none, or its entry was dropped when it was collected)
+    pub fn weak_map_get(&self, key: usize) -> Option<usize> {
+        self.weak_map.get(key)
+    }
+
+    //Before a collection -> drops the entries whose keys are garbage, and keeps the values of the rest alive (see the top of this module)
+    pub(crate) fn hold_weak_values(&mut self) {
+        let cells = &self.cells;
+        self.weak_map.entries.retain(|&(key, value)| !cells[key].freed && !cells[value].freed);      //Reclaimed since the last collection
+        let mut reached: Vec<bool> = self.reachable();
+        loop {
+            let found: Vec<usize> = self.weak_map.entries.iter().filter(|&&(key, value)| reached[key] && !reached[value]).map(|&(_, value)| value).collect();
+            if found.is_empty() {
+                break;
+            }
+            for (reached, found) in reached.iter_mut().zip(self.reachable_from(found)) {
+                *reached |= found;
+            }
+        }
+
+        let dead: Vec<(usize, usize)> = self.weak_map.entries.iter().copied().filter(|&(key, _)| !reached[key]).collect();
+        self.weak_map.entries.retain(|&(key, _)| reached[key]);
+        for (key, value) in dead {
+            self.log.emit(Event::WeakEntryDropped { key, value });
+        }
+        for (_, value) in self.weak_map.entries.clone() {
+            self.add_root(VALUES, value).expect("values are cells in the heap");
+        }
+    }
+
+    //After a collection -> the values go back to being held by their entries alone
+    pub(crate) fn release_weak_values(&mut self) {
+        self.drop_root_set(VALUES);
+    }
+
+    //Cells were moved (see copying.rs), so the entries go with them. An entry whose key or value wasn't copied is dropped
+    pub(crate) fn relocate_weak_map(&mut self, forwarding: &[Option<usize>]) {
+        let forward = |index: usize| forwarding.get(index).copied().flatten();
+        self.weak_map.entries = self.weak_map.entries.iter().filter_map(|&(key, value)| Some((forward(key)?, forward(value)?))).collect();
+    }
+
+    //A cell freed by hand takes any entry it is the key or value of with it
+    pub(crate) fn forget_weak_entry(&mut self, index: usize) {
+        self.weak_map.entries.retain(|&(key, value)| key != index && value != index);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{collect, tests::graph, unlink_reference};
+
+    #[test]
+    fn drops_weak_map_entries_with_their_keys() {
+        let mut heap: Heap = graph(5, &[(0, 1), (4, 3)]);
+        heap.log.echo = false;
+        heap.weak_map_put(1, 2).unwrap();
+        heap.weak_map_put(3, 4).unwrap();           //The value references its own key, which mustn't keep either alive
+
+        collect(&mut heap);
+        assert_eq!(heap.weak_map().entries(), [(1, 2)]);
+        assert!(!heap.cells[2].freed && heap.cells[3].freed && heap.cells[4].freed);
+        assert!(heap.root_sets().iter().all(|root_set| root_set.name != VALUES));
+
+        unlink_reference(&mut heap, 0, 1);
+        collect(&mut heap);
+        assert!(heap.weak_map().is_empty() && heap.cells[1].freed && heap.cells[2].freed);
+    }
+
+    #[test]
+    fn only_maps_cells_in_use() {
+        let mut heap: Heap = graph(3, &[(0, 1)]);
+        heap.log.echo = false;
+        assert_eq!(heap.weak_map_put(1, 9).unwrap_err(), GcError::new(ErrorKind::OutOfBounds, Operation::WeakMap, Some(9)));
+        assert_eq!(heap.weak_map_put(4, 1).unwrap_err().to_string(), "Couldn't weakly map cell 4, the memory was free, not suitable for use");
+        assert!(heap.weak_map().is_empty());
+
+        assert_eq!(heap.weak_map_put(1, 1).unwrap(), None);
+        assert_eq!(heap.weak_map_put(1, 2).unwrap(), Some(1));         //Replaced
+        assert_eq!((heap.weak_map_get(1), heap.weak_map_get(2), heap.weak_map().len()), (Some(2), None, 1));
+    }
+}
@@ -0,0 +1,62 @@
+//C API
+/*
+    The C API (src/ffi.rs) is called here the way a C program would call it -> through raw pointers, with every
+    failure coming back as a GcStatus rather than a Result. Only built with the ffi feature:
+
+        cargo test --features ffi --test ffi
+*/
+#![cfg(feature = "ffi")]
+
+use std::{ffi::CStr, ptr};
+
+use gc_rust::{ffi::*, Heap};
+
+#[test]
+fn drives_the_heap_through_the_c_api() {
+    unsafe {
+        let heap: *mut Heap = gc_heap_create(4, 1);
+        let (mut first, mut second) = (0, 0);
+        assert_eq!(gc_alloc(heap, 1, &mut first), GcStatus::Ok);
+        assert_eq!(gc_alloc(heap, 2, &mut second), GcStatus::Ok);
+        assert_eq!(gc_root(heap, first), GcStatus::Ok);
+        assert_eq!(gc_link(heap, first, 9), GcStatus::OutOfBounds);
+        assert_eq!(gc_collect(heap), 1);
+        assert_eq!(gc_link(heap, first, second), GcStatus::UseAfterFree);
+        assert_eq!(gc_stats(heap).live, 1);
+        gc_heap_destroy(heap);
+    }
+}
+
+#[test]
+fn says_why_a_call_failed() {
+    unsafe {
+        assert!(gc_heap_create(0, 1).is_null());
+        let heap: *mut Heap = gc_heap_create(2, 1);
+        let (mut root, mut other) = (0, 0);
+        assert_eq!(gc_alloc(heap, 1, &mut root), GcStatus::Ok);
+        assert_eq!(gc_alloc(heap, 2, &mut other), GcStatus::Ok);
+        assert_eq!(gc_root(heap, root), GcStatus::Ok);
+        assert_eq!(gc_link(heap, root, root), GcStatus::Ok);
+        assert_eq!(gc_alloc(heap, 3, &mut other), GcStatus::Ok);                  //Full, so the other cell is collected for it
+        assert_eq!(gc_link(heap, root, other), GcStatus::Ok);
+
+        assert_eq!(gc_free(heap, root), GcStatus::FreedRoot);
+        assert_eq!(gc_link(heap, root, other), GcStatus::Occupied);         //Already linked
+        assert_eq!(gc_root(heap, 2), GcStatus::OutOfBounds);
+        assert_eq!(gc_alloc(heap, 4, ptr::null_mut()), GcStatus::NoFreeMemory);
+        assert!(gc_unroot(heap, root) && !gc_unroot(heap, root));
+        assert_eq!(gc_free(heap, root), GcStatus::Ok);
+        assert_eq!(gc_free(heap, root), GcStatus::UseAfterFree);
+        gc_heap_destroy(heap);
+
+        //A null heap is refused by every call, rather than dereferenced
+        let null: *mut Heap = ptr::null_mut();
+        assert_eq!(gc_alloc(null, 1, ptr::null_mut()), GcStatus::NullHeap);
+        assert_eq!((gc_link(null, 0, 1), gc_free(null, 0), gc_root(null, 0)), (GcStatus::NullHeap, GcStatus::NullHeap, GcStatus::NullHeap));
+        assert_eq!((gc_collect(null), gc_unroot(null, 0), gc_stats(null)), (0, false, GcStats::default()));
+        gc_heap_destroy(null);
+
+        let message: &CStr = CStr::from_ptr(gc_status_message(GcStatus::FreedRoot));
+        assert_eq!(message.to_str(), Ok("roots can't be freed"));
+    }
+}
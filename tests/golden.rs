@@ -0,0 +1,110 @@
+//Golden output
+/*
+    Every script in tests/golden/ is run through the REPL (as `gc-rust --seed 1 --heap-size 8 --script <script>`),
+    and what it prints is compared with the .out file next to it. Any change to an output format, or to what a collector
+    does with the same commands, shows up as a difference here.
+
+    Pauses differ from run to run, so every duration is masked as <time> first. When a change is meant to alter
+    the output, regenerate the expectations and review the diff:
+
+        GOLDEN_BLESS=1 cargo test --test golden
+*/
+#![cfg(feature = "std")]
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output, Stdio},
+};
+
+#[test]
+fn scripted_sessions_match_their_golden_output() {
+    let dir: PathBuf = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden");
+    let bless: bool = env::var_os("GOLDEN_BLESS").is_some();
+
+    let mut scripts: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("tests/golden is readable")
+        .map(|entry| entry.expect("tests/golden is readable").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    scripts.sort();
+    assert!(!scripts.is_empty(), "there are no scripts in {}", dir.display());
+
+    let mut differences: Vec<String> = Vec::new();
+    for script in &scripts {
+        let golden: PathBuf = script.with_extension("out");
+        let actual: String = run(script);
+
+        if bless {
+            fs::write(&golden, &actual).expect("the golden file is writable");
+            continue;
+        }
+        let expected: String = fs::read_to_string(&golden).unwrap_or_default();
+        if let Some(difference) = first_difference(&expected, &actual) {
+            differences.push(format!("{}: {}", script.display(), difference));
+        }
+    }
+
+    assert!(differences.is_empty(), "output differs from the golden files (GOLDEN_BLESS=1 to accept it):\n{}", differences.join("\n"));
+}
+
+//Runs a script through the REPL, returning what it printed (with durations masked) and the code it exited with
+fn run(script: &Path) -> String {
+    let output: Output = Command::new(env!("CARGO_BIN_EXE_gc-rust"))
+        .args(["--seed", "1", "--heap-size", "8", "--script"])
+        .arg(script)
+        .env("HOME", env::temp_dir())            //Nothing is read from (or saved to) the real history file
+        .stdin(Stdio::null())
+        .output()
+        .expect("the REPL runs");
+
+    let mut actual: String = mask_durations(&String::from_utf8_lossy(&output.stdout));
+    actual.push_str(&format!("(exit code {})\n", output.status.code().unwrap_or(-1)));
+    actual
+}
+
+//The first line that differs, side by side
+fn first_difference(expected: &str, actual: &str) -> Option<String> {
+    let (mut expected_lines, mut actual_lines) = (expected.lines(), actual.lines());
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (expected, actual) if expected != actual => {
+                return Some(format!("line {}\n  expected: {}\n    actual: {}", line, expected.unwrap_or("(end)"), actual.unwrap_or("(end)")));
+            }
+            _ => {}
+        }
+    }
+    unreachable!()
+}
+
+//Replaces every duration (i.e. 21µs, 1.5ms, 3s) with <time>
+fn mask_durations(text: &str) -> String {
+    const UNITS: [&str; 4] = ["ns", "µs", "ms", "s"];
+    let mut masked: String = String::with_capacity(text.len());
+    let mut rest: &str = text;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_digit()) {
+        let before: Option<char> = rest[..start].chars().next_back().or_else(|| masked.chars().next_back());
+        let starts_word: bool = !before.is_some_and(char::is_alphanumeric);    //Not the end of a name like ChaCha8
+        masked.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let number: usize = rest.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(rest.len());
+        let unit: Option<&str> = UNITS
+            .into_iter()
+            .find(|unit| rest[number..].starts_with(unit) && !rest[number + unit.len()..].starts_with(|c: char| c.is_alphanumeric()));
+        match unit {
+            Some(unit) if starts_word => {
+                masked.push_str("<time>");
+                rest = &rest[number + unit.len()..];
+            }
+            _ => {
+                masked.push_str(&rest[..number]);
+                rest = &rest[number..];
+            }
+        }
+    }
+    masked.push_str(rest);
+    masked
+}
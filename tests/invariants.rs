@@ -0,0 +1,127 @@
+//Heap invariants, under random mutators
+/*
+    Each case simulates a small heap under one of the collectors, running a random sequence of mutator operations
+    (see src/mutator.rs and src/simulation.rs), checking after every step that:
+
+        the heap is sound -> every reference is recorded on both ends, and reference counts agree with the edges (see verify.rs)
+        no reachable cell is ever swept -> whatever could be reached before a step (other than an unlink or unroot,
+                                           which can make cells unreachable) is still in use after it
+        no unreachable cell survives two full collections -> under mark-sweep, copying and the treadmill, once the mutator is done
+
+    Cells are told apart by their data (every allocation is numbered), as the copying collector moves them.
+*/
+
+use gc_rust::{
+    mutator::Op,
+    simulation::{Simulated, Simulation},
+    Algorithm, Heap,
+};
+use proptest::prelude::*;
+
+fn algorithm() -> impl Strategy<Value = Algorithm> {
+    prop_oneof![Just(Algorithm::MarkSweep), Just(Algorithm::Copying), Just(Algorithm::RefCount), Just(Algorithm::Treadmill), Just(Algorithm::GarbageFirst), Just(Algorithm::BufferedRc), Just(Algorithm::Hybrid)]
+}
+
+//The algorithms that trace, which reclaim everything unreachable (the hybrid traces whatever reference counting can't)
+fn tracing() -> impl Strategy<Value = Algorithm> {
+    prop_oneof![Just(Algorithm::MarkSweep), Just(Algorithm::Copying), Just(Algorithm::Treadmill), Just(Algorithm::Hybrid)]
+}
+
+//Allocations are weighted up, so the heap fills and collects by itself as well as when asked to
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => Just(Op::Alloc(0)),
+        4 => (any::<usize>(), any::<usize>()).prop_map(|(from, to)| Op::Link(from, to)),
+        2 => (any::<usize>(), any::<usize>()).prop_map(|(from, to)| Op::Unlink(from, to)),
+        2 => any::<usize>().prop_map(Op::Root),
+        1 => any::<usize>().prop_map(Op::Unroot),
+        1 => Just(Op::Collect),
+    ]
+}
+
+//A simulation of the ops, with every allocation numbered so cells can be told apart wherever they are moved
+fn simulation(cells: usize, algorithm: Algorithm, ops: &[Op]) -> Simulation {
+    let mut simulation: Simulation = Simulation::new(cells, 0);
+    simulation.algorithm = algorithm;
+    simulation.ops = (1..)
+        .zip(ops)
+        .map(|(number, &op)| match op {
+            Op::Alloc(_) => Op::Alloc(number),
+            op => op,
+        })
+        .collect();
+    simulation
+}
+
+//The data of every cell in use, sorted
+fn objects(heap: &Heap) -> Vec<i32> {
+    let mut objects: Vec<i32> = heap.cells.iter().filter(|cell| !cell.freed).filter_map(|cell| cell.data).collect();
+    objects.sort_unstable();
+    objects
+}
+
+//The data of every cell that can be reached from a root, sorted
+fn reachable(heap: &Heap) -> Vec<i32> {
+    let reachable: Vec<bool> = heap.reachable();
+    let mut objects: Vec<i32> = heap.cells.iter().zip(reachable).filter(|(_, reachable)| *reachable).filter_map(|(cell, _)| cell.data).collect();
+    objects.sort_unstable();
+    objects
+}
+
+//Runs the simulation, checking the heap is sound and nothing reachable was swept after each step.
+//A full heap or a link that is already there are part of the test, so failed steps are carried on from
+fn run(simulation: &Simulation) -> Result<Heap, TestCaseError> {
+    let mut before: Vec<i32> = Vec::new();
+    let mut failure: Option<TestCaseError> = None;
+
+    let simulated: Simulated = simulation.run_with(|heap, outcome| {
+        if failure.is_some() {
+            return;
+        }
+        let violations = heap.verify();
+        let after: Vec<i32> = objects(heap);
+        let swept: Vec<&i32> = before.iter().filter(|object| !after.contains(object)).collect();
+
+        if !violations.is_empty() {
+            failure = Some(TestCaseError::fail(format!("after {}: {:?}", outcome.op, violations)));
+        } else if !swept.is_empty() && !matches!(outcome.op, Op::Unlink(..) | Op::Unroot(_)) {
+            failure = Some(TestCaseError::fail(format!("{} swept reachable objects {:?}", outcome.op, swept)));
+        }
+        before = reachable(heap);
+    });
+
+    match failure {
+        Some(failure) => Err(failure),
+        None => Ok(simulated.heap),
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn never_sweeps_reachable_cells(cells in 4..24usize, algorithm in algorithm(), ops in prop::collection::vec(op(), 0..80)) {
+        run(&simulation(cells, algorithm, &ops))?;
+    }
+
+    #[test]
+    fn reclaims_all_garbage_within_two_full_collections(cells in 4..24usize, tracing in tracing(), ops in prop::collection::vec(op(), 0..80)) {
+        let mut heap: Heap = run(&simulation(cells, tracing, &ops))?;
+
+        heap.step(Op::Collect).unwrap();
+        heap.step(Op::Collect).unwrap();
+        prop_assert_eq!(objects(&heap), reachable(&heap));
+    }
+
+    //Reference counting can't reclaim cycles, but anything left unreachable has to still be referenced
+    #[test]
+    fn counts_keep_only_referenced_garbage(cells in 4..24usize, ops in prop::collection::vec(op(), 0..80)) {
+        let mut heap: Heap = run(&simulation(cells, Algorithm::RefCount, &ops))?;
+
+        heap.step(Op::Collect).unwrap();
+        let reachable: Vec<bool> = heap.reachable();
+        for (i, cell) in heap.cells.iter().enumerate().filter(|(i, cell)| !cell.freed && !reachable[*i]) {
+            prop_assert!(!cell.by_ref.is_empty(), "cell {} is unreachable and unreferenced, but wasn't reclaimed", i);
+        }
+    }
+}